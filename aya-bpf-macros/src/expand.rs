@@ -290,6 +290,38 @@ impl CgroupSysctl {
     }
 }
 
+pub struct CgroupDevice {
+    item: ItemFn,
+    name: Option<String>,
+}
+
+impl CgroupDevice {
+    pub fn from_syn(mut args: Args, item: ItemFn) -> Result<CgroupDevice> {
+        let name = name_arg(&mut args)?;
+
+        Ok(CgroupDevice { item, name })
+    }
+
+    pub fn expand(&self) -> Result<TokenStream> {
+        let section_name = if let Some(name) = &self.name {
+            format!("cgroup/dev/{}", name)
+        } else {
+            ("cgroup/dev").to_owned()
+        };
+        let fn_name = &self.item.sig.ident;
+        let item = &self.item;
+        Ok(quote! {
+            #[no_mangle]
+            #[link_section = #section_name]
+            fn #fn_name(ctx: *mut ::aya_bpf::bindings::bpf_cgroup_dev_ctx) -> i32 {
+                return #fn_name(::aya_bpf::programs::DeviceContext::new(ctx));
+
+                #item
+            }
+        })
+    }
+}
+
 pub struct CgroupSockopt {
     item: ItemFn,
     attach_type: String,
@@ -788,6 +820,98 @@ impl FExit {
     }
 }
 
+pub struct FModRet {
+    item: ItemFn,
+    name: String,
+}
+
+impl FModRet {
+    pub fn from_syn(mut args: Args, item: ItemFn) -> Result<FModRet> {
+        let name = name_arg(&mut args)?.unwrap_or_else(|| item.sig.ident.to_string());
+
+        Ok(FModRet { item, name })
+    }
+
+    pub fn expand(&self) -> Result<TokenStream> {
+        let section_name = format!("fmod_ret/{}", self.name);
+        let fn_name = &self.item.sig.ident;
+        let item = &self.item;
+        Ok(quote! {
+            #[no_mangle]
+            #[link_section = #section_name]
+            fn #fn_name(ctx: *mut ::core::ffi::c_void) -> i32 {
+                return #fn_name(::aya_bpf::programs::FModRetContext::new(ctx));
+
+                #item
+            }
+        })
+    }
+}
+
+pub struct LircMode2 {
+    item: ItemFn,
+    name: Option<String>,
+}
+
+impl LircMode2 {
+    pub fn from_syn(mut args: Args, item: ItemFn) -> Result<LircMode2> {
+        let name = name_arg(&mut args)?;
+
+        Ok(LircMode2 { item, name })
+    }
+
+    pub fn expand(&self) -> Result<TokenStream> {
+        let section_name = if let Some(name) = &self.name {
+            format!("lirc_mode2/{}", name)
+        } else {
+            "lirc_mode2".to_owned()
+        };
+        let fn_name = &self.item.sig.ident;
+        let item = &self.item;
+        Ok(quote! {
+            #[no_mangle]
+            #[link_section = #section_name]
+            fn #fn_name(ctx: *mut u32) -> i32 {
+                return #fn_name(::aya_bpf::programs::LircMode2Context::new(ctx));
+
+                #item
+            }
+        })
+    }
+}
+
+pub struct Syscall {
+    item: ItemFn,
+    name: Option<String>,
+}
+
+impl Syscall {
+    pub fn from_syn(mut args: Args, item: ItemFn) -> Result<Syscall> {
+        let name = name_arg(&mut args)?;
+
+        Ok(Syscall { item, name })
+    }
+
+    pub fn expand(&self) -> Result<TokenStream> {
+        let section_name = if let Some(name) = &self.name {
+            format!("syscall/{}", name)
+        } else {
+            "syscall".to_owned()
+        };
+        let fn_name = &self.item.sig.ident;
+        let item = &self.item;
+        Ok(quote! {
+            #[no_mangle]
+            #[link_section = #section_name]
+            fn #fn_name(ctx: *mut ::core::ffi::c_void) -> i32 {
+                return #fn_name(::aya_bpf::programs::SyscallContext::new(ctx));
+
+                #item
+            }
+        })
+    }
+}
+
 pub struct SkLookup {
     item: ItemFn,
     name: Option<String>,
@@ -820,6 +944,38 @@ impl SkLookup {
     }
 }
 
+pub struct FlowDissector {
+    item: ItemFn,
+    name: Option<String>,
+}
+
+impl FlowDissector {
+    pub fn from_syn(mut args: Args, item: ItemFn) -> Result<FlowDissector> {
+        let name = name_arg(&mut args)?;
+
+        Ok(FlowDissector { item, name })
+    }
+
+    pub fn expand(&self) -> Result<TokenStream> {
+        let section_name = if let Some(name) = &self.name {
+            format!("flow_dissector/{}", name)
+        } else {
+            "flow_dissector".to_owned()
+        };
+        let fn_name = &self.item.sig.ident;
+        let item = &self.item;
+        Ok(quote! {
+            #[no_mangle]
+            #[link_section = #section_name]
+            fn #fn_name(ctx: *mut ::aya_bpf::bindings::__sk_buff) -> i32 {
+                return #fn_name(::aya_bpf::programs::FlowDissectorContext::new(ctx));
+
+                #item
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use syn::parse_quote;
@@ -1,9 +1,10 @@
 mod expand;
 
 use expand::{
-    Args, BtfTracePoint, CgroupSkb, CgroupSock, CgroupSockAddr, CgroupSockopt, CgroupSysctl,
-    FEntry, FExit, Lsm, Map, PerfEvent, Probe, ProbeKind, RawTracePoint, SchedClassifier, SkLookup,
-    SkMsg, SkSkb, SkSkbKind, SockAddrArgs, SockOps, SocketFilter, SockoptArgs, TracePoint, Xdp,
+    Args, BtfTracePoint, CgroupDevice, CgroupSkb, CgroupSock, CgroupSockAddr, CgroupSockopt,
+    CgroupSysctl, FEntry, FExit, FModRet, FlowDissector, LircMode2, Lsm, Map, PerfEvent, Probe,
+    ProbeKind, RawTracePoint, SchedClassifier, SkLookup, SkMsg, SkSkb, SkSkbKind, SockAddrArgs,
+    SockOps, SocketFilter, SockoptArgs, Syscall, TracePoint, Xdp,
 };
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, ItemFn, ItemStatic};
@@ -94,6 +95,17 @@ pub fn cgroup_sysctl(attrs: TokenStream, item: TokenStream) -> TokenStream {
         .into()
 }
 
+#[proc_macro_attribute]
+pub fn cgroup_device(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attrs as Args);
+    let item = parse_macro_input!(item as ItemFn);
+
+    CgroupDevice::from_syn(args, item)
+        .and_then(|u| u.expand())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
 #[proc_macro_attribute]
 pub fn cgroup_sockopt(attrs: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(attrs as SockoptArgs);
@@ -479,6 +491,124 @@ pub fn fexit(attrs: TokenStream, item: TokenStream) -> TokenStream {
         .into()
 }
 
+/// Marks a function as a fmod_ret eBPF program that can override the return
+/// value of a kernel function. The program runs before the function, and if
+/// it returns a non-zero value the function isn't called and the value is
+/// returned to the caller instead. Only the functions that allow error
+/// injection and the LSM hooks can be attached to.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.7.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya_bpf::{cty::c_int, macros::fmod_ret, programs::FModRetContext};
+///
+/// const ENOMEM: c_int = 12;
+///
+/// #[fmod_ret(name = "should_fail_alloc_page")]
+/// fn should_fail_alloc_page(ctx: FModRetContext) -> i32 {
+///     match unsafe { try_should_fail_alloc_page(ctx) } {
+///         Ok(ret) => ret,
+///         Err(_) => 0,
+///     }
+/// }
+///
+/// unsafe fn try_should_fail_alloc_page(ctx: FModRetContext) -> Result<i32, i32> {
+///     let order: u32 = ctx.arg(1);
+///     // fail the large allocations
+///     Ok(if order > 4 { -ENOMEM } else { 0 })
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn fmod_ret(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attrs as Args);
+    let item = parse_macro_input!(item as ItemFn);
+
+    FModRet::from_syn(args, item)
+        .and_then(|u| u.expand())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+/// Marks a function as a lirc_mode2 eBPF program that decodes the raw IR
+/// samples of an infrared receiver. The program is called for every pulse or
+/// space received by the device, and reports the decoded keys to the rc
+/// subsystem.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.18.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya_bpf::{
+///     macros::lirc_mode2,
+///     programs::{lirc_mode2::LIRC_MODE2_PULSE, LircMode2Context},
+/// };
+///
+/// #[lirc_mode2]
+/// fn decode_ir(ctx: LircMode2Context) -> i32 {
+///     // a long pulse is enough to decode the key in this protocol
+///     if ctx.mode() == LIRC_MODE2_PULSE && ctx.value() > 5000 {
+///         let _ = ctx.keydown(64, 0x1, 0);
+///     }
+///     0
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn lirc_mode2(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attrs as Args);
+    let item = parse_macro_input!(item as ItemFn);
+
+    LircMode2::from_syn(args, item)
+        .and_then(|u| u.expand())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+/// Marks a function as a syscall eBPF program. Syscall programs aren't
+/// attached to any hook, they're run on demand from user space with
+/// `BPF_PROG_RUN`, and can themselves issue `bpf()` syscalls to create maps
+/// and load other programs.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.14.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya_bpf::{macros::syscall, programs::SyscallContext};
+///
+/// #[repr(C)]
+/// struct Args {
+///     fd: u32,
+/// }
+///
+/// #[syscall]
+/// fn close_fd(ctx: SyscallContext) -> i32 {
+///     let args: Args = unsafe { ctx.read() };
+///     match ctx.sys_close(args.fd) {
+///         Ok(()) => 0,
+///         Err(ret) => ret as i32,
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn syscall(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attrs as Args);
+    let item = parse_macro_input!(item as ItemFn);
+
+    Syscall::from_syn(args, item)
+        .and_then(|u| u.expand())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
 /// Marks a function as an eBPF Socket Lookup program that can be attached to
 /// a network namespace.
 ///
@@ -507,3 +637,39 @@ pub fn sk_lookup(attrs: TokenStream, item: TokenStream) -> TokenStream {
         .unwrap_or_else(|err| err.to_compile_error())
         .into()
 }
+
+/// Marks a function as an eBPF Flow Dissector program that can be attached to
+/// a network namespace.
+///
+/// The program replaces the kernel's flow dissector. It fills in the flow
+/// keys returned by `FlowDissectorContext::flow_keys` and returns `BPF_OK`,
+/// or `BPF_DROP` if the packet can't be dissected.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.20.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya_bpf::{
+///     bindings::bpf_ret_code::BPF_OK, macros::flow_dissector, programs::FlowDissectorContext,
+/// };
+///
+/// #[flow_dissector(name = "dissect")]
+/// pub fn dissect(mut ctx: FlowDissectorContext) -> i32 {
+///     let keys = ctx.flow_keys();
+///     keys.n_proto = 0x0800u16.to_be();
+///     BPF_OK as i32
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn flow_dissector(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attrs as Args);
+    let item = parse_macro_input!(item as ItemFn);
+
+    FlowDissector::from_syn(args, item)
+        .and_then(|u| u.expand())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
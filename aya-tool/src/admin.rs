@@ -0,0 +1,221 @@
+//! Inspection and management of the BPF objects loaded in the kernel.
+//!
+//! These functions back the `list`, `pin`, `unpin`, `dump`, `attach` and
+//! `detach` subcommands of `aya-tool`, and are built on aya's public API
+//! only. Most of them require `CAP_SYS_ADMIN`.
+use std::{
+    fs::{self, File},
+    io,
+    os::unix::io::FromRawFd,
+    path::{Path, PathBuf},
+};
+
+use aya::{
+    maps::{loaded_maps, MapData, MapError, MapInfo, MapSnapshot},
+    pin::PinError,
+    programs::{
+        links::{FdLink, LinkError, PinnedLink},
+        loaded_programs, Program, ProgramError, ProgramInfo, Xdp, XdpFlags,
+    },
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Program(#[from] ProgramError),
+
+    #[error(transparent)]
+    Map(#[from] MapError),
+
+    #[error(transparent)]
+    Pin(#[from] PinError),
+
+    #[error(transparent)]
+    Link(#[from] LinkError),
+
+    #[error("error removing `{}`", path.display())]
+    Unpin {
+        path: PathBuf,
+        #[source]
+        io_error: io::Error,
+    },
+
+    #[error("program {id} is not an XDP program")]
+    NotXdp { id: u32 },
+
+    #[error("XDP links require kernel 5.9 or later, this is {release}")]
+    NoXdpLinks { release: String },
+
+    #[error("error reading the kernel release")]
+    KernelRelease(#[source] io::Error),
+}
+
+/// A BPF object, referred to either by id or by the path it's pinned to.
+#[derive(Debug, Clone)]
+pub enum Object {
+    Id(u32),
+    Pinned(PathBuf),
+}
+
+impl std::str::FromStr for Object {
+    type Err = std::convert::Infallible;
+
+    /// Parses ids like `42` and paths like `/sys/fs/bpf/foo`.
+    fn from_str(s: &str) -> Result<Object, Self::Err> {
+        Ok(match s.parse() {
+            Ok(id) => Object::Id(id),
+            Err(_) => Object::Pinned(PathBuf::from(s)),
+        })
+    }
+}
+
+/// Returns all the programs loaded in the kernel.
+pub fn list_programs() -> Result<Vec<ProgramInfo>, Error> {
+    Ok(loaded_programs().collect::<Result<_, _>>()?)
+}
+
+/// Returns all the maps created in the kernel.
+pub fn list_maps() -> Result<Vec<MapInfo>, Error> {
+    Ok(loaded_maps().collect::<Result<_, _>>()?)
+}
+
+/// Pins the program with the given id to `path`.
+pub fn pin_program<P: AsRef<Path>>(id: u32, path: P) -> Result<(), Error> {
+    Ok(ProgramInfo::from_id(id)?.pin(path)?)
+}
+
+/// Pins the map with the given id to `path`.
+pub fn pin_map<P: AsRef<Path>>(id: u32, path: P) -> Result<(), Error> {
+    Ok(MapData::from_id(id)?.pin_to(path)?)
+}
+
+/// Removes a pinned program, map or link.
+///
+/// The object is released by the kernel once nothing else refers to it.
+pub fn unpin<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let path = path.as_ref();
+    fs::remove_file(path).map_err(|io_error| Error::Unpin {
+        path: path.to_path_buf(),
+        io_error,
+    })
+}
+
+/// Returns the metadata and the entries of a map.
+pub fn dump_map(map: &Object) -> Result<MapSnapshot, Error> {
+    let map = match map {
+        Object::Id(id) => MapData::from_id(*id)?,
+        Object::Pinned(path) => MapData::from_pin(path)?,
+    };
+    Ok(map.snapshot()?)
+}
+
+/// Attaches an XDP program to `interface` and pins the link to `link_path`.
+///
+/// The program stays attached after the process exits, until the link is
+/// removed with [`detach`]. This requires bpf links, available since kernel
+/// 5.9. On older kernels [`Error::NoXdpLinks`] is returned before anything
+/// is attached.
+pub fn attach_xdp<P: AsRef<Path>>(
+    program: &Object,
+    interface: &str,
+    flags: XdpFlags,
+    link_path: P,
+) -> Result<(), Error> {
+    // older kernels attach through netlink, and those attachments can't be
+    // pinned
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease").map_err(Error::KernelRelease)?;
+    let release = release.trim();
+    if !has_xdp_links(release) {
+        return Err(Error::NoXdpLinks {
+            release: release.to_owned(),
+        });
+    }
+
+    let info = match program {
+        Object::Id(id) => ProgramInfo::from_id(*id)?,
+        Object::Pinned(path) => ProgramInfo::from_pin(path)?,
+    };
+    let fd = info.fd()?;
+    let mut program = match Program::from_fd(fd) {
+        Ok(program) => program,
+        Err(e) => {
+            // from_fd only takes ownership of the fd on success
+            drop(unsafe { File::from_raw_fd(fd) });
+            return Err(e.into());
+        }
+    };
+    let xdp: &mut Xdp = (&mut program)
+        .try_into()
+        .map_err(|_| Error::NotXdp { id: info.id() })?;
+    let link_id = xdp.attach(interface, flags)?;
+    let link = FdLink::try_from(xdp.take_link(link_id)?)?;
+    link.pin(link_path)?;
+    Ok(())
+}
+
+/// Detaches the program attached through the link pinned at `link_path`.
+pub fn detach<P: AsRef<Path>>(link_path: P) -> Result<(), Error> {
+    let link_path = link_path.as_ref();
+    PinnedLink::from_pin(link_path)?
+        .unpin()
+        .map_err(|io_error| Error::Unpin {
+            path: link_path.to_path_buf(),
+            io_error,
+        })?;
+    Ok(())
+}
+
+// Returns whether the kernel with the given release, like `5.15.0-91-generic`,
+// supports XDP bpf links.
+fn has_xdp_links(release: &str) -> bool {
+    let mut parts = release.split(|c: char| !c.is_ascii_digit());
+    let mut next = || {
+        parts
+            .next()
+            .and_then(|p| p.parse::<u32>().ok())
+            .unwrap_or(0)
+    };
+    let (major, minor) = (next(), next());
+    (major, minor) >= (5, 9)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+
+    use super::{has_xdp_links, unpin, Error, Object};
+
+    #[test]
+    fn test_parse_object() {
+        assert!(matches!("42".parse(), Ok(Object::Id(42))));
+        assert!(matches!(
+            "/sys/fs/bpf/foo".parse(),
+            Ok(Object::Pinned(path)) if path.to_str() == Some("/sys/fs/bpf/foo")
+        ));
+        assert!(matches!("-1".parse(), Ok(Object::Pinned(_))));
+    }
+
+    #[test]
+    fn test_unpin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prog");
+        std::fs::write(&path, b"").unwrap();
+
+        assert!(unpin(&path).is_ok());
+        assert!(!path.exists());
+        assert!(matches!(
+            unpin(&path),
+            Err(Error::Unpin { path: p, io_error }) if p == path && io_error.kind() == ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_has_xdp_links() {
+        assert!(has_xdp_links("5.9.0"));
+        assert!(has_xdp_links("5.15.0-91-generic"));
+        assert!(has_xdp_links("6.1.0"));
+        assert!(!has_xdp_links("5.8.18"));
+        assert!(!has_xdp_links("4.19.0-25-amd64"));
+    }
+}
@@ -1,4 +1,8 @@
-use aya_tool::generate::{generate, InputFile};
+use aya::programs::XdpFlags;
+use aya_tool::{
+    admin::{self, Object},
+    generate::{generate, InputFile},
+};
 
 use std::{path::PathBuf, process::exit};
 
@@ -24,6 +28,75 @@ enum Command {
         #[clap(last = true, action)]
         bindgen_args: Vec<String>,
     },
+    /// List the programs or the maps loaded in the kernel
+    #[clap(name = "list", subcommand)]
+    List(Kind),
+    /// Pin a loaded program or map by id
+    #[clap(name = "pin", subcommand)]
+    Pin(PinCommand),
+    /// Remove a pinned program, map or link
+    #[clap(name = "unpin", action)]
+    Unpin {
+        #[clap(action)]
+        path: PathBuf,
+    },
+    /// Dump the entries of a map, given its id or pinned path, as JSON
+    #[clap(name = "dump", action)]
+    Dump {
+        #[clap(action)]
+        map: Object,
+    },
+    /// Attach an XDP program, given its id or pinned path, and pin the link.
+    /// Only XDP programs are supported, on kernels with XDP links (5.9 or
+    /// later)
+    #[clap(name = "attach", action)]
+    Attach {
+        #[clap(long, action)]
+        iface: String,
+        /// Use the generic XDP mode
+        #[clap(long, action)]
+        skb_mode: bool,
+        #[clap(action)]
+        program: Object,
+        #[clap(action)]
+        link_path: PathBuf,
+    },
+    /// Detach a program by removing its pinned link
+    #[clap(name = "detach", action)]
+    Detach {
+        #[clap(action)]
+        link_path: PathBuf,
+    },
+}
+
+#[derive(Parser)]
+enum PinCommand {
+    /// Pin a program
+    #[clap(name = "program", action)]
+    Program {
+        #[clap(action)]
+        id: u32,
+        #[clap(action)]
+        path: PathBuf,
+    },
+    /// Pin a map
+    #[clap(name = "map", action)]
+    Map {
+        #[clap(action)]
+        id: u32,
+        #[clap(action)]
+        path: PathBuf,
+    },
+}
+
+#[derive(Parser)]
+enum Kind {
+    /// Programs
+    #[clap(name = "programs", alias = "program")]
+    Programs,
+    /// Maps
+    #[clap(name = "maps", alias = "map")]
+    Maps,
 }
 
 fn main() {
@@ -49,6 +122,35 @@ fn try_main() -> Result<(), anyhow::Error> {
             };
             println!("{}", bindings);
         }
+        Command::List(Kind::Programs) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&admin::list_programs()?)?
+            );
+        }
+        Command::List(Kind::Maps) => {
+            println!("{}", serde_json::to_string_pretty(&admin::list_maps()?)?);
+        }
+        Command::Pin(PinCommand::Program { id, path }) => admin::pin_program(id, path)?,
+        Command::Pin(PinCommand::Map { id, path }) => admin::pin_map(id, path)?,
+        Command::Unpin { path } => admin::unpin(path)?,
+        Command::Dump { map } => {
+            println!("{}", serde_json::to_string_pretty(&admin::dump_map(&map)?)?);
+        }
+        Command::Attach {
+            iface,
+            skb_mode,
+            program,
+            link_path,
+        } => {
+            let flags = if skb_mode {
+                XdpFlags::SKB_MODE
+            } else {
+                XdpFlags::default()
+            };
+            admin::attach_xdp(&program, &iface, flags, link_path)?
+        }
+        Command::Detach { link_path } => admin::detach(link_path)?,
     };
 
     Ok(())
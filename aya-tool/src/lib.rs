@@ -4,6 +4,7 @@ use std::{
     path::Path,
 };
 
+pub mod admin;
 pub mod bindgen;
 pub mod generate;
 pub mod rustfmt;
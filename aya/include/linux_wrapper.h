@@ -11,3 +11,5 @@
 int AYA_PERF_EVENT_IOC_ENABLE = PERF_EVENT_IOC_ENABLE;
 int AYA_PERF_EVENT_IOC_DISABLE = PERF_EVENT_IOC_DISABLE;
 int AYA_PERF_EVENT_IOC_SET_BPF = PERF_EVENT_IOC_SET_BPF;
+int AYA_PERF_EVENT_IOC_SET_FILTER = PERF_EVENT_IOC_SET_FILTER;
+int AYA_PERF_EVENT_IOC_PAUSE_OUTPUT = PERF_EVENT_IOC_PAUSE_OUTPUT;
@@ -1,11 +1,12 @@
 use std::{
-    borrow::Cow,
     collections::{HashMap, HashSet},
     error::Error,
     ffi::CString,
-    fs, io,
+    fmt, fs, io,
     os::{raw::c_int, unix::io::RawFd},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    thread,
 };
 
 use log::debug;
@@ -14,26 +15,31 @@ use thiserror::Error;
 use crate::{
     generated::{
         bpf_map_type, bpf_map_type::*, AYA_PERF_EVENT_IOC_DISABLE, AYA_PERF_EVENT_IOC_ENABLE,
-        AYA_PERF_EVENT_IOC_SET_BPF,
+        AYA_PERF_EVENT_IOC_PAUSE_OUTPUT, AYA_PERF_EVENT_IOC_SET_BPF, AYA_PERF_EVENT_IOC_SET_FILTER,
     },
-    maps::{Map, MapData, MapError},
+    maps::{migrate::MapMigration, Map, MapData, MapError},
     obj::{
+        self,
         btf::{Btf, BtfError},
-        MapKind, Object, ParseError, ProgramSection,
+        GlobalVariable, MapKind, Object, ParseError, ProgramSection,
     },
+    pin::{PinError, PinHandoff},
     programs::{
-        BtfTracePoint, CgroupSkb, CgroupSkbAttachType, CgroupSock, CgroupSockAddr, CgroupSockopt,
-        CgroupSysctl, Extension, FEntry, FExit, KProbe, LircMode2, Lsm, PerfEvent, ProbeKind,
-        Program, ProgramData, ProgramError, RawTracePoint, SchedClassifier, SkLookup, SkMsg, SkSkb,
-        SkSkbKind, SockOps, SocketFilter, TracePoint, UProbe, Xdp,
+        struct_ops, BtfTracePoint, CgroupDevice, CgroupSkb, CgroupSkbAttachType, CgroupSock,
+        CgroupSockAddr, CgroupSockopt, CgroupSysctl, Extension, FEntry, FExit, FModRet,
+        FlowDissector, Iter, KProbe, KProbeMulti, LircMode2, Lsm, Netfilter, PerfEvent, ProbeKind,
+        Program, ProgramData, ProgramError, RawTracePoint, SchedClassifier, SkLookup, SkMsg,
+        SkReuseport, SkReuseportKind, SkSkb, SkSkbKind, SockOps, SocketFilter, StructOps,
+        StructOpsError, StructOpsLink, Syscall, TracePoint, UProbe, UProbeMulti, Xdp,
     },
     sys::{
-        bpf_load_btf, bpf_map_freeze, bpf_map_update_elem_ptr, is_btf_datasec_supported,
-        is_btf_decl_tag_supported, is_btf_float_supported, is_btf_func_global_supported,
-        is_btf_func_supported, is_btf_supported, is_btf_type_tag_supported, is_prog_name_supported,
+        bpf_get_object, bpf_load_btf, bpf_map_freeze, bpf_map_lookup_elem_ptr,
+        bpf_map_update_elem_ptr, is_btf_datasec_supported, is_btf_decl_tag_supported,
+        is_btf_float_supported, is_btf_func_global_supported, is_btf_func_supported,
+        is_btf_supported, is_btf_type_tag_supported, is_prog_name_supported,
         retry_with_verifier_logs,
     },
-    util::{bytes_of, possible_cpus, VerifierLog, POSSIBLE_CPUS},
+    util::{bytes_of, kernel_object_name, nr_cpus, possible_cpus, VerifierLog, POSSIBLE_CPUS},
 };
 
 pub(crate) const BPF_OBJ_NAME_LEN: usize = 16;
@@ -41,6 +47,8 @@ pub(crate) const BPF_OBJ_NAME_LEN: usize = 16;
 pub(crate) const PERF_EVENT_IOC_ENABLE: c_int = AYA_PERF_EVENT_IOC_ENABLE;
 pub(crate) const PERF_EVENT_IOC_DISABLE: c_int = AYA_PERF_EVENT_IOC_DISABLE;
 pub(crate) const PERF_EVENT_IOC_SET_BPF: c_int = AYA_PERF_EVENT_IOC_SET_BPF;
+pub(crate) const PERF_EVENT_IOC_SET_FILTER: c_int = AYA_PERF_EVENT_IOC_SET_FILTER;
+pub(crate) const PERF_EVENT_IOC_PAUSE_OUTPUT: c_int = AYA_PERF_EVENT_IOC_PAUSE_OUTPUT;
 
 /// Marker trait for types that can safely be converted to and from byte slices.
 pub unsafe trait Pod: Copy + 'static {}
@@ -80,6 +88,8 @@ pub(crate) struct BtfMapDef {
     pub(crate) value_size: u32,
     pub(crate) max_entries: u32,
     pub(crate) map_flags: u32,
+    pub(crate) map_extra: u64,
+    pub(crate) numa_node: Option<u32>,
     pub(crate) pinning: PinningType,
     pub(crate) btf_key_type_id: u32,
     pub(crate) btf_value_type_id: u32,
@@ -165,6 +175,14 @@ impl Features {
     }
 }
 
+#[derive(Debug)]
+enum TargetBtf<'a> {
+    // the kernel BTF, only loaded if an object needs it
+    Kernel,
+    Borrowed(&'a Btf),
+    Shared(Arc<Btf>),
+}
+
 /// Builder style API for advanced loading of eBPF programs.
 ///
 /// Loading eBPF code involves a few steps, including loading maps and applying
@@ -188,13 +206,20 @@ impl Features {
 /// ```
 #[derive(Debug)]
 pub struct BpfLoader<'a> {
-    btf: Option<Cow<'a, Btf>>,
+    btf: Option<TargetBtf<'a>>,
     map_pin_path: Option<PathBuf>,
     globals: HashMap<&'a str, &'a [u8]>,
     max_entries: HashMap<&'a str, u32>,
+    map_extra: HashMap<&'a str, u64>,
+    numa_nodes: HashMap<&'a str, u32>,
     features: Features,
     extensions: HashSet<&'a str>,
+    licenses: HashMap<&'a str, &'a str>,
+    autoload: HashMap<&'a str, bool>,
+    shared_maps: HashMap<&'a str, &'a Map>,
+    migrations: HashMap<&'a str, &'a MapMigration>,
     verifier_log_level: VerifierLogLevel,
+    load_fallbacks: LoadFallbacks,
 }
 
 bitflags! {
@@ -219,25 +244,60 @@ impl Default for VerifierLogLevel {
     }
 }
 
+bitflags! {
+    /// Compatibility rewrites retried when loading a program fails, set with
+    /// [BpfLoader](BpfLoader::load_fallbacks()).
+    ///
+    /// When the kernel rejects a program with `EINVAL` or `E2BIG`, the enabled
+    /// fallbacks are applied one at a time, in the order below, and the load
+    /// is retried after each one until it succeeds. Each rewrite is kept for
+    /// the following attempts. If every attempt fails, the error of the last
+    /// attempt is returned.
+    #[derive(Default)]
+    pub struct LoadFallbacks: u32 {
+        /// Replaces calls to `bpf_probe_read_{kernel,user}[_str]`, which
+        /// require kernel 5.5, with `bpf_probe_read[_str]`.
+        const PROBE_READ = 1;
+        /// Replaces the sign-extending loads of `-mcpu=v4`, which the
+        /// kernel runs as `BPF_PROBE_MEMSX` when they read kernel memory and
+        /// which require kernel 6.6, with loads followed by a sign extension.
+        const PROBE_MEM = 8;
+        /// Loads the program without BTF function and line info.
+        const FUNC_INFO = 2;
+        /// Loads the program without an expected attach type, which kernels
+        /// older than 4.17 don't support.
+        const EXPECTED_ATTACH_TYPE = 4;
+    }
+}
+
 impl<'a> BpfLoader<'a> {
     /// Creates a new loader instance.
     pub fn new() -> BpfLoader<'a> {
         let mut features = Features::default();
         features.probe_features();
         BpfLoader {
-            btf: Btf::from_sys_fs().ok().map(Cow::Owned),
+            btf: Some(TargetBtf::Kernel),
             map_pin_path: None,
             globals: HashMap::new(),
             max_entries: HashMap::new(),
+            map_extra: HashMap::new(),
+            numa_nodes: HashMap::new(),
             features,
             extensions: HashSet::new(),
+            licenses: HashMap::new(),
+            autoload: HashMap::new(),
+            shared_maps: HashMap::new(),
+            migrations: HashMap::new(),
             verifier_log_level: VerifierLogLevel::default(),
+            load_fallbacks: LoadFallbacks::default(),
         }
     }
 
     /// Sets the target [BTF](Btf) info.
     ///
-    /// The loader defaults to loading `BTF` info using [Btf::from_sys_fs].
+    /// The loader defaults to loading `BTF` info using [Btf::from_sys_fs_cached],
+    /// the first time an object with `BTF` relocations is loaded. Objects
+    /// without relocations don't require the kernel to provide `BTF`.
     /// Use this method if you want to load `BTF` from a custom location or
     /// pass `None` to disable `BTF` relocations entirely.
     /// # Example
@@ -253,7 +313,33 @@ impl<'a> BpfLoader<'a> {
     /// # Ok::<(), aya::BpfError>(())
     /// ```
     pub fn btf(&mut self, btf: Option<&'a Btf>) -> &mut BpfLoader<'a> {
-        self.btf = btf.map(Cow::Borrowed);
+        self.btf = btf.map(TargetBtf::Borrowed);
+        self
+    }
+
+    /// Sets the target [BTF](Btf) info from a shared instance.
+    ///
+    /// This is the same as [BpfLoader::btf], but takes shared ownership of the
+    /// `BTF` so that it can be parsed once and reused by any number of
+    /// loaders, including ones that outlive the scope the `BTF` was created
+    /// in.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use aya::{BpfLoader, Btf, Endianness};
+    ///
+    /// let btf = Arc::new(Btf::parse_file("/custom_btf_file", Endianness::default())?);
+    /// for file in ["first.o", "second.o"] {
+    ///     let bpf = BpfLoader::new()
+    ///         .shared_btf(Some(btf.clone()))
+    ///         .load_file(file)?;
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn shared_btf(&mut self, btf: Option<Arc<Btf>>) -> &mut BpfLoader<'a> {
+        self.btf = btf.map(TargetBtf::Shared);
         self
     }
 
@@ -262,6 +348,11 @@ impl<'a> BpfLoader<'a> {
     /// Pinned maps will be loaded from `path/MAP_NAME`.
     /// The caller is responsible for ensuring the directory exists.
     ///
+    /// If a pinned map's type, key size, value size, max entries or flags
+    /// don't match its definition, loading fails with
+    /// [`MapError::IncompatibleMap`], unless the values can be converted by a
+    /// migration registered with [`BpfLoader::migrate_map`].
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -338,6 +429,94 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Sets the `map_extra` attribute of the specified map.
+    ///
+    /// The meaning of `map_extra` depends on the map type: it's the number
+    /// of hash functions of a [`BloomFilter`](crate::maps::BloomFilter) and
+    /// the address in user space of an [`Arena`](crate::maps::Arena).
+    /// Overwrites the value set by the map definition, if any, before the
+    /// map is created.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     // use 3 hash functions instead of the default of 5
+    ///     .set_map_extra("filter", 3)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn set_map_extra(&mut self, name: &'a str, map_extra: u64) -> &mut BpfLoader<'a> {
+        self.map_extra.insert(name, map_extra);
+        self
+    }
+
+    /// Allocates the memory of the specified map on the given NUMA node.
+    ///
+    /// By default the kernel allocates maps on the node of the CPU creating
+    /// them. Overwrites the node set by the map definition, if any, before
+    /// the map is created.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .set_numa_node("map", 1)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn set_numa_node(&mut self, name: &'a str, node: u32) -> &mut BpfLoader<'a> {
+        self.numa_nodes.insert(name, node);
+        self
+    }
+
+    /// Uses an existing map instead of creating the map with the same name.
+    ///
+    /// This lets multiple instances of the same object, see
+    /// [`BpfLoader::load_object`], share some of their maps while keeping the
+    /// others private. The shared map isn't initialized with the object's
+    /// data, and loading fails with [`MapError::IncompatibleMap`] if its
+    /// definition doesn't match the map defined in the object.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::{BpfLoader, BpfObject};
+    ///
+    /// let object = BpfObject::parse_file("file.o")?;
+    /// let first = BpfLoader::new().load_object(&object)?;
+    /// let second = BpfLoader::new()
+    ///     .share_map("STATS", first.map("STATS").unwrap())
+    ///     .load_object(&object)?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn share_map(&mut self, name: &'a str, map: &'a Map) -> &mut BpfLoader<'a> {
+        self.shared_maps.insert(name, map);
+        self
+    }
+
+    /// Converts the values of the map pinned as `name` when their layout
+    /// doesn't match the map definition anymore.
+    ///
+    /// When the pinned map is incompatible only because of its value size,
+    /// and `migration` can convert the pinned values to the new size, a new
+    /// map is created with the converted entries and pinned in place of the
+    /// old one instead of failing with [`MapError::IncompatibleMap`]. See
+    /// [`MapMigration`] for an example.
+    pub fn migrate_map(
+        &mut self,
+        name: &'a str,
+        migration: &'a MapMigration,
+    ) -> &mut BpfLoader<'a> {
+        self.migrations.insert(name, migration);
+        self
+    }
+
     /// Treat the provided program as an [`Extension`]
     ///
     /// When attempting to load the program with the provided `name`
@@ -360,6 +539,52 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Overrides the license of the provided program.
+    ///
+    /// By default every program is loaded with the license found in the
+    /// object's `license` section. This sets the license passed to the kernel
+    /// when loading the program with the provided `name` instead.
+    ///
+    /// Programs calling GPL-only helpers must have a GPL compatible license,
+    /// otherwise loading them fails with [`ProgramError::GplOnlyHelper`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .program_license("myprog", "Dual MIT/GPL")
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn program_license(&mut self, name: &'a str, license: &'a str) -> &mut BpfLoader<'a> {
+        self.licenses.insert(name, license);
+        self
+    }
+
+    /// Sets whether the provided program is loaded by [`Bpf::load_programs`].
+    ///
+    /// This overrides the default found in the object, see
+    /// [`Program::autoload`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .autoload("debug_probe", false)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn autoload(&mut self, name: &'a str, autoload: bool) -> &mut BpfLoader<'a> {
+        self.autoload.insert(name, autoload);
+        self
+    }
+
     /// Sets BPF verifier log level.
     ///
     /// # Example
@@ -378,6 +603,29 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Sets the compatibility rewrites to retry when loading a program fails.
+    ///
+    /// This allows a single object to be loaded on older kernels whose
+    /// verifier rejects some of the features newer toolchains emit. No
+    /// fallbacks are enabled by default. See [`LoadFallbacks`] for the
+    /// available rewrites.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::{BpfLoader, LoadFallbacks};
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .load_fallbacks(LoadFallbacks::all())
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn load_fallbacks(&mut self, fallbacks: LoadFallbacks) -> &mut BpfLoader<'a> {
+        self.load_fallbacks = fallbacks;
+        self
+    }
+
     /// Loads eBPF bytecode from a file.
     ///
     /// # Examples
@@ -409,8 +657,39 @@ impl<'a> BpfLoader<'a> {
     /// # Ok::<(), aya::BpfError>(())
     /// ```
     pub fn load(&mut self, data: &[u8]) -> Result<Bpf, BpfError> {
+        self.load_obj(Object::parse(data)?)
+    }
+
+    /// Loads an already parsed eBPF object.
+    ///
+    /// The object isn't consumed, so it can be loaded any number of times,
+    /// each time with the settings of the loader it's loaded with, without
+    /// parsing the ELF file again.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aya::{BpfLoader, BpfObject};
+    ///
+    /// let object = BpfObject::parse_file("file.o")?;
+    /// let mut instances = Vec::new();
+    /// for (i, iface) in ["eth0", "eth1"].iter().enumerate() {
+    ///     let id = i as u32;
+    ///     let bpf = BpfLoader::new()
+    ///         .set_global("INSTANCE_ID", &id)
+    ///         .load_object(&object)?;
+    ///     instances.push((iface, bpf));
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn load_object(&mut self, object: &BpfObject) -> Result<Bpf, BpfError> {
+        self.load_obj(object.obj.clone())
+    }
+
+    fn load_obj(&mut self, mut obj: Object) -> Result<Bpf, BpfError> {
+        trace_span!("load");
         let verifier_log_level = self.verifier_log_level.bits;
-        let mut obj = Object::parse(data)?;
+        let load_fallbacks = self.load_fallbacks;
         obj.patch_map_data(self.globals.clone())?;
 
         let btf_fd = if self.features.btf {
@@ -429,11 +708,30 @@ impl<'a> BpfLoader<'a> {
             None
         };
 
-        if let Some(btf) = &self.btf {
-            obj.relocate_btf(btf)?;
+        if obj.has_btf_relocations() {
+            let kernel_btf;
+            let target_btf = match &self.btf {
+                Some(TargetBtf::Kernel) => {
+                    kernel_btf = Btf::from_sys_fs_cached()
+                        .map_err(|e| debug!("kernel BTF not available: {}", e))
+                        .ok();
+                    kernel_btf.as_deref()
+                }
+                Some(TargetBtf::Borrowed(btf)) => Some(*btf),
+                Some(TargetBtf::Shared(btf)) => Some(btf.as_ref()),
+                None => None,
+            };
+            if let Some(btf) = target_btf {
+                trace_span!("relocate_btf");
+                obj.relocate_btf(btf)?;
+            }
         }
+        let globals = obj.global_variables();
+        let mut kernel_names = KernelNames::default();
         let mut maps = HashMap::new();
         for (name, mut obj) in obj.maps.drain() {
+            trace_span!("map", name = %name);
+            kernel_names.insert_map(&name);
             match self.max_entries.get(name.as_str()) {
                 Some(size) => obj.set_max_entries(*size),
                 None => {
@@ -451,12 +749,24 @@ impl<'a> BpfLoader<'a> {
                     }
                 }
             }
+            if let Some(map_extra) = self.map_extra.get(name.as_str()) {
+                obj.set_map_extra(*map_extra);
+            }
+            if let Some(node) = self.numa_nodes.get(name.as_str()) {
+                obj.set_numa_node(*node);
+            }
             let mut map = MapData {
                 obj,
                 fd: None,
                 pinned: false,
                 btf_fd,
             };
+            if let Some(shared) = self.shared_maps.get(name.as_str()) {
+                trace_event!("reusing shared map");
+                map.reuse(&name, shared.data())?;
+                maps.insert(name, map);
+                continue;
+            }
             let fd = match map.obj.pinning() {
                 PinningType::ByName => {
                     let path = match &self.map_pin_path {
@@ -466,9 +776,20 @@ impl<'a> BpfLoader<'a> {
                     // try to open map in case it's already pinned
                     match map.open_pinned(&name, path) {
                         Ok(fd) => {
+                            trace_event!(path = %path.display(), "reusing pinned map");
                             map.pinned = true;
                             fd as RawFd
                         }
+                        Err(e @ MapError::IncompatibleMap { .. }) => {
+                            match self.migrations.get(name.as_str()) {
+                                Some(migration) => {
+                                    let fd = map.migrate_pinned(&name, path, migration)?;
+                                    trace_event!(path = %path.display(), "migrated pinned map");
+                                    fd
+                                }
+                                None => return Err(e.into()),
+                            }
+                        }
                         Err(_) => {
                             let fd = map.create(&name)?;
                             map.pin(&name, path).map_err(|error| MapError::PinError {
@@ -494,24 +815,44 @@ impl<'a> BpfLoader<'a> {
                     io_error,
                 })?;
             }
+            trace_event!(fd, "map loaded");
             maps.insert(name, map);
         }
 
-        obj.relocate_maps(&maps)?;
-        obj.relocate_calls()?;
+        {
+            trace_span!("relocate");
+            obj.relocate_maps(&maps)?;
+            obj.relocate_calls()?;
+        }
+
+        for (name, license) in &self.licenses {
+            if let Some(program) = obj.programs.get_mut(*name) {
+                program.license =
+                    CString::new(*license).map_err(|_| ParseError::InvalidLicense {
+                        data: license.as_bytes().to_vec(),
+                    })?;
+            }
+        }
+        for (name, autoload) in &self.autoload {
+            if let Some(program) = obj.programs.get_mut(*name) {
+                program.autoload = *autoload;
+            }
+        }
 
+        let struct_ops = obj.struct_ops;
         let programs = obj
             .programs
             .drain()
             .map(|(name, obj)| {
                 let prog_name = if self.features.bpf_name {
+                    kernel_names.insert_program(&name);
                     Some(name.clone())
                 } else {
                     None
                 };
                 let section = obj.section.clone();
 
-                let program = if self.extensions.contains(name.as_str()) {
+                let mut program = if self.extensions.contains(name.as_str()) {
                     Program::Extension(Extension {
                         data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
                     })
@@ -525,6 +866,26 @@ impl<'a> BpfLoader<'a> {
                             data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
                             kind: ProbeKind::KRetProbe,
                         }),
+                        ProgramSection::KProbeMulti { .. } => Program::KProbeMulti(KProbeMulti {
+                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            kind: ProbeKind::KProbe,
+                        }),
+                        ProgramSection::KRetProbeMulti { .. } => {
+                            Program::KProbeMulti(KProbeMulti {
+                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                kind: ProbeKind::KRetProbe,
+                            })
+                        }
+                        ProgramSection::UProbeMulti { .. } => Program::UProbeMulti(UProbeMulti {
+                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            kind: ProbeKind::UProbe,
+                        }),
+                        ProgramSection::URetProbeMulti { .. } => {
+                            Program::UProbeMulti(UProbeMulti {
+                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                kind: ProbeKind::URetProbe,
+                            })
+                        }
                         ProgramSection::UProbe { .. } => Program::UProbe(UProbe {
                             data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
                             kind: ProbeKind::UProbe,
@@ -552,6 +913,11 @@ impl<'a> BpfLoader<'a> {
                                 data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
                             })
                         }
+                        ProgramSection::CgroupDevice { .. } => {
+                            Program::CgroupDevice(CgroupDevice {
+                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            })
+                        }
                         ProgramSection::CgroupSockopt { attach_type, .. } => {
                             Program::CgroupSockopt(CgroupSockopt {
                                 data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
@@ -621,9 +987,17 @@ impl<'a> BpfLoader<'a> {
                         ProgramSection::FExit { .. } => Program::FExit(FExit {
                             data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
                         }),
+                        ProgramSection::FModRet { .. } => Program::FModRet(FModRet {
+                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                        }),
                         ProgramSection::Extension { .. } => Program::Extension(Extension {
                             data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
                         }),
+                        ProgramSection::FlowDissector { .. } => {
+                            Program::FlowDissector(FlowDissector {
+                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            })
+                        }
                         ProgramSection::SkLookup { .. } => Program::SkLookup(SkLookup {
                             data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
                         }),
@@ -633,8 +1007,32 @@ impl<'a> BpfLoader<'a> {
                                 attach_type: *attach_type,
                             })
                         }
+                        ProgramSection::Syscall { .. } => Program::Syscall(Syscall {
+                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                        }),
+                        ProgramSection::StructOps { .. } => Program::StructOps(StructOps {
+                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            member: struct_ops_member(&struct_ops, &name),
+                        }),
+                        ProgramSection::Iter { .. } => Program::Iter(Iter {
+                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                        }),
+                        ProgramSection::Netfilter { .. } => Program::Netfilter(Netfilter {
+                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                        }),
+                        ProgramSection::SkReuseport { .. } => Program::SkReuseport(SkReuseport {
+                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            kind: SkReuseportKind::Select,
+                        }),
+                        ProgramSection::SkReuseportMigrate { .. } => {
+                            Program::SkReuseport(SkReuseport {
+                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                kind: SkReuseportKind::SelectOrMigrate,
+                            })
+                        }
                     }
                 };
+                program.set_load_fallbacks(load_fallbacks);
                 (name, program)
             })
             .collect();
@@ -643,10 +1041,26 @@ impl<'a> BpfLoader<'a> {
         Ok(Bpf {
             maps: maps?,
             programs,
+            struct_ops,
+            globals,
+            kernel_names,
         })
     }
 }
 
+// Returns the struct_ops type and the member implemented by `program`.
+fn struct_ops_member(
+    struct_ops: &HashMap<String, obj::StructOps>,
+    program: &str,
+) -> Option<(String, String)> {
+    struct_ops.values().find_map(|ops| {
+        ops.members
+            .iter()
+            .find(|m| m.program.as_deref() == Some(program))
+            .map(|m| (ops.type_name.clone(), m.name.clone()))
+    })
+}
+
 fn parse_map(data: (String, MapData)) -> Result<(String, Map), BpfError> {
     let name = data.0;
     let map = data.1;
@@ -657,16 +1071,21 @@ fn parse_map(data: (String, MapData)) -> Result<(String, Map), BpfError> {
         BPF_MAP_TYPE_PROG_ARRAY => Ok(Map::ProgramArray(map)),
         BPF_MAP_TYPE_HASH => Ok(Map::HashMap(map)),
         BPF_MAP_TYPE_PERCPU_HASH => Ok(Map::PerCpuHashMap(map)),
-        BPF_MAP_TYPE_PERF_EVENT_ARRAY | BPF_MAP_TYPE_LRU_PERCPU_HASH => {
-            Ok(Map::PerfEventArray(map))
-        }
+        BPF_MAP_TYPE_LRU_HASH => Ok(Map::LruHashMap(map)),
+        BPF_MAP_TYPE_LRU_PERCPU_HASH => Ok(Map::PerCpuLruHashMap(map)),
+        BPF_MAP_TYPE_PERF_EVENT_ARRAY => Ok(Map::PerfEventArray(map)),
         BPF_MAP_TYPE_SOCKHASH => Ok(Map::SockHash(map)),
         BPF_MAP_TYPE_SOCKMAP => Ok(Map::SockMap(map)),
+        BPF_MAP_TYPE_REUSEPORT_SOCKARRAY => Ok(Map::ReusePortSockArray(map)),
         BPF_MAP_TYPE_BLOOM_FILTER => Ok(Map::BloomFilter(map)),
         BPF_MAP_TYPE_LPM_TRIE => Ok(Map::LpmTrie(map)),
         BPF_MAP_TYPE_STACK => Ok(Map::Stack(map)),
         BPF_MAP_TYPE_STACK_TRACE => Ok(Map::StackTraceMap(map)),
         BPF_MAP_TYPE_QUEUE => Ok(Map::Queue(map)),
+        BPF_MAP_TYPE_RINGBUF => Ok(Map::RingBuf(map)),
+        BPF_MAP_TYPE_ARENA => Ok(Map::Arena(map)),
+        BPF_MAP_TYPE_DEVMAP => Ok(Map::DevMap(map)),
+        BPF_MAP_TYPE_DEVMAP_HASH => Ok(Map::DevMapHash(map)),
         m => Err(BpfError::MapError(MapError::InvalidMapType {
             map_type: m as u32,
         })),
@@ -682,18 +1101,207 @@ impl<'a> Default for BpfLoader<'a> {
 }
 
 /// The main entry point into the library, used to work with eBPF programs and maps.
+///
+/// `Bpf`, [`Program`] and [`Map`] are `Send` and `Sync`. Individual maps can
+/// be moved out with [`Bpf::take_map`], and [`Bpf::into_shared`] turns the
+/// instance into a [`SharedBpf`], which multithreaded servers and async tasks
+/// can use concurrently without wrapping it in a `Mutex`.
 #[derive(Debug)]
 pub struct Bpf {
     maps: HashMap<String, Map>,
     programs: HashMap<String, Program>,
+    struct_ops: HashMap<String, obj::StructOps>,
+    globals: HashMap<String, GlobalVariable>,
+    kernel_names: KernelNames,
+}
+
+/// The names given in the kernel to the maps and programs of a [`Bpf`].
+///
+/// The kernel limits the names of maps and programs to 15 bytes, so longer
+/// names are truncated when the objects are created. Tools like `bpftool`
+/// only show the truncated names, and distinct maps can end up with the same
+/// name in the kernel. `KernelNames` translates between the names used in the
+/// object file, which are the ones [`Bpf::map`] and [`Bpf::program`] take,
+/// and the kernel names.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let bpf = aya::Bpf::load(&[])?;
+/// for (name, kernel_name) in bpf.kernel_names().renamed_maps() {
+///     println!("map {} is called {} in the kernel", name, kernel_name);
+/// }
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct KernelNames {
+    maps: HashMap<String, String>,
+    programs: HashMap<String, String>,
+}
+
+impl KernelNames {
+    fn insert_map(&mut self, name: &str) {
+        self.maps
+            .insert(name.to_owned(), kernel_object_name(name).to_owned());
+    }
+
+    fn insert_program(&mut self, name: &str) {
+        self.programs
+            .insert(name.to_owned(), kernel_object_name(name).to_owned());
+    }
+
+    /// Returns the kernel name of the map called `name` in the object file.
+    pub fn map(&self, name: &str) -> Option<&str> {
+        self.maps.get(name).map(String::as_str)
+    }
+
+    /// Returns the kernel name of the program called `name` in the object
+    /// file.
+    ///
+    /// Returns `None` if the kernel doesn't support program names.
+    pub fn program(&self, name: &str) -> Option<&str> {
+        self.programs.get(name).map(String::as_str)
+    }
+
+    /// An iterator over the `(name, kernel_name)` pairs of the maps whose
+    /// name was truncated.
+    pub fn renamed_maps(&self) -> impl Iterator<Item = (&str, &str)> {
+        renamed(&self.maps)
+    }
+
+    /// An iterator over the `(name, kernel_name)` pairs of the programs whose
+    /// name was truncated.
+    pub fn renamed_programs(&self) -> impl Iterator<Item = (&str, &str)> {
+        renamed(&self.programs)
+    }
+
+    /// Returns the names of the maps called `kernel_name` in the kernel,
+    /// sorted.
+    ///
+    /// More than one name is returned if the names of several maps only
+    /// differ after the first 15 bytes.
+    pub fn maps_with_kernel_name(&self, kernel_name: &str) -> Vec<&str> {
+        with_kernel_name(&self.maps, kernel_name)
+    }
+
+    /// Returns the names of the programs called `kernel_name` in the kernel,
+    /// sorted.
+    pub fn programs_with_kernel_name(&self, kernel_name: &str) -> Vec<&str> {
+        with_kernel_name(&self.programs, kernel_name)
+    }
+}
+
+fn renamed(names: &HashMap<String, String>) -> impl Iterator<Item = (&str, &str)> {
+    names
+        .iter()
+        .filter(|(name, kernel_name)| name != kernel_name)
+        .map(|(name, kernel_name)| (name.as_str(), kernel_name.as_str()))
+}
+
+fn with_kernel_name<'a>(names: &'a HashMap<String, String>, kernel_name: &str) -> Vec<&'a str> {
+    let mut found = names
+        .iter()
+        .filter(|(_, k)| *k == kernel_name)
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    found.sort_unstable();
+    found
+}
+
+// Fail to compile if any of the types holding kernel objects stops being
+// Send or Sync.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Bpf>();
+    assert_send_sync::<BpfObject>();
+    assert_send_sync::<Program>();
+    assert_send_sync::<Map>();
+    assert_send_sync::<SharedBpf>();
+};
+
+/// A [`Bpf`] instance that can be used from many threads at once.
+///
+/// `SharedBpf` is created with [`Bpf::into_shared`] and is cheap to clone.
+/// Every program and map has its own lock, so a thread attaching a program
+/// doesn't wait for the threads reading maps, maps are read concurrently,
+/// and no borrow of the whole instance is held across `.await` points.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::HashMap;
+///
+/// let bpf = bpf.into_shared();
+/// let worker = bpf.clone();
+/// std::thread::spawn(move || {
+///     let map = worker.map("CONNECTIONS").unwrap();
+///     let connections: HashMap<_, u32, u64> = HashMap::try_from(&*map).unwrap();
+///     for entry in connections.iter() {
+///         let (id, bytes) = entry.unwrap();
+///         println!("{} sent {} bytes", id, bytes);
+///     }
+/// });
+///
+/// let mut map = bpf.map_mut("CONNECTIONS").unwrap();
+/// let mut connections: HashMap<_, u32, u64> = HashMap::try_from(&mut *map)?;
+/// connections.remove(&42)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedBpf {
+    maps: Arc<HashMap<String, RwLock<Map>>>,
+    programs: Arc<HashMap<String, Mutex<Program>>>,
+}
+
+impl SharedBpf {
+    /// Returns the map with the given name, locked for reading.
+    ///
+    /// Any number of threads can read a map at the same time. This blocks
+    /// while the map is locked by [`map_mut`](SharedBpf::map_mut).
+    pub fn map(&self, name: &str) -> Option<RwLockReadGuard<'_, Map>> {
+        // a panic while the map was locked doesn't leave it in an invalid
+        // state, the kernel owns its contents
+        self.maps
+            .get(name)
+            .map(|map| map.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Returns the map with the given name, locked for writing.
+    ///
+    /// This blocks until no other thread has the map locked.
+    pub fn map_mut(&self, name: &str) -> Option<RwLockWriteGuard<'_, Map>> {
+        self.maps
+            .get(name)
+            .map(|map| map.write().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Returns the program with the given name, locked.
+    ///
+    /// This blocks until no other thread has the program locked.
+    pub fn program(&self, name: &str) -> Option<MutexGuard<'_, Program>> {
+        self.programs
+            .get(name)
+            .map(|program| program.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// An iterator over the names of the maps.
+    pub fn map_names(&self) -> impl Iterator<Item = &str> {
+        self.maps.keys().map(String::as_str)
+    }
+
+    /// An iterator over the names of the programs.
+    pub fn program_names(&self) -> impl Iterator<Item = &str> {
+        self.programs.keys().map(String::as_str)
+    }
 }
 
 impl Bpf {
     /// Loads eBPF bytecode from a file.
     ///
     /// Parses the given object code file and initializes the [maps](crate::maps) defined in it. If
-    /// the kernel supports [BTF](Btf) debug info, it is automatically loaded from
-    /// `/sys/kernel/btf/vmlinux`.
+    /// the object contains `BTF` relocations and the kernel supports [BTF](Btf) debug info, it is
+    /// automatically loaded from `/sys/kernel/btf/vmlinux`.
     ///
     /// For more loading options, see [BpfLoader].
     ///
@@ -706,16 +1314,15 @@ impl Bpf {
     /// # Ok::<(), aya::BpfError>(())
     /// ```
     pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Bpf, BpfError> {
-        BpfLoader::new()
-            .btf(Btf::from_sys_fs().ok().as_ref())
-            .load_file(path)
+        BpfLoader::new().load_file(path)
     }
 
     /// Loads eBPF bytecode from a buffer.
     ///
     /// Parses the object code contained in `data` and initializes the
-    /// [maps](crate::maps) defined in it. If the kernel supports [BTF](Btf)
-    /// debug info, it is automatically loaded from `/sys/kernel/btf/vmlinux`.
+    /// [maps](crate::maps) defined in it. If the object contains `BTF`
+    /// relocations and the kernel supports [BTF](Btf) debug info, it is
+    /// automatically loaded from `/sys/kernel/btf/vmlinux`.
     ///
     /// For more loading options, see [BpfLoader].
     ///
@@ -731,9 +1338,144 @@ impl Bpf {
     /// # Ok::<(), aya::BpfError>(())
     /// ```
     pub fn load(data: &[u8]) -> Result<Bpf, BpfError> {
-        BpfLoader::new()
-            .btf(Btf::from_sys_fs().ok().as_ref())
-            .load(data)
+        BpfLoader::new().load(data)
+    }
+
+    /// Loads eBPF bytecode from a file without blocking the async runtime.
+    ///
+    /// Loading runs the verifier on every program, which can take seconds for
+    /// large programs. This runs [`Bpf::load_file`] on the tokio blocking
+    /// thread pool, so other tasks keep running in the meantime.
+    ///
+    /// For more loading options, call [`BpfLoader`] from
+    /// `tokio::task::spawn_blocking`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn load() -> Result<(), aya::BpfError> {
+    /// use aya::Bpf;
+    ///
+    /// let bpf = Bpf::load_file_async("file.o").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async_tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async_tokio")))]
+    pub async fn load_file_async<P: AsRef<Path>>(path: P) -> Result<Bpf, BpfError> {
+        let path = path.as_ref().to_path_buf();
+        spawn_blocking(move || Bpf::load_file(path)).await
+    }
+
+    /// Loads eBPF bytecode from a buffer without blocking the async runtime.
+    ///
+    /// See [`Bpf::load_file_async`].
+    #[cfg(feature = "async_tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async_tokio")))]
+    pub async fn load_async<D: Into<Vec<u8>>>(data: D) -> Result<Bpf, BpfError> {
+        let data = data.into();
+        spawn_blocking(move || Bpf::load(&data)).await
+    }
+
+    /// Adopts the maps and programs pinned to `dir` by [`Bpf::pin_all`] or a
+    /// [`PinHandoff`].
+    ///
+    /// Programs are named after their pin, and have the types reported by the
+    /// kernel. Programs that [`Program::from_fd`] can't recover, like kprobes,
+    /// are skipped. They stay pinned, and can still be inspected with
+    /// [`ProgramInfo::from_pin`](crate::programs::ProgramInfo::from_pin).
+    /// Pinned links stay attached and can be adopted with
+    /// [`PinnedLink::from_pin`](crate::programs::links::PinnedLink::from_pin).
+    ///
+    /// Global variables can't be accessed by name on the returned object.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let bpf = aya::Bpf::load_from_pins("/sys/fs/bpf/myapp")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn load_from_pins<P: AsRef<Path>>(dir: P) -> Result<Bpf, BpfError> {
+        let dir = dir.as_ref();
+        let mut maps = HashMap::new();
+        for name in pinned_names(&dir.join("maps"))? {
+            let map = MapData::from_pin(dir.join("maps").join(&name))?;
+            let (name, map) = parse_map((name, map))?;
+            maps.insert(name, map);
+        }
+
+        let mut programs = HashMap::new();
+        for name in pinned_names(&dir.join("programs"))? {
+            let path = dir.join("programs").join(&name);
+            let path_string = CString::new(path.to_string_lossy().into_owned()).map_err(|e| {
+                PinError::InvalidPinPath {
+                    error: e.to_string(),
+                }
+            })?;
+            let fd = bpf_get_object(&path_string).map_err(|(_, io_error)| {
+                ProgramError::SyscallError {
+                    call: "BPF_OBJ_GET".to_owned(),
+                    io_error,
+                }
+            })? as RawFd;
+            match Program::from_fd(fd) {
+                Ok(program) => {
+                    programs.insert(name, program);
+                }
+                Err(ProgramError::UnexpectedProgramType) => {
+                    unsafe { libc::close(fd) };
+                    debug!("skipping pinned program {}: unsupported program type", name);
+                }
+                Err(e) => {
+                    unsafe { libc::close(fd) };
+                    return Err(e.into());
+                }
+            }
+        }
+
+        let mut kernel_names = KernelNames::default();
+        for name in maps.keys() {
+            kernel_names.insert_map(name);
+        }
+        for name in programs.keys() {
+            kernel_names.insert_program(name);
+        }
+
+        Ok(Bpf {
+            maps,
+            programs,
+            struct_ops: HashMap::new(),
+            globals: HashMap::new(),
+            kernel_names,
+        })
+    }
+
+    /// Pins all the maps and loaded programs to `dir` atomically.
+    ///
+    /// This is a shortcut for staging every map and loaded program in a
+    /// [`PinHandoff`] and committing it. `dir` must not exist yet, and its
+    /// parent must be on a BPF file system (bpffs). Use [`PinHandoff`]
+    /// directly to pin links too.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// bpf.pin_all("/sys/fs/bpf/myapp")?;
+    /// // after a restart
+    /// let bpf = aya::Bpf::load_from_pins("/sys/fs/bpf/myapp")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn pin_all<P: AsRef<Path>>(&self, dir: P) -> Result<(), BpfError> {
+        let handoff = PinHandoff::new(dir)?;
+        for (name, map) in &self.maps {
+            handoff.pin_map(name, map.data())?;
+        }
+        for (name, program) in self.programs.iter().filter(|(_, p)| p.fd().is_some()) {
+            handoff.pin_program(name, program)?;
+        }
+        handoff.commit()?;
+        Ok(())
     }
 
     /// Returns a reference to the map with the given name.
@@ -747,6 +1489,49 @@ impl Bpf {
         self.maps.get(name)
     }
 
+    /// Returns the names the maps and programs were given in the kernel.
+    ///
+    /// Maps and programs are always looked up by the name they have in the
+    /// object file, even if it was truncated in the kernel. See
+    /// [`KernelNames`] to translate kernel names back.
+    pub fn kernel_names(&self) -> &KernelNames {
+        &self.kernel_names
+    }
+
+    /// Returns a reference to the map called `kernel_name` in the kernel.
+    ///
+    /// Returns `None` if there is no such map, or if several maps have the
+    /// same kernel name.
+    pub fn map_by_kernel_name(&self, kernel_name: &str) -> Option<&Map> {
+        match self.kernel_names.maps_with_kernel_name(kernel_name)[..] {
+            [name] => self.maps.get(name),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the program called `kernel_name` in the kernel.
+    ///
+    /// Returns `None` if there is no such program, or if several programs
+    /// have the same kernel name.
+    pub fn program_by_kernel_name(&self, kernel_name: &str) -> Option<&Program> {
+        match self.kernel_names.programs_with_kernel_name(kernel_name)[..] {
+            [name] => self.programs.get(name),
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable reference to the program called `kernel_name` in the
+    /// kernel.
+    ///
+    /// Returns `None` if there is no such program, or if several programs
+    /// have the same kernel name.
+    pub fn program_by_kernel_name_mut(&mut self, kernel_name: &str) -> Option<&mut Program> {
+        match self.kernel_names.programs_with_kernel_name(kernel_name)[..] {
+            [name] => self.programs.get_mut(name),
+            _ => None,
+        }
+    }
+
     /// Returns a mutable reference to the map with the given name.
     ///
     /// The returned type is mostly opaque. In order to do anything useful with it you need to
@@ -774,6 +1559,28 @@ impl Bpf {
         self.maps.remove(name)
     }
 
+    /// Turns the instance into a [`SharedBpf`], to use its programs and maps
+    /// from many threads at once.
+    ///
+    /// Only the programs and maps are kept, so global variables must be
+    /// updated with [`Bpf::set_global`] before.
+    pub fn into_shared(self) -> SharedBpf {
+        let Bpf { maps, programs, .. } = self;
+        SharedBpf {
+            maps: Arc::new(
+                maps.into_iter()
+                    .map(|(name, map)| (name, RwLock::new(map)))
+                    .collect(),
+            ),
+            programs: Arc::new(
+                programs
+                    .into_iter()
+                    .map(|(name, program)| (name, Mutex::new(program)))
+                    .collect(),
+            ),
+        }
+    }
+
     /// An iterator over all the maps.
     ///
     /// # Examples
@@ -791,6 +1598,134 @@ impl Bpf {
         self.maps.iter().map(|(name, map)| (name.as_str(), map))
     }
 
+    /// Returns the number of bytes of kernel memory used by all the maps.
+    ///
+    /// The usage of each map can be obtained with
+    /// [`Map::memory_usage`](crate::maps::Map::memory_usage).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// println!("maps use {} bytes", bpf.memory_usage()?);
+    /// for (name, map) in bpf.maps() {
+    ///     println!("{}: {} bytes", name, map.memory_usage()?);
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn memory_usage(&self) -> Result<u64, MapError> {
+        self.maps.values().map(|map| map.memory_usage()).sum()
+    }
+
+    /// Sets the value of a global variable of the loaded object.
+    ///
+    /// Unlike [`BpfLoader::set_global`], this can be called after the
+    /// programs are loaded, and the new value is seen by running programs.
+    /// Only variables stored in the `.data` and `.bss` sections can be
+    /// changed, since `.rodata` is frozen at load time.
+    ///
+    /// The whole section is read and written back, so a concurrent write by a
+    /// program to another variable of the same section can be lost.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// bpf.set_global("SAMPLE_RATE", &100u32)?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn set_global<V: Pod>(&mut self, name: &str, value: &V) -> Result<(), BpfError> {
+        self.write_global(name, unsafe { bytes_of(value) })
+    }
+
+    /// Turns the runtime toggle with the given name on or off.
+    ///
+    /// A toggle is either an integer global variable stored in the `.data` or
+    /// `.bss` section, or an [`Array`](crate::maps::Array) map with a single
+    /// integer entry. Integers of 1, 2, 4 and 8 bytes are supported (this
+    /// includes `bool`). The toggle is set to `1` when enabled and to `0`
+    /// otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// // in the eBPF program: `static mut FEATURE_X: u8 = 0;`
+    /// bpf.toggle("FEATURE_X", true)?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn toggle(&mut self, name: &str, enabled: bool) -> Result<(), BpfError> {
+        if let Some(var) = self.globals.get(name) {
+            let value = toggle_value(name, var.size, enabled)?;
+            return self.write_global(name, &value);
+        }
+
+        match self.maps.get(name) {
+            Some(Map::Array(map)) if map.obj.max_entries() == 1 => {
+                let mut value = toggle_value(name, map.obj.value_size() as usize, enabled)?;
+                let fd = map.fd_or_err()?;
+                bpf_map_update_elem_ptr(fd, &0u32, value.as_mut_ptr(), 0).map_err(
+                    |(_, io_error)| MapError::SyscallError {
+                        call: "bpf_map_update_elem".to_owned(),
+                        io_error,
+                    },
+                )?;
+                Ok(())
+            }
+            _ => Err(BpfError::GlobalNotFound {
+                name: name.to_owned(),
+            }),
+        }
+    }
+
+    fn write_global(&mut self, name: &str, data: &[u8]) -> Result<(), BpfError> {
+        let var = self
+            .globals
+            .get(name)
+            .ok_or_else(|| BpfError::GlobalNotFound {
+                name: name.to_owned(),
+            })?;
+        if data.len() != var.size {
+            return Err(BpfError::InvalidGlobalSize {
+                name: name.to_owned(),
+                size: var.size,
+                data_size: data.len(),
+            });
+        }
+        let map = self
+            .maps
+            .get(&var.map)
+            .ok_or_else(|| BpfError::GlobalNotFound {
+                name: name.to_owned(),
+            })?
+            .data();
+        let fd = map.fd_or_err()?;
+
+        let mut value = vec![0u8; map.obj.value_size() as usize];
+        let end = var.offset + var.size;
+        if end > value.len() {
+            return Err(BpfError::InvalidGlobalSize {
+                name: name.to_owned(),
+                size: var.size,
+                data_size: data.len(),
+            });
+        }
+        bpf_map_lookup_elem_ptr(fd, Some(&0u32), value.as_mut_ptr(), 0).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            },
+        )?;
+        value[var.offset..end].copy_from_slice(data);
+        bpf_map_update_elem_ptr(fd, &0u32, value.as_mut_ptr(), 0).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+
     /// Returns a reference to the program with the given name.
     ///
     /// You can use this to inspect a program and its properties. To load and attach a program, use
@@ -831,6 +1766,70 @@ impl Bpf {
         self.programs.get_mut(name)
     }
 
+    /// Takes ownership of the program with the given name.
+    ///
+    /// Use this when a program and its links must outlive the rest of the
+    /// `Bpf` instance, for example to hand them to a long-lived task. Once
+    /// a program is loaded, the maps it uses stay alive in the kernel for as
+    /// long as the program does, even if the `Bpf` instance is dropped. The
+    /// program must therefore be loaded before the `Bpf` instance is
+    /// dropped. The returned program is unloaded on `Drop`, detaching all
+    /// the links it still owns.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::{Xdp, XdpFlags};
+    ///
+    /// let xdp: &mut Xdp = bpf.program_mut("xdp_filter").unwrap().try_into()?;
+    /// xdp.load()?;
+    /// xdp.attach("eth0", XdpFlags::default())?;
+    ///
+    /// let program = bpf.take_program("xdp_filter").unwrap();
+    /// drop(bpf);
+    /// std::thread::spawn(move || {
+    ///     // the program stays attached until it's dropped
+    ///     let _program = program;
+    /// });
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn take_program(&mut self, name: &str) -> Option<Program> {
+        self.programs.remove(name)
+    }
+
+    /// Registers the struct_ops implementation `name` with the kernel.
+    ///
+    /// `name` is the name of the variable defining the implementation in the
+    /// `.struct_ops` or `.struct_ops.link` section. The [`StructOps`]
+    /// programs it references are loaded if they aren't yet, and the kernel
+    /// types are looked up in `btf`. The implementation is unregistered when
+    /// the returned link is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::Btf;
+    ///
+    /// let btf = Btf::from_sys_fs()?;
+    /// let link = bpf.register_struct_ops("bpf_dctcp", &btf)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn register_struct_ops(
+        &mut self,
+        name: &str,
+        btf: &Btf,
+    ) -> Result<StructOpsLink, ProgramError> {
+        let ops = self
+            .struct_ops
+            .get(name)
+            .ok_or_else(|| StructOpsError::NotFound {
+                name: name.to_owned(),
+            })?;
+        struct_ops::register(name, ops, &mut self.programs, btf)
+    }
+
     /// An iterator over all the programs.
     ///
     /// # Examples
@@ -871,6 +1870,213 @@ impl Bpf {
     pub fn programs_mut(&mut self) -> impl Iterator<Item = (&str, &mut Program)> {
         self.programs.iter_mut().map(|(s, p)| (s.as_str(), p))
     }
+
+    /// Loads programs concurrently using up to `threads` threads.
+    ///
+    /// Verifying large programs is CPU bound, so loading the programs of an
+    /// object in parallel can considerably reduce the total load time. `load`
+    /// is called once for every program, from one of the worker threads, and
+    /// is responsible for calling the type specific `load` method, or for
+    /// skipping the program. Programs that aren't marked for
+    /// [autoload](Program::autoload) are skipped.
+    ///
+    /// All the programs are attempted even if some fail to load. The error for
+    /// the first program that failed is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::{programs::Program, Btf};
+    ///
+    /// let btf = Btf::from_sys_fs()?;
+    /// bpf.load_programs(4, |name, program| match program {
+    ///     Program::KProbe(p) => p.load(),
+    ///     Program::Xdp(p) => p.load(),
+    ///     Program::FEntry(p) => p.load(name, &btf),
+    ///     _ => Ok(()),
+    /// })?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn load_programs<F>(&mut self, threads: usize, load: F) -> Result<(), BpfError>
+    where
+        F: Fn(&str, &mut Program) -> Result<(), ProgramError> + Sync,
+    {
+        let threads = threads.clamp(1, self.programs.len().max(1));
+        let queue = Mutex::new(self.programs.iter_mut().filter(|(_, p)| p.autoload()));
+        let errors = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    let next = queue.lock().unwrap().next();
+                    let (name, program) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    if let Err(error) = load(name, program) {
+                        errors.lock().unwrap().push((name.clone(), error));
+                    }
+                });
+            }
+        });
+
+        match errors.into_inner().unwrap().into_iter().next() {
+            Some((name, error)) => Err(BpfError::LoadProgramError { name, error }),
+            None => Ok(()),
+        }
+    }
+}
+
+// Runs `f` on the tokio blocking thread pool and returns its result,
+// propagating panics to the caller.
+#[cfg(feature = "async_tokio")]
+async fn spawn_blocking<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(ret) => ret,
+        // blocking tasks can't be cancelled, so the task panicked
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// A parsed eBPF object.
+///
+/// Parsing an object can be done once, and the result can then be loaded
+/// multiple times, for example once per network interface, with
+/// [`BpfLoader::load_object`].
+#[derive(Clone)]
+#[doc(alias = "Object")]
+pub struct BpfObject {
+    obj: Object,
+}
+
+impl BpfObject {
+    /// Parses eBPF bytecode from a buffer.
+    pub fn parse(data: &[u8]) -> Result<BpfObject, BpfError> {
+        Ok(BpfObject {
+            obj: Object::parse(data)?,
+        })
+    }
+
+    /// Parses eBPF bytecode from a file.
+    pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<BpfObject, BpfError> {
+        let path = path.as_ref();
+        BpfObject::parse(&fs::read(path).map_err(|error| BpfError::FileError {
+            path: path.to_owned(),
+            error,
+        })?)
+    }
+
+    /// Links the programs, maps and functions of `other` into this object, so
+    /// that both can be loaded together.
+    ///
+    /// This is meant for programs split across several compilation units,
+    /// which then each contain a copy of the functions they have in common.
+    /// A function of `other` that is identical to a function of this object
+    /// is deduplicated: the programs of `other` are linked with the existing
+    /// copy and its BTF func info. The BTF of both objects is merged, so it's
+    /// loaded once for all the programs.
+    ///
+    /// Programs and maps, including the `.data`, `.bss` and `.rodata`
+    /// sections, must not be defined by both objects.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aya::{BpfLoader, BpfObject};
+    ///
+    /// let mut object = BpfObject::parse_file("ingress.o")?;
+    /// object.link(BpfObject::parse_file("egress.o")?)?;
+    /// let bpf = BpfLoader::new().load_object(&object)?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn link(&mut self, other: BpfObject) -> Result<(), BpfError> {
+        Ok(self.obj.link(other.obj)?)
+    }
+
+    /// Estimates the resources needed to load the object.
+    ///
+    /// This can be used to decide whether to load an object before creating
+    /// any map or program. The map memory is an estimate based on the map
+    /// definitions in the object, see [`ResourceEstimate::map_memory`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aya::BpfObject;
+    ///
+    /// let object = BpfObject::parse_file("file.o")?;
+    /// let estimate = object.estimate_resources();
+    /// if estimate.map_memory > 64 * 1024 * 1024 {
+    ///     panic!("refusing to lock {} bytes", estimate.map_memory);
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn estimate_resources(&self) -> ResourceEstimate {
+        // per-CPU maps are sized using the number of possible CPUs
+        let nr_cpus = nr_cpus().unwrap_or(1);
+        let subprogram_instructions = self
+            .obj
+            .functions
+            .values()
+            .map(|f| f.instructions.len())
+            .sum::<usize>();
+        let program_instructions = self
+            .obj
+            .programs
+            .values()
+            .map(|p| p.function.instructions.len());
+
+        ResourceEstimate {
+            map_memory: self
+                .obj
+                .maps
+                .values()
+                .map(|m| m.memory_estimate(nr_cpus))
+                .sum(),
+            maps: self.obj.maps.len(),
+            programs: self.obj.programs.len(),
+            instructions: program_instructions.clone().sum::<usize>() + subprogram_instructions,
+            max_program_instructions: program_instructions.max().unwrap_or(0),
+        }
+    }
+}
+
+/// The resources needed to load a [`BpfObject`].
+///
+/// See [`BpfObject::estimate_resources`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResourceEstimate {
+    /// The estimated number of bytes of kernel memory used by the maps.
+    ///
+    /// This counts against `RLIMIT_MEMLOCK` on kernels older than 5.11, and
+    /// against the memory cgroup on newer kernels. Maps are assumed to be
+    /// full, and the fixed overhead of each map isn't included. Once loaded,
+    /// the actual usage is returned by [`Bpf::memory_usage`].
+    pub map_memory: u64,
+    /// The number of maps, including the maps holding global data.
+    pub maps: usize,
+    /// The number of programs.
+    pub programs: usize,
+    /// The number of instructions of the programs and of the functions they
+    /// call.
+    pub instructions: usize,
+    /// The number of instructions of the largest program, not counting the
+    /// functions it calls.
+    pub max_program_instructions: usize,
+}
+
+impl fmt::Debug for BpfObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BpfObject")
+            .field("programs", &self.obj.programs.keys().collect::<Vec<_>>())
+            .field("maps", &self.obj.maps.keys().collect::<Vec<_>>())
+            .finish()
+    }
 }
 
 /// The error type returned by [`Bpf::load_file`] and [`Bpf::load`].
@@ -933,6 +2139,116 @@ pub enum BpfError {
     #[error("program error")]
     /// A program error
     ProgramError(#[from] ProgramError),
+
+    #[error("pin error")]
+    /// A pinning error
+    PinError(#[from] PinError),
+
+    /// The global variable or toggle was not found
+    #[error("global variable `{name}` not found")]
+    GlobalNotFound {
+        /// The variable name
+        name: String,
+    },
+
+    /// The size of the value doesn't match the size of the global variable
+    #[error("invalid value of {data_size} bytes for global variable `{name}` of {size} bytes")]
+    InvalidGlobalSize {
+        /// The variable name
+        name: String,
+        /// The size of the variable
+        size: usize,
+        /// The size of the value
+        data_size: usize,
+    },
+
+    /// The toggle is not an integer of a supported size
+    #[error("`{name}` of {size} bytes can't be used as a toggle")]
+    InvalidToggle {
+        /// The toggle name
+        name: String,
+        /// The size of the toggle value
+        size: usize,
+    },
+
+    /// Error loading a program with [`Bpf::load_programs`]
+    #[error("error loading program `{name}`")]
+    LoadProgramError {
+        /// The program name
+        name: String,
+        #[source]
+        /// The original error
+        error: ProgramError,
+    },
+}
+
+impl BpfError {
+    /// Returns the name of the failed syscall and the [`io::Error`] it
+    /// returned, if this error was caused by a syscall failure.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aya::Bpf;
+    ///
+    /// if let Err(e) = Bpf::load_file("file.o") {
+    ///     if let Some((call, io_error)) = e.syscall_error() {
+    ///         if io_error.raw_os_error() == Some(libc::EPERM) {
+    ///             eprintln!("`{}` requires more privileges", call);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn syscall_error(&self) -> Option<(&str, &io::Error)> {
+        match self {
+            BpfError::BtfError(BtfError::LoadError { io_error, .. }) => {
+                Some(("bpf_btf_load", io_error))
+            }
+            BpfError::MapError(e) => e.syscall_error(),
+            BpfError::PinError(PinError::SyscallError { name, io_error }) => Some((name, io_error)),
+            BpfError::ProgramError(e) | BpfError::LoadProgramError { error: e, .. } => {
+                e.syscall_error()
+            }
+            _ => None,
+        }
+    }
+}
+
+// Returns the native endian representation of a toggle of the given size.
+fn toggle_value(name: &str, size: usize, enabled: bool) -> Result<Vec<u8>, BpfError> {
+    let value = enabled as u64;
+    Ok(match size {
+        1 => vec![value as u8],
+        2 => (value as u16).to_ne_bytes().to_vec(),
+        4 => (value as u32).to_ne_bytes().to_vec(),
+        8 => value.to_ne_bytes().to_vec(),
+        _ => {
+            return Err(BpfError::InvalidToggle {
+                name: name.to_owned(),
+                size,
+            })
+        }
+    })
+}
+
+// Returns the names of the objects pinned in a PinHandoff subdirectory.
+fn pinned_names(dir: &Path) -> Result<Vec<String>, BpfError> {
+    let file_error = |error| BpfError::FileError {
+        path: dir.to_owned(),
+        error,
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(file_error(e)),
+    };
+    entries
+        .map(|entry| {
+            entry
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .map_err(file_error)
+        })
+        .collect()
 }
 
 fn load_btf(raw_btf: Vec<u8>) -> Result<RawFd, BtfError> {
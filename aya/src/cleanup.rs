@@ -0,0 +1,590 @@
+//! Removing the attachments and pins left behind by a crashed process.
+//!
+//! Programs and maps are released by the kernel once nothing references them,
+//! which for most attachments happens when the process that created them
+//! exits. XDP and tc programs attached with netlink, programs attached to
+//! cgroups with `BPF_PROG_ATTACH` and pinned objects are kept though, and a
+//! process that crashed can't detach them. [`Cleanup`] finds them and removes
+//! them.
+//!
+//! Objects are recognized as belonging to the process by a naming convention:
+//! the names of its programs start with a common prefix, and its maps,
+//! programs and links are pinned under a common directory.
+use std::{
+    ffi::CString,
+    fs, io,
+    os::unix::{ffi::OsStrExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+use libc::{close, if_freenameindex, if_nameindex, EBUSY, EINVAL, ENOENT, EOPNOTSUPP};
+use thiserror::Error;
+
+use crate::{
+    generated::bpf_attach_type::{self, *},
+    programs::{query, ProgramError, ProgramInfo, TcAttachType},
+    sys::{
+        bpf_fd_kind, bpf_get_object, bpf_link_detach, bpf_prog_detach, bpf_prog_get_fd_by_id,
+        netlink_filters, netlink_qdisc_detach, netlink_set_xdp_fd, netlink_xdp_programs, BpfFdKind,
+    },
+    util::kernel_object_name,
+};
+
+// the attach types of cgroup programs
+const CGROUP_ATTACH_TYPES: &[bpf_attach_type] = &[
+    BPF_CGROUP_INET_INGRESS,
+    BPF_CGROUP_INET_EGRESS,
+    BPF_CGROUP_INET_SOCK_CREATE,
+    BPF_CGROUP_INET_SOCK_RELEASE,
+    BPF_CGROUP_SOCK_OPS,
+    BPF_CGROUP_DEVICE,
+    BPF_CGROUP_INET4_BIND,
+    BPF_CGROUP_INET6_BIND,
+    BPF_CGROUP_INET4_CONNECT,
+    BPF_CGROUP_INET6_CONNECT,
+    BPF_CGROUP_INET4_POST_BIND,
+    BPF_CGROUP_INET6_POST_BIND,
+    BPF_CGROUP_UDP4_SENDMSG,
+    BPF_CGROUP_UDP6_SENDMSG,
+    BPF_CGROUP_UDP4_RECVMSG,
+    BPF_CGROUP_UDP6_RECVMSG,
+    BPF_CGROUP_INET4_GETPEERNAME,
+    BPF_CGROUP_INET6_GETPEERNAME,
+    BPF_CGROUP_INET4_GETSOCKNAME,
+    BPF_CGROUP_INET6_GETSOCKNAME,
+    BPF_CGROUP_SYSCTL,
+    BPF_CGROUP_GETSOCKOPT,
+    BPF_CGROUP_SETSOCKOPT,
+];
+
+/// The error type returned by [`Cleanup::run`].
+#[derive(Debug, Error)]
+pub enum CleanupError {
+    /// Listing or detaching programs failed.
+    #[error(transparent)]
+    ProgramError(#[from] ProgramError),
+
+    /// An error occurred making a syscall.
+    #[error("{call} failed")]
+    SyscallError {
+        /// The syscall name.
+        call: String,
+        /// The [`io::Error`] returned by the syscall.
+        #[source]
+        io_error: io::Error,
+    },
+
+    /// The program name prefix is empty, so every program would be removed.
+    #[error("the program name prefix is empty")]
+    EmptyPrefix,
+
+    /// A pin directory or a cgroup couldn't be accessed.
+    #[error("failed to access `{}`", path.display())]
+    IoError {
+        /// The path.
+        path: PathBuf,
+        /// The [`io::Error`] of the access.
+        #[source]
+        io_error: io::Error,
+    },
+}
+
+/// An object removed by [`Cleanup::run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CleanupAction {
+    /// A pinned map or program was unpinned.
+    Unpin {
+        /// The pin path.
+        path: PathBuf,
+    },
+    /// A pinned link was detached and unpinned.
+    DetachLink {
+        /// The pin path.
+        path: PathBuf,
+    },
+    /// An XDP program was detached from an interface.
+    DetachXdp {
+        /// The index of the interface.
+        if_index: u32,
+        /// The id of the program.
+        program_id: u32,
+    },
+    /// A tc classifier was detached from an interface.
+    DetachTc {
+        /// The index of the interface.
+        if_index: u32,
+        /// The attach type of the classifier.
+        attach_type: TcAttachType,
+        /// The priority of the classifier.
+        priority: u16,
+        /// The name of the classifier.
+        name: String,
+    },
+    /// A program was detached from a cgroup.
+    DetachCgroup {
+        /// The path of the cgroup.
+        path: PathBuf,
+        /// The id of the program.
+        program_id: u32,
+    },
+}
+
+/// Finds and removes the attachments and pins of the programs of a crashed
+/// process.
+///
+/// Every program whose name starts with the given prefix is detached from the
+/// network interfaces and from the cgroups added with [`Cleanup::cgroup`],
+/// and everything pinned under the directories added with
+/// [`Cleanup::pin_path`] is unpinned. Pinned links are detached before they're
+/// unpinned, so that the programs they attach are released even if another
+/// process holds a reference to the link.
+///
+/// The cleanup doesn't check that the process is gone, so running it while
+/// the process is still running detaches its programs.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::cleanup::Cleanup;
+///
+/// let removed = Cleanup::new("myagent_")
+///     .pin_path("/sys/fs/bpf/myagent")
+///     .cgroup("/sys/fs/cgroup/myagent")
+///     .run()?;
+/// for action in removed {
+///     println!("removed {:?}", action);
+/// }
+/// # Ok::<(), aya::cleanup::CleanupError>(())
+/// ```
+#[derive(Debug)]
+pub struct Cleanup {
+    prefix: String,
+    pin_paths: Vec<PathBuf>,
+    cgroups: Vec<PathBuf>,
+    dry_run: bool,
+}
+
+impl Cleanup {
+    /// Creates a cleanup of the programs whose name starts with `prefix`.
+    ///
+    /// Program names are truncated by the kernel, so the names of the
+    /// programs should fit in 15 bytes, and so should the prefix. The prefix
+    /// must not be empty, see [`Cleanup::run`].
+    pub fn new(prefix: &str) -> Cleanup {
+        Cleanup {
+            prefix: prefix.to_owned(),
+            pin_paths: Vec::new(),
+            cgroups: Vec::new(),
+            dry_run: false,
+        }
+    }
+
+    /// Adds a directory of the BPF filesystem whose content is unpinned.
+    ///
+    /// The directory itself is kept, the directories it contains are removed
+    /// once empty.
+    pub fn pin_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Cleanup {
+        self.pin_paths.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Adds a cgroup whose programs are detached.
+    ///
+    /// Cgroups can't be listed efficiently, so only the programs attached to
+    /// the added cgroups are detached, not the ones of their descendants.
+    pub fn cgroup<P: AsRef<Path>>(&mut self, path: P) -> &mut Cleanup {
+        self.cgroups.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Only reports the objects that would be removed, without removing them.
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Cleanup {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Removes the objects, and returns what was removed.
+    ///
+    /// Objects that disappear while the cleanup runs, for example because
+    /// the programs are unloaded concurrently, are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CleanupError::EmptyPrefix`] if the prefix is empty, since
+    /// every program of the system would match it.
+    pub fn run(&self) -> Result<Vec<CleanupAction>, CleanupError> {
+        if self.prefix.is_empty() {
+            return Err(CleanupError::EmptyPrefix);
+        }
+        let mut actions = Vec::new();
+        // pinned links are removed first, since the XDP programs they attach
+        // can't be detached with netlink
+        for path in &self.pin_paths {
+            self.unpin_all(path, &mut actions)?;
+        }
+        self.detach_xdp(&mut actions)?;
+        self.detach_tc(&mut actions)?;
+        for path in &self.cgroups {
+            self.detach_cgroup(path, &mut actions)?;
+        }
+        Ok(actions)
+    }
+
+    fn owns(&self, kernel_name: &str) -> bool {
+        kernel_name.starts_with(kernel_object_name(&self.prefix))
+    }
+
+    // Returns whether the program with the given id belongs to the process.
+    fn owns_program(&self, id: u32) -> Result<bool, CleanupError> {
+        match ProgramInfo::from_id(id) {
+            Ok(info) => Ok(matches!(info.name_as_str(), Some(name) if self.owns(name))),
+            Err(ProgramError::SyscallError { io_error, .. })
+                if io_error.raw_os_error() == Some(ENOENT) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn detach_xdp(&self, actions: &mut Vec<CleanupAction>) -> Result<(), CleanupError> {
+        let programs =
+            unsafe { netlink_xdp_programs() }.map_err(|io_error| CleanupError::SyscallError {
+                call: "netlink_xdp_programs".to_owned(),
+                io_error,
+            })?;
+        for (if_index, program_id, mode) in programs {
+            if !self.owns_program(program_id)? {
+                continue;
+            }
+            if !self.dry_run {
+                match unsafe { netlink_set_xdp_fd(if_index, -1, None, mode) } {
+                    Ok(()) => {}
+                    // the program is attached with a link that isn't pinned
+                    // under the cleaned up paths, and can't be detached here
+                    Err(e) if e.raw_os_error() == Some(EBUSY) => continue,
+                    Err(e) => {
+                        return Err(CleanupError::SyscallError {
+                            call: "netlink_set_xdp_fd".to_owned(),
+                            io_error: e.into(),
+                        })
+                    }
+                }
+            }
+            actions.push(CleanupAction::DetachXdp {
+                if_index: if_index as u32,
+                program_id,
+            });
+        }
+        Ok(())
+    }
+
+    fn detach_tc(&self, actions: &mut Vec<CleanupAction>) -> Result<(), CleanupError> {
+        for if_index in interfaces() {
+            for attach_type in [TcAttachType::Ingress, TcAttachType::Egress] {
+                // interfaces without a clsact qdisc have no filters to dump
+                let filters = match unsafe { netlink_filters(if_index as i32, attach_type) } {
+                    Ok(filters) => filters,
+                    Err(io_error) if matches!(io_error.raw_os_error(), Some(EINVAL | ENOENT)) => {
+                        continue
+                    }
+                    Err(io_error) => {
+                        return Err(CleanupError::SyscallError {
+                            call: "netlink_filters".to_owned(),
+                            io_error,
+                        })
+                    }
+                };
+                for filter in filters {
+                    let priority = filter.priority;
+                    let name = match filter.name {
+                        Some(name) => name.to_string_lossy().into_owned(),
+                        None => continue,
+                    };
+                    if !name.starts_with(&self.prefix) {
+                        continue;
+                    }
+                    if !self.dry_run {
+                        unsafe { netlink_qdisc_detach(if_index as i32, &attach_type, priority) }
+                            .map_err(|e| CleanupError::SyscallError {
+                                call: "netlink_qdisc_detach".to_owned(),
+                                io_error: e.into(),
+                            })?;
+                    }
+                    actions.push(CleanupAction::DetachTc {
+                        if_index,
+                        attach_type,
+                        priority,
+                        name,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn detach_cgroup(
+        &self,
+        path: &Path,
+        actions: &mut Vec<CleanupAction>,
+    ) -> Result<(), CleanupError> {
+        let cgroup = fs::File::open(path).map_err(|io_error| CleanupError::IoError {
+            path: path.to_owned(),
+            io_error,
+        })?;
+        for attach_type in CGROUP_ATTACH_TYPES {
+            let program_ids = match query(cgroup.as_raw_fd(), *attach_type, 0, &mut None) {
+                Ok(ids) => ids,
+                // the attach type isn't supported by the kernel
+                Err(ProgramError::SyscallError { io_error, .. })
+                    if io_error.raw_os_error() == Some(EINVAL) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+            for program_id in program_ids {
+                if !self.owns_program(program_id)? {
+                    continue;
+                }
+                if !self.dry_run {
+                    let prog_fd = match bpf_prog_get_fd_by_id(program_id) {
+                        Ok(fd) => fd,
+                        Err(io_error) if io_error.raw_os_error() == Some(ENOENT) => continue,
+                        Err(io_error) => {
+                            return Err(CleanupError::SyscallError {
+                                call: "bpf_prog_get_fd_by_id".to_owned(),
+                                io_error,
+                            })
+                        }
+                    };
+                    let ret = bpf_prog_detach(prog_fd, cgroup.as_raw_fd(), *attach_type);
+                    unsafe { close(prog_fd) };
+                    ret.map_err(|(_, io_error)| CleanupError::SyscallError {
+                        call: "bpf_prog_detach".to_owned(),
+                        io_error,
+                    })?;
+                }
+                actions.push(CleanupAction::DetachCgroup {
+                    path: path.to_owned(),
+                    program_id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn unpin_all(&self, dir: &Path, actions: &mut Vec<CleanupAction>) -> Result<(), CleanupError> {
+        let io_error = |path: &Path| {
+            let path = path.to_owned();
+            move |io_error| CleanupError::IoError { path, io_error }
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(io_error(dir)(e)),
+        };
+        for entry in entries {
+            let entry = entry.map_err(io_error(dir))?;
+            let path = entry.path();
+            if entry.file_type().map_err(io_error(&path))?.is_dir() {
+                self.unpin_all(&path, actions)?;
+                if !self.dry_run {
+                    // other files may have been pinned in the meantime
+                    let _ = fs::remove_dir(&path);
+                }
+                continue;
+            }
+
+            let action = if self.unpin(&path)? {
+                CleanupAction::DetachLink { path }
+            } else {
+                CleanupAction::Unpin { path }
+            };
+            actions.push(action);
+        }
+        Ok(())
+    }
+
+    // Unpins the object at `path`, detaching it first if it's a link. Returns
+    // whether the object is a link.
+    fn unpin(&self, path: &Path) -> Result<bool, CleanupError> {
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let is_link = match bpf_get_object(&c_path) {
+            Ok(fd) => {
+                let fd = fd as i32;
+                let is_link = bpf_fd_kind(fd) == Some(BpfFdKind::Link);
+                let ret = if is_link && !self.dry_run {
+                    bpf_link_detach(fd)
+                } else {
+                    Ok(0)
+                };
+                unsafe { close(fd) };
+                match ret {
+                    // links that can't be detached are released once unpinned
+                    Err((_, io_error))
+                        if !matches!(io_error.raw_os_error(), Some(EINVAL | EOPNOTSUPP)) =>
+                    {
+                        return Err(CleanupError::SyscallError {
+                            call: "bpf_link_detach".to_owned(),
+                            io_error,
+                        });
+                    }
+                    _ => is_link,
+                }
+            }
+            Err(_) => false,
+        };
+        if !self.dry_run {
+            fs::remove_file(path).map_err(|io_error| CleanupError::IoError {
+                path: path.to_owned(),
+                io_error,
+            })?;
+        }
+        Ok(is_link)
+    }
+}
+
+// Returns the indexes of the network interfaces.
+fn interfaces() -> Vec<u32> {
+    let mut indexes = Vec::new();
+    // Safety: libc wrappers, the array ends with a zeroed entry
+    unsafe {
+        let names = if_nameindex();
+        if names.is_null() {
+            return indexes;
+        }
+        let mut entry = names;
+        while (*entry).if_index != 0 {
+            indexes.push((*entry).if_index);
+            entry = entry.add(1);
+        }
+        if_freenameindex(names);
+    }
+    indexes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, fs::File, os::unix::io::IntoRawFd};
+
+    use crate::{
+        generated::{bpf_cmd, bpf_prog_info},
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    thread_local! {
+        // the number of detach syscalls made on this thread
+        static DETACHES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn fake_fd() -> i64 {
+        File::open("/dev/null").unwrap().into_raw_fd() as i64
+    }
+
+    fn fake_syscall(call: Syscall) -> SysResult {
+        match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_QUERY,
+                attr,
+            } => {
+                // the other ids are left zeroed, see BPF_PROG_GET_FD_BY_ID
+                unsafe { *(attr.query.prog_ids as *mut u32) = 7 };
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_GET_FD_BY_ID,
+                attr,
+            } => match unsafe { attr.__bindgen_anon_6.__bindgen_anon_1.prog_id } {
+                7 => Ok(fake_fd()),
+                _ => Err((-1, io::Error::from_raw_os_error(ENOENT))),
+            },
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET,
+                ..
+            } => Ok(fake_fd()),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_prog_info) };
+                info.id = 7;
+                for (dst, src) in info.name.iter_mut().zip(b"agent_dev") {
+                    *dst = *src as _;
+                }
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_DETACH | bpf_cmd::BPF_LINK_DETACH,
+                ..
+            } => {
+                DETACHES.with(|detaches| detaches.set(detaches.get() + 1));
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        }
+    }
+
+    #[test]
+    fn test_owns() {
+        let cleanup = Cleanup::new("agent_");
+        assert!(cleanup.owns("agent_xdp"));
+        assert!(!cleanup.owns("other_xdp"));
+        assert!(!cleanup.owns("agent"));
+
+        // the kernel names are truncated to 15 bytes
+        let cleanup = Cleanup::new("a_very_long_prefix_");
+        assert!(cleanup.owns("a_very_long_pre"));
+        assert!(!cleanup.owns("a_very_long"));
+    }
+
+    #[test]
+    fn test_unpin_missing_dir() {
+        let mut actions = Vec::new();
+        Cleanup::new("agent_")
+            .unpin_all(Path::new("/sys/fs/bpf/does-not-exist"), &mut actions)
+            .unwrap();
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn test_empty_prefix() {
+        assert!(matches!(
+            Cleanup::new("").run(),
+            Err(CleanupError::EmptyPrefix)
+        ));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        override_syscall(fake_syscall);
+
+        let cgroup = std::env::temp_dir();
+        let pins = cgroup.join(format!("aya-cleanup-test-{}", std::process::id()));
+        fs::create_dir_all(&pins).unwrap();
+        let pin = pins.join("agent_map");
+        File::create(&pin).unwrap();
+
+        let mut cleanup = Cleanup::new("agent_");
+        cleanup.dry_run(true);
+        let mut actions = Vec::new();
+        cleanup.detach_cgroup(&cgroup, &mut actions).unwrap();
+        cleanup.unpin_all(&pins, &mut actions).unwrap();
+
+        let expected = CGROUP_ATTACH_TYPES
+            .iter()
+            .map(|_| CleanupAction::DetachCgroup {
+                path: cgroup.clone(),
+                program_id: 7,
+            })
+            .chain([CleanupAction::Unpin { path: pin.clone() }])
+            .collect::<Vec<_>>();
+        assert_eq!(actions, expected);
+        assert_eq!(DETACHES.with(|detaches| detaches.get()), 0);
+        assert!(pin.exists());
+
+        fs::remove_dir_all(&pins).unwrap();
+    }
+}
@@ -275,6 +275,12 @@ pub enum bpf_cmd {
     BPF_LINK_DETACH = 34,
     BPF_PROG_BIND_MAP = 35,
 }
+impl bpf_map_type {
+    pub const BPF_MAP_TYPE_CGROUP_STORAGE: bpf_map_type =
+        bpf_map_type::BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED;
+    pub const BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE: bpf_map_type =
+        bpf_map_type::BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE_DEPRECATED;
+}
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub enum bpf_map_type {
@@ -297,9 +303,9 @@ pub enum bpf_map_type {
     BPF_MAP_TYPE_CPUMAP = 16,
     BPF_MAP_TYPE_XSKMAP = 17,
     BPF_MAP_TYPE_SOCKHASH = 18,
-    BPF_MAP_TYPE_CGROUP_STORAGE = 19,
+    BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED = 19,
     BPF_MAP_TYPE_REUSEPORT_SOCKARRAY = 20,
-    BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE = 21,
+    BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE_DEPRECATED = 21,
     BPF_MAP_TYPE_QUEUE = 22,
     BPF_MAP_TYPE_STACK = 23,
     BPF_MAP_TYPE_SK_STORAGE = 24,
@@ -309,6 +315,10 @@ pub enum bpf_map_type {
     BPF_MAP_TYPE_INODE_STORAGE = 28,
     BPF_MAP_TYPE_TASK_STORAGE = 29,
     BPF_MAP_TYPE_BLOOM_FILTER = 30,
+    BPF_MAP_TYPE_USER_RINGBUF = 31,
+    BPF_MAP_TYPE_CGRP_STORAGE = 32,
+    BPF_MAP_TYPE_ARENA = 33,
+    __MAX_BPF_MAP_TYPE = 34,
 }
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
@@ -345,6 +355,8 @@ pub enum bpf_prog_type {
     BPF_PROG_TYPE_LSM = 29,
     BPF_PROG_TYPE_SK_LOOKUP = 30,
     BPF_PROG_TYPE_SYSCALL = 31,
+    BPF_PROG_TYPE_NETFILTER = 32,
+    __MAX_BPF_PROG_TYPE = 33,
 }
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
@@ -393,7 +405,21 @@ pub enum bpf_attach_type {
     BPF_PERF_EVENT = 41,
     BPF_TRACE_KPROBE_MULTI = 42,
     BPF_LSM_CGROUP = 43,
-    __MAX_BPF_ATTACH_TYPE = 44,
+    BPF_STRUCT_OPS = 44,
+    BPF_NETFILTER = 45,
+    BPF_TCX_INGRESS = 46,
+    BPF_TCX_EGRESS = 47,
+    BPF_TRACE_UPROBE_MULTI = 48,
+    BPF_CGROUP_UNIX_CONNECT = 49,
+    BPF_CGROUP_UNIX_SENDMSG = 50,
+    BPF_CGROUP_UNIX_RECVMSG = 51,
+    BPF_CGROUP_UNIX_GETPEERNAME = 52,
+    BPF_CGROUP_UNIX_GETSOCKNAME = 53,
+    BPF_NETKIT_PRIMARY = 54,
+    BPF_NETKIT_PEER = 55,
+    BPF_TRACE_KPROBE_SESSION = 56,
+    BPF_TRACE_UPROBE_SESSION = 57,
+    __MAX_BPF_ATTACH_TYPE = 58,
 }
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
@@ -2080,3 +2106,5 @@ pub type _bindgen_ty_154 = ::std::os::raw::c_uint;
 pub const AYA_PERF_EVENT_IOC_ENABLE: ::std::os::raw::c_int = 9216;
 pub const AYA_PERF_EVENT_IOC_DISABLE: ::std::os::raw::c_int = 9217;
 pub const AYA_PERF_EVENT_IOC_SET_BPF: ::std::os::raw::c_int = 1074013192;
+pub const AYA_PERF_EVENT_IOC_SET_FILTER: ::std::os::raw::c_int = 1074275334;
+pub const AYA_PERF_EVENT_IOC_PAUSE_OUTPUT: ::std::os::raw::c_int = 1074013193;
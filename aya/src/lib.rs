@@ -45,12 +45,34 @@ extern crate lazy_static;
 #[macro_use]
 extern crate bitflags;
 
+// Enters a `tracing` span until the end of the enclosing scope. Expands to
+// nothing unless the `tracing` feature is enabled.
+macro_rules! trace_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($name $(, $($fields)*)?).entered();
+    };
+}
+
+// Emits a debug `tracing` event. Expands to nothing unless the `tracing`
+// feature is enabled.
+macro_rules! trace_event {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($args)*);
+    };
+}
+
 mod bpf;
+pub mod cleanup;
 mod generated;
+pub mod link_events;
 pub mod maps;
 mod obj;
+pub mod pcap;
 pub mod pin;
 pub mod programs;
+pub mod skel;
 mod sys;
 pub mod util;
 
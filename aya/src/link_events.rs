@@ -0,0 +1,444 @@
+//! Notifications of network interface changes.
+//!
+//! Programs attached to each network interface, like XDP or TC programs,
+//! need to be attached again when interfaces are created, and their
+//! attachments go away when interfaces are removed. [`LinkEvents`]
+//! subscribes to the kernel's `RTNLGRP_LINK` netlink notifications, which
+//! report these changes as they happen.
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    os::unix::io::{AsRawFd, RawFd},
+    time::Duration,
+};
+
+#[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
+use async_io::Async;
+
+#[cfg(feature = "async_tokio")]
+use tokio::io::unix::AsyncFd;
+
+use libc::{poll, pollfd, ENOBUFS, POLLIN, RTNLGRP_LINK};
+
+use crate::{
+    programs::XdpFlags,
+    sys::{netlink_links, NetlinkMonitor, NlLinkEvent},
+};
+
+/// A change of a network interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// An interface was created.
+    Added {
+        /// The index of the interface.
+        if_index: u32,
+        /// The name of the interface.
+        name: String,
+    },
+
+    /// An interface was removed.
+    Removed {
+        /// The index the interface had.
+        if_index: u32,
+        /// The name the interface had.
+        name: String,
+    },
+
+    /// An interface was renamed.
+    Renamed {
+        /// The index of the interface.
+        if_index: u32,
+        /// The previous name of the interface.
+        old_name: String,
+        /// The new name of the interface.
+        new_name: String,
+    },
+
+    /// The XDP programs attached to an interface changed.
+    XdpChanged {
+        /// The index of the interface.
+        if_index: u32,
+        /// The programs now attached to the interface, if any.
+        programs: Vec<XdpAttachment>,
+    },
+}
+
+/// An XDP program attached to an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XdpAttachment {
+    /// The id of the program.
+    pub program_id: u32,
+    /// The mode the program is attached in, one of
+    /// [`XdpFlags::SKB_MODE`], [`XdpFlags::DRV_MODE`] or
+    /// [`XdpFlags::HW_MODE`].
+    pub mode: XdpFlags,
+}
+
+/// A subscription to the changes of the network interfaces.
+///
+/// Each event is reported once, in the order the kernel sent them. The
+/// kernel drops notifications when they aren't read fast enough; the
+/// subscription then lists the interfaces again, and reports the difference
+/// with what it knew as events, so no change is missed even though
+/// intermediate states may be.
+///
+/// `LinkEvents` is an [`Iterator`] that blocks until the next event. It can
+/// also be polled with [`LinkEvents::try_next`] once its file descriptor is
+/// readable, or turned into an `AsyncLinkEvents` with one of the `async_*`
+/// features.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::link_events::{LinkEvent, LinkEvents};
+///
+/// let events = LinkEvents::subscribe()?;
+/// for event in events {
+///     match event? {
+///         LinkEvent::Added { name, .. } => println!("{} was created", name),
+///         LinkEvent::Removed { name, .. } => println!("{} was removed", name),
+///         _ => {}
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct LinkEvents {
+    monitor: NetlinkMonitor,
+    links: LinkTable,
+    pending: VecDeque<LinkEvent>,
+}
+
+impl LinkEvents {
+    /// Subscribes to the changes of the network interfaces.
+    ///
+    /// The interfaces that exist when subscribing aren't reported as
+    /// [`LinkEvent::Added`], see [`LinkEvents::links`].
+    pub fn subscribe() -> Result<LinkEvents, io::Error> {
+        // subscribe before listing the interfaces, so that changes made in
+        // between aren't missed
+        let monitor = NetlinkMonitor::new(RTNLGRP_LINK)?;
+        let mut links = LinkTable::default();
+        // Safety: the request is built by netlink_links
+        links.resync(unsafe { netlink_links() }?);
+        Ok(LinkEvents {
+            monitor,
+            links,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Returns the indices and names of the interfaces, as of the last
+    /// event.
+    pub fn links(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.links
+            .links
+            .iter()
+            .map(|(if_index, link)| (*if_index, link.name.as_str()))
+    }
+
+    /// Returns the next event if one is available, without blocking.
+    pub fn try_next(&mut self) -> Result<Option<LinkEvent>, io::Error> {
+        while self.pending.is_empty() {
+            let events = match self.monitor.recv_links() {
+                Ok(events) if events.is_empty() => return Ok(None),
+                Ok(events) => events,
+                // notifications were dropped, list the interfaces again
+                Err(e) if e.raw_os_error() == Some(ENOBUFS) => {
+                    // Safety: the request is built by netlink_links
+                    let links = unsafe { netlink_links() }?;
+                    self.pending.extend(self.links.resync(links));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            for event in events {
+                self.pending.extend(self.links.update(event));
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    /// Returns the next event, waiting up to `timeout` for one, or forever if
+    /// `timeout` is `None`.
+    ///
+    /// Returns `None` if no event happened before the timeout.
+    pub fn next_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<LinkEvent>, io::Error> {
+        loop {
+            if let Some(event) = self.try_next()? {
+                return Ok(Some(event));
+            }
+            let mut fds = [pollfd {
+                fd: self.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            }];
+            let timeout = timeout.map(|t| t.as_millis() as i32).unwrap_or(-1);
+            // Safety: libc wrapper
+            match unsafe { poll(fds.as_mut_ptr(), 1, timeout) } {
+                0 => return Ok(None),
+                ret if ret < 0 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Turns the subscription into an [`AsyncLinkEvents`].
+    #[cfg(any(feature = "async_tokio", feature = "async_std"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "async_tokio", feature = "async_std"))))]
+    pub fn into_async(self) -> Result<AsyncLinkEvents, io::Error> {
+        let fd = self.as_raw_fd();
+        Ok(AsyncLinkEvents {
+            events: self,
+
+            #[cfg(feature = "async_tokio")]
+            async_fd: AsyncFd::new(fd)?,
+
+            #[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
+            async_fd: Async::new(fd)?,
+        })
+    }
+}
+
+impl Iterator for LinkEvents {
+    type Item = Result<LinkEvent, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_timeout(None).transpose()
+    }
+}
+
+impl AsRawFd for LinkEvents {
+    fn as_raw_fd(&self) -> RawFd {
+        self.monitor.fd()
+    }
+}
+
+/// A `Future` based [`LinkEvents`].
+///
+/// It's returned by [`LinkEvents::into_async`].
+#[cfg(any(feature = "async_tokio", feature = "async_std"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "async_tokio", feature = "async_std"))))]
+pub struct AsyncLinkEvents {
+    events: LinkEvents,
+
+    #[cfg(feature = "async_tokio")]
+    async_fd: AsyncFd<RawFd>,
+
+    #[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
+    async_fd: Async<RawFd>,
+}
+
+#[cfg(any(feature = "async_tokio", feature = "async_std"))]
+impl AsyncLinkEvents {
+    /// Returns the indices and names of the interfaces, as of the last
+    /// event.
+    pub fn links(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.events.links()
+    }
+}
+
+#[cfg(feature = "async_tokio")]
+impl AsyncLinkEvents {
+    /// Waits for the next event.
+    pub async fn next(&mut self) -> Result<LinkEvent, io::Error> {
+        loop {
+            let mut guard = self.async_fd.readable_mut().await?;
+
+            match self.events.try_next()? {
+                Some(event) => return Ok(event),
+                None => guard.clear_ready(),
+            }
+        }
+    }
+}
+
+#[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
+impl AsyncLinkEvents {
+    /// Waits for the next event.
+    pub async fn next(&mut self) -> Result<LinkEvent, io::Error> {
+        loop {
+            if let Some(event) = self.events.try_next()? {
+                return Ok(event);
+            }
+            self.async_fd.readable().await?;
+        }
+    }
+}
+
+struct LinkState {
+    name: String,
+    xdp_programs: Vec<XdpAttachment>,
+}
+
+// The last known state of the interfaces, which notifications are compared
+// to.
+#[derive(Default)]
+struct LinkTable {
+    links: HashMap<u32, LinkState>,
+}
+
+impl LinkTable {
+    fn update(&mut self, event: NlLinkEvent) -> Vec<LinkEvent> {
+        let if_index = event.if_index as u32;
+        if event.deleted {
+            return self
+                .links
+                .remove(&if_index)
+                .map(|link| LinkEvent::Removed {
+                    if_index,
+                    name: link.name,
+                })
+                .into_iter()
+                .collect();
+        }
+
+        let xdp_programs = event
+            .xdp_programs
+            .iter()
+            .map(|(program_id, mode)| XdpAttachment {
+                program_id: *program_id,
+                mode: XdpFlags::from_bits_truncate(*mode),
+            })
+            .collect::<Vec<_>>();
+        let mut events = Vec::new();
+        match self.links.get_mut(&if_index) {
+            Some(link) => {
+                if let Some(name) = event.name {
+                    if name != link.name {
+                        events.push(LinkEvent::Renamed {
+                            if_index,
+                            old_name: std::mem::replace(&mut link.name, name.clone()),
+                            new_name: name,
+                        });
+                    }
+                }
+                if xdp_programs != link.xdp_programs {
+                    link.xdp_programs = xdp_programs.clone();
+                    events.push(LinkEvent::XdpChanged {
+                        if_index,
+                        programs: xdp_programs,
+                    });
+                }
+            }
+            None => {
+                let name = event.name.unwrap_or_default();
+                events.push(LinkEvent::Added {
+                    if_index,
+                    name: name.clone(),
+                });
+                if !xdp_programs.is_empty() {
+                    events.push(LinkEvent::XdpChanged {
+                        if_index,
+                        programs: xdp_programs.clone(),
+                    });
+                }
+                self.links
+                    .insert(if_index, LinkState { name, xdp_programs });
+            }
+        }
+        events
+    }
+
+    // Replaces the state with a new listing of the interfaces, and returns
+    // the differences.
+    fn resync(&mut self, links: Vec<NlLinkEvent>) -> Vec<LinkEvent> {
+        let mut removed = self.links.keys().copied().collect::<Vec<_>>();
+        let mut events = Vec::new();
+        for link in links {
+            removed.retain(|if_index| *if_index != link.if_index as u32);
+            events.extend(self.update(link));
+        }
+        for if_index in removed {
+            events.extend(self.update(NlLinkEvent {
+                deleted: true,
+                if_index: if_index as i32,
+                name: None,
+                xdp_programs: Vec::new(),
+            }));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(if_index: i32, name: &str, xdp_programs: Vec<(u32, u32)>) -> NlLinkEvent {
+        NlLinkEvent {
+            deleted: false,
+            if_index,
+            name: Some(name.to_owned()),
+            xdp_programs,
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let mut links = LinkTable::default();
+        assert_eq!(links.resync(vec![link(1, "lo", vec![])]).len(), 1);
+
+        assert_eq!(links.update(link(1, "lo", vec![])), vec![]);
+        assert_eq!(
+            links.update(link(2, "eth0", vec![])),
+            vec![LinkEvent::Added {
+                if_index: 2,
+                name: "eth0".to_owned()
+            }]
+        );
+        assert_eq!(
+            links.update(link(2, "wan0", vec![(42, XdpFlags::DRV_MODE.bits())])),
+            vec![
+                LinkEvent::Renamed {
+                    if_index: 2,
+                    old_name: "eth0".to_owned(),
+                    new_name: "wan0".to_owned()
+                },
+                LinkEvent::XdpChanged {
+                    if_index: 2,
+                    programs: vec![XdpAttachment {
+                        program_id: 42,
+                        mode: XdpFlags::DRV_MODE
+                    }]
+                }
+            ]
+        );
+        let mut removed = link(2, "wan0", vec![]);
+        removed.deleted = true;
+        assert_eq!(
+            links.update(removed),
+            vec![LinkEvent::Removed {
+                if_index: 2,
+                name: "wan0".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_resync() {
+        let mut links = LinkTable::default();
+        links.resync(vec![link(1, "lo", vec![]), link(2, "eth0", vec![])]);
+
+        assert_eq!(
+            links.resync(vec![link(1, "lo", vec![]), link(3, "eth1", vec![])]),
+            vec![
+                LinkEvent::Added {
+                    if_index: 3,
+                    name: "eth1".to_owned()
+                },
+                LinkEvent::Removed {
+                    if_index: 2,
+                    name: "eth0".to_owned()
+                }
+            ]
+        );
+    }
+}
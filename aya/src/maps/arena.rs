@@ -0,0 +1,220 @@
+//! Memory shared between eBPF programs and user space.
+use std::{
+    convert::{AsMut, AsRef},
+    ffi::{c_int, c_void},
+    io, mem,
+    os::unix::io::{AsRawFd, RawFd},
+    ptr,
+};
+
+use libc::{munmap, MAP_FAILED, MAP_FIXED_NOREPLACE, MAP_SHARED, PROT_READ, PROT_WRITE};
+
+use crate::{
+    maps::{MapData, MapError},
+    util::page_size,
+    Pod,
+};
+
+/// A region of memory shared between eBPF programs and user space.
+///
+/// Unlike the values of other maps, the memory of an arena isn't split into
+/// fixed size entries: eBPF programs allocate pages in the arena and can
+/// build any data structure in them, including structures containing
+/// pointers. The arena is mapped in the memory of the process, so user space
+/// reads and writes it in place without going through syscalls.
+///
+/// The kernel sets the address of the arena in user space the first time it
+/// is mapped, or uses the address given in the `map_extra` attribute of the
+/// map definition. For the eBPF programs using the arena to be loaded, the
+/// `Arena` must be created after loading the object and before loading the
+/// programs. Pointers stored in the arena by eBPF programs can then be
+/// turned into offsets with [`Arena::offset_of`].
+///
+/// The size of the arena, given by `max_entries`, is a number of pages, and
+/// the map must be created with the `BPF_F_MMAPABLE` flag.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.9.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::Arena;
+///
+/// let mut arena = Arena::try_from(bpf.take_map("ARENA").unwrap())?;
+/// // the eBPF program keeps the number of entries of a shared index at the
+/// // start of the arena
+/// let len: u64 = arena.read(0)?;
+/// println!("{} entries, arena mapped at {:?}", len, arena.as_ptr());
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_ARENA")]
+pub struct Arena<T> {
+    _map: T,
+    fd: RawFd,
+    ptr: *mut c_void,
+    size: usize,
+}
+
+// the mapped memory is only written through `&mut self` or raw pointers
+unsafe impl<T: Send> Send for Arena<T> {}
+unsafe impl<T: Sync> Sync for Arena<T> {}
+
+impl<T: AsRef<MapData>> Arena<T> {
+    pub(crate) fn new(map: T) -> Result<Arena<T>, MapError> {
+        let data = map.as_ref();
+        let fd = data.fd_or_err()?;
+        let size = data.obj.max_entries() as usize * page_size();
+        let (addr, flags) = mmap_hint(data.obj.map_extra());
+
+        let ptr = unsafe { libc::mmap(addr, size, PROT_READ | PROT_WRITE, flags, fd, 0) };
+        if ptr == MAP_FAILED {
+            return Err(MapError::SyscallError {
+                call: "mmap".to_owned(),
+                io_error: io::Error::last_os_error(),
+            });
+        }
+
+        Ok(Arena {
+            _map: map,
+            fd,
+            ptr,
+            size,
+        })
+    }
+
+    /// Returns the size of the arena in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the address of the arena in user space.
+    ///
+    /// Accessing the arena through the returned pointer is unsafe: the
+    /// memory can be modified by eBPF programs at any time, so shared data
+    /// structures must be synchronized with atomics.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr as *mut u8
+    }
+
+    /// Returns the offset in the arena of a pointer stored by an eBPF
+    /// program, or `None` if the pointer is outside of the arena.
+    pub fn offset_of(&self, addr: u64) -> Option<usize> {
+        let offset = addr.checked_sub(self.ptr as u64)? as usize;
+        (offset < self.size).then_some(offset)
+    }
+
+    /// Copies the value at `offset` out of the arena.
+    ///
+    /// The value is read with a plain copy, so it can be torn if an eBPF
+    /// program updates it concurrently.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfRange`] if the value doesn't fit in the
+    /// arena at `offset`.
+    pub fn read<V: Pod>(&self, offset: usize) -> Result<V, MapError> {
+        check_range(offset, mem::size_of::<V>(), self.size)?;
+        Ok(unsafe { ptr::read_unaligned(self.as_ptr().add(offset) as *const V) })
+    }
+
+    /// Copies `bytes.len()` bytes at `offset` out of the arena.
+    pub fn read_bytes(&self, offset: usize, bytes: &mut [u8]) -> Result<(), MapError> {
+        check_range(offset, bytes.len(), self.size)?;
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr().add(offset), bytes.as_mut_ptr(), bytes.len())
+        };
+        Ok(())
+    }
+}
+
+impl<T: AsMut<MapData>> Arena<T> {
+    /// Copies `value` into the arena at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfRange`] if the value doesn't fit in the
+    /// arena at `offset`.
+    pub fn write<V: Pod>(&mut self, offset: usize, value: &V) -> Result<(), MapError> {
+        check_range(offset, mem::size_of::<V>(), self.size)?;
+        unsafe { ptr::write_unaligned((self.ptr as *mut u8).add(offset) as *mut V, *value) };
+        Ok(())
+    }
+
+    /// Copies `bytes` into the arena at `offset`.
+    pub fn write_bytes(&mut self, offset: usize, bytes: &[u8]) -> Result<(), MapError> {
+        check_range(offset, bytes.len(), self.size)?;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                (self.ptr as *mut u8).add(offset),
+                bytes.len(),
+            )
+        };
+        Ok(())
+    }
+}
+
+impl<T> AsRawFd for Arena<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl<T> Drop for Arena<T> {
+    fn drop(&mut self) {
+        unsafe { munmap(self.ptr, self.size) };
+    }
+}
+
+// the kernel requires the arena to be mapped at the address given in
+// map_extra, if any, so don't let mmap pick another one
+fn mmap_hint(map_extra: u64) -> (*mut c_void, c_int) {
+    if map_extra == 0 {
+        (ptr::null_mut(), MAP_SHARED)
+    } else {
+        (map_extra as *mut c_void, MAP_SHARED | MAP_FIXED_NOREPLACE)
+    }
+}
+
+fn check_range(offset: usize, len: usize, size: usize) -> Result<(), MapError> {
+    match offset.checked_add(len) {
+        Some(end) if end <= size => Ok(()),
+        _ => Err(MapError::OutOfRange { offset, len, size }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_range() {
+        assert!(check_range(0, 8, 4096).is_ok());
+        assert!(check_range(4088, 8, 4096).is_ok());
+        assert!(check_range(4096, 0, 4096).is_ok());
+        assert!(matches!(
+            check_range(4092, 8, 4096),
+            Err(MapError::OutOfRange {
+                offset: 4092,
+                len: 8,
+                size: 4096
+            })
+        ));
+        assert!(check_range(usize::MAX, 8, 4096).is_err());
+    }
+
+    #[test]
+    fn test_mmap_hint() {
+        assert_eq!(mmap_hint(0), (ptr::null_mut(), MAP_SHARED));
+        assert_eq!(
+            mmap_hint(0x1_0000_0000),
+            (
+                0x1_0000_0000 as *mut c_void,
+                MAP_SHARED | MAP_FIXED_NOREPLACE
+            )
+        );
+    }
+}
@@ -101,6 +101,8 @@ mod tests {
             symbol_index: 0,
             data: Vec::new(),
             kind: obj::MapKind::Other,
+            map_extra: 0,
+            numa_node: None,
         })
     }
 
@@ -140,6 +142,8 @@ mod tests {
                 symbol_index: 0,
                 data: Vec::new(),
                 kind: obj::MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
             }),
             fd: None,
             pinned: false,
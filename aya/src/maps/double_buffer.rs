@@ -0,0 +1,226 @@
+//! Double-buffered maps, to read and reset counters without losing updates.
+use std::{
+    convert::{AsMut, AsRef},
+    thread,
+    time::Duration,
+};
+
+use crate::maps::{Array, MapData, MapError};
+
+/// Two maps written alternately by eBPF programs, selected by an index
+/// stored in a third map.
+///
+/// Reading counters from a map and then resetting them loses the updates
+/// made between the read and the reset. With two buffers, eBPF programs
+/// update the active buffer while user space reads and resets the inactive
+/// one: [`swap`](DoubleBuffered::swap) flips the index, so the programs
+/// switch to the other buffer, and returns the buffer they were using.
+///
+/// The index is the first entry of an `Array` of `u32` that the eBPF
+/// programs read every time they run, like this:
+///
+/// ```ignore
+/// #[map]
+/// static INDEX: Array<u32> = Array::with_max_entries(1, 0);
+/// #[map]
+/// static STATS_0: PerCpuArray<u64> = PerCpuArray::with_max_entries(16, 0);
+/// #[map]
+/// static STATS_1: PerCpuArray<u64> = PerCpuArray::with_max_entries(16, 0);
+///
+/// let stats = match INDEX.get(0) {
+///     Some(1) => &STATS_1,
+///     _ => &STATS_0,
+/// };
+/// ```
+///
+/// A program that read the index just before the swap can still update the
+/// buffer that's no longer active, so `swap` waits for a grace period, which
+/// must be longer than the programs take to run, before returning it.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::{
+///     maps::{double_buffer::DoubleBuffered, Array, PerCpuArray, PerCpuValues},
+///     util::nr_cpus,
+/// };
+/// use std::{thread, time::Duration};
+///
+/// let index = Array::try_from(bpf.take_map("INDEX").unwrap())?;
+/// let stats_0 = PerCpuArray::<_, u64>::try_from(bpf.take_map("STATS_0").unwrap())?;
+/// let stats_1 = PerCpuArray::<_, u64>::try_from(bpf.take_map("STATS_1").unwrap())?;
+/// let mut stats = DoubleBuffered::new(index, [stats_0, stats_1])?;
+///
+/// let nr_cpus = nr_cpus()?;
+/// loop {
+///     thread::sleep(Duration::from_secs(1));
+///     let inactive = stats.swap(Duration::from_millis(1))?;
+///     let packets = inactive.get(&0, 0)?.iter().sum::<u64>();
+///     println!("{} packets/s", packets);
+///     // reset the buffer before it's active again
+///     inactive.set(0, PerCpuValues::try_from(vec![0; nr_cpus])?, 0)?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct DoubleBuffered<T, M> {
+    index: Array<T, u32>,
+    buffers: [M; 2],
+    active: usize,
+}
+
+impl<T: AsRef<MapData> + AsMut<MapData>, M> DoubleBuffered<T, M> {
+    /// Creates a double-buffered map from the `index` array and the two
+    /// buffers.
+    ///
+    /// The active buffer is the one selected by the current value of the
+    /// index. Values other than 0 and 1 are reset to 0.
+    pub fn new(
+        mut index: Array<T, u32>,
+        buffers: [M; 2],
+    ) -> Result<DoubleBuffered<T, M>, MapError> {
+        let active = match index.get(&0, 0)? {
+            active @ (0 | 1) => active as usize,
+            _ => {
+                index.set(0, 0, 0)?;
+                0
+            }
+        };
+        Ok(DoubleBuffered {
+            index,
+            buffers,
+            active,
+        })
+    }
+
+    /// Returns the index of the buffer the eBPF programs are updating.
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Returns the buffer the eBPF programs are updating.
+    pub fn active_buffer(&self) -> &M {
+        &self.buffers[self.active]
+    }
+
+    /// Returns the buffer the eBPF programs are not updating.
+    pub fn inactive_buffer(&mut self) -> &mut M {
+        &mut self.buffers[1 - self.active]
+    }
+
+    /// Makes the eBPF programs update the inactive buffer, waits for `grace`
+    /// and returns the previously active buffer.
+    ///
+    /// The returned buffer contains all the updates made until the swap,
+    /// except the ones of programs still running after `grace`, and must be
+    /// reset before the next swap makes it active again.
+    pub fn swap(&mut self, grace: Duration) -> Result<&mut M, MapError> {
+        let previous = self.active;
+        self.index.set(0, 1 - previous as u32, 0)?;
+        self.active = 1 - previous;
+        if !grace.is_zero() {
+            thread::sleep(grace);
+        }
+        Ok(&mut self.buffers[previous])
+    }
+
+    /// Returns the index array and the two buffers.
+    pub fn into_inner(self) -> (Array<T, u32>, [M; 2]) {
+        (self.index, self.buffers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_attr, bpf_cmd, bpf_map_type::BPF_MAP_TYPE_ARRAY},
+        obj,
+        sys::{override_syscall, SysResult, Syscall},
+    };
+    use libc::EFAULT;
+    use std::{cell::Cell, io};
+
+    thread_local! {
+        static INDEX: Cell<u32> = const { Cell::new(0) };
+    }
+
+    fn new_map() -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(obj::LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: obj::MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn value_ptr(attr: &bpf_attr) -> *mut u32 {
+        unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value as *mut u32 }
+    }
+
+    unsafe fn fake_index(call: Syscall) -> SysResult {
+        match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                *value_ptr(attr) = INDEX.with(|i| i.get());
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                INDEX.with(|i| i.set(*value_ptr(attr)));
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        }
+    }
+
+    #[test]
+    fn test_swap() {
+        override_syscall(fake_index);
+        INDEX.with(|i| i.set(1));
+
+        let mut map = new_map();
+        let index = Array::new(&mut map).unwrap();
+        let mut buffers = DoubleBuffered::new(index, ["a", "b"]).unwrap();
+        assert_eq!(buffers.active(), 1);
+        assert_eq!(*buffers.active_buffer(), "b");
+        assert_eq!(*buffers.inactive_buffer(), "a");
+
+        assert_eq!(*buffers.swap(Duration::ZERO).unwrap(), "b");
+        assert_eq!(INDEX.with(|i| i.get()), 0);
+        assert_eq!(buffers.active(), 0);
+        assert_eq!(*buffers.swap(Duration::ZERO).unwrap(), "a");
+        assert_eq!(INDEX.with(|i| i.get()), 1);
+    }
+
+    #[test]
+    fn test_invalid_index() {
+        override_syscall(fake_index);
+        INDEX.with(|i| i.set(7));
+
+        let mut map = new_map();
+        let index = Array::new(&mut map).unwrap();
+        let buffers = DoubleBuffered::new(index, [(), ()]).unwrap();
+        assert_eq!(buffers.active(), 0);
+        assert_eq!(INDEX.with(|i| i.get()), 0);
+    }
+}
@@ -0,0 +1,437 @@
+//! Decoding of map entries using BTF.
+//!
+//! [`MapData::dump`] decodes the keys and values of a map using the BTF types
+//! the map was created with, the way `bpftool map dump` does. The decoded
+//! [`BtfValue`]s can be inspected or pretty-printed with their [`Display`]
+//! implementation, without knowing the types at compile time.
+//!
+//! [`Display`]: fmt::Display
+use std::fmt;
+
+use crate::{
+    maps::{field::map_btf, Map, MapData, MapError},
+    obj::btf::{BtfType, IntEncoding},
+    sys::bpf_map_get_info_by_fd,
+    Btf,
+};
+
+// nested types deeper than this are dumped as bytes
+const MAX_DEPTH: usize = 32;
+
+/// A value decoded using its BTF type.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(untagged))]
+pub enum BtfValue {
+    /// A boolean.
+    Bool(bool),
+    /// A signed integer.
+    Int(i64),
+    /// An unsigned integer, a pointer or a character.
+    Uint(u64),
+    /// A floating point number.
+    Float(f64),
+    /// An enum, with the name of the variant if the value is known.
+    Enum {
+        /// The name of the variant.
+        name: Option<String>,
+        /// The value.
+        value: i64,
+    },
+    /// An array of `char`, up to the first NUL.
+    String(String),
+    /// An array.
+    Array(Vec<BtfValue>),
+    /// A struct or union, with the names and values of the members.
+    Struct(Vec<(String, BtfValue)>),
+    /// Data that can't be decoded, like 128 bit integers.
+    Bytes(Vec<u8>),
+}
+
+impl fmt::Display for BtfValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BtfValue::Bool(v) => write!(f, "{}", v),
+            BtfValue::Int(v) => write!(f, "{}", v),
+            BtfValue::Uint(v) => write!(f, "{}", v),
+            BtfValue::Float(v) => write!(f, "{}", v),
+            BtfValue::Enum {
+                name: Some(name), ..
+            } => write!(f, "{:?}", name),
+            BtfValue::Enum { name: None, value } => write!(f, "{}", value),
+            BtfValue::String(v) => write!(f, "{:?}", v),
+            BtfValue::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            BtfValue::Struct(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: {}", name, value)?;
+                }
+                write!(f, "}}")
+            }
+            BtfValue::Bytes(bytes) => {
+                write!(f, "[")?;
+                for (i, b) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{:#04x}", b)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// A map entry decoded by [`MapData::dump`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DecodedEntry {
+    /// The key.
+    pub key: BtfValue,
+    /// The value, or one value per possible CPU for per-CPU maps.
+    pub values: Vec<BtfValue>,
+}
+
+impl fmt::Display for DecodedEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.values.as_slice() {
+            [value] => write!(f, "{}: {}", self.key, value),
+            values => write!(f, "{}: {}", self.key, BtfValue::Array(values.to_vec())),
+        }
+    }
+}
+
+impl MapData {
+    /// Returns the entries of the map, decoded using the BTF types of the
+    /// keys and the values.
+    ///
+    /// Maps without a BTF key type, such as arrays created by older
+    /// compilers, get their keys decoded as [`BtfValue::Bytes`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// for entry in bpf.map("CONNECTIONS").unwrap().dump()? {
+    ///     println!("{}", entry);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`MapError::NoBtf`] is returned if the map was created without BTF.
+    pub fn dump(&self) -> Result<Vec<DecodedEntry>, MapError> {
+        let fd = self.fd_or_err()?;
+        let info = bpf_map_get_info_by_fd(fd).map_err(|io_error| MapError::SyscallError {
+            call: "bpf_map_get_info_by_fd".to_owned(),
+            io_error,
+        })?;
+        if info.btf_id == 0 || info.btf_value_type_id == 0 {
+            return Err(MapError::NoBtf);
+        }
+        let btf = map_btf(info.btf_id)?;
+
+        let decode_key = |key: &[u8]| match info.btf_key_type_id {
+            0 => BtfValue::Bytes(key.to_vec()),
+            type_id => decode(&btf, type_id, key),
+        };
+        Ok(self
+            .snapshot()?
+            .entries
+            .iter()
+            .map(|entry| DecodedEntry {
+                key: decode_key(&entry.key),
+                values: entry
+                    .values
+                    .iter()
+                    .map(|value| decode(&btf, info.btf_value_type_id, value))
+                    .collect(),
+            })
+            .collect())
+    }
+}
+
+impl Map {
+    /// Returns the entries of the map decoded using BTF, see
+    /// [`MapData::dump`].
+    pub fn dump(&self) -> Result<Vec<DecodedEntry>, MapError> {
+        self.data().dump()
+    }
+}
+
+// Decodes `data` as a value of type `type_id`. Whatever can't be decoded is
+// returned as bytes.
+fn decode(btf: &Btf, type_id: u32, data: &[u8]) -> BtfValue {
+    decode_at_depth(btf, type_id, data, 0).unwrap_or_else(|| BtfValue::Bytes(data.to_vec()))
+}
+
+fn decode_at_depth(btf: &Btf, type_id: u32, data: &[u8], depth: usize) -> Option<BtfValue> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    let type_id = btf.resolve_type(type_id).ok()?;
+    let ty = btf.type_by_id(type_id).ok()?;
+    let value = match ty {
+        BtfType::Int(int) => {
+            let size = int.size as usize;
+            let bits = read_uint(data.get(..size)?)?;
+            int_value(int.encoding(), bits, size as u32 * 8)?
+        }
+        BtfType::Ptr(_) => BtfValue::Uint(read_uint(data.get(..8)?)? as u64),
+        BtfType::Float(float) => match float.size {
+            4 => BtfValue::Float(f32::from_ne_bytes(data.get(..4)?.try_into().ok()?) as f64),
+            8 => BtfValue::Float(f64::from_ne_bytes(data.get(..8)?.try_into().ok()?)),
+            _ => return None,
+        },
+        BtfType::Enum(e) => {
+            let bits = read_uint(data.get(..e.size as usize)?)?;
+            let signed = e.is_signed();
+            let value = if signed {
+                sign_extend(bits, e.size * 8)
+            } else {
+                bits as i64
+            };
+            // the variant values are stored as 32 bits, whatever the size of
+            // the enum
+            let name = e
+                .variants
+                .iter()
+                .find(|v| {
+                    let variant = if signed {
+                        v.value as i64
+                    } else {
+                        v.value as u32 as i64
+                    };
+                    variant == value
+                })
+                .and_then(|v| btf.string_at(v.name_offset).ok())
+                .map(|name| name.into_owned());
+            BtfValue::Enum { name, value }
+        }
+        BtfType::Array(array) => {
+            let array = &array.array;
+            let elem_size = btf.type_size(array.element_type).ok()?;
+            let len = array.len as usize;
+            let data = data.get(..elem_size.checked_mul(len)?)?;
+            if is_char(btf, array.element_type) {
+                let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                BtfValue::String(String::from_utf8_lossy(&data[..end]).into_owned())
+            } else if elem_size == 0 {
+                BtfValue::Array(Vec::new())
+            } else {
+                BtfValue::Array(
+                    data.chunks_exact(elem_size)
+                        .map(|elem| {
+                            decode_at_depth(btf, array.element_type, elem, depth + 1)
+                                .unwrap_or_else(|| BtfValue::Bytes(elem.to_vec()))
+                        })
+                        .collect(),
+                )
+            }
+        }
+        BtfType::Struct(_) | BtfType::Union(_) => {
+            let mut members = Vec::new();
+            for member in ty.members()? {
+                let name = btf.string_at(member.name_offset).ok()?.into_owned();
+                let bit_offset = ty.member_bit_offset(member)?;
+                let bit_size = ty.member_bit_field_size(member)?;
+                let value = if bit_size > 0 {
+                    bitfield(btf, member.btf_type, data, bit_offset, bit_size)?
+                } else {
+                    let data = data.get(bit_offset / 8..)?;
+                    decode_at_depth(btf, member.btf_type, data, depth + 1)
+                        .unwrap_or_else(|| BtfValue::Bytes(Vec::new()))
+                };
+                members.push((name, value));
+            }
+            BtfValue::Struct(members)
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn int_value(encoding: IntEncoding, bits: u128, nr_bits: u32) -> Option<BtfValue> {
+    if nr_bits > 64 {
+        return None;
+    }
+    Some(match encoding {
+        IntEncoding::Bool => BtfValue::Bool(bits != 0),
+        IntEncoding::Signed => BtfValue::Int(sign_extend(bits, nr_bits)),
+        _ => BtfValue::Uint(bits as u64),
+    })
+}
+
+// Reads a bitfield member. This assumes a little endian layout.
+fn bitfield(
+    btf: &Btf,
+    type_id: u32,
+    data: &[u8],
+    bit_offset: usize,
+    bit_size: usize,
+) -> Option<BtfValue> {
+    if bit_size > 64 {
+        return None;
+    }
+    let start = bit_offset / 8;
+    let shift = bit_offset % 8;
+    let len = (shift + bit_size).div_ceil(8);
+    let bits = (read_uint(data.get(start..start + len)?)? >> shift) & ((1u128 << bit_size) - 1);
+
+    let encoding = match btf.type_by_id(btf.resolve_type(type_id).ok()?).ok()? {
+        BtfType::Int(int) => int.encoding(),
+        BtfType::Enum(e) if e.is_signed() => IntEncoding::Signed,
+        _ => IntEncoding::None,
+    };
+    int_value(encoding, bits, bit_size as u32)
+}
+
+fn is_char(btf: &Btf, type_id: u32) -> bool {
+    let ty = match btf
+        .resolve_type(type_id)
+        .and_then(|type_id| btf.type_by_id(type_id))
+    {
+        Ok(ty) => ty,
+        Err(_) => return false,
+    };
+    match ty {
+        BtfType::Int(int) if int.size == 1 => {
+            int.encoding() == IntEncoding::Char
+                || btf.type_name(ty).is_ok_and(|name| name == "char")
+        }
+        _ => false,
+    }
+}
+
+fn read_uint(data: &[u8]) -> Option<u128> {
+    if data.len() > 16 {
+        return None;
+    }
+    let mut buf = [0u8; 16];
+    if cfg!(target_endian = "little") {
+        buf[..data.len()].copy_from_slice(data);
+    } else {
+        buf[16 - data.len()..].copy_from_slice(data);
+    }
+    Some(u128::from_ne_bytes(buf))
+}
+
+fn sign_extend(value: u128, nr_bits: u32) -> i64 {
+    if nr_bits == 0 || nr_bits > 64 {
+        return value as i64;
+    }
+    let shift = 64 - nr_bits;
+    ((value as u64) << shift) as i64 >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::btf::{Array, BtfEnum, BtfMember, Enum, Int, Struct};
+
+    #[test]
+    fn test_decode() {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("u32".to_owned());
+        let u32_type = btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+        let name_offset = btf.add_string("s16".to_owned());
+        let s16_type = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            2,
+            IntEncoding::Signed,
+            0,
+        )));
+        let name_offset = btf.add_string("char".to_owned());
+        let char_type = btf.add_type(BtfType::Int(Int::new(name_offset, 1, IntEncoding::Char, 0)));
+        let name_type = btf.add_type(BtfType::Array(Array::new(0, char_type, u32_type, 8)));
+        let pair_type = btf.add_type(BtfType::Array(Array::new(0, s16_type, u32_type, 2)));
+        let variants = vec![
+            BtfEnum {
+                name_offset: btf.add_string("IDLE".to_owned()),
+                value: 0,
+            },
+            BtfEnum {
+                name_offset: btf.add_string("BUSY".to_owned()),
+                value: 1,
+            },
+        ];
+        let name_offset = btf.add_string("state".to_owned());
+        let state_type = btf.add_type(BtfType::Enum(Enum::new(name_offset, variants)));
+
+        let member = |btf: &mut Btf, name: &str, btf_type, offset| BtfMember {
+            name_offset: btf.add_string(name.to_owned()),
+            btf_type,
+            offset,
+        };
+        let members = vec![
+            member(&mut btf, "id", u32_type, 0),
+            member(&mut btf, "name", name_type, 32),
+            member(&mut btf, "state", state_type, 96),
+            member(&mut btf, "pair", pair_type, 128),
+        ];
+        let name_offset = btf.add_string("value".to_owned());
+        let value_type = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, 20)));
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&42u32.to_ne_bytes());
+        data.extend_from_slice(b"eth0\0\0\0\0");
+        data.extend_from_slice(&1u32.to_ne_bytes());
+        data.extend_from_slice(&(-3i16).to_ne_bytes());
+        data.extend_from_slice(&7i16.to_ne_bytes());
+
+        let value = decode(&btf, value_type, &data);
+        assert_eq!(
+            value,
+            BtfValue::Struct(vec![
+                ("id".to_owned(), BtfValue::Uint(42)),
+                ("name".to_owned(), BtfValue::String("eth0".to_owned())),
+                (
+                    "state".to_owned(),
+                    BtfValue::Enum {
+                        name: Some("BUSY".to_owned()),
+                        value: 1
+                    }
+                ),
+                (
+                    "pair".to_owned(),
+                    BtfValue::Array(vec![BtfValue::Int(-3), BtfValue::Int(7)])
+                ),
+            ])
+        );
+        assert_eq!(
+            value.to_string(),
+            r#"{"id": 42, "name": "eth0", "state": "BUSY", "pair": [-3, 7]}"#
+        );
+
+        // unsigned enums smaller than 32 bits aren't sign extended
+        let variants = vec![BtfEnum {
+            name_offset: btf.add_string("HIGH".to_owned()),
+            value: 200,
+        }];
+        let mut level = Enum::new(btf.add_string("level".to_owned()), variants);
+        level.size = 1;
+        let level_type = btf.add_type(BtfType::Enum(level));
+        assert_eq!(
+            decode(&btf, level_type, &[200]),
+            BtfValue::Enum {
+                name: Some("HIGH".to_owned()),
+                value: 200
+            }
+        );
+
+        // truncated data can't be decoded
+        assert_eq!(decode(&btf, u32_type, &[1, 2]), BtfValue::Bytes(vec![1, 2]));
+    }
+}
@@ -0,0 +1,245 @@
+//! Access to the fields of map values by name.
+//!
+//! Maps created from objects with BTF carry the type of their values. This
+//! module uses it to read and write individual fields of a value without a
+//! Rust definition of the value type, which is useful for generic tools that
+//! inspect maps of arbitrary programs.
+use std::{mem, os::unix::io::RawFd};
+
+use object::Endianness;
+
+use crate::{
+    generated::bpf_map_type::{
+        BPF_MAP_TYPE_LRU_PERCPU_HASH, BPF_MAP_TYPE_PERCPU_ARRAY, BPF_MAP_TYPE_PERCPU_HASH,
+    },
+    maps::{Map, MapData, MapError},
+    sys::{
+        bpf_btf_get_fd_by_id, bpf_map_get_info_by_fd, bpf_map_lookup_elem_ptr,
+        bpf_map_update_elem_ptr, btf_obj_get_data,
+    },
+    Btf, Pod,
+};
+
+impl MapData {
+    /// Reads the field called `field` of the value stored at `key`.
+    ///
+    /// Nested fields are separated by dots, like `stats.rx_bytes`. The type
+    /// of the field is looked up in the BTF of the map, and its size must
+    /// match the size of `V`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// let map = bpf.map("COUNTERS").unwrap();
+    /// let rx_bytes: u64 = map.get_field(&0u32, "rx_bytes")?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`MapError::NoBtf`] is returned if the map was created without BTF,
+    /// [`MapError::FieldNotFound`] if the value has no such field and
+    /// [`MapError::KeyNotFound`] if `key` isn't in the map. Per-CPU maps
+    /// aren't supported.
+    pub fn get_field<K: Pod, V: Pod>(&self, key: &K, field: &str) -> Result<V, MapError> {
+        let fd = self.fd_or_err()?;
+        let (offset, value) = field_value::<K, V>(fd, key, field)?;
+        Ok(unsafe { (value[offset..].as_ptr() as *const V).read_unaligned() })
+    }
+
+    /// Writes the field called `field` of the value stored at `key`.
+    ///
+    /// The other fields of the value are left unchanged. The value is read,
+    /// modified and written back, so concurrent updates from eBPF programs
+    /// to other fields of the same value may be lost.
+    ///
+    /// See [`MapData::get_field`] for the format of `field` and the errors
+    /// returned.
+    pub fn set_field<K: Pod, V: Pod>(
+        &mut self,
+        key: &K,
+        field: &str,
+        value: V,
+    ) -> Result<(), MapError> {
+        let fd = self.fd_or_err()?;
+        let (offset, mut buf) = field_value::<K, V>(fd, key, field)?;
+        unsafe { (buf[offset..].as_mut_ptr() as *mut V).write_unaligned(value) };
+        bpf_map_update_elem_ptr(fd, key, buf.as_mut_ptr(), 0).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+}
+
+impl Map {
+    /// Reads the field called `field` of the value stored at `key`.
+    ///
+    /// See [`MapData::get_field`].
+    pub fn get_field<K: Pod, V: Pod>(&self, key: &K, field: &str) -> Result<V, MapError> {
+        self.data().get_field(key, field)
+    }
+
+    /// Writes the field called `field` of the value stored at `key`.
+    ///
+    /// See [`MapData::set_field`].
+    pub fn set_field<K: Pod, V: Pod>(
+        &mut self,
+        key: &K,
+        field: &str,
+        value: V,
+    ) -> Result<(), MapError> {
+        self.data_mut().set_field(key, field, value)
+    }
+}
+
+// Returns the offset of `field` and the value stored at `key`.
+fn field_value<K: Pod, V: Pod>(
+    fd: RawFd,
+    key: &K,
+    field: &str,
+) -> Result<(usize, Vec<u8>), MapError> {
+    let info = bpf_map_get_info_by_fd(fd).map_err(|io_error| MapError::SyscallError {
+        call: "bpf_map_get_info_by_fd".to_owned(),
+        io_error,
+    })?;
+    let per_cpu = [
+        BPF_MAP_TYPE_PERCPU_HASH,
+        BPF_MAP_TYPE_PERCPU_ARRAY,
+        BPF_MAP_TYPE_LRU_PERCPU_HASH,
+    ];
+    if per_cpu.iter().any(|t| *t as u32 == info.type_) {
+        return Err(MapError::InvalidMapType {
+            map_type: info.type_,
+        });
+    }
+    if info.btf_id == 0 || info.btf_value_type_id == 0 {
+        return Err(MapError::NoBtf);
+    }
+    if info.key_size as usize != mem::size_of::<K>() {
+        return Err(MapError::InvalidKeySize {
+            size: mem::size_of::<K>(),
+            expected: info.key_size as usize,
+        });
+    }
+
+    let btf = map_btf(info.btf_id)?;
+    let (offset, size) = field_location(&btf, info.btf_value_type_id, field)?;
+    if size != mem::size_of::<V>() {
+        return Err(MapError::InvalidValueSize {
+            size: mem::size_of::<V>(),
+            expected: size,
+        });
+    }
+
+    let mut value = vec![0u8; info.value_size as usize];
+    match bpf_map_lookup_elem_ptr(fd, Some(key), value.as_mut_ptr(), 0) {
+        Ok(Some(())) => Ok((offset, value)),
+        Ok(None) => Err(MapError::KeyNotFound),
+        Err((_, io_error)) => Err(MapError::SyscallError {
+            call: "bpf_map_lookup_elem".to_owned(),
+            io_error,
+        }),
+    }
+}
+
+pub(super) fn map_btf(btf_id: u32) -> Result<Btf, MapError> {
+    let syscall_error = |call: &str, io_error| MapError::SyscallError {
+        call: call.to_owned(),
+        io_error,
+    };
+    let btf_fd =
+        bpf_btf_get_fd_by_id(btf_id).map_err(|e| syscall_error("bpf_btf_get_fd_by_id", e))?;
+
+    let result = btf_obj_get_data(btf_fd);
+    unsafe { libc::close(btf_fd) };
+    let buf = result.map_err(|e| syscall_error("bpf_obj_get_info_by_fd", e))?;
+
+    Ok(Btf::parse(&buf, Endianness::default())?)
+}
+
+// Returns the offset and the size of `path` within the type `type_id`.
+fn field_location(btf: &Btf, type_id: u32, path: &str) -> Result<(usize, usize), MapError> {
+    let not_found = || MapError::FieldNotFound {
+        field: path.to_owned(),
+    };
+
+    let mut type_id = btf.resolve_type(type_id)?;
+    let mut offset = 0;
+    for name in path.split('.') {
+        let ty = btf.type_by_id(type_id)?;
+        let members = ty.members().ok_or_else(not_found)?;
+        let mut found = None;
+        for member in members {
+            if btf.string_at(member.name_offset)? == name {
+                found = Some(member);
+                break;
+            }
+        }
+        let member = found.ok_or_else(not_found)?;
+        let bit_offset = ty.member_bit_offset(member).unwrap();
+        // bitfields can't be accessed as plain values
+        if ty.member_bit_field_size(member).unwrap() != 0 || bit_offset % 8 != 0 {
+            return Err(not_found());
+        }
+        offset += bit_offset / 8;
+        type_id = btf.resolve_type(member.btf_type)?;
+    }
+
+    Ok((offset, btf.type_size(type_id)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::btf::{BtfMember, BtfType, Int, IntEncoding, Struct};
+
+    #[test]
+    fn test_field_location() {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("u64".to_owned());
+        let u64_type = btf.add_type(BtfType::Int(Int::new(name_offset, 8, IntEncoding::None, 0)));
+        let name_offset = btf.add_string("u32".to_owned());
+        let u32_type = btf.add_type(BtfType::Int(Int::new(name_offset, 4, IntEncoding::None, 0)));
+
+        let member = |btf: &mut Btf, name: &str, btf_type, offset| BtfMember {
+            name_offset: btf.add_string(name.to_owned()),
+            btf_type,
+            offset,
+        };
+        let members = vec![
+            member(&mut btf, "rx_bytes", u64_type, 0),
+            member(&mut btf, "flags", u32_type, 64),
+        ];
+        let name_offset = btf.add_string("stats".to_owned());
+        let stats_type = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, 16)));
+        let members = vec![
+            member(&mut btf, "id", u32_type, 0),
+            member(&mut btf, "stats", stats_type, 64),
+        ];
+        let name_offset = btf.add_string("value".to_owned());
+        let value_type = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, 24)));
+
+        assert_eq!(field_location(&btf, value_type, "id").unwrap(), (0, 4));
+        assert_eq!(
+            field_location(&btf, value_type, "stats.rx_bytes").unwrap(),
+            (8, 8)
+        );
+        assert_eq!(
+            field_location(&btf, value_type, "stats.flags").unwrap(),
+            (16, 4)
+        );
+        assert_eq!(field_location(&btf, value_type, "stats").unwrap(), (8, 16));
+        assert!(matches!(
+            field_location(&btf, value_type, "missing"),
+            Err(MapError::FieldNotFound { field }) if field == "missing"
+        ));
+        assert!(matches!(
+            field_location(&btf, value_type, "id.rx_bytes"),
+            Err(MapError::FieldNotFound { .. })
+        ));
+    }
+}
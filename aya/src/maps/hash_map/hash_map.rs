@@ -30,7 +30,6 @@ use crate::{
 /// # Ok::<(), aya::BpfError>(())
 /// ```
 #[doc(alias = "BPF_MAP_TYPE_HASH")]
-#[doc(alias = "BPF_MAP_TYPE_LRU_HASH")]
 #[derive(Debug)]
 pub struct HashMap<T, K, V> {
     inner: T,
@@ -130,6 +129,8 @@ mod tests {
             data: Vec::new(),
             kind: obj::MapKind::Other,
             symbol_index: 0,
+            map_extra: 0,
+            numa_node: None,
         })
     }
 
@@ -261,6 +262,8 @@ mod tests {
                 symbol_index: 0,
                 data: Vec::new(),
                 kind: obj::MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
             }),
             fd: Some(42),
             pinned: false,
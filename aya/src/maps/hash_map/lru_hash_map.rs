@@ -0,0 +1,187 @@
+use std::{
+    convert::{AsMut, AsRef},
+    marker::PhantomData,
+};
+
+use crate::{
+    maps::{check_kv_size, hash_map, IterableMap, MapData, MapError, MapIter, MapKeys},
+    sys::bpf_map_lookup_elem,
+    Pod,
+};
+
+/// A hash map that evicts the least recently used entries when it's full.
+///
+/// Inserting a key in a full [`HashMap`](crate::maps::HashMap) fails, so
+/// user space has to remove stale entries. When an `LruHashMap` is full, the
+/// kernel makes room for new keys by evicting the entries that haven't been
+/// looked up or updated for the longest time instead, which suits flow tables
+/// and caches.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.10.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::LruHashMap;
+///
+/// let flows = LruHashMap::<_, [u8; 13], u64>::try_from(bpf.map("FLOWS").unwrap())?;
+/// for flow in flows.iter() {
+///     let (key, bytes) = flow?;
+///     println!("{:?}: {} bytes", key, bytes);
+/// }
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_LRU_HASH")]
+#[derive(Debug)]
+pub struct LruHashMap<T, K, V> {
+    inner: T,
+    _k: PhantomData<K>,
+    _v: PhantomData<V>,
+}
+
+impl<T: AsRef<MapData>, K: Pod, V: Pod> LruHashMap<T, K, V> {
+    pub(crate) fn new(map: T) -> Result<LruHashMap<T, K, V>, MapError> {
+        let data = map.as_ref();
+        check_kv_size::<K, V>(data)?;
+        let _ = data.fd_or_err()?;
+
+        Ok(LruHashMap {
+            inner: map,
+            _k: PhantomData,
+            _v: PhantomData,
+        })
+    }
+
+    /// Returns a copy of the value associated with the key.
+    ///
+    /// Looking up a key marks its entry as recently used.
+    pub fn get(&self, key: &K, flags: u64) -> Result<V, MapError> {
+        let fd = self.inner.as_ref().fd_or_err()?;
+        let value = bpf_map_lookup_elem(fd, key, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        value.ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator item type is `Result<(K, V), MapError>`.
+    pub fn iter(&self) -> MapIter<'_, K, V, Self> {
+        MapIter::new(self)
+    }
+
+    /// An iterator visiting all keys in arbitrary order. The iterator element
+    /// type is `Result<K, MapError>`.
+    pub fn keys(&self) -> MapKeys<'_, K> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>, K: Pod, V: Pod> LruHashMap<T, K, V> {
+    /// Inserts a key-value pair into the map, evicting the least recently
+    /// used entries if the map is full.
+    pub fn insert(&mut self, key: K, value: V, flags: u64) -> Result<(), MapError> {
+        hash_map::insert(self.inner.as_mut(), key, value, flags)
+    }
+
+    /// Removes a key from the map.
+    pub fn remove(&mut self, key: &K) -> Result<(), MapError> {
+        hash_map::remove(self.inner.as_mut(), key)
+    }
+}
+
+impl<T: AsRef<MapData>, K: Pod, V: Pod> IterableMap<K, V> for LruHashMap<T, K, V> {
+    fn map(&self) -> &MapData {
+        self.inner.as_ref()
+    }
+
+    fn get(&self, key: &K) -> Result<V, MapError> {
+        LruHashMap::get(self, key, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::{EFAULT, ENOENT};
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_LRU_HASH},
+        maps::{Map, MapData},
+        obj,
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map() -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(obj::LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_LRU_HASH as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 1024,
+                    ..Default::default()
+                },
+                section_index: 0,
+                data: Vec::new(),
+                kind: obj::MapKind::Other,
+                symbol_index: 0,
+                map_extra: 0,
+                numa_node: None,
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    #[test]
+    fn test_try_from_ok() {
+        let map = Map::LruHashMap(new_map());
+        assert!(LruHashMap::<_, u32, u32>::try_from(&map).is_ok())
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let map = Map::PerCpuLruHashMap(new_map());
+        assert!(matches!(
+            LruHashMap::<_, u32, u32>::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM | bpf_cmd::BPF_MAP_DELETE_ELEM,
+                ..
+            } => Ok(1),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                ..
+            } => sys_error(ENOENT),
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = new_map();
+        let mut hm = LruHashMap::<_, u32, u32>::new(&mut map).unwrap();
+
+        assert!(hm.insert(1, 42, 0).is_ok());
+        assert!(hm.remove(&1).is_ok());
+        assert!(matches!(hm.get(&1, 0), Err(MapError::KeyNotFound)));
+    }
+}
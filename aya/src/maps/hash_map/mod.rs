@@ -6,10 +6,14 @@ use crate::{
 
 #[allow(clippy::module_inception)]
 mod hash_map;
+mod lru_hash_map;
 mod per_cpu_hash_map;
+mod per_cpu_lru_hash_map;
 
 pub use hash_map::*;
+pub use lru_hash_map::*;
 pub use per_cpu_hash_map::*;
+pub use per_cpu_lru_hash_map::*;
 
 use super::MapData;
 
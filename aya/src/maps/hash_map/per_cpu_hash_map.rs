@@ -15,9 +15,6 @@ use crate::{
 /// Similar to [`HashMap`](crate::maps::HashMap) but each CPU holds a separate value for a given key. Tipically used to
 /// minimize lock contention in eBPF programs.
 ///
-/// This type can be used with eBPF maps of type `BPF_MAP_TYPE_PERCPU_HASH` and
-/// `BPF_MAP_TYPE_LRU_PERCPU_HASH`.
-///
 /// # Minimum kernel version
 ///
 /// The minimum kernel version required to use this feature is 4.6.
@@ -39,7 +36,6 @@ use crate::{
 /// }
 /// # Ok::<(), aya::BpfError>(())
 /// ```
-#[doc(alias = "BPF_MAP_TYPE_LRU_PERCPU_HASH")]
 #[doc(alias = "BPF_MAP_TYPE_PERCPU_HASH")]
 pub struct PerCpuHashMap<T, K: Pod, V: Pod> {
     inner: T,
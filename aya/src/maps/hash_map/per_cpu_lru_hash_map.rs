@@ -0,0 +1,204 @@
+//! Per-CPU LRU hash map.
+use std::{
+    convert::{AsMut, AsRef},
+    marker::PhantomData,
+};
+
+use crate::{
+    maps::{
+        check_kv_size, hash_map, IterableMap, MapData, MapError, MapIter, MapKeys, PerCpuValues,
+    },
+    sys::{bpf_map_lookup_elem_per_cpu, bpf_map_update_elem_per_cpu},
+    Pod,
+};
+
+/// Similar to [`LruHashMap`](crate::maps::LruHashMap) but each CPU holds a
+/// separate value for a given key.
+///
+/// The least recently used entries are tracked per CPU, so when the map is
+/// full an entry can be evicted while it's still in use on other CPUs.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.10.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::PerCpuLruHashMap;
+///
+/// let flows = PerCpuLruHashMap::<_, [u8; 13], u64>::try_from(bpf.map("FLOWS").unwrap())?;
+/// for flow in flows.iter() {
+///     let (key, bytes) = flow?;
+///     println!("{:?}: {} bytes", key, bytes.iter().sum::<u64>());
+/// }
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_LRU_PERCPU_HASH")]
+pub struct PerCpuLruHashMap<T, K: Pod, V: Pod> {
+    inner: T,
+    _k: PhantomData<K>,
+    _v: PhantomData<V>,
+}
+
+impl<T: AsRef<MapData>, K: Pod, V: Pod> PerCpuLruHashMap<T, K, V> {
+    pub(crate) fn new(map: T) -> Result<PerCpuLruHashMap<T, K, V>, MapError> {
+        let data = map.as_ref();
+        check_kv_size::<K, V>(data)?;
+
+        let _ = data.fd_or_err()?;
+
+        Ok(PerCpuLruHashMap {
+            inner: map,
+            _k: PhantomData,
+            _v: PhantomData,
+        })
+    }
+
+    /// Returns a slice of values - one for each CPU - associated with the key.
+    pub fn get(&self, key: &K, flags: u64) -> Result<PerCpuValues<V>, MapError> {
+        let fd = self.inner.as_ref().fd_or_err()?;
+        let values = bpf_map_lookup_elem_per_cpu(fd, key, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        values.ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator item type is `Result<(K, PerCpuValues<V>), MapError>`.
+    pub fn iter(&self) -> MapIter<'_, K, PerCpuValues<V>, Self> {
+        MapIter::new(self)
+    }
+
+    /// An iterator visiting all keys in arbitrary order. The iterator element
+    /// type is `Result<K, MapError>`.
+    pub fn keys(&self) -> MapKeys<'_, K> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>, K: Pod, V: Pod> PerCpuLruHashMap<T, K, V> {
+    /// Inserts a slice of values - one for each CPU - for the given key,
+    /// evicting the least recently used entries if the map is full.
+    pub fn insert(&mut self, key: K, values: PerCpuValues<V>, flags: u64) -> Result<(), MapError> {
+        let fd = self.inner.as_mut().fd_or_err()?;
+        bpf_map_update_elem_per_cpu(fd, &key, &values, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+
+        Ok(())
+    }
+
+    /// Removes a key from the map.
+    pub fn remove(&mut self, key: &K) -> Result<(), MapError> {
+        hash_map::remove(self.inner.as_mut(), key)
+    }
+}
+
+impl<T: AsRef<MapData>, K: Pod, V: Pod> IterableMap<K, PerCpuValues<V>>
+    for PerCpuLruHashMap<T, K, V>
+{
+    fn map(&self) -> &MapData {
+        self.inner.as_ref()
+    }
+
+    fn get(&self, key: &K) -> Result<PerCpuValues<V>, MapError> {
+        PerCpuLruHashMap::get(self, key, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::{EFAULT, ENOENT};
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_LRU_PERCPU_HASH},
+        maps::{Map, MapData},
+        obj,
+        sys::{override_syscall, SysResult, Syscall},
+        util::nr_cpus,
+    };
+
+    use super::*;
+
+    fn new_map() -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(obj::LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_LRU_PERCPU_HASH as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 1024,
+                    ..Default::default()
+                },
+                section_index: 0,
+                data: Vec::new(),
+                kind: obj::MapKind::Other,
+                symbol_index: 0,
+                map_extra: 0,
+                numa_node: None,
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    #[test]
+    fn test_try_from_ok() {
+        let map = Map::PerCpuLruHashMap(new_map());
+        assert!(PerCpuLruHashMap::<_, u32, u32>::try_from(&map).is_ok())
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let map = Map::PerCpuHashMap(new_map());
+        assert!(matches!(
+            PerCpuLruHashMap::<_, u32, u32>::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+
+        let map = Map::LruHashMap(new_map());
+        assert!(matches!(
+            PerCpuLruHashMap::<_, u32, u32>::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM | bpf_cmd::BPF_MAP_DELETE_ELEM,
+                ..
+            } => Ok(1),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                ..
+            } => sys_error(ENOENT),
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = new_map();
+        let mut hm = PerCpuLruHashMap::<_, u32, u32>::new(&mut map).unwrap();
+
+        let values = PerCpuValues::try_from(vec![42u32; nr_cpus().unwrap()]).unwrap();
+        assert!(hm.insert(1, values, 0).is_ok());
+        assert!(hm.remove(&1).is_ok());
+        assert!(matches!(hm.get(&1, 0), Err(MapError::KeyNotFound)));
+    }
+}
@@ -0,0 +1,448 @@
+//! Log2 histograms aggregated in per-CPU maps.
+use std::{
+    convert::{AsMut, AsRef, TryFrom},
+    mem,
+    ops::Range,
+};
+
+use crate::{
+    generated::bpf_map_type::BPF_MAP_TYPE_PERCPU_HASH,
+    maps::{check_kv_size, Map, MapData, MapError},
+    sys::{bpf_map_lookup_batch, bpf_map_lookup_elem_per_cpu, bpf_map_update_batch},
+    util::nr_cpus,
+};
+
+/// A snapshot of a log2 histogram.
+///
+/// Bucket 0 counts the zero values, and bucket `i` the values in
+/// `2^(i-1)..2^i`, which is what `64 - value.leading_zeros()` returns for a
+/// value. The larger the values, the wider the buckets, so 65 buckets cover
+/// the whole range of `u64`, like the histograms of bcc and bpftrace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Histogram {
+    buckets: Vec<u64>,
+}
+
+impl Histogram {
+    /// Creates a histogram from the counts of its buckets.
+    pub fn new(buckets: Vec<u64>) -> Histogram {
+        Histogram { buckets }
+    }
+
+    /// Returns the index of the bucket counting `value`.
+    pub fn bucket_index(value: u64) -> usize {
+        (u64::BITS - value.leading_zeros()) as usize
+    }
+
+    /// Returns the range of the values counted by the bucket `index`.
+    pub fn bucket_range(index: usize) -> Range<u64> {
+        let bound = |i: usize| 1u64.checked_shl(i as u32).unwrap_or(u64::MAX);
+        match index {
+            0 => 0..1,
+            i => bound(i - 1)..bound(i),
+        }
+    }
+
+    /// Returns the counts of the buckets.
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    /// Returns the number of values counted.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Adds the counts of `other` to this histogram.
+    ///
+    /// This merges the snapshots of several maps, for example of the two
+    /// generations of a double-buffered histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (count, other) in self.buckets.iter_mut().zip(&other.buckets) {
+            *count += other;
+        }
+    }
+
+    /// Returns an upper bound of the `q` quantile, the end of the bucket
+    /// containing it, or `None` if the histogram is empty.
+    ///
+    /// `q` is between 0 and 1, 0.5 for the median.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, c) in self.buckets.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return Some(Histogram::bucket_range(i).end);
+            }
+        }
+        None
+    }
+}
+
+/// A log2 histogram stored in a per-CPU map.
+///
+/// eBPF programs increment the bucket of each value on the CPU they run on,
+/// without any contention, and [`merge`](PerCpuHistogram::merge) sums the
+/// buckets of all the CPUs into a single [`Histogram`]. The keys of the map
+/// are the `u32` indexes of the buckets, see [`Histogram::bucket_index`],
+/// and its values are `u64` counts.
+///
+/// The map can be a `BPF_MAP_TYPE_PERCPU_ARRAY` or a
+/// `BPF_MAP_TYPE_PERCPU_HASH`, whose missing buckets count zero values.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.6, and 5.6
+/// for [`merge_and_reset`](PerCpuHistogram::merge_and_reset).
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::histogram::{Histogram, PerCpuHistogram};
+///
+/// let mut latencies = PerCpuHistogram::try_from(bpf.map_mut("LATENCIES").unwrap())?;
+/// let histogram = latencies.merge_and_reset()?;
+/// for (i, count) in histogram.buckets().iter().enumerate() {
+///     println!("{:?}: {}", Histogram::bucket_range(i), count);
+/// }
+/// println!("p99 <= {:?}", histogram.quantile(0.99));
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_PERCPU_ARRAY")]
+#[doc(alias = "BPF_MAP_TYPE_PERCPU_HASH")]
+pub struct PerCpuHistogram<T> {
+    inner: T,
+}
+
+impl<T: AsRef<MapData>> PerCpuHistogram<T> {
+    pub(crate) fn new(map: T) -> Result<PerCpuHistogram<T>, MapError> {
+        let data = map.as_ref();
+        check_kv_size::<u32, u64>(data)?;
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(PerCpuHistogram { inner: map })
+    }
+
+    /// Returns the number of buckets, the `max_entries` of the map.
+    pub fn len(&self) -> u32 {
+        self.inner.as_ref().obj.max_entries()
+    }
+
+    /// Returns the histogram of the values counted by all the CPUs.
+    ///
+    /// The buckets are read one at a time, so the histogram can include
+    /// values counted while it's read.
+    pub fn merge(&self) -> Result<Histogram, MapError> {
+        let fd = self.inner.as_ref().fd_or_err()?;
+        let buckets = (0..self.len())
+            .map(|i| {
+                let values =
+                    bpf_map_lookup_elem_per_cpu::<_, u64>(fd, &i, 0).map_err(|(_, io_error)| {
+                        MapError::SyscallError {
+                            call: "bpf_map_lookup_elem".to_owned(),
+                            io_error,
+                        }
+                    })?;
+                Ok(values.map(|v| v.iter().sum()).unwrap_or(0))
+            })
+            .collect::<Result<_, MapError>>()?;
+        Ok(Histogram::new(buckets))
+    }
+}
+
+impl<T: AsMut<MapData>> PerCpuHistogram<T> {
+    /// Returns the histogram of the values counted by all the CPUs, and
+    /// resets the buckets to zero.
+    ///
+    /// With a hash map, the buckets are read and deleted with
+    /// `BPF_MAP_LOOKUP_AND_DELETE_BATCH`, so no value is lost: values
+    /// counted once a bucket is deleted create it again. Array entries can't
+    /// be deleted, so the buckets of an array are read and then zeroed with
+    /// two batch operations, and the values counted in between are lost.
+    pub fn merge_and_reset(&mut self) -> Result<Histogram, MapError> {
+        let data = self.inner.as_mut();
+        let fd = data.fd_or_err()?;
+        let delete = data.obj.map_type() == BPF_MAP_TYPE_PERCPU_HASH as u32;
+        let len = data.obj.max_entries() as usize;
+        let nr_cpus = nr_cpus().map_err(|io_error| MapError::SyscallError {
+            call: "nr_cpus".to_owned(),
+            io_error,
+        })?;
+
+        let mut buckets = vec![0; len];
+        let mut keys = vec![0u32; len];
+        let mut values = vec![0u8; len * nr_cpus * mem::size_of::<u64>()];
+        let mut in_batch = None;
+        let mut out_batch = 0u32;
+        loop {
+            let (count, more) = bpf_map_lookup_batch(
+                fd,
+                in_batch.as_ref(),
+                &mut out_batch,
+                &mut keys,
+                &mut values,
+                delete,
+            )
+            .map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_lookup_batch".to_owned(),
+                io_error,
+            })?;
+            let count = count as usize;
+            add_buckets(&mut buckets, &keys[..count], &values, nr_cpus);
+
+            if !delete && count > 0 {
+                let zeros = vec![0u8; count * nr_cpus * mem::size_of::<u64>()];
+                bpf_map_update_batch(fd, &keys[..count], &zeros, 0).map_err(|(_, io_error)| {
+                    MapError::SyscallError {
+                        call: "bpf_map_update_batch".to_owned(),
+                        io_error,
+                    }
+                })?;
+            }
+            if !more {
+                break;
+            }
+            in_batch = Some(out_batch);
+        }
+        Ok(Histogram::new(buckets))
+    }
+}
+
+// Adds the per-CPU counts of the buckets `keys`, laid out one key after the
+// other in `values`, to `buckets`.
+fn add_buckets(buckets: &mut Vec<u64>, keys: &[u32], values: &[u8], nr_cpus: usize) {
+    let stride = nr_cpus * mem::size_of::<u64>();
+    for (key, values) in keys.iter().zip(values.chunks(stride)) {
+        let key = *key as usize;
+        if key >= buckets.len() {
+            buckets.resize(key + 1, 0);
+        }
+        buckets[key] += values
+            .chunks(mem::size_of::<u64>())
+            .map(|v| u64::from_ne_bytes(v.try_into().unwrap()))
+            .sum::<u64>();
+    }
+}
+
+impl<'a> TryFrom<&'a Map> for PerCpuHistogram<&'a MapData> {
+    type Error = MapError;
+
+    fn try_from(map: &'a Map) -> Result<PerCpuHistogram<&'a MapData>, MapError> {
+        match map {
+            Map::PerCpuArray(m) | Map::PerCpuHashMap(m) => PerCpuHistogram::new(m),
+            _ => Err(MapError::InvalidMapType {
+                map_type: map.map_type(),
+            }),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a mut Map> for PerCpuHistogram<&'a mut MapData> {
+    type Error = MapError;
+
+    fn try_from(map: &'a mut Map) -> Result<PerCpuHistogram<&'a mut MapData>, MapError> {
+        let map_type = map.map_type();
+        match map {
+            Map::PerCpuArray(m) | Map::PerCpuHashMap(m) => PerCpuHistogram::new(m),
+            _ => Err(MapError::InvalidMapType { map_type }),
+        }
+    }
+}
+
+impl TryFrom<Map> for PerCpuHistogram<MapData> {
+    type Error = MapError;
+
+    fn try_from(map: Map) -> Result<PerCpuHistogram<MapData>, MapError> {
+        let map_type = map.map_type();
+        match map {
+            Map::PerCpuArray(m) | Map::PerCpuHashMap(m) => PerCpuHistogram::new(m),
+            _ => Err(MapError::InvalidMapType { map_type }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, io};
+
+    use libc::{EFAULT, ENOENT};
+
+    use super::*;
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_attr, bpf_cmd, bpf_map_type::BPF_MAP_TYPE_PERCPU_ARRAY},
+        obj,
+        sys::{override_syscall, SysResult, Syscall, TEST_BATCH_COUNT},
+    };
+
+    thread_local! {
+        // the number of bpf_map_update_batch calls made on this thread
+        static UPDATES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn new_map(map_type: u32) -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(obj::LegacyMap {
+                def: bpf_map_def {
+                    map_type,
+                    key_size: 4,
+                    value_size: 8,
+                    max_entries: 4,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: obj::MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    // Writes a batch of `keys` whose per-CPU values are all `value`, like
+    // BPF_MAP_LOOKUP_BATCH does.
+    fn write_batch(attr: &bpf_attr, keys: &[u32], value: u64) {
+        let nr_cpus = nr_cpus().unwrap();
+        let batch = unsafe { &attr.batch };
+        let out_keys = batch.keys as *mut u32;
+        let out_values = batch.values as *mut u64;
+        for (i, key) in keys.iter().enumerate() {
+            unsafe { *out_keys.add(i) = *key };
+            for cpu in 0..nr_cpus {
+                unsafe { *out_values.add(i * nr_cpus + cpu) = value };
+            }
+        }
+        TEST_BATCH_COUNT.with(|count| *count.borrow_mut() = Some(keys.len() as u32));
+    }
+
+    #[test]
+    fn test_merge_and_reset_hash() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_AND_DELETE_BATCH,
+                attr,
+            } => {
+                if unsafe { attr.batch.in_batch } == 0 {
+                    write_batch(attr, &[1, 3], 1);
+                    Ok(0)
+                } else {
+                    // the last batch ends with ENOENT
+                    write_batch(attr, &[0], 5);
+                    sys_error(ENOENT)
+                }
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = new_map(BPF_MAP_TYPE_PERCPU_HASH as u32);
+        let mut histogram = PerCpuHistogram::new(&mut map).unwrap();
+        let nr_cpus = nr_cpus().unwrap() as u64;
+        assert_eq!(
+            histogram.merge_and_reset().unwrap().buckets(),
+            &[5 * nr_cpus, nr_cpus, 0, nr_cpus]
+        );
+    }
+
+    #[test]
+    fn test_merge_and_reset_array() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_BATCH,
+                attr,
+            } => {
+                write_batch(attr, &[0, 1, 2, 3], 2);
+                sys_error(ENOENT)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_BATCH,
+                attr,
+            } => {
+                let batch = unsafe { &attr.batch };
+                assert_eq!(batch.count, 4);
+                let len = 4 * nr_cpus().unwrap();
+                let values = unsafe { std::slice::from_raw_parts(batch.values as *const u64, len) };
+                assert!(values.iter().all(|v| *v == 0));
+                UPDATES.with(|updates| updates.set(updates.get() + 1));
+                Ok(0)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = new_map(BPF_MAP_TYPE_PERCPU_ARRAY as u32);
+        let mut histogram = PerCpuHistogram::new(&mut map).unwrap();
+        let nr_cpus = nr_cpus().unwrap() as u64;
+        assert_eq!(
+            histogram.merge_and_reset().unwrap().buckets(),
+            &[2 * nr_cpus; 4]
+        );
+        assert_eq!(UPDATES.with(|updates| updates.get()), 1);
+    }
+
+    #[test]
+    fn test_merge_and_reset_error() {
+        override_syscall(|_| sys_error(EFAULT));
+
+        let mut map = new_map(BPF_MAP_TYPE_PERCPU_HASH as u32);
+        let mut histogram = PerCpuHistogram::new(&mut map).unwrap();
+        assert!(matches!(
+            histogram.merge_and_reset(),
+            Err(MapError::SyscallError { call, io_error }) if call == "bpf_map_lookup_batch" && io_error.raw_os_error() == Some(EFAULT)
+        ));
+    }
+
+    #[test]
+    fn test_buckets() {
+        assert_eq!(Histogram::bucket_index(0), 0);
+        assert_eq!(Histogram::bucket_index(1), 1);
+        assert_eq!(Histogram::bucket_index(3), 2);
+        assert_eq!(Histogram::bucket_index(4), 3);
+        assert_eq!(Histogram::bucket_index(u64::MAX), 64);
+        for value in [0, 1, 2, 3, 4, 1000, 1 << 40] {
+            assert!(Histogram::bucket_range(Histogram::bucket_index(value)).contains(&value));
+        }
+        assert_eq!(Histogram::bucket_range(64), 1 << 63..u64::MAX);
+    }
+
+    #[test]
+    fn test_add_buckets() {
+        let values = [1u64, 2, 10, 20, 100, 200]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect::<Vec<_>>();
+        let mut buckets = vec![0; 4];
+        add_buckets(&mut buckets, &[0, 3, 5], &values, 2);
+        assert_eq!(buckets, vec![3, 0, 0, 30, 0, 300]);
+    }
+
+    #[test]
+    fn test_merge_and_quantile() {
+        let mut histogram = Histogram::new(vec![0, 5, 5]);
+        assert_eq!(histogram.count(), 10);
+        histogram.merge(&Histogram::new(vec![0, 0, 0, 10]));
+        assert_eq!(histogram.buckets(), &[0, 5, 5, 10]);
+        assert_eq!(histogram.quantile(0.0), Some(2));
+        assert_eq!(histogram.quantile(0.25), Some(2));
+        assert_eq!(histogram.quantile(0.5), Some(4));
+        assert_eq!(histogram.quantile(1.0), Some(8));
+        assert_eq!(Histogram::default().quantile(0.5), None);
+    }
+}
@@ -194,6 +194,8 @@ mod tests {
             symbol_index: 0,
             data: Vec::new(),
             kind: obj::MapKind::Other,
+            map_extra: 0,
+            numa_node: None,
         })
     }
 
@@ -250,6 +252,8 @@ mod tests {
                 symbol_index: 0,
                 data: Vec::new(),
                 kind: obj::MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
             }),
             fd: None,
             btf_fd: None,
@@ -0,0 +1,283 @@
+//! Migration of pinned maps to a new value layout.
+//!
+//! Pinned maps outlive the programs that created them, so a new version of a
+//! program can find a map pinned by an older version whose values have a
+//! different layout. By default loading fails with
+//! [`MapError::IncompatibleMap`]. A [`MapMigration`] registered with
+//! [`BpfLoader::migrate_map`](crate::BpfLoader::migrate_map) instead
+//! describes how to convert the old values, and the loader replaces the
+//! pinned map with a new map holding the converted entries.
+use std::{fmt, fs, mem, os::unix::io::RawFd, path::Path, ptr};
+
+use crate::{
+    generated::bpf_map_type::{
+        self, BPF_MAP_TYPE_LRU_PERCPU_HASH, BPF_MAP_TYPE_PERCPU_ARRAY,
+        BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE_DEPRECATED, BPF_MAP_TYPE_PERCPU_HASH,
+    },
+    maps::{map_mismatches, MapData, MapError, PinError},
+    sys::{bpf_map_get_info_by_fd, bpf_map_update_elem_ptr},
+    util::bytes_of,
+    Pod,
+};
+
+type Transform = Box<dyn Fn(&[u8]) -> Vec<u8>>;
+
+struct Step {
+    old_size: usize,
+    new_size: usize,
+    transform: Transform,
+}
+
+/// A list of transforms converting the values of a map from older layouts to
+/// newer ones.
+///
+/// Each layout is identified by the size of its values, so every step must
+/// change the value size. When a pinned map with a different value size is
+/// found, the steps are chained starting from the size of the pinned values
+/// until the size of the map definition is reached. The keys, the type, the
+/// maximum number of entries and the flags of the map must be unchanged.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::{maps::migrate::MapMigration, BpfLoader, Pod};
+///
+/// #[derive(Clone, Copy)]
+/// #[repr(C)]
+/// struct StatsV1 {
+///     packets: u64,
+/// }
+///
+/// #[derive(Clone, Copy)]
+/// #[repr(C)]
+/// struct StatsV2 {
+///     packets: u64,
+///     bytes: u64,
+/// }
+///
+/// unsafe impl Pod for StatsV1 {}
+/// unsafe impl Pod for StatsV2 {}
+///
+/// let mut migration = MapMigration::new();
+/// migration.step(|old: StatsV1| StatsV2 {
+///     packets: old.packets,
+///     bytes: 0,
+/// });
+///
+/// let bpf = BpfLoader::new()
+///     .map_pin_path("/sys/fs/bpf/my-program")
+///     .migrate_map("STATS", &migration)
+///     .load_file("file.o")?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Default)]
+pub struct MapMigration {
+    steps: Vec<Step>,
+}
+
+impl MapMigration {
+    /// Creates a migration without any steps.
+    pub fn new() -> MapMigration {
+        MapMigration::default()
+    }
+
+    /// Adds a step converting values of type `Old` to values of type `New`.
+    pub fn step<Old: Pod, New: Pod>(
+        &mut self,
+        transform: impl Fn(Old) -> New + 'static,
+    ) -> &mut MapMigration {
+        self.steps.push(Step {
+            old_size: mem::size_of::<Old>(),
+            new_size: mem::size_of::<New>(),
+            transform: Box::new(move |value| {
+                // the size of the value is checked in MapMigration::steps
+                let old = unsafe { ptr::read_unaligned(value.as_ptr() as *const Old) };
+                unsafe { bytes_of(&transform(old)) }.to_vec()
+            }),
+        });
+        self
+    }
+
+    // Returns the steps converting values of `from` bytes to values of `to`
+    // bytes, or None if there's no such chain.
+    fn steps(&self, from: usize, to: usize) -> Option<Vec<&Step>> {
+        let mut steps = Vec::new();
+        let mut size = from;
+        while size != to {
+            // each step is used at most once so cycles terminate
+            if steps.len() == self.steps.len() {
+                return None;
+            }
+            let step = self.steps.iter().find(|step| step.old_size == size)?;
+            size = step.new_size;
+            steps.push(step);
+        }
+        Some(steps)
+    }
+}
+
+impl fmt::Debug for MapMigration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.steps.iter().map(|step| (step.old_size, step.new_size)))
+            .finish()
+    }
+}
+
+fn apply(steps: &[&Step], value: &[u8]) -> Vec<u8> {
+    steps
+        .iter()
+        .fold(value.to_vec(), |value, step| (step.transform)(&value))
+}
+
+impl MapData {
+    /// Replaces the map pinned as `name` in `path` with a new map whose
+    /// entries are converted by `migration`.
+    ///
+    /// The new map is pinned next to the old one first, then renamed over it,
+    /// so the pin always refers to a complete map. Updates made to the old map
+    /// while it's being copied are lost.
+    pub(crate) fn migrate_pinned<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+        migration: &MapMigration,
+    ) -> Result<RawFd, MapError> {
+        let pin_path = path.as_ref().join(name);
+        let old = MapData::from_pin(&pin_path)?;
+        let info = bpf_map_get_info_by_fd(old.fd_or_err()?).map_err(|io_error| {
+            MapError::SyscallError {
+                call: "BPF_MAP_GET_INFO_BY_FD".to_owned(),
+                io_error,
+            }
+        })?;
+        let mismatches = map_mismatches(&self.obj, &info);
+        let steps = mismatches
+            .iter()
+            .all(|m| m.field == "value_size")
+            .then(|| migration.steps(info.value_size as usize, self.obj.value_size() as usize))
+            .flatten()
+            .ok_or_else(|| MapError::IncompatibleMap {
+                name: name.to_owned(),
+                mismatches: mismatches.clone(),
+            })?;
+
+        let snapshot = old.snapshot()?;
+        let fd = self.create(name)?;
+        let value_size = self.obj.value_size() as usize;
+        // per-CPU maps have a single value on single CPU machines too
+        let per_cpu = matches!(
+            bpf_map_type::try_from(info.type_),
+            Ok(BPF_MAP_TYPE_PERCPU_HASH
+                | BPF_MAP_TYPE_PERCPU_ARRAY
+                | BPF_MAP_TYPE_LRU_PERCPU_HASH
+                | BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE_DEPRECATED)
+        );
+        for entry in snapshot.entries {
+            let mut value = if per_cpu {
+                // per-CPU values are padded to 8 bytes
+                let stride = (value_size + 7) & !7;
+                let mut value = vec![0u8; stride * entry.values.len()];
+                for (cpu, old) in entry.values.iter().enumerate() {
+                    value[cpu * stride..][..value_size].copy_from_slice(&apply(&steps, old));
+                }
+                value
+            } else {
+                apply(&steps, &entry.values[0])
+            };
+            bpf_map_update_elem_ptr(fd, entry.key.as_ptr(), value.as_mut_ptr(), 0).map_err(
+                |(_, io_error)| MapError::SyscallError {
+                    call: "bpf_map_update_elem".to_owned(),
+                    io_error,
+                },
+            )?;
+        }
+
+        let pin_error = |error| MapError::PinError {
+            name: Some(name.to_owned()),
+            error,
+        };
+        let tmp_path = path.as_ref().join(format!("{name}.migrating"));
+        // left behind by a migration that didn't complete
+        let _ = fs::remove_file(&tmp_path);
+        self.pin_at(name, &tmp_path).map_err(pin_error)?;
+        fs::rename(&tmp_path, &pin_path).map_err(|io_error| {
+            let _ = fs::remove_file(&tmp_path);
+            pin_error(PinError::SyscallError {
+                name: "rename".to_owned(),
+                io_error,
+            })
+        })?;
+        Ok(fd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct V1 {
+        a: u32,
+    }
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct V2 {
+        a: u32,
+        b: u32,
+    }
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct V3 {
+        a: u64,
+        b: u32,
+        c: u32,
+    }
+
+    unsafe impl Pod for V1 {}
+    unsafe impl Pod for V2 {}
+    unsafe impl Pod for V3 {}
+
+    fn migration() -> MapMigration {
+        let mut migration = MapMigration::new();
+        migration
+            .step(|old: V2| V3 {
+                a: old.a as u64,
+                b: old.b,
+                c: 3,
+            })
+            .step(|old: V1| V2 { a: old.a, b: 2 });
+        migration
+    }
+
+    #[test]
+    fn test_chain_steps() {
+        let migration = migration();
+        let steps = migration.steps(4, 16).unwrap();
+        assert_eq!(
+            apply(&steps, &1u32.to_ne_bytes()),
+            unsafe { bytes_of(&V3 { a: 1, b: 2, c: 3 }) }.to_vec()
+        );
+
+        let steps = migration.steps(8, 16).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert!(migration.steps(16, 16).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_no_steps() {
+        let migration = migration();
+        assert!(migration.steps(16, 4).is_none());
+        assert!(migration.steps(12, 16).is_none());
+
+        // a cycle never reaching the target size
+        let mut migration = MapMigration::new();
+        migration
+            .step(|old: V1| V2 { a: old.a, b: 0 })
+            .step(|old: V2| V1 { a: old.a });
+        assert!(migration.steps(4, 16).is_none());
+    }
+}
@@ -53,39 +53,60 @@ use log::warn;
 use thiserror::Error;
 
 use crate::{
-    generated::bpf_map_type,
-    obj::{self, parse_map_info},
+    generated::{bpf_map_info, bpf_map_type},
+    obj::{self, btf::BtfError, parse_map_info},
     pin::PinError,
     sys::{
-        bpf_create_map, bpf_get_object, bpf_map_get_info_by_fd, bpf_map_get_next_key,
-        bpf_pin_object, kernel_version,
+        bpf_create_map, bpf_fd_kind, bpf_get_object, bpf_map_get_fd_by_id, bpf_map_get_info_by_fd,
+        bpf_map_get_next_key, bpf_pin_object, kernel_version, BpfFdKind,
     },
     util::nr_cpus,
     PinningType, Pod,
 };
 
+pub mod arena;
 pub mod array;
 pub mod bloom_filter;
+pub mod double_buffer;
+pub mod dump;
+mod field;
 pub mod hash_map;
+pub mod histogram;
 pub mod lpm_trie;
+pub mod migrate;
+pub mod notify;
 pub mod perf;
+pub mod poll;
 pub mod queue;
+pub mod ring_buf;
+pub mod snapshot;
 pub mod sock;
 pub mod stack;
 pub mod stack_trace;
+pub mod xdp;
 
+pub use arena::Arena;
 pub use array::{Array, PerCpuArray, ProgramArray};
 pub use bloom_filter::BloomFilter;
-pub use hash_map::{HashMap, PerCpuHashMap};
+pub use dump::{BtfValue, DecodedEntry};
+pub use hash_map::{HashMap, LruHashMap, PerCpuHashMap, PerCpuLruHashMap};
+pub use histogram::PerCpuHistogram;
 pub use lpm_trie::LpmTrie;
 #[cfg(feature = "async")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
 pub use perf::AsyncPerfEventArray;
 pub use perf::PerfEventArray;
+pub use poll::PollSet;
 pub use queue::Queue;
-pub use sock::{SockHash, SockMap};
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use ring_buf::AsyncRingBuf;
+pub use ring_buf::RingBuf;
+pub use snapshot::{loaded_maps, MapEntry, MapInfo, MapSnapshot};
+pub use sock::{ReusePortSockArray, SockHash, SockMap};
 pub use stack::Stack;
 pub use stack_trace::StackTraceMap;
+pub use xdp::{DevMap, DevMapHash};
 
 #[derive(Error, Debug)]
 /// Errors occuring from working with Maps
@@ -154,6 +175,17 @@ pub enum MapError {
         max_entries: u32,
     },
 
+    /// An access is out of the memory mapped by a map
+    #[error("{len} bytes at offset {offset} are out of bounds, the map is {size} bytes")]
+    OutOfRange {
+        /// Offset accessed
+        offset: usize,
+        /// Number of bytes accessed
+        len: usize,
+        /// Size of the mapped memory
+        size: usize,
+    },
+
     /// Key not found
     #[error("key not found")]
     KeyNotFound,
@@ -166,12 +198,20 @@ pub enum MapError {
     #[error("the program is not loaded")]
     ProgramNotLoaded,
 
+    /// The file descriptor doesn't refer to a BPF map
+    #[error("file descriptor {fd} is not a BPF map")]
+    InvalidFd {
+        /// The file descriptor
+        fd: RawFd,
+    },
+
     /// Syscall failed
     #[error("the `{call}` syscall failed")]
     SyscallError {
         /// Syscall Name
         call: String,
         /// Original io::Error
+        #[source]
         io_error: io::Error,
     },
 
@@ -184,6 +224,75 @@ pub enum MapError {
         #[source]
         error: PinError,
     },
+
+    /// A pinned map doesn't match the map definition it's being reused for
+    #[error(
+        "pinned map `{name}` is incompatible with its definition: {}",
+        .mismatches.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    IncompatibleMap {
+        /// The map name
+        name: String,
+        /// The fields that don't match
+        mismatches: Vec<MapFieldMismatch>,
+    },
+
+    /// The map was created without BTF type information
+    #[error("the map has no BTF type information")]
+    NoBtf,
+
+    /// The map value has no field with the given name
+    #[error("the map value has no field `{field}`")]
+    FieldNotFound {
+        /// The field name
+        field: String,
+    },
+
+    /// An error occurred while working with BTF
+    #[error(transparent)]
+    Btf(#[from] BtfError),
+}
+
+impl MapError {
+    /// Returns the name of the failed syscall and the [`io::Error`] it
+    /// returned, if this error was caused by a syscall failure.
+    ///
+    /// This can be used to branch on the raw errno, eg to tell `EPERM` apart
+    /// from `E2BIG` when creating a map.
+    pub fn syscall_error(&self) -> Option<(&str, &io::Error)> {
+        match self {
+            MapError::CreateError { io_error, .. } => Some(("bpf_map_create", io_error)),
+            MapError::SyscallError { call, io_error } => Some((call, io_error)),
+            MapError::PinError {
+                error: PinError::SyscallError { name, io_error },
+                ..
+            } => Some((name, io_error)),
+            _ => None,
+        }
+    }
+}
+
+/// A map attribute whose value differs between a pinned map and its definition.
+///
+/// Returned as part of [`MapError::IncompatibleMap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapFieldMismatch {
+    /// The name of the attribute, eg `key_size`
+    pub field: &'static str,
+    /// The value in the map definition
+    pub expected: u32,
+    /// The value of the pinned map
+    pub found: u32,
+}
+
+impl fmt::Display for MapFieldMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (expected {}, found {})",
+            self.field, self.expected, self.found
+        )
+    }
 }
 
 /// A map file descriptor.
@@ -218,7 +327,7 @@ fn maybe_warn_rlimit() {
         let limit = unsafe { limit.assume_init() };
 
         let limit: RlimitSize = RlimitSize(limit.rlim_cur.try_into().unwrap());
-        if limit.0 == RLIM_INFINITY.try_into().unwrap() {
+        if limit.0 == usize::try_from(RLIM_INFINITY).unwrap() {
             return;
         }
         warn!(
@@ -243,12 +352,18 @@ pub enum Map {
     HashMap(MapData),
     /// A [`PerCpuHashMap`] map
     PerCpuHashMap(MapData),
+    /// A [`LruHashMap`] map
+    LruHashMap(MapData),
+    /// A [`PerCpuLruHashMap`] map
+    PerCpuLruHashMap(MapData),
     /// A [`PerfEventArray`] map
     PerfEventArray(MapData),
     /// A [`SockMap`] map
     SockMap(MapData),
     /// A [`SockHash`] map
     SockHash(MapData),
+    /// A [`ReusePortSockArray`] map
+    ReusePortSockArray(MapData),
     /// A [`BloomFilter`] map
     BloomFilter(MapData),
     /// A [`LpmTrie`] map
@@ -259,6 +374,14 @@ pub enum Map {
     StackTraceMap(MapData),
     /// A [`Queue`] map
     Queue(MapData),
+    /// A [`RingBuf`] map
+    RingBuf(MapData),
+    /// An [`Arena`] map
+    Arena(MapData),
+    /// A [`DevMap`] map
+    DevMap(MapData),
+    /// A [`DevMapHash`] map
+    DevMapHash(MapData),
 }
 
 impl Map {
@@ -270,16 +393,99 @@ impl Map {
             Map::ProgramArray(map) => map.obj.map_type(),
             Map::HashMap(map) => map.obj.map_type(),
             Map::PerCpuHashMap(map) => map.obj.map_type(),
+            Map::LruHashMap(map) => map.obj.map_type(),
+            Map::PerCpuLruHashMap(map) => map.obj.map_type(),
             Map::PerfEventArray(map) => map.obj.map_type(),
             Map::SockHash(map) => map.obj.map_type(),
             Map::SockMap(map) => map.obj.map_type(),
+            Map::ReusePortSockArray(map) => map.obj.map_type(),
             Map::BloomFilter(map) => map.obj.map_type(),
             Map::LpmTrie(map) => map.obj.map_type(),
             Map::Stack(map) => map.obj.map_type(),
             Map::StackTraceMap(map) => map.obj.map_type(),
             Map::Queue(map) => map.obj.map_type(),
+            Map::RingBuf(map) => map.obj.map_type(),
+            Map::Arena(map) => map.obj.map_type(),
+            Map::DevMap(map) => map.obj.map_type(),
+            Map::DevMapHash(map) => map.obj.map_type(),
         }
     }
+
+    pub(crate) fn data(&self) -> &MapData {
+        match self {
+            Map::Array(map) => map,
+            Map::PerCpuArray(map) => map,
+            Map::ProgramArray(map) => map,
+            Map::HashMap(map) => map,
+            Map::PerCpuHashMap(map) => map,
+            Map::LruHashMap(map) => map,
+            Map::PerCpuLruHashMap(map) => map,
+            Map::PerfEventArray(map) => map,
+            Map::SockHash(map) => map,
+            Map::SockMap(map) => map,
+            Map::ReusePortSockArray(map) => map,
+            Map::BloomFilter(map) => map,
+            Map::LpmTrie(map) => map,
+            Map::Stack(map) => map,
+            Map::StackTraceMap(map) => map,
+            Map::Queue(map) => map,
+            Map::RingBuf(map) => map,
+            Map::Arena(map) => map,
+            Map::DevMap(map) => map,
+            Map::DevMapHash(map) => map,
+        }
+    }
+
+    pub(crate) fn data_mut(&mut self) -> &mut MapData {
+        match self {
+            Map::Array(map) => map,
+            Map::PerCpuArray(map) => map,
+            Map::ProgramArray(map) => map,
+            Map::HashMap(map) => map,
+            Map::PerCpuHashMap(map) => map,
+            Map::LruHashMap(map) => map,
+            Map::PerCpuLruHashMap(map) => map,
+            Map::PerfEventArray(map) => map,
+            Map::SockHash(map) => map,
+            Map::SockMap(map) => map,
+            Map::ReusePortSockArray(map) => map,
+            Map::BloomFilter(map) => map,
+            Map::LpmTrie(map) => map,
+            Map::Stack(map) => map,
+            Map::StackTraceMap(map) => map,
+            Map::Queue(map) => map,
+            Map::RingBuf(map) => map,
+            Map::Arena(map) => map,
+            Map::DevMap(map) => map,
+            Map::DevMapHash(map) => map,
+        }
+    }
+
+    /// Returns a new handle to the same map, see [`MapData::try_clone`].
+    pub fn try_clone(&self) -> Result<Map, MapError> {
+        Ok(match self {
+            Map::Array(map) => Map::Array(map.try_clone()?),
+            Map::PerCpuArray(map) => Map::PerCpuArray(map.try_clone()?),
+            Map::ProgramArray(map) => Map::ProgramArray(map.try_clone()?),
+            Map::HashMap(map) => Map::HashMap(map.try_clone()?),
+            Map::PerCpuHashMap(map) => Map::PerCpuHashMap(map.try_clone()?),
+            Map::LruHashMap(map) => Map::LruHashMap(map.try_clone()?),
+            Map::PerCpuLruHashMap(map) => Map::PerCpuLruHashMap(map.try_clone()?),
+            Map::PerfEventArray(map) => Map::PerfEventArray(map.try_clone()?),
+            Map::SockHash(map) => Map::SockHash(map.try_clone()?),
+            Map::SockMap(map) => Map::SockMap(map.try_clone()?),
+            Map::ReusePortSockArray(map) => Map::ReusePortSockArray(map.try_clone()?),
+            Map::BloomFilter(map) => Map::BloomFilter(map.try_clone()?),
+            Map::LpmTrie(map) => Map::LpmTrie(map.try_clone()?),
+            Map::Stack(map) => Map::Stack(map.try_clone()?),
+            Map::StackTraceMap(map) => Map::StackTraceMap(map.try_clone()?),
+            Map::Queue(map) => Map::Queue(map.try_clone()?),
+            Map::RingBuf(map) => Map::RingBuf(map.try_clone()?),
+            Map::Arena(map) => Map::Arena(map.try_clone()?),
+            Map::DevMap(map) => Map::DevMap(map.try_clone()?),
+            Map::DevMapHash(map) => Map::DevMapHash(map.try_clone()?),
+        })
+    }
 }
 
 macro_rules! impl_try_from_map {
@@ -330,14 +536,20 @@ macro_rules! impl_try_from_map {
 impl_try_from_map!(
     ProgramArray from Map::ProgramArray,
     SockMap from Map::SockMap,
+    ReusePortSockArray from Map::ReusePortSockArray,
     PerfEventArray from Map::PerfEventArray,
     StackTraceMap from Map::StackTraceMap,
+    RingBuf from Map::RingBuf,
+    Arena from Map::Arena,
+    DevMap from Map::DevMap,
+    DevMapHash from Map::DevMapHash,
 );
 
 #[cfg(feature = "async")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
 impl_try_from_map!(
     AsyncPerfEventArray from Map::PerfEventArray,
+    AsyncRingBuf from Map::RingBuf,
 );
 
 macro_rules! impl_try_from_map_generic_key_or_value {
@@ -419,7 +631,13 @@ macro_rules! impl_try_from_map_generic_key_and_value {
    }
 }
 
-impl_try_from_map_generic_key_and_value!(HashMap, PerCpuHashMap, LpmTrie);
+impl_try_from_map_generic_key_and_value!(
+    HashMap,
+    PerCpuHashMap,
+    LruHashMap,
+    PerCpuLruHashMap,
+    LpmTrie
+);
 
 pub(crate) fn check_bounds(map: &MapData, index: u32) -> Result<(), MapError> {
     let max_entries = map.obj.max_entries();
@@ -519,6 +737,22 @@ impl MapData {
             io_error,
         })? as RawFd;
 
+        let info = bpf_map_get_info_by_fd(fd).map_err(|io_error| {
+            unsafe { libc::close(fd) };
+            MapError::SyscallError {
+                call: "BPF_MAP_GET_INFO_BY_FD".to_owned(),
+                io_error,
+            }
+        })?;
+        let mismatches = map_mismatches(&self.obj, &info);
+        if !mismatches.is_empty() {
+            unsafe { libc::close(fd) };
+            return Err(MapError::IncompatibleMap {
+                name: name.into(),
+                mismatches,
+            });
+        }
+
         self.fd = Some(fd);
 
         Ok(fd)
@@ -559,11 +793,18 @@ impl MapData {
     /// If loading from a BPF Filesystem (bpffs) you should use [`Map::from_pin`](crate::maps::MapData::from_pin).
     /// This API is intended for cases where you have received a valid BPF FD from some other means.
     /// For example, you received an FD over Unix Domain Socket.
+    ///
+    /// The fd is checked to refer to a BPF map of a known type before it's
+    /// wrapped. On success the returned map takes ownership of `fd`.
     pub fn from_fd(fd: RawFd) -> Result<MapData, MapError> {
+        if bpf_fd_kind(fd) != Some(BpfFdKind::Map) {
+            return Err(MapError::InvalidFd { fd });
+        }
         let info = bpf_map_get_info_by_fd(fd).map_err(|io_error| MapError::SyscallError {
-            call: "BPF_OBJ_GET".to_owned(),
+            call: "BPF_MAP_GET_INFO_BY_FD".to_owned(),
             io_error,
         })?;
+        bpf_map_type::try_from(info.type_)?;
 
         Ok(MapData {
             obj: parse_map_info(info, PinningType::None),
@@ -573,6 +814,60 @@ impl MapData {
         })
     }
 
+    /// Opens a map from its id.
+    ///
+    /// Map ids can be obtained with [`loaded_maps`].
+    pub fn from_id(id: u32) -> Result<MapData, MapError> {
+        let fd = bpf_map_get_fd_by_id(id).map_err(|io_error| MapError::SyscallError {
+            call: "bpf_map_get_fd_by_id".to_owned(),
+            io_error,
+        })?;
+        match MapData::from_fd(fd) {
+            Ok(map) => Ok(map),
+            Err(e) => {
+                // from_fd only takes ownership of the fd on success
+                unsafe { libc::close(fd) };
+                Err(e)
+            }
+        }
+    }
+
+    /// Makes this map refer to the already created `other` map instead of
+    /// creating a new one.
+    pub(crate) fn reuse(&mut self, name: &str, other: &MapData) -> Result<RawFd, MapError> {
+        if self.fd.is_some() {
+            return Err(MapError::AlreadyCreated { name: name.into() });
+        }
+        let other_fd = other.fd_or_err()?;
+        let mismatches = field_mismatches(map_fields(&self.obj), map_fields(&other.obj));
+        if !mismatches.is_empty() {
+            return Err(MapError::IncompatibleMap {
+                name: name.into(),
+                mismatches,
+            });
+        }
+
+        let fd = dup_fd(other_fd)?;
+        self.fd = Some(fd);
+        Ok(fd)
+    }
+
+    /// Returns a new handle to the same map.
+    ///
+    /// The new handle owns a duplicate of the map file descriptor, so it can
+    /// outlive `self`. The descriptor can be passed to another process, for
+    /// example over a Unix socket with `SCM_RIGHTS`, and turned back into a
+    /// map there with [`MapData::from_fd`]. Alternatively the map can be pinned
+    /// and opened with [`MapData::from_pin`].
+    pub fn try_clone(&self) -> Result<MapData, MapError> {
+        Ok(MapData {
+            obj: self.obj.clone(),
+            fd: self.fd.map(dup_fd).transpose()?,
+            btf_fd: self.btf_fd,
+            pinned: self.pinned,
+        })
+    }
+
     pub(crate) fn fd_or_err(&self) -> Result<RawFd, MapError> {
         self.fd.ok_or(MapError::NotCreated)
     }
@@ -581,17 +876,30 @@ impl MapData {
         if self.pinned {
             return Err(PinError::AlreadyPinned { name: name.into() });
         }
-        let map_path = path.as_ref().join(name);
+        self.pin_at(name, path.as_ref().join(name))
+    }
+
+    /// Pins the map to a BPF filesystem.
+    ///
+    /// The map stays alive after the fd is closed, until the pin is removed.
+    /// It can be opened again with [`MapData::from_pin`].
+    pub fn pin_to<P: AsRef<Path>>(&mut self, path: P) -> Result<(), PinError> {
+        let name = path.as_ref().to_string_lossy().into_owned();
+        self.pin_at(&name, path)
+    }
+
+    fn pin_at<P: AsRef<Path>>(&mut self, name: &str, map_path: P) -> Result<(), PinError> {
         let fd = self.fd.ok_or(PinError::NoFd {
             name: name.to_string(),
         })?;
-        let path_string = CString::new(map_path.to_string_lossy().into_owned()).map_err(|e| {
-            PinError::InvalidPinPath {
-                error: e.to_string(),
-            }
-        })?;
+        let path_string =
+            CString::new(map_path.as_ref().to_string_lossy().into_owned()).map_err(|e| {
+                PinError::InvalidPinPath {
+                    error: e.to_string(),
+                }
+            })?;
         bpf_pin_object(fd, &path_string).map_err(|(_, io_error)| PinError::SyscallError {
-            name: "BPF_OBJ_GET".to_string(),
+            name: "BPF_OBJ_PIN".to_string(),
             io_error,
         })?;
         self.pinned = true;
@@ -615,22 +923,73 @@ impl Drop for MapData {
     }
 }
 
+/// Clones the map handle, see [`MapData::try_clone`].
+///
+/// # Panics
+///
+/// Panics if the map file descriptor can't be duplicated.
 impl Clone for MapData {
     fn clone(&self) -> MapData {
-        MapData {
-            obj: self.obj.clone(),
-            fd: {
-                if let Some(fd) = self.fd {
-                    unsafe { Some(libc::dup(fd)) };
-                }
-                None
-            },
-            btf_fd: self.btf_fd,
-            pinned: self.pinned,
-        }
+        self.try_clone()
+            .expect("failed to duplicate the map file descriptor")
     }
 }
 
+fn dup_fd(fd: RawFd) -> Result<RawFd, MapError> {
+    let fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(MapError::SyscallError {
+            call: "fcntl".to_owned(),
+            io_error: io::Error::last_os_error(),
+        });
+    }
+    Ok(fd)
+}
+
+const MAP_FIELDS: [&str; 5] = [
+    "map_type",
+    "key_size",
+    "value_size",
+    "max_entries",
+    "map_flags",
+];
+
+fn map_fields(def: &obj::Map) -> [u32; 5] {
+    [
+        def.map_type(),
+        def.key_size(),
+        def.value_size(),
+        def.max_entries(),
+        def.map_flags(),
+    ]
+}
+
+fn map_mismatches(def: &obj::Map, info: &bpf_map_info) -> Vec<MapFieldMismatch> {
+    field_mismatches(
+        map_fields(def),
+        [
+            info.type_,
+            info.key_size,
+            info.value_size,
+            info.max_entries,
+            info.map_flags,
+        ],
+    )
+}
+
+fn field_mismatches(expected: [u32; 5], found: [u32; 5]) -> Vec<MapFieldMismatch> {
+    MAP_FIELDS
+        .into_iter()
+        .zip(expected.into_iter().zip(found))
+        .filter(|(_, (expected, found))| expected != found)
+        .map(|(field, (expected, found))| MapFieldMismatch {
+            field,
+            expected,
+            found,
+        })
+        .collect()
+}
+
 /// An iterable map
 pub trait IterableMap<K: Pod, V> {
     /// Get a generic map handle
@@ -754,10 +1113,12 @@ impl TryFrom<u32> for bpf_map_type {
             x if x == BPF_MAP_TYPE_CPUMAP as u32 => BPF_MAP_TYPE_CPUMAP,
             x if x == BPF_MAP_TYPE_XSKMAP as u32 => BPF_MAP_TYPE_XSKMAP,
             x if x == BPF_MAP_TYPE_SOCKHASH as u32 => BPF_MAP_TYPE_SOCKHASH,
-            x if x == BPF_MAP_TYPE_CGROUP_STORAGE as u32 => BPF_MAP_TYPE_CGROUP_STORAGE,
+            x if x == BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED as u32 => {
+                BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED
+            }
             x if x == BPF_MAP_TYPE_REUSEPORT_SOCKARRAY as u32 => BPF_MAP_TYPE_REUSEPORT_SOCKARRAY,
-            x if x == BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE as u32 => {
-                BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE
+            x if x == BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE_DEPRECATED as u32 => {
+                BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE_DEPRECATED
             }
             x if x == BPF_MAP_TYPE_QUEUE as u32 => BPF_MAP_TYPE_QUEUE,
             x if x == BPF_MAP_TYPE_STACK as u32 => BPF_MAP_TYPE_STACK,
@@ -767,6 +1128,9 @@ impl TryFrom<u32> for bpf_map_type {
             x if x == BPF_MAP_TYPE_RINGBUF as u32 => BPF_MAP_TYPE_RINGBUF,
             x if x == BPF_MAP_TYPE_INODE_STORAGE as u32 => BPF_MAP_TYPE_INODE_STORAGE,
             x if x == BPF_MAP_TYPE_TASK_STORAGE as u32 => BPF_MAP_TYPE_TASK_STORAGE,
+            x if x == BPF_MAP_TYPE_USER_RINGBUF as u32 => BPF_MAP_TYPE_USER_RINGBUF,
+            x if x == BPF_MAP_TYPE_CGRP_STORAGE as u32 => BPF_MAP_TYPE_CGRP_STORAGE,
+            x if x == BPF_MAP_TYPE_ARENA as u32 => BPF_MAP_TYPE_ARENA,
             _ => return Err(MapError::InvalidMapType { map_type }),
         })
     }
@@ -872,14 +1236,15 @@ impl<T: Pod> Deref for PerCpuValues<T> {
 
 #[cfg(test)]
 mod tests {
-    use libc::EFAULT;
+    use libc::{EFAULT, EPERM};
 
     use crate::{
         bpf_map_def,
         generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_HASH},
         maps::MapData,
         obj::MapKind,
-        sys::{override_syscall, Syscall},
+        programs::ProgramError,
+        sys::{override_syscall, SysResult, Syscall},
     };
 
     use super::*;
@@ -897,6 +1262,8 @@ mod tests {
             symbol_index: 0,
             data: Vec::new(),
             kind: MapKind::Other,
+            map_extra: 0,
+            numa_node: None,
         })
     }
 
@@ -947,4 +1314,153 @@ mod tests {
         }
         assert_eq!(map.fd, None);
     }
+
+    #[test]
+    fn test_from_fd_invalid() {
+        use std::{fs::File, os::unix::io::AsRawFd};
+
+        let file = File::open("/dev/null").unwrap();
+        let fd = file.as_raw_fd();
+        assert!(matches!(
+            MapData::from_fd(fd),
+            Err(MapError::InvalidFd { fd: f }) if f == fd
+        ));
+    }
+
+    #[test]
+    fn test_try_clone() {
+        use std::{fs::File, os::unix::io::IntoRawFd};
+
+        let map = new_map();
+        assert_eq!(map.try_clone().unwrap().fd, None);
+
+        let mut map = new_map();
+        map.fd = Some(File::open("/dev/null").unwrap().into_raw_fd());
+        let clone = map.try_clone().unwrap();
+        assert!(clone.fd.is_some());
+        assert_ne!(clone.fd, map.fd);
+        assert_eq!(clone.obj.max_entries(), map.obj.max_entries());
+
+        let clone = Map::HashMap(map).try_clone().unwrap();
+        assert!(matches!(clone, Map::HashMap(MapData { fd: Some(_), .. })));
+    }
+
+    #[test]
+    fn test_syscall_error() {
+        override_syscall(|_| Err((-1, io::Error::from_raw_os_error(EPERM))));
+
+        let mut map = new_map();
+        let err = map.create("foo").unwrap_err();
+        let (call, io_error) = err.syscall_error().unwrap();
+        assert_eq!(call, "bpf_map_create");
+        assert_eq!(io_error.raw_os_error(), Some(EPERM));
+
+        let err = ProgramError::from(err);
+        assert_eq!(err.syscall_error().unwrap().0, "bpf_map_create");
+        assert!(MapError::KeyNotFound.syscall_error().is_none());
+    }
+
+    unsafe fn fake_pinned_map(call: Syscall) -> SysResult {
+        match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET,
+                ..
+            } => Ok(42),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = &mut *(attr.info.info as *mut bpf_map_info);
+                info.type_ = BPF_MAP_TYPE_HASH as u32;
+                info.key_size = 4;
+                info.value_size = 8;
+                info.max_entries = 1024;
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        }
+    }
+
+    #[test]
+    fn test_open_pinned_incompatible() {
+        override_syscall(fake_pinned_map);
+
+        let mut map = new_map();
+        let ret = map.open_pinned("foo", "/sys/fs/bpf");
+        match ret {
+            Err(MapError::IncompatibleMap { name, mismatches }) => {
+                assert_eq!(name, "foo");
+                assert_eq!(
+                    mismatches,
+                    vec![MapFieldMismatch {
+                        field: "value_size",
+                        expected: 4,
+                        found: 8,
+                    }]
+                );
+            }
+            _ => panic!("unexpected result"),
+        }
+        assert_eq!(map.fd, None);
+    }
+
+    #[test]
+    fn test_reuse() {
+        use std::{fs::File, os::unix::io::IntoRawFd};
+
+        let mut other = new_map();
+        other.fd = Some(File::open("/dev/null").unwrap().into_raw_fd());
+
+        let mut map = new_map();
+        let fd = map.reuse("foo", &other).unwrap();
+        assert_ne!(Some(fd), other.fd);
+        assert_eq!(map.fd, Some(fd));
+        assert!(matches!(
+            map.reuse("foo", &other),
+            Err(MapError::AlreadyCreated { .. })
+        ));
+
+        let mut map = new_map();
+        map.obj.set_max_entries(16);
+        match map.reuse("foo", &other) {
+            Err(MapError::IncompatibleMap { mismatches, .. }) => assert_eq!(
+                mismatches,
+                vec![MapFieldMismatch {
+                    field: "max_entries",
+                    expected: 16,
+                    found: 1024,
+                }]
+            ),
+            _ => panic!("unexpected result"),
+        }
+        assert_eq!(map.fd, None);
+    }
+
+    #[test]
+    fn test_open_pinned_compatible() {
+        override_syscall(fake_pinned_map);
+
+        let mut map = MapData {
+            obj: obj::Map::Legacy(obj::LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_HASH as u32,
+                    key_size: 4,
+                    value_size: 8,
+                    max_entries: 1024,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
+            }),
+            fd: None,
+            pinned: false,
+            btf_fd: None,
+        };
+        assert!(matches!(map.open_pinned("foo", "/sys/fs/bpf"), Ok(42)));
+        assert_eq!(map.fd, Some(42));
+    }
 }
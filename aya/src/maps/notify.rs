@@ -0,0 +1,312 @@
+//! Notifications of map updates received through perf buffers.
+//!
+//! Watching a large map for changes by reading it on a timer is slow and
+//! adds latency. Instead, the eBPF programs updating the map can write a
+//! small notification to a [`PerfEventArray`] after each update, telling user
+//! space which map changed and, optionally, which key:
+//!
+//! ```ignore
+//! #[map]
+//! static CONNECTIONS: HashMap<u32, Connection> = HashMap::with_max_entries(65536, 0);
+//! #[map]
+//! static UPDATES: PerfEventByteArray = PerfEventByteArray::new(0);
+//!
+//! const CONNECTIONS_TAG: u32 = 1;
+//!
+//! CONNECTIONS.insert(&id, &connection, 0)?;
+//! UPDATES.notify_update(&ctx, CONNECTIONS_TAG, &id);
+//! ```
+//!
+//! Each map is identified by a tag chosen by the program. On the user space
+//! side, [`MapUpdates`] turns the notifications back into map names and keys.
+//!
+//! The eBPF `RingBuf` map has `notify_update` and `notify_changed` methods
+//! too. The items read from a [`RingBuf`](crate::maps::RingBuf) are then
+//! accumulated with [`MapUpdates::push`].
+//!
+//! [`PerfEventArray`]: crate::maps::PerfEventArray
+use std::{collections::HashMap, mem};
+
+use bytes::BytesMut;
+
+use crate::{
+    maps::{
+        perf::{Events, PerfBufferError, PerfEventArrayBuffer},
+        MapData,
+    },
+    Pod,
+};
+
+// the layout of `aya_bpf::maps::perf::MapUpdate`
+const HEADER_SIZE: usize = 2 * mem::size_of::<u32>();
+
+/// A map update read from a perf buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapUpdate {
+    /// The tag identifying the map.
+    pub tag: u32,
+    /// The key that changed, or `None` if any key may have changed.
+    pub key: Option<Vec<u8>>,
+}
+
+impl MapUpdate {
+    /// Parses a notification written by the eBPF side.
+    ///
+    /// Returns `None` if `data` isn't a valid notification.
+    pub fn parse(data: &[u8]) -> Option<MapUpdate> {
+        if data.len() < HEADER_SIZE {
+            return None;
+        }
+        let tag = u32::from_ne_bytes(data[..4].try_into().unwrap());
+        let key_size = u32::from_ne_bytes(data[4..8].try_into().unwrap()) as usize;
+        if key_size == 0 {
+            return Some(MapUpdate { tag, key: None });
+        }
+        let key = data.get(HEADER_SIZE..HEADER_SIZE + key_size)?;
+        Some(MapUpdate {
+            tag,
+            key: Some(key.to_vec()),
+        })
+    }
+
+    /// Returns the key as a `K`, or `None` if the key is unknown or doesn't
+    /// have the size of `K`.
+    pub fn key<K: Pod>(&self) -> Option<K> {
+        let key = self.key.as_ref()?;
+        if key.len() != mem::size_of::<K>() {
+            return None;
+        }
+        Some(unsafe { (key.as_ptr() as *const K).read_unaligned() })
+    }
+}
+
+/// The changes to a map accumulated by [`MapUpdates::drain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapChanges {
+    /// The entries at the given keys changed.
+    Keys(Vec<Vec<u8>>),
+    /// Any entry may have changed, so the whole map should be read again.
+    All,
+}
+
+/// Accumulates the map update notifications read from perf buffers.
+///
+/// Notifications are read from the buffers of all the CPUs with
+/// [`MapUpdates::read_events`], and the changes accumulated so far are
+/// taken, merged by map, with [`MapUpdates::drain`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::{
+///     notify::{MapChanges, MapUpdates},
+///     HashMap, PerfEventArray, PollSet,
+/// };
+/// use aya::util::online_cpus;
+/// use bytes::BytesMut;
+///
+/// let mut perf_array = PerfEventArray::try_from(bpf.take_map("UPDATES").unwrap())?;
+/// let mut buffers = Vec::new();
+/// let mut poll = PollSet::new()?;
+/// for cpu_id in online_cpus()? {
+///     let buf = perf_array.open(cpu_id, None)?;
+///     poll.add(&buf, buffers.len() as u64)?;
+///     buffers.push(buf);
+/// }
+///
+/// let mut updates = MapUpdates::new();
+/// updates.register(1, "CONNECTIONS");
+/// let mut out_bufs = (0..16).map(|_| BytesMut::with_capacity(64)).collect::<Vec<_>>();
+///
+/// let connections: HashMap<_, u32, u64> = HashMap::try_from(bpf.map("CONNECTIONS").unwrap())?;
+/// loop {
+///     for token in poll.poll(None)? {
+///         let buf = &mut buffers[token as usize];
+///         while buf.readable() {
+///             updates.read_events(buf, &mut out_bufs)?;
+///         }
+///     }
+///     for (tag, changes) in updates.drain() {
+///         if updates.map_name(tag) != Some("CONNECTIONS") {
+///             continue;
+///         }
+///         match changes {
+///             MapChanges::Keys(keys) => {
+///                 for key in keys {
+///                     let id = u32::from_ne_bytes(key[..].try_into()?);
+///                     println!("{} = {:?}", id, connections.get(&id, 0).ok());
+///                 }
+///             }
+///             MapChanges::All => println!("CONNECTIONS changed"),
+///         }
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct MapUpdates {
+    names: HashMap<u32, String>,
+    changes: HashMap<u32, MapChanges>,
+    malformed: usize,
+    lost: usize,
+}
+
+impl MapUpdates {
+    /// Creates an empty set of map updates.
+    pub fn new() -> MapUpdates {
+        MapUpdates::default()
+    }
+
+    /// Associates the map called `name` with `tag`.
+    pub fn register(&mut self, tag: u32, name: &str) -> &mut MapUpdates {
+        self.names.insert(tag, name.to_owned());
+        self
+    }
+
+    /// Returns the name of the map registered with `tag`.
+    pub fn map_name(&self, tag: u32) -> Option<&str> {
+        self.names.get(&tag).map(String::as_str)
+    }
+
+    /// Reads notifications from `buf` and accumulates them.
+    ///
+    /// This reads events like [`PerfEventArrayBuffer::read_events`], using
+    /// `out_bufs` as scratch space, and returns the number of events read and
+    /// lost. When events were lost, the lost notifications could have been for
+    /// any map, so every registered map is marked as [`MapChanges::All`].
+    ///
+    /// Events that aren't valid notifications are skipped and counted, see
+    /// [`MapUpdates::malformed`].
+    pub fn read_events<T: AsMut<MapData> + AsRef<MapData>>(
+        &mut self,
+        buf: &mut PerfEventArrayBuffer<T>,
+        out_bufs: &mut [BytesMut],
+    ) -> Result<Events, PerfBufferError> {
+        let events = buf.read_events(out_bufs)?;
+        for data in &out_bufs[..events.read] {
+            self.push(data);
+        }
+        self.add_lost(events.lost);
+        Ok(events)
+    }
+
+    /// Accumulates a single notification.
+    pub fn push(&mut self, data: &[u8]) {
+        match MapUpdate::parse(data) {
+            Some(update) => merge(&mut self.changes, update),
+            None => self.malformed += 1,
+        }
+    }
+
+    /// Takes the changes accumulated so far, merged by map.
+    ///
+    /// The keys of each map are deduplicated and returned in the order they
+    /// were first seen. Once a map has a notification without key, all its
+    /// keys are merged into [`MapChanges::All`].
+    pub fn drain(&mut self) -> HashMap<u32, MapChanges> {
+        mem::take(&mut self.changes)
+    }
+
+    /// Returns the number of events that weren't valid notifications.
+    pub fn malformed(&self) -> usize {
+        self.malformed
+    }
+
+    /// Returns the number of notifications lost because the perf buffers
+    /// were full.
+    pub fn lost(&self) -> usize {
+        self.lost
+    }
+
+    fn add_lost(&mut self, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.lost += count;
+        for tag in self.names.keys() {
+            self.changes.insert(*tag, MapChanges::All);
+        }
+    }
+}
+
+fn merge(changes: &mut HashMap<u32, MapChanges>, update: MapUpdate) {
+    let entry = changes
+        .entry(update.tag)
+        .or_insert_with(|| MapChanges::Keys(Vec::new()));
+    match (entry, update.key) {
+        (MapChanges::All, _) => {}
+        (entry, None) => *entry = MapChanges::All,
+        (MapChanges::Keys(keys), Some(key)) => {
+            if !keys.contains(&key) {
+                keys.push(key)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(tag: u32, key: Option<u32>) -> Vec<u8> {
+        let mut data = tag.to_ne_bytes().to_vec();
+        match key {
+            Some(key) => {
+                data.extend_from_slice(&4u32.to_ne_bytes());
+                data.extend_from_slice(&key.to_ne_bytes());
+            }
+            None => data.extend_from_slice(&0u32.to_ne_bytes()),
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_and_merge() {
+        let update = MapUpdate::parse(&record(1, Some(42))).unwrap();
+        assert_eq!(update.tag, 1);
+        assert_eq!(update.key::<u32>(), Some(42));
+        assert_eq!(update.key::<u64>(), None);
+        assert_eq!(
+            MapUpdate::parse(&record(2, None)),
+            Some(MapUpdate { tag: 2, key: None })
+        );
+        assert_eq!(MapUpdate::parse(&record(1, Some(42))[..6]), None);
+        assert_eq!(MapUpdate::parse(&record(1, Some(42))[..10]), None);
+
+        let mut changes = HashMap::new();
+        for (tag, key) in [(1, Some(1)), (1, Some(2)), (1, Some(1)), (2, Some(1))] {
+            merge(&mut changes, MapUpdate::parse(&record(tag, key)).unwrap());
+        }
+        merge(&mut changes, MapUpdate::parse(&record(2, None)).unwrap());
+        merge(&mut changes, MapUpdate::parse(&record(2, Some(3))).unwrap());
+        assert_eq!(
+            changes[&1],
+            MapChanges::Keys(vec![
+                1u32.to_ne_bytes().to_vec(),
+                2u32.to_ne_bytes().to_vec()
+            ])
+        );
+        assert_eq!(changes[&2], MapChanges::All);
+    }
+
+    #[test]
+    fn test_lost_marks_registered_maps() {
+        let mut updates = MapUpdates::new();
+        updates.register(1, "FIRST").register(2, "SECOND");
+        updates.push(&record(1, Some(42)));
+        updates.push(&record(3, Some(7)));
+        updates.push(&[0; 4]);
+        updates.add_lost(2);
+
+        let changes = updates.drain();
+        assert_eq!(changes[&1], MapChanges::All);
+        assert_eq!(changes[&2], MapChanges::All);
+        assert_eq!(
+            changes[&3],
+            MapChanges::Keys(vec![7u32.to_ne_bytes().to_vec()])
+        );
+        assert_eq!((updates.lost(), updates.malformed()), (2, 1));
+        assert!(updates.drain().is_empty());
+    }
+}
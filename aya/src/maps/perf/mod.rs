@@ -6,9 +6,11 @@
 mod async_perf_event_array;
 mod perf_buffer;
 mod perf_event_array;
+mod record;
 
 #[cfg(any(feature = "async"))]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
 pub use async_perf_event_array::*;
 pub use perf_buffer::*;
 pub use perf_event_array::*;
+pub use record::*;
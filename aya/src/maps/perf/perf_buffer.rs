@@ -1,9 +1,11 @@
 use std::{
     ffi::c_void,
-    io, mem,
+    hint, io, mem,
     os::unix::io::{AsRawFd, RawFd},
     ptr, slice,
     sync::atomic::{self, AtomicPtr, Ordering},
+    thread,
+    time::{Duration, Instant},
 };
 
 use bytes::BytesMut;
@@ -15,6 +17,7 @@ use crate::{
         perf_event_header, perf_event_mmap_page,
         perf_event_type::{PERF_RECORD_LOST, PERF_RECORD_SAMPLE},
     },
+    maps::MapError,
     sys::{perf_event_ioctl, perf_event_open_bpf},
     PERF_EVENT_IOC_DISABLE, PERF_EVENT_IOC_ENABLE,
 };
@@ -72,6 +75,59 @@ pub enum PerfBufferError {
     /// An IO error occurred.
     #[error(transparent)]
     IOError(#[from] io::Error),
+
+    /// The perf event array map isn't loaded.
+    #[error(transparent)]
+    MapError(#[from] MapError),
+}
+
+/// When the kernel wakes up the readers of a perf buffer, see
+/// [`PerfEventArray::open_with_wakeup`](crate::maps::PerfEventArray::open_with_wakeup).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wakeup {
+    /// Wakes readers up every `n` events.
+    ///
+    /// [`PerfEventArray::open`](crate::maps::PerfEventArray::open) wakes
+    /// readers up for every event.
+    Events(u32),
+    /// Wakes readers up once at least `n` bytes are waiting to be read.
+    ///
+    /// Notifying user space for every event is expensive at high event
+    /// rates. With a watermark, a reader sleeping until it's notified wakes
+    /// up once per batch of events instead.
+    Watermark(u32),
+}
+
+/// How [`PerfEventArrayBuffer::wait_readable`](crate::maps::perf::PerfEventArrayBuffer::wait_readable)
+/// and [`RingBuf::wait_readable`](crate::maps::RingBuf::wait_readable) wait
+/// for events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeupStrategy {
+    /// Sleeps in `poll()` until the kernel notifies that events were
+    /// submitted.
+    ///
+    /// Perf buffers are notified as configured by the [`Wakeup`] they were
+    /// opened with. Ring buffers are notified for every event, unless the
+    /// eBPF program submits events with `BPF_RB_NO_WAKEUP`, for example to
+    /// only wake user space up once enough data is available.
+    Notify,
+    /// Checks the buffer in a loop without ever sleeping.
+    ///
+    /// This has the lowest latency, at the cost of keeping a CPU busy.
+    BusyPoll,
+    /// Checks the buffer and sleeps between checks, starting with `min` and
+    /// doubling the sleep up to `max` while the buffer stays empty.
+    ///
+    /// This doesn't rely on kernel notifications, so perf buffers can be
+    /// opened with a large [`Wakeup::Watermark`], and eBPF programs can
+    /// submit all their ring buffer events with `BPF_RB_NO_WAKEUP`, to save
+    /// the cost of the notifications.
+    AdaptiveSleep {
+        /// The first and shortest sleep
+        min: Duration,
+        /// The longest sleep
+        max: Duration,
+    },
 }
 
 /// Return type of `read_events()`.
@@ -95,12 +151,13 @@ impl PerfBuffer {
         cpu_id: u32,
         page_size: usize,
         page_count: usize,
+        wakeup: Wakeup,
     ) -> Result<PerfBuffer, PerfBufferError> {
         if !page_count.is_power_of_two() {
             return Err(PerfBufferError::InvalidPageCount { page_count });
         }
 
-        let fd = perf_event_open_bpf(cpu_id as i32)
+        let fd = perf_event_open_bpf(cpu_id as i32, wakeup)
             .map_err(|(_, io_error)| PerfBufferError::OpenError { io_error })?
             as RawFd;
         let size = page_size * page_count;
@@ -140,6 +197,20 @@ impl PerfBuffer {
         head != tail
     }
 
+    pub(crate) fn advise_hugepages(&self) -> Result<(), PerfBufferError> {
+        let ret = unsafe {
+            libc::madvise(
+                self.buf.load(Ordering::SeqCst) as *mut c_void,
+                self.size + self.page_size,
+                libc::MADV_HUGEPAGE,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
     pub(crate) fn read_events(
         &mut self,
         buffers: &mut [BytesMut],
@@ -256,6 +327,94 @@ impl PerfBuffer {
 
         Ok(events)
     }
+
+    pub(crate) fn read_batch<F: FnMut(&[BytesMut])>(
+        &mut self,
+        out_bufs: &mut [BytesMut],
+        max_latency: Duration,
+        mut f: F,
+    ) -> Result<Events, PerfBufferError> {
+        let mut total = Events { read: 0, lost: 0 };
+        loop {
+            let events = self.read_events(out_bufs)?;
+            total.lost += events.lost;
+            if events.read == 0 {
+                return Ok(total);
+            }
+
+            let mut read = events.read;
+            let deadline = Instant::now() + max_latency;
+            while read < out_bufs.len() {
+                let remaining = match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => remaining,
+                    _ => break,
+                };
+                if !self.readable() && !self.wait(Some(remaining))? {
+                    break;
+                }
+                let events = self.read_events(&mut out_bufs[read..])?;
+                read += events.read;
+                total.lost += events.lost;
+            }
+
+            f(&out_bufs[..read]);
+            total.read += read;
+        }
+    }
+
+    pub(crate) fn wait_readable(
+        &self,
+        strategy: WakeupStrategy,
+        timeout: Option<Duration>,
+    ) -> Result<bool, PerfBufferError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut sleep = Duration::ZERO;
+        loop {
+            if self.readable() {
+                return Ok(true);
+            }
+            let remaining = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => Some(remaining),
+                    _ => return Ok(false),
+                },
+                None => None,
+            };
+            match strategy {
+                WakeupStrategy::Notify => {
+                    self.wait(remaining)?;
+                }
+                WakeupStrategy::BusyPoll => hint::spin_loop(),
+                WakeupStrategy::AdaptiveSleep { min, max } => {
+                    sleep = next_sleep(sleep, min, max);
+                    thread::sleep(remaining.map_or(sleep, |remaining| sleep.min(remaining)));
+                }
+            }
+        }
+    }
+
+    // Waits for the kernel to notify that the buffer is readable, returns
+    // false on timeout.
+    pub(crate) fn wait(&self, timeout: Option<Duration>) -> Result<bool, PerfBufferError> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // round up so that short timeouts don't turn into busy loops
+        let timeout_ms = timeout.map_or(-1, |timeout| {
+            timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+        });
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ret < 0 {
+            let io_error = io::Error::last_os_error();
+            if io_error.kind() == io::ErrorKind::Interrupted {
+                return Ok(true);
+            }
+            return Err(io_error.into());
+        }
+        Ok(ret > 0)
+    }
 }
 
 impl AsRawFd for PerfBuffer {
@@ -277,6 +436,14 @@ impl Drop for PerfBuffer {
     }
 }
 
+pub(crate) fn next_sleep(sleep: Duration, min: Duration, max: Duration) -> Duration {
+    if sleep.is_zero() {
+        min
+    } else {
+        (sleep * 2).min(max).max(min)
+    }
+}
+
 #[cfg_attr(test, allow(unused_variables))]
 unsafe fn mmap(
     addr: *mut c_void,
@@ -337,15 +504,15 @@ mod tests {
     #[test]
     fn test_invalid_page_count() {
         assert!(matches!(
-            PerfBuffer::open(1, PAGE_SIZE, 0),
+            PerfBuffer::open(1, PAGE_SIZE, 0, Wakeup::Events(1)),
             Err(PerfBufferError::InvalidPageCount { .. })
         ));
         assert!(matches!(
-            PerfBuffer::open(1, PAGE_SIZE, 3),
+            PerfBuffer::open(1, PAGE_SIZE, 3, Wakeup::Events(1)),
             Err(PerfBufferError::InvalidPageCount { .. })
         ));
         assert!(matches!(
-            PerfBuffer::open(1, PAGE_SIZE, 5),
+            PerfBuffer::open(1, PAGE_SIZE, 5, Wakeup::Events(1)),
             Err(PerfBufferError::InvalidPageCount { .. })
         ));
     }
@@ -358,7 +525,7 @@ mod tests {
         };
         fake_mmap(&mut mmapped_buf);
 
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
         assert!(matches!(
             buf.read_events(&mut []),
             Err(PerfBufferError::NoBuffers)
@@ -373,7 +540,7 @@ mod tests {
         };
         fake_mmap(&mut mmapped_buf);
 
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
         let out_buf = BytesMut::with_capacity(4);
         assert_eq!(
             buf.read_events(&mut [out_buf]).unwrap(),
@@ -400,7 +567,7 @@ mod tests {
         };
         write(&mut mmapped_buf, 0, evt);
 
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
         let out_buf = BytesMut::with_capacity(0);
         let events = buf.read_events(&mut [out_buf]).unwrap();
         assert_eq!(events.lost, 0xCAFEBABE);
@@ -453,7 +620,7 @@ mod tests {
             data: [0; PAGE_SIZE * 2],
         };
         fake_mmap(&mut mmapped_buf);
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
 
         write_sample(&mut mmapped_buf, 0, 0xCAFEBABEu32);
 
@@ -471,7 +638,7 @@ mod tests {
             data: [0; PAGE_SIZE * 2],
         };
         fake_mmap(&mut mmapped_buf);
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
 
         let next = write_sample(&mut mmapped_buf, 0, 0xCAFEBABEu32);
         write_sample(&mut mmapped_buf, next, 0xBADCAFEu32);
@@ -494,7 +661,7 @@ mod tests {
             data: [0; PAGE_SIZE * 2],
         };
         fake_mmap(&mut mmapped_buf);
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
 
         let next = write_sample(&mut mmapped_buf, 0, 0xCAFEBABEu32);
         write_sample(&mut mmapped_buf, next, 0xBADCAFEu32);
@@ -516,7 +683,7 @@ mod tests {
             data: [0; PAGE_SIZE * 2],
         };
         fake_mmap(&mut mmapped_buf);
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
 
         let offset = PAGE_SIZE - mem::size_of::<PerfSample<u32>>();
         mmapped_buf.mmap_page.data_tail = offset as u64;
@@ -536,7 +703,7 @@ mod tests {
             data: [0; PAGE_SIZE * 2],
         };
         fake_mmap(&mut mmapped_buf);
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
 
         let header = perf_event_header {
             type_: PERF_RECORD_SAMPLE as u32,
@@ -565,7 +732,7 @@ mod tests {
             data: [0; PAGE_SIZE * 2],
         };
         fake_mmap(&mut mmapped_buf);
-        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
 
         let sample = PerfSample {
             s_hdr: Sample {
@@ -590,4 +757,49 @@ mod tests {
         assert_eq!(events, Events { lost: 0, read: 1 });
         assert_eq!(u64_from_buf(&out_bufs[0]), 0xBAADCAFECAFEBABE);
     }
+
+    #[test]
+    fn test_next_sleep() {
+        let min = Duration::from_micros(10);
+        let max = Duration::from_micros(50);
+        let mut sleep = Duration::ZERO;
+        let mut sleeps = Vec::new();
+        for _ in 0..5 {
+            sleep = next_sleep(sleep, min, max);
+            sleeps.push(sleep.as_micros());
+        }
+        assert_eq!(sleeps, vec![10, 20, 40, 50, 50]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_read_batch() {
+        let mut mmapped_buf = MMappedBuf {
+            data: [0; PAGE_SIZE * 2],
+        };
+        fake_mmap(&mut mmapped_buf);
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1, Wakeup::Events(1)).unwrap();
+
+        let mut next = 0;
+        for value in [1u32, 2, 3] {
+            next = write_sample(&mut mmapped_buf, next, value);
+        }
+
+        let mut out_bufs = (0..2)
+            .map(|_| BytesMut::with_capacity(4))
+            .collect::<Vec<_>>();
+        let mut batches = Vec::new();
+        let events = buf
+            .read_batch(&mut out_bufs, Duration::ZERO, |batch| {
+                batches.push(batch.iter().map(|b| u32_from_buf(b)).collect::<Vec<_>>())
+            })
+            .unwrap();
+        assert_eq!(events, Events { read: 3, lost: 0 });
+        assert_eq!(batches, vec![vec![1, 2], vec![3]]);
+
+        let events = buf
+            .read_batch(&mut out_bufs, Duration::ZERO, |_| panic!())
+            .unwrap();
+        assert_eq!(events, Events { read: 0, lost: 0 });
+    }
 }
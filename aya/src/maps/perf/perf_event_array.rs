@@ -2,21 +2,26 @@
 //!
 //! [`perf`]: https://perf.wiki.kernel.org/index.php/Main_Page.
 use std::{
+    collections::HashMap,
     convert::AsMut,
+    io,
     ops::Deref,
     os::unix::io::{AsRawFd, RawFd},
     sync::Arc,
+    time::Duration,
 };
 
 use bytes::BytesMut;
 
 use crate::{
+    generated::PERF_FLAG_FD_CLOEXEC,
     maps::{
-        perf::{Events, PerfBuffer, PerfBufferError},
+        perf::{Events, PerfBuffer, PerfBufferError, Wakeup, WakeupStrategy},
         MapData, MapError,
     },
-    sys::bpf_map_update_elem,
-    util::page_size,
+    programs::perf_event::PerfEventAttr,
+    sys::{bpf_map_delete_elem, bpf_map_update_elem, perf_event_open_attr},
+    util::{online_cpus, page_size},
 };
 
 /// A ring buffer that can receive events from eBPF programs.
@@ -53,6 +58,97 @@ impl<T: AsMut<MapData> + AsRef<MapData>> PerfEventArrayBuffer<T> {
     pub fn read_events(&mut self, out_bufs: &mut [BytesMut]) -> Result<Events, PerfBufferError> {
         self.buf.read_events(out_bufs)
     }
+
+    /// Reads the pending events in batches and passes each batch to `f`.
+    ///
+    /// Each batch holds up to `out_bufs.len()` events, read into `out_bufs`
+    /// like with [`read_events`](PerfEventArrayBuffer::read_events). This
+    /// amortizes the per event overhead of the consumer, for example when
+    /// sending the events to a channel. When fewer events are available than
+    /// `out_bufs` can hold, the buffer is polled for up to `max_latency` for
+    /// more events to arrive, so a batch is handed off at the latest
+    /// `max_latency` after its first event was read. Pass [`Duration::ZERO`]
+    /// to only read the events already in the buffer.
+    ///
+    /// Returns the number of events read and the number of events lost. No
+    /// events are read if the buffer was empty.
+    ///
+    /// # Errors
+    ///
+    /// [`PerfBufferError::NoBuffers`] is returned when `out_bufs` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::maps::PerfEventArray;
+    /// use bytes::BytesMut;
+    /// use std::time::Duration;
+    ///
+    /// let mut perf_array = PerfEventArray::try_from(bpf.map_mut("EVENTS").unwrap())?;
+    /// let mut buf = perf_array.open(0, None)?;
+    /// let mut out_bufs = (0..64)
+    ///     .map(|_| BytesMut::with_capacity(1024))
+    ///     .collect::<Vec<_>>();
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// buf.read_batch(&mut out_bufs, Duration::from_millis(1), |events| {
+    ///     let batch: Vec<Vec<u8>> = events.iter().map(|event| event.to_vec()).collect();
+    ///     tx.send(batch).unwrap();
+    /// })?;
+    /// # drop(rx);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn read_batch<F: FnMut(&[BytesMut])>(
+        &mut self,
+        out_bufs: &mut [BytesMut],
+        max_latency: Duration,
+        f: F,
+    ) -> Result<Events, PerfBufferError> {
+        self.buf.read_batch(out_bufs, max_latency, f)
+    }
+
+    /// Waits until the buffer contains events, using `strategy`.
+    ///
+    /// Returns `false` if `timeout` expired first. A `timeout` of `None`
+    /// waits forever.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::maps::{perf::WakeupStrategy, PerfEventArray};
+    /// use bytes::BytesMut;
+    ///
+    /// let mut perf_array = PerfEventArray::try_from(bpf.map_mut("EVENTS").unwrap())?;
+    /// let mut buf = perf_array.open(0, None)?;
+    /// let mut out_bufs = [BytesMut::with_capacity(1024)];
+    /// loop {
+    ///     buf.wait_readable(WakeupStrategy::BusyPoll, None)?;
+    ///     buf.read_events(&mut out_bufs)?;
+    ///     // process out_bufs
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn wait_readable(
+        &self,
+        strategy: WakeupStrategy,
+        timeout: Option<Duration>,
+    ) -> Result<bool, PerfBufferError> {
+        self.buf.wait_readable(strategy, timeout)
+    }
+
+    /// Asks the kernel to back the buffer with huge pages, with
+    /// `madvise(MADV_HUGEPAGE)`.
+    ///
+    /// This can reduce TLB pressure when reading multi-megabyte buffers, see
+    /// the `page_count` argument of [`PerfEventArray::open`], at a high rate.
+    /// The memory of the buffer is allocated by the kernel when the buffer is
+    /// opened, so it can't be mapped with `MAP_HUGETLB`, and whether the hint
+    /// has any effect depends on the kernel. Kernels that don't support the
+    /// hint for perf buffers return `EINVAL`, which can be ignored.
+    pub fn advise_hugepages(&self) -> Result<(), PerfBufferError> {
+        self.buf.advise_hugepages()
+    }
 }
 
 impl<T: AsMut<MapData> + AsRef<MapData>> AsRawFd for PerfEventArrayBuffer<T> {
@@ -144,6 +240,40 @@ impl<T: AsMut<MapData> + AsRef<MapData>> AsRawFd for PerfEventArrayBuffer<T> {
 /// amounts of data, in order not to lose events you might want to process each
 /// [`PerfEventArrayBuffer`] on a different thread.
 ///
+/// On NUMA systems the kernel allocates each buffer on the node of its CPU.
+/// Reader threads can be pinned to the same node with
+/// [`cpu_numa_node`](crate::util::cpu_numa_node),
+/// [`numa_node_cpus`](crate::util::numa_node_cpus) and
+/// [`set_cpu_affinity`](crate::util::set_cpu_affinity):
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::PerfEventArray;
+/// use aya::util::{cpu_numa_node, numa_node_cpus, online_cpus, set_cpu_affinity};
+///
+/// let mut perf_array = PerfEventArray::try_from(bpf.take_map("EVENTS").unwrap())?;
+/// for cpu_id in online_cpus()? {
+///     let buf = perf_array.open(cpu_id, None)?;
+///     let node = cpu_numa_node(cpu_id)?;
+///     std::thread::spawn(move || {
+///         if let Some(node) = node {
+///             set_cpu_affinity(&numa_node_cpus(node)?)?;
+///         }
+///         // read from buf
+/// #       drop(buf);
+///         Ok::<_, std::io::Error>(())
+///     });
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// # CPU hotplug
+///
+/// Buffers are opened for a fixed set of CPUs. If CPUs can be brought online
+/// or taken offline while the program is running, keep the buffers in a map
+/// keyed by CPU id and periodically call [`PerfEventArray::reconcile`] to open
+/// buffers for new CPUs and close the buffers of CPUs that went away.
+///
 /// # Async
 ///
 /// If you are using [tokio] or [async-std], you should use `AsyncPerfEventArray` which
@@ -179,13 +309,38 @@ impl<T: AsMut<MapData> + AsRef<MapData>> PerfEventArray<T> {
         &mut self,
         index: u32,
         page_count: Option<usize>,
+    ) -> Result<PerfEventArrayBuffer<T>, PerfBufferError> {
+        self.open_with_wakeup(index, page_count, Wakeup::Events(1))
+    }
+
+    /// Opens the perf buffer at the given index, waking readers up as
+    /// configured by `wakeup`.
+    ///
+    /// [`PerfEventArray::open`] wakes readers up for every event, which is
+    /// expensive at high event rates. See [`Wakeup`] for the alternatives.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::maps::{perf::Wakeup, PerfEventArray};
+    ///
+    /// let mut perf_array = PerfEventArray::try_from(bpf.map_mut("EVENTS").unwrap())?;
+    /// // only wake up once 64KiB of events are waiting to be read
+    /// let buf = perf_array.open_with_wakeup(0, Some(64), Wakeup::Watermark(64 * 1024))?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn open_with_wakeup(
+        &mut self,
+        index: u32,
+        page_count: Option<usize>,
+        wakeup: Wakeup,
     ) -> Result<PerfEventArrayBuffer<T>, PerfBufferError> {
         // FIXME: keep track of open buffers
 
-        // this cannot fail as new() checks that the fd is open
         let map_data: &MapData = self.map.deref().as_ref();
-        let map_fd = map_data.fd_or_err().unwrap();
-        let buf = PerfBuffer::open(index, self.page_size, page_count.unwrap_or(2))?;
+        let map_fd = map_data.fd_or_err()?;
+        let buf = PerfBuffer::open(index, self.page_size, page_count.unwrap_or(2), wakeup)?;
         bpf_map_update_elem(map_fd, Some(&index), &buf.as_raw_fd(), 0)
             .map_err(|(_, io_error)| io_error)?;
 
@@ -194,4 +349,183 @@ impl<T: AsMut<MapData> + AsRef<MapData>> PerfEventArray<T> {
             _map: self.map.clone(),
         })
     }
+
+    /// Reconciles `buffers` with the set of CPUs currently online.
+    ///
+    /// `buffers` maps CPU ids to the buffers opened for them. A buffer is
+    /// opened for every online CPU that isn't in `buffers`, and the buffers of
+    /// CPUs that are no longer online are removed from `buffers` and returned
+    /// in [`CpuChanges::closed`] so that any remaining events can be drained
+    /// before they're dropped.
+    ///
+    /// If an error is returned, the buffers of the CPUs that are no longer
+    /// online are left in `buffers`, so that no events are lost. Calling this
+    /// again returns them in [`CpuChanges::closed`].
+    pub fn reconcile(
+        &mut self,
+        buffers: &mut HashMap<u32, PerfEventArrayBuffer<T>>,
+        page_count: Option<usize>,
+    ) -> Result<CpuChanges<T>, PerfBufferError> {
+        let online = online_cpus()?;
+        let (opened, closed) = diff_cpus(buffers.keys().copied(), &online);
+
+        let map_data: &MapData = self.map.deref().as_ref();
+        let map_fd = map_data.fd_or_err()?;
+        let mut changes = CpuChanges {
+            opened: Vec::new(),
+            closed: Vec::new(),
+        };
+        for cpu_id in closed {
+            match bpf_map_delete_elem(map_fd, &cpu_id) {
+                Ok(_) => {}
+                Err((_, io_error)) if io_error.kind() == io::ErrorKind::NotFound => {}
+                Err((_, io_error)) => {
+                    buffers.extend(changes.closed);
+                    return Err(io_error.into());
+                }
+            }
+            if let Some(buf) = buffers.remove(&cpu_id) {
+                changes.closed.push((cpu_id, buf));
+            }
+        }
+        for cpu_id in opened {
+            match self.open(cpu_id, page_count) {
+                Ok(buf) => {
+                    buffers.insert(cpu_id, buf);
+                    changes.opened.push(cpu_id);
+                }
+                Err(e) => {
+                    buffers.extend(changes.closed);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Opens a counter for the event described by `attr` on the given CPU
+    /// and stores it at the index `cpu`.
+    ///
+    /// eBPF programs can then read the counter of the CPU they run on with
+    /// `bpf_perf_event_read_value()`. The counter counts the events of all
+    /// the processes running on the CPU. It stays in the map until the index
+    /// is overwritten, even after the returned [`PerfCounter`] is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::{
+    ///     maps::PerfEventArray,
+    ///     programs::perf_event::{perf_hw_id::PERF_COUNT_HW_INSTRUCTIONS, PerfEventAttrBuilder, PerfTypeId},
+    ///     util::online_cpus,
+    /// };
+    ///
+    /// let attr = PerfEventAttrBuilder::new(PerfTypeId::Hardware, PERF_COUNT_HW_INSTRUCTIONS as u64)
+    ///     .exclude_kernel(true)
+    ///     .build()?;
+    /// let mut counters = PerfEventArray::try_from(bpf.map_mut("INSTRUCTIONS").unwrap())?;
+    /// let mut handles = Vec::new();
+    /// for cpu in online_cpus()? {
+    ///     handles.push(counters.set_counter(cpu, &attr)?);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_counter(&mut self, cpu: u32, attr: &PerfEventAttr) -> Result<PerfCounter, MapError> {
+        let map_data: &MapData = self.map.deref().as_ref();
+        let map_fd = map_data.fd_or_err()?;
+        let fd = perf_event_open_attr(attr.attr, -1, cpu as i32, PERF_FLAG_FD_CLOEXEC).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "perf_event_open".to_owned(),
+                io_error,
+            },
+        )? as RawFd;
+        let counter = PerfCounter { fd };
+        bpf_map_update_elem(map_fd, Some(&cpu), &fd, 0).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(counter)
+    }
+}
+
+/// A perf event counter, see [`PerfEventArray::set_counter`].
+///
+/// The file descriptor is closed when the counter is dropped.
+#[derive(Debug)]
+pub struct PerfCounter {
+    fd: RawFd,
+}
+
+impl PerfCounter {
+    /// Reads the current value of the counter.
+    pub fn read(&self) -> Result<u64, io::Error> {
+        let mut value = 0u64;
+        let ret = unsafe {
+            libc::read(
+                self.fd,
+                &mut value as *mut _ as *mut _,
+                std::mem::size_of::<u64>(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(value)
+    }
+}
+
+impl AsRawFd for PerfCounter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for PerfCounter {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// The result of [`PerfEventArray::reconcile`].
+pub struct CpuChanges<T> {
+    /// The CPUs for which a new buffer was opened.
+    pub opened: Vec<u32>,
+    /// The CPUs that went offline, together with their buffers.
+    pub closed: Vec<(u32, PerfEventArrayBuffer<T>)>,
+}
+
+// Returns the CPUs that need a buffer opened and the CPUs whose buffer needs to be closed.
+fn diff_cpus(open: impl Iterator<Item = u32>, online: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut open = open.collect::<Vec<_>>();
+    open.sort_unstable();
+    let added = online
+        .iter()
+        .copied()
+        .filter(|cpu| open.binary_search(cpu).is_err())
+        .collect();
+    let removed = open
+        .into_iter()
+        .filter(|cpu| !online.contains(cpu))
+        .collect();
+    (added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_cpus() {
+        assert_eq!(diff_cpus([].into_iter(), &[0, 1]), (vec![0, 1], vec![]));
+        assert_eq!(
+            diff_cpus([0, 1, 2].into_iter(), &[0, 2, 3]),
+            (vec![3], vec![1])
+        );
+        assert_eq!(diff_cpus([1, 0].into_iter(), &[0, 1]), (vec![], vec![]));
+        assert_eq!(diff_cpus([0, 1].into_iter(), &[]), (vec![], vec![0, 1]));
+    }
 }
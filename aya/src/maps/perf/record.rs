@@ -0,0 +1,542 @@
+//! Recording and replaying of perf and ring buffer events.
+//!
+//! [`PerfEventRecorder`] writes the events read from perf buffers or from a
+//! [`RingBuf`](crate::maps::RingBuf) to a file, and [`PerfEventReplayer`]
+//! reads them back. The replayer exposes the same
+//! [`read_events`](PerfEventReplayer::read_events) interface as
+//! [`PerfEventArrayBuffer`](crate::maps::perf::PerfEventArrayBuffer) and the
+//! same [`read_batch`](PerfEventReplayer::read_batch) interface as
+//! [`RingBuf`](crate::maps::RingBuf), so the code that decodes events can be
+//! run offline against captured traffic.
+//!
+//! # File format
+//!
+//! A recording starts with the 8 byte magic `AYAPERF\0` followed by a `u32`
+//! version. Then each record is made of a one byte kind, the `u32` CPU id, a
+//! `u64` timestamp in nanoseconds since the UNIX epoch and a `u32` length.
+//! Sample and ring buffer records are followed by `length` bytes of data,
+//! while for lost records the length is the number of events lost. Ring
+//! buffers are shared by all the CPUs, so the CPU id of ring buffer records is
+//! always 0. All the integers are little endian.
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{Bytes, BytesMut};
+
+use crate::maps::perf::{Events, PerfBufferError};
+
+const MAGIC: &[u8; 8] = b"AYAPERF\0";
+const VERSION: u32 = 1;
+
+const RECORD_SAMPLE: u8 = 0;
+const RECORD_LOST: u8 = 1;
+const RECORD_RING_BUF: u8 = 2;
+
+// The kernel limits ring buffer records to a quarter of 4GiB, perf samples
+// are much smaller. Longer records can only come from corrupt recordings.
+const MAX_RECORD_LEN: usize = u32::MAX as usize / 4;
+
+/// A record read by [`PerfEventReplayer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PerfRecord {
+    /// An event sent by an eBPF program.
+    Sample {
+        /// The CPU the event was read from.
+        cpu_id: u32,
+        /// The time the event was read at.
+        timestamp: SystemTime,
+        /// The event data.
+        data: Bytes,
+    },
+    /// Events lost because the perf buffer was full.
+    Lost {
+        /// The CPU the events were lost on.
+        cpu_id: u32,
+        /// The time the loss was noticed at.
+        timestamp: SystemTime,
+        /// The number of events lost.
+        count: usize,
+    },
+    /// An event read from a [`RingBuf`](crate::maps::RingBuf).
+    RingBuf {
+        /// The time the event was read at.
+        timestamp: SystemTime,
+        /// The event data.
+        data: Bytes,
+    },
+}
+
+/// Writes perf and ring buffer events to a recording.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use std::fs::File;
+/// use aya::maps::perf::{PerfEventArray, PerfEventRecorder};
+/// use bytes::BytesMut;
+///
+/// let mut perf_array = PerfEventArray::try_from(bpf.map_mut("EVENTS").unwrap())?;
+/// let mut buf = perf_array.open(0, None)?;
+/// let mut recorder = PerfEventRecorder::new(File::create("events.rec")?)?;
+/// let mut out_bufs = [BytesMut::with_capacity(1024)];
+///
+/// loop {
+///     let events = buf.read_events(&mut out_bufs)?;
+///     recorder.record(0, &events, &out_bufs)?;
+///     // process the events
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// Ring buffer events can be recorded from the
+/// [`RingBuf::read_batch`](crate::maps::RingBuf::read_batch) callback:
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use std::{fs::File, time::Duration};
+/// use aya::maps::{perf::PerfEventRecorder, RingBuf};
+///
+/// let mut ring_buf = RingBuf::try_from(bpf.map_mut("EVENTS").unwrap())?;
+/// let mut recorder = PerfEventRecorder::new(File::create("events.rec")?)?;
+///
+/// loop {
+///     let mut result = Ok(());
+///     ring_buf.read_batch(64, Duration::from_millis(1), |events| {
+///         result = recorder.record_ring_buf(events);
+///         // process the events
+///     })?;
+///     result?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct PerfEventRecorder<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PerfEventRecorder<W> {
+    /// Creates a new recorder writing to `writer`.
+    pub fn new(mut writer: W) -> Result<PerfEventRecorder<W>, io::Error> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(PerfEventRecorder { writer })
+    }
+
+    /// Records the result of a call to `read_events()`.
+    ///
+    /// `out_bufs` is the slice of buffers passed to `read_events()`, of which
+    /// the first `events.read` are recorded. All the events get the current
+    /// time as timestamp.
+    pub fn record(
+        &mut self,
+        cpu_id: u32,
+        events: &Events,
+        out_bufs: &[BytesMut],
+    ) -> Result<(), io::Error> {
+        let timestamp = SystemTime::now();
+        if events.lost > 0 {
+            self.write_header(RECORD_LOST, cpu_id, timestamp, events.lost)?;
+        }
+        for buf in out_bufs.iter().take(events.read) {
+            self.record_sample(cpu_id, timestamp, buf)?;
+        }
+        Ok(())
+    }
+
+    /// Records a single event.
+    pub fn record_sample(
+        &mut self,
+        cpu_id: u32,
+        timestamp: SystemTime,
+        data: &[u8],
+    ) -> Result<(), io::Error> {
+        self.write_header(RECORD_SAMPLE, cpu_id, timestamp, data.len())?;
+        self.writer.write_all(data)
+    }
+
+    /// Records a batch of events read from a [`RingBuf`](crate::maps::RingBuf).
+    ///
+    /// All the events get the current time as timestamp.
+    pub fn record_ring_buf(&mut self, events: &[&[u8]]) -> Result<(), io::Error> {
+        let timestamp = SystemTime::now();
+        for data in events {
+            self.record_ring_buf_event(timestamp, data)?;
+        }
+        Ok(())
+    }
+
+    /// Records a single event read from a [`RingBuf`](crate::maps::RingBuf).
+    pub fn record_ring_buf_event(
+        &mut self,
+        timestamp: SystemTime,
+        data: &[u8],
+    ) -> Result<(), io::Error> {
+        self.write_header(RECORD_RING_BUF, 0, timestamp, data.len())?;
+        self.writer.write_all(data)
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(
+        &mut self,
+        kind: u8,
+        cpu_id: u32,
+        timestamp: SystemTime,
+        len: usize,
+    ) -> Result<(), io::Error> {
+        if kind != RECORD_LOST && len > MAX_RECORD_LEN {
+            return Err(invalid_data("record too large"));
+        }
+        let len = u32::try_from(len).map_err(|_| invalid_data("record too large"))?;
+        let nanos = timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+
+        let mut header = [0u8; 17];
+        header[0] = kind;
+        header[1..5].copy_from_slice(&cpu_id.to_le_bytes());
+        header[5..13].copy_from_slice(&nanos.to_le_bytes());
+        header[13..17].copy_from_slice(&len.to_le_bytes());
+        self.writer.write_all(&header)
+    }
+}
+
+/// Reads events from a recording made with [`PerfEventRecorder`].
+///
+/// The replayer is an iterator over the records. Alternatively
+/// [`read_events`](PerfEventReplayer::read_events) can be used in place of
+/// [`PerfEventArrayBuffer::read_events`](crate::maps::perf::PerfEventArrayBuffer::read_events)
+/// to feed the perf events of all the CPUs to the same consumer, and
+/// [`read_batch`](PerfEventReplayer::read_batch) in place of
+/// [`RingBuf::read_batch`](crate::maps::RingBuf::read_batch) to feed the ring
+/// buffer events.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::{fs::File, io::BufReader};
+/// use aya::maps::perf::PerfEventReplayer;
+/// use bytes::BytesMut;
+///
+/// let file = BufReader::new(File::open("events.rec")?);
+/// let mut replayer = PerfEventReplayer::new(file)?;
+/// let mut out_bufs = (0..10)
+///     .map(|_| BytesMut::with_capacity(1024))
+///     .collect::<Vec<_>>();
+///
+/// loop {
+///     let events = replayer.read_events(&mut out_bufs)?;
+///     if events.read == 0 && events.lost == 0 {
+///         break;
+///     }
+///     // process the events
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct PerfEventReplayer<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> PerfEventReplayer<R> {
+    /// Creates a new replayer reading from `reader`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if `reader`
+    /// doesn't contain a recording.
+    pub fn new(mut reader: R) -> Result<PerfEventReplayer<R>, io::Error> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid_data("not a perf event recording"));
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid_data("unsupported perf event recording version"));
+        }
+        Ok(PerfEventReplayer { reader })
+    }
+
+    /// Reads the next record, returning `None` at the end of the recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if the record
+    /// is corrupt, or [`io::ErrorKind::UnexpectedEof`] if the recording is
+    /// truncated.
+    pub fn next_record(&mut self) -> Result<Option<PerfRecord>, io::Error> {
+        let mut header = [0u8; 17];
+        match self.reader.read(&mut header[..1])? {
+            0 => return Ok(None),
+            _ => self.reader.read_exact(&mut header[1..])?,
+        }
+        let cpu_id = u32::from_le_bytes(header[1..5].try_into().unwrap());
+        let nanos = u64::from_le_bytes(header[5..13].try_into().unwrap());
+        let timestamp = UNIX_EPOCH + Duration::from_nanos(nanos);
+        let len = u32::from_le_bytes(header[13..17].try_into().unwrap()) as usize;
+
+        match header[0] {
+            RECORD_SAMPLE => Ok(Some(PerfRecord::Sample {
+                cpu_id,
+                timestamp,
+                data: self.read_data(len)?,
+            })),
+            RECORD_LOST => Ok(Some(PerfRecord::Lost {
+                cpu_id,
+                timestamp,
+                count: len,
+            })),
+            RECORD_RING_BUF => Ok(Some(PerfRecord::RingBuf {
+                timestamp,
+                data: self.read_data(len)?,
+            })),
+            _ => Err(invalid_data("invalid perf event record")),
+        }
+    }
+
+    fn read_data(&mut self, len: usize) -> Result<Bytes, io::Error> {
+        if len > MAX_RECORD_LEN {
+            return Err(invalid_data("invalid perf event record length"));
+        }
+        // the length comes from the recording, so let the buffer grow with
+        // the data actually read instead of allocating it upfront
+        let mut data = Vec::new();
+        (&mut self.reader).take(len as u64).read_to_end(&mut data)?;
+        if data.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(data.into())
+    }
+
+    /// Reads events from the recording.
+    ///
+    /// This behaves like
+    /// [`PerfEventArrayBuffer::read_events`](crate::maps::perf::PerfEventArrayBuffer::read_events):
+    /// events are read into the provided buffers, filling each buffer in order
+    /// and stopping when the recording ends or all the buffers have been
+    /// filled. The events of all the CPUs are returned in the order they were
+    /// recorded, ring buffer events are skipped. At the end of the recording,
+    /// no events are read or lost.
+    ///
+    /// # Errors
+    ///
+    /// [`PerfBufferError::NoBuffers`] is returned when `out_bufs` is empty.
+    pub fn read_events(&mut self, out_bufs: &mut [BytesMut]) -> Result<Events, PerfBufferError> {
+        if out_bufs.is_empty() {
+            return Err(PerfBufferError::NoBuffers);
+        }
+
+        let mut events = Events { read: 0, lost: 0 };
+        while events.read < out_bufs.len() {
+            match self.next_record()? {
+                Some(PerfRecord::Sample { data, .. }) => {
+                    let buf = &mut out_bufs[events.read];
+                    buf.clear();
+                    buf.extend_from_slice(&data);
+                    events.read += 1;
+                }
+                Some(PerfRecord::Lost { count, .. }) => events.lost += count,
+                Some(PerfRecord::RingBuf { .. }) => {}
+                None => break,
+            }
+        }
+        Ok(events)
+    }
+
+    /// Reads ring buffer events from the recording in batches of up to
+    /// `max_batch` events and passes each batch to `f`.
+    ///
+    /// This behaves like [`RingBuf::read_batch`](crate::maps::RingBuf::read_batch)
+    /// without waiting for events: all the ring buffer events left in the
+    /// recording are read, while perf events are skipped. Returns the number
+    /// of events read, which is 0 at the end of the recording.
+    pub fn read_batch<F: FnMut(&[&[u8]])>(
+        &mut self,
+        max_batch: usize,
+        mut f: F,
+    ) -> Result<usize, io::Error> {
+        let mut batch = Vec::new();
+        let mut total = 0;
+        loop {
+            let done = match self.next_record()? {
+                Some(PerfRecord::RingBuf { data, .. }) => {
+                    batch.push(data);
+                    false
+                }
+                Some(_) => false,
+                None => true,
+            };
+            if !batch.is_empty() && (done || batch.len() >= max_batch) {
+                let events: Vec<&[u8]> = batch.iter().map(|data| &data[..]).collect();
+                f(&events);
+                total += batch.len();
+                batch.clear();
+            }
+            if done {
+                return Ok(total);
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for PerfEventReplayer<R> {
+    type Item = Result<PerfRecord, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_record_replay() {
+        let mut recorder = PerfEventRecorder::new(Vec::new()).unwrap();
+        let bufs = [
+            BytesMut::from(&b"first"[..]),
+            BytesMut::from(&b"second"[..]),
+            BytesMut::from(&b"unused"[..]),
+        ];
+        recorder
+            .record(1, &Events { read: 2, lost: 3 }, &bufs)
+            .unwrap();
+        let timestamp = UNIX_EPOCH + Duration::from_nanos(42);
+        recorder.record_sample(0, timestamp, b"third").unwrap();
+        let recording = recorder.into_inner();
+
+        let records = PerfEventReplayer::new(Cursor::new(&recording))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 4);
+        assert!(matches!(
+            records[0],
+            PerfRecord::Lost {
+                cpu_id: 1,
+                count: 3,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &records[1],
+            PerfRecord::Sample { cpu_id: 1, data, .. } if data == &b"first"[..]
+        ));
+        assert_eq!(
+            records[3],
+            PerfRecord::Sample {
+                cpu_id: 0,
+                timestamp,
+                data: Bytes::from_static(b"third"),
+            }
+        );
+
+        let mut replayer = PerfEventReplayer::new(Cursor::new(&recording)).unwrap();
+        let mut out_bufs = [BytesMut::new(), BytesMut::new()];
+        assert_eq!(
+            replayer.read_events(&mut out_bufs).unwrap(),
+            Events { read: 2, lost: 3 }
+        );
+        assert_eq!(&out_bufs[1][..], b"second");
+        assert_eq!(
+            replayer.read_events(&mut out_bufs).unwrap(),
+            Events { read: 1, lost: 0 }
+        );
+        assert_eq!(&out_bufs[0][..], b"third");
+        assert_eq!(
+            replayer.read_events(&mut out_bufs).unwrap(),
+            Events { read: 0, lost: 0 }
+        );
+        assert!(matches!(
+            replayer.read_events(&mut []),
+            Err(PerfBufferError::NoBuffers)
+        ));
+
+        assert_eq!(
+            PerfEventReplayer::new(Cursor::new(b"not a recording"))
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_record_replay_ring_buf() {
+        let mut recorder = PerfEventRecorder::new(Vec::new()).unwrap();
+        recorder.record_ring_buf(&[b"first", b"second"]).unwrap();
+        recorder
+            .record_sample(1, SystemTime::now(), b"perf")
+            .unwrap();
+        recorder.record_ring_buf(&[b"third"]).unwrap();
+        let recording = recorder.into_inner();
+
+        let mut replayer = PerfEventReplayer::new(Cursor::new(&recording)).unwrap();
+        let mut batches = Vec::new();
+        let read = replayer
+            .read_batch(2, |events| {
+                batches.push(events.iter().map(|e| e.to_vec()).collect::<Vec<_>>())
+            })
+            .unwrap();
+        assert_eq!(read, 3);
+        assert_eq!(
+            batches,
+            vec![
+                vec![b"first".to_vec(), b"second".to_vec()],
+                vec![b"third".to_vec()]
+            ]
+        );
+        assert_eq!(replayer.read_batch(2, |_| panic!()).unwrap(), 0);
+
+        let mut replayer = PerfEventReplayer::new(Cursor::new(&recording)).unwrap();
+        let mut out_bufs = [BytesMut::new(), BytesMut::new()];
+        assert_eq!(
+            replayer.read_events(&mut out_bufs).unwrap(),
+            Events { read: 1, lost: 0 }
+        );
+        assert_eq!(&out_bufs[0][..], b"perf");
+    }
+
+    #[test]
+    fn test_replay_corrupt_length() {
+        let mut recording = PerfEventRecorder::new(Vec::new()).unwrap().into_inner();
+        let mut header = [0u8; 17];
+        header[0] = RECORD_RING_BUF;
+        header[13..].copy_from_slice(&u32::MAX.to_le_bytes());
+        recording.extend_from_slice(&header);
+
+        let mut replayer = PerfEventReplayer::new(Cursor::new(&recording)).unwrap();
+        assert_eq!(
+            replayer.next_record().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // a length that is valid but larger than what's left in the recording
+        let len = recording.len() - 4;
+        recording[len..].copy_from_slice(&1024u32.to_le_bytes());
+        recording.extend_from_slice(b"truncated");
+        let mut replayer = PerfEventReplayer::new(Cursor::new(&recording)).unwrap();
+        assert_eq!(
+            replayer.next_record().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
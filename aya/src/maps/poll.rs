@@ -0,0 +1,247 @@
+//! Wait for readiness on multiple map sources at once.
+use std::{
+    io,
+    os::unix::io::{AsRawFd, RawFd},
+    time::Duration,
+};
+
+use libc::{
+    close, epoll_create1, epoll_ctl, epoll_event, epoll_wait, EINTR, EPOLLIN, EPOLL_CLOEXEC,
+    EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD,
+};
+use thiserror::Error;
+
+/// Errors returned by [`PollSet`].
+#[derive(Error, Debug)]
+pub enum PollError {
+    /// An epoll syscall failed.
+    #[error("`{call}` failed")]
+    SyscallError {
+        /// The name of the syscall which failed.
+        call: String,
+        /// The [`io::Error`] returned by the syscall.
+        #[source]
+        io_error: io::Error,
+    },
+}
+
+/// A set of event sources polled through a single epoll instance.
+///
+/// Perf buffers, ring buffers and any other source implementing [`AsRawFd`]
+/// can be registered with a user supplied token. [`PollSet::poll`] blocks
+/// until at least one of the sources becomes readable and returns the tokens
+/// of the ready sources, so a single thread can service buffers for every CPU
+/// and every map.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #    #[error(transparent)]
+/// #    IO(#[from] std::io::Error),
+/// #    #[error(transparent)]
+/// #    Map(#[from] aya::maps::MapError),
+/// #    #[error(transparent)]
+/// #    Bpf(#[from] aya::BpfError),
+/// #    #[error(transparent)]
+/// #    PerfBuf(#[from] aya::maps::perf::PerfBufferError),
+/// #    #[error(transparent)]
+/// #    Poll(#[from] aya::maps::poll::PollError),
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::{poll::PollSet, PerfEventArray};
+/// use aya::util::online_cpus;
+/// use bytes::BytesMut;
+///
+/// let mut perf_array = PerfEventArray::try_from(bpf.map_mut("EVENTS").unwrap())?;
+///
+/// let mut poll = PollSet::new()?;
+/// let mut buffers = Vec::new();
+/// for cpu_id in online_cpus()? {
+///     let buf = perf_array.open(cpu_id, None)?;
+///     poll.add(&buf, buffers.len() as u64)?;
+///     buffers.push(buf);
+/// }
+///
+/// let mut out_bufs = [BytesMut::with_capacity(1024)];
+/// loop {
+///     for token in poll.poll(None)? {
+///         let events = buffers[token as usize].read_events(&mut out_bufs)?;
+///         // process out_bufs
+///     }
+/// }
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+pub struct PollSet {
+    epoll_fd: RawFd,
+    len: usize,
+    events: Vec<epoll_event>,
+}
+
+impl PollSet {
+    /// Creates a new, empty poll set.
+    pub fn new() -> Result<PollSet, PollError> {
+        let epoll_fd = unsafe { epoll_create1(EPOLL_CLOEXEC) };
+        if epoll_fd < 0 {
+            return Err(PollError::SyscallError {
+                call: "epoll_create1".to_owned(),
+                io_error: io::Error::last_os_error(),
+            });
+        }
+        Ok(PollSet {
+            epoll_fd,
+            len: 0,
+            events: Vec::new(),
+        })
+    }
+
+    /// Registers `source` with the set. `token` is returned by
+    /// [`PollSet::poll`] whenever `source` is readable.
+    pub fn add<T: AsRawFd>(&mut self, source: &T, token: u64) -> Result<(), PollError> {
+        self.ctl(EPOLL_CTL_ADD, source.as_raw_fd(), token)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Changes the token associated with an already registered `source`.
+    pub fn modify<T: AsRawFd>(&mut self, source: &T, token: u64) -> Result<(), PollError> {
+        self.ctl(EPOLL_CTL_MOD, source.as_raw_fd(), token)
+    }
+
+    /// Removes `source` from the set.
+    pub fn remove<T: AsRawFd>(&mut self, source: &T) -> Result<(), PollError> {
+        self.ctl(EPOLL_CTL_DEL, source.as_raw_fd(), 0)?;
+        self.len -= 1;
+        Ok(())
+    }
+
+    /// Returns the number of registered sources.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no sources are registered.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Waits until at least one source is readable and returns the tokens of
+    /// the ready sources.
+    ///
+    /// If `timeout` is `None` this blocks indefinitely, otherwise it returns
+    /// an empty list once the timeout expires. Interrupted waits are retried.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<Vec<u64>, PollError> {
+        let timeout = timeout_ms(timeout);
+        self.events.clear();
+        self.events
+            .resize(self.len.max(1), epoll_event { events: 0, u64: 0 });
+        let n = loop {
+            let ret = unsafe {
+                epoll_wait(
+                    self.epoll_fd,
+                    self.events.as_mut_ptr(),
+                    self.events.len() as i32,
+                    timeout,
+                )
+            };
+            if ret >= 0 {
+                break ret as usize;
+            }
+            let io_error = io::Error::last_os_error();
+            if io_error.raw_os_error() != Some(EINTR) {
+                return Err(PollError::SyscallError {
+                    call: "epoll_wait".to_owned(),
+                    io_error,
+                });
+            }
+        };
+
+        Ok(self.events[..n].iter().map(|e| e.u64).collect())
+    }
+
+    fn ctl(&self, op: i32, fd: RawFd, token: u64) -> Result<(), PollError> {
+        let mut event = epoll_event {
+            events: EPOLLIN as u32,
+            u64: token,
+        };
+        if unsafe { epoll_ctl(self.epoll_fd, op, fd, &mut event) } < 0 {
+            return Err(PollError::SyscallError {
+                call: "epoll_ctl".to_owned(),
+                io_error: io::Error::last_os_error(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for PollSet {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll_fd
+    }
+}
+
+impl Drop for PollSet {
+    fn drop(&mut self) {
+        unsafe { close(self.epoll_fd) };
+    }
+}
+
+// Converts a timeout to the milliseconds epoll_wait expects, rounding up so
+// that timeouts under a millisecond don't turn into a busy loop.
+fn timeout_ms(timeout: Option<Duration>) -> i32 {
+    match timeout {
+        Some(t) => t.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32,
+        None => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write, os::unix::io::FromRawFd};
+
+    use super::*;
+
+    fn pipe() -> (File, File) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+    }
+
+    #[test]
+    fn test_poll_ready() {
+        let (r1, _w1) = pipe();
+        let (r2, mut w2) = pipe();
+
+        let mut poll = PollSet::new().unwrap();
+        poll.add(&r1, 1).unwrap();
+        poll.add(&r2, 2).unwrap();
+        assert_eq!(poll.len(), 2);
+
+        assert!(poll
+            .poll(Some(Duration::from_millis(0)))
+            .unwrap()
+            .is_empty());
+
+        w2.write_all(b"x").unwrap();
+        assert_eq!(poll.poll(Some(Duration::from_millis(0))).unwrap(), vec![2]);
+
+        poll.remove(&r2).unwrap();
+        assert_eq!(poll.len(), 1);
+        assert!(poll
+            .poll(Some(Duration::from_millis(0)))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_timeout_ms() {
+        assert_eq!(timeout_ms(None), -1);
+        assert_eq!(timeout_ms(Some(Duration::ZERO)), 0);
+        assert_eq!(timeout_ms(Some(Duration::from_micros(1))), 1);
+        assert_eq!(timeout_ms(Some(Duration::from_millis(5))), 5);
+        assert_eq!(timeout_ms(Some(Duration::from_micros(5001))), 6);
+        assert_eq!(timeout_ms(Some(Duration::MAX)), i32::MAX);
+    }
+}
@@ -0,0 +1,818 @@
+//! A ring buffer shared by all the CPUs, used to receive events from eBPF
+//! programs.
+use std::{
+    ffi::c_void,
+    hint, io,
+    ops::Deref,
+    os::unix::io::{AsRawFd, RawFd},
+    ptr, slice,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
+use async_io::Async;
+
+#[cfg(feature = "async_tokio")]
+use tokio::io::unix::AsyncFd;
+
+use libc::{munmap, MAP_FAILED, MAP_SHARED, PROT_READ, PROT_WRITE};
+
+use crate::{
+    generated::{BPF_RINGBUF_BUSY_BIT, BPF_RINGBUF_DISCARD_BIT, BPF_RINGBUF_HDR_SZ},
+    maps::{
+        perf::{next_sleep, WakeupStrategy},
+        MapData, MapError,
+    },
+    util::page_size,
+};
+
+/// A map that can be used to receive events from eBPF programs.
+///
+/// Unlike [`PerfEventArray`](crate::maps::PerfEventArray), a ring buffer is
+/// shared by all the CPUs, so events are received in the order they were
+/// submitted and memory doesn't need to be allocated for every CPU.
+///
+/// The ring buffer is readable while it contains events, so it can be
+/// registered with a [`PollSet`](crate::maps::PollSet) or any other epoll
+/// based event loop.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::RingBuf;
+///
+/// let mut ring_buf = RingBuf::try_from(bpf.map_mut("EVENTS").unwrap())?;
+/// while let Some(item) = ring_buf.next() {
+///     println!("received {} bytes", item.len());
+/// }
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_RINGBUF")]
+pub struct RingBuf<T> {
+    _map: T,
+    fd: RawFd,
+    consumer: *mut c_void,
+    producer: *mut c_void,
+    page_size: usize,
+    size: usize,
+}
+
+// the mmapped pages are only accessed through `&mut self` or atomics
+unsafe impl<T: Send> Send for RingBuf<T> {}
+unsafe impl<T: Sync> Sync for RingBuf<T> {}
+
+impl<T: AsRef<MapData>> RingBuf<T> {
+    pub(crate) fn new(map: T) -> Result<RingBuf<T>, MapError> {
+        let data = map.as_ref();
+        let fd = data.fd_or_err()?;
+        let page_size = page_size();
+        let size = data.obj.max_entries() as usize;
+
+        // the consumer page is the only one that can be written to
+        let consumer = mmap(fd, page_size, PROT_READ | PROT_WRITE, 0)?;
+        // the data pages are mapped twice in a row, so records wrapping
+        // around the end of the buffer can be read contiguously
+        let producer = match mmap(fd, page_size + 2 * size, PROT_READ, page_size) {
+            Ok(producer) => producer,
+            Err(e) => {
+                unsafe { munmap(consumer, page_size) };
+                return Err(e);
+            }
+        };
+
+        Ok(RingBuf {
+            _map: map,
+            fd,
+            consumer,
+            producer,
+            page_size,
+            size,
+        })
+    }
+
+    /// Returns the size of the ring buffer in bytes.
+    ///
+    /// This corresponds to the value of `bpf_map_def::max_entries` on the eBPF side.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Asks the kernel to back the data pages of the ring buffer with huge
+    /// pages, with `madvise(MADV_HUGEPAGE)`.
+    ///
+    /// This can reduce TLB pressure when reading multi-megabyte buffers at a
+    /// high rate. The memory of the ring buffer is allocated by the kernel
+    /// when the map is created, so it can't be mapped with `MAP_HUGETLB`, and
+    /// whether the hint has any effect depends on the kernel. Kernels that
+    /// don't support the hint for ring buffers return `EINVAL`, which can be
+    /// ignored.
+    pub fn advise_hugepages(&self) -> Result<(), MapError> {
+        let ret = unsafe {
+            libc::madvise(
+                self.producer,
+                self.page_size + 2 * self.size,
+                libc::MADV_HUGEPAGE,
+            )
+        };
+        if ret < 0 {
+            return Err(MapError::SyscallError {
+                call: "madvise".to_owned(),
+                io_error: io::Error::last_os_error(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns true if the ring buffer contains events that haven't been
+    /// read yet.
+    pub fn readable(&self) -> bool {
+        self.consumer_pos().load(Ordering::Acquire) != self.producer_pos().load(Ordering::Acquire)
+    }
+
+    /// Returns the next event, or `None` if the ring buffer is empty.
+    ///
+    /// The event is consumed when the returned item is dropped. Events
+    /// discarded by the eBPF program are skipped.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<RingBufItem<'_>> {
+        let record = self.next_record()?;
+        Some(self.item(record))
+    }
+
+    // Returns the offset and the length of the next record, and the consumer
+    // position after it, see `next_record`.
+    fn next_record(&self) -> Option<(usize, usize, usize)> {
+        unsafe {
+            next_record(
+                self.consumer_pos(),
+                self.producer_pos(),
+                self.data(),
+                self.size,
+            )
+        }
+    }
+
+    fn item(&self, (start, len, end): (usize, usize, usize)) -> RingBufItem<'_> {
+        RingBufItem {
+            data: unsafe { slice::from_raw_parts(self.data().add(start), len) },
+            consumer_pos: self.consumer_pos(),
+            end,
+        }
+    }
+
+    fn data(&self) -> *const u8 {
+        unsafe { (self.producer as *const u8).add(self.page_size) }
+    }
+
+    /// Reads the pending events in batches of up to `max_batch` events and
+    /// passes each batch to `f`.
+    ///
+    /// This amortizes the per event overhead of the consumer, for example
+    /// when sending the events to a channel. When fewer than `max_batch`
+    /// events are available, the ring buffer is polled for up to
+    /// `max_latency` for more events to arrive, so a batch is handed off
+    /// at the latest `max_latency` after its first event was read. Pass
+    /// [`Duration::ZERO`] to only read the events already in the buffer.
+    ///
+    /// The events of a batch are consumed when `f` returns, so the ring
+    /// buffer stays readable while a batch is collected and new events are
+    /// checked for with an adaptive sleep instead of kernel notifications.
+    /// Returns the number of events read, which is 0 if the ring buffer was
+    /// empty.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::maps::RingBuf;
+    /// use std::time::Duration;
+    ///
+    /// let mut ring_buf = RingBuf::try_from(bpf.map_mut("EVENTS").unwrap())?;
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// ring_buf.read_batch(64, Duration::from_millis(1), |events| {
+    ///     let batch: Vec<Vec<u8>> = events.iter().map(|event| event.to_vec()).collect();
+    ///     tx.send(batch).unwrap();
+    /// })?;
+    /// # drop(rx);
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn read_batch<F: FnMut(&[&[u8]])>(
+        &mut self,
+        max_batch: usize,
+        max_latency: Duration,
+        mut f: F,
+    ) -> Result<usize, MapError> {
+        let data = unsafe { (self.producer as *const u8).add(self.page_size) };
+        let mut records = Vec::new();
+        let mut total = 0;
+        loop {
+            let start = self.consumer_pos().load(Ordering::Acquire);
+            let mut seen = self.producer_pos().load(Ordering::Acquire);
+            let mut pos = unsafe {
+                read_records(
+                    start,
+                    self.producer_pos(),
+                    data,
+                    self.size,
+                    max_batch,
+                    &mut records,
+                )
+            };
+            if records.is_empty() {
+                if pos != start {
+                    // only discarded records
+                    self.consumer_pos().store(pos, Ordering::Release);
+                    continue;
+                }
+                return Ok(total);
+            }
+
+            let deadline = Instant::now() + max_latency;
+            while records.len() < max_batch {
+                match self.wait_producer(seen, deadline) {
+                    Some(producer_pos) => seen = producer_pos,
+                    None => break,
+                }
+                pos = unsafe {
+                    read_records(
+                        pos,
+                        self.producer_pos(),
+                        data,
+                        self.size,
+                        max_batch,
+                        &mut records,
+                    )
+                };
+            }
+
+            let batch: Vec<&[u8]> = records
+                .iter()
+                .map(|(start, len)| unsafe { slice::from_raw_parts(data.add(*start), *len) })
+                .collect();
+            f(&batch);
+            total += records.len();
+            records.clear();
+            self.consumer_pos().store(pos, Ordering::Release);
+        }
+    }
+
+    /// Waits until the ring buffer contains events, using `strategy`.
+    ///
+    /// Returns `false` if `timeout` expired first. A `timeout` of `None`
+    /// waits forever.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::maps::{perf::WakeupStrategy, RingBuf};
+    ///
+    /// let mut ring_buf = RingBuf::try_from(bpf.map_mut("EVENTS").unwrap())?;
+    /// loop {
+    ///     ring_buf.wait_readable(WakeupStrategy::BusyPoll, None)?;
+    ///     while let Some(item) = ring_buf.next() {
+    ///         println!("received {} bytes", item.len());
+    ///     }
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn wait_readable(
+        &self,
+        strategy: WakeupStrategy,
+        timeout: Option<Duration>,
+    ) -> Result<bool, MapError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut sleep = Duration::ZERO;
+        loop {
+            if self.readable() {
+                return Ok(true);
+            }
+            let remaining = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => Some(remaining),
+                    _ => return Ok(false),
+                },
+                None => None,
+            };
+            match strategy {
+                WakeupStrategy::Notify => {
+                    self.wait(remaining)?;
+                }
+                WakeupStrategy::BusyPoll => hint::spin_loop(),
+                WakeupStrategy::AdaptiveSleep { min, max } => {
+                    sleep = next_sleep(sleep, min, max);
+                    thread::sleep(remaining.map_or(sleep, |remaining| sleep.min(remaining)));
+                }
+            }
+        }
+    }
+
+    // Waits for the producer position to move past `seen` and returns it, or
+    // returns `None` once `deadline` is reached. This can't poll() since the
+    // records read before `seen` aren't consumed yet, which keeps the ring
+    // buffer readable.
+    fn wait_producer(&self, seen: usize, deadline: Instant) -> Option<usize> {
+        let mut sleep = Duration::ZERO;
+        loop {
+            let producer_pos = self.producer_pos().load(Ordering::Acquire);
+            if producer_pos != seen {
+                return Some(producer_pos);
+            }
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|remaining| !remaining.is_zero())?;
+            sleep = next_sleep(sleep, BATCH_MIN_SLEEP, BATCH_MAX_SLEEP);
+            thread::sleep(sleep.min(remaining));
+
+            #[cfg(test)]
+            TEST_WAITS.with(|waits| waits.set(waits.get() + 1));
+        }
+    }
+
+    // Waits for the kernel to notify that the ring buffer is readable,
+    // returns false on timeout.
+    fn wait(&self, timeout: Option<Duration>) -> Result<bool, MapError> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // round up so that short timeouts don't turn into busy loops
+        let timeout_ms = timeout.map_or(-1, |timeout| {
+            timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+        });
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ret < 0 {
+            let io_error = io::Error::last_os_error();
+            if io_error.kind() == io::ErrorKind::Interrupted {
+                return Ok(true);
+            }
+            return Err(MapError::SyscallError {
+                call: "poll".to_owned(),
+                io_error,
+            });
+        }
+        Ok(ret > 0)
+    }
+
+    fn consumer_pos(&self) -> &AtomicUsize {
+        unsafe { &*(self.consumer as *const AtomicUsize) }
+    }
+
+    fn producer_pos(&self) -> &AtomicUsize {
+        unsafe { &*(self.producer as *const AtomicUsize) }
+    }
+}
+
+impl<T> AsRawFd for RingBuf<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl<T> Drop for RingBuf<T> {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.consumer, self.page_size);
+            munmap(self.producer, self.page_size + 2 * self.size);
+        }
+    }
+}
+
+/// A `Future` based [`RingBuf`].
+///
+/// This is the async version of [`RingBuf`], which waits for the ring buffer
+/// to be readable with [tokio](https://docs.rs/tokio) or
+/// [async-std](https://docs.rs/async-std), so events can be received from an
+/// event loop without a dedicated polling thread.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "async_tokio")]
+/// # async fn try_main() -> Result<(), aya::BpfError> {
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::ring_buf::AsyncRingBuf;
+///
+/// let mut ring_buf = AsyncRingBuf::try_from(bpf.take_map("EVENTS").unwrap())?;
+/// loop {
+///     let item = ring_buf.next().await?;
+///     println!("received {} bytes", item.len());
+/// }
+/// # }
+/// ```
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[doc(alias = "BPF_MAP_TYPE_RINGBUF")]
+pub struct AsyncRingBuf<T> {
+    ring_buf: RingBuf<T>,
+
+    #[cfg(feature = "async_tokio")]
+    async_fd: AsyncFd<RawFd>,
+
+    #[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
+    async_fd: Async<RawFd>,
+}
+
+#[cfg(feature = "async")]
+impl<T: AsRef<MapData>> AsyncRingBuf<T> {
+    pub(crate) fn new(map: T) -> Result<AsyncRingBuf<T>, MapError> {
+        let ring_buf = RingBuf::new(map)?;
+        #[cfg(any(feature = "async_tokio", feature = "async_std"))]
+        let fd = ring_buf.fd;
+        Ok(AsyncRingBuf {
+            ring_buf,
+
+            #[cfg(feature = "async_tokio")]
+            async_fd: AsyncFd::new(fd).map_err(register_error)?,
+
+            #[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
+            async_fd: Async::new(fd).map_err(register_error)?,
+        })
+    }
+
+    /// Returns the underlying [`RingBuf`].
+    pub fn get_ref(&self) -> &RingBuf<T> {
+        &self.ring_buf
+    }
+}
+
+#[cfg(feature = "async_tokio")]
+impl<T: AsRef<MapData>> AsyncRingBuf<T> {
+    /// Waits for the next event.
+    ///
+    /// The event is consumed when the returned item is dropped. Events
+    /// discarded by the eBPF program are skipped.
+    pub async fn next(&mut self) -> Result<RingBufItem<'_>, MapError> {
+        loop {
+            let mut guard = self.async_fd.readable_mut().await.map_err(register_error)?;
+            if let Some(record) = self.ring_buf.next_record() {
+                return Ok(self.ring_buf.item(record));
+            }
+            guard.clear_ready();
+        }
+    }
+}
+
+#[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
+impl<T: AsRef<MapData>> AsyncRingBuf<T> {
+    /// Waits for the next event.
+    ///
+    /// The event is consumed when the returned item is dropped. Events
+    /// discarded by the eBPF program are skipped.
+    pub async fn next(&mut self) -> Result<RingBufItem<'_>, MapError> {
+        loop {
+            if let Some(record) = self.ring_buf.next_record() {
+                return Ok(self.ring_buf.item(record));
+            }
+            self.async_fd.readable().await.map_err(register_error)?;
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> AsRawFd for AsyncRingBuf<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.ring_buf.fd
+    }
+}
+
+#[cfg(any(feature = "async_tokio", feature = "async_std"))]
+fn register_error(io_error: io::Error) -> MapError {
+    MapError::SyscallError {
+        call: "epoll_ctl".to_owned(),
+        io_error,
+    }
+}
+
+// the bounds of the sleeps between the checks for new events of read_batch
+const BATCH_MIN_SLEEP: Duration = Duration::from_micros(10);
+const BATCH_MAX_SLEEP: Duration = Duration::from_millis(1);
+
+#[cfg(test)]
+thread_local! {
+    // the number of sleeps of RingBuf::wait_producer on this thread
+    static TEST_WAITS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// An event read from a [`RingBuf`].
+///
+/// The item derefs to the bytes of the event, which are read in place from
+/// the ring buffer. The event is consumed, and its space made available to
+/// the eBPF programs, when the item is dropped.
+pub struct RingBufItem<'a> {
+    data: &'a [u8],
+    consumer_pos: &'a AtomicUsize,
+    end: usize,
+}
+
+impl Deref for RingBufItem<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl Drop for RingBufItem<'_> {
+    fn drop(&mut self) {
+        self.consumer_pos.store(self.end, Ordering::Release);
+    }
+}
+
+// Returns the offset and the length of the next record within `data`, and the
+// consumer position after it. Discarded records are consumed on the way.
+unsafe fn next_record(
+    consumer_pos: &AtomicUsize,
+    producer_pos: &AtomicUsize,
+    data: *const u8,
+    size: usize,
+) -> Option<(usize, usize, usize)> {
+    let mut pos = consumer_pos.load(Ordering::Acquire);
+    loop {
+        let (start, len, next, discarded) = read_record(pos, producer_pos, data, size)?;
+        if discarded {
+            pos = next;
+            consumer_pos.store(pos, Ordering::Release);
+            continue;
+        }
+        return Some((start, len, next));
+    }
+}
+
+// Reads the header of the record at `pos`. Returns the offset and the length
+// of the record within `data`, the position after it and whether it was
+// discarded, or `None` if there's no committed record at `pos`.
+unsafe fn read_record(
+    pos: usize,
+    producer_pos: &AtomicUsize,
+    data: *const u8,
+    size: usize,
+) -> Option<(usize, usize, usize, bool)> {
+    if pos == producer_pos.load(Ordering::Acquire) {
+        return None;
+    }
+    let offset = pos & (size - 1);
+    // the length is written last by the producer, when it commits the
+    // record
+    let header = &*(data.add(offset) as *const AtomicU32);
+    let len = header.load(Ordering::Acquire);
+    if len & BPF_RINGBUF_BUSY_BIT != 0 {
+        return None;
+    }
+    let data_len = (len & !(BPF_RINGBUF_BUSY_BIT | BPF_RINGBUF_DISCARD_BIT)) as usize;
+    let hdr_size = BPF_RINGBUF_HDR_SZ as usize;
+    let next = pos + (hdr_size + data_len).div_ceil(8) * 8;
+    Some((
+        offset + hdr_size,
+        data_len,
+        next,
+        len & BPF_RINGBUF_DISCARD_BIT != 0,
+    ))
+}
+
+// Collects up to `max_batch` records starting from `pos`, skipping discarded
+// records. Returns the position after the last record read.
+unsafe fn read_records(
+    mut pos: usize,
+    producer_pos: &AtomicUsize,
+    data: *const u8,
+    size: usize,
+    max_batch: usize,
+    records: &mut Vec<(usize, usize)>,
+) -> usize {
+    while records.len() < max_batch {
+        match read_record(pos, producer_pos, data, size) {
+            Some((start, len, next, discarded)) => {
+                if !discarded {
+                    records.push((start, len));
+                }
+                pos = next;
+            }
+            None => break,
+        }
+    }
+    pos
+}
+
+fn mmap(fd: RawFd, len: usize, prot: i32, offset: usize) -> Result<*mut c_void, MapError> {
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            prot,
+            MAP_SHARED,
+            fd,
+            offset as libc::off_t,
+        )
+    };
+    if ptr == MAP_FAILED {
+        return Err(MapError::SyscallError {
+            call: "mmap".to_owned(),
+            io_error: io::Error::last_os_error(),
+        });
+    }
+    Ok(ptr)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bpf_map_def, generated::bpf_map_type::BPF_MAP_TYPE_RINGBUF, obj};
+
+    use super::*;
+
+    const SIZE: usize = 64;
+
+    #[repr(align(8))]
+    struct Data([u8; 2 * SIZE]);
+
+    fn fake_ring_buf() -> RingBuf<MapData> {
+        let page_size = page_size();
+        let anon = |len| unsafe {
+            let ptr = libc::mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(ptr, MAP_FAILED);
+            ptr
+        };
+        RingBuf {
+            _map: MapData {
+                obj: obj::Map::Legacy(obj::LegacyMap {
+                    def: bpf_map_def {
+                        map_type: BPF_MAP_TYPE_RINGBUF as u32,
+                        max_entries: page_size as u32,
+                        ..Default::default()
+                    },
+                    section_index: 0,
+                    data: Vec::new(),
+                    kind: obj::MapKind::Other,
+                    symbol_index: 0,
+                    map_extra: 0,
+                    numa_node: None,
+                }),
+                fd: None,
+                pinned: false,
+                btf_fd: None,
+            },
+            fd: -1,
+            consumer: anon(page_size),
+            producer: anon(3 * page_size),
+            page_size,
+            size: page_size,
+        }
+    }
+
+    fn write_record(data: &mut Data, pos: usize, len: u32, payload: &[u8]) -> usize {
+        let mut record = len.to_ne_bytes().to_vec();
+        record.extend_from_slice(&[0; 4]);
+        record.extend_from_slice(payload);
+        for (i, b) in record.iter().enumerate() {
+            // emulate the double mapping of the data pages
+            let offset = (pos + i) % SIZE;
+            data.0[offset] = *b;
+            data.0[offset + SIZE] = *b;
+        }
+        pos + record.len().div_ceil(8) * 8
+    }
+
+    #[test]
+    fn test_next_record() {
+        let mut data = Data([0; 2 * SIZE]);
+        let consumer = AtomicUsize::new(0);
+        let producer = AtomicUsize::new(0);
+        let next =
+            |data: &Data| unsafe { next_record(&consumer, &producer, data.0.as_ptr(), SIZE) };
+
+        assert_eq!(next(&data), None);
+
+        let pos = write_record(&mut data, 0, 5, b"first");
+        let pos = write_record(&mut data, pos, 4 | BPF_RINGBUF_DISCARD_BIT, b"skip");
+        let pos = write_record(&mut data, pos, 6, b"second");
+        let busy = write_record(&mut data, pos, 4 | BPF_RINGBUF_BUSY_BIT, b"busy");
+        producer.store(busy, Ordering::Release);
+
+        assert_eq!(next(&data), Some((8, 5, 16)));
+        consumer.store(16, Ordering::Release);
+        assert_eq!(next(&data), Some((40, 6, 48)));
+        // the discarded record was consumed
+        assert_eq!(consumer.load(Ordering::Acquire), 32);
+        consumer.store(48, Ordering::Release);
+        assert_eq!(next(&data), None);
+
+        // the busy record is committed and wraps around the end of the buffer
+        let end = write_record(&mut data, pos, 26, b"wrapping around the buffer");
+        producer.store(end, Ordering::Release);
+        let (start, len, next_pos) = next(&data).unwrap();
+        assert_eq!((start, len, next_pos), (56, 26, 88));
+        assert_eq!(&data.0[start..start + len], b"wrapping around the buffer");
+    }
+
+    #[test]
+    fn test_read_records() {
+        let mut data = Data([0; 2 * SIZE]);
+        let producer = AtomicUsize::new(0);
+
+        let pos = write_record(&mut data, 0, 5, b"first");
+        let pos = write_record(&mut data, pos, 4 | BPF_RINGBUF_DISCARD_BIT, b"skip");
+        let pos = write_record(&mut data, pos, 6, b"second");
+        let end = write_record(&mut data, pos, 5, b"third");
+        producer.store(end, Ordering::Release);
+
+        let mut records = Vec::new();
+        let next = unsafe { read_records(0, &producer, data.0.as_ptr(), SIZE, 2, &mut records) };
+        assert_eq!(records, vec![(8, 5), (40, 6)]);
+        assert_eq!(next, pos);
+
+        let next = unsafe { read_records(next, &producer, data.0.as_ptr(), SIZE, 4, &mut records) };
+        assert_eq!(records, vec![(8, 5), (40, 6), (56, 5)]);
+        assert_eq!(next, end);
+    }
+
+    #[test]
+    fn test_read_batch_waits() {
+        let mut ring_buf = fake_ring_buf();
+        let data = ring_buf.data() as *mut u8;
+        unsafe {
+            ptr::copy_nonoverlapping(5u32.to_ne_bytes().as_ptr(), data, 4);
+            ptr::copy_nonoverlapping(b"first".as_ptr(), data.add(8), 5);
+        }
+        ring_buf.producer_pos().store(16, Ordering::Release);
+
+        let mut batches = Vec::new();
+        let mut read = |max_latency| {
+            TEST_WAITS.with(|waits| waits.set(0));
+            let n = ring_buf
+                .read_batch(4, max_latency, |events| {
+                    batches.push(events.iter().map(|e| e.to_vec()).collect::<Vec<_>>())
+                })
+                .unwrap();
+            (n, TEST_WAITS.with(|waits| waits.get()))
+        };
+
+        // the unconsumed record doesn't wake the batch up, the sleeps back
+        // off until the deadline instead
+        let (n, waits) = read(Duration::from_millis(20));
+        assert_eq!(n, 1);
+        assert!((1..=30).contains(&waits), "{waits} waits");
+        assert_eq!(read(Duration::from_millis(20)), (0, 0));
+        assert_eq!(batches, vec![vec![b"first".to_vec()]]);
+        assert_eq!(ring_buf.consumer_pos().load(Ordering::Acquire), 16);
+    }
+
+    #[cfg(feature = "async_tokio")]
+    #[tokio::test]
+    async fn test_async_next() {
+        let mut ring_buf = fake_ring_buf();
+        // stands in for the map fd, which the kernel makes readable when
+        // records are submitted
+        let efd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        assert!(efd >= 0);
+        ring_buf.fd = efd;
+        let mut async_ring_buf = AsyncRingBuf {
+            ring_buf,
+            async_fd: AsyncFd::new(efd).unwrap(),
+        };
+
+        let data = async_ring_buf.ring_buf.data() as *mut u8;
+        unsafe {
+            ptr::copy_nonoverlapping(5u32.to_ne_bytes().as_ptr(), data, 4);
+            ptr::copy_nonoverlapping(b"first".as_ptr(), data.add(8), 5);
+        }
+        async_ring_buf
+            .ring_buf
+            .producer_pos()
+            .store(16, Ordering::Release);
+        let ret = unsafe { libc::eventfd_write(efd, 1) };
+        assert_eq!(ret, 0);
+
+        let item = async_ring_buf.next().await.unwrap();
+        assert_eq!(&*item, b"first");
+        drop(item);
+        assert_eq!(
+            async_ring_buf
+                .ring_buf
+                .consumer_pos()
+                .load(Ordering::Acquire),
+            16
+        );
+
+        drop(async_ring_buf);
+        unsafe { libc::close(efd) };
+    }
+}
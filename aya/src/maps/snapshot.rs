@@ -0,0 +1,448 @@
+//! Map metadata and contents snapshots.
+//!
+//! With the `serde` feature enabled, the types in this module implement
+//! `serde::Serialize`, so map dumps can be exported as JSON or any other
+//! format supported by serde.
+use std::{fs, io, iter, os::unix::io::RawFd};
+
+use libc::ENOENT;
+
+use crate::{
+    generated::{bpf_map_info, bpf_map_type, bpf_map_type::*},
+    maps::{Map, MapData, MapError},
+    sys::{
+        bpf_map_get_fd_by_id, bpf_map_get_info_by_fd, bpf_map_get_next_id,
+        bpf_map_get_next_key_bytes, bpf_map_lookup_elem_ptr,
+    },
+    util::nr_cpus,
+};
+
+/// Information about a loaded map, as reported by the kernel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MapInfo {
+    /// The map id. Each map has a unique id.
+    pub id: u32,
+    /// The name of the map, limited to 16 bytes by the kernel.
+    pub name: String,
+    /// The `bpf_map_type` of the map.
+    pub map_type: u32,
+    /// The size of the keys.
+    pub key_size: u32,
+    /// The size of the values.
+    pub value_size: u32,
+    /// The maximum number of entries.
+    pub max_entries: u32,
+    /// The flags the map was created with.
+    pub map_flags: u32,
+}
+
+impl From<bpf_map_info> for MapInfo {
+    fn from(info: bpf_map_info) -> MapInfo {
+        let name = info
+            .name
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as u8)
+            .collect::<Vec<_>>();
+        MapInfo {
+            id: info.id,
+            name: String::from_utf8_lossy(&name).into_owned(),
+            map_type: info.type_,
+            key_size: info.key_size,
+            value_size: info.value_size,
+            max_entries: info.max_entries,
+            map_flags: info.map_flags,
+        }
+    }
+}
+
+/// An entry of a [`MapSnapshot`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MapEntry {
+    /// The key.
+    pub key: Vec<u8>,
+    /// The value, or one value per possible CPU for per-CPU maps.
+    pub values: Vec<Vec<u8>>,
+}
+
+/// The metadata and the entries of a map at a point in time.
+///
+/// See [`MapData::snapshot`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MapSnapshot {
+    /// The map metadata.
+    pub info: MapInfo,
+    /// The entries of the map, in iteration order.
+    pub entries: Vec<MapEntry>,
+}
+
+impl MapData {
+    /// Returns information about the map.
+    pub fn info(&self) -> Result<MapInfo, MapError> {
+        let fd = self.fd_or_err()?;
+        map_info(fd)
+    }
+
+    /// Returns a snapshot of the metadata and the entries of the map.
+    ///
+    /// Keys and values are returned as raw bytes, whatever the type of the map.
+    /// Entries being concurrently deleted are skipped. The entries of maps
+    /// that can't be iterated from user space, like queues, stacks, bloom
+    /// filters, perf event arrays and socket maps, are not included.
+    ///
+    /// This makes two syscalls per entry, which can take a long time for
+    /// large maps. From async code, call it from `tokio::task::spawn_blocking`
+    /// or an equivalent to avoid stalling the runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// let snapshot = bpf.map("CONNECTIONS").unwrap().snapshot()?;
+    /// println!("{} has {} entries", snapshot.info.name, snapshot.entries.len());
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn snapshot(&self) -> Result<MapSnapshot, MapError> {
+        let fd = self.fd_or_err()?;
+        let info = map_info(fd)?;
+        let entries = match bpf_map_type::try_from(info.map_type) {
+            Ok(
+                BPF_MAP_TYPE_QUEUE
+                | BPF_MAP_TYPE_STACK
+                | BPF_MAP_TYPE_BLOOM_FILTER
+                | BPF_MAP_TYPE_PERF_EVENT_ARRAY
+                | BPF_MAP_TYPE_RINGBUF
+                | BPF_MAP_TYPE_ARENA
+                | BPF_MAP_TYPE_SOCKMAP
+                | BPF_MAP_TYPE_SOCKHASH,
+            ) => Vec::new(),
+            Ok(
+                BPF_MAP_TYPE_PERCPU_HASH
+                | BPF_MAP_TYPE_PERCPU_ARRAY
+                | BPF_MAP_TYPE_LRU_PERCPU_HASH
+                | BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE_DEPRECATED,
+            ) => {
+                let nr_cpus = nr_cpus().map_err(|io_error| MapError::SyscallError {
+                    call: "nr_cpus".to_owned(),
+                    io_error,
+                })?;
+                entries(fd, &info, nr_cpus)?
+            }
+            _ => entries(fd, &info, 1)?,
+        };
+
+        Ok(MapSnapshot { info, entries })
+    }
+
+    /// Returns the number of bytes of kernel memory charged for the map.
+    ///
+    /// This is the `memlock` value the kernel reports in
+    /// `/proc/self/fdinfo`, which covers the keys, the values and the
+    /// bookkeeping of the map. It's what counts against `RLIMIT_MEMLOCK` on
+    /// kernels older than 5.11, and against the memory cgroup of the
+    /// process on newer kernels.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 4.10.
+    pub fn memory_usage(&self) -> Result<u64, MapError> {
+        let fd = self.fd_or_err()?;
+        let io_error = |io_error| MapError::SyscallError {
+            call: "fdinfo".to_owned(),
+            io_error,
+        };
+        let fdinfo = fs::read_to_string(format!("/proc/self/fdinfo/{}", fd)).map_err(io_error)?;
+        parse_memlock(&fdinfo).ok_or_else(|| {
+            io_error(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "memlock not found in fdinfo",
+            ))
+        })
+    }
+}
+
+impl Map {
+    /// Returns information about the map, see [`MapData::info`].
+    pub fn info(&self) -> Result<MapInfo, MapError> {
+        self.data().info()
+    }
+
+    /// Returns a snapshot of the map, see [`MapData::snapshot`].
+    pub fn snapshot(&self) -> Result<MapSnapshot, MapError> {
+        self.data().snapshot()
+    }
+
+    /// Returns the kernel memory used by the map, see
+    /// [`MapData::memory_usage`].
+    pub fn memory_usage(&self) -> Result<u64, MapError> {
+        self.data().memory_usage()
+    }
+}
+
+/// Returns an iterator over all the maps created in the kernel.
+///
+/// This includes the maps created by other processes. Listing maps requires
+/// `CAP_SYS_ADMIN`. Maps freed while iterating are skipped. A map can be
+/// opened from its id with [`MapData::from_id`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::maps::loaded_maps;
+///
+/// for map in loaded_maps() {
+///     let map = map?;
+///     println!("{} {} {} entries", map.id, map.name, map.max_entries);
+/// }
+/// # Ok::<(), aya::maps::MapError>(())
+/// ```
+pub fn loaded_maps() -> impl Iterator<Item = Result<MapInfo, MapError>> {
+    let mut next_id = Some(0);
+    iter::from_fn(move || loop {
+        let id = match bpf_map_get_next_id(next_id?) {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                next_id = None;
+                return None;
+            }
+            Err(io_error) => {
+                next_id = None;
+                return Some(Err(MapError::SyscallError {
+                    call: "bpf_map_get_next_id".to_owned(),
+                    io_error,
+                }));
+            }
+        };
+        next_id = Some(id);
+
+        let fd = match bpf_map_get_fd_by_id(id) {
+            Ok(fd) => fd,
+            // the map was freed after we got its id
+            Err(io_error) if io_error.raw_os_error() == Some(ENOENT) => continue,
+            Err(io_error) => {
+                return Some(Err(MapError::SyscallError {
+                    call: "bpf_map_get_fd_by_id".to_owned(),
+                    io_error,
+                }))
+            }
+        };
+        let info = map_info(fd);
+        unsafe { libc::close(fd) };
+        return Some(info);
+    })
+}
+
+fn parse_memlock(fdinfo: &str) -> Option<u64> {
+    fdinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("memlock:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+fn map_info(fd: RawFd) -> Result<MapInfo, MapError> {
+    bpf_map_get_info_by_fd(fd)
+        .map(MapInfo::from)
+        .map_err(|io_error| MapError::SyscallError {
+            call: "BPF_MAP_GET_INFO_BY_FD".to_owned(),
+            io_error,
+        })
+}
+
+// Reads all the entries of a map. Per-CPU values are padded to 8 bytes by the
+// kernel.
+fn entries(fd: RawFd, info: &MapInfo, nr_values: usize) -> Result<Vec<MapEntry>, MapError> {
+    let key_size = info.key_size as usize;
+    let value_size = info.value_size as usize;
+    let stride = if nr_values > 1 {
+        (value_size + 7) & !7
+    } else {
+        value_size
+    };
+
+    let mut entries = Vec::new();
+    let mut key: Option<Vec<u8>> = None;
+    loop {
+        let mut next_key = vec![0u8; key_size];
+        let found = bpf_map_get_next_key_bytes(fd, key.as_deref(), &mut next_key).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_get_next_key".to_owned(),
+                io_error,
+            },
+        )?;
+        if !found {
+            break;
+        }
+
+        let mut value = vec![0u8; stride * nr_values];
+        // keys have a non-zero size, which the kernel checks when creating
+        // the map
+        match bpf_map_lookup_elem_ptr(fd, Some(&next_key[0]), value.as_mut_ptr(), 0) {
+            Ok(Some(())) => entries.push(MapEntry {
+                key: next_key.clone(),
+                values: value
+                    .chunks(stride)
+                    .map(|v| v[..value_size].to_vec())
+                    .collect(),
+            }),
+            Ok(None) => {}
+            Err((_, io_error)) if io_error.raw_os_error() == Some(ENOENT) => {}
+            Err((_, io_error)) => {
+                return Err(MapError::SyscallError {
+                    call: "bpf_map_lookup_elem".to_owned(),
+                    io_error,
+                })
+            }
+        }
+        key = Some(next_key);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::{EFAULT, EPERM};
+
+    use super::*;
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_attr, bpf_cmd},
+        obj,
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    fn bpf_key(attr: &bpf_attr) -> Option<u32> {
+        match unsafe { attr.__bindgen_anon_2.key } as *const u32 {
+            p if p.is_null() => None,
+            p => Some(unsafe { *p }),
+        }
+    }
+
+    fn syscall(call: Syscall) -> SysResult {
+        match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_map_info) };
+                info.type_ = BPF_MAP_TYPE_HASH as u32;
+                info.id = 7;
+                info.key_size = 4;
+                info.value_size = 4;
+                info.max_entries = 16;
+                info.name[..5]
+                    .copy_from_slice(&[b'c' as _, b'o' as _, b'n' as _, b'n' as _, b's' as _]);
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_GET_NEXT_KEY,
+                attr,
+            } => {
+                let next = match bpf_key(attr) {
+                    None => 10,
+                    Some(10) => 20,
+                    Some(20) => 30,
+                    Some(_) => return sys_error(ENOENT),
+                };
+                let next_key = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.next_key };
+                unsafe { *(next_key as *mut u32) = next };
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let value = match bpf_key(attr) {
+                    Some(10) => 100u32,
+                    Some(30) => 300,
+                    // deleted while iterating
+                    _ => return sys_error(ENOENT),
+                };
+                let ptr = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value };
+                unsafe { *(ptr as *mut u32) = value };
+                Ok(0)
+            }
+            _ => sys_error(EFAULT),
+        }
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_snapshot() {
+        override_syscall(syscall);
+        let map = MapData {
+            obj: obj::Map::Legacy(obj::LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_HASH as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 16,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: obj::MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+
+        let snapshot = map.snapshot().unwrap();
+        assert_eq!(snapshot.info.name, "conns");
+        assert_eq!(snapshot.info.id, 7);
+        assert_eq!(
+            snapshot.entries,
+            vec![
+                MapEntry {
+                    key: 10u32.to_ne_bytes().to_vec(),
+                    values: vec![100u32.to_ne_bytes().to_vec()],
+                },
+                MapEntry {
+                    key: 30u32.to_ne_bytes().to_vec(),
+                    values: vec![300u32.to_ne_bytes().to_vec()],
+                },
+            ]
+        );
+
+        #[cfg(feature = "serde")]
+        assert!(serde_json::to_string(&snapshot)
+            .unwrap()
+            .contains(r#""name":"conns""#));
+    }
+
+    #[test]
+    fn test_parse_memlock() {
+        let fdinfo = "pos:\t0\nflags:\t02000002\nmnt_id:\t15\nino:\t1052\nmap_type:\t1\nkey_size:\t4\nvalue_size:\t8\nmax_entries:\t1024\nmap_flags:\t0x0\nmap_extra:\t0x0\nmemlock:\t86016\nmap_id:\t7\nfrozen:\t0\n";
+        assert_eq!(parse_memlock(fdinfo), Some(86016));
+        assert_eq!(parse_memlock("pos:\t0\n"), None);
+    }
+
+    #[test]
+    fn test_loaded_maps() {
+        override_syscall(|_| sys_error(ENOENT));
+        assert_eq!(loaded_maps().count(), 0);
+
+        override_syscall(|_| sys_error(EPERM));
+        let mut maps = loaded_maps();
+        assert!(matches!(
+            maps.next(),
+            Some(Err(MapError::SyscallError { call, .. })) if call == "bpf_map_get_next_id"
+        ));
+        assert!(maps.next().is_none());
+    }
+}
@@ -1,7 +1,9 @@
 //! Socket maps.
+mod reuseport_sock_array;
 mod sock_hash;
 mod sock_map;
 
+pub use reuseport_sock_array::ReusePortSockArray;
 pub use sock_hash::SockHash;
 pub use sock_map::SockMap;
 
@@ -0,0 +1,415 @@
+//! An array of listening sockets used by `SO_REUSEPORT` groups.
+
+use std::{
+    convert::{AsMut, AsRef},
+    io, mem,
+    os::unix::io::{AsRawFd, RawFd},
+};
+
+use crate::{
+    maps::{check_bounds, check_kv_size, MapData, MapError},
+    sys::{bpf_map_delete_elem, bpf_map_lookup_elem, bpf_map_update_elem},
+};
+
+// not exported by older versions of libc
+const SO_COOKIE: libc::c_int = 57;
+
+/// An array of listening sockets belonging to the same `SO_REUSEPORT` group.
+///
+/// `sk_reuseport` programs call `bpf_sk_select_reuseport()` with an index in
+/// the array to choose the socket an incoming connection or datagram is
+/// delivered to. The sockets must be TCP sockets in the listening state or
+/// bound UDP sockets, with `SO_REUSEPORT` set.
+///
+/// The socket at a given index must stay the same while the program uses it
+/// for load balancing, so [`ReusePortSockArray::populate`] stores the
+/// sockets in the order they're given and clears the rest of the array. When
+/// a socket is rebuilt, [`ReusePortSockArray::revalidate`] puts the new
+/// socket back at its index.
+///
+/// The map value must be a `u64` for the sockets to be looked up from user
+/// space, which [`ReusePortSockArray::revalidate`] relies on.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.19.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::ReusePortSockArray;
+/// use std::net::TcpListener;
+///
+/// use std::{io, mem, os::unix::io::FromRawFd};
+///
+/// // the listeners must set SO_REUSEPORT before binding to share the port
+/// fn listen(port: u16) -> io::Result<TcpListener> {
+///     unsafe {
+///         let fd = libc::socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+///         if fd < 0 {
+///             return Err(io::Error::last_os_error());
+///         }
+///         let listener = TcpListener::from_raw_fd(fd);
+///         let one: libc::c_int = 1;
+///         let addr = libc::sockaddr_in {
+///             sin_family: libc::AF_INET as libc::sa_family_t,
+///             sin_port: port.to_be(),
+///             sin_addr: libc::in_addr { s_addr: 0 },
+///             sin_zero: [0; 8],
+///         };
+///         if libc::setsockopt(
+///             fd,
+///             libc::SOL_SOCKET,
+///             libc::SO_REUSEPORT,
+///             &one as *const _ as *const _,
+///             mem::size_of_val(&one) as libc::socklen_t,
+///         ) < 0
+///             || libc::bind(
+///                 fd,
+///                 &addr as *const _ as *const _,
+///                 mem::size_of_val(&addr) as libc::socklen_t,
+///             ) < 0
+///             || libc::listen(fd, 128) < 0
+///         {
+///             return Err(io::Error::last_os_error());
+///         }
+///         Ok(listener)
+///     }
+/// }
+///
+/// let mut sockets = ReusePortSockArray::try_from(bpf.map_mut("SOCKETS").unwrap())?;
+/// let mut listeners = (0..4).map(|_| listen(8080)).collect::<Result<Vec<_>, _>>()?;
+/// sockets.populate(&listeners)?;
+///
+/// // later, after a listener is rebuilt
+/// listeners[2] = listen(8080)?;
+/// let repaired = sockets.revalidate(&listeners)?;
+/// assert_eq!(repaired, vec![2]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_REUSEPORT_SOCKARRAY")]
+pub struct ReusePortSockArray<T> {
+    pub(crate) inner: T,
+}
+
+impl<T: AsRef<MapData>> ReusePortSockArray<T> {
+    pub(crate) fn new(map: T) -> Result<ReusePortSockArray<T>, MapError> {
+        let data = map.as_ref();
+        // the kernel accepts both 32 and 64 bit values
+        if data.obj.value_size() as usize == mem::size_of::<u32>() {
+            check_kv_size::<u32, u32>(data)?;
+        } else {
+            check_kv_size::<u32, u64>(data)?;
+        }
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(ReusePortSockArray { inner: map })
+    }
+
+    /// Returns the cookie of the socket stored at `index`, or `None` if the
+    /// index is empty.
+    ///
+    /// The cookie identifies the socket and can be compared with the
+    /// `SO_COOKIE` socket option.
+    pub fn cookie(&self, index: u32) -> Result<Option<u64>, MapError> {
+        let data = self.inner.as_ref();
+        let fd = data.fd_or_err()?;
+        check_bounds(data, index)?;
+        bpf_map_lookup_elem(fd, &index, 0).map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_lookup_elem".to_owned(),
+            io_error,
+        })
+    }
+
+    /// Returns the indices of `sockets` that aren't stored at their index in
+    /// the array, for example because they were closed and rebuilt.
+    pub fn stale_indices<S: AsRawFd>(&self, sockets: &[S]) -> Result<Vec<u32>, MapError> {
+        self.check_len(sockets.len())?;
+        let mut stale = Vec::new();
+        for (index, socket) in (0..).zip(sockets) {
+            if self.cookie(index)? != Some(socket_cookie(socket.as_raw_fd())?) {
+                stale.push(index);
+            }
+        }
+        Ok(stale)
+    }
+
+    fn check_len(&self, len: usize) -> Result<(), MapError> {
+        let max_entries = self.inner.as_ref().obj.max_entries();
+        if len > max_entries as usize {
+            return Err(MapError::OutOfBounds {
+                index: len as u32 - 1,
+                max_entries,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<T: AsMut<MapData>> ReusePortSockArray<T> {
+    /// Stores a socket into the array.
+    pub fn set<S: AsRawFd>(&mut self, index: u32, socket: &S, flags: u64) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        let fd = data.fd_or_err()?;
+        check_bounds(data, index)?;
+        let socket = socket.as_raw_fd();
+        let ret = if data.obj.value_size() as usize == mem::size_of::<u64>() {
+            bpf_map_update_elem(fd, Some(&index), &(socket as u64), flags)
+        } else {
+            bpf_map_update_elem(fd, Some(&index), &(socket as u32), flags)
+        };
+        ret.map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_update_elem".to_owned(),
+            io_error,
+        })?;
+        Ok(())
+    }
+
+    /// Removes the socket stored at `index` from the array.
+    ///
+    /// Clearing an empty index isn't an error.
+    pub fn clear_index(&mut self, index: &u32) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        let fd = data.fd_or_err()?;
+        check_bounds(data, *index)?;
+        match bpf_map_delete_elem(fd, index) {
+            Ok(_) => Ok(()),
+            Err((_, io_error)) if io_error.raw_os_error() == Some(libc::ENOENT) => Ok(()),
+            Err((_, io_error)) => Err(MapError::SyscallError {
+                call: "bpf_map_delete_elem".to_owned(),
+                io_error,
+            }),
+        }
+    }
+
+    /// Stores `sockets` at the indices matching their position in the slice
+    /// and clears the remaining indices.
+    ///
+    /// The kernel refuses to store a socket that is already in a reuseport
+    /// array, so sockets already stored at their index are skipped. This
+    /// requires looking up the stored sockets, so with `u32` values the array
+    /// can only be populated once, and with `u64` values a socket can't move
+    /// to another index without clearing its current index first.
+    pub fn populate<S: AsRawFd>(&mut self, sockets: &[S]) -> Result<(), MapError>
+    where
+        T: AsRef<MapData>,
+    {
+        self.check_len(sockets.len())?;
+        let lookups = self.inner.as_ref().obj.value_size() as usize == mem::size_of::<u64>();
+        for (index, socket) in (0..).zip(sockets) {
+            if lookups {
+                if let Some(cookie) = self.cookie(index)? {
+                    if cookie == socket_cookie(socket.as_raw_fd())? {
+                        continue;
+                    }
+                }
+            }
+            self.set(index, socket, 0)?;
+        }
+        for index in sockets.len() as u32..self.inner.as_ref().obj.max_entries() {
+            self.clear_index(&index)?;
+        }
+        Ok(())
+    }
+
+    /// Stores again the sockets of `sockets` that aren't at their index in
+    /// the array and returns their indices.
+    ///
+    /// This is meant to be called after some of the sockets previously passed
+    /// to [`ReusePortSockArray::populate`] were rebuilt. The array must have
+    /// `u64` values.
+    pub fn revalidate<S: AsRawFd>(&mut self, sockets: &[S]) -> Result<Vec<u32>, MapError>
+    where
+        T: AsRef<MapData>,
+    {
+        let stale = self.stale_indices(sockets)?;
+        for index in &stale {
+            self.set(*index, &sockets[*index as usize], 0)?;
+        }
+        Ok(stale)
+    }
+}
+
+fn socket_cookie(fd: RawFd) -> Result<u64, MapError> {
+    let mut cookie = 0u64;
+    let mut len = mem::size_of::<u64>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            SO_COOKIE,
+            &mut cookie as *mut _ as *mut _,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(MapError::SyscallError {
+            call: "getsockopt".to_owned(),
+            io_error: io::Error::last_os_error(),
+        });
+    }
+    Ok(cookie)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io, net::UdpSocket};
+
+    use libc::{EFAULT, ENOENT};
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_REUSEPORT_SOCKARRAY},
+        obj,
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    thread_local! {
+        static CALLS: RefCell<Vec<(bpf_cmd, u32, Option<u64>)>> = const { RefCell::new(Vec::new()) };
+        // the cookies of the sockets stored in the fake array
+        static COOKIES: RefCell<Vec<(u32, u64)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct FakeSocket(RawFd);
+
+    impl AsRawFd for FakeSocket {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0
+        }
+    }
+
+    fn new_map(value_size: u32) -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(obj::LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_REUSEPORT_SOCKARRAY as u32,
+                    key_size: 4,
+                    value_size,
+                    max_entries: 4,
+                    ..Default::default()
+                },
+                section_index: 0,
+                data: Vec::new(),
+                kind: obj::MapKind::Other,
+                symbol_index: 0,
+                map_extra: 0,
+                numa_node: None,
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    unsafe fn record_call(call: Syscall) -> SysResult {
+        match call {
+            Syscall::Bpf { cmd, attr } => {
+                let u = &attr.__bindgen_anon_2;
+                let key = *(u.key as *const u32);
+                let value = match cmd {
+                    bpf_cmd::BPF_MAP_UPDATE_ELEM => Some(*(u.__bindgen_anon_1.value as *const u64)),
+                    _ => None,
+                };
+                CALLS.with(|calls| calls.borrow_mut().push((cmd, key, value)));
+                match cmd {
+                    bpf_cmd::BPF_MAP_LOOKUP_ELEM => {
+                        let cookie = COOKIES.with(|cookies| {
+                            cookies
+                                .borrow()
+                                .iter()
+                                .find(|(index, _)| *index == key)
+                                .map(|(_, cookie)| *cookie)
+                        });
+                        match cookie {
+                            Some(cookie) => {
+                                *(u.__bindgen_anon_1.value as *mut u64) = cookie;
+                                Ok(0)
+                            }
+                            None => Err((-1, io::Error::from_raw_os_error(ENOENT))),
+                        }
+                    }
+                    // the last index was never set
+                    bpf_cmd::BPF_MAP_DELETE_ELEM if key == 3 => {
+                        Err((-1, io::Error::from_raw_os_error(ENOENT)))
+                    }
+                    _ => Ok(0),
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        }
+    }
+
+    #[test]
+    fn test_wrong_value_size() {
+        let map = new_map(2);
+        assert!(matches!(
+            ReusePortSockArray::new(&map),
+            Err(MapError::InvalidValueSize {
+                size: 8,
+                expected: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_populate() {
+        override_syscall(record_call);
+        let mut map = new_map(8);
+        let mut array = ReusePortSockArray::new(&mut map).unwrap();
+
+        let sockets = [FakeSocket(10), FakeSocket(11)];
+        array.populate(&sockets).unwrap();
+        CALLS.with(|calls| {
+            assert_eq!(
+                *calls.borrow(),
+                vec![
+                    (bpf_cmd::BPF_MAP_LOOKUP_ELEM, 0, None),
+                    (bpf_cmd::BPF_MAP_UPDATE_ELEM, 0, Some(10)),
+                    (bpf_cmd::BPF_MAP_LOOKUP_ELEM, 1, None),
+                    (bpf_cmd::BPF_MAP_UPDATE_ELEM, 1, Some(11)),
+                    (bpf_cmd::BPF_MAP_DELETE_ELEM, 2, None),
+                    (bpf_cmd::BPF_MAP_DELETE_ELEM, 3, None),
+                ]
+            )
+        });
+
+        let too_many: Vec<_> = (0..5).map(FakeSocket).collect();
+        assert!(matches!(
+            array.populate(&too_many),
+            Err(MapError::OutOfBounds {
+                index: 4,
+                max_entries: 4
+            })
+        ));
+    }
+    #[test]
+    fn test_populate_again() {
+        override_syscall(record_call);
+        let mut map = new_map(8);
+        let mut array = ReusePortSockArray::new(&mut map).unwrap();
+
+        let sockets = [
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+        ];
+        // the first socket is already stored at its index, the kernel would
+        // fail to store it again with EBUSY
+        let cookie = socket_cookie(sockets[0].as_raw_fd()).unwrap();
+        COOKIES.with(|cookies| cookies.borrow_mut().push((0, cookie)));
+
+        array.populate(&sockets).unwrap();
+        CALLS.with(|calls| {
+            let updates: Vec<_> = calls
+                .borrow()
+                .iter()
+                .filter(|(cmd, _, _)| *cmd == bpf_cmd::BPF_MAP_UPDATE_ELEM)
+                .map(|(_, index, _)| *index)
+                .collect();
+            assert_eq!(updates, vec![1]);
+        });
+    }
+}
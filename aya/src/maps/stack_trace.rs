@@ -173,7 +173,9 @@ impl StackTrace {
     ///
     /// You can use [`util::kernel_symbols()`](crate::util::kernel_symbols) to load kernel symbols. For
     /// user-space traces you need to provide the symbols, for example loading
-    /// them from debug info.
+    /// them from debug info. For symbols with a known size, like the JIT
+    /// compiled code loaded by [`util::perf_map_symbols()`](crate::util::perf_map_symbols),
+    /// see [`StackTrace::resolve_sized`].
     pub fn resolve(&mut self, symbols: &BTreeMap<u64, String>) -> &StackTrace {
         for frame in self.frames.iter_mut() {
             frame.symbol_name = symbols
@@ -185,6 +187,24 @@ impl StackTrace {
         self
     }
 
+    /// Resolves symbol names using the given map of start address to
+    /// `(size, name)`.
+    ///
+    /// Unlike [`StackTrace::resolve`], a frame is only resolved if its address
+    /// falls within the size of the closest symbol below it, so frames in
+    /// code that isn't in the map aren't attributed to the previous function.
+    pub fn resolve_sized(&mut self, symbols: &BTreeMap<u64, (u64, String)>) -> &StackTrace {
+        for frame in self.frames.iter_mut() {
+            frame.symbol_name = symbols
+                .range(..=frame.ip)
+                .next_back()
+                .filter(|(start, (size, _))| frame.ip - *start < *size)
+                .map(|(_, (_, s))| s.clone())
+        }
+
+        self
+    }
+
     /// Returns the frames in this stack trace.
     pub fn frames(&self) -> &[StackFrame] {
         &self.frames
@@ -208,3 +228,34 @@ fn sysctl<T: FromStr>(key: &str) -> Result<T, io::Error> {
         .parse::<T>()
         .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, val))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_sized() {
+        let mut trace = StackTrace {
+            id: 1,
+            frames: [0x1000, 0x103f, 0x1040, 0x2010, 0x500]
+                .into_iter()
+                .map(|ip| StackFrame {
+                    ip,
+                    symbol_name: None,
+                })
+                .collect(),
+        };
+        let symbols = BTreeMap::from([
+            (0x1000, (0x40, "foo".to_owned())),
+            (0x2000, (0x20, "bar".to_owned())),
+        ]);
+
+        let names = trace
+            .resolve_sized(&symbols)
+            .frames()
+            .iter()
+            .map(|frame| frame.symbol_name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("foo"), Some("foo"), None, Some("bar"), None]);
+    }
+}
@@ -0,0 +1,218 @@
+//! Keeping redirect maps pointing at interfaces given by name.
+use std::{collections::HashMap, io, time::Duration};
+
+use libc::{ENOENT, RTNLGRP_LINK};
+
+use crate::{
+    maps::{xdp::InterfaceMap, MapError},
+    sys::NetlinkMonitor,
+    util::ifindex_from_ifname,
+};
+
+/// Binds the entries of a [`DevMap`](crate::maps::xdp::DevMap) or a
+/// [`DevMapHash`](crate::maps::xdp::DevMapHash) to interfaces given by name.
+///
+/// Redirect maps store ifindexes, which change when an interface is removed
+/// and created again, for example when a VM or a container restarts. A
+/// binding resolves the names of the interfaces, and once watching the
+/// netlink link notifications, updates the entries whenever interfaces are
+/// added, removed or renamed. The entries of the interfaces that don't exist
+/// are removed.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::xdp::{DevMap, InterfaceBinding};
+///
+/// let mut devmap = DevMap::try_from(bpf.take_map("IFACES").unwrap())?;
+/// let mut binding = InterfaceBinding::new();
+/// binding.bind(0, "eth0").bind(1, "veth-vm1");
+/// binding.watch()?;
+/// binding.apply(&mut devmap)?;
+///
+/// std::thread::spawn(move || loop {
+///     if let Err(e) = binding.reconcile(&mut devmap, None) {
+///         eprintln!("failed to update the redirect map: {}", e);
+///     }
+/// });
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Default)]
+pub struct InterfaceBinding {
+    interfaces: Vec<(u32, String)>,
+    // the ifindex each key was last set to, None once the entry is cleared
+    bound: HashMap<u32, Option<u32>>,
+    monitor: Option<NetlinkMonitor>,
+}
+
+impl InterfaceBinding {
+    /// Creates a binding without any interfaces.
+    pub fn new() -> InterfaceBinding {
+        InterfaceBinding::default()
+    }
+
+    /// Binds the entry at `key` to the interface called `interface`.
+    pub fn bind(&mut self, key: u32, interface: &str) -> &mut InterfaceBinding {
+        self.interfaces.retain(|(k, _)| *k != key);
+        self.interfaces.push((key, interface.to_owned()));
+        self
+    }
+
+    /// Returns the ifindex the entry at `key` was last set to.
+    pub fn if_index(&self, key: u32) -> Option<u32> {
+        self.bound.get(&key).copied().flatten()
+    }
+
+    /// Subscribes to the netlink link notifications.
+    ///
+    /// Call this before the first [`InterfaceBinding::apply`], so that no
+    /// change happening in between is missed.
+    pub fn watch(&mut self) -> Result<(), MapError> {
+        if self.monitor.is_none() {
+            self.monitor = Some(NetlinkMonitor::new(RTNLGRP_LINK).map_err(|io_error| {
+                MapError::SyscallError {
+                    call: "netlink link monitor".to_owned(),
+                    io_error,
+                }
+            })?);
+        }
+        Ok(())
+    }
+
+    /// Resolves the names of the interfaces and updates the entries of
+    /// `map`. Returns whether any entry was changed.
+    pub fn apply<M: InterfaceMap>(&mut self, map: &mut M) -> Result<bool, MapError> {
+        let mut changed = false;
+        for (key, name) in &self.interfaces {
+            let if_index = ifindex_from_ifname(name).ok();
+            if self.bound.get(key) == Some(&if_index) {
+                continue;
+            }
+            match if_index {
+                Some(if_index) => {
+                    map.set_ifindex(*key, if_index)?;
+                }
+                None => match map.clear_ifindex(*key) {
+                    Err(MapError::SyscallError { io_error, .. })
+                        if io_error.raw_os_error() == Some(ENOENT) => {}
+                    ret => ret?,
+                },
+            }
+            self.bound.insert(*key, if_index);
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Waits for link notifications, then updates the entries of `map`.
+    ///
+    /// Returns whether any entry was changed, or `false` if `timeout` expired
+    /// without notifications. Watches the notifications if
+    /// [`InterfaceBinding::watch`] wasn't called.
+    pub fn reconcile<M: InterfaceMap>(
+        &mut self,
+        map: &mut M,
+        timeout: Option<Duration>,
+    ) -> Result<bool, MapError> {
+        self.watch()?;
+        let monitor = self.monitor.as_ref().unwrap();
+
+        let mut pollfd = libc::pollfd {
+            fd: monitor.fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.map_or(-1, |timeout| {
+            timeout.as_millis().min(i32::MAX as u128) as i32
+        });
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ret < 0 {
+            let io_error = io::Error::last_os_error();
+            if io_error.kind() != io::ErrorKind::Interrupted {
+                return Err(MapError::SyscallError {
+                    call: "poll".to_owned(),
+                    io_error,
+                });
+            }
+        }
+        if ret <= 0 {
+            return Ok(false);
+        }
+
+        // the content of the notifications doesn't matter, all the names are
+        // resolved again
+        loop {
+            match monitor.recv_links() {
+                Ok(events) if events.is_empty() => break,
+                Ok(_) => {}
+                // notifications were dropped, which the new resolution covers
+                Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {}
+                Err(io_error) => {
+                    return Err(MapError::SyscallError {
+                        call: "recv".to_owned(),
+                        io_error,
+                    })
+                }
+            }
+        }
+        self.apply(map)
+    }
+}
+
+impl std::fmt::Debug for InterfaceBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InterfaceBinding")
+            .field("interfaces", &self.interfaces)
+            .field("bound", &self.bound)
+            .field("watching", &self.monitor.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::xdp::sealed::Sealed;
+
+    #[derive(Default)]
+    struct FakeMap {
+        entries: HashMap<u32, u32>,
+    }
+
+    impl Sealed for FakeMap {
+        fn set_ifindex(&mut self, key: u32, if_index: u32) -> Result<(), MapError> {
+            self.entries.insert(key, if_index);
+            Ok(())
+        }
+
+        fn clear_ifindex(&mut self, key: u32) -> Result<(), MapError> {
+            match self.entries.remove(&key) {
+                Some(_) => Ok(()),
+                None => Err(MapError::SyscallError {
+                    call: "bpf_map_delete_elem".to_owned(),
+                    io_error: io::Error::from_raw_os_error(ENOENT),
+                }),
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let lo = ifindex_from_ifname("lo").unwrap();
+        let mut map = FakeMap::default();
+        map.entries.insert(1, 1234);
+
+        let mut binding = InterfaceBinding::new();
+        binding.bind(0, "lo").bind(1, "does-not-exist");
+        assert!(binding.apply(&mut map).unwrap());
+        assert_eq!(map.entries.get(&0), Some(&lo));
+        // the interface doesn't exist, the entry is removed
+        assert_eq!(map.entries.get(&1), None);
+        assert_eq!(binding.if_index(0), Some(lo));
+        assert_eq!(binding.if_index(1), None);
+
+        // nothing changed
+        assert!(!binding.apply(&mut map).unwrap());
+    }
+}
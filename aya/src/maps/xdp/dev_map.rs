@@ -0,0 +1,117 @@
+//! An array of network devices.
+use std::convert::{AsMut, AsRef};
+
+use crate::{
+    maps::{
+        check_bounds,
+        xdp::{check_value_size, lookup_ifindex, sealed::Sealed},
+        MapData, MapError, MapKeys,
+    },
+    sys::{bpf_map_delete_elem, bpf_map_update_elem_ptr},
+};
+
+/// An array of network devices, used by XDP programs to redirect packets.
+///
+/// XDP programs redirect packets to the interface stored at an index of the
+/// array with the `bpf_redirect_map` helper. The entries are the indexes of
+/// the interfaces, see [`InterfaceBinding`](crate::maps::xdp::InterfaceBinding)
+/// to keep them pointing at interfaces given by name.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.14.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::xdp::DevMap;
+///
+/// let mut devmap = DevMap::try_from(bpf.map_mut("IFACES").unwrap())?;
+/// // redirect the packets sent to index 0 to the interface with ifindex 2
+/// devmap.set(0, 2, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_DEVMAP")]
+pub struct DevMap<T> {
+    inner: T,
+}
+
+impl<T: AsRef<MapData>> DevMap<T> {
+    pub(crate) fn new(map: T) -> Result<DevMap<T>, MapError> {
+        let data = map.as_ref();
+        let key_size = data.obj.key_size() as usize;
+        if key_size != 4 {
+            return Err(MapError::InvalidKeySize {
+                size: key_size,
+                expected: 4,
+            });
+        }
+        check_value_size(data)?;
+        let _fd = data.fd_or_err()?;
+
+        Ok(DevMap { inner: map })
+    }
+
+    /// Returns the number of elements in the array.
+    ///
+    /// This corresponds to the value of `bpf_map_def::max_entries` on the eBPF side.
+    pub fn len(&self) -> u32 {
+        self.inner.as_ref().obj.max_entries()
+    }
+
+    /// Returns the ifindex of the interface stored at `index`, or `None` if
+    /// the entry is empty.
+    pub fn get(&self, index: u32, flags: u64) -> Result<Option<u32>, MapError> {
+        let data = self.inner.as_ref();
+        check_bounds(data, index)?;
+        lookup_ifindex(data, &index, flags)
+    }
+
+    /// An iterator over the indices of the array, including the ones that
+    /// don't point to an interface, see [`get`](DevMap::get).
+    /// The iterator item type is `Result<u32, MapError>`.
+    pub fn indices(&self) -> MapKeys<'_, u32> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>> DevMap<T> {
+    /// Stores the ifindex of an interface at `index`.
+    pub fn set(&mut self, index: u32, if_index: u32, flags: u64) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        check_bounds(data, index)?;
+        let fd = data.fd_or_err()?;
+        let mut value = [if_index, 0];
+        bpf_map_update_elem_ptr(fd, &index, value.as_mut_ptr(), flags).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Removes the interface stored at `index`.
+    pub fn clear_index(&mut self, index: &u32) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        check_bounds(data, *index)?;
+        let fd = data.fd_or_err()?;
+        bpf_map_delete_elem(fd, index)
+            .map(|_| ())
+            .map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_delete_elem".to_owned(),
+                io_error,
+            })
+    }
+}
+
+impl<T: AsMut<MapData>> Sealed for DevMap<T> {
+    fn set_ifindex(&mut self, key: u32, if_index: u32) -> Result<(), MapError> {
+        self.set(key, if_index, 0)
+    }
+
+    fn clear_ifindex(&mut self, key: u32) -> Result<(), MapError> {
+        self.clear_index(&key)
+    }
+}
@@ -0,0 +1,94 @@
+//! A hash map of network devices.
+use std::convert::{AsMut, AsRef};
+
+use crate::{
+    maps::{
+        check_kv_size, hash_map,
+        xdp::{check_value_size, lookup_ifindex, sealed::Sealed},
+        MapData, MapError, MapKeys,
+    },
+    sys::bpf_map_update_elem_ptr,
+};
+
+/// A hash map of network devices, used by XDP programs to redirect packets.
+///
+/// Like a [`DevMap`](crate::maps::xdp::DevMap), but the entries are
+/// identified by arbitrary `u32` keys, for example the ifindexes of the
+/// interfaces themselves, instead of indexes in an array.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.4.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::xdp::DevMapHash;
+///
+/// let mut devmap = DevMapHash::try_from(bpf.map_mut("IFACES").unwrap())?;
+/// devmap.insert(2, 2, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_DEVMAP_HASH")]
+pub struct DevMapHash<T> {
+    inner: T,
+}
+
+impl<T: AsRef<MapData>> DevMapHash<T> {
+    pub(crate) fn new(map: T) -> Result<DevMapHash<T>, MapError> {
+        let data = map.as_ref();
+        check_value_size(data)?;
+        if data.obj.value_size() == 4 {
+            check_kv_size::<u32, u32>(data)?;
+        } else {
+            check_kv_size::<u32, u64>(data)?;
+        }
+        let _fd = data.fd_or_err()?;
+
+        Ok(DevMapHash { inner: map })
+    }
+
+    /// Returns the ifindex of the interface stored at `key`, or `None` if
+    /// there's no such entry.
+    pub fn get(&self, key: u32, flags: u64) -> Result<Option<u32>, MapError> {
+        lookup_ifindex(self.inner.as_ref(), &key, flags)
+    }
+
+    /// An iterator over the keys of the map. The iterator item type is
+    /// `Result<u32, MapError>`.
+    pub fn keys(&self) -> MapKeys<'_, u32> {
+        MapKeys::new(self.inner.as_ref())
+    }
+}
+
+impl<T: AsMut<MapData>> DevMapHash<T> {
+    /// Stores the ifindex of an interface at `key`.
+    pub fn insert(&mut self, key: u32, if_index: u32, flags: u64) -> Result<(), MapError> {
+        let data = self.inner.as_mut();
+        let fd = data.fd_or_err()?;
+        let mut value = [if_index, 0];
+        bpf_map_update_elem_ptr(fd, &key, value.as_mut_ptr(), flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Removes the interface stored at `key`.
+    pub fn remove(&mut self, key: &u32) -> Result<(), MapError> {
+        hash_map::remove(self.inner.as_mut(), key)
+    }
+}
+
+impl<T: AsMut<MapData>> Sealed for DevMapHash<T> {
+    fn set_ifindex(&mut self, key: u32, if_index: u32) -> Result<(), MapError> {
+        self.insert(key, if_index, 0)
+    }
+
+    fn clear_ifindex(&mut self, key: u32) -> Result<(), MapError> {
+        self.remove(&key)
+    }
+}
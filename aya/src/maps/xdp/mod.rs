@@ -0,0 +1,54 @@
+//! XDP redirect maps.
+mod binding;
+mod dev_map;
+mod dev_map_hash;
+
+pub use binding::InterfaceBinding;
+pub use dev_map::DevMap;
+pub use dev_map_hash::DevMapHash;
+
+use crate::{
+    maps::{MapData, MapError},
+    sys::bpf_map_lookup_elem_ptr,
+};
+
+// The value of DEVMAP and DEVMAP_HASH entries, either the ifindex alone or a
+// struct bpf_devmap_val whose program fd is set to 0, meaning no program.
+const DEVMAP_VALUE_SIZES: [u32; 2] = [4, 8];
+
+fn check_value_size(map: &MapData) -> Result<(), MapError> {
+    let size = map.obj.value_size();
+    if !DEVMAP_VALUE_SIZES.contains(&size) {
+        return Err(MapError::InvalidValueSize {
+            size: size as usize,
+            expected: 4,
+        });
+    }
+    Ok(())
+}
+
+fn lookup_ifindex(map: &MapData, key: &u32, flags: u64) -> Result<Option<u32>, MapError> {
+    let fd = map.fd_or_err()?;
+    let mut value = [0u32; 2];
+    bpf_map_lookup_elem_ptr(fd, Some(key), value.as_mut_ptr(), flags)
+        .map(|found| found.map(|_| value[0]))
+        .map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_lookup_elem".to_owned(),
+            io_error,
+        })
+}
+
+pub(crate) mod sealed {
+    use crate::maps::MapError;
+
+    pub trait Sealed {
+        fn set_ifindex(&mut self, key: u32, if_index: u32) -> Result<(), MapError>;
+        fn clear_ifindex(&mut self, key: u32) -> Result<(), MapError>;
+    }
+}
+
+/// A map whose entries point to interfaces, which can be updated by an
+/// [`InterfaceBinding`].
+pub trait InterfaceMap: sealed::Sealed {}
+
+impl<T: sealed::Sealed> InterfaceMap for T {}
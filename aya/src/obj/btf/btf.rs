@@ -6,6 +6,7 @@ use std::{
     fs, io, mem,
     path::{Path, PathBuf},
     ptr,
+    sync::{Arc, Mutex},
 };
 
 use bytes::BufMut;
@@ -158,6 +159,10 @@ pub enum BtfError {
     InvalidSymbolName,
 }
 
+lazy_static! {
+    static ref SYS_FS_BTF: Mutex<Option<Arc<Btf>>> = Mutex::new(None);
+}
+
 /// Bpf Type Format metadata.
 ///
 /// BTF is a kind of debug metadata that allows eBPF programs compiled against one kernel version
@@ -214,11 +219,47 @@ impl Btf {
         type_id as u32
     }
 
+    /// Appends the types and strings of `other`, returning the offsets that
+    /// were added to its type ids and string offsets.
+    ///
+    /// Types aren't deduplicated, `other`'s types just get new ids after the
+    /// existing ones.
+    pub(crate) fn append(&mut self, other: &Btf) -> (u32, u32) {
+        // type 0 is void in both, so the first type of `other` gets the id
+        // following the last existing type
+        let type_offset = self.types.len() as u32 - 1;
+        let string_offset = self.strings.len() as u32;
+        self.strings.extend(&other.strings);
+        self.header.str_len = self.strings.len() as u32;
+        for ty in other.types().skip(1) {
+            let mut ty = ty.clone();
+            ty.remap(type_offset, string_offset);
+            self.add_type(ty);
+        }
+        (type_offset, string_offset)
+    }
+
     /// Loads BTF metadata from `/sys/kernel/btf/vmlinux`.
     pub fn from_sys_fs() -> Result<Btf, BtfError> {
         Btf::parse_file("/sys/kernel/btf/vmlinux", Endianness::default())
     }
 
+    /// Loads BTF metadata from `/sys/kernel/btf/vmlinux`, parsing it at
+    /// most once per process.
+    ///
+    /// The first successful call parses the kernel BTF and every following
+    /// call returns the same instance. Errors aren't cached, so a failed call
+    /// is retried the next time.
+    pub fn from_sys_fs_cached() -> Result<Arc<Btf>, BtfError> {
+        let mut cached = SYS_FS_BTF.lock().unwrap();
+        if let Some(btf) = &*cached {
+            return Ok(btf.clone());
+        }
+        let btf = Arc::new(Btf::from_sys_fs()?);
+        *cached = Some(btf.clone());
+        Ok(btf)
+    }
+
     /// Loads BTF metadata from the given `path`.
     pub fn parse_file<P: AsRef<Path>>(path: P, endianness: Endianness) -> Result<Btf, BtfError> {
         let path = path.as_ref();
@@ -721,6 +762,22 @@ impl BtfExt {
         self.relocations.iter()
     }
 
+    /// Shifts the type ids and string offsets of the CO-RE relocations, when
+    /// the BTF they refer to is appended to another with [`Btf::append`].
+    pub(crate) fn remap(&mut self, type_offset: u32, string_offset: u32) {
+        for (sec_name_offset, relos) in &mut self.relocations {
+            *sec_name_offset += string_offset;
+            relos
+                .iter_mut()
+                .for_each(|rel| rel.remap(type_offset, string_offset));
+        }
+    }
+
+    /// Appends the CO-RE relocations of `other`.
+    pub(crate) fn append_relocations(&mut self, other: BtfExt) {
+        self.relocations.extend(other.relocations);
+    }
+
     pub(crate) fn func_info_rec_size(&self) -> usize {
         self.func_info_rec_size
     }
@@ -982,6 +1039,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_append_btf() {
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            4,
+            IntEncoding::Signed,
+            0,
+        )));
+
+        let mut other = Btf::new();
+        let name_offset = other.add_string("u8".to_string());
+        let u8_type_id =
+            other.add_type(BtfType::Int(Int::new(name_offset, 1, IntEncoding::None, 0)));
+        let name_offset = other.add_string("ptr".to_string());
+        other.add_type(BtfType::Ptr(Ptr::new(name_offset, u8_type_id)));
+
+        let (type_offset, string_offset) = btf.append(&other);
+        assert_eq!(type_offset, 1);
+        assert_eq!(string_offset, 5);
+
+        let btf = Btf::parse(&btf.to_bytes(), Endianness::default()).unwrap();
+        assert_eq!(btf.type_name(btf.type_by_id(1).unwrap()).unwrap(), "int");
+        assert_eq!(btf.type_name(btf.type_by_id(2).unwrap()).unwrap(), "u8");
+        match btf.type_by_id(3).unwrap() {
+            BtfType::Ptr(ptr) => {
+                assert_eq!(btf.string_at(ptr.name_offset).unwrap(), "ptr");
+                assert_eq!(ptr.btf_type, 2);
+            }
+            t => panic!("expected ptr, got {:#?}", t),
+        }
+    }
+
     #[test]
     fn test_fixup_ptr() {
         let mut btf = Btf::new();
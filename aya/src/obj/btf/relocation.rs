@@ -71,6 +71,13 @@ pub enum RelocationError {
         index: usize,
         error: String,
     },
+
+    #[error("relocation #{relocation_number}: `{access}` not found in the target BTF\n{layouts}")]
+    NoMatchingTarget {
+        relocation_number: usize,
+        access: String,
+        layouts: String,
+    },
 }
 
 fn err_type_name(name: &Option<String>) -> String {
@@ -118,6 +125,22 @@ impl TryFrom<u32> for RelocationKind {
     }
 }
 
+impl RelocationKind {
+    // Returns whether the relocation can only be computed against a matching target type.
+    fn needs_target(&self) -> bool {
+        use RelocationKind::*;
+        matches!(
+            self,
+            FieldByteOffset
+                | FieldByteSize
+                | FieldSigned
+                | FieldLShift64
+                | FieldRShift64
+                | EnumVariantValue
+        )
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Relocation {
     kind: RelocationKind,
@@ -144,9 +167,25 @@ impl Relocation {
             number,
         })
     }
+
+    /// Shifts the type id and access string offset of the relocation, see
+    /// [`Btf::append`].
+    pub(crate) fn remap(&mut self, type_offset: u32, string_offset: u32) {
+        self.type_id += type_offset;
+        self.access_str_offset += string_offset;
+    }
 }
 
 impl Object {
+    pub(crate) fn has_btf_relocations(&self) -> bool {
+        self.btf.is_some()
+            && self
+                .btf_ext
+                .as_ref()
+                .map(|ext| ext.relocations().any(|(_, relos)| !relos.is_empty()))
+                .unwrap_or(false)
+    }
+
     pub fn relocate_btf(&mut self, target_btf: &Btf) -> Result<(), BpfError> {
         let (local_btf, btf_ext) = match (&self.btf, &self.btf_ext) {
             (Some(btf), Some(btf_ext)) => (btf, btf_ext),
@@ -238,6 +277,15 @@ fn relocate_btf_program<'target>(
             }
         };
 
+        if matches.is_empty() && rel.kind.needs_target() {
+            return Err(RelocationError::NoMatchingTarget {
+                relocation_number: rel.number,
+                access: access_path(local_btf, &local_spec)?,
+                layouts: layout_diff(&local_spec, target_btf)?,
+            }
+            .into());
+        }
+
         let comp_rel = if !matches.is_empty() {
             let mut matches = matches.into_iter();
             let (_, target_spec, target_comp_rel) = matches.next().unwrap();
@@ -276,6 +324,110 @@ fn relocate_btf_program<'target>(
     Ok(())
 }
 
+// Formats the access described by a spec, eg `task_struct.se.vruntime`.
+fn access_path(btf: &Btf, spec: &AccessSpec) -> Result<String, BtfError> {
+    let root = btf.type_by_id(spec.root_type_id)?;
+    let mut path = err_type_name(&btf.err_type_name(root));
+    for (i, accessor) in spec.accessors.iter().enumerate() {
+        match &accessor.name {
+            Some(name) => {
+                path.push('.');
+                path.push_str(name);
+            }
+            // the first accessor indexes the root pointer, only show it if it's not 0
+            None if i == 0 && accessor.index == 0 => {}
+            None => path.push_str(&format!("[{}]", accessor.index)),
+        }
+    }
+    Ok(path)
+}
+
+// Renders the local layout of every type traversed by `local_spec` next to the layout of the
+// type with the same name in the target BTF.
+fn layout_diff(local_spec: &AccessSpec, target_btf: &Btf) -> Result<String, BtfError> {
+    let local_btf = local_spec.btf;
+    let mut type_ids = vec![local_btf.resolve_type(local_spec.root_type_id)?];
+    for accessor in &local_spec.accessors {
+        if accessor.name.is_some() && !type_ids.contains(&accessor.type_id) {
+            type_ids.push(accessor.type_id);
+        }
+    }
+
+    let mut out = String::new();
+    for type_id in type_ids {
+        let local_ty = local_btf.type_by_id(type_id)?;
+        out.push_str("local:  ");
+        out.push_str(&describe_type(local_btf, type_id)?);
+
+        let name = local_btf.type_name(local_ty)?;
+        let target = if name.is_empty() {
+            None
+        } else {
+            find_candidates(local_ty, &name, target_btf)?
+                .into_iter()
+                .next()
+        };
+        out.push_str("target: ");
+        match target {
+            Some(candidate) => out.push_str(&describe_type(target_btf, candidate.type_id)?),
+            None => out.push_str(&format!(
+                "{} not found\n",
+                err_type_name(&local_btf.err_type_name(local_ty))
+            )),
+        }
+    }
+
+    Ok(out)
+}
+
+// Describes a type and, for structs, unions and enums, the offset and size of its members.
+fn describe_type(btf: &Btf, type_id: u32) -> Result<String, BtfError> {
+    let ty = btf.type_by_id(type_id)?;
+    let name = err_type_name(&btf.err_type_name(ty));
+    let mut out = match ty.size() {
+        Some(size) => format!("{} {} (size {})\n", ty.kind(), name, size),
+        None => format!("{} {}\n", ty.kind(), name),
+    };
+    match ty {
+        BtfType::Struct(_) | BtfType::Union(_) => {
+            for member in ty.members().unwrap() {
+                let member_name = btf.string_at(member.name_offset)?;
+                let member_name = if member_name.is_empty() {
+                    "[anonymous]"
+                } else {
+                    &member_name
+                };
+                let bit_offset = ty.member_bit_offset(member).unwrap();
+                let bit_size = ty.member_bit_field_size(member).unwrap();
+                if bit_size > 0 {
+                    out.push_str(&format!(
+                        "    bit offset {:<6} bits {:<6} {}\n",
+                        bit_offset, bit_size, member_name
+                    ));
+                } else {
+                    out.push_str(&format!(
+                        "    offset {:<10} size {:<6} {}\n",
+                        bit_offset / 8,
+                        btf.type_size(member.btf_type).unwrap_or(0),
+                        member_name
+                    ));
+                }
+            }
+        }
+        BtfType::Enum(en) => {
+            for variant in &en.variants {
+                out.push_str(&format!(
+                    "    {} = {}\n",
+                    btf.string_at(variant.name_offset)?,
+                    variant.value
+                ));
+            }
+        }
+        _ => {}
+    }
+    Ok(out)
+}
+
 fn flavorless_name(name: &str) -> &str {
     name.split_once("___").map_or(name, |x| x.0)
 }
@@ -1068,3 +1220,130 @@ enum ErrorWrapper {
     #[error(transparent)]
     RelocationError(#[from] RelocationError),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+    use crate::obj::{
+        btf::{Int, IntEncoding},
+        Function, KernelVersion, SectionIndex,
+    };
+
+    // Adds `struct foo` with an int member for each of `members`.
+    fn add_foo(btf: &mut Btf, members: &[&str]) -> u32 {
+        let int_name = btf.add_string("int".to_string());
+        let int_type_id = btf.add_type(BtfType::Int(Int::new(int_name, 4, IntEncoding::Signed, 0)));
+        let members = members
+            .iter()
+            .enumerate()
+            .map(|(i, name)| BtfMember {
+                name_offset: btf.add_string(name.to_string()),
+                btf_type: int_type_id,
+                offset: i as u32 * 32,
+            })
+            .collect::<Vec<_>>();
+        let size = members.len() as u32 * 4;
+        let name = btf.add_string("foo".to_string());
+        btf.add_type(BtfType::Struct(Struct::new(name, members, size)))
+    }
+
+    #[test]
+    fn test_describe_type() {
+        let mut btf = Btf::new();
+        let int_name = btf.add_string("int".to_string());
+        let int_type_id = btf.add_type(BtfType::Int(Int::new(int_name, 4, IntEncoding::Signed, 0)));
+        let a = btf.add_string("a".to_string());
+        let b = btf.add_string("b".to_string());
+        let name = btf.add_string("foo".to_string());
+        let struct_type_id = btf.add_type(BtfType::Struct(Struct::new(
+            name,
+            vec![
+                BtfMember {
+                    name_offset: a,
+                    btf_type: int_type_id,
+                    offset: 0,
+                },
+                BtfMember {
+                    name_offset: b,
+                    btf_type: int_type_id,
+                    offset: 32,
+                },
+            ],
+            8,
+        )));
+
+        assert_eq!(
+            describe_type(&btf, struct_type_id).unwrap(),
+            "[STRUCT] foo (size 8)\n    offset 0          size 4      a\n    offset 4          size 4      b\n"
+        );
+    }
+
+    #[test]
+    fn test_no_matching_target() {
+        let mut local_btf = Btf::new();
+        let type_id = add_foo(&mut local_btf, &["a", "b"]);
+        // foo.b
+        let access_str_offset = local_btf.add_string("0:1".to_string());
+        let mut target_btf = Btf::new();
+        add_foo(&mut target_btf, &["a"]);
+
+        let mut program = Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section: ProgramSection::KProbe {
+                name: "test".to_string(),
+            },
+            function: Function {
+                address: 0,
+                name: "test".to_string(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: vec![unsafe { mem::zeroed() }],
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+            autoload: true,
+        };
+        let rel = Relocation {
+            kind: RelocationKind::FieldByteOffset,
+            ins_offset: 0,
+            type_id,
+            access_str_offset,
+            number: 3,
+        };
+
+        let err = relocate_btf_program(
+            &mut program,
+            &[rel],
+            &local_btf,
+            &target_btf,
+            &mut HashMap::new(),
+        )
+        .unwrap_err();
+        match err {
+            ErrorWrapper::RelocationError(
+                ref error @ RelocationError::NoMatchingTarget {
+                    relocation_number,
+                    ref access,
+                    ref layouts,
+                },
+            ) => {
+                assert_eq!(relocation_number, 3);
+                assert_eq!(access, "foo.b");
+                assert_eq!(
+                    layouts,
+                    "local:  [STRUCT] foo (size 8)\n    offset 0          size 4      a\n    offset 4          size 4      b\n\
+                     target: [STRUCT] foo (size 4)\n    offset 0          size 4      a\n"
+                );
+                assert!(error
+                    .to_string()
+                    .starts_with("relocation #3: `foo.b` not found in the target BTF\nlocal:  "));
+            }
+            err => panic!("unexpected error: {err}"),
+        }
+    }
+}
@@ -415,6 +415,12 @@ impl Enum {
         BtfKind::Enum
     }
 
+    // The kind flag of enums tells whether the values are signed, older
+    // compilers leave it unset for all enums.
+    pub(crate) fn is_signed(&self) -> bool {
+        self.info >> 31 == 1
+    }
+
     pub(crate) fn type_info_size(&self) -> usize {
         mem::size_of::<Fwd>() + mem::size_of::<BtfEnum>() * self.variants.len()
     }
@@ -1122,6 +1128,101 @@ impl BtfType {
         }
     }
 
+    /// Shifts the type ids and string offsets referenced by the type.
+    ///
+    /// This is used when the types of a BTF blob are appended to another, so
+    /// that they keep referencing their own types and strings. Offset 0 (the
+    /// empty string) and type id 0 (void) are shared and never shifted.
+    pub(crate) fn remap(&mut self, type_offset: u32, string_offset: u32) {
+        let ty = |id: &mut u32| {
+            if *id != 0 {
+                *id += type_offset
+            }
+        };
+        let name = |off: &mut u32| {
+            if *off != 0 {
+                *off += string_offset
+            }
+        };
+        match self {
+            BtfType::Unknown => {}
+            BtfType::Fwd(t) => name(&mut t.name_offset),
+            BtfType::Const(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+            BtfType::Volatile(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+            BtfType::Restrict(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+            BtfType::Ptr(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+            BtfType::Typedef(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+            BtfType::Func(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+            BtfType::Int(t) => name(&mut t.name_offset),
+            BtfType::Float(t) => name(&mut t.name_offset),
+            BtfType::Enum(t) => {
+                name(&mut t.name_offset);
+                t.variants.iter_mut().for_each(|v| name(&mut v.name_offset));
+            }
+            BtfType::Array(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.array.element_type);
+                ty(&mut t.array.index_type);
+            }
+            BtfType::Struct(t) => {
+                name(&mut t.name_offset);
+                for m in &mut t.members {
+                    name(&mut m.name_offset);
+                    ty(&mut m.btf_type);
+                }
+            }
+            BtfType::Union(t) => {
+                name(&mut t.name_offset);
+                for m in &mut t.members {
+                    name(&mut m.name_offset);
+                    ty(&mut m.btf_type);
+                }
+            }
+            BtfType::FuncProto(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.return_type);
+                for p in &mut t.params {
+                    name(&mut p.name_offset);
+                    ty(&mut p.btf_type);
+                }
+            }
+            BtfType::Var(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+            BtfType::DataSec(t) => {
+                name(&mut t.name_offset);
+                t.entries.iter_mut().for_each(|e| ty(&mut e.btf_type));
+            }
+            BtfType::DeclTag(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+            BtfType::TypeTag(t) => {
+                name(&mut t.name_offset);
+                ty(&mut t.btf_type);
+            }
+        }
+    }
+
     pub(crate) fn kind(&self) -> BtfKind {
         match self {
             BtfType::Unknown => BtfKind::Unknown,
@@ -0,0 +1,414 @@
+use std::collections::{HashMap, HashSet};
+
+use log::debug;
+use object::{SectionIndex, SymbolKind};
+
+use crate::{
+    generated::bpf_insn,
+    obj::{
+        relocation::{insn_is_call, INS_SIZE},
+        Function, Map, Object, ParseError,
+    },
+};
+
+impl Object {
+    /// Links `other` into this object, so that the programs of both objects
+    /// can be loaded together.
+    ///
+    /// The BTF of `other` is appended to ours, so that a single BTF blob is
+    /// loaded for all the programs. The `.text` functions of `other` are
+    /// appended after ours, except the functions that are identical to one
+    /// of ours: the programs of `other` call our copy instead, and share its
+    /// BTF func info.
+    ///
+    /// Programs, struct_ops and maps, including the global data sections,
+    /// can't be defined by both objects.
+    pub(crate) fn link(&mut self, mut other: Object) -> Result<(), ParseError> {
+        if self.endianness != other.endianness {
+            return Err(ParseError::EndiannessMismatch);
+        }
+        if let Some(name) = other
+            .programs
+            .keys()
+            .find(|name| self.programs.contains_key(*name))
+            .or_else(|| other.maps.keys().find(|name| self.maps.contains_key(*name)))
+            .or_else(|| {
+                other
+                    .struct_ops
+                    .keys()
+                    .find(|name| self.struct_ops.contains_key(*name))
+            })
+        {
+            return Err(ParseError::DuplicateDefinition {
+                name: name.to_owned(),
+            });
+        }
+
+        let (type_offset, string_offset) = match (&mut self.btf, other.btf.take()) {
+            (Some(btf), Some(other_btf)) => btf.append(&other_btf),
+            (None, other_btf) => {
+                self.btf = other_btf;
+                (0, 0)
+            }
+            (Some(_), None) => (0, 0),
+        };
+        if let Some(mut other_ext) = other.btf_ext.take() {
+            other_ext.remap(type_offset, string_offset);
+            match &mut self.btf_ext {
+                Some(ext) => ext.append_relocations(other_ext),
+                None => self.btf_ext = Some(other_ext),
+            }
+        }
+
+        // the sections and symbols of `other` are renumbered after ours,
+        // except its .text section which is appended to ours
+        let section_offset = self
+            .symbols_by_index
+            .values()
+            .filter_map(|s| s.section_index)
+            .chain(self.relocations.keys().map(|s| s.0))
+            .chain(self.programs.values().map(|p| p.function.section_index.0))
+            .chain(self.maps.values().map(|m| m.section_index()))
+            .max()
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let symbol_offset = self
+            .symbols_by_index
+            .keys()
+            .max()
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let text_offset = self
+            .functions
+            .values()
+            .map(|f| f.address + (f.instructions.len() * INS_SIZE) as u64)
+            .max()
+            .unwrap_or(0);
+        let other_text = other.text_section_index;
+        let text_section_index = match (self.text_section_index, other_text) {
+            (Some(index), _) => Some(index),
+            (None, Some(index)) => Some(index + section_offset),
+            (None, None) => None,
+        };
+        let is_text = |index: usize| other_text == Some(index);
+        let map_section = |index: usize| {
+            if is_text(index) {
+                text_section_index.unwrap()
+            } else {
+                index + section_offset
+            }
+        };
+        self.text_section_index = text_section_index;
+
+        for (index, mut sym) in other.symbols_by_index.drain() {
+            if sym.section_index.map(is_text).unwrap_or(false) {
+                sym.address += text_offset;
+            }
+            sym.index += symbol_offset;
+            sym.section_index = sym.section_index.map(map_section);
+            self.symbols_by_index.insert(index + symbol_offset, sym);
+        }
+
+        for (SectionIndex(index), relocations) in other.relocations.drain() {
+            let shift = if is_text(index) { text_offset } else { 0 };
+            self.relocations
+                .entry(SectionIndex(map_section(index)))
+                .or_default()
+                .extend(relocations.into_values().map(|mut rel| {
+                    rel.offset += shift;
+                    rel.symbol_index += symbol_offset;
+                    (rel.offset, rel)
+                }));
+        }
+
+        let remap_function = |fun: &mut Function| {
+            fun.section_index = SectionIndex(map_section(fun.section_index.0));
+            for info in &mut fun.func_info.func_info {
+                info.type_id += type_offset;
+            }
+            for info in &mut fun.line_info.line_info {
+                info.file_name_off += string_offset;
+                info.line_off += string_offset;
+            }
+        };
+
+        let functions_by_name = self
+            .functions
+            .values()
+            .map(|f| (f.name.clone(), f.address))
+            .collect::<HashMap<_, _>>();
+        let mut candidates = HashMap::new();
+        for (_, mut fun) in other.functions.drain() {
+            remap_function(&mut fun);
+            fun.address += text_offset;
+            fun.section_offset += text_offset as usize;
+            if let Some(address) = functions_by_name.get(&fun.name) {
+                candidates.insert(fun.name.clone(), (fun.address, *address));
+            }
+            self.functions.insert(fun.address, fun);
+        }
+        self.deduplicate_functions(candidates);
+
+        for (name, mut program) in other.programs.drain() {
+            remap_function(&mut program.function);
+            self.programs.insert(name, program);
+        }
+
+        for (name, mut map) in other.maps.drain() {
+            match &mut map {
+                Map::Legacy(m) => {
+                    m.section_index = map_section(m.section_index);
+                    m.symbol_index += symbol_offset;
+                }
+                Map::Btf(m) => {
+                    m.section_index = map_section(m.section_index);
+                    m.symbol_index += symbol_offset;
+                    for id in [&mut m.def.btf_key_type_id, &mut m.def.btf_value_type_id] {
+                        if *id != 0 {
+                            *id += type_offset;
+                        }
+                    }
+                }
+            }
+            self.maps.insert(name, map);
+        }
+        self.struct_ops.extend(other.struct_ops.drain());
+
+        for (name, size) in other.section_sizes.drain() {
+            // sections like .maps exist in both objects, but their DATASEC
+            // types are only checked against the size of the section
+            self.section_sizes
+                .entry(name)
+                .and_modify(|s| *s = (*s).max(size))
+                .or_insert(size);
+        }
+        for (name, offset) in other.symbol_offset_by_name.drain() {
+            self.symbol_offset_by_name.entry(name).or_insert(offset);
+        }
+
+        Ok(())
+    }
+
+    // `candidates` maps function names to the address of the function being
+    // linked in and the address of the existing function with the same name.
+    fn deduplicate_functions(&mut self, mut candidates: HashMap<String, (u64, u64)>) {
+        candidates.retain(|_, (address, existing)| {
+            self.same_function(&self.functions[address], &self.functions[existing])
+        });
+
+        // a function can only be replaced if the functions it calls are
+        // replaced too, otherwise it would call the wrong copy of them
+        loop {
+            let invalid = candidates
+                .iter()
+                .filter(|(_, (address, _))| {
+                    self.relocation_targets(&self.functions[address])
+                        .unwrap_or_default()
+                        .iter()
+                        .any(|(_, name, kind)| {
+                            *kind == SymbolKind::Text && !candidates.contains_key(name)
+                        })
+                })
+                .map(|(name, _)| name.clone())
+                .collect::<HashSet<_>>();
+            if invalid.is_empty() {
+                break;
+            }
+            candidates.retain(|name, _| !invalid.contains(name));
+        }
+
+        for (name, (address, existing)) in candidates {
+            debug!("deduplicating function {}", name);
+            self.functions.remove(&address);
+            self.function_aliases.insert(address, existing);
+        }
+    }
+
+    fn same_function(&self, a: &Function, b: &Function) -> bool {
+        let ins_eq = |a: &bpf_insn, b: &bpf_insn| {
+            a.code == b.code
+                && a.dst_reg() == b.dst_reg()
+                && a.src_reg() == b.src_reg()
+                && a.off == b.off
+                && a.imm == b.imm
+        };
+        a.name == b.name
+            && a.func_info.func_info.is_empty() == b.func_info.func_info.is_empty()
+            && a.instructions.len() == b.instructions.len()
+            && a.instructions
+                .iter()
+                .zip(&b.instructions)
+                .all(|(a, b)| ins_eq(a, b))
+            && match (self.relocation_targets(a), self.relocation_targets(b)) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            }
+    }
+
+    // Returns the instruction index and the name of the symbol of the
+    // relocations of `fun`, or `None` if one of them can't be identified by
+    // name.
+    fn relocation_targets(&self, fun: &Function) -> Option<Vec<(usize, String, SymbolKind)>> {
+        let relocations = self.relocations.get(&fun.section_index);
+        let mut targets = Vec::new();
+        for (index, ins) in fun.instructions.iter().enumerate() {
+            let offset = (fun.section_offset + index * INS_SIZE) as u64;
+            match relocations.and_then(|r| r.get(&offset)) {
+                Some(rel) => {
+                    let sym = self.symbols_by_index.get(&rel.symbol_index)?;
+                    // section symbols are relative to the section contents,
+                    // which differ between objects
+                    if sym.kind == SymbolKind::Section {
+                        return None;
+                    }
+                    targets.push((index, sym.name.clone()?, sym.kind));
+                }
+                // pc-relative calls depend on the layout of the section
+                None if insn_is_call(ins) => return None,
+                None => {}
+            }
+        }
+        Some(targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use object::Endianness;
+
+    use crate::{
+        generated::BPF_PSEUDO_CALL,
+        obj::{
+            btf::{FuncSecInfo, LineSecInfo},
+            relocation::{Relocation, Symbol},
+            KernelVersion, Program, ProgramSection,
+        },
+    };
+
+    use super::*;
+
+    fn ins(code: u8, imm: i32) -> bpf_insn {
+        let mut ins: bpf_insn = unsafe { std::mem::zeroed() };
+        ins.code = code;
+        ins.imm = imm;
+        if code == 0x85 {
+            ins.set_src_reg(BPF_PSEUDO_CALL as u8);
+        }
+        ins
+    }
+
+    fn function(name: &str, section_index: usize, instructions: Vec<bpf_insn>) -> Function {
+        Function {
+            address: 0,
+            name: name.to_owned(),
+            section_index: SectionIndex(section_index),
+            section_offset: 0,
+            instructions,
+            func_info: FuncSecInfo::default(),
+            line_info: LineSecInfo::default(),
+            func_info_rec_size: 0,
+            line_info_rec_size: 0,
+        }
+    }
+
+    fn symbol(index: usize, section_index: usize, name: &str, address: u64) -> Symbol {
+        Symbol {
+            index,
+            section_index: Some(section_index),
+            name: Some(name.to_owned()),
+            address,
+            size: 0,
+            is_definition: true,
+            kind: SymbolKind::Text,
+        }
+    }
+
+    // an object with a `prog` program calling a `helper` function returning
+    // `helper_ret`
+    fn object(prog: &str, helper_ret: i32) -> Object {
+        let mut obj = Object::new(
+            Endianness::Little,
+            CString::new("GPL").unwrap(),
+            KernelVersion::Any,
+        );
+        // section 1 is .text, section 2 is the program
+        obj.text_section_index = Some(1);
+        let mut helper = function("helper", 1, vec![ins(0xb7, helper_ret), ins(0x95, 0)]);
+        helper.address = 8;
+        helper.section_offset = 8;
+        // the first function of .text isn't shared
+        let other = function("other", 1, vec![ins(0x95, 0)]);
+        obj.functions.insert(0, other);
+        obj.functions.insert(8, helper);
+        obj.symbols_by_index.insert(1, symbol(1, 1, "other", 0));
+        obj.symbols_by_index.insert(2, symbol(2, 1, "helper", 8));
+        obj.programs.insert(
+            prog.to_owned(),
+            Program {
+                license: obj.license.clone(),
+                kernel_version: KernelVersion::Any,
+                section: ProgramSection::KProbe {
+                    name: prog.to_owned(),
+                },
+                function: function(prog, 2, vec![ins(0x85, -1), ins(0x95, 0)]),
+                autoload: true,
+            },
+        );
+        obj.relocations.insert(
+            SectionIndex(2),
+            [(
+                0,
+                Relocation {
+                    offset: 0,
+                    symbol_index: 2,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        );
+        obj
+    }
+
+    #[test]
+    fn test_link_shares_identical_functions() {
+        let mut obj = object("prog1", 0);
+        obj.link(object("prog2", 0)).unwrap();
+
+        // `other` comes first in .text, it's identical but not called
+        assert_eq!(obj.functions.len(), 2);
+        assert_eq!(obj.function_aliases.len(), 2);
+        assert_eq!(obj.function_aliases[&32], 8);
+
+        obj.relocate_calls().unwrap();
+        for prog in ["prog1", "prog2"] {
+            let instructions = &obj.programs[prog].function.instructions;
+            assert_eq!(instructions.len(), 4);
+            assert_eq!(instructions[0].imm, 1);
+            assert_eq!(instructions[2].imm, 0);
+        }
+    }
+
+    #[test]
+    fn test_link_keeps_different_functions() {
+        let mut obj = object("prog1", 0);
+        obj.link(object("prog2", 1)).unwrap();
+
+        assert_eq!(obj.functions.len(), 3);
+        assert!(!obj.function_aliases.contains_key(&32));
+
+        obj.relocate_calls().unwrap();
+        assert_eq!(obj.programs["prog1"].function.instructions[2].imm, 0);
+        assert_eq!(obj.programs["prog2"].function.instructions[2].imm, 1);
+    }
+
+    #[test]
+    fn test_link_duplicate_program() {
+        let mut obj = object("prog", 0);
+        assert!(matches!(
+            obj.link(object("prog", 0)),
+            Err(ParseError::DuplicateDefinition { name }) if name == "prog"
+        ));
+    }
+}
@@ -1,5 +1,7 @@
 pub(crate) mod btf;
+mod link;
 mod relocation;
+mod struct_ops;
 
 use log::debug;
 use object::{
@@ -16,12 +18,17 @@ use std::{
 use thiserror::Error;
 
 use relocation::*;
+pub(crate) use struct_ops::{member_size, StructOps};
 
 use crate::{
     bpf_map_def,
-    generated::{bpf_insn, bpf_map_info, bpf_map_type::BPF_MAP_TYPE_ARRAY, BPF_F_RDONLY_PROG},
+    generated::{
+        bpf_insn, bpf_map_info, bpf_map_type::BPF_MAP_TYPE_ARRAY, BPF_F_NUMA_NODE,
+        BPF_F_RDONLY_PROG,
+    },
     obj::btf::{Btf, BtfError, BtfExt, BtfType},
     programs::{CgroupSockAddrAttachType, CgroupSockAttachType, CgroupSockoptAttachType},
+    util::page_size,
     BpfError, BtfMapDef, PinningType,
 };
 use std::slice::from_raw_parts_mut;
@@ -41,7 +48,11 @@ pub struct Object {
     pub btf_ext: Option<BtfExt>,
     pub(crate) maps: HashMap<String, Map>,
     pub(crate) programs: HashMap<String, Program>,
+    pub(crate) struct_ops: HashMap<String, StructOps>,
     pub(crate) functions: HashMap<u64, Function>,
+    // the functions removed by `link`, mapped to the identical function
+    // they were merged with
+    pub(crate) function_aliases: HashMap<u64, u64>,
     pub(crate) relocations: HashMap<SectionIndex, HashMap<u64, Relocation>>,
     pub(crate) symbols_by_index: HashMap<usize, Symbol>,
     pub(crate) section_sizes: HashMap<String, u64>,
@@ -122,6 +133,41 @@ impl Map {
         }
     }
 
+    pub(crate) fn map_extra(&self) -> u64 {
+        match self {
+            Map::Legacy(m) => m.map_extra,
+            Map::Btf(m) => m.def.map_extra,
+        }
+    }
+
+    pub(crate) fn set_map_extra(&mut self, v: u64) {
+        match self {
+            Map::Legacy(m) => m.map_extra = v,
+            Map::Btf(m) => m.def.map_extra = v,
+        }
+    }
+
+    pub(crate) fn numa_node(&self) -> Option<u32> {
+        match self {
+            Map::Legacy(m) => m.numa_node,
+            Map::Btf(m) => m.def.numa_node,
+        }
+    }
+
+    // the kernel ignores numa_node unless BPF_F_NUMA_NODE is set
+    pub(crate) fn set_numa_node(&mut self, v: u32) {
+        match self {
+            Map::Legacy(m) => {
+                m.numa_node = Some(v);
+                m.def.map_flags |= BPF_F_NUMA_NODE;
+            }
+            Map::Btf(m) => {
+                m.def.numa_node = Some(v);
+                m.def.map_flags |= BPF_F_NUMA_NODE;
+            }
+        }
+    }
+
     pub(crate) fn pinning(&self) -> PinningType {
         match self {
             Map::Legacy(m) => m.def.pinning,
@@ -163,6 +209,80 @@ impl Map {
             Map::Btf(m) => m.symbol_index,
         }
     }
+
+    /// Estimates the kernel memory the map will use once created.
+    ///
+    /// This follows the layout of the kernel map implementations, leaving out
+    /// fixed per map overheads. Maps whose memory is allocated on demand,
+    /// like hash maps created with `BPF_F_NO_PREALLOC` and LPM tries, are
+    /// assumed to be full.
+    pub(crate) fn memory_estimate(&self, nr_cpus: usize) -> u64 {
+        use crate::generated::bpf_map_type::{self, *};
+
+        // size of the kernel's struct htab_elem, without key and value
+        const HTAB_ELEM_SIZE: u64 = 48;
+        // size of the kernel's struct lpm_trie_node, without key and value
+        const LPM_NODE_SIZE: u64 = 32;
+        const PTR_SIZE: u64 = 8;
+
+        let round_up = |n: u64| (n + 7) & !7;
+        let key_size = round_up(self.key_size() as u64);
+        let value_size = round_up(self.value_size() as u64);
+        let nr_cpus = nr_cpus as u64;
+        let map_type = bpf_map_type::try_from(self.map_type()).ok();
+        let mut max_entries = self.max_entries() as u64;
+        if max_entries == 0 && map_type == Some(BPF_MAP_TYPE_PERF_EVENT_ARRAY) {
+            // aya sets the size of perf event arrays to the number of CPUs
+            max_entries = nr_cpus;
+        }
+        let buckets = max_entries.next_power_of_two() * 2 * PTR_SIZE;
+
+        match map_type {
+            Some(BPF_MAP_TYPE_ARRAY) => max_entries * value_size,
+            Some(BPF_MAP_TYPE_PERCPU_ARRAY) => max_entries * (PTR_SIZE + value_size * nr_cpus),
+            Some(BPF_MAP_TYPE_HASH | BPF_MAP_TYPE_LRU_HASH) => {
+                buckets + max_entries * (HTAB_ELEM_SIZE + key_size + value_size)
+            }
+            Some(BPF_MAP_TYPE_PERCPU_HASH | BPF_MAP_TYPE_LRU_PERCPU_HASH) => {
+                buckets
+                    + max_entries * (HTAB_ELEM_SIZE + key_size + PTR_SIZE + value_size * nr_cpus)
+            }
+            Some(BPF_MAP_TYPE_HASH_OF_MAPS | BPF_MAP_TYPE_SOCKHASH | BPF_MAP_TYPE_DEVMAP_HASH) => {
+                buckets + max_entries * (HTAB_ELEM_SIZE + key_size + PTR_SIZE)
+            }
+            Some(
+                BPF_MAP_TYPE_PROG_ARRAY
+                | BPF_MAP_TYPE_PERF_EVENT_ARRAY
+                | BPF_MAP_TYPE_CGROUP_ARRAY
+                | BPF_MAP_TYPE_ARRAY_OF_MAPS
+                | BPF_MAP_TYPE_SOCKMAP
+                | BPF_MAP_TYPE_DEVMAP
+                | BPF_MAP_TYPE_CPUMAP
+                | BPF_MAP_TYPE_XSKMAP,
+            ) => max_entries * PTR_SIZE,
+            Some(BPF_MAP_TYPE_LPM_TRIE) => max_entries * (LPM_NODE_SIZE + key_size + value_size),
+            Some(BPF_MAP_TYPE_QUEUE | BPF_MAP_TYPE_STACK) => {
+                (max_entries + 1) * self.value_size() as u64
+            }
+            Some(BPF_MAP_TYPE_STACK_TRACE) => {
+                buckets / 2 + max_entries * (PTR_SIZE * 2 + value_size)
+            }
+            Some(BPF_MAP_TYPE_RINGBUF) => max_entries,
+            // arenas are sized in pages
+            Some(BPF_MAP_TYPE_ARENA) => max_entries * page_size() as u64,
+            Some(BPF_MAP_TYPE_BLOOM_FILTER) => {
+                // the low 4 bits of map_extra are the number of hash
+                // functions, 5 by default, with 7 bits per entry and hash
+                // function
+                let nr_hashes = match self.map_extra() & 0xf {
+                    0 => 5,
+                    n => n,
+                };
+                (max_entries * nr_hashes * 7).next_power_of_two() / 8
+            }
+            _ => max_entries * (key_size + value_size),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -172,6 +292,9 @@ pub struct LegacyMap {
     pub(crate) symbol_index: usize,
     pub(crate) data: Vec<u8>,
     pub(crate) kind: MapKind,
+    // not part of bpf_map_def, these can only be set by the loader
+    pub(crate) map_extra: u64,
+    pub(crate) numa_node: Option<u32>,
 }
 
 #[derive(Debug, Clone)]
@@ -183,12 +306,24 @@ pub struct BtfMap {
     pub(crate) data: Vec<u8>,
 }
 
+/// A global variable stored in a `.data` or `.bss` map.
+#[derive(Debug, Clone)]
+pub(crate) struct GlobalVariable {
+    /// The name of the map backing the variable's section.
+    pub(crate) map: String,
+    /// The offset of the variable in the map value.
+    pub(crate) offset: usize,
+    /// The size of the variable.
+    pub(crate) size: usize,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Program {
     pub(crate) license: CString,
     pub(crate) kernel_version: KernelVersion,
     pub(crate) section: ProgramSection,
     pub(crate) function: Function,
+    pub(crate) autoload: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -258,6 +393,9 @@ pub enum ProgramSection {
     CgroupSysctl {
         name: String,
     },
+    CgroupDevice {
+        name: String,
+    },
     CgroupSockopt {
         name: String,
         attach_type: CgroupSockoptAttachType,
@@ -283,9 +421,15 @@ pub enum ProgramSection {
     FExit {
         name: String,
     },
+    FModRet {
+        name: String,
+    },
     Extension {
         name: String,
     },
+    FlowDissector {
+        name: String,
+    },
     SkLookup {
         name: String,
     },
@@ -293,6 +437,36 @@ pub enum ProgramSection {
         name: String,
         attach_type: CgroupSockAttachType,
     },
+    Syscall {
+        name: String,
+    },
+    StructOps {
+        name: String,
+    },
+    Iter {
+        name: String,
+    },
+    KProbeMulti {
+        name: String,
+    },
+    KRetProbeMulti {
+        name: String,
+    },
+    UProbeMulti {
+        name: String,
+    },
+    URetProbeMulti {
+        name: String,
+    },
+    Netfilter {
+        name: String,
+    },
+    SkReuseport {
+        name: String,
+    },
+    SkReuseportMigrate {
+        name: String,
+    },
 }
 
 impl ProgramSection {
@@ -315,6 +489,7 @@ impl ProgramSection {
             ProgramSection::CgroupSkbEgress { name, .. } => name,
             ProgramSection::CgroupSockAddr { name, .. } => name,
             ProgramSection::CgroupSysctl { name } => name,
+            ProgramSection::CgroupDevice { name } => name,
             ProgramSection::CgroupSockopt { name, .. } => name,
             ProgramSection::LircMode2 { name } => name,
             ProgramSection::PerfEvent { name } => name,
@@ -323,9 +498,21 @@ impl ProgramSection {
             ProgramSection::BtfTracePoint { name } => name,
             ProgramSection::FEntry { name } => name,
             ProgramSection::FExit { name } => name,
+            ProgramSection::FModRet { name } => name,
             ProgramSection::Extension { name } => name,
+            ProgramSection::FlowDissector { name } => name,
             ProgramSection::SkLookup { name } => name,
             ProgramSection::CgroupSock { name, .. } => name,
+            ProgramSection::Syscall { name } => name,
+            ProgramSection::StructOps { name } => name,
+            ProgramSection::Iter { name } => name,
+            ProgramSection::KProbeMulti { name } => name,
+            ProgramSection::KRetProbeMulti { name } => name,
+            ProgramSection::UProbeMulti { name } => name,
+            ProgramSection::URetProbeMulti { name } => name,
+            ProgramSection::Netfilter { name } => name,
+            ProgramSection::SkReuseport { name } => name,
+            ProgramSection::SkReuseportMigrate { name } => name,
         }
     }
 }
@@ -348,8 +535,12 @@ impl FromStr for ProgramSection {
         Ok(match kind {
             "kprobe" => KProbe { name },
             "kretprobe" => KRetProbe { name },
+            "kprobe.multi" => KProbeMulti { name },
+            "kretprobe.multi" => KRetProbeMulti { name },
             "uprobe" => UProbe { name },
             "uretprobe" => URetProbe { name },
+            "uprobe.multi" => UProbeMulti { name },
+            "uretprobe.multi" => URetProbeMulti { name },
             "xdp" => Xdp { name },
             "tp_btf" => BtfTracePoint { name },
             _ if kind.starts_with("tracepoint") || kind.starts_with("tp") => {
@@ -390,6 +581,7 @@ impl FromStr for ProgramSection {
                 attach_type: CgroupSockAttachType::default(),
             },
             "cgroup/sysctl" => CgroupSysctl { name },
+            "cgroup/dev" => CgroupDevice { name },
             "cgroup/getsockopt" => CgroupSockopt {
                 name,
                 attach_type: CgroupSockoptAttachType::Get,
@@ -401,6 +593,7 @@ impl FromStr for ProgramSection {
             "cgroup" => match &*name {
                 "skb" => CgroupSkb { name },
                 "sysctl" => CgroupSysctl { name },
+                "dev" => CgroupDevice { name },
                 "getsockopt" | "setsockopt" => {
                     if let Ok(attach_type) = CgroupSockoptAttachType::try_from(name.as_str()) {
                         CgroupSockopt { name, attach_type }
@@ -503,8 +696,19 @@ impl FromStr for ProgramSection {
             "lsm" => Lsm { name },
             "fentry" => FEntry { name },
             "fexit" => FExit { name },
+            "fmod_ret" => FModRet { name },
             "freplace" => Extension { name },
+            "flow_dissector" => FlowDissector { name },
             "sk_lookup" => SkLookup { name },
+            "syscall" => Syscall { name },
+            "struct_ops" => StructOps { name },
+            "iter" => Iter { name },
+            "netfilter" => Netfilter { name },
+            "sk_reuseport" => match &*name {
+                "migrate" => SkReuseportMigrate { name },
+                _ => SkReuseport { name },
+            },
+            "sk_reuseport/migrate" => SkReuseportMigrate { name },
             _ => {
                 return Err(ParseError::InvalidProgramSection {
                     section: section.to_owned(),
@@ -567,6 +771,8 @@ impl Object {
             }
         }
 
+        // struct_ops sections reference programs so they're parsed last
+        let mut struct_ops = Vec::new();
         for s in obj.sections() {
             if let Ok(name) = s.name() {
                 if name == ".BTF" || name == ".BTF.ext" {
@@ -574,6 +780,14 @@ impl Object {
                 }
             }
 
+            let section = Section::try_from(&s)?;
+            if let BpfSectionKind::StructOps = section.kind {
+                struct_ops.push(s);
+                continue;
+            }
+            bpf_obj.parse_section(section)?;
+        }
+        for s in struct_ops {
             bpf_obj.parse_section(Section::try_from(&s)?)?;
         }
 
@@ -589,7 +803,9 @@ impl Object {
             btf_ext: None,
             maps: HashMap::new(),
             programs: HashMap::new(),
+            struct_ops: HashMap::new(),
             functions: HashMap::new(),
+            function_aliases: HashMap::new(),
             relocations: HashMap::new(),
             symbols_by_index: HashMap::new(),
             section_sizes: HashMap::new(),
@@ -643,6 +859,33 @@ impl Object {
         Ok(())
     }
 
+    /// Returns the writable global variables, ie the variables defined in the
+    /// `.data` and `.bss` sections, keyed by name.
+    pub(crate) fn global_variables(&self) -> HashMap<String, GlobalVariable> {
+        let data_maps: HashMap<usize, &str> = self
+            .maps
+            .iter()
+            .filter(|(_, m)| matches!(m.kind(), MapKind::Data | MapKind::Bss))
+            .map(|(name, m)| (m.section_index(), name.as_str()))
+            .collect();
+
+        self.symbols_by_index
+            .values()
+            .filter(|s| s.is_definition && s.kind == SymbolKind::Data && s.size > 0)
+            .filter_map(|s| {
+                let map = data_maps.get(&s.section_index?)?;
+                Some((
+                    s.name.clone()?,
+                    GlobalVariable {
+                        map: map.to_string(),
+                        offset: s.address as usize,
+                        size: s.size as usize,
+                    },
+                ))
+            })
+            .collect()
+    }
+
     fn parse_btf(&mut self, section: &Section) -> Result<(), BtfError> {
         self.btf = Some(Btf::parse(section.data, self.endianness)?);
 
@@ -659,7 +902,12 @@ impl Object {
     }
 
     fn parse_program(&self, section: &Section) -> Result<Program, ParseError> {
-        let prog_sec = ProgramSection::from_str(section.name)?;
+        // like libbpf, a `?` prefix marks programs that aren't loaded by default
+        let (autoload, sec_name) = match section.name.strip_prefix('?') {
+            Some(sec_name) => (false, sec_name),
+            None => (true, section.name),
+        };
+        let prog_sec = ProgramSection::from_str(sec_name)?;
         let name = prog_sec.name().to_owned();
 
         let (func_info, line_info, func_info_rec_size, line_info_rec_size) =
@@ -691,6 +939,7 @@ impl Object {
                 func_info_rec_size,
                 line_info_rec_size,
             },
+            autoload,
         })
     }
 
@@ -813,6 +1062,8 @@ impl Object {
                     def,
                     data: Vec::new(),
                     kind: MapKind::Other,
+                    map_extra: 0,
+                    numa_node: None,
                 }),
             );
         }
@@ -932,6 +1183,7 @@ impl Object {
                     );
                 }
             }
+            BpfSectionKind::StructOps => self.parse_struct_ops_section(&section)?,
             BpfSectionKind::Undefined | BpfSectionKind::License | BpfSectionKind::Version => {}
         }
 
@@ -999,6 +1251,15 @@ pub enum ParseError {
 
     #[error("no symbols found for the maps included in the maps section")]
     NoSymbolsInMapSection {},
+
+    #[error("`{name}` is defined by more than one of the linked objects")]
+    DuplicateDefinition { name: String },
+
+    #[error("the linked objects have different endianness")]
+    EndiannessMismatch,
+
+    #[error("invalid struct_ops `{name}`")]
+    InvalidStructOps { name: String },
 }
 
 #[derive(Debug)]
@@ -1013,6 +1274,7 @@ enum BpfSectionKind {
     BtfExt,
     License,
     Version,
+    StructOps,
 }
 
 impl BpfSectionKind {
@@ -1036,6 +1298,8 @@ impl BpfSectionKind {
             BpfSectionKind::Btf
         } else if name == ".BTF.ext" {
             BpfSectionKind::BtfExt
+        } else if name == ".struct_ops" || name == ".struct_ops.link" {
+            BpfSectionKind::StructOps
         } else {
             BpfSectionKind::Undefined
         }
@@ -1203,6 +1467,8 @@ fn parse_map(section: &Section, name: &str) -> Result<Map, ParseError> {
         def,
         data,
         kind,
+        map_extra: 0,
+        numa_node: None,
     }))
 }
 
@@ -1289,6 +1555,13 @@ fn parse_btf_map_def(btf: &Btf, info: &DataSecEntry) -> Result<(String, BtfMapDe
             "map_flags" => {
                 map_def.map_flags = get_map_field(btf, m.btf_type)?;
             }
+            "map_extra" => {
+                map_def.map_extra = get_map_field(btf, m.btf_type)? as u64;
+            }
+            "numa_node" => {
+                map_def.numa_node = Some(get_map_field(btf, m.btf_type)?);
+                map_def.map_flags |= BPF_F_NUMA_NODE;
+            }
             "pinning" => {
                 let pinning = get_map_field(btf, m.btf_type)?;
                 map_def.pinning = PinningType::try_from(pinning).unwrap_or_else(|_| {
@@ -1314,6 +1587,8 @@ pub(crate) fn parse_map_info(info: bpf_map_info, pinned: PinningType) -> Map {
                 value_size: info.value_size,
                 max_entries: info.max_entries,
                 map_flags: info.map_flags,
+                map_extra: info.map_extra,
+                numa_node: None,
                 pinning: pinned,
                 btf_key_type_id: info.btf_key_type_id,
                 btf_value_type_id: info.btf_value_type_id,
@@ -1340,6 +1615,8 @@ pub(crate) fn parse_map_info(info: bpf_map_info, pinned: PinningType) -> Map {
             data: Vec::new(),
             // We should never be loading the .bss or .data or .rodata FDs
             kind: MapKind::Other,
+            map_extra: info.map_extra,
+            numa_node: None,
         })
     }
 }
@@ -1596,7 +1873,9 @@ mod tests {
                     pinning: PinningType::None,
                 },
                 data,
-                kind
+                kind,
+                map_extra: 0,
+                numa_node: None,
             })) if data == map_data && value_size == map_data.len() as u32 && kind == MapKind::Bss
         ))
     }
@@ -1639,7 +1918,23 @@ mod tests {
                     section_index: SectionIndex(0),
                     section_offset: 0,
                     instructions,
-                    ..} }) if license.to_string_lossy() == "GPL" && name == "foo" && instructions.len() == 1
+                    ..},
+                autoload: true,
+            }) if license.to_string_lossy() == "GPL" && name == "foo" && instructions.len() == 1
+        );
+    }
+
+    #[test]
+    fn test_parse_program_no_autoload() {
+        let obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_program(&fake_section(BpfSectionKind::Program, "?kprobe/foo", bytes_of(&fake_ins()))),
+            Ok(Program {
+                section: ProgramSection::KProbe { name },
+                autoload: false,
+                ..
+            }) if name == "foo"
         );
     }
 
@@ -1919,6 +2214,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_syscall() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "syscall/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::Syscall { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_btf_tracepoint() {
         let mut obj = fake_obj();
@@ -1940,6 +2256,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_sk_msg() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_msg/my_sk_msg",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("my_sk_msg"),
+            Some(Program {
+                section: ProgramSection::SkMsg { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_skskb_unnamed() {
         let mut obj = fake_obj();
@@ -1982,6 +2319,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_sock_ops() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sockops/my_sock_ops",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("my_sock_ops"),
+            Some(Program {
+                section: ProgramSection::SockOps { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_skskb_stream_verdict() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_skb/stream_verdict/my_verdict",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("my_verdict"),
+            Some(Program {
+                section: ProgramSection::SkSkbStreamVerdict { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_fentry() {
         let mut obj = fake_obj();
@@ -2024,6 +2403,331 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_fmod_ret() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "fmod_ret/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::FModRet { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_perf_event() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "perf_event/observe_cpu_clock",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("observe_cpu_clock"),
+            Some(Program {
+                section: ProgramSection::PerfEvent { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_freplace() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "freplace/pass_filter",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("pass_filter"),
+            Some(Program {
+                section: ProgramSection::Extension { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_lirc_mode2() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "lirc_mode2/decode_ir",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("decode_ir"),
+            Some(Program {
+                section: ProgramSection::LircMode2 { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_flow_dissector() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "flow_dissector/dissect",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("dissect"),
+            Some(Program {
+                section: ProgramSection::FlowDissector { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_struct_ops() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "struct_ops/dctcp_init",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("dctcp_init"),
+            Some(Program {
+                section: ProgramSection::StructOps { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_struct_ops_section() {
+        use crate::obj::btf::{
+            BtfMember, DataSec, FuncProto, Int, IntEncoding, Ptr, Struct, Var, VarLinkage,
+        };
+
+        let mut obj = fake_obj();
+        obj.parse_section(fake_section(
+            BpfSectionKind::Program,
+            "struct_ops/dctcp_init",
+            bytes_of(&fake_ins()),
+        ))
+        .unwrap();
+        fake_sym(&mut obj, 0, 0, "dctcp_init", 0);
+
+        let mut btf = Btf::new();
+        let name_offset = btf.add_string("int".to_string());
+        let int_type = btf.add_type(BtfType::Int(Int::new(
+            name_offset,
+            4,
+            IntEncoding::Signed,
+            0,
+        )));
+        let proto = btf.add_type(BtfType::FuncProto(FuncProto::new(Vec::new(), int_type)));
+        let ptr = btf.add_type(BtfType::Ptr(Ptr::new(0, proto)));
+        let members = vec![
+            BtfMember {
+                name_offset: btf.add_string("flags".to_string()),
+                btf_type: int_type,
+                offset: 0,
+            },
+            BtfMember {
+                name_offset: btf.add_string("init".to_string()),
+                btf_type: ptr,
+                offset: 64,
+            },
+        ];
+        let name_offset = btf.add_string("tcp_congestion_ops".to_string());
+        let struct_type = btf.add_type(BtfType::Struct(Struct::new(name_offset, members, 16)));
+        let name_offset = btf.add_string("bpf_dctcp".to_string());
+        let var = btf.add_type(BtfType::Var(Var::new(
+            name_offset,
+            struct_type,
+            VarLinkage::Global,
+        )));
+        let name_offset = btf.add_string(".struct_ops".to_string());
+        btf.add_type(BtfType::DataSec(DataSec::new(
+            name_offset,
+            vec![DataSecEntry {
+                btf_type: var,
+                offset: 0,
+                size: 16,
+            }],
+            16,
+        )));
+        obj.btf = Some(btf);
+
+        let data = [1u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut section = fake_section(BpfSectionKind::StructOps, ".struct_ops", &data);
+        section.relocations.push(Relocation {
+            offset: 8,
+            symbol_index: 1,
+        });
+        obj.parse_section(section).unwrap();
+
+        let ops = obj.struct_ops.get("bpf_dctcp").unwrap();
+        assert_eq!(ops.type_name, "tcp_congestion_ops");
+        assert!(!ops.link);
+        assert_eq!(ops.members.len(), 2);
+        assert_eq!(ops.members[0].name, "flags");
+        assert_eq!(ops.member_data(&ops.members[0]), &[1, 0, 0, 0]);
+        assert_eq!(ops.members[0].program, None);
+        assert_eq!(ops.members[1].name, "init");
+        assert_eq!(ops.members[1].offset, 8);
+        assert_eq!(ops.members[1].size, 8);
+        assert_eq!(ops.members[1].program.as_deref(), Some("dctcp_init"));
+    }
+
+    #[test]
+    fn test_parse_section_kprobe_multi() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "kretprobe.multi/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::KRetProbeMulti { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_uprobe_multi() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "uprobe.multi/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::UProbeMulti { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_netfilter() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "netfilter/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::Netfilter { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_sk_reuseport() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_reuseport/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::SkReuseport { .. },
+                ..
+            })
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_reuseport/migrate/bar",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("bar"),
+            Some(Program {
+                section: ProgramSection::SkReuseportMigrate { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_iter() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "iter/task",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("task"),
+            Some(Program {
+                section: ProgramSection::Iter { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_cgroup_skb_ingress_unnamed() {
         let mut obj = fake_obj();
@@ -2108,6 +2812,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_cgroup_device_unnamed() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "cgroup/dev",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("dev"),
+            Some(Program {
+                section: ProgramSection::CgroupDevice { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_cgroup_device_named() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "cgroup/dev/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::CgroupDevice { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_sock_addr_named() {
         let mut obj = fake_obj();
@@ -2224,6 +2970,8 @@ mod tests {
                 symbol_index: 1,
                 data: vec![0, 0, 0],
                 kind: MapKind::Rodata,
+                map_extra: 0,
+                numa_node: None,
             }),
         );
         obj.symbols_by_index.insert(
@@ -2247,6 +2995,121 @@ mod tests {
         assert_eq!(test_data, map.data());
     }
 
+    #[test]
+    fn test_map_memory_estimate() {
+        use crate::generated::bpf_map_type::*;
+
+        let map = |map_type, key_size, value_size, max_entries| {
+            Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: map_type as u32,
+                    key_size,
+                    value_size,
+                    max_entries,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
+            })
+        };
+
+        assert_eq!(
+            map(BPF_MAP_TYPE_ARRAY, 4, 12, 10).memory_estimate(4),
+            10 * 16
+        );
+        assert_eq!(
+            map(BPF_MAP_TYPE_PERCPU_ARRAY, 4, 8, 10).memory_estimate(4),
+            10 * (8 + 8 * 4)
+        );
+        assert_eq!(
+            map(BPF_MAP_TYPE_HASH, 4, 8, 100).memory_estimate(4),
+            128 * 16 + 100 * (48 + 8 + 8)
+        );
+        // perf event arrays default to one entry per CPU
+        assert_eq!(
+            map(BPF_MAP_TYPE_PERF_EVENT_ARRAY, 4, 4, 0).memory_estimate(4),
+            4 * 8
+        );
+        assert_eq!(
+            map(BPF_MAP_TYPE_RINGBUF, 0, 0, 4096).memory_estimate(4),
+            4096
+        );
+        let mut bloom_filter = map(BPF_MAP_TYPE_BLOOM_FILTER, 0, 4, 100);
+        assert_eq!(bloom_filter.memory_estimate(4), 4096 / 8);
+        bloom_filter.set_map_extra(2);
+        assert_eq!(bloom_filter.memory_estimate(4), 2048 / 8);
+    }
+
+    #[test]
+    fn test_set_numa_node() {
+        let mut map = Map::Btf(BtfMap {
+            def: BtfMapDef {
+                map_flags: BPF_F_RDONLY_PROG,
+                ..Default::default()
+            },
+            section_index: 0,
+            symbol_index: 0,
+            kind: MapKind::Other,
+            data: Vec::new(),
+        });
+        assert_eq!(map.numa_node(), None);
+        map.set_numa_node(0);
+        assert_eq!(map.numa_node(), Some(0));
+        assert_eq!(map.map_flags(), BPF_F_RDONLY_PROG | BPF_F_NUMA_NODE);
+    }
+
+    #[test]
+    fn test_global_variables() {
+        let mut obj = fake_obj();
+        for (name, section_index, kind) in
+            [(".rodata", 1, MapKind::Rodata), (".data", 2, MapKind::Data)]
+        {
+            obj.maps.insert(
+                name.to_string(),
+                Map::Legacy(LegacyMap {
+                    def: bpf_map_def {
+                        map_type: BPF_MAP_TYPE_ARRAY as u32,
+                        key_size: mem::size_of::<u32>() as u32,
+                        value_size: 8,
+                        max_entries: 1,
+                        ..Default::default()
+                    },
+                    section_index,
+                    symbol_index: section_index,
+                    data: vec![0; 8],
+                    kind,
+                    map_extra: 0,
+                    numa_node: None,
+                }),
+            );
+        }
+        for (index, section_index, name, address) in [(1, 1, "CONFIG", 0), (2, 2, "ENABLED", 4)] {
+            obj.symbols_by_index.insert(
+                index,
+                Symbol {
+                    index,
+                    section_index: Some(section_index),
+                    name: Some(name.to_string()),
+                    address,
+                    size: 4,
+                    is_definition: true,
+                    kind: SymbolKind::Data,
+                },
+            );
+        }
+
+        let globals = obj.global_variables();
+        assert_eq!(globals.len(), 1);
+        assert!(matches!(
+            globals.get("ENABLED"),
+            Some(GlobalVariable { map, offset: 4, size: 4 }) if map == ".data"
+        ));
+    }
+
     #[test]
     fn test_parse_btf_map_section() {
         let mut obj = fake_obj();
@@ -104,6 +104,7 @@ impl Object {
             let linker = FunctionLinker::new(
                 self.text_section_index,
                 &self.functions,
+                &self.function_aliases,
                 &self.relocations,
                 &self.symbols_by_index,
             );
@@ -204,6 +205,7 @@ fn relocate_maps<'a, I: Iterator<Item = &'a Relocation>>(
 struct FunctionLinker<'a> {
     text_section_index: Option<usize>,
     functions: &'a HashMap<u64, Function>,
+    function_aliases: &'a HashMap<u64, u64>,
     linked_functions: HashMap<u64, usize>,
     relocations: &'a HashMap<SectionIndex, HashMap<u64, Relocation>>,
     symbol_table: &'a HashMap<usize, Symbol>,
@@ -213,12 +215,14 @@ impl<'a> FunctionLinker<'a> {
     fn new(
         text_section_index: Option<usize>,
         functions: &'a HashMap<u64, Function>,
+        function_aliases: &'a HashMap<u64, u64>,
         relocations: &'a HashMap<SectionIndex, HashMap<u64, Relocation>>,
         symbol_table: &'a HashMap<usize, Symbol>,
     ) -> FunctionLinker<'a> {
         FunctionLinker {
             text_section_index,
             functions,
+            function_aliases,
             linked_functions: HashMap::new(),
             relocations,
             symbol_table,
@@ -335,6 +339,14 @@ impl<'a> FunctionLinker<'a> {
                 },
             );
 
+            // functions deduplicated when linking objects together are
+            // replaced with the copy that was kept
+            let callee_address = self
+                .function_aliases
+                .get(&callee_address)
+                .copied()
+                .unwrap_or(callee_address);
+
             // lookup and link the callee if it hasn't been linked already. `callee_ins_index` will
             // contain the instruction index of the callee inside the program.
             let callee =
@@ -421,7 +433,7 @@ impl<'a> FunctionLinker<'a> {
     }
 }
 
-fn insn_is_call(ins: &bpf_insn) -> bool {
+pub(crate) fn insn_is_call(ins: &bpf_insn) -> bool {
     let klass = (ins.code & 0x07) as u32;
     let op = (ins.code & 0xF0) as u32;
     let src = (ins.code & 0x08) as u32;
@@ -471,6 +483,8 @@ mod test {
                 symbol_index,
                 data: Vec::new(),
                 kind: MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
             }),
             fd: Some(fd),
             btf_fd: None,
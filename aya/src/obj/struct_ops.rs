@@ -0,0 +1,146 @@
+use crate::{
+    obj::{
+        btf::{Btf, BtfError, BtfType},
+        Object, ParseError, Section,
+    },
+    BpfError,
+};
+
+/// A variable of a `.struct_ops` section, like a `tcp_congestion_ops`
+/// implementation.
+///
+/// Each variable becomes a `BPF_MAP_TYPE_STRUCT_OPS` map holding the
+/// kernel's version of the struct, whose function pointers are the programs
+/// implementing them.
+#[derive(Debug, Clone)]
+pub(crate) struct StructOps {
+    // the name of the struct type, eg `tcp_congestion_ops`
+    pub(crate) type_name: String,
+    pub(crate) data: Vec<u8>,
+    pub(crate) members: Vec<StructOpsMember>,
+    // defined in a `.struct_ops.link` section, registered with a link
+    pub(crate) link: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StructOpsMember {
+    pub(crate) name: String,
+    // the offset and size of the member in `StructOps::data`
+    pub(crate) offset: usize,
+    pub(crate) size: usize,
+    // the program referenced by a function pointer member
+    pub(crate) program: Option<String>,
+}
+
+impl StructOps {
+    /// Returns the data of `member` in the variable.
+    pub(crate) fn member_data(&self, member: &StructOpsMember) -> &[u8] {
+        &self.data[member.offset..][..member.size]
+    }
+}
+
+impl Object {
+    // struct_ops sections reference programs, so they must be parsed after
+    // the program sections
+    pub(super) fn parse_struct_ops_section(&mut self, section: &Section) -> Result<(), BpfError> {
+        let btf = self.btf.as_ref().ok_or(BpfError::NoBTF)?;
+        let datasec = btf
+            .types()
+            .find_map(|t| match t {
+                BtfType::DataSec(datasec)
+                    if btf.type_name(t).ok().as_deref() == Some(section.name) =>
+                {
+                    Some(datasec)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| ParseError::InvalidStructOps {
+                name: section.name.to_owned(),
+            })?;
+
+        let mut struct_ops = Vec::new();
+        for info in &datasec.entries {
+            let var = match btf.type_by_id(info.btf_type)? {
+                BtfType::Var(var) => var,
+                other => {
+                    return Err(BtfError::UnexpectedBtfType {
+                        type_id: other.btf_type().unwrap_or(0),
+                    }
+                    .into())
+                }
+            };
+            let name = btf.string_at(var.name_offset)?.into_owned();
+            let ty = btf.type_by_id(btf.resolve_type(var.btf_type)?)?;
+            let s = match ty {
+                BtfType::Struct(s) => s,
+                _ => return Err(ParseError::InvalidStructOps { name }.into()),
+            };
+            let start = self
+                .symbol_offset_by_name
+                .get(&name)
+                .copied()
+                .unwrap_or(info.offset as u64) as usize;
+            let data = section
+                .data
+                .get(start..start + s.size as usize)
+                .ok_or_else(|| ParseError::InvalidStructOps { name: name.clone() })?
+                .to_vec();
+
+            let mut members = Vec::new();
+            for m in &s.members {
+                let offset = s.member_bit_offset(m) / 8;
+                let size = member_size(btf, m.btf_type)?;
+                // function pointers are relocated to the program they point to
+                let program = section
+                    .relocations
+                    .iter()
+                    .find(|r| r.offset as usize == start + offset)
+                    .map(|r| self.struct_ops_program(r.symbol_index))
+                    .transpose()?;
+                members.push(StructOpsMember {
+                    name: btf.string_at(m.name_offset)?.into_owned(),
+                    offset,
+                    size,
+                    program,
+                });
+            }
+            struct_ops.push((
+                name,
+                StructOps {
+                    type_name: btf.type_name(ty)?.into_owned(),
+                    data,
+                    members,
+                    link: section.name == ".struct_ops.link",
+                },
+            ));
+        }
+        self.struct_ops.extend(struct_ops);
+        Ok(())
+    }
+
+    // Returns the name of the program defined by the symbol at `symbol_index`.
+    fn struct_ops_program(&self, symbol_index: usize) -> Result<String, ParseError> {
+        let symbol = self
+            .symbols_by_index
+            .get(&symbol_index)
+            .ok_or(ParseError::InvalidSymbol {
+                index: symbol_index,
+                name: None,
+            })?;
+        self.programs
+            .iter()
+            .find(|(_, p)| Some(p.function.section_index.0) == symbol.section_index)
+            .map(|(name, _)| name.clone())
+            .ok_or_else(|| ParseError::SymbolNotFound {
+                name: symbol.name.clone().unwrap_or_default(),
+            })
+    }
+}
+
+// Btf::type_size follows pointers to the size of their target
+pub(crate) fn member_size(btf: &Btf, type_id: u32) -> Result<usize, BtfError> {
+    match btf.type_by_id(btf.resolve_type(type_id)?)? {
+        BtfType::Ptr(_) => Ok(8),
+        _ => btf.type_size(type_id),
+    }
+}
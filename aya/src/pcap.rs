@@ -0,0 +1,368 @@
+//! Packet captures in the pcapng format.
+//!
+//! Socket filter, TC and XDP programs can copy the packets they see to user
+//! space, for example with `bpf_perf_event_output` and the number of bytes to
+//! copy in the upper 32 bits of the flags, which makes the kernel append the
+//! packet bytes to the event. [`PcapWriter`] writes such packets in the pcapng
+//! format read by wireshark and tcpdump, so capture tools can be built
+//! without a libpcap dependency.
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    io::{self, Write},
+    mem,
+    os::raw::c_char,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libc::{clock_gettime, if_indextoname, timespec, CLOCK_MONOTONIC, IF_NAMESIZE};
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+const OPT_ENDOFOPT: u16 = 0;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+
+/// The link type of Ethernet frames, `LINKTYPE_ETHERNET`.
+pub const LINKTYPE_ETHERNET: u16 = 1;
+/// The link type of raw IPv4 or IPv6 packets, `LINKTYPE_RAW`.
+pub const LINKTYPE_RAW: u16 = 101;
+
+/// The header of the packet events read by [`PcapWriter::write_event`].
+///
+/// Programs that capture packets output this header followed by the packet
+/// bytes, for example with `bpf_perf_event_output(ctx, &EVENTS,
+/// (u64)header.captured_len << 32 | BPF_F_CURRENT_CPU, &header,
+/// sizeof(header))`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketEventHeader {
+    /// The time the packet was captured, as returned by `bpf_ktime_get_ns`.
+    pub timestamp_ns: u64,
+    /// The index of the interface the packet was captured on.
+    pub if_index: u32,
+    /// The length of the packet, which can be larger than the number of
+    /// bytes copied.
+    pub len: u32,
+    /// The number of packet bytes copied after the header.
+    pub captured_len: u32,
+}
+
+/// Writes packets in the pcapng format.
+///
+/// Packets are captured on interfaces, which are added with
+/// [`add_interface`](PcapWriter::add_interface) before writing the packets.
+/// Timestamps are written with a nanosecond resolution.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::{
+///     maps::perf::PerfEventArray,
+///     pcap::PcapWriter,
+///     util::online_cpus,
+/// };
+/// use bytes::BytesMut;
+/// use std::fs::File;
+///
+/// let mut events = PerfEventArray::try_from(bpf.map_mut("PACKETS").unwrap())?;
+/// let mut buffer = events.open(online_cpus()?[0], None)?;
+/// let mut pcap = PcapWriter::new(File::create("capture.pcapng")?)?;
+///
+/// let mut buffers = vec![BytesMut::with_capacity(65536); 10];
+/// loop {
+///     let read = buffer.read_events(&mut buffers)?;
+///     for event in &buffers[..read.read] {
+///         pcap.write_event(event)?;
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    interfaces: u32,
+    if_indexes: HashMap<u32, u32>,
+    boot_time: Duration,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Creates a writer and writes the pcapng section header to `writer`.
+    pub fn new(mut writer: W) -> Result<PcapWriter<W>, io::Error> {
+        let mut body = Vec::with_capacity(16);
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_ne_bytes());
+        // version 1.0
+        body.extend_from_slice(&1u16.to_ne_bytes());
+        body.extend_from_slice(&0u16.to_ne_bytes());
+        // unknown section length
+        body.extend_from_slice(&(-1i64).to_ne_bytes());
+        write_block(&mut writer, SECTION_HEADER_BLOCK, &body)?;
+
+        Ok(PcapWriter {
+            writer,
+            interfaces: 0,
+            if_indexes: HashMap::new(),
+            boot_time: boot_time()?,
+        })
+    }
+
+    /// Adds an interface and returns its id, to be passed to
+    /// [`write_packet`](PcapWriter::write_packet).
+    ///
+    /// `link_type` is the type of the captured frames, like
+    /// [`LINKTYPE_ETHERNET`], and `snap_len` the maximum number of bytes
+    /// captured from each packet, or 0 for no limit.
+    pub fn add_interface(
+        &mut self,
+        name: Option<&str>,
+        link_type: u16,
+        snap_len: u32,
+    ) -> Result<u32, io::Error> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&link_type.to_ne_bytes());
+        body.extend_from_slice(&0u16.to_ne_bytes());
+        body.extend_from_slice(&snap_len.to_ne_bytes());
+        if let Some(name) = name {
+            push_option(&mut body, IF_NAME, name.as_bytes());
+        }
+        // nanosecond timestamps
+        push_option(&mut body, IF_TSRESOL, &[9]);
+        push_option(&mut body, OPT_ENDOFOPT, &[]);
+        write_block(&mut self.writer, INTERFACE_DESCRIPTION_BLOCK, &body)?;
+
+        let id = self.interfaces;
+        self.interfaces += 1;
+        Ok(id)
+    }
+
+    /// Writes a packet captured on the interface `interface_id`.
+    ///
+    /// `timestamp` is the time the packet was captured, since the Unix
+    /// epoch, and `original_len` the length of the packet, which can be
+    /// larger than `data` if the packet was truncated.
+    pub fn write_packet(
+        &mut self,
+        interface_id: u32,
+        timestamp: Duration,
+        data: &[u8],
+        original_len: u32,
+    ) -> Result<(), io::Error> {
+        if interface_id >= self.interfaces {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown interface {interface_id}"),
+            ));
+        }
+        let captured_len = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "packet too large"))?;
+        let timestamp = timestamp.as_nanos() as u64;
+
+        let mut body = Vec::with_capacity(20 + data.len() + 3);
+        body.extend_from_slice(&interface_id.to_ne_bytes());
+        body.extend_from_slice(&((timestamp >> 32) as u32).to_ne_bytes());
+        body.extend_from_slice(&(timestamp as u32).to_ne_bytes());
+        body.extend_from_slice(&captured_len.to_ne_bytes());
+        body.extend_from_slice(&original_len.max(captured_len).to_ne_bytes());
+        body.extend_from_slice(data);
+        pad(&mut body);
+        write_block(&mut self.writer, ENHANCED_PACKET_BLOCK, &body)
+    }
+
+    /// Writes the packet of an event made of a [`PacketEventHeader`]
+    /// followed by the packet bytes.
+    ///
+    /// The interface of the packet is added the first time one of its
+    /// packets is written, with the [`LINKTYPE_ETHERNET`] link type. The
+    /// timestamp of the event is converted from the time since boot to the
+    /// time since the Unix epoch.
+    pub fn write_event(&mut self, event: &[u8]) -> Result<(), io::Error> {
+        if event.len() < mem::size_of::<PacketEventHeader>() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "event smaller than its header",
+            ));
+        }
+        // Safety: the event is at least as large as the header
+        let header = unsafe { (event.as_ptr() as *const PacketEventHeader).read_unaligned() };
+        let data = &event[mem::size_of::<PacketEventHeader>()..];
+        // the kernel pads the events to 8 bytes
+        let data = &data[..data.len().min(header.captured_len as usize)];
+
+        let interface_id = match self.if_indexes.get(&header.if_index) {
+            Some(id) => *id,
+            None => {
+                let name = if_name(header.if_index);
+                let id = self.add_interface(name.as_deref(), LINKTYPE_ETHERNET, 0)?;
+                self.if_indexes.insert(header.if_index, id);
+                id
+            }
+        };
+        let timestamp = self.boot_time + Duration::from_nanos(header.timestamp_ns);
+        self.write_packet(interface_id, timestamp, data, header.len)
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+// Blocks are made of their type and length, the body, and the length again.
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> Result<(), io::Error> {
+    let len = (12 + body.len()) as u32;
+    writer.write_all(&block_type.to_ne_bytes())?;
+    writer.write_all(&len.to_ne_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&len.to_ne_bytes())
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_ne_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_ne_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+// Pads to 32 bits.
+fn pad(body: &mut Vec<u8>) {
+    body.resize((body.len() + 3) & !3, 0);
+}
+
+// Returns the time of boot since the Unix epoch, to convert the timestamps of
+// `bpf_ktime_get_ns`, which uses `CLOCK_MONOTONIC`.
+fn boot_time() -> Result<Duration, io::Error> {
+    let mut ts = timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { clock_gettime(CLOCK_MONOTONIC, &mut ts) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let since_boot = Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(now.saturating_sub(since_boot))
+}
+
+fn if_name(if_index: u32) -> Option<String> {
+    let mut buf = [0 as c_char; IF_NAMESIZE];
+    if unsafe { if_indextoname(if_index, buf.as_mut_ptr()) }.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(buf.as_ptr()) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(buf: &[u8], offset: usize) -> u32 {
+        u32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn event_header(header: PacketEventHeader) -> Vec<u8> {
+        // zero the padding at the end of the header
+        let mut event = vec![0; mem::size_of::<PacketEventHeader>()];
+        unsafe { (event.as_mut_ptr() as *mut PacketEventHeader).write_unaligned(header) };
+        event
+    }
+
+    #[test]
+    fn test_write_packet() {
+        let mut pcap = PcapWriter::new(Vec::new()).unwrap();
+        let id = pcap
+            .add_interface(Some("eth0"), LINKTYPE_ETHERNET, 0)
+            .unwrap();
+        assert_eq!(id, 0);
+        assert!(pcap.write_packet(1, Duration::ZERO, &[0; 4], 4).is_err());
+        pcap.write_packet(id, Duration::new(1, 5), &[1, 2, 3, 4, 5], 60)
+            .unwrap();
+        let buf = pcap.into_inner();
+
+        // section header
+        assert_eq!(u32_at(&buf, 0), SECTION_HEADER_BLOCK);
+        assert_eq!(u32_at(&buf, 4), 28);
+        assert_eq!(u32_at(&buf, 8), BYTE_ORDER_MAGIC);
+        assert_eq!(u32_at(&buf, 24), 28);
+
+        // interface description: link type, snap len, {if_name, if_tsresol,
+        // opt_endofopt}
+        let idb = &buf[28..];
+        assert_eq!(u32_at(idb, 0), INTERFACE_DESCRIPTION_BLOCK);
+        assert_eq!(u32_at(idb, 4), 12 + 8 + 8 + 8 + 4);
+        assert_eq!(&idb[16..24], &[2, 0, 4, 0, b'e', b't', b'h', b'0'][..]);
+        assert_eq!(&idb[24..32], &[9, 0, 1, 0, 9, 0, 0, 0][..]);
+        assert_eq!(u32_at(idb, 36), 40);
+
+        // enhanced packet, padded to 32 bits
+        let epb = &buf[28 + 40..];
+        assert_eq!(u32_at(epb, 0), ENHANCED_PACKET_BLOCK);
+        assert_eq!(u32_at(epb, 4), 12 + 20 + 8);
+        let timestamp = (u32_at(epb, 12) as u64) << 32 | u32_at(epb, 16) as u64;
+        assert_eq!(timestamp, 1_000_000_005);
+        assert_eq!(u32_at(epb, 20), 5);
+        assert_eq!(u32_at(epb, 24), 60);
+        assert_eq!(&epb[28..36], &[1, 2, 3, 4, 5, 0, 0, 0][..]);
+        assert_eq!(epb.len(), 40);
+    }
+
+    #[test]
+    fn test_write_event() {
+        let mut pcap = PcapWriter::new(Vec::new()).unwrap();
+        let mut event = event_header(PacketEventHeader {
+            timestamp_ns: 0,
+            if_index: 0,
+            len: 3,
+            captured_len: 3,
+        });
+        // the bytes after the packet are the kernel's padding
+        event.extend_from_slice(&[1, 2, 3, 0, 0, 0, 0, 0]);
+        pcap.write_event(&event).unwrap();
+        pcap.write_event(&event).unwrap();
+        assert!(pcap.write_event(&event[..8]).is_err());
+        assert_eq!(pcap.interfaces, 1);
+
+        let buf = pcap.into_inner();
+        // section header, interface without name, two packets
+        let epb = &buf[28 + 32..];
+        assert_eq!(u32_at(epb, 0), ENHANCED_PACKET_BLOCK);
+        assert_eq!(u32_at(epb, 20), 3);
+        assert_eq!(&epb[28..32], &[1, 2, 3, 0][..]);
+        assert_eq!(buf.len(), 28 + 32 + 2 * 36);
+    }
+
+    #[test]
+    fn test_write_truncated_event() {
+        let mut pcap = PcapWriter::new(Vec::new()).unwrap();
+        let mut event = event_header(PacketEventHeader {
+            timestamp_ns: 0,
+            if_index: 0,
+            len: 60,
+            captured_len: 3,
+        });
+        event.extend_from_slice(&[1, 2, 3, 0, 0, 0, 0, 0]);
+        pcap.write_event(&event).unwrap();
+
+        let buf = pcap.into_inner();
+        let epb = &buf[28 + 32..];
+        // only the captured bytes are written, with the original length
+        assert_eq!(u32_at(epb, 4), 12 + 20 + 4);
+        assert_eq!(u32_at(epb, 20), 3);
+        assert_eq!(u32_at(epb, 24), 60);
+        assert_eq!(&epb[28..32], &[1, 2, 3, 0][..]);
+    }
+}
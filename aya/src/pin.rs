@@ -1,7 +1,28 @@
 //! Pinning BPF objects to the BPF filesystem.
-use std::io;
+use std::{
+    ffi::CString,
+    fs, io, mem,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, RawFd},
+    },
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
+use libc::{
+    inotify_add_watch, inotify_event, inotify_init1, IN_CLOEXEC, IN_CREATE, IN_DELETE,
+    IN_DELETE_SELF, IN_IGNORED, IN_MOVED_FROM, IN_MOVED_TO, IN_MOVE_SELF, IN_NONBLOCK,
+};
+
+use crate::{
+    maps::{MapData, MapError},
+    programs::{links::FdLink, Program},
+    sys::{bpf_get_object, bpf_map_get_info_by_fd, bpf_pin_object},
+};
+
 /// An error ocurred working with a pinned BPF object.
 #[derive(Error, Debug)]
 pub enum PinError {
@@ -33,3 +54,443 @@ pub enum PinError {
         io_error: io::Error,
     },
 }
+
+/// Pins maps, programs and links to a directory atomically, so that another
+/// process can adopt them.
+///
+/// Objects are first pinned to a staging directory next to the target
+/// directory. [`PinHandoff::commit`] then renames the staging directory to the
+/// target, so a process watching for the target directory, for example a
+/// restarted daemon, sees either all the objects or none of them. If the
+/// handoff is dropped without being committed, the staging directory is
+/// removed.
+///
+/// Maps are pinned under `maps/`, programs under `programs/` and links under
+/// `links/`. The target directory can be adopted with
+/// [`Bpf::load_from_pins`](crate::Bpf::load_from_pins).
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError),
+/// #     #[error(transparent)]
+/// #     Pin(#[from] aya::pin::PinError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::{pin::PinHandoff, programs::{links::FdLink, Extension}};
+///
+/// let handoff = PinHandoff::new("/sys/fs/bpf/myapp")?;
+/// let prog: &mut Extension = bpf.program_mut("example").unwrap().try_into()?;
+/// let link_id = prog.attach()?;
+/// let link: FdLink = prog.take_link(link_id)?.into();
+/// handoff.pin_link("example", link)?;
+/// handoff.pin_program("example", bpf.program("example").unwrap())?;
+/// handoff.commit()?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+pub struct PinHandoff {
+    staging: PathBuf,
+    target: PathBuf,
+    committed: bool,
+}
+
+impl PinHandoff {
+    /// Creates the staging directory for the given target directory.
+    ///
+    /// The parent of `target` must exist and be on a BPF file system (bpffs),
+    /// and `target` must not exist yet.
+    pub fn new<P: AsRef<Path>>(target: P) -> Result<PinHandoff, PinError> {
+        let target = target.as_ref().to_path_buf();
+        let file_name = target
+            .file_name()
+            .ok_or_else(|| PinError::InvalidPinPath {
+                error: target.display().to_string(),
+            })?
+            .to_string_lossy();
+        let staging = target.with_file_name(format!(".{}.staging-{}", file_name, process::id()));
+
+        let mkdir_error = |io_error| PinError::SyscallError {
+            name: "mkdir".to_string(),
+            io_error,
+        };
+        fs::create_dir(&staging).map_err(mkdir_error)?;
+        let handoff = PinHandoff {
+            staging,
+            target,
+            committed: false,
+        };
+        for dir in ["maps", "programs", "links"] {
+            fs::create_dir(handoff.staging.join(dir)).map_err(mkdir_error)?;
+        }
+        Ok(handoff)
+    }
+
+    /// Pins a map to `maps/<name>`.
+    pub fn pin_map(&self, name: &str, map: &MapData) -> Result<(), PinError> {
+        let fd = map.fd().ok_or_else(|| PinError::NoFd {
+            name: name.to_string(),
+        })?;
+        self.pin_fd("maps", name, fd.as_raw_fd())
+    }
+
+    /// Pins a loaded program to `programs/<name>`.
+    pub fn pin_program(&self, name: &str, program: &Program) -> Result<(), PinError> {
+        let fd = program.fd().ok_or_else(|| PinError::NoFd {
+            name: name.to_string(),
+        })?;
+        self.pin_fd("programs", name, fd.as_raw_fd())
+    }
+
+    /// Pins a link to `links/<name>`.
+    ///
+    /// Once the handoff is committed, the link stays attached until the pin is
+    /// removed, see [`PinnedLink`](crate::programs::links::PinnedLink).
+    pub fn pin_link(&self, name: &str, link: FdLink) -> Result<(), PinError> {
+        let fd = link.fd.ok_or_else(|| PinError::NoFd {
+            name: name.to_string(),
+        })?;
+        self.pin_fd("links", name, fd)
+    }
+
+    fn pin_fd(&self, kind: &str, name: &str, fd: RawFd) -> Result<(), PinError> {
+        let path = self.staging.join(kind).join(name);
+        let path_string = CString::new(path.to_string_lossy().into_owned()).map_err(|e| {
+            PinError::InvalidPinPath {
+                error: e.to_string(),
+            }
+        })?;
+        bpf_pin_object(fd, &path_string).map_err(|(_, io_error)| PinError::SyscallError {
+            name: "BPF_OBJ_PIN".to_string(),
+            io_error,
+        })?;
+        Ok(())
+    }
+
+    /// Atomically moves the pinned objects to the target directory.
+    ///
+    /// Returns the target directory.
+    pub fn commit(mut self) -> Result<PathBuf, PinError> {
+        // rename(2) fails with ENOTEMPTY or EEXIST if the target is already
+        // populated, so a handoff never mixes objects from two processes
+        fs::rename(&self.staging, &self.target).map_err(|io_error| PinError::SyscallError {
+            name: "rename".to_string(),
+            io_error,
+        })?;
+        self.committed = true;
+        Ok(self.target.clone())
+    }
+}
+
+impl Drop for PinHandoff {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_dir_all(&self.staging);
+        }
+    }
+}
+
+/// A change of the map pinned at the path watched by a [`MapPinWatcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapPinChange {
+    /// A map was pinned at the path, replacing the map with id `old_id`, if
+    /// any.
+    Replaced {
+        /// The id of the previous map.
+        old_id: Option<u32>,
+        /// The id of the new map.
+        new_id: u32,
+    },
+    /// The pin was removed.
+    Removed {
+        /// The id of the previous map.
+        old_id: u32,
+    },
+}
+
+/// Watches a pinned map path for the map being replaced.
+///
+/// When cooperating processes share a pinned map, one of them may migrate to
+/// a new map, for example with a different layout, and pin it at the same
+/// path. The others then need to reopen the map with
+/// [`MapData::from_pin`]. The watcher detects this by comparing the id of
+/// the map pinned at the path.
+///
+/// The parent directory is watched with inotify so changes are noticed
+/// immediately. The id is also checked every
+/// [poll interval](MapPinWatcher::set_poll_interval), which is the only
+/// detection method if inotify isn't available.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::{
+///     maps::{HashMap, MapData},
+///     pin::{MapPinChange, MapPinWatcher},
+/// };
+///
+/// let path = "/sys/fs/bpf/myapp/maps/CONNECTIONS";
+/// let mut watcher = MapPinWatcher::new(path)?;
+/// let mut map = MapData::from_pin(path)?;
+/// loop {
+///     if let Some(MapPinChange::Replaced { .. }) = watcher.wait(None)? {
+///         map = MapData::from_pin(path)?;
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct MapPinWatcher {
+    path: PathBuf,
+    map_id: Option<u32>,
+    inotify_fd: Option<RawFd>,
+    poll_interval: Duration,
+}
+
+impl MapPinWatcher {
+    /// Starts watching the given path.
+    ///
+    /// The path doesn't need to exist yet.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<MapPinWatcher, MapError> {
+        let path = path.as_ref().to_path_buf();
+        let mut watcher = MapPinWatcher {
+            map_id: pinned_map_id(&path)?,
+            path,
+            inotify_fd: None,
+            poll_interval: Duration::from_secs(1),
+        };
+        watcher.watch_parent();
+        Ok(watcher)
+    }
+
+    /// Returns the id of the map currently pinned at the path, as of the last
+    /// check.
+    pub fn map_id(&self) -> Option<u32> {
+        self.map_id
+    }
+
+    /// Sets how often the id of the pinned map is checked by
+    /// [`MapPinWatcher::wait`]. Defaults to one second.
+    pub fn set_poll_interval(&mut self, interval: Duration) -> &mut MapPinWatcher {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Returns the inotify file descriptor, if inotify is available.
+    ///
+    /// It becomes readable when the parent directory changes, after which
+    /// [`MapPinWatcher::check`] should be called. It can be registered with
+    /// an event loop or a [`PollSet`](crate::maps::PollSet).
+    pub fn fd(&self) -> Option<RawFd> {
+        self.inotify_fd
+    }
+
+    /// Checks whether the pinned map changed since the last check, without
+    /// blocking.
+    pub fn check(&mut self) -> Result<Option<MapPinChange>, MapError> {
+        if self.drain_events() {
+            // the parent directory was removed or moved away, possibly to be
+            // replaced by another one
+            self.close_inotify();
+            self.watch_parent();
+        }
+        let map_id = pinned_map_id(&self.path)?;
+        let change = map_pin_change(self.map_id, map_id);
+        self.map_id = map_id;
+        Ok(change)
+    }
+
+    /// Blocks until the pinned map changes or `timeout` expires.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Option<MapPinChange>, MapError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(change) = self.check()? {
+                return Ok(Some(change));
+            }
+            let mut interval = self.poll_interval;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(None);
+                }
+                interval = interval.min(deadline - now);
+            }
+            match self.inotify_fd {
+                Some(fd) => {
+                    let mut pollfd = libc::pollfd {
+                        fd,
+                        events: libc::POLLIN,
+                        revents: 0,
+                    };
+                    let timeout_ms = interval.as_millis().min(i32::MAX as u128) as i32;
+                    // EINTR only makes the id be checked earlier
+                    unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+                }
+                None => std::thread::sleep(interval),
+            }
+        }
+    }
+
+    fn watch_parent(&mut self) {
+        let parent = match self.path.parent() {
+            Some(parent) => parent,
+            None => return,
+        };
+        let parent = match CString::new(parent.as_os_str().as_bytes()) {
+            Ok(parent) => parent,
+            Err(_) => return,
+        };
+        let fd = unsafe { inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+        if fd < 0 {
+            return;
+        }
+        let mask =
+            IN_CREATE | IN_DELETE | IN_MOVED_FROM | IN_MOVED_TO | IN_DELETE_SELF | IN_MOVE_SELF;
+        if unsafe { inotify_add_watch(fd, parent.as_ptr(), mask) } < 0 {
+            unsafe { libc::close(fd) };
+            return;
+        }
+        self.inotify_fd = Some(fd);
+    }
+
+    // Consumes the pending inotify events. Returns true if the watch on the
+    // parent directory is gone.
+    fn drain_events(&mut self) -> bool {
+        let fd = match self.inotify_fd {
+            Some(fd) => fd,
+            // retry, the parent directory may exist now
+            None => return true,
+        };
+        let mut gone = false;
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+            if len <= 0 {
+                return gone;
+            }
+            let mut offset = 0;
+            while offset + mem::size_of::<inotify_event>() <= len as usize {
+                let event =
+                    unsafe { (buf.as_ptr().add(offset) as *const inotify_event).read_unaligned() };
+                if event.mask & (IN_IGNORED | IN_DELETE_SELF | IN_MOVE_SELF) != 0 {
+                    gone = true;
+                }
+                offset += mem::size_of::<inotify_event>() + event.len as usize;
+            }
+        }
+    }
+
+    fn close_inotify(&mut self) {
+        if let Some(fd) = self.inotify_fd.take() {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+impl Drop for MapPinWatcher {
+    fn drop(&mut self) {
+        self.close_inotify();
+    }
+}
+
+// Returns the id of the map pinned at `path`, or `None` if nothing is pinned
+// there.
+fn pinned_map_id(path: &Path) -> Result<Option<u32>, MapError> {
+    let path_string =
+        CString::new(path.as_os_str().as_bytes()).map_err(|e| MapError::PinError {
+            name: None,
+            error: PinError::InvalidPinPath {
+                error: e.to_string(),
+            },
+        })?;
+    let fd = match bpf_get_object(&path_string) {
+        Ok(fd) => fd as RawFd,
+        Err((_, io_error)) if io_error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err((_, io_error)) => {
+            return Err(MapError::SyscallError {
+                call: "BPF_OBJ_GET".to_owned(),
+                io_error,
+            })
+        }
+    };
+    let info = bpf_map_get_info_by_fd(fd);
+    unsafe { libc::close(fd) };
+    let info = info.map_err(|io_error| MapError::SyscallError {
+        call: "BPF_MAP_GET_INFO_BY_FD".to_owned(),
+        io_error,
+    })?;
+    Ok(Some(info.id))
+}
+
+fn map_pin_change(old_id: Option<u32>, new_id: Option<u32>) -> Option<MapPinChange> {
+    match (old_id, new_id) {
+        (old_id, new_id) if old_id == new_id => None,
+        (old_id, Some(new_id)) => Some(MapPinChange::Replaced { old_id, new_id }),
+        (Some(old_id), None) => Some(MapPinChange::Removed { old_id }),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn test_pin_handoff() {
+        let root = env::temp_dir().join(format!("aya-pin-handoff-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let target = root.join("app");
+
+        // dropping an uncommitted handoff removes the staging directory
+        let handoff = PinHandoff::new(&target).unwrap();
+        let staging = handoff.staging.clone();
+        assert!(staging.join("maps").is_dir());
+        drop(handoff);
+        assert!(!staging.exists());
+        assert!(!target.exists());
+
+        let handoff = PinHandoff::new(&target).unwrap();
+        assert_eq!(handoff.commit().unwrap(), target);
+        assert!(target.join("programs").is_dir());
+
+        // a populated target is never replaced
+        let handoff = PinHandoff::new(&target).unwrap();
+        assert!(matches!(
+            handoff.commit(),
+            Err(PinError::SyscallError { name, .. }) if name == "rename"
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_map_pin_change() {
+        assert_eq!(map_pin_change(Some(1), Some(1)), None);
+        assert_eq!(map_pin_change(None, None), None);
+        assert_eq!(
+            map_pin_change(Some(1), Some(2)),
+            Some(MapPinChange::Replaced {
+                old_id: Some(1),
+                new_id: 2
+            })
+        );
+        assert_eq!(
+            map_pin_change(None, Some(2)),
+            Some(MapPinChange::Replaced {
+                old_id: None,
+                new_id: 2
+            })
+        );
+        assert_eq!(
+            map_pin_change(Some(1), None),
+            Some(MapPinChange::Removed { old_id: 1 })
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Cgroup program inheritance.
+//!
+//! Programs attached to a cgroup can also run for the descendants of the
+//! cgroup, depending on the mode they were attached with:
+//!
+//! * [`CgroupAttachMode::Single`]: the program runs for the descendants, but
+//!   no program can be attached to them.
+//! * [`CgroupAttachMode::AllowOverride`]: a program attached to a descendant
+//!   replaces the program for that descendant and its own descendants.
+//! * [`CgroupAttachMode::AllowMulti`]: the programs attached to the
+//!   descendants run first, followed by the programs of the cgroup.
+//!
+//! The `attach_multi` methods of the cgroup programs, for example
+//! [`CgroupSkb::attach_multi`](crate::programs::CgroupSkb::attach_multi),
+//! attach a program in multi mode, so that it runs for a whole subtree while
+//! leaving room for the programs of the descendants. Other loaders, like
+//! systemd, may use other modes. The functions in this module report which
+//! programs are attached to a cgroup and which programs actually run for it.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use aya::programs::{cgroup, CgroupSkbAttachType};
+//!
+//! let file = File::open("/sys/fs/cgroup/unified/system.slice/foo.service")?;
+//! let ingress = cgroup::CgroupAttachType::Skb(CgroupSkbAttachType::Ingress);
+//! for id in cgroup::effective_programs(file, ingress)? {
+//!     println!("program {} runs for foo.service", id);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use std::{
+    fs,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    generated::{bpf_attach_type, BPF_F_ALLOW_MULTI, BPF_F_ALLOW_OVERRIDE, BPF_F_QUERY_EFFECTIVE},
+    programs::{
+        query, utils::open_cgroup, CgroupSkbAttachType, CgroupSockAddrAttachType,
+        CgroupSockAttachType, CgroupSockoptAttachType, ProgramError,
+    },
+};
+
+/// The attach point of a cgroup program.
+#[derive(Copy, Clone, Debug)]
+pub enum CgroupAttachType {
+    /// A [`CgroupSkb`](crate::programs::CgroupSkb) attach point.
+    Skb(CgroupSkbAttachType),
+    /// A [`CgroupSock`](crate::programs::CgroupSock) attach point.
+    Sock(CgroupSockAttachType),
+    /// A [`CgroupSockAddr`](crate::programs::CgroupSockAddr) attach point.
+    SockAddr(CgroupSockAddrAttachType),
+    /// A [`CgroupSockopt`](crate::programs::CgroupSockopt) attach point.
+    Sockopt(CgroupSockoptAttachType),
+    /// The [`SockOps`](crate::programs::SockOps) attach point.
+    SockOps,
+    /// The [`CgroupSysctl`](crate::programs::CgroupSysctl) attach point.
+    Sysctl,
+    /// The [`CgroupDevice`](crate::programs::CgroupDevice) attach point.
+    Device,
+}
+
+impl From<CgroupAttachType> for bpf_attach_type {
+    fn from(t: CgroupAttachType) -> bpf_attach_type {
+        match t {
+            CgroupAttachType::Skb(CgroupSkbAttachType::Ingress) => {
+                bpf_attach_type::BPF_CGROUP_INET_INGRESS
+            }
+            CgroupAttachType::Skb(CgroupSkbAttachType::Egress) => {
+                bpf_attach_type::BPF_CGROUP_INET_EGRESS
+            }
+            CgroupAttachType::Sock(t) => t.into(),
+            CgroupAttachType::SockAddr(t) => t.into(),
+            CgroupAttachType::Sockopt(t) => t.into(),
+            CgroupAttachType::SockOps => bpf_attach_type::BPF_CGROUP_SOCK_OPS,
+            CgroupAttachType::Sysctl => bpf_attach_type::BPF_CGROUP_SYSCTL,
+            CgroupAttachType::Device => bpf_attach_type::BPF_CGROUP_DEVICE,
+        }
+    }
+}
+
+/// The mode the programs of a cgroup were attached with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CgroupAttachMode {
+    /// A single program which can't be overridden by descendants.
+    Single,
+    /// A single program which descendants can override.
+    AllowOverride,
+    /// Multiple programs, which run after the programs of the descendants.
+    AllowMulti,
+}
+
+impl CgroupAttachMode {
+    fn from_flags(flags: u32) -> CgroupAttachMode {
+        if flags & BPF_F_ALLOW_MULTI != 0 {
+            CgroupAttachMode::AllowMulti
+        } else if flags & BPF_F_ALLOW_OVERRIDE != 0 {
+            CgroupAttachMode::AllowOverride
+        } else {
+            CgroupAttachMode::Single
+        }
+    }
+}
+
+/// The programs attached directly to a cgroup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CgroupPrograms {
+    /// The mode the programs were attached with.
+    pub mode: CgroupAttachMode,
+    /// The ids of the programs, in the order they run.
+    pub program_ids: Vec<u32>,
+}
+
+/// Returns the programs attached directly to the given cgroup.
+///
+/// Programs inherited from the ancestors of the cgroup aren't included, see
+/// [`effective_programs`].
+pub fn attached_programs<T: AsRawFd>(
+    cgroup: T,
+    attach_type: CgroupAttachType,
+) -> Result<CgroupPrograms, ProgramError> {
+    let mut flags = Some(0);
+    let program_ids = query(cgroup, attach_type.into(), 0, &mut flags)?;
+    Ok(CgroupPrograms {
+        mode: CgroupAttachMode::from_flags(flags.unwrap_or_default()),
+        program_ids,
+    })
+}
+
+/// Returns the ids of the programs that run for the given cgroup, in the order
+/// they run.
+///
+/// This includes the programs inherited from the ancestors of the cgroup, as
+/// computed by the kernel.
+pub fn effective_programs<T: AsRawFd>(
+    cgroup: T,
+    attach_type: CgroupAttachType,
+) -> Result<Vec<u32>, ProgramError> {
+    query(cgroup, attach_type.into(), BPF_F_QUERY_EFFECTIVE, &mut None)
+}
+
+/// Returns the effective programs of `root` and all its descendant cgroups.
+///
+/// `root` is a cgroup v2 directory. The returned paths are in depth-first
+/// order, starting with `root`.
+pub fn effective_programs_in_subtree<P: AsRef<Path>>(
+    root: P,
+    attach_type: CgroupAttachType,
+) -> Result<Vec<(PathBuf, Vec<u32>)>, ProgramError> {
+    let mut result = Vec::new();
+    let mut pending = vec![root.as_ref().to_path_buf()];
+    let io_error = |io_error| ProgramError::InvalidCgroup {
+        call: "readdir".to_owned(),
+        io_error,
+    };
+
+    while let Some(path) = pending.pop() {
+        let file = open_cgroup(&path)?;
+        let ids = effective_programs(file.as_raw_fd(), attach_type)?;
+
+        let mut children = Vec::new();
+        for entry in fs::read_dir(&path).map_err(io_error)? {
+            let entry = entry.map_err(io_error)?;
+            if entry.file_type().map_err(io_error)?.is_dir() {
+                children.push(entry.path());
+            }
+        }
+        children.sort();
+        pending.extend(children.into_iter().rev());
+        result.push((path, ids));
+    }
+
+    Ok(result)
+}
+
+/// Computes the programs that run for a cgroup from the programs attached to it
+/// and to its ancestors.
+///
+/// `hierarchy` starts with the cgroup and ends with the root cgroup. This
+/// follows the rules the kernel applies when a program is attached, so it can
+/// be used to predict the effect of attaching a program before doing it.
+///
+/// # Examples
+///
+/// ```
+/// use aya::programs::cgroup::{effective_programs_of, CgroupAttachMode, CgroupPrograms};
+///
+/// let root = CgroupPrograms {
+///     mode: CgroupAttachMode::AllowMulti,
+///     program_ids: vec![1, 2],
+/// };
+/// let child = CgroupPrograms {
+///     mode: CgroupAttachMode::AllowOverride,
+///     program_ids: vec![3],
+/// };
+/// // the programs of the child run first, followed by the programs of the
+/// // root since they were attached in multi mode
+/// assert_eq!(effective_programs_of(&[child, root]), vec![3, 1, 2]);
+/// ```
+pub fn effective_programs_of(hierarchy: &[CgroupPrograms]) -> Vec<u32> {
+    let mut ids = Vec::new();
+    for cgroup in hierarchy {
+        // once a program has been found, only ancestors in multi mode add
+        // their programs
+        if ids.is_empty() || cgroup.mode == CgroupAttachMode::AllowMulti {
+            ids.extend_from_slice(&cgroup.program_ids);
+        }
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn programs(mode: CgroupAttachMode, program_ids: &[u32]) -> CgroupPrograms {
+        CgroupPrograms {
+            mode,
+            program_ids: program_ids.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_effective_programs_of() {
+        use CgroupAttachMode::*;
+
+        // the example from the BPF_PROG_ATTACH documentation in bpf.h:
+        // cgrp1 (MULTI progs A, B) ->
+        //    cgrp2 (OVERRIDE prog C) ->
+        //      cgrp3 (MULTI prog D) ->
+        //        cgrp4 (OVERRIDE prog E) ->
+        //          cgrp5 (NONE prog F)
+        let (a, b, c, d, e, f) = (1, 2, 3, 4, 5, 6);
+        let cgrp1 = programs(AllowMulti, &[a, b]);
+        let cgrp2 = programs(AllowOverride, &[c]);
+        let cgrp3 = programs(AllowMulti, &[d]);
+        let cgrp4 = programs(AllowOverride, &[e]);
+        let cgrp5 = programs(Single, &[f]);
+
+        let hierarchy = [cgrp5, cgrp4, cgrp3, cgrp2, cgrp1];
+        assert_eq!(effective_programs_of(&hierarchy), vec![f, d, a, b]);
+        assert_eq!(effective_programs_of(&hierarchy[1..]), vec![e, d, a, b]);
+        assert_eq!(effective_programs_of(&hierarchy[3..]), vec![c, a, b]);
+
+        // a cgroup without programs inherits the closest programs
+        let empty = programs(Single, &[]);
+        assert_eq!(
+            effective_programs_of(&[empty, hierarchy[3].clone(), hierarchy[4].clone()]),
+            vec![c, a, b]
+        );
+    }
+}
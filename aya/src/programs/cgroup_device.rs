@@ -0,0 +1,186 @@
+//! Cgroup device programs.
+use std::{
+    hash::Hash,
+    os::unix::prelude::{AsRawFd, RawFd},
+    path::Path,
+};
+
+use crate::{
+    generated::{
+        bpf_attach_type::BPF_CGROUP_DEVICE, bpf_prog_type::BPF_PROG_TYPE_CGROUP_DEVICE,
+        BPF_F_ALLOW_MULTI,
+    },
+    programs::{
+        cgroup_attach_error, define_link_wrapper, load_program, utils::open_cgroup, FdLink, Link,
+        ProgAttachLink, ProgramData, ProgramError,
+    },
+    sys::{bpf_link_create, bpf_prog_attach, kernel_version},
+};
+
+/// A program used to control access to devices.
+///
+/// [`CgroupDevice`] programs can be attached to a cgroup and will be called every
+/// time a process inside that cgroup tries to open, read from, write to or create
+/// a device node. The program decides whether the access is allowed, which is how
+/// container runtimes enforce device policies on cgroup v2.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.15.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(Debug, thiserror::Error)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     IO(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     Map(#[from] aya::maps::MapError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use std::fs::File;
+/// use aya::programs::CgroupDevice;
+///
+/// let file = File::open("/sys/fs/cgroup/unified")?;
+/// let program: &mut CgroupDevice = bpf.program_mut("cgroup_dev").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach(file)?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_CGROUP_DEVICE")]
+pub struct CgroupDevice {
+    pub(crate) data: ProgramData<CgroupDeviceLink>,
+}
+
+impl CgroupDevice {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        load_program(BPF_PROG_TYPE_CGROUP_DEVICE, &mut self.data)
+    }
+
+    /// Attaches the program to the given cgroup.
+    ///
+    /// The returned value can be used to detach, see [CgroupDevice::detach].
+    pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<CgroupDeviceLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), 0)
+    }
+
+    /// Attaches the program to the given cgroup in multi mode.
+    ///
+    /// Other programs can then be attached to the cgroup and its descendants,
+    /// and the program runs for the descendants after their own programs, see
+    /// [`cgroup`](crate::programs::cgroup).
+    ///
+    /// On kernels 5.7 and later the program is attached with a bpf link, which
+    /// is always in multi mode, so this is the same as [`CgroupDevice::attach`].
+    ///
+    /// The returned value can be used to detach, see [CgroupDevice::detach].
+    pub fn attach_multi<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+    ) -> Result<CgroupDeviceLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), BPF_F_ALLOW_MULTI)
+    }
+
+    fn attach_with_flags(
+        &mut self,
+        cgroup_fd: RawFd,
+        flags: u32,
+    ) -> Result<CgroupDeviceLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+
+        let k_ver = kernel_version().unwrap();
+        if k_ver >= (5, 7, 0) {
+            let link_fd = bpf_link_create(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE, None, 0)
+                .map_err(|(_, io_error)| cgroup_attach_error("bpf_link_create", io_error))?
+                as RawFd;
+            self.data
+                .links
+                .insert(CgroupDeviceLink(CgroupDeviceLinkInner::Fd(FdLink::new(
+                    link_fd,
+                ))))
+        } else {
+            bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE, flags)
+                .map_err(|(_, io_error)| cgroup_attach_error("bpf_prog_attach", io_error))?;
+
+            self.data
+                .links
+                .insert(CgroupDeviceLink(CgroupDeviceLinkInner::ProgAttach(
+                    ProgAttachLink::new(prog_fd, cgroup_fd, BPF_CGROUP_DEVICE),
+                )))
+        }
+    }
+
+    /// Attaches the program to the cgroup v2 directory at `path`, see
+    /// [`CgroupDevice::attach`].
+    pub fn attach_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<CgroupDeviceLinkId, ProgramError> {
+        let cgroup = open_cgroup(path.as_ref())?;
+        self.attach(cgroup)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: CgroupDeviceLinkId,
+    ) -> Result<CgroupDeviceLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [CgroupDevice::attach].
+    pub fn detach(&mut self, link_id: CgroupDeviceLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+}
+
+#[derive(Debug, Hash, Eq, PartialEq)]
+enum CgroupDeviceLinkIdInner {
+    Fd(<FdLink as Link>::Id),
+    ProgAttach(<ProgAttachLink as Link>::Id),
+}
+
+#[derive(Debug)]
+enum CgroupDeviceLinkInner {
+    Fd(FdLink),
+    ProgAttach(ProgAttachLink),
+}
+
+impl Link for CgroupDeviceLinkInner {
+    type Id = CgroupDeviceLinkIdInner;
+
+    fn id(&self) -> Self::Id {
+        match self {
+            CgroupDeviceLinkInner::Fd(fd) => CgroupDeviceLinkIdInner::Fd(fd.id()),
+            CgroupDeviceLinkInner::ProgAttach(p) => CgroupDeviceLinkIdInner::ProgAttach(p.id()),
+        }
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        match self {
+            CgroupDeviceLinkInner::Fd(fd) => fd.detach(),
+            CgroupDeviceLinkInner::ProgAttach(p) => p.detach(),
+        }
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [CgroupDevice] programs.
+    CgroupDeviceLink,
+    /// The type returned by [CgroupDevice::attach]. Can be passed to [CgroupDevice::detach].
+    CgroupDeviceLinkId,
+    CgroupDeviceLinkInner,
+    CgroupDeviceLinkIdInner
+);
@@ -2,15 +2,18 @@
 use std::{
     hash::Hash,
     os::unix::prelude::{AsRawFd, RawFd},
+    path::Path,
 };
 
 use crate::{
     generated::{
         bpf_attach_type::{BPF_CGROUP_INET_EGRESS, BPF_CGROUP_INET_INGRESS},
         bpf_prog_type::BPF_PROG_TYPE_CGROUP_SKB,
+        BPF_F_ALLOW_MULTI,
     },
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_attach_error, define_link_wrapper, load_program, utils::open_cgroup, FdLink, Link,
+        ProgAttachLink, ProgramData, ProgramError,
     },
     sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
@@ -87,9 +90,35 @@ impl CgroupSkb {
         &mut self,
         cgroup: T,
         attach_type: CgroupSkbAttachType,
+    ) -> Result<CgroupSkbLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), attach_type, 0)
+    }
+
+    /// Attaches the program to the given cgroup in multi mode.
+    ///
+    /// Other programs can then be attached to the cgroup and its descendants,
+    /// and the program runs for the descendants after their own programs, see
+    /// [`cgroup`](crate::programs::cgroup).
+    ///
+    /// On kernels 5.7 and later the program is attached with a bpf link, which
+    /// is always in multi mode, so this is the same as [`CgroupSkb::attach`].
+    ///
+    /// The returned value can be used to detach, see [CgroupSkb::detach].
+    pub fn attach_multi<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+        attach_type: CgroupSkbAttachType,
+    ) -> Result<CgroupSkbLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), attach_type, BPF_F_ALLOW_MULTI)
+    }
+
+    fn attach_with_flags(
+        &mut self,
+        cgroup_fd: RawFd,
+        attach_type: CgroupSkbAttachType,
+        flags: u32,
     ) -> Result<CgroupSkbLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
-        let cgroup_fd = cgroup.as_raw_fd();
 
         let attach_type = match attach_type {
             CgroupSkbAttachType::Ingress => BPF_CGROUP_INET_INGRESS,
@@ -97,22 +126,15 @@ impl CgroupSkb {
         };
         let k_ver = kernel_version().unwrap();
         if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
+            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0)
+                .map_err(|(_, io_error)| cgroup_attach_error("bpf_link_create", io_error))?
+                as RawFd;
             self.data
                 .links
                 .insert(CgroupSkbLink(CgroupSkbLinkInner::Fd(FdLink::new(link_fd))))
         } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, attach_type).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
+            bpf_prog_attach(prog_fd, cgroup_fd, attach_type, flags)
+                .map_err(|(_, io_error)| cgroup_attach_error("bpf_prog_attach", io_error))?;
 
             self.data
                 .links
@@ -122,6 +144,17 @@ impl CgroupSkb {
         }
     }
 
+    /// Attaches the program to the cgroup v2 directory at `path`, see
+    /// [`CgroupSkb::attach`].
+    pub fn attach_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        attach_type: CgroupSkbAttachType,
+    ) -> Result<CgroupSkbLinkId, ProgramError> {
+        let cgroup = open_cgroup(path.as_ref())?;
+        self.attach(cgroup, attach_type)
+    }
+
     /// Takes ownership of the link referenced by the provided link_id.
     ///
     /// The link will be detached on `Drop` and the caller is now responsible
@@ -185,3 +218,64 @@ pub enum CgroupSkbAttachType {
     /// Attach to egress.
     Egress,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, fs::File, os::unix::io::IntoRawFd};
+
+    use libc::EINVAL;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::ProgramSection,
+        sys::{override_syscall, Syscall},
+    };
+
+    #[test]
+    fn test_attach_path() {
+        thread_local! {
+            static TARGET_FD: Cell<u32> = const { Cell::new(0) };
+        }
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH,
+                attr,
+            } => {
+                TARGET_FD.with(|fd| fd.set(unsafe { attr.__bindgen_anon_5.target_fd }));
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                TARGET_FD.with(|fd| fd.set(unsafe { attr.link_create.__bindgen_anon_1.target_fd }));
+                Ok(File::open("/dev/null").unwrap().into_raw_fd() as i64)
+            }
+            _ => Err((-1, std::io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let mut prog = CgroupSkb {
+            data: ProgramData::from_fd(
+                None,
+                42,
+                ProgramSection::CgroupSkb {
+                    name: "skb".to_owned(),
+                },
+            ),
+            expected_attach_type: None,
+        };
+        assert!(matches!(
+            prog.attach_path(
+                "/sys/fs/cgroup/does-not-exist",
+                CgroupSkbAttachType::Ingress
+            ),
+            Err(ProgramError::InvalidCgroup { .. })
+        ));
+        assert_eq!(TARGET_FD.with(|fd| fd.get()), 0);
+
+        prog.attach_path(std::env::temp_dir(), CgroupSkbAttachType::Ingress)
+            .unwrap();
+        assert_ne!(TARGET_FD.with(|fd| fd.get()), 0);
+    }
+}
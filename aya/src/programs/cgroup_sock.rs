@@ -5,12 +5,14 @@ use crate::generated::bpf_attach_type;
 use std::{
     hash::Hash,
     os::unix::prelude::{AsRawFd, RawFd},
+    path::Path,
 };
 
 use crate::{
-    generated::bpf_prog_type::BPF_PROG_TYPE_CGROUP_SOCK,
+    generated::{bpf_prog_type::BPF_PROG_TYPE_CGROUP_SOCK, BPF_F_ALLOW_MULTI},
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_attach_error, define_link_wrapper, load_program, utils::open_cgroup, FdLink, Link,
+        ProgAttachLink, ProgramData, ProgramError,
     },
     sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
@@ -68,29 +70,46 @@ impl CgroupSock {
     ///
     /// The returned value can be used to detach, see [CgroupSock::detach].
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<CgroupSockLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), 0)
+    }
+
+    /// Attaches the program to the given cgroup in multi mode.
+    ///
+    /// Other programs can then be attached to the cgroup and its descendants,
+    /// and the program runs for the descendants after their own programs, see
+    /// [`cgroup`](crate::programs::cgroup).
+    ///
+    /// On kernels 5.7 and later the program is attached with a bpf link, which
+    /// is always in multi mode, so this is the same as [`CgroupSock::attach`].
+    ///
+    /// The returned value can be used to detach, see [CgroupSock::detach].
+    pub fn attach_multi<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+    ) -> Result<CgroupSockLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), BPF_F_ALLOW_MULTI)
+    }
+
+    fn attach_with_flags(
+        &mut self,
+        cgroup_fd: RawFd,
+        flags: u32,
+    ) -> Result<CgroupSockLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
-        let cgroup_fd = cgroup.as_raw_fd();
         let attach_type = self.data.expected_attach_type.unwrap();
         let k_ver = kernel_version().unwrap();
         if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
+            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0)
+                .map_err(|(_, io_error)| cgroup_attach_error("bpf_link_create", io_error))?
+                as RawFd;
             self.data
                 .links
                 .insert(CgroupSockLink(CgroupSockLinkInner::Fd(FdLink::new(
                     link_fd,
                 ))))
         } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, attach_type).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
+            bpf_prog_attach(prog_fd, cgroup_fd, attach_type, flags)
+                .map_err(|(_, io_error)| cgroup_attach_error("bpf_prog_attach", io_error))?;
 
             self.data
                 .links
@@ -100,6 +119,16 @@ impl CgroupSock {
         }
     }
 
+    /// Attaches the program to the cgroup v2 directory at `path`, see
+    /// [`CgroupSock::attach`].
+    pub fn attach_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<CgroupSockLinkId, ProgramError> {
+        let cgroup = open_cgroup(path.as_ref())?;
+        self.attach(cgroup)
+    }
+
     /// Takes ownership of the link referenced by the provided link_id.
     ///
     /// The link will be detached on `Drop` and the caller is now responsible
@@ -162,9 +191,9 @@ pub enum CgroupSockAttachType {
     PostBind4,
     /// Called after the IPv6 bind events.
     PostBind6,
-    /// Attach to IPv4 connect events.
+    /// Called when a socket is created.
     SockCreate,
-    /// Attach to IPv6 connect events.
+    /// Called when a socket is released.
     SockRelease,
 }
 
@@ -5,12 +5,14 @@ use crate::generated::bpf_attach_type;
 use std::{
     hash::Hash,
     os::unix::prelude::{AsRawFd, RawFd},
+    path::Path,
 };
 
 use crate::{
-    generated::bpf_prog_type::BPF_PROG_TYPE_CGROUP_SOCK_ADDR,
+    generated::{bpf_prog_type::BPF_PROG_TYPE_CGROUP_SOCK_ADDR, BPF_F_ALLOW_MULTI},
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        cgroup_attach_error, define_link_wrapper, load_program, utils::open_cgroup, FdLink, Link,
+        ProgAttachLink, ProgramData, ProgramError,
     },
     sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
@@ -69,29 +71,46 @@ impl CgroupSockAddr {
     ///
     /// The returned value can be used to detach, see [CgroupSockAddr::detach].
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<CgroupSockAddrLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), 0)
+    }
+
+    /// Attaches the program to the given cgroup in multi mode.
+    ///
+    /// Other programs can then be attached to the cgroup and its descendants,
+    /// and the program runs for the descendants after their own programs, see
+    /// [`cgroup`](crate::programs::cgroup).
+    ///
+    /// On kernels 5.7 and later the program is attached with a bpf link, which
+    /// is always in multi mode, so this is the same as [`CgroupSockAddr::attach`].
+    ///
+    /// The returned value can be used to detach, see [CgroupSockAddr::detach].
+    pub fn attach_multi<T: AsRawFd>(
+        &mut self,
+        cgroup: T,
+    ) -> Result<CgroupSockAddrLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), BPF_F_ALLOW_MULTI)
+    }
+
+    fn attach_with_flags(
+        &mut self,
+        cgroup_fd: RawFd,
+        flags: u32,
+    ) -> Result<CgroupSockAddrLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
-        let cgroup_fd = cgroup.as_raw_fd();
         let attach_type = self.data.expected_attach_type.unwrap();
         let k_ver = kernel_version().unwrap();
         if k_ver >= (5, 7, 0) {
-            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
+            let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0)
+                .map_err(|(_, io_error)| cgroup_attach_error("bpf_link_create", io_error))?
+                as RawFd;
             self.data
                 .links
                 .insert(CgroupSockAddrLink(CgroupSockAddrLinkInner::Fd(
                     FdLink::new(link_fd),
                 )))
         } else {
-            bpf_prog_attach(prog_fd, cgroup_fd, attach_type).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_prog_attach".to_owned(),
-                    io_error,
-                }
-            })?;
+            bpf_prog_attach(prog_fd, cgroup_fd, attach_type, flags)
+                .map_err(|(_, io_error)| cgroup_attach_error("bpf_prog_attach", io_error))?;
 
             self.data
                 .links
@@ -101,6 +120,16 @@ impl CgroupSockAddr {
         }
     }
 
+    /// Attaches the program to the cgroup v2 directory at `path`, see
+    /// [`CgroupSockAddr::attach`].
+    pub fn attach_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<CgroupSockAddrLinkId, ProgramError> {
+        let cgroup = open_cgroup(path.as_ref())?;
+        self.attach(cgroup)
+    }
+
     /// Takes ownership of the link referenced by the provided link_id.
     ///
     /// The link will be detached on `Drop` and the caller is now responsible
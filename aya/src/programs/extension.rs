@@ -6,9 +6,10 @@ use object::Endianness;
 
 use crate::{
     generated::{bpf_attach_type::BPF_CGROUP_INET_INGRESS, bpf_prog_type::BPF_PROG_TYPE_EXT},
-    obj::btf::BtfKind,
+    obj::btf::{BtfKind, BtfType, FuncLinkage},
     programs::{
-        define_link_wrapper, load_program, FdLink, FdLinkId, ProgramData, ProgramError, ProgramFd,
+        attach_error, define_link_wrapper, load_program, FdLink, FdLinkId, ProgramData,
+        ProgramError, ProgramFd,
     },
     sys::{self, bpf_link_create},
     Btf,
@@ -20,6 +21,13 @@ pub enum ExtensionError {
     /// Target BPF program does not have BTF loaded to the kernel.
     #[error("target BPF program does not have BTF loaded to the kernel")]
     NoBTF,
+
+    /// The target program has no extension slot with the given name.
+    #[error("`{name}` is not an extension slot of the target program")]
+    UnknownSlot {
+        /// The name of the slot.
+        name: String,
+    },
 }
 
 /// A program used to extend existing BPF programs.
@@ -91,10 +99,8 @@ impl Extension {
         let btf_id = self.data.attach_btf_id.ok_or(ProgramError::NotLoaded)?;
         // the attach type must be set as 0, which is bpf_attach_type::BPF_CGROUP_INET_INGRESS
         let link_fd = bpf_link_create(prog_fd, target_fd, BPF_CGROUP_INET_INGRESS, Some(btf_id), 0)
-            .map_err(|(_, io_error)| ProgramError::SyscallError {
-                call: "bpf_link_create".to_owned(),
-                io_error,
-            })? as RawFd;
+            .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+            as RawFd;
         self.data.links.insert(ExtensionLink(FdLink::new(link_fd)))
     }
 
@@ -119,10 +125,8 @@ impl Extension {
         let prog_fd = self.data.fd_or_err()?;
         // the attach type must be set as 0, which is bpf_attach_type::BPF_CGROUP_INET_INGRESS
         let link_fd = bpf_link_create(prog_fd, target_fd, BPF_CGROUP_INET_INGRESS, Some(btf_id), 0)
-            .map_err(|(_, io_error)| ProgramError::SyscallError {
-                call: "bpf_link_create".to_owned(),
-                io_error,
-            })? as RawFd;
+            .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+            as RawFd;
         self.data.links.insert(ExtensionLink(FdLink::new(link_fd)))
     }
 
@@ -146,6 +150,43 @@ impl Extension {
 /// Retrieves the FD of the BTF object for the provided `prog_fd` and the BTF ID of the function
 /// with the name `func_name` within that BTF object.
 fn get_btf_info(prog_fd: i32, func_name: &str) -> Result<(RawFd, u32), ProgramError> {
+    let (btf_fd, btf) = get_btf(prog_fd)?;
+
+    let btf_id = btf
+        .id_by_type_name_kind(func_name, BtfKind::Func)
+        .map_err(ProgramError::Btf)?;
+
+    Ok((btf_fd, btf_id))
+}
+
+/// Returns the names of the functions of the program loaded as `prog_fd` that
+/// can be replaced by an [`Extension`].
+///
+/// These are the global functions called by the program. The main function
+/// isn't included.
+pub(crate) fn extension_slots(prog_fd: RawFd) -> Result<Vec<String>, ProgramError> {
+    let func_info =
+        sys::bpf_prog_get_func_info(prog_fd).map_err(|io_error| ProgramError::SyscallError {
+            call: "bpf_prog_get_info_by_fd".to_owned(),
+            io_error,
+        })?;
+    let (btf_fd, btf) = get_btf(prog_fd)?;
+    unsafe { libc::close(btf_fd) };
+
+    let mut slots = Vec::new();
+    for info in func_info.iter().skip(1) {
+        let ty = btf.type_by_id(info.type_id).map_err(ProgramError::Btf)?;
+        if let BtfType::Func(func) = ty {
+            if func.linkage() == FuncLinkage::Global {
+                slots.push(btf.type_name(ty).map_err(ProgramError::Btf)?.into_owned());
+            }
+        }
+    }
+    Ok(slots)
+}
+
+/// Retrieves the FD and the contents of the BTF object for the provided `prog_fd`.
+fn get_btf(prog_fd: RawFd) -> Result<(RawFd, Btf), ProgramError> {
     // retrieve program information
     let info =
         sys::bpf_prog_get_info_by_fd(prog_fd).map_err(|io_error| ProgramError::SyscallError {
@@ -165,39 +206,14 @@ fn get_btf_info(prog_fd: i32, func_name: &str) -> Result<(RawFd, u32), ProgramEr
             io_error,
         })?;
 
-    // we need to read the btf bytes into a buffer but we don't know the size ahead of time.
-    // assume 4kb. if this is too small we can resize based on the size obtained in the response.
-    let mut buf = vec![0u8; 4096];
-    let btf_info = match sys::btf_obj_get_info_by_fd(btf_fd, &mut buf) {
-        Ok(info) => {
-            if info.btf_size > buf.len() as u32 {
-                buf.resize(info.btf_size as usize, 0u8);
-                let btf_info =
-                    sys::btf_obj_get_info_by_fd(btf_fd, &mut buf).map_err(|io_error| {
-                        ProgramError::SyscallError {
-                            call: "bpf_prog_get_info_by_fd".to_owned(),
-                            io_error,
-                        }
-                    })?;
-                Ok(btf_info)
-            } else {
-                Ok(info)
-            }
-        }
-        Err(io_error) => Err(ProgramError::SyscallError {
-            call: "bpf_prog_get_info_by_fd".to_owned(),
-            io_error,
-        }),
-    }?;
-
-    let btf = Btf::parse(&buf[0..btf_info.btf_size as usize], Endianness::default())
-        .map_err(ProgramError::Btf)?;
+    let buf = sys::btf_obj_get_data(btf_fd).map_err(|io_error| ProgramError::SyscallError {
+        call: "bpf_prog_get_info_by_fd".to_owned(),
+        io_error,
+    })?;
 
-    let btf_id = btf
-        .id_by_type_name_kind(func_name, BtfKind::Func)
-        .map_err(ProgramError::Btf)?;
+    let btf = Btf::parse(&buf, Endianness::default()).map_err(ProgramError::Btf)?;
 
-    Ok((btf_fd, btf_id))
+    Ok((btf_fd, btf))
 }
 
 define_link_wrapper!(
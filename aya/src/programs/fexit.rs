@@ -9,12 +9,12 @@ use crate::{
     },
 };
 
-/// A program that can be attached to the exit point of (almost) anny kernel
+/// A program that can be attached to the exit point of (almost) any kernel
 /// function.
 ///
 /// [`FExit`] programs are similar to [kretprobes](crate::programs::KProbe),
 /// but the difference is that fexit has practically zero overhead to call
-/// before kernel function. Fexit programs can be also attached to other eBPF
+/// after kernel function. Fexit programs can be also attached to other eBPF
 /// programs.
 ///
 /// # Minimum kernel version
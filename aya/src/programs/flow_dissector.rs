@@ -0,0 +1,154 @@
+//! Flow dissector programs.
+use std::{
+    hash::Hash,
+    os::unix::prelude::{AsRawFd, RawFd},
+};
+
+use crate::{
+    generated::{bpf_attach_type::BPF_FLOW_DISSECTOR, bpf_prog_type::BPF_PROG_TYPE_FLOW_DISSECTOR},
+    programs::{
+        attach_error, define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData,
+        ProgramError,
+    },
+    sys::{bpf_link_create, bpf_prog_attach, kernel_version},
+};
+
+/// A program used to dissect packets into flow keys.
+///
+/// [`FlowDissector`] programs are attached to network namespaces and replace
+/// the kernel's built-in flow dissector. They parse the headers of a packet
+/// and fill in the `bpf_flow_keys` used for example by RPS, the
+/// `flower` classifier and hashing.
+///
+/// Only one flow dissector can be attached to a network namespace at a time.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.20.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(Debug, thiserror::Error)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     IO(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     Map(#[from] aya::maps::MapError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use std::fs::File;
+/// use aya::programs::FlowDissector;
+///
+/// let file = File::open("/proc/self/ns/net")?;
+/// let program: &mut FlowDissector = bpf.program_mut("dissect").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach(file)?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_FLOW_DISSECTOR")]
+pub struct FlowDissector {
+    pub(crate) data: ProgramData<FlowDissectorLink>,
+}
+
+impl FlowDissector {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_FLOW_DISSECTOR);
+        load_program(BPF_PROG_TYPE_FLOW_DISSECTOR, &mut self.data)
+    }
+
+    /// Attaches the program to the given network namespace.
+    ///
+    /// The program is attached with a link on kernels 5.8 and later, and
+    /// with `BPF_PROG_ATTACH` on older kernels.
+    ///
+    /// The returned value can be used to detach, see [FlowDissector::detach].
+    pub fn attach<T: AsRawFd>(&mut self, netns: T) -> Result<FlowDissectorLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let netns_fd = netns.as_raw_fd();
+
+        let k_ver = kernel_version().unwrap();
+        if k_ver >= (5, 8, 0) {
+            let link_fd = bpf_link_create(prog_fd, netns_fd, BPF_FLOW_DISSECTOR, None, 0)
+                .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+                as RawFd;
+            self.data
+                .links
+                .insert(FlowDissectorLink(FlowDissectorLinkInner::Fd(FdLink::new(
+                    link_fd,
+                ))))
+        } else {
+            bpf_prog_attach(prog_fd, netns_fd, BPF_FLOW_DISSECTOR, 0)
+                .map_err(|(_, io_error)| attach_error("bpf_prog_attach", io_error))?;
+
+            self.data
+                .links
+                .insert(FlowDissectorLink(FlowDissectorLinkInner::ProgAttach(
+                    ProgAttachLink::new(prog_fd, netns_fd, BPF_FLOW_DISSECTOR),
+                )))
+        }
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: FlowDissectorLinkId,
+    ) -> Result<FlowDissectorLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [FlowDissector::attach].
+    pub fn detach(&mut self, link_id: FlowDissectorLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+}
+
+#[derive(Debug, Hash, Eq, PartialEq)]
+enum FlowDissectorLinkIdInner {
+    Fd(<FdLink as Link>::Id),
+    ProgAttach(<ProgAttachLink as Link>::Id),
+}
+
+#[derive(Debug)]
+enum FlowDissectorLinkInner {
+    Fd(FdLink),
+    ProgAttach(ProgAttachLink),
+}
+
+impl Link for FlowDissectorLinkInner {
+    type Id = FlowDissectorLinkIdInner;
+
+    fn id(&self) -> Self::Id {
+        match self {
+            FlowDissectorLinkInner::Fd(fd) => FlowDissectorLinkIdInner::Fd(fd.id()),
+            FlowDissectorLinkInner::ProgAttach(p) => FlowDissectorLinkIdInner::ProgAttach(p.id()),
+        }
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        match self {
+            FlowDissectorLinkInner::Fd(fd) => fd.detach(),
+            FlowDissectorLinkInner::ProgAttach(p) => p.detach(),
+        }
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [FlowDissector] programs.
+    FlowDissectorLink,
+    /// The type returned by [FlowDissector::attach]. Can be passed to [FlowDissector::detach].
+    FlowDissectorLinkId,
+    FlowDissectorLinkInner,
+    FlowDissectorLinkIdInner
+);
@@ -0,0 +1,95 @@
+//! Modify return programs.
+
+use crate::{
+    generated::{bpf_attach_type::BPF_MODIFY_RETURN, bpf_prog_type::BPF_PROG_TYPE_TRACING},
+    obj::btf::{Btf, BtfKind},
+    programs::{
+        define_link_wrapper, load_program, utils::attach_raw_tracepoint, FdLink, FdLinkId,
+        ProgramData, ProgramError,
+    },
+};
+
+/// A program that can override the return value of a kernel function.
+///
+/// [`FModRet`] programs run before the kernel function they're attached to,
+/// like [`FEntry`](crate::programs::FEntry) programs. If the program returns
+/// a non-zero value, the function isn't called and the value is returned to
+/// its caller instead. This is used for error injection, and only works on
+/// the functions the kernel allows it for, like the ones marked with
+/// `ALLOW_ERROR_INJECTION` and the LSM hooks.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.7.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     BtfError(#[from] aya::BtfError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError),
+/// # }
+/// # let mut bpf = Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{Bpf, programs::FModRet, BtfError, Btf};
+///
+/// let btf = Btf::from_sys_fs()?;
+/// let program: &mut FModRet = bpf.program_mut("should_fail_alloc_page").unwrap().try_into()?;
+/// program.load("should_fail_alloc_page", &btf)?;
+/// program.attach()?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_MODIFY_RETURN")]
+#[doc(alias = "BPF_PROG_TYPE_TRACING")]
+pub struct FModRet {
+    pub(crate) data: ProgramData<FModRetLink>,
+}
+
+impl FModRet {
+    /// Loads the program inside the kernel.
+    ///
+    /// Loads the program so it's executed when the kernel function `fn_name`
+    /// is called, and can override its return value. The `btf` argument must
+    /// contain the BTF info for the running kernel.
+    pub fn load(&mut self, fn_name: &str, btf: &Btf) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_MODIFY_RETURN);
+        self.data.attach_btf_id = Some(btf.id_by_type_name_kind(fn_name, BtfKind::Func)?);
+        load_program(BPF_PROG_TYPE_TRACING, &mut self.data)
+    }
+
+    /// Attaches the program.
+    ///
+    /// The returned value can be used to detach, see [FModRet::detach].
+    pub fn attach(&mut self) -> Result<FModRetLinkId, ProgramError> {
+        attach_raw_tracepoint(&mut self.data, None)
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [FModRet::attach].
+    pub fn detach(&mut self, link_id: FModRetLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(&mut self, link_id: FModRetLinkId) -> Result<FModRetLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [FModRet] programs.
+    FModRetLink,
+    /// The type returned by [FModRet::attach]. Can be passed to [FModRet::detach].
+    FModRetLinkId,
+    FdLink,
+    FdLinkId
+);
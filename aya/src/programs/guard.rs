@@ -0,0 +1,454 @@
+//! Rollback of partially configured datapaths.
+//!
+//! Setting up a datapath often takes several steps, like adding a `clsact`
+//! qdisc and attaching a few programs. If the process dies half way through,
+//! the interfaces are left half configured. A [`DetachGuard`] records each
+//! step and undoes them all, in reverse order, unless it's committed.
+//!
+//! A guard dropped while its thread unwinds from a panic rolls back its
+//! changes like any other uncommitted guard. With `panic = "abort"` nothing
+//! is unwound, so nothing is rolled back. Signals like `SIGTERM` and `SIGINT`
+//! terminate the process without running destructors either, unless
+//! [`install_signal_handlers`] is called to roll back all the uncommitted
+//! guards first. No global handler is installed otherwise.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # let mut bpf = aya::Bpf::load(&[])?;
+//! use aya::programs::{
+//!     guard::{install_signal_handlers, DetachGuard},
+//!     SchedClassifier, TcAttachType,
+//! };
+//!
+//! install_signal_handlers()?;
+//!
+//! let mut guard = DetachGuard::new();
+//! guard.add_clsact("eth0")?;
+//! let program: &mut SchedClassifier = bpf.program_mut("ingress").unwrap().try_into()?;
+//! program.load()?;
+//! let link_id = program.attach("eth0", TcAttachType::Ingress, 0)?;
+//! let link = program.take_link(link_id)?;
+//! guard.track(&link)?;
+//! // ... more setup ...
+//! guard.commit();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use std::{
+    collections::BTreeMap,
+    io, mem,
+    os::unix::io::RawFd,
+    sync::{
+        atomic::{AtomicI32, AtomicU64, Ordering},
+        Mutex, Once,
+    },
+    thread,
+};
+
+use libc::{c_int, EEXIST, SIGINT, SIGTERM, SIG_DFL};
+
+use crate::{
+    programs::{ProgramError, TcAttachType},
+    sys::{
+        bpf_link_detach, netlink_qdisc_add_clsact, netlink_qdisc_del_clsact, netlink_qdisc_detach,
+        netlink_set_xdp_fd,
+    },
+    util::ifindex_from_ifname,
+};
+
+lazy_static! {
+    static ref GUARDS: Mutex<BTreeMap<u64, Vec<Rollback>>> = Mutex::new(BTreeMap::new());
+}
+
+static NEXT_GUARD_ID: AtomicU64 = AtomicU64::new(0);
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+static INSTALL: Once = Once::new();
+
+/// A change that can be undone by a [`DetachGuard`].
+#[derive(Debug)]
+pub struct Rollback(RollbackInner);
+
+#[derive(Debug)]
+enum RollbackInner {
+    // a duplicate of the link fd, so the link can be detached even if the
+    // original fd was closed
+    BpfLink(RawFd),
+    Xdp {
+        if_index: i32,
+        prog_fd: RawFd,
+        flags: u32,
+    },
+    Tc {
+        if_index: i32,
+        attach_type: TcAttachType,
+        priority: u16,
+    },
+    Clsact {
+        if_index: i32,
+    },
+}
+
+impl Rollback {
+    pub(crate) fn bpf_link(fd: RawFd) -> Result<Rollback, ProgramError> {
+        Ok(Rollback(RollbackInner::BpfLink(dup_fd(fd)?)))
+    }
+
+    pub(crate) fn xdp(if_index: i32, prog_fd: RawFd, flags: u32) -> Result<Rollback, ProgramError> {
+        Ok(Rollback(RollbackInner::Xdp {
+            if_index,
+            prog_fd: dup_fd(prog_fd)?,
+            flags,
+        }))
+    }
+
+    pub(crate) fn tc(if_index: i32, attach_type: TcAttachType, priority: u16) -> Rollback {
+        Rollback(RollbackInner::Tc {
+            if_index,
+            attach_type,
+            priority,
+        })
+    }
+
+    fn run(self) {
+        self.undo();
+        self.discard();
+    }
+
+    fn undo(&self) {
+        // errors are ignored, since the change may have been undone already
+        match self.0 {
+            RollbackInner::BpfLink(fd) => {
+                let _ = bpf_link_detach(fd);
+            }
+            RollbackInner::Xdp {
+                if_index,
+                prog_fd,
+                flags,
+            } => {
+                let _ = unsafe { netlink_set_xdp_fd(if_index, -1, Some(prog_fd), flags) };
+            }
+            RollbackInner::Tc {
+                if_index,
+                attach_type,
+                priority,
+            } => {
+                let _ = unsafe { netlink_qdisc_detach(if_index, &attach_type, priority) };
+            }
+            RollbackInner::Clsact { if_index } => {
+                let _ = unsafe { netlink_qdisc_del_clsact(if_index) };
+            }
+        }
+    }
+
+    fn discard(self) {
+        match self.0 {
+            RollbackInner::BpfLink(fd) | RollbackInner::Xdp { prog_fd: fd, .. } => unsafe {
+                libc::close(fd);
+            },
+            RollbackInner::Tc { .. } | RollbackInner::Clsact { .. } => {}
+        }
+    }
+}
+
+pub(crate) mod sealed {
+    use super::Rollback;
+    use crate::programs::ProgramError;
+
+    pub trait Sealed {
+        fn rollback(&self) -> Result<Rollback, ProgramError>;
+    }
+}
+
+/// A link that can be tracked by a [`DetachGuard`].
+///
+/// This is implemented by [`FdLink`](crate::programs::links::FdLink),
+/// [`XdpLink`](crate::programs::xdp::XdpLink) and
+/// [`SchedClassifierLink`](crate::programs::tc::SchedClassifierLink).
+pub trait TrackedLink: sealed::Sealed {}
+
+impl<T: sealed::Sealed> TrackedLink for T {}
+
+/// Undoes a sequence of attach operations unless committed.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Debug)]
+pub struct DetachGuard {
+    id: u64,
+}
+
+impl DetachGuard {
+    /// Creates a new guard.
+    pub fn new() -> DetachGuard {
+        let id = NEXT_GUARD_ID.fetch_add(1, Ordering::Relaxed);
+        guards().insert(id, Vec::new());
+        DetachGuard { id }
+    }
+
+    /// Records a link, so it's detached on rollback.
+    ///
+    /// The link isn't owned by the guard and must be kept alive until the
+    /// guard is committed.
+    pub fn track<L: TrackedLink>(&mut self, link: &L) -> Result<(), ProgramError> {
+        let rollback = link.rollback()?;
+        self.push(rollback);
+        Ok(())
+    }
+
+    /// Adds the `clsact` qdisc to the given interface, removing it on
+    /// rollback.
+    ///
+    /// If the interface already has a `clsact` qdisc, this does nothing and
+    /// the qdisc is not removed on rollback.
+    pub fn add_clsact(&mut self, if_name: &str) -> Result<(), io::Error> {
+        let if_index = ifindex_from_ifname(if_name)? as i32;
+        match unsafe { netlink_qdisc_add_clsact(if_index) } {
+            Ok(()) => {
+                self.push(Rollback(RollbackInner::Clsact { if_index }));
+                Ok(())
+            }
+            Err(e) if e.raw_os_error() == Some(EEXIST) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Commits the changes, so they're no longer rolled back.
+    pub fn commit(self) {
+        if let Some(rollbacks) = guards().remove(&self.id) {
+            rollbacks.into_iter().for_each(Rollback::discard);
+        }
+        mem::forget(self);
+    }
+
+    /// Rolls back all the changes recorded so far.
+    ///
+    /// This is what happens when the guard is dropped without being
+    /// committed.
+    pub fn rollback(self) {
+        drop(self)
+    }
+
+    fn push(&mut self, rollback: Rollback) {
+        match guards().get_mut(&self.id) {
+            Some(rollbacks) => rollbacks.push(rollback),
+            // the guard was already rolled back by a rollback handler
+            None => rollback.run(),
+        }
+    }
+}
+
+impl Default for DetachGuard {
+    fn default() -> Self {
+        DetachGuard::new()
+    }
+}
+
+impl Drop for DetachGuard {
+    fn drop(&mut self) {
+        let rollbacks = guards().remove(&self.id);
+        if let Some(rollbacks) = rollbacks {
+            rollbacks.into_iter().rev().for_each(Rollback::run);
+        }
+    }
+}
+
+/// Rolls back all the uncommitted [`DetachGuard`]s when the process receives
+/// `SIGTERM` or `SIGINT`.
+///
+/// The rollback runs on a dedicated thread, after which the default action
+/// of the signal is restored and the signal raised again, terminating the
+/// process. This replaces any handler previously installed for those
+/// signals, so applications that handle them themselves shouldn't call this.
+///
+/// Calling this more than once has no effect.
+pub fn install_signal_handlers() -> Result<(), io::Error> {
+    let mut result = Ok(());
+    INSTALL.call_once(|| result = install());
+    result
+}
+
+fn install() -> Result<(), io::Error> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let [read_fd, write_fd] = fds;
+    SIGNAL_PIPE.store(write_fd, Ordering::SeqCst);
+
+    thread::Builder::new()
+        .name("aya-rollback".to_owned())
+        .spawn(move || {
+            let mut sig = 0u8;
+            loop {
+                let ret = unsafe { libc::read(read_fd, &mut sig as *mut _ as *mut _, 1) };
+                if ret == 1 {
+                    break;
+                }
+                if ret < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return;
+            }
+            rollback_all(&mut guards());
+            unsafe {
+                libc::signal(sig as c_int, SIG_DFL);
+                libc::raise(sig as c_int);
+            }
+        })?;
+
+    for signal in [SIGTERM, SIGINT] {
+        let handler = on_signal as extern "C" fn(c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+extern "C" fn on_signal(sig: c_int) {
+    // only async-signal-safe calls are allowed here, so the rollback is left
+    // to the rollback thread
+    let sig = sig as u8;
+    unsafe {
+        libc::write(
+            SIGNAL_PIPE.load(Ordering::SeqCst),
+            &sig as *const _ as *const _,
+            1,
+        )
+    };
+}
+
+fn rollback_all(guards: &mut BTreeMap<u64, Vec<Rollback>>) {
+    // undo the most recent guards first
+    while let Some((_, rollbacks)) = guards.pop_last() {
+        rollbacks.into_iter().rev().for_each(Rollback::run);
+    }
+}
+
+fn guards() -> std::sync::MutexGuard<'static, BTreeMap<u64, Vec<Rollback>>> {
+    // a panic while holding the lock doesn't leave the map inconsistent
+    GUARDS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn dup_fd(fd: RawFd) -> Result<RawFd, ProgramError> {
+    let fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(ProgramError::SyscallError {
+            call: "fcntl".to_owned(),
+            io_error: io::Error::last_os_error(),
+        });
+    }
+    Ok(fd)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, fs::File, os::unix::io::AsRawFd, panic};
+
+    use crate::{
+        generated::bpf_cmd,
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    thread_local! {
+        // the link fds detached on this thread, in order
+        static DETACHED: RefCell<Vec<RawFd>> = const { RefCell::new(Vec::new()) };
+        // the types of the netlink requests sent on this thread, in order
+        static NETLINK_REQUESTS: RefCell<Vec<u16>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn fake_syscall(call: Syscall) -> SysResult {
+        match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_DETACH,
+                attr,
+            } => {
+                let fd = unsafe { attr.link_detach.link_fd } as RawFd;
+                DETACHED.with(|detached| detached.borrow_mut().push(fd));
+                Ok(0)
+            }
+            Syscall::Netlink { msg } => {
+                let nlmsg_type = u16::from_ne_bytes([msg[4], msg[5]]);
+                NETLINK_REQUESTS.with(|requests| requests.borrow_mut().push(nlmsg_type));
+                // the interface already has a clsact qdisc
+                if nlmsg_type == libc::RTM_NEWQDISC {
+                    Err((-1, io::Error::from_raw_os_error(EEXIST)))
+                } else {
+                    Ok(0)
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        }
+    }
+
+    // returns the rollback of a link along with the fd it detaches
+    fn link_rollback(file: &File) -> (Rollback, RawFd) {
+        let rollback = Rollback::bpf_link(file.as_raw_fd()).unwrap();
+        let fd = match rollback.0 {
+            RollbackInner::BpfLink(fd) => fd,
+            _ => unreachable!(),
+        };
+        (rollback, fd)
+    }
+
+    fn take_detached() -> Vec<RawFd> {
+        DETACHED.with(|detached| detached.take())
+    }
+
+    #[test]
+    fn test_commit_and_rollback() {
+        override_syscall(fake_syscall);
+        let file = File::open("/dev/null").unwrap();
+
+        let mut guard = DetachGuard::new();
+        let id = guard.id;
+        guard.push(link_rollback(&file).0);
+        guard.push(link_rollback(&file).0);
+        assert_eq!(guards()[&id].len(), 2);
+        guard.commit();
+        assert!(!guards().contains_key(&id));
+        assert!(take_detached().is_empty());
+
+        let mut guard = DetachGuard::new();
+        let id = guard.id;
+        let (first, first_fd) = link_rollback(&file);
+        let (second, second_fd) = link_rollback(&file);
+        guard.push(first);
+        guard.push(second);
+        drop(guard);
+        assert!(!guards().contains_key(&id));
+        // undone in reverse order
+        assert_eq!(take_detached(), vec![second_fd, first_fd]);
+    }
+
+    #[test]
+    fn test_rollback_on_unwind() {
+        override_syscall(fake_syscall);
+        let file = File::open("/dev/null").unwrap();
+
+        let (rollback, fd) = link_rollback(&file);
+        let result = panic::catch_unwind(move || {
+            let mut guard = DetachGuard::new();
+            guard.push(rollback);
+            panic!("setup failed");
+        });
+        assert!(result.is_err());
+        assert_eq!(take_detached(), vec![fd]);
+    }
+
+    #[test]
+    fn test_add_existing_clsact() {
+        override_syscall(fake_syscall);
+
+        let mut guard = DetachGuard::new();
+        guard.add_clsact("lo").unwrap();
+        guard.rollback();
+        // the qdisc was there before, so it's left in place
+        assert_eq!(
+            NETLINK_REQUESTS.with(|requests| requests.take()),
+            vec![libc::RTM_NEWQDISC]
+        );
+    }
+}
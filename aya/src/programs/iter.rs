@@ -0,0 +1,143 @@
+//! BPF iterators.
+use std::{
+    fs::File,
+    os::unix::io::{FromRawFd, RawFd},
+};
+
+use crate::{
+    generated::{bpf_attach_type::BPF_TRACE_ITER, bpf_prog_type::BPF_PROG_TYPE_TRACING},
+    obj::btf::{Btf, BtfKind},
+    programs::{
+        attach_error, define_link_wrapper, links::LinkError, load_program, FdLink, FdLinkId,
+        ProgramData, ProgramError,
+    },
+    sys::{bpf_create_iter, bpf_link_create},
+};
+
+/// A BPF iterator, which dumps kernel data structures.
+///
+/// Iterator programs are called once for each element of a kernel data
+/// structure, like the tasks or the elements of a map, and write their
+/// output with the `bpf_seq_write` and `bpf_seq_printf` helpers. Each time
+/// the iterator link is opened with [`IterLink::file`] or
+/// [`IterLink::into_file`], a new iteration starts, and its output is read
+/// from the returned file.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     BtfError(#[from] aya::BtfError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Link(#[from] aya::programs::links::LinkError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError),
+/// #     #[error(transparent)]
+/// #     Io(#[from] std::io::Error),
+/// # }
+/// # let mut bpf = aya::Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{programs::Iter, Btf};
+/// use std::io::Read;
+///
+/// let btf = Btf::from_sys_fs()?;
+/// let program: &mut Iter = bpf.program_mut("task").unwrap().try_into()?;
+/// program.load("task", &btf)?;
+///
+/// let link_id = program.attach()?;
+/// let link = program.take_link(link_id)?;
+/// let mut file = link.file()?;
+///
+/// let mut output = String::new();
+/// file.read_to_string(&mut output)?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_TRACE_ITER")]
+#[doc(alias = "BPF_PROG_TYPE_TRACING")]
+pub struct Iter {
+    pub(crate) data: ProgramData<IterLink>,
+}
+
+impl Iter {
+    /// Loads the program inside the kernel.
+    ///
+    /// `iter_type` is the kind of iterator, like `task` or `bpf_map`, which
+    /// is resolved to the `bpf_iter_<iter_type>` function of the
+    /// kernel BTF.
+    pub fn load(&mut self, iter_type: &str, btf: &Btf) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_TRACE_ITER);
+        let type_name = format!("bpf_iter_{}", iter_type);
+        self.data.attach_btf_id =
+            Some(btf.id_by_type_name_kind(type_name.as_str(), BtfKind::Func)?);
+        load_program(BPF_PROG_TYPE_TRACING, &mut self.data)
+    }
+
+    /// Attaches the program.
+    ///
+    /// The iterator doesn't run until the returned link is opened, see
+    /// [`IterLink::file`].
+    pub fn attach(&mut self) -> Result<IterLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let link_fd = bpf_link_create(prog_fd, 0, BPF_TRACE_ITER, None, 0)
+            .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+            as RawFd;
+        self.data.links.insert(IterLink(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [Iter::attach].
+    pub fn detach(&mut self, link_id: IterLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(&mut self, link_id: IterLinkId) -> Result<IterLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+impl IterLink {
+    /// Starts an iteration and returns the file its output is read from.
+    ///
+    /// The iteration runs as the file is read, and the file reaches its end
+    /// once every element has been visited. This can be called again to
+    /// start another iteration, each with its own file.
+    pub fn file(&self) -> Result<File, LinkError> {
+        let link_fd = self.0.fd.ok_or(LinkError::InvalidLink)?;
+        let fd = bpf_create_iter(link_fd).map_err(|(code, io_error)| LinkError::SyscallError {
+            call: "bpf_iter_create".to_owned(),
+            code,
+            io_error,
+        })? as RawFd;
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Starts an iteration and returns the file its output is read from,
+    /// releasing the link.
+    ///
+    /// The returned file keeps the program alive, see [`IterLink::file`].
+    pub fn into_file(self) -> Result<File, LinkError> {
+        self.file()
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [Iter] programs.
+    IterLink,
+    /// The type returned by [Iter::attach]. Can be passed to [Iter::detach].
+    IterLinkId,
+    FdLink,
+    FdLinkId
+);
@@ -6,8 +6,8 @@ use crate::{
     generated::bpf_prog_type::BPF_PROG_TYPE_KPROBE,
     programs::{
         define_link_wrapper, load_program,
-        perf_attach::{PerfLink, PerfLinkId},
-        probe::{attach, ProbeKind},
+        perf_attach::{impl_perf_link_control, PerfLink, PerfLinkId},
+        probe::{self, attach, ProbeKind},
         ProgramData, ProgramError,
     },
 };
@@ -66,7 +66,35 @@ impl KProbe {
     ///
     /// The returned value can be used to detach from the given function, see [KProbe::detach].
     pub fn attach(&mut self, fn_name: &str, offset: u64) -> Result<KProbeLinkId, ProgramError> {
-        attach(&mut self.data, self.kind, fn_name, offset, None)
+        attach(&mut self.data, self.kind, fn_name, offset, None, None)
+    }
+
+    /// Attaches the program, with a trace event filter.
+    ///
+    /// Like [KProbe::attach], but the kernel evaluates `filter`, a trace event
+    /// filter expression such as `common_pid != 0`, for each event. See the
+    /// kernel's [event filtering] documentation for the syntax.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidPerfEventFilter`] if the kernel rejects
+    /// the filter.
+    ///
+    /// [event filtering]: https://docs.kernel.org/trace/events.html#event-filtering
+    pub fn attach_with_filter(
+        &mut self,
+        fn_name: &str,
+        offset: u64,
+        filter: &str,
+    ) -> Result<KProbeLinkId, ProgramError> {
+        attach(
+            &mut self.data,
+            self.kind,
+            fn_name,
+            offset,
+            None,
+            Some(filter),
+        )
     }
 
     /// Detaches the program.
@@ -94,6 +122,8 @@ define_link_wrapper!(
     PerfLinkId
 );
 
+impl_perf_link_control!(KProbeLink);
+
 /// The type returned when attaching a [`KProbe`] fails.
 #[derive(Debug, Error)]
 pub enum KProbeError {
@@ -107,3 +137,26 @@ pub enum KProbeError {
         io_error: io::Error,
     },
 }
+
+/// Returns the kprobe events left in tracefs by processes that exited.
+///
+/// On kernels older than 4.17, kprobes are created as events of the tracefs
+/// `kprobe_events` file, which are deleted when the probe is detached. The
+/// events of a process that crashed are never deleted, and they eventually
+/// prevent new probes from being attached. The events created by aya are
+/// named after the pid of the process that created them, this returns the
+/// names of the events whose process doesn't exist anymore.
+pub fn stale_probe_events() -> Result<Vec<String>, KProbeError> {
+    probe::stale_probe_events(ProbeKind::KProbe)
+        .map_err(|(filename, io_error)| KProbeError::FileError { filename, io_error })
+}
+
+/// Deletes the kprobe events left in tracefs by processes that exited, and
+/// returns their names.
+///
+/// The events still used by a perf event aren't deleted. See
+/// [`stale_probe_events`].
+pub fn remove_stale_probe_events() -> Result<Vec<String>, KProbeError> {
+    probe::remove_stale_probe_events(ProbeKind::KProbe)
+        .map_err(|(filename, io_error)| KProbeError::FileError { filename, io_error })
+}
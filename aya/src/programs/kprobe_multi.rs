@@ -0,0 +1,222 @@
+//! Kernel probes attached to many functions at once.
+use std::{collections::BTreeSet, ffi::CString, fs, io, os::unix::io::RawFd};
+use thiserror::Error;
+
+use crate::{
+    generated::{
+        bpf_attach_type::BPF_TRACE_KPROBE_MULTI, bpf_prog_type::BPF_PROG_TYPE_KPROBE,
+        BPF_F_KPROBE_MULTI_RETURN,
+    },
+    programs::{
+        attach_error, define_link_wrapper, load_program, probe::ProbeKind,
+        trace_point::find_tracefs_path, utils::glob_match, FdLink, FdLinkId, ProgramData,
+        ProgramError,
+    },
+    sys::bpf_link_create_kprobe_multi,
+};
+
+/// A kernel probe attached to many kernel functions with a single link.
+///
+/// [`KProbe`](crate::programs::KProbe) creates one perf event for each
+/// function it's attached to, which is too slow to trace hundreds of
+/// functions. Multi kprobes are attached to all the functions at once with a
+/// `BPF_TRACE_KPROBE_MULTI` link. Like kprobes, they can be of two kinds:
+///
+/// - `kprobe.multi`: get attached to the *start* of the target functions
+/// - `kretprobe.multi`: get attached to the *return address* of the target functions
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.18.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{Bpf, programs::KProbeMulti};
+///
+/// let program: &mut KProbeMulti = bpf.program_mut("trace_tcp").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach("tcp_*")?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_TRACE_KPROBE_MULTI")]
+#[doc(alias = "BPF_PROG_TYPE_KPROBE")]
+pub struct KProbeMulti {
+    pub(crate) data: ProgramData<KProbeMultiLink>,
+    pub(crate) kind: ProbeKind,
+}
+
+impl KProbeMulti {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_TRACE_KPROBE_MULTI);
+        load_program(BPF_PROG_TYPE_KPROBE, &mut self.data)
+    }
+
+    /// Returns `KProbe` if the program is a `kprobe.multi`, or `KRetProbe` if
+    /// the program is a `kretprobe.multi`.
+    pub fn kind(&self) -> ProbeKind {
+        self.kind
+    }
+
+    /// Attaches the program to the kernel functions matching `pattern`.
+    ///
+    /// `pattern` is a glob pattern, where `*` matches any sequence of
+    /// characters and `?` matches any single character, which is matched
+    /// against the functions of the tracefs `available_filter_functions` file.
+    ///
+    /// The returned value can be used to detach from the functions, see
+    /// [KProbeMulti::detach].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KProbeMultiError::NoMatchingFunctions`] if no function
+    /// matches `pattern`.
+    pub fn attach(&mut self, pattern: &str) -> Result<KProbeMultiLinkId, ProgramError> {
+        let symbols = matching_functions(pattern)?;
+        if symbols.is_empty() {
+            return Err(KProbeMultiError::NoMatchingFunctions {
+                pattern: pattern.to_owned(),
+            }
+            .into());
+        }
+        self.attach_symbols(&symbols)
+    }
+
+    /// Attaches the program to the given kernel functions.
+    ///
+    /// The returned value can be used to detach from the functions, see
+    /// [KProbeMulti::detach].
+    pub fn attach_symbols<T: AsRef<str>>(
+        &mut self,
+        symbols: &[T],
+    ) -> Result<KProbeMultiLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let symbols = symbols
+            .iter()
+            .map(|s| {
+                CString::new(s.as_ref()).map_err(|_| KProbeMultiError::InvalidSymbol {
+                    symbol: s.as_ref().to_owned(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let flags = match self.kind {
+            ProbeKind::KRetProbe => BPF_F_KPROBE_MULTI_RETURN,
+            _ => 0,
+        };
+        let link_fd = bpf_link_create_kprobe_multi(prog_fd, &symbols, flags)
+            .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+            as RawFd;
+        self.data
+            .links
+            .insert(KProbeMultiLink(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [KProbeMulti::attach].
+    pub fn detach(&mut self, link_id: KProbeMultiLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: KProbeMultiLinkId,
+    ) -> Result<KProbeMultiLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [KProbeMulti] programs.
+    KProbeMultiLink,
+    /// The type returned by [KProbeMulti::attach]. Can be passed to [KProbeMulti::detach].
+    KProbeMultiLinkId,
+    FdLink,
+    FdLinkId
+);
+
+/// The type returned when attaching a [`KProbeMulti`] fails.
+#[derive(Debug, Error)]
+pub enum KProbeMultiError {
+    /// No kernel function matches the pattern.
+    #[error("no kernel function matches `{pattern}`")]
+    NoMatchingFunctions {
+        /// The pattern.
+        pattern: String,
+    },
+
+    /// The symbol name contains a nul byte.
+    #[error("invalid symbol name `{symbol}`")]
+    InvalidSymbol {
+        /// The symbol name.
+        symbol: String,
+    },
+
+    /// Error reading the functions available in tracefs.
+    #[error("`{filename}`")]
+    FileError {
+        /// The file name
+        filename: String,
+        /// The [`io::Error`] returned from the file operation
+        #[source]
+        io_error: io::Error,
+    },
+}
+
+// Returns the traceable kernel functions matching `pattern`.
+fn matching_functions(pattern: &str) -> Result<Vec<String>, ProgramError> {
+    let file = find_tracefs_path()?.join("available_filter_functions");
+    let functions = fs::read_to_string(&file).map_err(|io_error| KProbeMultiError::FileError {
+        filename: file.display().to_string(),
+        io_error,
+    })?;
+    Ok(filter_functions(&functions, pattern))
+}
+
+// Parses the lines of `available_filter_functions`, like `tcp_sendmsg` or
+// `nf_conntrack_in [nf_conntrack]`. Functions defined in several places are
+// listed once for each of them, but the kernel rejects duplicate symbols.
+fn filter_functions(functions: &str, pattern: &str) -> Vec<String> {
+    functions
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        // the addresses the kernel failed to resolve
+        .filter(|name| !name.starts_with("__ftrace_invalid_address__"))
+        .filter(|name| glob_match(pattern, name))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_functions() {
+        let functions = "tcp_sendmsg\n\
+                         tcp_recvmsg\n\
+                         udp_sendmsg\n\
+                         nf_conntrack_in [nf_conntrack]\n\
+                         tcp_sendmsg\n\
+                         __ftrace_invalid_address___64\n";
+        assert_eq!(
+            filter_functions(functions, "tcp_*"),
+            vec!["tcp_recvmsg", "tcp_sendmsg"]
+        );
+        assert_eq!(
+            filter_functions(functions, "*_sendmsg"),
+            vec!["tcp_sendmsg", "udp_sendmsg"]
+        );
+        assert_eq!(filter_functions(functions, "nf_*"), vec!["nf_conntrack_in"]);
+        assert!(filter_functions(functions, "__ftrace*").is_empty());
+    }
+}
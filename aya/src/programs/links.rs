@@ -13,8 +13,11 @@ use std::{
 use crate::{
     generated::bpf_attach_type,
     pin::PinError,
-    programs::ProgramError,
-    sys::{bpf_get_object, bpf_pin_object, bpf_prog_detach},
+    programs::{
+        guard::{self, Rollback},
+        ProgramError,
+    },
+    sys::{bpf_get_object, bpf_link_get_info_by_fd, bpf_pin_object, bpf_prog_detach},
 };
 
 /// A Link.
@@ -48,6 +51,7 @@ impl<T: Link> LinkMap<T> {
             Entry::Occupied(_) => return Err(ProgramError::AlreadyAttached),
             Entry::Vacant(e) => e.insert(link),
         };
+        trace_event!(link = ?id, "program attached");
 
         Ok(id)
     }
@@ -139,6 +143,35 @@ impl FdLink {
     }
 }
 
+impl FdLink {
+    /// Returns information about the link.
+    pub fn info(&self) -> Result<LinkInfo, LinkError> {
+        let fd = self.fd.ok_or(LinkError::InvalidLink)?;
+        let info = bpf_link_get_info_by_fd(fd).map_err(|io_error| LinkError::SyscallError {
+            call: "BPF_OBJ_GET_INFO_BY_FD".to_string(),
+            code: 0,
+            io_error,
+        })?;
+        Ok(LinkInfo {
+            id: info.id,
+            link_type: info.type_,
+            program_id: info.prog_id,
+        })
+    }
+}
+
+/// Information about a link, as reported by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LinkInfo {
+    /// The link id. Each link has a unique id.
+    pub id: u32,
+    /// The `bpf_link_type` of the link.
+    pub link_type: u32,
+    /// The id of the program attached by the link.
+    pub program_id: u32,
+}
+
 impl Link for FdLink {
     type Id = FdLinkId;
 
@@ -153,6 +186,12 @@ impl Link for FdLink {
     }
 }
 
+impl guard::sealed::Sealed for FdLink {
+    fn rollback(&self) -> Result<Rollback, ProgramError> {
+        Rollback::bpf_link(self.fd.ok_or(ProgramError::NotAttached)?)
+    }
+}
+
 impl Drop for FdLink {
     fn drop(&mut self) {
         if let Some(fd) = self.fd.take() {
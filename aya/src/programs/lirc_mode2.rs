@@ -3,7 +3,7 @@ use std::os::unix::prelude::{AsRawFd, RawFd};
 
 use crate::{
     generated::{bpf_attach_type::BPF_LIRC_MODE2, bpf_prog_type::BPF_PROG_TYPE_LIRC_MODE2},
-    programs::{load_program, query, Link, ProgramData, ProgramError, ProgramInfo},
+    programs::{attach_error, load_program, query, Link, ProgramData, ProgramError, ProgramInfo},
     sys::{bpf_prog_attach, bpf_prog_detach, bpf_prog_get_fd_by_id, bpf_prog_get_info_by_fd},
 };
 
@@ -64,12 +64,8 @@ impl LircMode2 {
         let prog_fd = self.data.fd_or_err()?;
         let lircdev_fd = lircdev.as_raw_fd();
 
-        bpf_prog_attach(prog_fd, lircdev_fd, BPF_LIRC_MODE2).map_err(|(_, io_error)| {
-            ProgramError::SyscallError {
-                call: "bpf_prog_attach".to_owned(),
-                io_error,
-            }
-        })?;
+        bpf_prog_attach(prog_fd, lircdev_fd, BPF_LIRC_MODE2, 0)
+            .map_err(|(_, io_error)| attach_error("bpf_prog_attach", io_error))?;
 
         self.data.links.insert(LircLink::new(prog_fd, lircdev_fd))
     }
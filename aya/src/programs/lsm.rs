@@ -39,7 +39,7 @@ use crate::{
 ///
 /// let btf = Btf::from_sys_fs()?;
 /// let program: &mut Lsm = bpf.program_mut("lsm_prog").unwrap().try_into()?;
-/// program.load("security_bprm_exec", &btf)?;
+/// program.load("bprm_check_security", &btf)?;
 /// program.attach()?;
 /// # Ok::<(), LsmError>(())
 /// ```
@@ -57,10 +57,15 @@ impl Lsm {
     /// # Arguments
     ///
     /// * `lsm_hook_name` - full name of the LSM hook that the program should
-    ///   be attached to
+    ///   be attached to, like `bprm_check_security`. The hook is resolved to
+    ///   the `bpf_lsm_<hook>` function of the kernel BTF, a name that already
+    ///   has the `bpf_lsm_` prefix is used as is.
     pub fn load(&mut self, lsm_hook_name: &str, btf: &Btf) -> Result<(), ProgramError> {
         self.data.expected_attach_type = Some(BPF_LSM_MAC);
-        let type_name = format!("bpf_lsm_{}", lsm_hook_name);
+        let hook = lsm_hook_name
+            .strip_prefix("bpf_lsm_")
+            .unwrap_or(lsm_hook_name);
+        let type_name = format!("bpf_lsm_{}", hook);
         self.data.attach_btf_id =
             Some(btf.id_by_type_name_kind(type_name.as_str(), BtfKind::Func)?);
         load_program(BPF_PROG_TYPE_LSM, &mut self.data)
@@ -35,6 +35,8 @@
 //! [`Bpf::program`]: crate::Bpf::program
 //! [`Bpf::program_mut`]: crate::Bpf::program_mut
 //! [`maps`]: crate::maps
+pub mod cgroup;
+pub mod cgroup_device;
 pub mod cgroup_skb;
 pub mod cgroup_sock;
 pub mod cgroup_sock_addr;
@@ -43,35 +45,49 @@ pub mod cgroup_sysctl;
 pub mod extension;
 pub mod fentry;
 pub mod fexit;
+pub mod flow_dissector;
+pub mod fmod_ret;
+pub mod guard;
+pub mod iter;
 pub mod kprobe;
+pub mod kprobe_multi;
 pub mod links;
 pub mod lirc_mode2;
 pub mod lsm;
+pub mod netfilter;
 pub mod perf_attach;
 pub mod perf_event;
+pub mod pipeline;
 mod probe;
 mod raw_trace_point;
 mod sk_lookup;
 mod sk_msg;
+mod sk_reuseport;
 mod sk_skb;
 mod sock_ops;
-mod socket_filter;
+pub mod socket_filter;
+pub mod struct_ops;
+mod syscall;
 pub mod tc;
 pub mod tp_btf;
 pub mod trace_point;
 pub mod uprobe;
+pub mod uprobe_multi;
 mod utils;
 pub mod xdp;
 
-use libc::ENOSPC;
+use libc::{E2BIG, EACCES, EBADF, EINVAL, ENOENT, ENOSPC, EPERM};
+use log::debug;
+use object::SectionIndex;
 use std::{
     ffi::CString,
     io,
     os::unix::io::{AsRawFd, RawFd},
-    path::Path,
+    path::{Path, PathBuf},
 };
 use thiserror::Error;
 
+pub use cgroup_device::CgroupDevice;
 pub use cgroup_skb::{CgroupSkb, CgroupSkbAttachType};
 pub use cgroup_sock::{CgroupSock, CgroupSockAttachType};
 pub use cgroup_sock_addr::{CgroupSockAddr, CgroupSockAddrAttachType};
@@ -80,36 +96,52 @@ pub use cgroup_sysctl::CgroupSysctl;
 pub use extension::{Extension, ExtensionError};
 pub use fentry::FEntry;
 pub use fexit::FExit;
+pub use flow_dissector::FlowDissector;
+pub use fmod_ret::FModRet;
+pub use iter::Iter;
 pub use kprobe::{KProbe, KProbeError};
+pub use kprobe_multi::{KProbeMulti, KProbeMultiError};
 pub use links::Link;
 use links::*;
 pub use lirc_mode2::LircMode2;
 pub use lsm::Lsm;
+pub use netfilter::Netfilter;
 use perf_attach::*;
-pub use perf_event::{PerfEvent, PerfEventScope, PerfTypeId, SamplePolicy};
+pub use perf_event::{
+    PerfEvent, PerfEventAttr, PerfEventAttrBuilder, PerfEventScope, PerfTypeId, SamplePolicy,
+};
 pub use probe::ProbeKind;
 pub use raw_trace_point::RawTracePoint;
 pub use sk_lookup::SkLookup;
 pub use sk_msg::SkMsg;
+pub use sk_reuseport::{SkReuseport, SkReuseportError, SkReuseportKind};
 pub use sk_skb::{SkSkb, SkSkbKind};
 pub use sock_ops::SockOps;
 pub use socket_filter::{SocketFilter, SocketFilterError};
+pub use struct_ops::{StructOps, StructOpsError, StructOpsLink};
+pub use syscall::Syscall;
 pub use tc::{SchedClassifier, TcAttachType, TcError};
 pub use tp_btf::BtfTracePoint;
 pub use trace_point::{TracePoint, TracePointError};
 pub use uprobe::{UProbe, UProbeError};
+pub use uprobe_multi::UProbeMulti;
 pub use xdp::{Xdp, XdpError, XdpFlags};
 
 use crate::{
     generated::{bpf_attach_type, bpf_prog_info, bpf_prog_type},
     maps::MapError,
-    obj::{self, btf::BtfError, Function, KernelVersion},
+    obj::{self, btf::BtfError, Function, KernelVersion, ProgramSection},
     pin::PinError,
+    programs::utils::{
+        find_gpl_only_helper, is_gpl_compatible, rewrite_probe_mem, rewrite_probe_read,
+    },
     sys::{
-        bpf_get_object, bpf_load_program, bpf_pin_object, bpf_prog_get_fd_by_id,
-        bpf_prog_get_info_by_fd, bpf_prog_query, retry_with_verifier_logs, BpfLoadProgramAttrs,
+        bpf_fd_kind, bpf_get_object, bpf_load_program, bpf_pin_object, bpf_prog_get_fd_by_id,
+        bpf_prog_get_info_by_fd, bpf_prog_get_next_id, bpf_prog_query, retry_with_verifier_logs,
+        BpfFdKind, BpfLoadProgramAttrs, SysResult,
     },
-    util::VerifierLog,
+    util::{kernel_object_name, VerifierLog},
+    LoadFallbacks, VerifierLogLevel,
 };
 
 /// Error type returned when working with programs.
@@ -151,6 +183,13 @@ pub enum ProgramError {
         io_error: io::Error,
     },
 
+    /// The file descriptor doesn't refer to a BPF program.
+    #[error("file descriptor {fd} is not a BPF program")]
+    InvalidFd {
+        /// The file descriptor.
+        fd: RawFd,
+    },
+
     /// The network interface does not exist.
     #[error("unknown network interface {name}")]
     UnknownInterface {
@@ -158,6 +197,50 @@ pub enum ProgramError {
         name: String,
     },
 
+    /// The attach target is not a cgroup v2 directory.
+    #[error("the attach target is not a cgroup v2 directory")]
+    InvalidCgroup {
+        /// The name of the failed call.
+        call: String,
+        /// The [`io::Error`] returned by the call.
+        #[source]
+        io_error: io::Error,
+    },
+
+    /// The tracefs file system is not mounted.
+    #[error("tracefs is not available at `{}`", .path.display())]
+    TraceFsUnavailable {
+        /// The expected tracefs mount point.
+        path: PathBuf,
+    },
+
+    /// The kernel symbol to attach to could not be found.
+    #[error("symbol `{symbol}` not found")]
+    SymbolNotFound {
+        /// The symbol name.
+        symbol: String,
+    },
+
+    /// The kernel rejected the trace event filter of a perf event.
+    #[error("invalid perf event filter `{filter}`")]
+    InvalidPerfEventFilter {
+        /// The filter expression.
+        filter: String,
+        /// The [`io::Error`] describing why the filter is invalid.
+        #[source]
+        io_error: io::Error,
+    },
+
+    /// The caller lacks the privileges required to attach the program.
+    #[error("permission denied while calling `{call}`")]
+    PermissionDenied {
+        /// The name of the syscall which failed.
+        call: String,
+        /// The [`io::Error`] returned by the syscall.
+        #[source]
+        io_error: io::Error,
+    },
+
     /// The program is not of the expected type.
     #[error("unexpected program type")]
     UnexpectedProgramType,
@@ -170,6 +253,10 @@ pub enum ProgramError {
     #[error(transparent)]
     KProbeError(#[from] KProbeError),
 
+    /// An error occurred while working with a [`KProbeMulti`].
+    #[error(transparent)]
+    KProbeMultiError(#[from] KProbeMultiError),
+
     /// An error occurred while working with an [`UProbe`].
     #[error(transparent)]
     UProbeError(#[from] UProbeError),
@@ -182,6 +269,10 @@ pub enum ProgramError {
     #[error(transparent)]
     SocketFilterError(#[from] SocketFilterError),
 
+    /// An error occurred while working with a [`SkReuseport`] program.
+    #[error(transparent)]
+    SkReuseportError(#[from] SkReuseportError),
+
     /// An error occurred while working with an [`Xdp`] program.
     #[error(transparent)]
     XdpError(#[from] XdpError),
@@ -190,20 +281,103 @@ pub enum ProgramError {
     #[error(transparent)]
     TcError(#[from] TcError),
 
+    /// The attributes of a [`PerfEvent`] are invalid.
+    #[error(transparent)]
+    PerfEventAttrError(#[from] perf_event::PerfEventAttrError),
+
     /// An error occurred while working with an [`Extension`] program.
     #[error(transparent)]
     ExtensionError(#[from] ExtensionError),
 
+    /// An error occurred while registering a [`StructOps`] implementation.
+    #[error(transparent)]
+    StructOpsError(#[from] StructOpsError),
+
     /// An error occurred while working with BTF.
     #[error(transparent)]
     Btf(#[from] BtfError),
 
+    /// The program calls a GPL-only helper but its license isn't GPL compatible.
+    #[error("the program calls the GPL-only helper `{helper}` but its license `{license}` is not GPL compatible")]
+    GplOnlyHelper {
+        /// the helper name
+        helper: String,
+        /// the program license
+        license: String,
+    },
+
     /// The program is not attached.
     #[error("the program name `{name}` is invalid")]
     InvalidName {
         /// program name
         name: String,
     },
+
+    /// An error occurred while working with a pinned program.
+    #[error(transparent)]
+    PinError(#[from] PinError),
+}
+
+impl ProgramError {
+    /// Returns the name of the failed syscall and the [`io::Error`] it
+    /// returned, if this error was caused by a syscall failure.
+    pub fn syscall_error(&self) -> Option<(&str, &io::Error)> {
+        match self {
+            ProgramError::LoadError { io_error, .. } => Some(("bpf_prog_load", io_error)),
+            ProgramError::SyscallError { call, io_error }
+            | ProgramError::PermissionDenied { call, io_error }
+            | ProgramError::InvalidCgroup { call, io_error } => Some((call, io_error)),
+            ProgramError::InvalidPerfEventFilter { io_error, .. } => {
+                Some(("PERF_EVENT_IOC_SET_FILTER", io_error))
+            }
+            ProgramError::MapError(e) => e.syscall_error(),
+            ProgramError::PinError(PinError::SyscallError { name, io_error }) => {
+                Some((name, io_error))
+            }
+            ProgramError::SocketFilterError(SocketFilterError::SoAttachBpfError { io_error })
+            | ProgramError::SocketFilterError(SocketFilterError::SoAttachFilterError {
+                io_error,
+            })
+            | ProgramError::SkReuseportError(SkReuseportError::SoAttachReuseportEbpfError {
+                io_error,
+            }) => Some(("setsockopt", io_error)),
+            ProgramError::XdpError(XdpError::NetlinkError { io_error, .. })
+            | ProgramError::TcError(TcError::NetlinkError { io_error, .. }) => {
+                Some(("netlink", io_error))
+            }
+            ProgramError::Btf(BtfError::LoadError { io_error, .. }) => {
+                Some(("bpf_btf_load", io_error))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Turns the error returned by an attach syscall into a ProgramError, so that
+// permission problems can be told apart from other failures.
+pub(crate) fn attach_error(call: &str, io_error: io::Error) -> ProgramError {
+    match io_error.raw_os_error() {
+        Some(EPERM | EACCES) => ProgramError::PermissionDenied {
+            call: call.to_owned(),
+            io_error,
+        },
+        _ => ProgramError::SyscallError {
+            call: call.to_owned(),
+            io_error,
+        },
+    }
+}
+
+// Like attach_error, but for attach targets that must be cgroup v2 directories.
+// The kernel returns EBADF for anything else.
+pub(crate) fn cgroup_attach_error(call: &str, io_error: io::Error) -> ProgramError {
+    match io_error.raw_os_error() {
+        Some(EBADF) => ProgramError::InvalidCgroup {
+            call: call.to_owned(),
+            io_error,
+        },
+        _ => attach_error(call, io_error),
+    }
 }
 
 /// A [`Program`] file descriptor.
@@ -243,6 +417,8 @@ pub enum Program {
     CgroupSkb(CgroupSkb),
     /// A [`CgroupSysctl`] program
     CgroupSysctl(CgroupSysctl),
+    /// A [`CgroupDevice`] program
+    CgroupDevice(CgroupDevice),
     /// A [`CgroupSockopt`] program
     CgroupSockopt(CgroupSockopt),
     /// A [`LircMode2`] program
@@ -259,15 +435,188 @@ pub enum Program {
     FEntry(FEntry),
     /// A [`FExit`] program
     FExit(FExit),
+    /// A [`FModRet`] program
+    FModRet(FModRet),
     /// A [`Extension`] program
     Extension(Extension),
     /// A [`SkLookup`] program
     SkLookup(SkLookup),
+    /// A [`FlowDissector`] program
+    FlowDissector(FlowDissector),
     /// A [`CgroupSock`] program
     CgroupSock(CgroupSock),
+    /// A [`Syscall`] program
+    Syscall(Syscall),
+    /// A [`StructOps`] program
+    StructOps(StructOps),
+    /// A [`Iter`] program
+    Iter(Iter),
+    /// A [`KProbeMulti`] program
+    KProbeMulti(KProbeMulti),
+    /// A [`UProbeMulti`] program
+    UProbeMulti(UProbeMulti),
+    /// A [`Netfilter`] program
+    Netfilter(Netfilter),
+    /// A [`SkReuseport`] program
+    SkReuseport(SkReuseport),
 }
 
 impl Program {
+    /// Wraps a program loaded outside of aya, for example by a service
+    /// manager or another library.
+    ///
+    /// The fd is checked to refer to a BPF program, and the program type is
+    /// read from the kernel. On success the returned program takes ownership
+    /// of `fd`. It's already loaded, so it can be attached, pinned and
+    /// unloaded but not loaded again.
+    ///
+    /// Programs whose attach behaviour depends on how they were loaded, like
+    /// kprobes, uprobes, `sk_skb`, `cgroup/sock*` and BTF based tracing
+    /// programs, can't be recovered from the fd and return
+    /// [`ProgramError::UnexpectedProgramType`].
+    pub fn from_fd(fd: RawFd) -> Result<Program, ProgramError> {
+        use crate::generated::bpf_prog_type::*;
+
+        if bpf_fd_kind(fd) != Some(BpfFdKind::Program) {
+            return Err(ProgramError::InvalidFd { fd });
+        }
+        let info = bpf_prog_get_info_by_fd(fd).map_err(|io_error| ProgramError::SyscallError {
+            call: "bpf_prog_get_info_by_fd".to_owned(),
+            io_error,
+        })?;
+        let info = ProgramInfo(info);
+        let name = info.name_as_str().map(String::from);
+        let section_name = name.clone().unwrap_or_default();
+
+        let program = match info.0.type_ {
+            x if x == BPF_PROG_TYPE_SOCKET_FILTER as u32 => Program::SocketFilter(SocketFilter {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::SocketFilter { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_SCHED_CLS as u32 => Program::SchedClassifier(SchedClassifier {
+                name: CString::new(section_name.clone())
+                    .unwrap_or_default()
+                    .into_boxed_c_str(),
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::SchedClassifier { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_TRACEPOINT as u32 => Program::TracePoint(TracePoint {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::TracePoint { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_XDP as u32 => Program::Xdp(Xdp {
+                data: ProgramData::from_fd(name, fd, ProgramSection::Xdp { name: section_name }),
+            }),
+            x if x == BPF_PROG_TYPE_PERF_EVENT as u32 => Program::PerfEvent(PerfEvent {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::PerfEvent { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_CGROUP_SKB as u32 => Program::CgroupSkb(CgroupSkb {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::CgroupSkb { name: section_name },
+                ),
+                expected_attach_type: None,
+            }),
+            x if x == BPF_PROG_TYPE_SOCK_OPS as u32 => Program::SockOps(SockOps {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::SockOps { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_SK_MSG as u32 => Program::SkMsg(SkMsg {
+                data: ProgramData::from_fd(name, fd, ProgramSection::SkMsg { name: section_name }),
+            }),
+            x if x == BPF_PROG_TYPE_RAW_TRACEPOINT as u32 => {
+                Program::RawTracePoint(RawTracePoint {
+                    data: ProgramData::from_fd(
+                        name,
+                        fd,
+                        ProgramSection::RawTracePoint { name: section_name },
+                    ),
+                })
+            }
+            x if x == BPF_PROG_TYPE_LIRC_MODE2 as u32 => Program::LircMode2(LircMode2 {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::LircMode2 { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_CGROUP_SYSCTL as u32 => Program::CgroupSysctl(CgroupSysctl {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::CgroupSysctl { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_CGROUP_DEVICE as u32 => Program::CgroupDevice(CgroupDevice {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::CgroupDevice { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_LSM as u32 => Program::Lsm(Lsm {
+                data: ProgramData::from_fd(name, fd, ProgramSection::Lsm { name: section_name }),
+            }),
+            x if x == BPF_PROG_TYPE_SK_LOOKUP as u32 => Program::SkLookup(SkLookup {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::SkLookup { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_FLOW_DISSECTOR as u32 => {
+                Program::FlowDissector(FlowDissector {
+                    data: ProgramData::from_fd(
+                        name,
+                        fd,
+                        ProgramSection::FlowDissector { name: section_name },
+                    ),
+                })
+            }
+            x if x == BPF_PROG_TYPE_SYSCALL as u32 => Program::Syscall(Syscall {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::Syscall { name: section_name },
+                ),
+            }),
+            x if x == BPF_PROG_TYPE_STRUCT_OPS as u32 => Program::StructOps(StructOps {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::StructOps { name: section_name },
+                ),
+                member: None,
+            }),
+            x if x == BPF_PROG_TYPE_NETFILTER as u32 => Program::Netfilter(Netfilter {
+                data: ProgramData::from_fd(
+                    name,
+                    fd,
+                    ProgramSection::Netfilter { name: section_name },
+                ),
+            }),
+            _ => return Err(ProgramError::UnexpectedProgramType),
+        };
+        Ok(program)
+    }
+
     /// Returns the low level program type.
     pub fn prog_type(&self) -> bpf_prog_type {
         use crate::generated::bpf_prog_type::*;
@@ -283,6 +632,7 @@ impl Program {
             Program::SchedClassifier(_) => BPF_PROG_TYPE_SCHED_CLS,
             Program::CgroupSkb(_) => BPF_PROG_TYPE_CGROUP_SKB,
             Program::CgroupSysctl(_) => BPF_PROG_TYPE_CGROUP_SYSCTL,
+            Program::CgroupDevice(_) => BPF_PROG_TYPE_CGROUP_DEVICE,
             Program::CgroupSockopt(_) => BPF_PROG_TYPE_CGROUP_SOCKOPT,
             Program::LircMode2(_) => BPF_PROG_TYPE_LIRC_MODE2,
             Program::PerfEvent(_) => BPF_PROG_TYPE_PERF_EVENT,
@@ -291,10 +641,19 @@ impl Program {
             Program::BtfTracePoint(_) => BPF_PROG_TYPE_TRACING,
             Program::FEntry(_) => BPF_PROG_TYPE_TRACING,
             Program::FExit(_) => BPF_PROG_TYPE_TRACING,
+            Program::FModRet(_) => BPF_PROG_TYPE_TRACING,
             Program::Extension(_) => BPF_PROG_TYPE_EXT,
             Program::CgroupSockAddr(_) => BPF_PROG_TYPE_CGROUP_SOCK_ADDR,
             Program::SkLookup(_) => BPF_PROG_TYPE_SK_LOOKUP,
+            Program::FlowDissector(_) => BPF_PROG_TYPE_FLOW_DISSECTOR,
             Program::CgroupSock(_) => BPF_PROG_TYPE_CGROUP_SOCK,
+            Program::Syscall(_) => BPF_PROG_TYPE_SYSCALL,
+            Program::StructOps(_) => BPF_PROG_TYPE_STRUCT_OPS,
+            Program::Iter(_) => BPF_PROG_TYPE_TRACING,
+            Program::KProbeMulti(_) => BPF_PROG_TYPE_KPROBE,
+            Program::UProbeMulti(_) => BPF_PROG_TYPE_KPROBE,
+            Program::Netfilter(_) => BPF_PROG_TYPE_NETFILTER,
+            Program::SkReuseport(_) => BPF_PROG_TYPE_SK_REUSEPORT,
         }
     }
 
@@ -312,6 +671,7 @@ impl Program {
             Program::SchedClassifier(p) => p.pin(path),
             Program::CgroupSkb(p) => p.pin(path),
             Program::CgroupSysctl(p) => p.pin(path),
+            Program::CgroupDevice(p) => p.pin(path),
             Program::CgroupSockopt(p) => p.pin(path),
             Program::LircMode2(p) => p.pin(path),
             Program::PerfEvent(p) => p.pin(path),
@@ -320,10 +680,19 @@ impl Program {
             Program::BtfTracePoint(p) => p.pin(path),
             Program::FEntry(p) => p.pin(path),
             Program::FExit(p) => p.pin(path),
+            Program::FModRet(p) => p.pin(path),
             Program::Extension(p) => p.pin(path),
             Program::CgroupSockAddr(p) => p.pin(path),
             Program::SkLookup(p) => p.pin(path),
+            Program::FlowDissector(p) => p.pin(path),
             Program::CgroupSock(p) => p.pin(path),
+            Program::Syscall(p) => p.pin(path),
+            Program::StructOps(p) => p.pin(path),
+            Program::Iter(p) => p.pin(path),
+            Program::KProbeMulti(p) => p.pin(path),
+            Program::UProbeMulti(p) => p.pin(path),
+            Program::Netfilter(p) => p.pin(path),
+            Program::SkReuseport(p) => p.pin(path),
         }
     }
 
@@ -341,6 +710,7 @@ impl Program {
             Program::SchedClassifier(p) => p.unload(),
             Program::CgroupSkb(p) => p.unload(),
             Program::CgroupSysctl(p) => p.unload(),
+            Program::CgroupDevice(p) => p.unload(),
             Program::CgroupSockopt(p) => p.unload(),
             Program::LircMode2(p) => p.unload(),
             Program::PerfEvent(p) => p.unload(),
@@ -349,10 +719,19 @@ impl Program {
             Program::BtfTracePoint(p) => p.unload(),
             Program::FEntry(p) => p.unload(),
             Program::FExit(p) => p.unload(),
+            Program::FModRet(p) => p.unload(),
             Program::Extension(p) => p.unload(),
             Program::CgroupSockAddr(p) => p.unload(),
             Program::SkLookup(p) => p.unload(),
+            Program::FlowDissector(p) => p.unload(),
             Program::CgroupSock(p) => p.unload(),
+            Program::Syscall(p) => p.unload(),
+            Program::StructOps(p) => p.unload(),
+            Program::Iter(p) => p.unload(),
+            Program::KProbeMulti(p) => p.unload(),
+            Program::UProbeMulti(p) => p.unload(),
+            Program::Netfilter(p) => p.unload(),
+            Program::SkReuseport(p) => p.unload(),
         }
     }
 
@@ -373,6 +752,7 @@ impl Program {
             Program::SchedClassifier(p) => p.fd(),
             Program::CgroupSkb(p) => p.fd(),
             Program::CgroupSysctl(p) => p.fd(),
+            Program::CgroupDevice(p) => p.fd(),
             Program::CgroupSockopt(p) => p.fd(),
             Program::LircMode2(p) => p.fd(),
             Program::PerfEvent(p) => p.fd(),
@@ -381,10 +761,285 @@ impl Program {
             Program::BtfTracePoint(p) => p.fd(),
             Program::FEntry(p) => p.fd(),
             Program::FExit(p) => p.fd(),
+            Program::FModRet(p) => p.fd(),
             Program::Extension(p) => p.fd(),
             Program::CgroupSockAddr(p) => p.fd(),
             Program::SkLookup(p) => p.fd(),
+            Program::FlowDissector(p) => p.fd(),
             Program::CgroupSock(p) => p.fd(),
+            Program::Syscall(p) => p.fd(),
+            Program::StructOps(p) => p.fd(),
+            Program::Iter(p) => p.fd(),
+            Program::KProbeMulti(p) => p.fd(),
+            Program::UProbeMulti(p) => p.fd(),
+            Program::Netfilter(p) => p.fd(),
+            Program::SkReuseport(p) => p.fd(),
+        }
+    }
+
+    /// Returns the number of instructions of the program, including the
+    /// subprograms it calls.
+    pub fn instruction_count(&self) -> usize {
+        match self {
+            Program::KProbe(p) => p.instruction_count(),
+            Program::UProbe(p) => p.instruction_count(),
+            Program::TracePoint(p) => p.instruction_count(),
+            Program::SocketFilter(p) => p.instruction_count(),
+            Program::Xdp(p) => p.instruction_count(),
+            Program::SkMsg(p) => p.instruction_count(),
+            Program::SkSkb(p) => p.instruction_count(),
+            Program::SockOps(p) => p.instruction_count(),
+            Program::SchedClassifier(p) => p.instruction_count(),
+            Program::CgroupSkb(p) => p.instruction_count(),
+            Program::CgroupSysctl(p) => p.instruction_count(),
+            Program::CgroupDevice(p) => p.instruction_count(),
+            Program::CgroupSockopt(p) => p.instruction_count(),
+            Program::LircMode2(p) => p.instruction_count(),
+            Program::PerfEvent(p) => p.instruction_count(),
+            Program::RawTracePoint(p) => p.instruction_count(),
+            Program::Lsm(p) => p.instruction_count(),
+            Program::BtfTracePoint(p) => p.instruction_count(),
+            Program::FEntry(p) => p.instruction_count(),
+            Program::FExit(p) => p.instruction_count(),
+            Program::FModRet(p) => p.instruction_count(),
+            Program::Extension(p) => p.instruction_count(),
+            Program::CgroupSockAddr(p) => p.instruction_count(),
+            Program::SkLookup(p) => p.instruction_count(),
+            Program::FlowDissector(p) => p.instruction_count(),
+            Program::CgroupSock(p) => p.instruction_count(),
+            Program::Syscall(p) => p.instruction_count(),
+            Program::StructOps(p) => p.instruction_count(),
+            Program::Iter(p) => p.instruction_count(),
+            Program::KProbeMulti(p) => p.instruction_count(),
+            Program::UProbeMulti(p) => p.instruction_count(),
+            Program::Netfilter(p) => p.instruction_count(),
+            Program::SkReuseport(p) => p.instruction_count(),
+        }
+    }
+
+    /// Returns whether the program is loaded by
+    /// [`Bpf::load_programs`](crate::Bpf::load_programs).
+    ///
+    /// Programs are loaded by default. Like with libbpf, programs in sections
+    /// starting with `?`, like `?kprobe/debug`, aren't. This can be overridden
+    /// with [`BpfLoader::autoload`](crate::BpfLoader::autoload) or
+    /// [`Program::set_autoload`]. Programs can always be loaded explicitly
+    /// with their `load` method.
+    pub fn autoload(&self) -> bool {
+        match self {
+            Program::KProbe(p) => p.autoload(),
+            Program::UProbe(p) => p.autoload(),
+            Program::TracePoint(p) => p.autoload(),
+            Program::SocketFilter(p) => p.autoload(),
+            Program::Xdp(p) => p.autoload(),
+            Program::SkMsg(p) => p.autoload(),
+            Program::SkSkb(p) => p.autoload(),
+            Program::SockOps(p) => p.autoload(),
+            Program::SchedClassifier(p) => p.autoload(),
+            Program::CgroupSkb(p) => p.autoload(),
+            Program::CgroupSysctl(p) => p.autoload(),
+            Program::CgroupDevice(p) => p.autoload(),
+            Program::CgroupSockopt(p) => p.autoload(),
+            Program::LircMode2(p) => p.autoload(),
+            Program::PerfEvent(p) => p.autoload(),
+            Program::RawTracePoint(p) => p.autoload(),
+            Program::Lsm(p) => p.autoload(),
+            Program::BtfTracePoint(p) => p.autoload(),
+            Program::FEntry(p) => p.autoload(),
+            Program::FExit(p) => p.autoload(),
+            Program::FModRet(p) => p.autoload(),
+            Program::Extension(p) => p.autoload(),
+            Program::CgroupSockAddr(p) => p.autoload(),
+            Program::SkLookup(p) => p.autoload(),
+            Program::FlowDissector(p) => p.autoload(),
+            Program::CgroupSock(p) => p.autoload(),
+            Program::Syscall(p) => p.autoload(),
+            Program::StructOps(p) => p.autoload(),
+            Program::Iter(p) => p.autoload(),
+            Program::KProbeMulti(p) => p.autoload(),
+            Program::UProbeMulti(p) => p.autoload(),
+            Program::Netfilter(p) => p.autoload(),
+            Program::SkReuseport(p) => p.autoload(),
+        }
+    }
+
+    /// Sets whether the program is loaded by
+    /// [`Bpf::load_programs`](crate::Bpf::load_programs).
+    pub fn set_autoload(&mut self, autoload: bool) {
+        match self {
+            Program::KProbe(p) => p.set_autoload(autoload),
+            Program::UProbe(p) => p.set_autoload(autoload),
+            Program::TracePoint(p) => p.set_autoload(autoload),
+            Program::SocketFilter(p) => p.set_autoload(autoload),
+            Program::Xdp(p) => p.set_autoload(autoload),
+            Program::SkMsg(p) => p.set_autoload(autoload),
+            Program::SkSkb(p) => p.set_autoload(autoload),
+            Program::SockOps(p) => p.set_autoload(autoload),
+            Program::SchedClassifier(p) => p.set_autoload(autoload),
+            Program::CgroupSkb(p) => p.set_autoload(autoload),
+            Program::CgroupSysctl(p) => p.set_autoload(autoload),
+            Program::CgroupDevice(p) => p.set_autoload(autoload),
+            Program::CgroupSockopt(p) => p.set_autoload(autoload),
+            Program::LircMode2(p) => p.set_autoload(autoload),
+            Program::PerfEvent(p) => p.set_autoload(autoload),
+            Program::RawTracePoint(p) => p.set_autoload(autoload),
+            Program::Lsm(p) => p.set_autoload(autoload),
+            Program::BtfTracePoint(p) => p.set_autoload(autoload),
+            Program::FEntry(p) => p.set_autoload(autoload),
+            Program::FExit(p) => p.set_autoload(autoload),
+            Program::FModRet(p) => p.set_autoload(autoload),
+            Program::Extension(p) => p.set_autoload(autoload),
+            Program::CgroupSockAddr(p) => p.set_autoload(autoload),
+            Program::SkLookup(p) => p.set_autoload(autoload),
+            Program::FlowDissector(p) => p.set_autoload(autoload),
+            Program::CgroupSock(p) => p.set_autoload(autoload),
+            Program::Syscall(p) => p.set_autoload(autoload),
+            Program::StructOps(p) => p.set_autoload(autoload),
+            Program::Iter(p) => p.set_autoload(autoload),
+            Program::KProbeMulti(p) => p.set_autoload(autoload),
+            Program::UProbeMulti(p) => p.set_autoload(autoload),
+            Program::Netfilter(p) => p.set_autoload(autoload),
+            Program::SkReuseport(p) => p.set_autoload(autoload),
+        }
+    }
+
+    // Sets the compatibility rewrites retried when loading the program fails,
+    // see BpfLoader::load_fallbacks
+    pub(crate) fn set_load_fallbacks(&mut self, fallbacks: LoadFallbacks) {
+        match self {
+            Program::KProbe(p) => p.data.load_fallbacks = fallbacks,
+            Program::UProbe(p) => p.data.load_fallbacks = fallbacks,
+            Program::TracePoint(p) => p.data.load_fallbacks = fallbacks,
+            Program::SocketFilter(p) => p.data.load_fallbacks = fallbacks,
+            Program::Xdp(p) => p.data.load_fallbacks = fallbacks,
+            Program::SkMsg(p) => p.data.load_fallbacks = fallbacks,
+            Program::SkSkb(p) => p.data.load_fallbacks = fallbacks,
+            Program::SockOps(p) => p.data.load_fallbacks = fallbacks,
+            Program::SchedClassifier(p) => p.data.load_fallbacks = fallbacks,
+            Program::CgroupSkb(p) => p.data.load_fallbacks = fallbacks,
+            Program::CgroupSysctl(p) => p.data.load_fallbacks = fallbacks,
+            Program::CgroupDevice(p) => p.data.load_fallbacks = fallbacks,
+            Program::CgroupSockopt(p) => p.data.load_fallbacks = fallbacks,
+            Program::LircMode2(p) => p.data.load_fallbacks = fallbacks,
+            Program::PerfEvent(p) => p.data.load_fallbacks = fallbacks,
+            Program::RawTracePoint(p) => p.data.load_fallbacks = fallbacks,
+            Program::Lsm(p) => p.data.load_fallbacks = fallbacks,
+            Program::BtfTracePoint(p) => p.data.load_fallbacks = fallbacks,
+            Program::FEntry(p) => p.data.load_fallbacks = fallbacks,
+            Program::FExit(p) => p.data.load_fallbacks = fallbacks,
+            Program::FModRet(p) => p.data.load_fallbacks = fallbacks,
+            Program::Extension(p) => p.data.load_fallbacks = fallbacks,
+            Program::CgroupSockAddr(p) => p.data.load_fallbacks = fallbacks,
+            Program::SkLookup(p) => p.data.load_fallbacks = fallbacks,
+            Program::FlowDissector(p) => p.data.load_fallbacks = fallbacks,
+            Program::CgroupSock(p) => p.data.load_fallbacks = fallbacks,
+            Program::Syscall(p) => p.data.load_fallbacks = fallbacks,
+            Program::StructOps(p) => p.data.load_fallbacks = fallbacks,
+            Program::Iter(p) => p.data.load_fallbacks = fallbacks,
+            Program::KProbeMulti(p) => p.data.load_fallbacks = fallbacks,
+            Program::UProbeMulti(p) => p.data.load_fallbacks = fallbacks,
+            Program::Netfilter(p) => p.data.load_fallbacks = fallbacks,
+            Program::SkReuseport(p) => p.data.load_fallbacks = fallbacks,
+        }
+    }
+
+    /// Returns the names of the functions of the program that can be replaced
+    /// with [`Program::attach_extension`].
+    ///
+    /// These are the global functions called by the program, for example
+    /// functions declared as `__attribute__((noinline)) int filter(...)` in C.
+    /// The program must be loaded, with BTF.
+    pub fn extension_slots(&self) -> Result<Vec<String>, ProgramError> {
+        let fd = self.fd().ok_or(ProgramError::NotLoaded)?;
+        extension::extension_slots(fd.as_raw_fd())
+    }
+
+    /// Replaces the function `slot_name` of the program with `extension`.
+    ///
+    /// If `extension` isn't loaded yet, it's loaded targeting `slot_name`.
+    /// Otherwise the signature of the slot must match the function the
+    /// extension was loaded for.
+    ///
+    /// The returned value can be used to detach the extension, see
+    /// [`Extension::detach`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aya::{programs::{Extension, Xdp, XdpFlags}, BpfLoader};
+    ///
+    /// let mut core = BpfLoader::new().load_file("core.o")?;
+    /// let mut tenant = BpfLoader::new().extension("tenant_filter").load_file("tenant.o")?;
+    ///
+    /// let program: &mut Xdp = core.program_mut("main").unwrap().try_into()?;
+    /// program.load()?;
+    /// program.attach("eth0", XdpFlags::default())?;
+    ///
+    /// let ext: &mut Extension = tenant.program_mut("tenant_filter").unwrap().try_into()?;
+    /// core.program("main").unwrap().attach_extension("filter", ext)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`ExtensionError::UnknownSlot`] is returned if `slot_name` isn't one of
+    /// the [extension slots](Program::extension_slots) of the program.
+    pub fn attach_extension(
+        &self,
+        slot_name: &str,
+        extension: &mut Extension,
+    ) -> Result<extension::ExtensionLinkId, ProgramError> {
+        let fd = self.fd().ok_or(ProgramError::NotLoaded)?;
+        if !self.extension_slots()?.iter().any(|slot| slot == slot_name) {
+            return Err(ProgramError::ExtensionError(ExtensionError::UnknownSlot {
+                name: slot_name.to_owned(),
+            }));
+        }
+        if extension.fd().is_none() {
+            extension.load(fd, slot_name)?;
+            extension.attach()
+        } else {
+            extension.attach_to_program(fd, slot_name)
+        }
+    }
+
+    /// Returns the statistics reported by the verifier when the program was
+    /// loaded.
+    pub fn verifier_stats(&self) -> Option<VerifierStats> {
+        match self {
+            Program::KProbe(p) => p.verifier_stats(),
+            Program::UProbe(p) => p.verifier_stats(),
+            Program::TracePoint(p) => p.verifier_stats(),
+            Program::SocketFilter(p) => p.verifier_stats(),
+            Program::Xdp(p) => p.verifier_stats(),
+            Program::SkMsg(p) => p.verifier_stats(),
+            Program::SkSkb(p) => p.verifier_stats(),
+            Program::SockOps(p) => p.verifier_stats(),
+            Program::SchedClassifier(p) => p.verifier_stats(),
+            Program::CgroupSkb(p) => p.verifier_stats(),
+            Program::CgroupSysctl(p) => p.verifier_stats(),
+            Program::CgroupDevice(p) => p.verifier_stats(),
+            Program::CgroupSockopt(p) => p.verifier_stats(),
+            Program::LircMode2(p) => p.verifier_stats(),
+            Program::PerfEvent(p) => p.verifier_stats(),
+            Program::RawTracePoint(p) => p.verifier_stats(),
+            Program::Lsm(p) => p.verifier_stats(),
+            Program::BtfTracePoint(p) => p.verifier_stats(),
+            Program::FEntry(p) => p.verifier_stats(),
+            Program::FExit(p) => p.verifier_stats(),
+            Program::FModRet(p) => p.verifier_stats(),
+            Program::Extension(p) => p.verifier_stats(),
+            Program::CgroupSockAddr(p) => p.verifier_stats(),
+            Program::SkLookup(p) => p.verifier_stats(),
+            Program::FlowDissector(p) => p.verifier_stats(),
+            Program::CgroupSock(p) => p.verifier_stats(),
+            Program::Syscall(p) => p.verifier_stats(),
+            Program::StructOps(p) => p.verifier_stats(),
+            Program::Iter(p) => p.verifier_stats(),
+            Program::KProbeMulti(p) => p.verifier_stats(),
+            Program::UProbeMulti(p) => p.verifier_stats(),
+            Program::Netfilter(p) => p.verifier_stats(),
+            Program::SkReuseport(p) => p.verifier_stats(),
         }
     }
 }
@@ -402,11 +1057,17 @@ pub(crate) struct ProgramData<T: Link> {
     pub(crate) fd: Option<RawFd>,
     pub(crate) links: LinkMap<T>,
     pub(crate) expected_attach_type: Option<bpf_attach_type>,
+    // passed to the kernel instead of expected_attach_type, see
+    // StructOps::load
+    pub(crate) struct_ops_member: Option<u32>,
     pub(crate) attach_btf_obj_fd: Option<u32>,
     pub(crate) attach_btf_id: Option<u32>,
     pub(crate) attach_prog_fd: Option<RawFd>,
     pub(crate) btf_fd: Option<RawFd>,
     pub(crate) verifier_log_level: u32,
+    pub(crate) flags: u32,
+    pub(crate) verifier_stats: Option<VerifierStats>,
+    pub(crate) load_fallbacks: LoadFallbacks,
 }
 
 impl<T: Link> ProgramData<T> {
@@ -422,15 +1083,46 @@ impl<T: Link> ProgramData<T> {
             fd: None,
             links: LinkMap::new(),
             expected_attach_type: None,
+            struct_ops_member: None,
             attach_btf_obj_fd: None,
             attach_btf_id: None,
             attach_prog_fd: None,
             btf_fd,
             verifier_log_level,
+            flags: 0,
+            verifier_stats: None,
+            load_fallbacks: LoadFallbacks::empty(),
         }
     }
 }
 
+impl<T: Link> ProgramData<T> {
+    // Wraps a program loaded outside of aya. The object is a placeholder
+    // without instructions, so the program can't be loaded again.
+    fn from_fd(name: Option<String>, fd: RawFd, section: ProgramSection) -> ProgramData<T> {
+        let obj = obj::Program {
+            license: CString::default(),
+            kernel_version: KernelVersion::Any,
+            section,
+            function: Function {
+                address: 0,
+                name: name.clone().unwrap_or_default(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: Vec::new(),
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+            autoload: false,
+        };
+        let mut data = ProgramData::new(name, obj, None, 0);
+        data.fd = Some(fd);
+        data
+    }
+}
+
 impl<T: Link> ProgramData<T> {
     fn fd_or_err(&self) -> Result<RawFd, ProgramError> {
         self.fd.ok_or(ProgramError::NotLoaded)
@@ -477,7 +1169,13 @@ fn load_program<T: Link>(
     prog_type: bpf_prog_type,
     data: &mut ProgramData<T>,
 ) -> Result<(), ProgramError> {
-    let ProgramData { obj, fd, .. } = data;
+    trace_span!("load_program", name = ?data.name, ?prog_type);
+    let ProgramData {
+        obj,
+        fd,
+        verifier_stats,
+        ..
+    } = data;
     if fd.is_some() {
         return Err(ProgramError::AlreadyLoaded);
     }
@@ -504,27 +1202,37 @@ fn load_program<T: Link>(
         _ => (*kernel_version).into(),
     };
 
+    // The kernel rejects these too, but only with a terse verifier message
+    if !is_gpl_compatible(license) {
+        if let Some(helper) = find_gpl_only_helper(instructions) {
+            return Err(ProgramError::GplOnlyHelper {
+                helper: helper.to_owned(),
+                license: license.to_string_lossy().into_owned(),
+            });
+        }
+    }
+
     let mut logger = VerifierLog::new();
 
     let prog_name = if let Some(name) = &data.name {
-        let mut name = name.clone();
-        if name.len() > 15 {
-            name.truncate(15);
-        }
-        let prog_name = CString::new(name.clone())
-            .map_err(|_| ProgramError::InvalidName { name: name.clone() })?;
+        let name = kernel_object_name(name);
+        let prog_name = CString::new(name).map_err(|_| ProgramError::InvalidName {
+            name: name.to_owned(),
+        })?;
         Some(prog_name)
     } else {
         None
     };
 
-    let attr = BpfLoadProgramAttrs {
+    let mut attr = BpfLoadProgramAttrs {
         name: prog_name,
         ty: prog_type,
         insns: instructions,
         license,
         kernel_version: target_kernel_version,
-        expected_attach_type: data.expected_attach_type,
+        expected_attach_type: data
+            .struct_ops_member
+            .or(data.expected_attach_type.map(|t| t as u32)),
         prog_btf_fd: data.btf_fd,
         attach_btf_obj_fd: data.attach_btf_obj_fd,
         attach_btf_id: data.attach_btf_id,
@@ -533,19 +1241,100 @@ fn load_program<T: Link>(
         func_info: func_info.clone(),
         line_info_rec_size: *line_info_rec_size,
         line_info: line_info.clone(),
+        flags: data.flags,
+        fd_array: None,
     };
 
     let verifier_log_level = data.verifier_log_level;
-    let ret = retry_with_verifier_logs(10, &mut logger, |logger| {
-        bpf_load_program(&attr, logger, verifier_log_level)
-    });
+    // When only stats are requested the log is a single line, so capture it
+    // for successful loads too
+    if verifier_log_level == VerifierLogLevel::STATS.bits() {
+        logger.grow();
+    }
+    let load = |attr: &BpfLoadProgramAttrs, logger: &mut VerifierLog| {
+        retry_with_verifier_logs(10, logger, |logger| {
+            bpf_load_program(attr, logger, verifier_log_level)
+        })
+    };
+    let mut ret = load(&attr, &mut logger);
+
+    let fallbacks = data.load_fallbacks;
+    let rewritten = if fallbacks.contains(LoadFallbacks::PROBE_READ) {
+        rewrite_probe_read(instructions)
+    } else {
+        None
+    };
+    if let Some(insns) = &rewritten {
+        if should_fall_back(&ret) {
+            debug!("retrying load with bpf_probe_read helpers");
+            attr.insns = insns;
+            ret = load(&attr, &mut logger);
+        }
+    }
+    let rewritten = if fallbacks.contains(LoadFallbacks::PROBE_MEM) {
+        rewrite_probe_mem(attr.insns)
+    } else {
+        None
+    };
+    if let Some((insns, offsets)) = &rewritten {
+        if should_fall_back(&ret) {
+            debug!("retrying load without sign-extending loads");
+            attr.insns = insns;
+            for info in &mut attr.func_info.func_info {
+                info.insn_off = offsets
+                    .get(info.insn_off as usize)
+                    .copied()
+                    .unwrap_or(info.insn_off);
+            }
+            for info in &mut attr.line_info.line_info {
+                info.insn_off = offsets
+                    .get(info.insn_off as usize)
+                    .copied()
+                    .unwrap_or(info.insn_off);
+            }
+            ret = load(&attr, &mut logger);
+        }
+    }
+    if fallbacks.contains(LoadFallbacks::FUNC_INFO)
+        && (attr.func_info_rec_size > 0 || attr.line_info_rec_size > 0)
+        && should_fall_back(&ret)
+    {
+        debug!("retrying load without func_info and line_info");
+        attr.func_info_rec_size = 0;
+        attr.line_info_rec_size = 0;
+        ret = load(&attr, &mut logger);
+    }
+    if fallbacks.contains(LoadFallbacks::EXPECTED_ATTACH_TYPE)
+        && attr.expected_attach_type.is_some()
+        && should_fall_back(&ret)
+    {
+        debug!("retrying load without expected_attach_type");
+        attr.expected_attach_type = None;
+        ret = load(&attr, &mut logger);
+    }
 
     match ret {
         Ok(prog_fd) => {
-            *fd = Some(prog_fd as RawFd);
+            let prog_fd = prog_fd as RawFd;
+            trace_event!(fd = prog_fd, insns = attr.insns.len(), "program loaded");
+            *fd = Some(prog_fd);
+            *verifier_stats = logger
+                .as_c_str()
+                .and_then(|log| VerifierStats::parse(&log.to_string_lossy()))
+                .or_else(|| {
+                    // verified_insns is only reported since 5.16
+                    bpf_prog_get_info_by_fd(prog_fd)
+                        .ok()
+                        .filter(|info| info.verified_insns > 0)
+                        .map(|info| VerifierStats {
+                            processed_insns: info.verified_insns,
+                            ..Default::default()
+                        })
+                });
             Ok(())
         }
         Err((_, io_error)) => {
+            trace_event!(error = %io_error, "program load failed");
             logger.truncate();
             return Err(ProgramError::LoadError {
                 io_error,
@@ -558,6 +1347,10 @@ fn load_program<T: Link>(
     }
 }
 
+fn should_fall_back(ret: &SysResult) -> bool {
+    matches!(ret, Err((_, io_error)) if matches!(io_error.raw_os_error(), Some(EINVAL | E2BIG)))
+}
+
 pub(crate) fn query<T: AsRawFd>(
     target_fd: T,
     attach_type: bpf_attach_type,
@@ -624,6 +1417,7 @@ impl_program_unload!(
     SchedClassifier,
     CgroupSkb,
     CgroupSysctl,
+    CgroupDevice,
     CgroupSockopt,
     LircMode2,
     PerfEvent,
@@ -632,11 +1426,20 @@ impl_program_unload!(
     BtfTracePoint,
     FEntry,
     FExit,
+    FModRet,
     Extension,
     CgroupSockAddr,
     SkLookup,
+    FlowDissector,
     SockOps,
     CgroupSock,
+    Syscall,
+    StructOps,
+    Iter,
+    KProbeMulti,
+    UProbeMulti,
+    Netfilter,
+    SkReuseport,
 );
 
 macro_rules! impl_fd {
@@ -663,6 +1466,7 @@ impl_fd!(
     SchedClassifier,
     CgroupSkb,
     CgroupSysctl,
+    CgroupDevice,
     CgroupSockopt,
     LircMode2,
     PerfEvent,
@@ -671,11 +1475,20 @@ impl_fd!(
     BtfTracePoint,
     FEntry,
     FExit,
+    FModRet,
     Extension,
     CgroupSockAddr,
     SkLookup,
+    FlowDissector,
     SockOps,
     CgroupSock,
+    Syscall,
+    StructOps,
+    Iter,
+    KProbeMulti,
+    UProbeMulti,
+    Netfilter,
+    SkReuseport,
 );
 
 macro_rules! impl_program_pin{
@@ -707,6 +1520,126 @@ impl_program_pin!(
     SchedClassifier,
     CgroupSkb,
     CgroupSysctl,
+    CgroupDevice,
+    CgroupSockopt,
+    LircMode2,
+    PerfEvent,
+    Lsm,
+    RawTracePoint,
+    BtfTracePoint,
+    FEntry,
+    FExit,
+    FModRet,
+    Extension,
+    CgroupSockAddr,
+    SkLookup,
+    FlowDissector,
+    SockOps,
+    CgroupSock,
+    Syscall,
+    StructOps,
+    Iter,
+    KProbeMulti,
+    UProbeMulti,
+    Netfilter,
+    SkReuseport,
+);
+
+macro_rules! impl_program_autoload {
+    ($($struct_name:ident),+ $(,)?) => {
+        $(
+            impl $struct_name {
+                /// Returns whether the program is loaded by [`Bpf::load_programs`](crate::Bpf::load_programs).
+                ///
+                /// See [`Program::autoload`].
+                pub fn autoload(&self) -> bool {
+                    self.data.obj.autoload
+                }
+
+                /// Sets whether the program is loaded by [`Bpf::load_programs`](crate::Bpf::load_programs).
+                pub fn set_autoload(&mut self, autoload: bool) {
+                    self.data.obj.autoload = autoload;
+                }
+            }
+        )+
+    }
+}
+
+impl_program_autoload!(
+    KProbe,
+    UProbe,
+    TracePoint,
+    SocketFilter,
+    Xdp,
+    SkMsg,
+    SkSkb,
+    SchedClassifier,
+    CgroupSkb,
+    CgroupSysctl,
+    CgroupDevice,
+    CgroupSockopt,
+    LircMode2,
+    PerfEvent,
+    Lsm,
+    RawTracePoint,
+    BtfTracePoint,
+    FEntry,
+    FExit,
+    FModRet,
+    Extension,
+    CgroupSockAddr,
+    SkLookup,
+    FlowDissector,
+    SockOps,
+    CgroupSock,
+    Syscall,
+    StructOps,
+    Iter,
+    KProbeMulti,
+    UProbeMulti,
+    Netfilter,
+    SkReuseport,
+);
+
+macro_rules! impl_program_stats {
+    ($($struct_name:ident),+ $(,)?) => {
+        $(
+            impl $struct_name {
+                /// Returns the number of instructions of the program, including
+                /// the subprograms it calls.
+                ///
+                /// This is the static instruction count passed to the kernel
+                /// and is available before the program is loaded.
+                pub fn instruction_count(&self) -> usize {
+                    self.data.obj.function.instructions.len()
+                }
+
+                /// Returns the statistics reported by the verifier when the
+                /// program was loaded.
+                ///
+                /// Returns `None` if the program isn't loaded or if the kernel
+                /// didn't report any statistics. See [`VerifierStats`] for how
+                /// they're collected.
+                pub fn verifier_stats(&self) -> Option<VerifierStats> {
+                    self.data.verifier_stats
+                }
+            }
+        )+
+    }
+}
+
+impl_program_stats!(
+    KProbe,
+    UProbe,
+    TracePoint,
+    SocketFilter,
+    Xdp,
+    SkMsg,
+    SkSkb,
+    SchedClassifier,
+    CgroupSkb,
+    CgroupSysctl,
+    CgroupDevice,
     CgroupSockopt,
     LircMode2,
     PerfEvent,
@@ -715,11 +1648,20 @@ impl_program_pin!(
     BtfTracePoint,
     FEntry,
     FExit,
+    FModRet,
     Extension,
     CgroupSockAddr,
     SkLookup,
+    FlowDissector,
     SockOps,
     CgroupSock,
+    Syscall,
+    StructOps,
+    Iter,
+    KProbeMulti,
+    UProbeMulti,
+    Netfilter,
+    SkReuseport,
 );
 
 macro_rules! impl_try_from_program {
@@ -762,6 +1704,7 @@ impl_try_from_program!(
     SchedClassifier,
     CgroupSkb,
     CgroupSysctl,
+    CgroupDevice,
     CgroupSockopt,
     LircMode2,
     PerfEvent,
@@ -770,10 +1713,19 @@ impl_try_from_program!(
     BtfTracePoint,
     FEntry,
     FExit,
+    FModRet,
     Extension,
     CgroupSockAddr,
     SkLookup,
+    FlowDissector,
     CgroupSock,
+    Syscall,
+    StructOps,
+    Iter,
+    KProbeMulti,
+    UProbeMulti,
+    Netfilter,
+    SkReuseport,
 );
 
 /// Provides information about a loaded program, like name, id and statistics
@@ -805,6 +1757,19 @@ impl ProgramInfo {
         self.0.id
     }
 
+    /// The `bpf_prog_type` of the program.
+    pub fn program_type(&self) -> u32 {
+        self.0.type_
+    }
+
+    /// The number of instructions processed by the verifier when the program
+    /// was loaded.
+    ///
+    /// This is only reported since kernel 5.16 and is `0` on older kernels.
+    pub fn verified_instruction_count(&self) -> u32 {
+        self.0.verified_insns
+    }
+
     /// Returns the fd associated with the program.
     ///
     /// The returned fd must be closed when no longer needed.
@@ -817,6 +1782,47 @@ impl ProgramInfo {
         Ok(fd as RawFd)
     }
 
+    /// Pins the program to a BPF filesystem.
+    ///
+    /// Unlike [`Program::pin`], this works for any loaded program, including
+    /// the programs loaded by other processes.
+    pub fn pin<P: AsRef<Path>>(&self, path: P) -> Result<(), PinError> {
+        let path_string =
+            CString::new(path.as_ref().to_string_lossy().into_owned()).map_err(|e| {
+                PinError::InvalidPinPath {
+                    error: e.to_string(),
+                }
+            })?;
+        let fd = bpf_prog_get_fd_by_id(self.0.id).map_err(|io_error| PinError::SyscallError {
+            name: "bpf_prog_get_fd_by_id".to_owned(),
+            io_error,
+        })?;
+        let ret = bpf_pin_object(fd, &path_string);
+        unsafe { libc::close(fd) };
+        ret.map_err(|(_, io_error)| PinError::SyscallError {
+            name: "BPF_OBJ_PIN".to_owned(),
+            io_error,
+        })?;
+        Ok(())
+    }
+
+    /// Returns information about the program with the given id.
+    ///
+    /// Program ids can be obtained with [`loaded_programs`].
+    pub fn from_id(id: u32) -> Result<ProgramInfo, ProgramError> {
+        let fd = bpf_prog_get_fd_by_id(id).map_err(|io_error| ProgramError::SyscallError {
+            call: "bpf_prog_get_fd_by_id".to_owned(),
+            io_error,
+        })?;
+        let info = bpf_prog_get_info_by_fd(fd);
+        unsafe { libc::close(fd) };
+        info.map(ProgramInfo)
+            .map_err(|io_error| ProgramError::SyscallError {
+                call: "bpf_prog_get_info_by_fd".to_owned(),
+                io_error,
+            })
+    }
+
     /// Loads a program from a pinned path in bpffs.
     pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<ProgramInfo, ProgramError> {
         let path_string = CString::new(path.as_ref().to_str().unwrap()).unwrap();
@@ -837,3 +1843,214 @@ impl ProgramInfo {
         Ok(ProgramInfo(info))
     }
 }
+
+/// Returns an iterator over all the programs loaded in the kernel.
+///
+/// This includes the programs loaded by other processes. Listing programs
+/// requires `CAP_SYS_ADMIN`. Programs unloaded while iterating are skipped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::programs::loaded_programs;
+///
+/// for program in loaded_programs() {
+///     let program = program?;
+///     println!("{} {:?}", program.id(), program.name_as_str());
+/// }
+/// # Ok::<(), aya::programs::ProgramError>(())
+/// ```
+pub fn loaded_programs() -> impl Iterator<Item = Result<ProgramInfo, ProgramError>> {
+    let mut next_id = Some(0);
+    std::iter::from_fn(move || loop {
+        let id = match bpf_prog_get_next_id(next_id?) {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                next_id = None;
+                return None;
+            }
+            Err(io_error) => {
+                next_id = None;
+                return Some(Err(ProgramError::SyscallError {
+                    call: "bpf_prog_get_next_id".to_owned(),
+                    io_error,
+                }));
+            }
+        };
+        next_id = Some(id);
+
+        let fd = match bpf_prog_get_fd_by_id(id) {
+            Ok(fd) => fd,
+            // the program was unloaded after we got its id
+            Err(io_error) if io_error.raw_os_error() == Some(ENOENT) => continue,
+            Err(io_error) => {
+                return Some(Err(ProgramError::SyscallError {
+                    call: "bpf_prog_get_fd_by_id".to_owned(),
+                    io_error,
+                }))
+            }
+        };
+        let info = bpf_prog_get_info_by_fd(fd);
+        unsafe { libc::close(fd) };
+        return Some(
+            info.map(ProgramInfo)
+                .map_err(|io_error| ProgramError::SyscallError {
+                    call: "bpf_prog_get_info_by_fd".to_owned(),
+                    io_error,
+                }),
+        );
+    })
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ProgramInfo {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("ProgramInfo", 4)?;
+        s.serialize_field("id", &self.id())?;
+        s.serialize_field("name", &String::from_utf8_lossy(self.name()))?;
+        s.serialize_field("program_type", &self.0.type_)?;
+        s.serialize_field(
+            "verified_instruction_count",
+            &self.verified_instruction_count(),
+        )?;
+        s.end()
+    }
+}
+
+/// Statistics reported by the verifier when loading a program.
+///
+/// The verifier prints these at the end of its log. They're captured on
+/// successful loads when the loader's verifier log level is exactly
+/// [`VerifierLogLevel::STATS`]. Otherwise only
+/// [`processed_insns`](VerifierStats::processed_insns) is available, read back
+/// from the kernel on 5.16 and later.
+///
+/// [`VerifierStats::parse`] can also be used on the log of a
+/// [`ProgramError::LoadError`] to see how close a failing program got to the
+/// verifier limits.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VerifierStats {
+    /// The number of instructions processed by the verifier. Loading fails
+    /// once this reaches the verifier's complexity limit.
+    pub processed_insns: u32,
+    /// The complexity limit, if reported.
+    pub insn_limit: Option<u32>,
+    /// The maximum number of states explored for a single instruction.
+    pub max_states_per_insn: Option<u32>,
+    /// The total number of states explored.
+    pub total_states: Option<u32>,
+    /// The peak number of states kept in memory at once.
+    pub peak_states: Option<u32>,
+}
+
+impl VerifierStats {
+    /// Parses the statistics line from a verifier log.
+    ///
+    /// Returns `None` if the log doesn't contain a statistics line.
+    pub fn parse(log: &str) -> Option<VerifierStats> {
+        // processed 12 insns (limit 1000000) max_states_per_insn 0 total_states 1 peak_states 1 mark_read 0
+        let line = log.lines().rev().find(|l| l.starts_with("processed "))?;
+        let mut words = line
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+            .filter(|w| !w.is_empty());
+        words.next();
+        let processed_insns = words.next()?.parse().ok()?;
+        let mut stats = VerifierStats {
+            processed_insns,
+            ..Default::default()
+        };
+        while let Some(key) = words.next() {
+            let field = match key {
+                "limit" => &mut stats.insn_limit,
+                "max_states_per_insn" => &mut stats.max_states_per_insn,
+                "total_states" => &mut stats.total_states,
+                "peak_states" => &mut stats.peak_states,
+                _ => continue,
+            };
+            *field = words.next().and_then(|v| v.parse().ok());
+        }
+        Some(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attach_error() {
+        let err = |errno| io::Error::from_raw_os_error(errno);
+        assert!(matches!(
+            attach_error("bpf_link_create", err(EPERM)),
+            ProgramError::PermissionDenied { call, .. } if call == "bpf_link_create"
+        ));
+        assert!(matches!(
+            attach_error("bpf_link_create", err(EBADF)),
+            ProgramError::SyscallError { .. }
+        ));
+        assert!(matches!(
+            cgroup_attach_error("bpf_link_create", err(EBADF)),
+            ProgramError::InvalidCgroup { call, .. } if call == "bpf_link_create"
+        ));
+        assert!(matches!(
+            cgroup_attach_error("bpf_prog_attach", err(EACCES)),
+            ProgramError::PermissionDenied { .. }
+        ));
+    }
+
+    #[test]
+    fn test_syscall_error() {
+        let err = ProgramError::SocketFilterError(SocketFilterError::SoAttachFilterError {
+            io_error: io::Error::from_raw_os_error(EINVAL),
+        });
+        let (call, io_error) = err.syscall_error().unwrap();
+        assert_eq!(call, "setsockopt");
+        assert_eq!(io_error.raw_os_error(), Some(EINVAL));
+
+        let err = ProgramError::InvalidPerfEventFilter {
+            filter: "bogus".to_owned(),
+            io_error: io::Error::from_raw_os_error(EINVAL),
+        };
+        assert_eq!(err.syscall_error().unwrap().0, "PERF_EVENT_IOC_SET_FILTER");
+    }
+
+    #[test]
+    fn test_from_fd_invalid() {
+        let file = std::fs::File::open("/dev/null").unwrap();
+        let fd = file.as_raw_fd();
+        assert!(matches!(
+            Program::from_fd(fd),
+            Err(ProgramError::InvalidFd { fd: f }) if f == fd
+        ));
+    }
+
+    #[test]
+    fn test_parse_verifier_stats() {
+        let log = "0: (b7) r0 = 0\n1: (95) exit\nprocessed 2 insns (limit 1000000) max_states_per_insn 0 total_states 3 peak_states 4 mark_read 0\n";
+        assert_eq!(
+            VerifierStats::parse(log),
+            Some(VerifierStats {
+                processed_insns: 2,
+                insn_limit: Some(1000000),
+                max_states_per_insn: Some(0),
+                total_states: Some(3),
+                peak_states: Some(4),
+            })
+        );
+
+        // 4.19
+        assert_eq!(
+            VerifierStats::parse("processed 39 insns (limit 131072), stack depth 0\n"),
+            Some(VerifierStats {
+                processed_insns: 39,
+                insn_limit: Some(131072),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(VerifierStats::parse("0: (95) exit\n"), None);
+    }
+}
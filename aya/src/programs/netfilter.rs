@@ -0,0 +1,147 @@
+//! Netfilter programs.
+use std::os::unix::io::RawFd;
+
+use crate::{
+    generated::{bpf_attach_type::BPF_NETFILTER, bpf_prog_type::BPF_PROG_TYPE_NETFILTER},
+    programs::{
+        attach_error, define_link_wrapper, load_program, FdLink, FdLinkId, ProgramData,
+        ProgramError,
+    },
+    sys::bpf_link_create_netfilter,
+};
+
+// not in the generated bindings yet
+const BPF_F_NETFILTER_IP_DEFRAG: u32 = 1;
+
+/// The protocol family of the packets a [`Netfilter`] program sees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetfilterProtocolFamily {
+    /// IPv4 packets, `NFPROTO_IPV4`.
+    Ipv4 = 2,
+    /// IPv6 packets, `NFPROTO_IPV6`.
+    Ipv6 = 10,
+}
+
+/// The netfilter hook a [`Netfilter`] program is attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetfilterHook {
+    /// Before the routing decision for incoming packets, `NF_INET_PRE_ROUTING`.
+    PreRouting = 0,
+    /// Incoming packets destined to the local host, `NF_INET_LOCAL_IN`.
+    LocalIn = 1,
+    /// Packets routed through the host, `NF_INET_FORWARD`.
+    Forward = 2,
+    /// Packets sent by the local host, `NF_INET_LOCAL_OUT`.
+    LocalOut = 3,
+    /// After the routing decision for outgoing packets, `NF_INET_POST_ROUTING`.
+    PostRouting = 4,
+}
+
+bitflags! {
+    /// Flags passed to [`Netfilter::attach()`].
+    #[derive(Default)]
+    pub struct NetfilterFlags: u32 {
+        /// Reassemble IP fragments before running the program, like
+        /// conntrack does. Only valid with a priority after
+        /// `NF_IP_PRI_CONNTRACK_DEFRAG`, since kernel 6.6.
+        const IP_DEFRAG = BPF_F_NETFILTER_IP_DEFRAG;
+    }
+}
+
+/// A program attached to a netfilter hook.
+///
+/// Netfilter programs run at the netfilter hooks, along with the iptables
+/// and nftables chains, and return a verdict for each packet: `NF_ACCEPT`
+/// or `NF_DROP`. They let firewall-style logic be implemented without
+/// iptables extensions.
+///
+/// Hooks run in increasing order of priority; the priorities of the
+/// netfilter builtins, like `NF_IP_PRI_FILTER` (0) or `NF_IP_PRI_MANGLE`
+/// (-150), tell where the program runs relative to them.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.4.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::{
+///     netfilter::{NetfilterFlags, NetfilterHook, NetfilterProtocolFamily},
+///     Netfilter,
+/// };
+///
+/// let program: &mut Netfilter = bpf.program_mut("firewall").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach(
+///     NetfilterProtocolFamily::Ipv4,
+///     NetfilterHook::LocalIn,
+///     -128,
+///     NetfilterFlags::default(),
+/// )?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_NETFILTER")]
+pub struct Netfilter {
+    pub(crate) data: ProgramData<NetfilterLink>,
+}
+
+impl Netfilter {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_NETFILTER);
+        load_program(BPF_PROG_TYPE_NETFILTER, &mut self.data)
+    }
+
+    /// Attaches the program to the given hook.
+    ///
+    /// The kernel rejects the first and last priorities, `i32::MIN` and
+    /// `i32::MAX`, which are reserved.
+    ///
+    /// The returned value can be used to detach, see [Netfilter::detach].
+    pub fn attach(
+        &mut self,
+        protocol_family: NetfilterProtocolFamily,
+        hook: NetfilterHook,
+        priority: i32,
+        flags: NetfilterFlags,
+    ) -> Result<NetfilterLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let link_fd = bpf_link_create_netfilter(
+            prog_fd,
+            protocol_family as u32,
+            hook as u32,
+            priority,
+            flags.bits(),
+        )
+        .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+            as RawFd;
+        self.data.links.insert(NetfilterLink(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [Netfilter::attach].
+    pub fn detach(&mut self, link_id: NetfilterLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(&mut self, link_id: NetfilterLinkId) -> Result<NetfilterLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [Netfilter] programs.
+    NetfilterLink,
+    /// The type returned by [Netfilter::attach]. Can be passed to [Netfilter::detach].
+    NetfilterLinkId,
+    FdLink,
+    FdLinkId
+);
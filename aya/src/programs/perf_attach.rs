@@ -1,11 +1,12 @@
 //! Perf attach links.
-use libc::close;
-use std::os::unix::io::RawFd;
+use libc::{close, EINVAL};
+use std::{ffi::CString, io, os::unix::io::RawFd};
 
 use crate::{
-    programs::{probe::detach_debug_fs, Link, ProbeKind, ProgramData, ProgramError},
-    sys::perf_event_ioctl,
-    PERF_EVENT_IOC_DISABLE, PERF_EVENT_IOC_ENABLE, PERF_EVENT_IOC_SET_BPF,
+    programs::{attach_error, probe::detach_debug_fs, Link, ProbeKind, ProgramData, ProgramError},
+    sys::{perf_event_ioctl, perf_event_ioctl_set_filter},
+    PERF_EVENT_IOC_DISABLE, PERF_EVENT_IOC_ENABLE, PERF_EVENT_IOC_PAUSE_OUTPUT,
+    PERF_EVENT_IOC_SET_BPF,
 };
 
 /// The identifer of a PerfLink.
@@ -41,6 +42,73 @@ impl Link for PerfLink {
     }
 }
 
+impl PerfLink {
+    /// Enables the perf event, resuming a link disabled with
+    /// [`PerfLink::disable`].
+    ///
+    /// Links are enabled when they are attached.
+    pub fn enable(&self) -> Result<(), ProgramError> {
+        self.ioctl("PERF_EVENT_IOC_ENABLE", PERF_EVENT_IOC_ENABLE, 0)
+    }
+
+    /// Disables the perf event without detaching the program.
+    ///
+    /// While disabled, the program is not run for the event. This is much
+    /// cheaper than detaching and attaching the program again.
+    pub fn disable(&self) -> Result<(), ProgramError> {
+        self.ioctl("PERF_EVENT_IOC_DISABLE", PERF_EVENT_IOC_DISABLE, 0)
+    }
+
+    /// Pauses or resumes writing to the ring buffer of the perf event.
+    ///
+    /// Unlike [`PerfLink::disable`], the event keeps counting. The kernel
+    /// returns `EINVAL` if the event has no ring buffer.
+    pub fn pause_output(&self, paused: bool) -> Result<(), ProgramError> {
+        self.ioctl(
+            "PERF_EVENT_IOC_PAUSE_OUTPUT",
+            PERF_EVENT_IOC_PAUSE_OUTPUT,
+            paused as i32,
+        )
+    }
+
+    fn ioctl(&self, call: &str, request: i32, arg: i32) -> Result<(), ProgramError> {
+        perf_event_ioctl(self.perf_fd, request, arg).map_err(|(_, io_error)| {
+            ProgramError::SyscallError {
+                call: call.to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+}
+
+// Exposes the PerfLink enable/disable controls on a link wrapper defined with
+// define_link_wrapper.
+macro_rules! impl_perf_link_control {
+    ($wrapper:ident) => {
+        impl $wrapper {
+            /// Enables the link, see [`PerfLink::enable`](crate::programs::perf_attach::PerfLink::enable).
+            pub fn enable(&self) -> Result<(), ProgramError> {
+                self.0.enable()
+            }
+
+            /// Disables the link without detaching it, see
+            /// [`PerfLink::disable`](crate::programs::perf_attach::PerfLink::disable).
+            pub fn disable(&self) -> Result<(), ProgramError> {
+                self.0.disable()
+            }
+
+            /// Pauses or resumes the output of the link, see
+            /// [`PerfLink::pause_output`](crate::programs::perf_attach::PerfLink::pause_output).
+            pub fn pause_output(&self, paused: bool) -> Result<(), ProgramError> {
+                self.0.pause_output(paused)
+            }
+        }
+    };
+}
+
+pub(crate) use impl_perf_link_control;
+
 pub(crate) fn perf_attach<T: Link + From<PerfLink>>(
     data: &mut ProgramData<T>,
     fd: RawFd,
@@ -57,25 +125,41 @@ pub(crate) fn perf_attach_debugfs<T: Link + From<PerfLink>>(
     perf_attach_either(data, fd, Some(probe_kind), Some(event_alias))
 }
 
+// Sets a trace event filter on a tracepoint or probe perf event. The filter
+// must be set before the event is enabled. On failure the perf fd is closed.
+pub(crate) fn perf_event_set_filter(fd: RawFd, filter: &str) -> Result<(), ProgramError> {
+    let invalid_filter = |io_error| ProgramError::InvalidPerfEventFilter {
+        filter: filter.to_owned(),
+        io_error,
+    };
+    let res = CString::new(filter)
+        .map_err(|e| invalid_filter(io::Error::new(io::ErrorKind::InvalidInput, e)))
+        .and_then(|c_filter| {
+            perf_event_ioctl_set_filter(fd, &c_filter).map_err(|(_, io_error)| {
+                match io_error.raw_os_error() {
+                    Some(EINVAL) => invalid_filter(io_error),
+                    _ => attach_error("PERF_EVENT_IOC_SET_FILTER", io_error),
+                }
+            })
+        });
+    if res.is_err() {
+        unsafe { close(fd) };
+    }
+    res.map(|_| ())
+}
+
 fn perf_attach_either<T: Link + From<PerfLink>>(
     data: &mut ProgramData<T>,
     fd: RawFd,
     probe_kind: Option<ProbeKind>,
     event_alias: Option<String>,
 ) -> Result<T::Id, ProgramError> {
+    trace_span!("attach", name = ?data.name, perf_fd = fd);
     let prog_fd = data.fd_or_err()?;
-    perf_event_ioctl(fd, PERF_EVENT_IOC_SET_BPF, prog_fd).map_err(|(_, io_error)| {
-        ProgramError::SyscallError {
-            call: "PERF_EVENT_IOC_SET_BPF".to_owned(),
-            io_error,
-        }
-    })?;
-    perf_event_ioctl(fd, PERF_EVENT_IOC_ENABLE, 0).map_err(|(_, io_error)| {
-        ProgramError::SyscallError {
-            call: "PERF_EVENT_IOC_ENABLE".to_owned(),
-            io_error,
-        }
-    })?;
+    perf_event_ioctl(fd, PERF_EVENT_IOC_SET_BPF, prog_fd)
+        .map_err(|(_, io_error)| attach_error("PERF_EVENT_IOC_SET_BPF", io_error))?;
+    perf_event_ioctl(fd, PERF_EVENT_IOC_ENABLE, 0)
+        .map_err(|(_, io_error)| attach_error("PERF_EVENT_IOC_ENABLE", io_error))?;
 
     data.links.insert(
         PerfLink {
@@ -86,3 +170,86 @@ fn perf_attach_either<T: Link + From<PerfLink>>(
         .into(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use libc::{c_char, EINVAL, EPERM};
+
+    use super::*;
+    use crate::{
+        sys::{override_syscall, Syscall},
+        PERF_EVENT_IOC_SET_FILTER,
+    };
+
+    #[test]
+    fn test_perf_link_control() {
+        override_syscall(|call| match call {
+            Syscall::PerfEventIoctl {
+                fd: 42,
+                request: PERF_EVENT_IOC_ENABLE | PERF_EVENT_IOC_DISABLE,
+                arg: 0,
+            }
+            | Syscall::PerfEventIoctl {
+                fd: 42,
+                request: PERF_EVENT_IOC_PAUSE_OUTPUT,
+                arg: 0 | 1,
+            } => Ok(0),
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let link = PerfLink {
+            perf_fd: 42,
+            probe_kind: None,
+            event_alias: None,
+        };
+        assert!(link.disable().is_ok());
+        assert!(link.enable().is_ok());
+        assert!(link.pause_output(true).is_ok());
+        assert!(link.pause_output(false).is_ok());
+
+        let link = PerfLink {
+            perf_fd: 43,
+            probe_kind: None,
+            event_alias: None,
+        };
+        assert!(matches!(
+            link.disable(),
+            Err(ProgramError::SyscallError { call, .. }) if call == "PERF_EVENT_IOC_DISABLE"
+        ));
+    }
+
+    #[test]
+    fn test_perf_event_set_filter() {
+        override_syscall(|call| match call {
+            Syscall::PerfEventIoctl {
+                request: PERF_EVENT_IOC_SET_FILTER,
+                arg,
+                ..
+            } => {
+                let filter = unsafe { CStr::from_ptr(arg as *const c_char) };
+                match filter.to_bytes() {
+                    b"common_pid != 0" => Ok(0),
+                    b"bogus" => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+                    _ => Err((-1, io::Error::from_raw_os_error(EPERM))),
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        assert!(perf_event_set_filter(-1, "common_pid != 0").is_ok());
+        assert!(matches!(
+            perf_event_set_filter(-1, "bogus"),
+            Err(ProgramError::InvalidPerfEventFilter { filter, .. }) if filter == "bogus"
+        ));
+        assert!(matches!(
+            perf_event_set_filter(-1, "common_pid\0"),
+            Err(ProgramError::InvalidPerfEventFilter { .. })
+        ));
+        assert!(matches!(
+            perf_event_set_filter(-1, "common_pid == 1"),
+            Err(ProgramError::PermissionDenied { call, .. }) if call == "PERF_EVENT_IOC_SET_FILTER"
+        ));
+    }
+}
@@ -3,20 +3,26 @@ pub use crate::generated::{
     perf_hw_cache_id, perf_hw_cache_op_id, perf_hw_cache_op_result_id, perf_hw_id, perf_sw_ids,
 };
 
+use std::mem;
+
+use thiserror::Error;
+
 use crate::{
     generated::{
         bpf_prog_type::BPF_PROG_TYPE_PERF_EVENT,
+        perf_event_attr,
+        perf_event_sample_format::PERF_SAMPLE_RAW,
         perf_type_id::{
             PERF_TYPE_BREAKPOINT, PERF_TYPE_HARDWARE, PERF_TYPE_HW_CACHE, PERF_TYPE_RAW,
             PERF_TYPE_SOFTWARE, PERF_TYPE_TRACEPOINT,
         },
     },
     programs::{
-        load_program, perf_attach,
+        attach_error, load_program, perf_attach,
         perf_attach::{PerfLink, PerfLinkId},
         ProgramData, ProgramError,
     },
-    sys::perf_event_open,
+    sys::perf_event_open_attr,
 };
 
 /// The type of perf event
@@ -76,6 +82,162 @@ pub enum PerfEventScope {
     },
 }
 
+impl PerfEventScope {
+    pub(crate) fn pid_cpu(&self) -> (i32, i32) {
+        match *self {
+            PerfEventScope::CallingProcessAnyCpu => (0, -1),
+            PerfEventScope::CallingProcessOneCpu { cpu } => (0, cpu as i32),
+            PerfEventScope::OneProcessAnyCpu { pid } => (pid as i32, -1),
+            PerfEventScope::OneProcessOneCpu { cpu, pid } => (pid as i32, cpu as i32),
+            PerfEventScope::AllProcessesOneCpu { cpu } => (-1, cpu as i32),
+        }
+    }
+}
+
+/// The error returned by [`PerfEventAttrBuilder::build`].
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PerfEventAttrError {
+    /// The skid constraint is out of range.
+    #[error("invalid `precise_ip` {precise_ip}, expected a value between 0 and 3")]
+    InvalidPreciseIp {
+        /// The requested value
+        precise_ip: u8,
+    },
+
+    /// Sampling at a frequency of 0 is rejected by the kernel.
+    #[error("the sample frequency can't be 0")]
+    ZeroFrequency,
+}
+
+/// A builder for the attributes of a perf event.
+///
+/// The attributes are validated by [`PerfEventAttrBuilder::build`], so
+/// combinations the kernel would reject with `EINVAL` are reported with a
+/// descriptive error instead. The resulting [`PerfEventAttr`] can be passed
+/// to [`PerfEvent::attach_with_attr`] and
+/// [`PerfEventArray::set_counter`](crate::maps::PerfEventArray::set_counter).
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::programs::perf_event::{
+///     perf_hw_id::PERF_COUNT_HW_CPU_CYCLES, PerfEventAttrBuilder, PerfTypeId, SamplePolicy,
+/// };
+///
+/// let attr = PerfEventAttrBuilder::new(PerfTypeId::Hardware, PERF_COUNT_HW_CPU_CYCLES as u64)
+///     .sample_policy(SamplePolicy::Frequency(99))
+///     .exclude_kernel(true)
+///     .precise_ip(2)
+///     .build()?;
+/// # Ok::<(), aya::programs::perf_event::PerfEventAttrError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct PerfEventAttrBuilder {
+    perf_type: PerfTypeId,
+    config: u64,
+    sample_policy: SamplePolicy,
+    exclude_kernel: bool,
+    exclude_user: bool,
+    inherit: bool,
+    precise_ip: u8,
+}
+
+impl PerfEventAttrBuilder {
+    /// Creates a builder for an event of the given type.
+    ///
+    /// The possible values and encoding of `config` depend on `perf_type`,
+    /// see [`PerfEvent::attach`]. The event doesn't sample by default, which
+    /// is what counters read with `bpf_perf_event_read()` need.
+    pub fn new(perf_type: PerfTypeId, config: u64) -> PerfEventAttrBuilder {
+        PerfEventAttrBuilder {
+            perf_type,
+            config,
+            sample_policy: SamplePolicy::Period(0),
+            exclude_kernel: false,
+            exclude_user: false,
+            inherit: false,
+            precise_ip: 0,
+        }
+    }
+
+    /// Sets how often the event generates samples.
+    pub fn sample_policy(&mut self, sample_policy: SamplePolicy) -> &mut PerfEventAttrBuilder {
+        self.sample_policy = sample_policy;
+        self
+    }
+
+    /// Doesn't count events that happen in the kernel.
+    pub fn exclude_kernel(&mut self, exclude_kernel: bool) -> &mut PerfEventAttrBuilder {
+        self.exclude_kernel = exclude_kernel;
+        self
+    }
+
+    /// Doesn't count events that happen in user space.
+    pub fn exclude_user(&mut self, exclude_user: bool) -> &mut PerfEventAttrBuilder {
+        self.exclude_user = exclude_user;
+        self
+    }
+
+    /// Also counts the events of the children of the process, created after
+    /// the event is opened.
+    pub fn inherit(&mut self, inherit: bool) -> &mut PerfEventAttrBuilder {
+        self.inherit = inherit;
+        self
+    }
+
+    /// Sets the skid constraint of the samples, from 0 (arbitrary skid) to 3
+    /// (zero skid). Values above 0 usually require a hardware event.
+    pub fn precise_ip(&mut self, precise_ip: u8) -> &mut PerfEventAttrBuilder {
+        self.precise_ip = precise_ip;
+        self
+    }
+
+    /// Validates the attributes.
+    pub fn build(&self) -> Result<PerfEventAttr, PerfEventAttrError> {
+        if self.precise_ip > 3 {
+            return Err(PerfEventAttrError::InvalidPreciseIp {
+                precise_ip: self.precise_ip,
+            });
+        }
+        if let SamplePolicy::Frequency(0) = self.sample_policy {
+            return Err(PerfEventAttrError::ZeroFrequency);
+        }
+
+        let mut attr = unsafe { mem::zeroed::<perf_event_attr>() };
+        attr.size = mem::size_of::<perf_event_attr>() as u32;
+        attr.type_ = self.perf_type.clone() as u32;
+        attr.config = self.config;
+        attr.sample_type = PERF_SAMPLE_RAW as u64;
+        match self.sample_policy {
+            SamplePolicy::Period(period) => attr.__bindgen_anon_1.sample_period = period,
+            SamplePolicy::Frequency(frequency) => {
+                attr.set_freq(1);
+                attr.__bindgen_anon_1.sample_freq = frequency;
+            }
+        }
+        attr.set_exclude_kernel(self.exclude_kernel as u64);
+        attr.set_exclude_user(self.exclude_user as u64);
+        attr.set_inherit(self.inherit as u64);
+        attr.set_precise_ip(self.precise_ip as u64);
+        Ok(PerfEventAttr { attr })
+    }
+}
+
+/// The validated attributes of a perf event, see [`PerfEventAttrBuilder`].
+#[derive(Clone, Copy)]
+pub struct PerfEventAttr {
+    pub(crate) attr: perf_event_attr,
+}
+
+impl std::fmt::Debug for PerfEventAttr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PerfEventAttr")
+            .field("type", &self.attr.type_)
+            .field("config", &self.attr.config)
+            .finish_non_exhaustive()
+    }
+}
+
 /// A program that can be attached at a perf event.
 ///
 /// # Minimum kernel version
@@ -141,31 +303,24 @@ impl PerfEvent {
         scope: PerfEventScope,
         sample_policy: SamplePolicy,
     ) -> Result<PerfLinkId, ProgramError> {
-        let (sample_period, sample_frequency) = match sample_policy {
-            SamplePolicy::Period(period) => (period, None),
-            SamplePolicy::Frequency(frequency) => (0, Some(frequency)),
-        };
-        let (pid, cpu) = match scope {
-            PerfEventScope::CallingProcessAnyCpu => (0, -1),
-            PerfEventScope::CallingProcessOneCpu { cpu } => (0, cpu as i32),
-            PerfEventScope::OneProcessAnyCpu { pid } => (pid as i32, -1),
-            PerfEventScope::OneProcessOneCpu { cpu, pid } => (pid as i32, cpu as i32),
-            PerfEventScope::AllProcessesOneCpu { cpu } => (-1, cpu as i32),
-        };
-        let fd = perf_event_open(
-            perf_type as u32,
-            config,
-            pid,
-            cpu,
-            sample_period,
-            sample_frequency,
-            false,
-            0,
-        )
-        .map_err(|(_code, io_error)| ProgramError::SyscallError {
-            call: "perf_event_open".to_owned(),
-            io_error,
-        })? as i32;
+        let attr = PerfEventAttrBuilder::new(perf_type, config)
+            .sample_policy(sample_policy)
+            .build()?;
+        self.attach_with_attr(&attr, scope)
+    }
+
+    /// Attaches to the perf event described by `attr`.
+    ///
+    /// The returned value can be used to detach, see [PerfEvent::detach].
+    pub fn attach_with_attr(
+        &mut self,
+        attr: &PerfEventAttr,
+        scope: PerfEventScope,
+    ) -> Result<PerfLinkId, ProgramError> {
+        let (pid, cpu) = scope.pid_cpu();
+        let fd = perf_event_open_attr(attr.attr, pid, cpu, 0)
+            .map_err(|(_, io_error)| attach_error("perf_event_open", io_error))?
+            as i32;
 
         perf_attach(&mut self.data, fd)
     }
@@ -185,3 +340,51 @@ impl PerfEvent {
         self.data.take_link(link_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::generated::perf_hw_id::PERF_COUNT_HW_CPU_CYCLES;
+
+    use super::*;
+
+    #[test]
+    fn test_build_perf_event_attr() {
+        let mut builder =
+            PerfEventAttrBuilder::new(PerfTypeId::Hardware, PERF_COUNT_HW_CPU_CYCLES as u64);
+        let attr = builder
+            .sample_policy(SamplePolicy::Frequency(99))
+            .exclude_kernel(true)
+            .precise_ip(2)
+            .build()
+            .unwrap()
+            .attr;
+        assert_eq!(attr.type_, PERF_TYPE_HARDWARE as u32);
+        assert_eq!(attr.freq(), 1);
+        assert_eq!(unsafe { attr.__bindgen_anon_1.sample_freq }, 99);
+        assert_eq!(attr.exclude_kernel(), 1);
+        assert_eq!(attr.exclude_user(), 0);
+        assert_eq!(attr.precise_ip(), 2);
+
+        assert_eq!(
+            builder.precise_ip(4).build().unwrap_err(),
+            PerfEventAttrError::InvalidPreciseIp { precise_ip: 4 }
+        );
+        // the kernel accepts excluding both, for example to count the events
+        // of a hypervisor
+        let attr = builder
+            .precise_ip(0)
+            .exclude_user(true)
+            .build()
+            .unwrap()
+            .attr;
+        assert_eq!(attr.exclude_kernel(), 1);
+        assert_eq!(attr.exclude_user(), 1);
+        assert_eq!(
+            builder
+                .sample_policy(SamplePolicy::Frequency(0))
+                .build()
+                .unwrap_err(),
+            PerfEventAttrError::ZeroFrequency
+        );
+    }
+}
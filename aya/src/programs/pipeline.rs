@@ -0,0 +1,241 @@
+//! Tail call pipelines.
+//!
+//! A pipeline is an ordered list of programs that jump to each other with
+//! `bpf_tail_call(ctx, JUMP_TABLE, index)`. Rather than hardcoding the
+//! indices in the eBPF code and keeping them in sync with the code that
+//! populates the [`ProgramArray`], each stage can read its index from a
+//! global variable, which [`Pipeline`] sets when the object is loaded.
+//!
+//! On the eBPF side, the index of a stage is declared like any other global:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! static PARSE_IP_IDX: u32 = 0;
+//!
+//! // in the previous stage
+//! let index = unsafe { core::ptr::read_volatile(&PARSE_IP_IDX) };
+//! JUMP_TABLE.tail_call(&ctx, index);
+//! ```
+use thiserror::Error;
+
+use crate::{
+    maps::{MapError, ProgramArray},
+    programs::ProgramFd,
+    Bpf, BpfLoader,
+};
+
+/// The error type returned by [`Pipeline::populate`].
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    /// The prog array of the pipeline could not be found.
+    #[error("map `{name}` not found")]
+    MapNotFound {
+        /// The name of the map.
+        name: String,
+    },
+
+    /// A stage of the pipeline could not be found.
+    #[error("program `{name}` not found")]
+    ProgramNotFound {
+        /// The name of the program.
+        name: String,
+    },
+
+    /// A stage has a different program type than the first stage.
+    #[error("program `{name}` doesn't have the same type as program `{first}`")]
+    IncompatibleStage {
+        /// The name of the program.
+        name: String,
+        /// The name of the first stage.
+        first: String,
+    },
+
+    /// A stage is not loaded.
+    #[error("program `{name}` is not loaded")]
+    StageNotLoaded {
+        /// The name of the program.
+        name: String,
+    },
+
+    /// A map error.
+    #[error(transparent)]
+    Map(#[from] MapError),
+}
+
+#[derive(Debug, Clone)]
+struct Stage {
+    program: String,
+    index_global: Option<String>,
+    index: u32,
+}
+
+/// An ordered list of programs chained with tail calls.
+///
+/// The stages are stored in a [`ProgramArray`] at consecutive indices,
+/// starting from 0, in the order they're declared.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::{
+///     programs::{pipeline::Pipeline, Xdp, XdpFlags},
+///     BpfLoader,
+/// };
+///
+/// let mut pipeline = Pipeline::new("JUMP_TABLE");
+/// pipeline
+///     .stage("parse_eth")
+///     .stage_indexed("parse_ip", "PARSE_IP_IDX")
+///     .stage_indexed("filter", "FILTER_IDX");
+///
+/// let mut loader = BpfLoader::new();
+/// pipeline.set_globals(&mut loader);
+/// let mut bpf = loader.load_file("pipeline.o")?;
+///
+/// for name in pipeline.programs() {
+///     let program: &mut Xdp = bpf.program_mut(name).unwrap().try_into()?;
+///     program.load()?;
+/// }
+/// pipeline.populate(&mut bpf)?;
+///
+/// let entry: &mut Xdp = bpf.program_mut("parse_eth").unwrap().try_into()?;
+/// entry.attach("eth0", XdpFlags::default())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    map: String,
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Creates a new pipeline using the prog array map called `map`.
+    pub fn new(map: &str) -> Pipeline {
+        Pipeline {
+            map: map.to_owned(),
+            stages: Vec::new(),
+        }
+    }
+
+    /// Appends a stage whose index isn't exposed to the eBPF code.
+    ///
+    /// This is typically used for the entry program, which isn't the target
+    /// of a tail call.
+    pub fn stage(&mut self, program: &str) -> &mut Pipeline {
+        self.push(program, None)
+    }
+
+    /// Appends a stage, storing its index in the global variable called
+    /// `index_global`.
+    ///
+    /// The global must be a `u32`.
+    pub fn stage_indexed(&mut self, program: &str, index_global: &str) -> &mut Pipeline {
+        self.push(program, Some(index_global))
+    }
+
+    /// Returns the index of the stage running `program`.
+    pub fn index_of(&self, program: &str) -> Option<u32> {
+        self.stages
+            .iter()
+            .find(|s| s.program == program)
+            .map(|s| s.index)
+    }
+
+    /// Returns the names of the programs of the pipeline, in order.
+    pub fn programs(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|s| s.program.as_str())
+    }
+
+    /// Sets the index globals of the stages on `loader`.
+    ///
+    /// This must be called before the object is loaded.
+    pub fn set_globals<'a>(&'a self, loader: &mut BpfLoader<'a>) {
+        for stage in &self.stages {
+            if let Some(global) = &stage.index_global {
+                loader.set_global(global, &stage.index);
+            }
+        }
+    }
+
+    /// Stores the stages in the prog array.
+    ///
+    /// All the stages must be loaded and must have the same program type,
+    /// since the kernel only allows tail calls between programs of the same
+    /// type.
+    pub fn populate(&self, bpf: &mut Bpf) -> Result<(), PipelineError> {
+        let fds = self.stage_fds(bpf)?;
+        let map = bpf
+            .map_mut(&self.map)
+            .ok_or_else(|| PipelineError::MapNotFound {
+                name: self.map.clone(),
+            })?;
+        let mut prog_array = ProgramArray::try_from(map)?;
+        for (stage, fd) in self.stages.iter().zip(fds) {
+            prog_array.set(stage.index, fd, 0)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, program: &str, index_global: Option<&str>) -> &mut Pipeline {
+        let index = self.stages.len() as u32;
+        self.stages.push(Stage {
+            program: program.to_owned(),
+            index_global: index_global.map(str::to_owned),
+            index,
+        });
+        self
+    }
+
+    fn stage_fds(&self, bpf: &Bpf) -> Result<Vec<ProgramFd>, PipelineError> {
+        let mut first = None;
+        let mut fds = Vec::with_capacity(self.stages.len());
+        for stage in &self.stages {
+            let name = &stage.program;
+            let program = bpf
+                .program(name)
+                .ok_or_else(|| PipelineError::ProgramNotFound { name: name.clone() })?;
+            match first {
+                None => first = Some((name, program.prog_type())),
+                Some((first, prog_type)) if program.prog_type() != prog_type => {
+                    return Err(PipelineError::IncompatibleStage {
+                        name: name.clone(),
+                        first: first.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+            let fd = program
+                .fd()
+                .ok_or_else(|| PipelineError::StageNotLoaded { name: name.clone() })?;
+            fds.push(fd);
+        }
+        Ok(fds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_indices() {
+        let mut pipeline = Pipeline::new("JUMP_TABLE");
+        pipeline
+            .stage("entry")
+            .stage_indexed("second", "SECOND_IDX")
+            .stage_indexed("third", "THIRD_IDX");
+        assert_eq!(pipeline.index_of("third"), Some(2));
+        assert_eq!(pipeline.index_of("missing"), None);
+        assert_eq!(
+            pipeline.programs().collect::<Vec<_>>(),
+            vec!["entry", "second", "third"]
+        );
+
+        let globals = pipeline
+            .stages
+            .iter()
+            .filter_map(|s| s.index_global.as_deref().map(|g| (g, s.index)))
+            .collect::<Vec<_>>();
+        assert_eq!(globals, vec![("SECOND_IDX", 1), ("THIRD_IDX", 2)]);
+    }
+}
@@ -1,15 +1,21 @@
-use libc::pid_t;
+use libc::{pid_t, EBUSY, ENOENT};
 use std::{
     fs::{self, OpenOptions},
     io::{self, Write},
+    path::Path,
     process,
 };
 
 use crate::{
     programs::{
-        kprobe::KProbeError, perf_attach, perf_attach::PerfLink, perf_attach_debugfs,
-        trace_point::read_sys_fs_trace_point_id, uprobe::UProbeError, Link, ProgramData,
-        ProgramError,
+        attach_error,
+        kprobe::KProbeError,
+        perf_attach,
+        perf_attach::{perf_event_set_filter, PerfLink},
+        perf_attach_debugfs,
+        trace_point::{find_tracefs_path, read_sys_fs_trace_point_id},
+        uprobe::UProbeError,
+        Link, ProgramData, ProgramError,
     },
     sys::{kernel_version, perf_event_open_probe, perf_event_open_trace_point},
 };
@@ -42,17 +48,27 @@ pub(crate) fn attach<T: Link + From<PerfLink>>(
     fn_name: &str,
     offset: u64,
     pid: Option<pid_t>,
+    filter: Option<&str>,
 ) -> Result<T::Id, ProgramError> {
     // https://github.com/torvalds/linux/commit/e12f03d7031a977356e3d7b75a68c2185ff8d155
     // Use debugfs to create probe
     let k_ver = kernel_version().unwrap();
     if k_ver < (4, 17, 0) {
         let (fd, event_alias) = create_as_trace_point(kind, fn_name, offset, pid)?;
+        if let Some(filter) = filter {
+            if let Err(e) = perf_event_set_filter(fd, filter) {
+                let _ = detach_debug_fs(kind, &event_alias);
+                return Err(e);
+            }
+        }
 
         return perf_attach_debugfs(program_data, fd, kind, event_alias);
     };
 
     let fd = create_as_probe(kind, fn_name, offset, pid)?;
+    if let Some(filter) = filter {
+        perf_event_set_filter(fd, filter)?;
+    }
 
     perf_attach(program_data, fd)
 }
@@ -60,10 +76,11 @@ pub(crate) fn attach<T: Link + From<PerfLink>>(
 pub(crate) fn detach_debug_fs(kind: ProbeKind, event_alias: &str) -> Result<(), ProgramError> {
     use ProbeKind::*;
 
+    let tracefs = find_tracefs_path()?;
     match kind {
-        KProbe | KRetProbe => delete_probe_event(kind, event_alias)
+        KProbe | KRetProbe => delete_probe_event(tracefs, kind, event_alias)
             .map_err(|(filename, io_error)| KProbeError::FileError { filename, io_error })?,
-        UProbe | URetProbe => delete_probe_event(kind, event_alias)
+        UProbe | URetProbe => delete_probe_event(tracefs, kind, event_alias)
             .map_err(|(filename, io_error)| UProbeError::FileError { filename, io_error })?,
     };
 
@@ -97,12 +114,18 @@ fn create_as_probe(
         _ => None,
     };
 
-    let fd = perf_event_open_probe(perf_ty, ret_bit, fn_name, offset, pid).map_err(
-        |(_code, io_error)| ProgramError::SyscallError {
-            call: "perf_event_open".to_owned(),
-            io_error,
-        },
-    )? as i32;
+    let fd =
+        perf_event_open_probe(perf_ty, ret_bit, fn_name, offset, pid).map_err(|(_, io_error)| {
+            match kind {
+                // the kernel returns ENOENT when the kprobe target symbol doesn't exist
+                KProbe | KRetProbe if io_error.raw_os_error() == Some(ENOENT) => {
+                    ProgramError::SymbolNotFound {
+                        symbol: fn_name.to_owned(),
+                    }
+                }
+                _ => attach_error("perf_event_open", io_error),
+            }
+        })? as i32;
 
     Ok(fd)
 }
@@ -115,33 +138,31 @@ fn create_as_trace_point(
 ) -> Result<(i32, String), ProgramError> {
     use ProbeKind::*;
 
+    let tracefs = find_tracefs_path()?;
     let event_alias = match kind {
-        KProbe | KRetProbe => create_probe_event(kind, name, offset)
+        KProbe | KRetProbe => create_probe_event(tracefs, kind, name, offset)
             .map_err(|(filename, io_error)| KProbeError::FileError { filename, io_error })?,
-        UProbe | URetProbe => create_probe_event(kind, name, offset)
+        UProbe | URetProbe => create_probe_event(tracefs, kind, name, offset)
             .map_err(|(filename, io_error)| UProbeError::FileError { filename, io_error })?,
     };
 
     let category = format!("{}s", kind.pmu());
-    let tpid = read_sys_fs_trace_point_id(&category, &event_alias)?;
-    let fd = perf_event_open_trace_point(tpid, pid).map_err(|(_code, io_error)| {
-        ProgramError::SyscallError {
-            call: "perf_event_open".to_owned(),
-            io_error,
-        }
-    })? as i32;
+    let tpid = read_sys_fs_trace_point_id(tracefs, &category, &event_alias)?;
+    let fd = perf_event_open_trace_point(tpid, pid)
+        .map_err(|(_, io_error)| attach_error("perf_event_open", io_error))? as i32;
 
     Ok((fd, event_alias))
 }
 
 fn create_probe_event(
+    tracefs: &Path,
     kind: ProbeKind,
     fn_name: &str,
     offset: u64,
 ) -> Result<String, (String, io::Error)> {
     use ProbeKind::*;
 
-    let events_file_name = format!("/sys/kernel/debug/tracing/{}_events", kind.pmu());
+    let events_file_name = format!("{}/{}_events", tracefs.display(), kind.pmu());
     let probe_type_prefix = match kind {
         KProbe | UProbe => 'p',
         KRetProbe | URetProbe => 'r',
@@ -179,8 +200,12 @@ fn create_probe_event(
     Ok(event_alias)
 }
 
-fn delete_probe_event(kind: ProbeKind, event_alias: &str) -> Result<(), (String, io::Error)> {
-    let events_file_name = format!("/sys/kernel/debug/tracing/{}_events", kind.pmu());
+fn delete_probe_event(
+    tracefs: &Path,
+    kind: ProbeKind,
+    event_alias: &str,
+) -> Result<(), (String, io::Error)> {
+    let events_file_name = format!("{}/{}_events", tracefs.display(), kind.pmu());
 
     let events =
         fs::read_to_string(&events_file_name).map_err(|e| (events_file_name.clone(), e))?;
@@ -203,6 +228,79 @@ fn delete_probe_event(kind: ProbeKind, event_alias: &str) -> Result<(), (String,
     Ok(())
 }
 
+// Returns the aliases of the probe events created by processes that exited
+// without deleting them.
+pub(crate) fn stale_probe_events(kind: ProbeKind) -> Result<Vec<String>, (String, io::Error)> {
+    // no probe events can exist without tracefs
+    let tracefs = match find_tracefs_path() {
+        Ok(tracefs) => tracefs,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let events_file_name = format!("{}/{}_events", tracefs.display(), kind.pmu());
+
+    let events = match fs::read_to_string(&events_file_name) {
+        Ok(events) => events,
+        // or without probe support
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err((events_file_name, e)),
+    };
+
+    Ok(events
+        .lines()
+        .filter_map(probe_event_pid)
+        .filter(|(_, pid)| !Path::new(&format!("/proc/{}", pid)).exists())
+        .map(|(alias, _)| alias.to_owned())
+        .collect())
+}
+
+// Deletes the stale probe events and returns their aliases. The kernel
+// refuses to delete the events still referenced by a perf event, for
+// example when the pid of the exited process was reused, so they're skipped.
+pub(crate) fn remove_stale_probe_events(
+    kind: ProbeKind,
+) -> Result<Vec<String>, (String, io::Error)> {
+    let stale = stale_probe_events(kind)?;
+    // stale events are only found when tracefs is mounted
+    let tracefs = match find_tracefs_path() {
+        Ok(tracefs) if !stale.is_empty() => tracefs,
+        _ => return Ok(stale),
+    };
+
+    let events_file_name = format!("{}/{}_events", tracefs.display(), kind.pmu());
+    let mut events_file = OpenOptions::new()
+        .append(true)
+        .open(&events_file_name)
+        .map_err(|e| (events_file_name.clone(), e))?;
+
+    let mut removed = Vec::new();
+    for alias in stale {
+        match events_file.write_all(format!("-:{}\n", alias).as_bytes()) {
+            Ok(()) => removed.push(alias),
+            Err(e) if matches!(e.raw_os_error(), Some(EBUSY | ENOENT)) => {}
+            Err(e) => return Err((events_file_name, e)),
+        }
+    }
+
+    Ok(removed)
+}
+
+// Parses a line of the probe events file, like
+// `p:kprobes/aya_1234_p_try_to_wake_up_0x0 try_to_wake_up`, and returns the
+// alias and the pid of the process that created the event if it was created
+// by aya.
+fn probe_event_pid(line: &str) -> Option<(&str, u32)> {
+    let event = line.split_whitespace().next()?;
+    let (_, name) = event.split_once(':')?;
+    let alias = name.rsplit('/').next()?;
+    let pid = alias
+        .strip_prefix("aya_")?
+        .split('_')
+        .next()?
+        .parse()
+        .ok()?;
+    Some((alias, pid))
+}
+
 fn read_sys_fs_perf_type(pmu: &str) -> Result<u32, (String, io::Error)> {
     let file = format!("/sys/bus/event_source/devices/{}/type", pmu);
 
@@ -232,3 +330,23 @@ fn read_sys_fs_perf_ret_probe(pmu: &str) -> Result<u32, (String, io::Error)> {
         .parse::<u32>()
         .map_err(|e| (file, io::Error::new(io::ErrorKind::Other, e)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_event_pid() {
+        assert_eq!(
+            probe_event_pid("p:kprobes/aya_1234_p_try_to_wake_up_0x0 try_to_wake_up+0"),
+            Some(("aya_1234_p_try_to_wake_up_0x0", 1234))
+        );
+        assert_eq!(
+            probe_event_pid("r:uprobes/aya_42_r__libc_malloc_0x9a0e0 /lib/libc.so.6:0x9a0e0"),
+            Some(("aya_42_r__libc_malloc_0x9a0e0", 42))
+        );
+        assert_eq!(probe_event_pid("p:kprobes/my_probe do_sys_open"), None);
+        assert_eq!(probe_event_pid("p:kprobes/aya_probe do_sys_open"), None);
+        assert_eq!(probe_event_pid(""), None);
+    }
+}
@@ -2,7 +2,9 @@ use std::os::unix::prelude::{AsRawFd, RawFd};
 
 use crate::{
     generated::{bpf_attach_type::BPF_SK_LOOKUP, bpf_prog_type::BPF_PROG_TYPE_SK_LOOKUP},
-    programs::{define_link_wrapper, load_program, FdLinkId, ProgramData, ProgramError},
+    programs::{
+        attach_error, define_link_wrapper, load_program, FdLinkId, ProgramData, ProgramError,
+    },
     sys::bpf_link_create,
 };
 
@@ -64,12 +66,9 @@ impl SkLookup {
         let prog_fd = self.data.fd_or_err()?;
         let netns_fd = netns.as_raw_fd();
 
-        let link_fd = bpf_link_create(prog_fd, netns_fd, BPF_SK_LOOKUP, None, 0).map_err(
-            |(_, io_error)| ProgramError::SyscallError {
-                call: "bpf_link_create".to_owned(),
-                io_error,
-            },
-        )? as RawFd;
+        let link_fd = bpf_link_create(prog_fd, netns_fd, BPF_SK_LOOKUP, None, 0)
+            .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+            as RawFd;
         self.data.links.insert(SkLookupLink(FdLink::new(link_fd)))
     }
 
@@ -6,8 +6,8 @@ use crate::{
     generated::{bpf_attach_type::BPF_SK_MSG_VERDICT, bpf_prog_type::BPF_PROG_TYPE_SK_MSG},
     maps::sock::SockMapFd,
     programs::{
-        define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId, ProgramData,
-        ProgramError,
+        attach_error, define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId,
+        ProgramData, ProgramError,
     },
     sys::bpf_prog_attach,
 };
@@ -82,12 +82,8 @@ impl SkMsg {
         let prog_fd = self.data.fd_or_err()?;
         let map_fd = map.as_raw_fd();
 
-        bpf_prog_attach(prog_fd, map_fd, BPF_SK_MSG_VERDICT).map_err(|(_, io_error)| {
-            ProgramError::SyscallError {
-                call: "bpf_prog_attach".to_owned(),
-                io_error,
-            }
-        })?;
+        bpf_prog_attach(prog_fd, map_fd, BPF_SK_MSG_VERDICT, 0)
+            .map_err(|(_, io_error)| attach_error("bpf_prog_attach", io_error))?;
         self.data.links.insert(SkMsgLink(ProgAttachLink::new(
             prog_fd,
             map_fd,
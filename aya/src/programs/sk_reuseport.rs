@@ -0,0 +1,217 @@
+//! Programs selecting the socket of `SO_REUSEPORT` groups.
+use libc::{setsockopt, SOL_SOCKET};
+use std::{
+    io, mem,
+    os::unix::io::{AsRawFd, RawFd},
+};
+use thiserror::Error;
+
+use crate::{
+    generated::{
+        bpf_attach_type::{BPF_SK_REUSEPORT_SELECT, BPF_SK_REUSEPORT_SELECT_OR_MIGRATE},
+        bpf_prog_type::BPF_PROG_TYPE_SK_REUSEPORT,
+    },
+    programs::{load_program, Link, ProgramData, ProgramError},
+};
+
+const SO_ATTACH_REUSEPORT_EBPF: i32 = 52;
+const SO_DETACH_REUSEPORT_BPF: i32 = 68;
+
+/// The kind of [`SkReuseport`] program.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SkReuseportKind {
+    /// Selects the socket new connections and datagrams are delivered to.
+    Select,
+    /// Also selects the socket the pending connections of a closed listener
+    /// are migrated to, when `net.ipv4.tcp_migrate_req` is enabled.
+    SelectOrMigrate,
+}
+
+/// The type returned when attaching a [`SkReuseport`] program fails.
+#[derive(Debug, Error)]
+pub enum SkReuseportError {
+    /// Setting the `SO_ATTACH_REUSEPORT_EBPF` socket option failed.
+    #[error("setsockopt SO_ATTACH_REUSEPORT_EBPF failed")]
+    SoAttachReuseportEbpfError {
+        /// original [`io::Error`]
+        #[source]
+        io_error: io::Error,
+    },
+}
+
+/// A program selecting the socket of a `SO_REUSEPORT` group.
+///
+/// Without a program, the kernel spreads the connections and datagrams
+/// received by a group of sockets bound to the same address with
+/// `SO_REUSEPORT` using a hash of the flow. [`SkReuseport`] programs choose
+/// the socket instead, by calling `bpf_sk_select_reuseport()` with an index
+/// in a [`ReusePortSockArray`], which makes it possible to implement load
+/// balancing policies or to drain listeners before restarting them.
+///
+/// The program is attached to the group through any of its sockets, and
+/// stays attached until the last socket of the group is closed.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.19, and
+/// 5.14 for [`SkReuseportKind::SelectOrMigrate`] programs.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::{maps::ReusePortSockArray, programs::SkReuseport};
+/// use std::{
+///     io, mem,
+///     net::TcpListener,
+///     os::unix::io::{AsRawFd, FromRawFd},
+/// };
+///
+/// // the listeners must set SO_REUSEPORT before binding to share the port
+/// fn listen(port: u16) -> io::Result<TcpListener> {
+///     unsafe {
+///         let fd = libc::socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+///         if fd < 0 {
+///             return Err(io::Error::last_os_error());
+///         }
+///         let listener = TcpListener::from_raw_fd(fd);
+///         let one: libc::c_int = 1;
+///         let addr = libc::sockaddr_in {
+///             sin_family: libc::AF_INET as libc::sa_family_t,
+///             sin_port: port.to_be(),
+///             sin_addr: libc::in_addr { s_addr: 0 },
+///             sin_zero: [0; 8],
+///         };
+///         if libc::setsockopt(
+///             fd,
+///             libc::SOL_SOCKET,
+///             libc::SO_REUSEPORT,
+///             &one as *const _ as *const _,
+///             mem::size_of_val(&one) as libc::socklen_t,
+///         ) < 0
+///             || libc::bind(
+///                 fd,
+///                 &addr as *const _ as *const _,
+///                 mem::size_of_val(&addr) as libc::socklen_t,
+///             ) < 0
+///             || libc::listen(fd, 128) < 0
+///         {
+///             return Err(io::Error::last_os_error());
+///         }
+///         Ok(listener)
+///     }
+/// }
+///
+/// let listeners = (0..4).map(|_| listen(8080)).collect::<Result<Vec<_>, _>>()?;
+/// let mut sockets = ReusePortSockArray::try_from(bpf.map_mut("SOCKETS").unwrap())?;
+/// sockets.populate(&listeners)?;
+///
+/// let prog: &mut SkReuseport = bpf.program_mut("select_listener").unwrap().try_into()?;
+/// prog.load()?;
+/// prog.attach(listeners[0].as_raw_fd())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// [`ReusePortSockArray`]: crate::maps::ReusePortSockArray
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_SK_REUSEPORT")]
+pub struct SkReuseport {
+    pub(crate) data: ProgramData<SkReuseportLink>,
+    pub(crate) kind: SkReuseportKind,
+}
+
+impl SkReuseport {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(match self.kind {
+            SkReuseportKind::Select => BPF_SK_REUSEPORT_SELECT,
+            SkReuseportKind::SelectOrMigrate => BPF_SK_REUSEPORT_SELECT_OR_MIGRATE,
+        });
+        load_program(BPF_PROG_TYPE_SK_REUSEPORT, &mut self.data)
+    }
+
+    /// Returns `Select` if the program is a `sk_reuseport` program, or
+    /// `SelectOrMigrate` if it is a `sk_reuseport/migrate` program.
+    pub fn kind(&self) -> SkReuseportKind {
+        self.kind
+    }
+
+    /// Attaches the program to the `SO_REUSEPORT` group of `socket`,
+    /// replacing any program already attached to the group.
+    ///
+    /// The returned value can be used to detach, see [SkReuseport::detach].
+    pub fn attach<T: AsRawFd>(&mut self, socket: T) -> Result<SkReuseportLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let socket = socket.as_raw_fd();
+
+        let ret = unsafe {
+            setsockopt(
+                socket,
+                SOL_SOCKET,
+                SO_ATTACH_REUSEPORT_EBPF,
+                &prog_fd as *const _ as *const _,
+                mem::size_of::<RawFd>() as u32,
+            )
+        };
+        if ret < 0 {
+            return Err(SkReuseportError::SoAttachReuseportEbpfError {
+                io_error: io::Error::last_os_error(),
+            }
+            .into());
+        }
+
+        self.data.links.insert(SkReuseportLink { socket, prog_fd })
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [SkReuseport::attach].
+    pub fn detach(&mut self, link_id: SkReuseportLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: SkReuseportLinkId,
+    ) -> Result<SkReuseportLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+/// The type returned by [SkReuseport::attach]. Can be passed to [SkReuseport::detach].
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct SkReuseportLinkId(RawFd, RawFd);
+
+/// A SkReuseport Link
+#[derive(Debug)]
+pub struct SkReuseportLink {
+    socket: RawFd,
+    prog_fd: RawFd,
+}
+
+impl Link for SkReuseportLink {
+    type Id = SkReuseportLinkId;
+
+    fn id(&self) -> Self::Id {
+        SkReuseportLinkId(self.socket, self.prog_fd)
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        // SO_DETACH_REUSEPORT_BPF is only available since 5.3, before that
+        // the program stays attached until the group is closed
+        unsafe {
+            setsockopt(
+                self.socket,
+                SOL_SOCKET,
+                SO_DETACH_REUSEPORT_BPF,
+                &self.prog_fd as *const _ as *const _,
+                mem::size_of::<RawFd>() as u32,
+            );
+        }
+        Ok(())
+    }
+}
@@ -9,8 +9,8 @@ use crate::{
     },
     maps::sock::SockMapFd,
     programs::{
-        define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId, ProgramData,
-        ProgramError,
+        attach_error, define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId,
+        ProgramData, ProgramError,
     },
     sys::bpf_prog_attach,
 };
@@ -67,6 +67,12 @@ impl SkSkb {
         load_program(BPF_PROG_TYPE_SK_SKB, &mut self.data)
     }
 
+    /// Returns `StreamParser` if the program is a stream parser, or
+    /// `StreamVerdict` if it is a stream verdict program.
+    pub fn kind(&self) -> SkSkbKind {
+        self.kind
+    }
+
     /// Attaches the program to the given socket map.
     ///
     /// The returned value can be used to detach, see [SkSkb::detach].
@@ -78,12 +84,8 @@ impl SkSkb {
             SkSkbKind::StreamParser => BPF_SK_SKB_STREAM_PARSER,
             SkSkbKind::StreamVerdict => BPF_SK_SKB_STREAM_VERDICT,
         };
-        bpf_prog_attach(prog_fd, map_fd, attach_type).map_err(|(_, io_error)| {
-            ProgramError::SyscallError {
-                call: "bpf_prog_attach".to_owned(),
-                io_error,
-            }
-        })?;
+        bpf_prog_attach(prog_fd, map_fd, attach_type, 0)
+            .map_err(|(_, io_error)| attach_error("bpf_prog_attach", io_error))?;
         self.data
             .links
             .insert(SkSkbLink(ProgAttachLink::new(prog_fd, map_fd, attach_type)))
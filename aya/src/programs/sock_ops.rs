@@ -1,11 +1,17 @@
 //! Socket option programs.
-use std::os::unix::io::AsRawFd;
+use std::{
+    os::unix::io::{AsRawFd, RawFd},
+    path::Path,
+};
 
 use crate::{
-    generated::{bpf_attach_type::BPF_CGROUP_SOCK_OPS, bpf_prog_type::BPF_PROG_TYPE_SOCK_OPS},
+    generated::{
+        bpf_attach_type::BPF_CGROUP_SOCK_OPS, bpf_prog_type::BPF_PROG_TYPE_SOCK_OPS,
+        BPF_F_ALLOW_MULTI,
+    },
     programs::{
-        define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId, ProgramData,
-        ProgramError,
+        cgroup_attach_error, define_link_wrapper, load_program, utils::open_cgroup, ProgAttachLink,
+        ProgAttachLinkId, ProgramData, ProgramError,
     },
     sys::bpf_prog_attach,
 };
@@ -59,15 +65,29 @@ impl SockOps {
     ///
     /// The returned value can be used to detach, see [SockOps::detach].
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<SockOpsLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), 0)
+    }
+
+    /// Attaches the program to the given cgroup in multi mode.
+    ///
+    /// Other programs can then be attached to the cgroup and its descendants,
+    /// and the program runs for the descendants after their own programs, see
+    /// [`cgroup`](crate::programs::cgroup).
+    ///
+    /// The returned value can be used to detach, see [SockOps::detach].
+    pub fn attach_multi<T: AsRawFd>(&mut self, cgroup: T) -> Result<SockOpsLinkId, ProgramError> {
+        self.attach_with_flags(cgroup.as_raw_fd(), BPF_F_ALLOW_MULTI)
+    }
+
+    fn attach_with_flags(
+        &mut self,
+        cgroup_fd: RawFd,
+        flags: u32,
+    ) -> Result<SockOpsLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
-        let cgroup_fd = cgroup.as_raw_fd();
 
-        bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_SOCK_OPS).map_err(|(_, io_error)| {
-            ProgramError::SyscallError {
-                call: "bpf_prog_attach".to_owned(),
-                io_error,
-            }
-        })?;
+        bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_SOCK_OPS, flags)
+            .map_err(|(_, io_error)| cgroup_attach_error("bpf_prog_attach", io_error))?;
         self.data.links.insert(SockOpsLink(ProgAttachLink::new(
             prog_fd,
             cgroup_fd,
@@ -82,6 +102,13 @@ impl SockOps {
         self.data.links.remove(link_id)
     }
 
+    /// Attaches the program to the cgroup v2 directory at `path`, see
+    /// [`SockOps::attach`].
+    pub fn attach_path<P: AsRef<Path>>(&mut self, path: P) -> Result<SockOpsLinkId, ProgramError> {
+        let cgroup = open_cgroup(path.as_ref())?;
+        self.attach(cgroup)
+    }
+
     /// Takes ownership of the link referenced by the provided link_id.
     ///
     /// The link will be detached on `Drop` and the caller is now responsible
@@ -1,5 +1,8 @@
 //! Socket filter programs.
-use libc::{setsockopt, SOL_SOCKET};
+use libc::{
+    bind, close, recvfrom, setsockopt, sockaddr, sockaddr_ll, socket, socklen_t, AF_PACKET,
+    ETH_P_ALL, MSG_TRUNC, SOCK_CLOEXEC, SOCK_RAW, SOL_PACKET, SOL_SOCKET,
+};
 use std::{
     io, mem,
     os::unix::prelude::{AsRawFd, RawFd},
@@ -9,8 +12,13 @@ use thiserror::Error;
 use crate::{
     generated::{bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER, SO_ATTACH_BPF, SO_DETACH_BPF},
     programs::{load_program, Link, ProgramData, ProgramError},
+    util::ifindex_from_ifname,
 };
 
+const PACKET_FANOUT: i32 = 18;
+const SO_ATTACH_FILTER: i32 = 26;
+const BPF_MAXINSNS: usize = 4096;
+
 /// The type returned when attaching a [`SocketFilter`] fails.
 #[derive(Debug, Error)]
 pub enum SocketFilterError {
@@ -21,6 +29,33 @@ pub enum SocketFilterError {
         #[source]
         io_error: io::Error,
     },
+
+    /// Setting the `SO_ATTACH_FILTER` socket option failed.
+    #[error("setsockopt SO_ATTACH_FILTER failed")]
+    SoAttachFilterError {
+        /// original [`io::Error`]
+        #[source]
+        io_error: io::Error,
+    },
+
+    /// A classic BPF filter must have between 1 and 4096 instructions.
+    #[error("invalid classic BPF filter length {len}")]
+    InvalidClassicFilter {
+        /// The number of instructions
+        len: usize,
+    },
+}
+
+/// A filter that can be attached to a socket.
+///
+/// This is implemented by eBPF [`SocketFilter`] programs and by
+/// [`ClassicFilter`]s, so that code like [`PacketSocketBuilder::open`] can
+/// work with either.
+pub trait SocketAttach {
+    /// Attaches the filter to `socket`, replacing any filter already attached.
+    ///
+    /// The filter stays attached until the socket is closed.
+    fn attach_socket(&self, socket: RawFd) -> Result<(), ProgramError>;
 }
 
 /// A program used to inspect and filter incoming packets on a socket.
@@ -76,21 +111,7 @@ impl SocketFilter {
         let prog_fd = self.data.fd_or_err()?;
         let socket = socket.as_raw_fd();
 
-        let ret = unsafe {
-            setsockopt(
-                socket,
-                SOL_SOCKET,
-                SO_ATTACH_BPF as i32,
-                &prog_fd as *const _ as *const _,
-                mem::size_of::<RawFd>() as u32,
-            )
-        };
-        if ret < 0 {
-            return Err(SocketFilterError::SoAttachBpfError {
-                io_error: io::Error::last_os_error(),
-            }
-            .into());
-        }
+        attach_socket_filter(socket, prog_fd)?;
 
         self.data.links.insert(SocketFilterLink { socket, prog_fd })
     }
@@ -114,6 +135,129 @@ impl SocketFilter {
     }
 }
 
+impl SocketAttach for SocketFilter {
+    fn attach_socket(&self, socket: RawFd) -> Result<(), ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        attach_socket_filter(socket, prog_fd)?;
+        Ok(())
+    }
+}
+
+fn attach_socket_filter(socket: RawFd, prog_fd: RawFd) -> Result<(), SocketFilterError> {
+    let ret = unsafe {
+        setsockopt(
+            socket,
+            SOL_SOCKET,
+            SO_ATTACH_BPF as i32,
+            &prog_fd as *const _ as *const _,
+            mem::size_of::<RawFd>() as u32,
+        )
+    };
+    if ret < 0 {
+        return Err(SocketFilterError::SoAttachBpfError {
+            io_error: io::Error::last_os_error(),
+        });
+    }
+    Ok(())
+}
+
+/// A classic BPF instruction, the same as the kernel's `struct sock_filter`.
+///
+/// This is the format generated by libpcap and `tcpdump -dd`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClassicInsn {
+    /// The opcode
+    pub code: u16,
+    /// The jump offset if the condition is true
+    pub jt: u8,
+    /// The jump offset if the condition is false
+    pub jf: u8,
+    /// The generic field, eg the immediate value
+    pub k: u32,
+}
+
+#[repr(C)]
+struct SockFprog {
+    len: u16,
+    filter: *const ClassicInsn,
+}
+
+/// A classic BPF (cBPF) socket filter.
+///
+/// Classic filters don't need to be loaded and can be attached by
+/// unprivileged processes. The kernel translates them to eBPF when they're
+/// attached.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::{net::UdpSocket, os::unix::io::AsRawFd};
+/// use aya::programs::socket_filter::{ClassicFilter, ClassicInsn};
+///
+/// // `tcpdump -dd udp`, truncated for brevity
+/// let insns = [
+///     ClassicInsn { code: 0x28, jt: 0, jf: 0, k: 0x0000000c },
+///     // ...
+///     ClassicInsn { code: 0x06, jt: 0, jf: 0, k: 0x00040000 },
+/// ];
+/// let filter = ClassicFilter::new(&insns)?;
+/// let socket = UdpSocket::bind("127.0.0.1:0")?;
+/// filter.attach(socket.as_raw_fd())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClassicFilter {
+    insns: Vec<ClassicInsn>,
+}
+
+impl ClassicFilter {
+    /// Creates a new filter from its instructions.
+    pub fn new(insns: &[ClassicInsn]) -> Result<ClassicFilter, SocketFilterError> {
+        if insns.is_empty() || insns.len() > BPF_MAXINSNS {
+            return Err(SocketFilterError::InvalidClassicFilter { len: insns.len() });
+        }
+        Ok(ClassicFilter {
+            insns: insns.to_vec(),
+        })
+    }
+
+    /// Returns the instructions of the filter.
+    pub fn instructions(&self) -> &[ClassicInsn] {
+        &self.insns
+    }
+
+    /// Attaches the filter to the given socket, replacing any filter already
+    /// attached.
+    pub fn attach<T: AsRawFd>(&self, socket: T) -> Result<(), SocketFilterError> {
+        let prog = SockFprog {
+            len: self.insns.len() as u16,
+            filter: self.insns.as_ptr(),
+        };
+        let ret = unsafe {
+            setsockopt(
+                socket.as_raw_fd(),
+                SOL_SOCKET,
+                SO_ATTACH_FILTER,
+                &prog as *const _ as *const _,
+                mem::size_of::<SockFprog>() as u32,
+            )
+        };
+        if ret < 0 {
+            return Err(SocketFilterError::SoAttachFilterError {
+                io_error: io::Error::last_os_error(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl SocketAttach for ClassicFilter {
+    fn attach_socket(&self, socket: RawFd) -> Result<(), ProgramError> {
+        Ok(self.attach(socket)?)
+    }
+}
+
 /// The type returned by [SocketFilter::attach]. Can be passed to [SocketFilter::detach].
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub struct SocketFilterLinkId(RawFd, RawFd);
@@ -145,3 +289,262 @@ impl Link for SocketFilterLink {
         Ok(())
     }
 }
+
+/// The algorithm used to spread packets between the sockets of a fanout group.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PacketFanout {
+    /// Packets of the same flow go to the same socket.
+    Hash = 0,
+    /// Packets are spread round-robin.
+    LoadBalance = 1,
+    /// Packets go to the socket matching the CPU they arrived on.
+    Cpu = 2,
+    /// Packets go to the next socket once the current one is backlogged.
+    Rollover = 3,
+    /// Packets are spread randomly.
+    Random = 4,
+    /// Packets go to the socket matching their receive queue.
+    QueueMapping = 5,
+}
+
+/// Options for opening a [`PacketSocket`].
+///
+/// By default the socket captures packets of all protocols on all interfaces.
+#[derive(Debug, Clone)]
+pub struct PacketSocketBuilder {
+    protocol: u16,
+    interface: Option<String>,
+    fanout: Option<(u16, PacketFanout)>,
+}
+
+impl Default for PacketSocketBuilder {
+    fn default() -> Self {
+        PacketSocketBuilder {
+            protocol: ETH_P_ALL as u16,
+            interface: None,
+            fanout: None,
+        }
+    }
+}
+
+impl PacketSocketBuilder {
+    /// Creates a new builder with the default options.
+    pub fn new() -> PacketSocketBuilder {
+        PacketSocketBuilder::default()
+    }
+
+    /// Only captures packets of the given ethernet protocol, eg `ETH_P_IP`.
+    pub fn protocol(&mut self, protocol: u16) -> &mut PacketSocketBuilder {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Only captures packets received on the given interface.
+    pub fn interface(&mut self, interface: &str) -> &mut PacketSocketBuilder {
+        self.interface = Some(interface.to_owned());
+        self
+    }
+
+    /// Joins the fanout group `group_id`, spreading packets between all the
+    /// sockets of the group using `mode`.
+    pub fn fanout(&mut self, group_id: u16, mode: PacketFanout) -> &mut PacketSocketBuilder {
+        self.fanout = Some((group_id, mode));
+        self
+    }
+
+    /// Opens the socket and attaches `filter` to it.
+    ///
+    /// `filter` can be a [`SocketFilter`] or a [`ClassicFilter`]. It's
+    /// attached before the socket starts receiving packets, so no unfiltered
+    /// packet is ever queued. It stays attached until the socket is closed,
+    /// even if the [`SocketFilter`] is unloaded.
+    pub fn open<F: SocketAttach + ?Sized>(&self, filter: &F) -> Result<PacketSocket, ProgramError> {
+        let if_index = match &self.interface {
+            Some(name) => ifindex_from_ifname(name)
+                .map_err(|_| ProgramError::UnknownInterface { name: name.clone() })?
+                as i32,
+            None => 0,
+        };
+
+        // create the socket with no protocol so that it doesn't receive any
+        // packet until it's bound
+        let fd = unsafe { socket(AF_PACKET, SOCK_RAW | SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(syscall_error("socket"));
+        }
+        let sock = PacketSocket { fd };
+        filter.attach_socket(fd)?;
+
+        let mut addr = unsafe { mem::zeroed::<sockaddr_ll>() };
+        addr.sll_family = AF_PACKET as u16;
+        addr.sll_protocol = self.protocol.to_be();
+        addr.sll_ifindex = if_index;
+        let ret = unsafe {
+            bind(
+                fd,
+                &addr as *const _ as *const sockaddr,
+                mem::size_of::<sockaddr_ll>() as socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(syscall_error("bind"));
+        }
+
+        if let Some((group_id, mode)) = self.fanout {
+            let arg = group_id as i32 | (mode as i32) << 16;
+            let ret = unsafe {
+                setsockopt(
+                    fd,
+                    SOL_PACKET,
+                    PACKET_FANOUT,
+                    &arg as *const _ as *const _,
+                    mem::size_of::<i32>() as u32,
+                )
+            };
+            if ret < 0 {
+                return Err(syscall_error("setsockopt"));
+            }
+        }
+
+        Ok(sock)
+    }
+}
+
+fn syscall_error(call: &str) -> ProgramError {
+    ProgramError::SyscallError {
+        call: call.to_owned(),
+        io_error: io::Error::last_os_error(),
+    }
+}
+
+/// A packet received from a [`PacketSocket`].
+#[derive(Debug, Copy, Clone)]
+pub struct PacketInfo {
+    /// The number of bytes written to the buffer.
+    pub len: usize,
+    /// The length of the packet, larger than `len` if it was truncated.
+    pub orig_len: usize,
+    /// The index of the interface the packet was received on.
+    pub if_index: u32,
+    /// The ethernet protocol of the packet.
+    pub protocol: u16,
+    /// The packet type, eg `PACKET_HOST` or `PACKET_OUTGOING`.
+    pub pkt_type: u8,
+}
+
+/// An `AF_PACKET` socket with a [`SocketFilter`] attached.
+///
+/// Each read returns a single packet, starting with its link layer header.
+/// The socket can be registered with a [`PollSet`](crate::maps::poll::PollSet)
+/// or any other poller through [`AsRawFd`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(Debug, thiserror::Error)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     IO(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::{
+///     socket_filter::{PacketFanout, PacketSocketBuilder},
+///     SocketFilter,
+/// };
+///
+/// let prog: &mut SocketFilter = bpf.program_mut("capture").unwrap().try_into()?;
+/// prog.load()?;
+/// let sock = PacketSocketBuilder::new()
+///     .interface("eth0")
+///     .fanout(42, PacketFanout::Hash)
+///     .open(prog)?;
+///
+/// let mut buf = [0u8; 65536];
+/// loop {
+///     let packet = sock.recv(&mut buf)?;
+///     println!("{} bytes on if {}", packet.orig_len, packet.if_index);
+/// }
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+pub struct PacketSocket {
+    fd: RawFd,
+}
+
+impl PacketSocket {
+    /// Receives a single packet into `buf`, blocking until one is available.
+    ///
+    /// Packets larger than `buf` are truncated.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<PacketInfo, io::Error> {
+        let mut addr = unsafe { mem::zeroed::<sockaddr_ll>() };
+        let mut addr_len = mem::size_of::<sockaddr_ll>() as socklen_t;
+        let ret = unsafe {
+            recvfrom(
+                self.fd,
+                buf.as_mut_ptr() as *mut _,
+                buf.len(),
+                MSG_TRUNC,
+                &mut addr as *mut _ as *mut sockaddr,
+                &mut addr_len,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let orig_len = ret as usize;
+
+        Ok(PacketInfo {
+            len: orig_len.min(buf.len()),
+            orig_len,
+            if_index: addr.sll_ifindex as u32,
+            protocol: u16::from_be(addr.sll_protocol),
+            pkt_type: addr.sll_pkttype,
+        })
+    }
+}
+
+impl AsRawFd for PacketSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        // closing the socket also detaches the filter
+        unsafe { close(self.fd) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+
+    use super::*;
+
+    #[test]
+    fn test_classic_filter() {
+        assert!(matches!(
+            ClassicFilter::new(&[]),
+            Err(SocketFilterError::InvalidClassicFilter { len: 0 })
+        ));
+
+        // ret #0, drops every packet
+        let drop_all = ClassicInsn {
+            code: 0x06,
+            jt: 0,
+            jf: 0,
+            k: 0,
+        };
+        let filter = ClassicFilter::new(&[drop_all]).unwrap();
+        assert_eq!(filter.instructions(), &[drop_all]);
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        filter.attach(socket.as_raw_fd()).unwrap();
+        filter.attach_socket(socket.as_raw_fd()).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+//! struct_ops programs.
+use std::{collections::HashMap, ffi::CString, os::unix::io::RawFd};
+
+use thiserror::Error;
+
+use crate::{
+    generated::bpf_prog_type::BPF_PROG_TYPE_STRUCT_OPS,
+    obj::{
+        self,
+        btf::{Btf, BtfKind, BtfMember, BtfType, Struct},
+        member_size,
+    },
+    programs::{load_program, FdLink, Program, ProgramData, ProgramError},
+    sys::{
+        bpf_create_struct_ops_map, bpf_link_create_struct_ops, bpf_map_delete_elem,
+        bpf_map_update_elem_ptr,
+    },
+};
+
+// not in the generated bindings yet
+const BPF_F_LINK: u32 = 1 << 13;
+
+/// The type returned when loading or registering a [`StructOps`] program
+/// fails.
+#[derive(Debug, Error)]
+pub enum StructOpsError {
+    /// The object doesn't define a struct_ops with the given name.
+    #[error("struct_ops `{name}` not found")]
+    NotFound {
+        /// The name of the struct_ops.
+        name: String,
+    },
+
+    /// The program isn't referenced by any struct_ops of the object.
+    #[error("the program doesn't implement a struct_ops member")]
+    NoMember,
+
+    /// The kernel type has no member with the given name.
+    #[error("`{type_name}` has no member `{member}`")]
+    UnknownMember {
+        /// The name of the struct_ops type.
+        type_name: String,
+        /// The name of the member.
+        member: String,
+    },
+
+    /// A member has a different size in the object and in the kernel.
+    #[error("member `{member}` is {size} bytes long, but {expected} bytes in the kernel")]
+    InvalidMemberSize {
+        /// The name of the member.
+        member: String,
+        /// The size of the member in the object.
+        size: usize,
+        /// The size of the member in the kernel.
+        expected: usize,
+    },
+
+    /// The BTF of the object wasn't loaded, which struct_ops maps require.
+    #[error("the object BTF isn't loaded")]
+    NoBTF,
+}
+
+/// A program implementing a member of a kernel struct_ops type, like a TCP
+/// congestion control algorithm.
+///
+/// struct_ops let eBPF programs implement kernel interfaces defined as
+/// structs of function pointers, like `struct tcp_congestion_ops`. The
+/// implementation is defined in the object as a variable of the struct type
+/// in the `.struct_ops` or `.struct_ops.link` section, whose function
+/// pointers are the programs of `struct_ops/` sections. It's registered with
+/// [`Bpf::register_struct_ops`](crate::Bpf::register_struct_ops), which
+/// loads the programs if needed.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.6, and 6.4
+/// for implementations defined in `.struct_ops.link`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     BtfError(#[from] aya::BtfError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError),
+/// # }
+/// # let mut bpf = aya::Bpf::load_file("ebpf_programs.o")?;
+/// use aya::Btf;
+///
+/// let btf = Btf::from_sys_fs()?;
+/// // the tcp_congestion_ops variable of the object is named `bpf_dctcp`
+/// let link = bpf.register_struct_ops("bpf_dctcp", &btf)?;
+/// // the algorithm can be used until the link is dropped
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_STRUCT_OPS")]
+pub struct StructOps {
+    pub(crate) data: ProgramData<FdLink>,
+    // the struct_ops type and the member implemented by the program
+    pub(crate) member: Option<(String, String)>,
+}
+
+impl StructOps {
+    /// Loads the program inside the kernel.
+    ///
+    /// The program is checked against the struct_ops member it implements,
+    /// which is looked up in `btf`.
+    pub fn load(&mut self, btf: &Btf) -> Result<(), ProgramError> {
+        let (type_name, member) = self.member.as_ref().ok_or(StructOpsError::NoMember)?;
+        let (type_id, s) = btf_struct(btf, type_name)?;
+        let index = s
+            .members
+            .iter()
+            .position(|m| member_name(btf, m).as_deref() == Some(member.as_str()))
+            .ok_or_else(|| StructOpsError::UnknownMember {
+                type_name: type_name.clone(),
+                member: member.clone(),
+            })?;
+        self.data.attach_btf_id = Some(type_id);
+        // the kernel reads the index of the member from expected_attach_type
+        self.data.struct_ops_member = Some(index as u32);
+        load_program(BPF_PROG_TYPE_STRUCT_OPS, &mut self.data)
+    }
+}
+
+/// A struct_ops implementation registered with
+/// [`Bpf::register_struct_ops`](crate::Bpf::register_struct_ops).
+///
+/// The implementation is unregistered when the link is dropped.
+#[derive(Debug)]
+pub struct StructOpsLink {
+    map_fd: Option<RawFd>,
+    link_fd: Option<RawFd>,
+}
+
+impl StructOpsLink {
+    /// Unregisters the implementation.
+    pub fn detach(mut self) -> Result<(), ProgramError> {
+        self.unregister()
+    }
+
+    fn unregister(&mut self) -> Result<(), ProgramError> {
+        let map_fd = match self.map_fd.take() {
+            Some(fd) => fd,
+            None => return Ok(()),
+        };
+        let ret = match self.link_fd.take() {
+            Some(link_fd) => {
+                unsafe { libc::close(link_fd) };
+                Ok(())
+            }
+            None => bpf_map_delete_elem(map_fd, &0u32)
+                .map(|_| ())
+                .map_err(|(_, io_error)| ProgramError::SyscallError {
+                    call: "bpf_map_delete_elem".to_owned(),
+                    io_error,
+                }),
+        };
+        unsafe { libc::close(map_fd) };
+        ret
+    }
+}
+
+impl Drop for StructOpsLink {
+    fn drop(&mut self) {
+        let _ = self.unregister();
+    }
+}
+
+// Creates the struct_ops map of `ops`, whose value is the kernel's
+// `bpf_struct_ops_<type>` wrapper around the struct, and registers it.
+pub(crate) fn register(
+    name: &str,
+    ops: &obj::StructOps,
+    programs: &mut HashMap<String, Program>,
+    btf: &Btf,
+) -> Result<StructOpsLink, ProgramError> {
+    let (value_type_id, value_type) =
+        btf_struct(btf, &format!("bpf_struct_ops_{}", ops.type_name))?;
+    let data = value_type
+        .members
+        .iter()
+        .find(|m| member_name(btf, m).as_deref() == Some("data"))
+        .ok_or_else(|| StructOpsError::UnknownMember {
+            type_name: ops.type_name.clone(),
+            member: "data".to_owned(),
+        })?;
+    let data_offset = value_type.member_bit_offset(data) / 8;
+    let kernel_type = match btf.type_by_id(btf.resolve_type(data.btf_type)?)? {
+        BtfType::Struct(s) => s,
+        _ => {
+            return Err(StructOpsError::NotFound {
+                name: ops.type_name.clone(),
+            }
+            .into())
+        }
+    };
+
+    let mut value = vec![0u8; value_type.size as usize];
+    let mut btf_fd = None;
+    for member in &ops.members {
+        let member_data = ops.member_data(member);
+        let kernel_member = match kernel_type
+            .members
+            .iter()
+            .find(|m| member_name(btf, m).as_deref() == Some(member.name.as_str()))
+        {
+            Some(m) => m,
+            // members missing from older kernels can be left unset
+            None if member.program.is_none() && member_data.iter().all(|b| *b == 0) => continue,
+            None => {
+                return Err(StructOpsError::UnknownMember {
+                    type_name: ops.type_name.clone(),
+                    member: member.name.clone(),
+                }
+                .into())
+            }
+        };
+        let offset = data_offset + kernel_type.member_bit_offset(kernel_member) / 8;
+        let bytes = match &member.program {
+            Some(program) => {
+                let program = match programs.get_mut(program) {
+                    Some(Program::StructOps(p)) => p,
+                    _ => return Err(ProgramError::UnexpectedProgramType),
+                };
+                if program.data.fd.is_none() {
+                    program.load(btf)?;
+                }
+                btf_fd = btf_fd.or(program.data.btf_fd);
+                (program.data.fd_or_err()? as u64).to_ne_bytes().to_vec()
+            }
+            None => {
+                let expected = member_size(btf, kernel_member.btf_type)?;
+                if expected != member.size {
+                    return Err(StructOpsError::InvalidMemberSize {
+                        member: member.name.clone(),
+                        size: member.size,
+                        expected,
+                    }
+                    .into());
+                }
+                member_data.to_vec()
+            }
+        };
+        value[offset..][..bytes.len()].copy_from_slice(&bytes);
+    }
+
+    let btf_fd = btf_fd.ok_or(StructOpsError::NoBTF)?;
+    let flags = if ops.link { BPF_F_LINK } else { 0 };
+    let c_name = CString::new(name).unwrap();
+    let map_fd =
+        bpf_create_struct_ops_map(&c_name, value.len() as u32, value_type_id, btf_fd, flags)
+            .map_err(|(_, io_error)| ProgramError::SyscallError {
+                call: "bpf_map_create".to_owned(),
+                io_error,
+            })? as RawFd;
+    let mut link = StructOpsLink {
+        map_fd: Some(map_fd),
+        link_fd: None,
+    };
+    // registers the implementation, unless it's defined in .struct_ops.link
+    bpf_map_update_elem_ptr(map_fd, &0u32 as *const _, value.as_mut_ptr(), 0).map_err(
+        |(_, io_error)| ProgramError::SyscallError {
+            call: "bpf_map_update_elem".to_owned(),
+            io_error,
+        },
+    )?;
+    if ops.link {
+        let link_fd = bpf_link_create_struct_ops(map_fd).map_err(|(_, io_error)| {
+            ProgramError::SyscallError {
+                call: "bpf_link_create".to_owned(),
+                io_error,
+            }
+        })? as RawFd;
+        link.link_fd = Some(link_fd);
+    }
+    Ok(link)
+}
+
+fn btf_struct<'a>(btf: &'a Btf, name: &str) -> Result<(u32, &'a Struct), ProgramError> {
+    let type_id = btf.id_by_type_name_kind(name, BtfKind::Struct)?;
+    match btf.type_by_id(type_id)? {
+        BtfType::Struct(s) => Ok((type_id, s)),
+        _ => Err(StructOpsError::NotFound {
+            name: name.to_owned(),
+        }
+        .into()),
+    }
+}
+
+fn member_name(btf: &Btf, member: &BtfMember) -> Option<String> {
+    btf.string_at(member.name_offset)
+        .ok()
+        .map(|name| name.into_owned())
+}
@@ -0,0 +1,111 @@
+//! Syscall programs.
+use std::{
+    ffi::CString,
+    os::unix::{ffi::OsStrExt, io::RawFd},
+    path::Path,
+};
+
+use crate::{
+    generated::{bpf_prog_type::BPF_PROG_TYPE_SYSCALL, BPF_F_SLEEPABLE},
+    pin::PinError,
+    programs::{load_program, FdLink, ProgramData, ProgramError},
+    sys::{bpf_get_object, bpf_prog_test_run, BpfProgTestRunAttrs},
+};
+
+/// A program that is executed on demand from user space and that can itself
+/// issue `bpf()` syscalls.
+///
+/// Syscall programs aren't attached to any hook point. Instead they're run
+/// with [`Syscall::run`], which executes the program synchronously using the
+/// `BPF_PROG_RUN` command. This makes them suitable for loader programs that
+/// create maps and load other programs from inside the kernel, and for
+/// kernel-side computations triggered from user space.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.14.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{Bpf, programs::Syscall};
+///
+/// let program: &mut Syscall = bpf.program_mut("loader").unwrap().try_into()?;
+/// program.load()?;
+///
+/// // the context is passed to the program and is updated with any changes
+/// // the program makes to it
+/// let mut ctx = [0u8; 16];
+/// let ret = program.run(&mut ctx)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_SYSCALL")]
+pub struct Syscall {
+    pub(crate) data: ProgramData<FdLink>,
+}
+
+impl Syscall {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        // syscall programs must be sleepable
+        self.data.flags |= BPF_F_SLEEPABLE;
+        load_program(BPF_PROG_TYPE_SYSCALL, &mut self.data)
+    }
+
+    /// Runs the program with the given context and returns its return value.
+    ///
+    /// Any change the program makes to the context is written back to `ctx`.
+    pub fn run(&self, ctx: &mut [u8]) -> Result<u32, ProgramError> {
+        run_syscall(self.data.fd_or_err()?, ctx)
+    }
+
+    /// Runs a syscall program pinned at `path` and returns its return value.
+    ///
+    /// This can be used to run programs loaded and pinned by another process.
+    /// Any change the program makes to the context is written back to `ctx`.
+    pub fn run_pinned<P: AsRef<Path>>(path: P, ctx: &mut [u8]) -> Result<u32, ProgramError> {
+        let path_string = CString::new(path.as_ref().as_os_str().as_bytes()).map_err(|e| {
+            PinError::InvalidPinPath {
+                error: e.to_string(),
+            }
+        })?;
+        let fd =
+            bpf_get_object(&path_string).map_err(|(_, io_error)| ProgramError::SyscallError {
+                call: "BPF_OBJ_GET".to_owned(),
+                io_error,
+            })? as RawFd;
+        let ret = run_syscall(fd, ctx);
+        unsafe {
+            libc::close(fd);
+        }
+        ret
+    }
+}
+
+fn run_syscall(prog_fd: RawFd, ctx: &mut [u8]) -> Result<u32, ProgramError> {
+    let attr = BpfProgTestRunAttrs {
+        ctx_in: (!ctx.is_empty()).then_some(ctx),
+        ..Default::default()
+    };
+    bpf_prog_test_run(prog_fd, attr)
+        .map(|out| out.retval)
+        .map_err(|(_, io_error)| ProgramError::SyscallError {
+            call: "bpf_prog_test_run".to_owned(),
+            io_error,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_pinned_invalid_path() {
+        assert!(matches!(
+            Syscall::run_pinned("/sys/fs/bpf/\0prog", &mut []),
+            Err(ProgramError::PinError(PinError::InvalidPinPath { .. }))
+        ));
+    }
+}
@@ -2,18 +2,28 @@
 use thiserror::Error;
 
 use std::{
+    collections::VecDeque,
     ffi::{CStr, CString},
     io,
+    os::unix::io::{AsRawFd, RawFd},
+    time::Duration,
 };
 
+use libc::{poll, pollfd, POLLIN, RTNLGRP_TC};
+
 use crate::{
     generated::{
         bpf_prog_type::BPF_PROG_TYPE_SCHED_CLS, TC_H_CLSACT, TC_H_MIN_EGRESS, TC_H_MIN_INGRESS,
     },
-    programs::{define_link_wrapper, load_program, Link, ProgramData, ProgramError},
+    programs::{
+        define_link_wrapper,
+        guard::{self, Rollback},
+        load_program, Link, ProgramData, ProgramError,
+    },
     sys::{
-        netlink_find_filter_with_name, netlink_qdisc_add_clsact, netlink_qdisc_attach,
-        netlink_qdisc_detach,
+        ext_ack_suffix, netlink_filters, netlink_find_filter_with_name, netlink_qdisc_add_clsact,
+        netlink_qdisc_attach, netlink_qdisc_del_clsact, netlink_qdisc_detach, NetlinkError,
+        NetlinkMonitor, NlTcFilter,
     },
     util::{ifindex_from_ifname, tc_handler_make},
 };
@@ -78,11 +88,13 @@ pub struct SchedClassifier {
 #[derive(Debug, Error)]
 pub enum TcError {
     /// netlink error while attaching ebpf program
-    #[error("netlink error while attaching ebpf program to tc")]
+    #[error("netlink error while attaching ebpf program to tc{}", ext_ack_suffix(.message))]
     NetlinkError {
         /// the [`io::Error`] from the netlink call
         #[source]
         io_error: io::Error,
+        /// the extended ack message the kernel reported with the error, if any
+        message: Option<String>,
     },
     /// the clsact qdisc is already attached
     #[error("the clsact qdisc is already attached")]
@@ -97,6 +109,13 @@ impl TcAttachType {
             TcAttachType::Egress => tc_handler_make(TC_H_CLSACT, TC_H_MIN_EGRESS),
         }
     }
+
+    pub(crate) fn from_parent(parent: u32) -> TcAttachType {
+        [TcAttachType::Ingress, TcAttachType::Egress]
+            .into_iter()
+            .find(|attach_type| attach_type.parent() == parent)
+            .unwrap_or(TcAttachType::Custom(parent))
+    }
 }
 
 impl SchedClassifier {
@@ -125,13 +144,19 @@ impl SchedClassifier {
         attach_type: TcAttachType,
         priority: u16,
     ) -> Result<SchedClassifierLinkId, ProgramError> {
+        trace_span!("attach", name = ?self.data.name, interface, ?attach_type, priority);
         let prog_fd = self.data.fd_or_err()?;
-        let if_index = ifindex_from_ifname(interface)
-            .map_err(|io_error| TcError::NetlinkError { io_error })?;
+        let if_index =
+            ifindex_from_ifname(interface).map_err(|_| ProgramError::UnknownInterface {
+                name: interface.to_string(),
+            })?;
         let priority = unsafe {
             netlink_qdisc_attach(if_index as i32, &attach_type, prog_fd, &self.name, priority)
         }
-        .map_err(|io_error| TcError::NetlinkError { io_error })?;
+        .map_err(|NetlinkError { io_error, message }| TcError::NetlinkError {
+            io_error,
+            message,
+        })?;
 
         self.data.links.insert(SchedClassifierLink(TcLink {
             if_index: if_index as i32,
@@ -177,8 +202,9 @@ impl Link for TcLink {
     }
 
     fn detach(self) -> Result<(), ProgramError> {
-        unsafe { netlink_qdisc_detach(self.if_index, &self.attach_type, self.priority) }
-            .map_err(|io_error| TcError::NetlinkError { io_error })?;
+        unsafe { netlink_qdisc_detach(self.if_index, &self.attach_type, self.priority) }.map_err(
+            |NetlinkError { io_error, message }| TcError::NetlinkError { io_error, message },
+        )?;
         Ok(())
     }
 }
@@ -192,13 +218,32 @@ define_link_wrapper!(
     TcLinkId
 );
 
+impl guard::sealed::Sealed for SchedClassifierLink {
+    fn rollback(&self) -> Result<Rollback, ProgramError> {
+        Ok(Rollback::tc(
+            self.0.if_index,
+            self.0.attach_type,
+            self.0.priority,
+        ))
+    }
+}
+
 /// Add the `clasct` qdisc to the given interface.
 ///
 /// The `clsact` qdisc must be added to an interface before [`SchedClassifier`]
 /// programs can be attached.
 pub fn qdisc_add_clsact(if_name: &str) -> Result<(), io::Error> {
     let if_index = ifindex_from_ifname(if_name)?;
-    unsafe { netlink_qdisc_add_clsact(if_index as i32) }
+    unsafe { netlink_qdisc_add_clsact(if_index as i32) }.map_err(io::Error::from)
+}
+
+/// Removes the `clsact` qdisc from the given interface.
+///
+/// This also detaches all the [`SchedClassifier`] programs attached to the
+/// interface, including the ones attached by other processes.
+pub fn qdisc_remove_clsact(if_name: &str) -> Result<(), io::Error> {
+    let if_index = ifindex_from_ifname(if_name)?;
+    unsafe { netlink_qdisc_del_clsact(if_index as i32) }.map_err(io::Error::from)
 }
 
 /// Detaches the programs with the given name.
@@ -247,3 +292,315 @@ fn qdisc_detach_program_fast(
 
     Ok(())
 }
+
+/// A filter attached to a traffic control hook, see [`query_filters`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcFilter {
+    /// The priority of the filter. Filters run in increasing order of
+    /// priority.
+    pub priority: u16,
+    /// The handle of the filter.
+    pub handle: u32,
+    /// The classifier of the filter, `bpf` for [`SchedClassifier`] programs.
+    pub kind: String,
+    /// The name of a `bpf` filter.
+    pub name: Option<String>,
+    /// The id of the program of a `bpf` filter.
+    pub program_id: Option<u32>,
+    /// Whether a `bpf` filter runs in direct action mode, where the verdict of
+    /// the program is final and the filters after it don't run.
+    pub direct_action: bool,
+}
+
+impl From<NlTcFilter> for TcFilter {
+    fn from(filter: NlTcFilter) -> TcFilter {
+        TcFilter {
+            priority: filter.priority,
+            handle: filter.handle,
+            kind: filter.kind.unwrap_or_default(),
+            name: filter.name.map(|name| name.to_string_lossy().into_owned()),
+            program_id: filter.program_id,
+            direct_action: filter.direct_action,
+        }
+    }
+}
+
+/// Returns the filters attached to the given hook of `if_name`, in the order
+/// they run.
+///
+/// This includes the filters attached by other processes and tools, like
+/// `tc filter add`.
+pub fn query_filters(if_name: &str, attach_type: TcAttachType) -> Result<Vec<TcFilter>, io::Error> {
+    let if_index = ifindex_from_ifname(if_name)? as i32;
+    let mut filters = unsafe { netlink_filters(if_index, attach_type)? }
+        .into_iter()
+        // the dump starts each priority with an entry for the classifier
+        // itself, which isn't a filter
+        .filter(|filter| filter.handle != 0)
+        .map(TcFilter::from)
+        .collect::<Vec<_>>();
+    // the filters of a priority are dumped in the order they run, which isn't
+    // the order of their handles since cls_bpf inserts new filters first, so
+    // keep that order
+    filters.sort_by_key(|filter| filter.priority);
+    Ok(filters)
+}
+
+/// A problem with a filter found by [`check_filter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcConflict {
+    /// The filter isn't attached anymore, for example because another
+    /// process or `tc filter del` removed it.
+    Missing,
+    /// Other filters run before the filter, and can take the packets before
+    /// it sees them.
+    Outranked {
+        /// The filters running before the filter.
+        filters: Vec<TcFilter>,
+    },
+}
+
+/// Checks that the filter `name` attached at `priority` is still attached,
+/// and that no other filter runs before it.
+///
+/// `name` and `priority` are the name of the [`SchedClassifier`] program and
+/// the priority it was attached with. Returns `None` if the filter is
+/// attached and runs first.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::programs::tc::{self, TcAttachType, TcConflict};
+///
+/// match tc::check_filter("eth0", TcAttachType::Ingress, 50, "redirect_ingress")? {
+///     None => {}
+///     Some(TcConflict::Missing) => println!("our filter was removed"),
+///     Some(TcConflict::Outranked { filters }) => {
+///         println!("{} filters run before ours", filters.len())
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn check_filter(
+    if_name: &str,
+    attach_type: TcAttachType,
+    priority: u16,
+    name: &str,
+) -> Result<Option<TcConflict>, io::Error> {
+    let filters = query_filters(if_name, attach_type)?;
+    Ok(find_conflict(filters, priority, name))
+}
+
+// `filters` are in the order they run, as returned by `query_filters`.
+fn find_conflict(mut filters: Vec<TcFilter>, priority: u16, name: &str) -> Option<TcConflict> {
+    let index = match filters
+        .iter()
+        .position(|f| f.priority == priority && f.name.as_deref() == Some(name))
+    {
+        Some(index) => index,
+        None => return Some(TcConflict::Missing),
+    };
+    filters.truncate(index);
+    (!filters.is_empty()).then_some(TcConflict::Outranked { filters })
+}
+
+/// A change of the filters of a traffic control hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcFilterEvent {
+    /// A filter was added or replaced.
+    Added {
+        /// The index of the interface.
+        if_index: u32,
+        /// The hook of the filter.
+        attach_type: TcAttachType,
+        /// The filter.
+        filter: TcFilter,
+    },
+    /// A filter was removed.
+    ///
+    /// When all the filters of a priority are removed at once, which is how
+    /// [`SchedClassifier`] programs are detached, the handle of the filter is
+    /// 0.
+    Removed {
+        /// The index of the interface.
+        if_index: u32,
+        /// The hook of the filter.
+        attach_type: TcAttachType,
+        /// The filter.
+        filter: TcFilter,
+    },
+}
+
+/// A subscription to the changes of the traffic control filters of all the
+/// interfaces.
+///
+/// `TcFilterEvents` is an [`Iterator`] that blocks until the next event. It
+/// can also be polled with [`TcFilterEvents::try_next`] once its file
+/// descriptor is readable.
+///
+/// The kernel drops notifications when they aren't read fast enough, in
+/// which case reading fails with `ENOBUFS`; the filters should then be
+/// listed again with [`query_filters`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::programs::tc::{TcFilterEvent, TcFilterEvents};
+///
+/// for event in TcFilterEvents::subscribe()? {
+///     if let TcFilterEvent::Removed { if_index, filter, .. } = event? {
+///         println!("filter {:?} removed from {}", filter.name, if_index);
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct TcFilterEvents {
+    monitor: NetlinkMonitor,
+    pending: VecDeque<TcFilterEvent>,
+}
+
+impl TcFilterEvents {
+    /// Subscribes to the changes of the filters.
+    pub fn subscribe() -> Result<TcFilterEvents, io::Error> {
+        Ok(TcFilterEvents {
+            monitor: NetlinkMonitor::new(RTNLGRP_TC)?,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Returns the next event if one is available, without blocking.
+    pub fn try_next(&mut self) -> Result<Option<TcFilterEvent>, io::Error> {
+        while self.pending.is_empty() {
+            let filters = self.monitor.recv_filters()?;
+            if filters.is_empty() {
+                return Ok(None);
+            }
+            self.pending.extend(filters.into_iter().map(|filter| {
+                let if_index = filter.if_index as u32;
+                let attach_type = TcAttachType::from_parent(filter.parent);
+                if filter.deleted {
+                    TcFilterEvent::Removed {
+                        if_index,
+                        attach_type,
+                        filter: filter.into(),
+                    }
+                } else {
+                    TcFilterEvent::Added {
+                        if_index,
+                        attach_type,
+                        filter: filter.into(),
+                    }
+                }
+            }));
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    /// Returns the next event, waiting up to `timeout` for one, or forever if
+    /// `timeout` is `None`.
+    ///
+    /// Returns `None` if no event happened before the timeout.
+    pub fn next_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<TcFilterEvent>, io::Error> {
+        loop {
+            if let Some(event) = self.try_next()? {
+                return Ok(Some(event));
+            }
+            let mut fds = [pollfd {
+                fd: self.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            }];
+            let timeout = timeout.map(|t| t.as_millis() as i32).unwrap_or(-1);
+            // Safety: libc wrapper
+            match unsafe { poll(fds.as_mut_ptr(), 1, timeout) } {
+                0 => return Ok(None),
+                ret if ret < 0 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Iterator for TcFilterEvents {
+    type Item = Result<TcFilterEvent, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_timeout(None).transpose()
+    }
+}
+
+impl AsRawFd for TcFilterEvents {
+    fn as_raw_fd(&self) -> RawFd {
+        self.monitor.fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(priority: u16, name: &str) -> TcFilter {
+        TcFilter {
+            priority,
+            handle: 1,
+            kind: "bpf".to_owned(),
+            name: Some(name.to_owned()),
+            program_id: Some(42),
+            direct_action: true,
+        }
+    }
+
+    #[test]
+    fn test_find_conflict() {
+        assert_eq!(
+            find_conflict(vec![filter(50, "ours"), filter(60, "theirs")], 50, "ours"),
+            None
+        );
+        assert_eq!(
+            find_conflict(vec![filter(60, "theirs")], 50, "ours"),
+            Some(TcConflict::Missing)
+        );
+        // a filter with the same name at another priority isn't ours
+        assert_eq!(
+            find_conflict(vec![filter(51, "ours")], 50, "ours"),
+            Some(TcConflict::Missing)
+        );
+        assert_eq!(
+            find_conflict(vec![filter(10, "theirs"), filter(50, "ours")], 50, "ours"),
+            Some(TcConflict::Outranked {
+                filters: vec![filter(10, "theirs")]
+            })
+        );
+        // within a priority, the filters run in the order they're dumped,
+        // whatever their handles
+        let mut theirs = filter(50, "theirs");
+        let ours = filter(50, "ours");
+        theirs.handle = 3;
+        assert_eq!(
+            find_conflict(vec![theirs.clone(), ours.clone()], 50, "ours"),
+            Some(TcConflict::Outranked {
+                filters: vec![theirs.clone()]
+            })
+        );
+        assert_eq!(find_conflict(vec![ours, theirs], 50, "ours"), None);
+    }
+
+    #[test]
+    fn test_attach_type_from_parent() {
+        for attach_type in [
+            TcAttachType::Ingress,
+            TcAttachType::Egress,
+            TcAttachType::Custom(0x10001),
+        ] {
+            assert_eq!(TcAttachType::from_parent(attach_type.parent()), attach_type);
+        }
+    }
+}
@@ -8,13 +8,18 @@ use crate::{
     },
 };
 
-/// Marks a function as a [BTF-enabled raw tracepoint][1] eBPF program that can be attached at
-/// a pre-defined kernel trace point.
+/// A [BTF-enabled raw tracepoint][1] eBPF program that can be attached at a
+/// pre-defined kernel trace point.
 ///
 /// The kernel provides a set of pre-defined trace points that eBPF programs can
 /// be attached to. See `/sys/kernel/debug/tracing/events` for a list of which
 /// events can be traced.
 ///
+/// Unlike [`RawTracePoint`](crate::programs::RawTracePoint) programs, the
+/// arguments of the tracepoint are typed using the kernel BTF, so the
+/// verifier knows their types and pointer arguments can be dereferenced
+/// directly. The programs are found in `tp_btf/<tracepoint>` sections.
+///
 /// # Minimum kernel version
 ///
 /// The minimum kernel version required to use this feature is 5.5.
@@ -1,12 +1,14 @@
 //! Tracepoint programs.
-use std::{fs, io};
+use std::{fs, io, path::Path};
 use thiserror::Error;
 
 use crate::{
     generated::bpf_prog_type::BPF_PROG_TYPE_TRACEPOINT,
     programs::{
-        define_link_wrapper, load_program,
-        perf_attach::{perf_attach, PerfLink, PerfLinkId},
+        attach_error, define_link_wrapper, load_program,
+        perf_attach::{
+            impl_perf_link_control, perf_attach, perf_event_set_filter, PerfLink, PerfLinkId,
+        },
         ProgramData, ProgramError,
     },
     sys::perf_event_open_trace_point,
@@ -77,13 +79,47 @@ impl TracePoint {
     ///
     /// The returned value can be used to detach, see [TracePoint::detach].
     pub fn attach(&mut self, category: &str, name: &str) -> Result<TracePointLinkId, ProgramError> {
-        let id = read_sys_fs_trace_point_id(category, name)?;
-        let fd = perf_event_open_trace_point(id, None).map_err(|(_code, io_error)| {
-            ProgramError::SyscallError {
-                call: "perf_event_open".to_owned(),
-                io_error,
-            }
-        })? as i32;
+        self.attach_impl(category, name, None)
+    }
+
+    /// Attaches to a given trace point, with a trace event filter.
+    ///
+    /// `filter` is a trace event filter expression such as `common_pid != 0`,
+    /// evaluated by the kernel for each event. The fields available to the
+    /// filter are listed in `/sys/kernel/debug/tracing/events/<category>/<name>/format`.
+    /// See the kernel's [event filtering] documentation for the syntax.
+    ///
+    /// The returned value can be used to detach, see [TracePoint::detach].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidPerfEventFilter`] if the kernel rejects
+    /// the filter.
+    ///
+    /// [event filtering]: https://docs.kernel.org/trace/events.html#event-filtering
+    pub fn attach_with_filter(
+        &mut self,
+        category: &str,
+        name: &str,
+        filter: &str,
+    ) -> Result<TracePointLinkId, ProgramError> {
+        self.attach_impl(category, name, Some(filter))
+    }
+
+    fn attach_impl(
+        &mut self,
+        category: &str,
+        name: &str,
+        filter: Option<&str>,
+    ) -> Result<TracePointLinkId, ProgramError> {
+        let tracefs = find_tracefs_path()?;
+        let id = read_sys_fs_trace_point_id(tracefs, category, name)?;
+        let fd = perf_event_open_trace_point(id, None)
+            .map_err(|(_, io_error)| attach_error("perf_event_open", io_error))?
+            as i32;
+        if let Some(filter) = filter {
+            perf_event_set_filter(fd, filter)?;
+        }
 
         perf_attach(&mut self.data, fd)
     }
@@ -113,11 +149,31 @@ define_link_wrapper!(
     PerfLinkId
 );
 
+impl_perf_link_control!(TracePointLink);
+
+// tracefs has its own mount point since 4.1, older kernels and systems that
+// don't mount it there only expose it under debugfs.
+const TRACEFS_PATHS: [&str; 2] = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+// Returns the tracefs mount point, reporting a missing mount as such rather
+// than as a missing trace point file.
+pub(crate) fn find_tracefs_path() -> Result<&'static Path, ProgramError> {
+    TRACEFS_PATHS
+        .iter()
+        .map(Path::new)
+        // the mount points exist even when nothing is mounted on them
+        .find(|path| path.join("events").is_dir())
+        .ok_or_else(|| ProgramError::TraceFsUnavailable {
+            path: TRACEFS_PATHS[0].into(),
+        })
+}
+
 pub(crate) fn read_sys_fs_trace_point_id(
+    tracefs: &Path,
     category: &str,
     name: &str,
 ) -> Result<u32, TracePointError> {
-    let file = format!("/sys/kernel/debug/tracing/events/{}/{}/id", category, name);
+    let file = format!("{}/events/{}/{}/id", tracefs.display(), category, name);
 
     let id = fs::read_to_string(&file).map_err(|io_error| TracePointError::FileError {
         filename: file.clone(),
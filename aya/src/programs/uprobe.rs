@@ -17,8 +17,8 @@ use crate::{
     generated::bpf_prog_type::BPF_PROG_TYPE_KPROBE,
     programs::{
         define_link_wrapper, load_program,
-        perf_attach::{PerfLink, PerfLinkId},
-        probe::{attach, ProbeKind},
+        perf_attach::{impl_perf_link_control, PerfLink, PerfLinkId},
+        probe::{self, attach, ProbeKind},
         ProgramData, ProgramError,
     },
 };
@@ -67,6 +67,13 @@ impl UProbe {
     /// The `target` argument can be an absolute path to a binary or library, or
     /// a library name (eg: `"libc"`).
     ///
+    /// If `pid` is in another mount namespace, for example because it runs in
+    /// a container, the target is resolved in the filesystem of the process,
+    /// through `/proc/<pid>/root`. Absolute paths, the libraries mapped by the
+    /// process and `/etc/ld.so.cache` are then looked up inside the
+    /// container, so the paths of the host, like for the overlayfs layers of
+    /// the container, don't need to be known.
+    ///
     /// If the program is an `uprobe`, it is attached to the *start* address of the target
     /// function.  Instead if the program is a `uretprobe`, it is attached to the return address of
     /// the target function.
@@ -79,47 +86,44 @@ impl UProbe {
         target: T,
         pid: Option<pid_t>,
     ) -> Result<UProbeLinkId, ProgramError> {
-        let target = target.as_ref();
-        let target_str = &*target.as_os_str().to_string_lossy();
-
-        let mut path = if let Some(pid) = pid {
-            find_lib_in_proc_maps(pid, target_str).map_err(|io_error| UProbeError::FileError {
-                filename: format!("/proc/{}/maps", pid),
-                io_error,
-            })?
-        } else {
-            None
-        };
-
-        if path.is_none() {
-            path = if target.is_absolute() {
-                Some(target_str)
-            } else {
-                let cache =
-                    LD_SO_CACHE
-                        .as_ref()
-                        .map_err(|error| UProbeError::InvalidLdSoCache {
-                            io_error: error.clone(),
-                        })?;
-                cache.resolve(target_str)
-            }
-            .map(String::from)
-        };
+        self.attach_with_resolver(fn_name, offset, target, pid, &ElfSymbolResolver)
+    }
 
-        let path = path.ok_or(UProbeError::InvalidTarget {
-            path: target.to_owned(),
-        })?;
+    /// Attaches the program, resolving `fn_name` with the given resolver.
+    ///
+    /// This is the same as [`UProbe::attach`], except that the offset of
+    /// `fn_name` within the resolved target is provided by `resolver`
+    /// instead of being read from the ELF symbol table of the target. See
+    /// [`SymbolResolver`].
+    pub fn attach_with_resolver<T: AsRef<Path>>(
+        &mut self,
+        fn_name: Option<&str>,
+        offset: u64,
+        target: T,
+        pid: Option<pid_t>,
+        resolver: &dyn SymbolResolver,
+    ) -> Result<UProbeLinkId, ProgramError> {
+        let path = resolve_target(target.as_ref(), pid)?;
 
         let sym_offset = if let Some(fn_name) = fn_name {
-            resolve_symbol(&path, fn_name).map_err(|error| UProbeError::SymbolError {
-                symbol: fn_name.to_string(),
-                error: Box::new(error),
-            })?
+            resolver
+                .resolve(Path::new(&path), fn_name)
+                .map_err(|error| UProbeError::SymbolError {
+                    symbol: fn_name.to_string(),
+                    error,
+                })?
         } else {
             0
         };
 
-        attach(&mut self.data, self.kind, &path, sym_offset + offset, pid)
+        attach(
+            &mut self.data,
+            self.kind,
+            &path,
+            sym_offset + offset,
+            pid,
+            None,
+        )
     }
 
     /// Detaches the program.
@@ -147,6 +151,87 @@ define_link_wrapper!(
     PerfLinkId
 );
 
+impl_perf_link_control!(UProbeLink);
+
+/// Returns the uprobe events left in tracefs by processes that exited.
+///
+/// See [`kprobe::stale_probe_events`](crate::programs::kprobe::stale_probe_events),
+/// uprobes are created as events of the `uprobe_events` file on the same
+/// kernels.
+pub fn stale_probe_events() -> Result<Vec<String>, UProbeError> {
+    probe::stale_probe_events(ProbeKind::UProbe)
+        .map_err(|(filename, io_error)| UProbeError::FileError { filename, io_error })
+}
+
+/// Deletes the uprobe events left in tracefs by processes that exited, and
+/// returns their names.
+///
+/// The events still used by a perf event aren't deleted.
+pub fn remove_stale_probe_events() -> Result<Vec<String>, UProbeError> {
+    probe::remove_stale_probe_events(ProbeKind::UProbe)
+        .map_err(|(filename, io_error)| UProbeError::FileError { filename, io_error })
+}
+
+/// Resolves the functions uprobes are attached to.
+///
+/// [`UProbe::attach`] looks the function up in the ELF symbol table of the
+/// target, see [`ElfSymbolResolver`]. Functions that aren't in the symbol
+/// table, for example code generated by a JIT compiler and described by a
+/// perf map file or a runtime specific symbol server, can be attached to with
+/// [`UProbe::attach_with_resolver`] and a custom resolver.
+///
+/// Closures with the signature of [`SymbolResolver::resolve`] implement the
+/// trait.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::{
+///     uprobe::{ElfSymbolResolver, SymbolResolver},
+///     UProbe,
+/// };
+/// use std::path::Path;
+///
+/// // functions known to a symbol server, falling back to the symbol table
+/// let resolver = |target: &Path, symbol: &str| match symbol {
+///     "handle_request" => Ok(0x1f40),
+///     _ => ElfSymbolResolver.resolve(target, symbol),
+/// };
+///
+/// let program: &mut UProbe = bpf.program_mut("handle_request").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach_with_resolver(Some("handle_request"), 0, "/usr/bin/server", None, &resolver)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait SymbolResolver {
+    /// Returns the offset of `symbol` within `target`.
+    ///
+    /// `target` is the path to the binary or library the uprobe is attached
+    /// to, after library names have been resolved.
+    fn resolve(&self, target: &Path, symbol: &str) -> Result<u64, Box<dyn Error + Send + Sync>>;
+}
+
+impl<F> SymbolResolver for F
+where
+    F: Fn(&Path, &str) -> Result<u64, Box<dyn Error + Send + Sync>>,
+{
+    fn resolve(&self, target: &Path, symbol: &str) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        self(target, symbol)
+    }
+}
+
+/// The default [`SymbolResolver`], which reads the dynamic and static symbol
+/// tables of the target.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ElfSymbolResolver;
+
+impl SymbolResolver for ElfSymbolResolver {
+    fn resolve(&self, target: &Path, symbol: &str) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        Ok(resolve_symbol(target, symbol)?)
+    }
+}
+
 /// The type returned when attaching an [`UProbe`] fails.
 #[derive(Debug, Error)]
 pub enum UProbeError {
@@ -186,6 +271,71 @@ pub enum UProbeError {
     },
 }
 
+// Resolves the path of the binary or library a uprobe is attached to, see
+// UProbe::attach.
+pub(crate) fn resolve_target(target: &Path, pid: Option<pid_t>) -> Result<String, UProbeError> {
+    let target_str = &*target.as_os_str().to_string_lossy();
+
+    // the paths found in /proc/<pid>/maps and the absolute targets are
+    // relative to the root of the process, which differs from ours if the
+    // process runs in a container
+    let root = pid.and_then(proc_root);
+
+    let mut path = if let Some(pid) = pid {
+        find_lib_in_proc_maps(pid, target_str).map_err(|io_error| UProbeError::FileError {
+            filename: format!("/proc/{}/maps", pid),
+            io_error,
+        })?
+    } else {
+        None
+    };
+
+    if path.is_none() {
+        path = if target.is_absolute() {
+            Some(target_str.to_owned())
+        } else if let Some(root) = &root {
+            let cache =
+                LdSoCache::load(under_root(root, LD_SO_CACHE_FILE)).map_err(|io_error| {
+                    UProbeError::InvalidLdSoCache {
+                        io_error: Arc::new(io_error),
+                    }
+                })?;
+            cache.resolve(target_str).map(String::from)
+        } else {
+            let cache = LD_SO_CACHE
+                .as_ref()
+                .map_err(|error| UProbeError::InvalidLdSoCache {
+                    io_error: error.clone(),
+                })?;
+            cache.resolve(target_str).map(String::from)
+        }
+    };
+
+    let path = path.ok_or(UProbeError::InvalidTarget {
+        path: target.to_owned(),
+    })?;
+    Ok(match &root {
+        Some(root) => under_root(root, &path).to_string_lossy().into_owned(),
+        None => path,
+    })
+}
+
+// Returns `/proc/<pid>/root` if `pid` is in another mount namespace than the
+// current process. The namespaces can't be compared without the permission
+// to trace the process, the paths are then resolved from the current root as
+// before namespaces were taken into account.
+fn proc_root(pid: pid_t) -> Option<PathBuf> {
+    let ns = fs::read_link(format!("/proc/{}/ns/mnt", pid)).ok()?;
+    if ns == fs::read_link("/proc/self/ns/mnt").ok()? {
+        return None;
+    }
+    Some(PathBuf::from(format!("/proc/{}/root", pid)))
+}
+
+fn under_root(root: &Path, path: &str) -> PathBuf {
+    root.join(path.trim_start_matches('/'))
+}
+
 fn proc_maps_libs(pid: pid_t) -> Result<Vec<(String, String)>, io::Error> {
     let maps_file = format!("/proc/{}/maps", pid);
     let data = fs::read_to_string(maps_file)?;
@@ -321,7 +471,7 @@ enum ResolveSymbolError {
     Unknown(String),
 }
 
-fn resolve_symbol(path: &str, symbol: &str) -> Result<u64, ResolveSymbolError> {
+fn resolve_symbol(path: &Path, symbol: &str) -> Result<u64, ResolveSymbolError> {
     let data = fs::read(path)?;
     let obj = object::read::File::parse(&*data)?;
 
@@ -331,3 +481,110 @@ fn resolve_symbol(path: &str, symbol: &str) -> Result<u64, ResolveSymbolError> {
         .map(|s| s.address())
         .ok_or_else(|| ResolveSymbolError::Unknown(symbol.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use libc::EPERM;
+
+    use crate::{
+        obj::ProgramSection,
+        programs::ProgramError,
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    thread_local! {
+        // the offset of the last uprobe perf event opened on this thread
+        static PROBE_OFFSET: Cell<Option<u64>> = const { Cell::new(None) };
+    }
+
+    fn new_uprobe() -> UProbe {
+        UProbe {
+            data: ProgramData::from_fd(
+                None,
+                42,
+                ProgramSection::UProbe {
+                    name: "handle_request".to_owned(),
+                },
+            ),
+            kind: ProbeKind::UProbe,
+        }
+    }
+
+    #[test]
+    fn test_attach_with_resolver() {
+        // not in the symbol table of the target, which doesn't even exist.
+        // The uprobe is created with perf_event_open, as on kernels 4.17 and
+        // later.
+        let target = "/nonexistent/server";
+        let calls = RefCell::new(Vec::new());
+        let resolver = |target: &Path, symbol: &str| {
+            calls
+                .borrow_mut()
+                .push((target.to_owned(), symbol.to_owned()));
+            Ok(0x1f40)
+        };
+        override_syscall(|call| match call {
+            Syscall::PerfEventOpen { attr, .. } => {
+                PROBE_OFFSET
+                    .with(|offset| offset.set(Some(unsafe { attr.__bindgen_anon_4.config2 })));
+                Err((-1, io::Error::from_raw_os_error(EPERM)))
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EPERM))),
+        });
+
+        let mut uprobe = new_uprobe();
+        let res = uprobe.attach_with_resolver(Some("handle_request"), 4, target, None, &resolver);
+        assert!(res.is_err());
+        assert_eq!(
+            *calls.borrow(),
+            vec![(PathBuf::from(target), "handle_request".to_owned())]
+        );
+        assert_eq!(PROBE_OFFSET.with(|offset| offset.get()), Some(0x1f44));
+    }
+
+    #[test]
+    fn test_attach_with_resolver_error() {
+        let resolver = |_: &Path, symbol: &str| Err(format!("unknown symbol {symbol}").into());
+
+        let mut uprobe = new_uprobe();
+        let res = uprobe.attach_with_resolver(
+            Some("handle_request"),
+            0,
+            "/nonexistent/server",
+            None,
+            &resolver,
+        );
+        match res {
+            Err(ProgramError::UProbeError(UProbeError::SymbolError { symbol, error })) => {
+                assert_eq!(symbol, "handle_request");
+                assert_eq!(error.to_string(), "unknown symbol handle_request");
+            }
+            res => panic!("unexpected result: {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_under_root() {
+        let root = Path::new("/proc/42/root");
+        assert_eq!(
+            under_root(root, "/usr/lib/libc.so.6"),
+            Path::new("/proc/42/root/usr/lib/libc.so.6")
+        );
+        assert_eq!(
+            under_root(root, "app/server"),
+            Path::new("/proc/42/root/app/server")
+        );
+    }
+
+    #[test]
+    fn test_proc_root() {
+        // same mount namespace
+        assert_eq!(proc_root(std::process::id() as pid_t), None);
+        // the namespace can't be read, paths are resolved from our root
+        assert_eq!(proc_root(-1), None);
+    }
+}
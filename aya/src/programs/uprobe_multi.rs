@@ -0,0 +1,210 @@
+//! User space probes attached to many locations at once.
+use libc::pid_t;
+use std::{ffi::CString, os::unix::io::RawFd, path::Path};
+
+use crate::{
+    generated::{bpf_attach_type::BPF_TRACE_UPROBE_MULTI, bpf_prog_type::BPF_PROG_TYPE_KPROBE},
+    programs::{
+        attach_error, define_link_wrapper, load_program,
+        probe::ProbeKind,
+        uprobe::{resolve_target, ElfSymbolResolver, SymbolResolver, UProbeError},
+        FdLink, FdLinkId, ProgramData, ProgramError,
+    },
+    sys::bpf_link_create_uprobe_multi,
+};
+
+// not in the generated bindings yet
+const BPF_F_UPROBE_MULTI_RETURN: u32 = 1;
+
+/// A user space probe attached to many locations of a binary with a single
+/// link.
+///
+/// [`UProbe`](crate::programs::UProbe) creates one perf event for each
+/// location it's attached to. Multi uprobes are attached to all the
+/// locations at once with a `BPF_TRACE_UPROBE_MULTI` link, and each location
+/// can be given a cookie, which the program reads with
+/// `bpf_get_attach_cookie` to tell the locations apart. Like uprobes, they
+/// can be of two kinds:
+///
+/// - `uprobe.multi`: get attached to the *start* of the target functions
+/// - `uretprobe.multi`: get attached to the *return address* of the target functions
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.6.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::{uprobe_multi::UProbeMultiTarget, UProbeMulti};
+///
+/// let program: &mut UProbeMulti = bpf.program_mut("trace_malloc").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach(
+///     "libc",
+///     &[
+///         UProbeMultiTarget::symbol("malloc").cookie(1),
+///         UProbeMultiTarget::symbol("calloc").cookie(2),
+///         UProbeMultiTarget::symbol("realloc").cookie(3),
+///     ],
+///     None,
+/// )?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_TRACE_UPROBE_MULTI")]
+#[doc(alias = "BPF_PROG_TYPE_KPROBE")]
+pub struct UProbeMulti {
+    pub(crate) data: ProgramData<UProbeMultiLink>,
+    pub(crate) kind: ProbeKind,
+}
+
+/// A location a [`UProbeMulti`] is attached to.
+#[derive(Debug, Clone, Default)]
+pub struct UProbeMultiTarget {
+    symbol: Option<String>,
+    offset: u64,
+    cookie: u64,
+}
+
+impl UProbeMultiTarget {
+    /// The start of the function `symbol`.
+    pub fn symbol(symbol: &str) -> UProbeMultiTarget {
+        UProbeMultiTarget {
+            symbol: Some(symbol.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// The given offset in the target file.
+    pub fn offset(offset: u64) -> UProbeMultiTarget {
+        UProbeMultiTarget {
+            offset,
+            ..Default::default()
+        }
+    }
+
+    /// Adds `offset` to the address of the symbol.
+    pub fn with_offset(mut self, offset: u64) -> UProbeMultiTarget {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the cookie the program reads with `bpf_get_attach_cookie` when
+    /// it runs for this location.
+    pub fn cookie(mut self, cookie: u64) -> UProbeMultiTarget {
+        self.cookie = cookie;
+        self
+    }
+}
+
+impl UProbeMulti {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_TRACE_UPROBE_MULTI);
+        load_program(BPF_PROG_TYPE_KPROBE, &mut self.data)
+    }
+
+    /// Returns `UProbe` if the program is a `uprobe.multi`, or `URetProbe`
+    /// if the program is a `uretprobe.multi`.
+    pub fn kind(&self) -> ProbeKind {
+        self.kind
+    }
+
+    /// Attaches the program to the given locations of `target`.
+    ///
+    /// `target` and `pid` are resolved like for
+    /// [`UProbe::attach`](crate::programs::UProbe::attach): `target` is an
+    /// absolute path to a binary or library, or a library name, and if `pid`
+    /// is not `None` the program executes only when the locations are
+    /// executed by the given `pid`.
+    ///
+    /// The returned value can be used to detach, see [UProbeMulti::detach].
+    pub fn attach<T: AsRef<Path>>(
+        &mut self,
+        target: T,
+        targets: &[UProbeMultiTarget],
+        pid: Option<pid_t>,
+    ) -> Result<UProbeMultiLinkId, ProgramError> {
+        self.attach_with_resolver(target, targets, pid, &ElfSymbolResolver)
+    }
+
+    /// Attaches the program, resolving the symbols with the given resolver.
+    ///
+    /// See [`UProbe::attach_with_resolver`](crate::programs::UProbe::attach_with_resolver).
+    pub fn attach_with_resolver<T: AsRef<Path>>(
+        &mut self,
+        target: T,
+        targets: &[UProbeMultiTarget],
+        pid: Option<pid_t>,
+        resolver: &dyn SymbolResolver,
+    ) -> Result<UProbeMultiLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let path = resolve_target(target.as_ref(), pid)?;
+
+        let mut offsets = Vec::with_capacity(targets.len());
+        for t in targets {
+            let sym_offset = match &t.symbol {
+                Some(symbol) => resolver
+                    .resolve(Path::new(&path), symbol)
+                    .map_err(|error| UProbeError::SymbolError {
+                        symbol: symbol.clone(),
+                        error,
+                    })?,
+                None => 0,
+            };
+            offsets.push(sym_offset + t.offset);
+        }
+        let cookies = targets.iter().map(|t| t.cookie).collect::<Vec<_>>();
+        let cookies = cookies.iter().any(|c| *c != 0).then_some(cookies);
+
+        let c_path = CString::new(path.as_str()).map_err(|_| UProbeError::InvalidTarget {
+            path: path.clone().into(),
+        })?;
+        let flags = match self.kind {
+            ProbeKind::URetProbe => BPF_F_UPROBE_MULTI_RETURN,
+            _ => 0,
+        };
+        let link_fd = bpf_link_create_uprobe_multi(
+            prog_fd,
+            &c_path,
+            &offsets,
+            cookies.as_deref(),
+            pid,
+            flags,
+        )
+        .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+            as RawFd;
+        self.data
+            .links
+            .insert(UProbeMultiLink(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [UProbeMulti::attach].
+    pub fn detach(&mut self, link_id: UProbeMultiLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: UProbeMultiLinkId,
+    ) -> Result<UProbeMultiLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [UProbeMulti] programs.
+    UProbeMultiLink,
+    /// The type returned by [UProbeMulti::attach]. Can be passed to [UProbeMulti::detach].
+    UProbeMultiLinkId,
+    FdLink,
+    FdLinkId
+);
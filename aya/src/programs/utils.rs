@@ -1,8 +1,12 @@
 //! Common functions shared between multiple eBPF program types.
-use std::{ffi::CStr, os::unix::io::RawFd};
+use std::{ffi::CStr, fs::File, os::unix::io::RawFd, path::Path};
 
 use crate::{
-    programs::{FdLink, Link, ProgramData, ProgramError},
+    generated::{
+        bpf_insn, BPF_ALU64, BPF_B, BPF_CALL, BPF_DW, BPF_H, BPF_JMP, BPF_K, BPF_LD, BPF_LDX,
+        BPF_PSEUDO_CALL, BPF_PSEUDO_FUNC,
+    },
+    programs::{attach_error, FdLink, Link, ProgramData, ProgramError},
     sys::bpf_raw_tracepoint_open,
 };
 
@@ -13,12 +17,328 @@ pub(crate) fn attach_raw_tracepoint<T: Link + From<FdLink>>(
 ) -> Result<T::Id, ProgramError> {
     let prog_fd = program_data.fd_or_err()?;
 
-    let pfd = bpf_raw_tracepoint_open(tp_name, prog_fd).map_err(|(_code, io_error)| {
-        ProgramError::SyscallError {
-            call: "bpf_raw_tracepoint_open".to_owned(),
-            io_error,
-        }
-    })? as RawFd;
+    let pfd = bpf_raw_tracepoint_open(tp_name, prog_fd)
+        .map_err(|(_, io_error)| attach_error("bpf_raw_tracepoint_open", io_error))?
+        as RawFd;
 
     program_data.links.insert(FdLink::new(pfd).into())
 }
+
+/// Opens the cgroup v2 directory at `path`, to attach programs to it.
+pub(crate) fn open_cgroup(path: &Path) -> Result<File, ProgramError> {
+    File::open(path).map_err(|io_error| ProgramError::InvalidCgroup {
+        call: "open".to_owned(),
+        io_error,
+    })
+}
+
+// Licenses the kernel considers GPL compatible, see include/linux/license.h
+const GPL_COMPATIBLE_LICENSES: &[&str] = &[
+    "GPL",
+    "GPL v2",
+    "GPL and additional rights",
+    "Dual BSD/GPL",
+    "Dual MIT/GPL",
+    "Dual MPL/GPL",
+];
+
+// Helpers whose kernel proto has `gpl_only = true`
+const GPL_ONLY_HELPERS: &[(i32, &str)] = &[
+    (4, "bpf_probe_read"),
+    (6, "bpf_trace_printk"),
+    (22, "bpf_perf_event_read"),
+    (25, "bpf_perf_event_output"),
+    (27, "bpf_get_stackid"),
+    (35, "bpf_get_current_task"),
+    (36, "bpf_probe_write_user"),
+    (45, "bpf_probe_read_str"),
+    (55, "bpf_perf_event_read_value"),
+    (56, "bpf_perf_prog_read_value"),
+    (58, "bpf_override_return"),
+    (67, "bpf_get_stack"),
+    (111, "bpf_skb_output"),
+    (112, "bpf_probe_read_user"),
+    (113, "bpf_probe_read_kernel"),
+    (114, "bpf_probe_read_user_str"),
+    (115, "bpf_probe_read_kernel_str"),
+    (119, "bpf_read_branch_records"),
+    (121, "bpf_xdp_output"),
+    (126, "bpf_seq_printf"),
+    (127, "bpf_seq_write"),
+    (150, "bpf_seq_printf_btf"),
+    (158, "bpf_get_current_task_btf"),
+    (165, "bpf_snprintf"),
+    (169, "bpf_timer_init"),
+    (170, "bpf_timer_set_callback"),
+    (171, "bpf_timer_start"),
+    (172, "bpf_timer_cancel"),
+    (175, "bpf_task_pt_regs"),
+    (176, "bpf_get_branch_snapshot"),
+    (177, "bpf_trace_vprintk"),
+    (191, "bpf_copy_from_user_task"),
+];
+
+/// Returns `true` if the kernel treats `license` as GPL compatible.
+pub(crate) fn is_gpl_compatible(license: &CStr) -> bool {
+    license
+        .to_str()
+        .map(|l| GPL_COMPATIBLE_LICENSES.contains(&l))
+        .unwrap_or(false)
+}
+
+/// Returns the name of the first GPL-only helper called by `instructions`.
+pub(crate) fn find_gpl_only_helper(instructions: &[bpf_insn]) -> Option<&'static str> {
+    instructions
+        .iter()
+        .filter(|ins| is_helper_call(ins))
+        .find_map(|ins| {
+            GPL_ONLY_HELPERS
+                .iter()
+                .find(|(id, _)| *id == ins.imm)
+                .map(|(_, name)| *name)
+        })
+}
+
+// (bpf_probe_read_{kernel,user}[_str], bpf_probe_read[_str])
+const PROBE_READ_REWRITES: &[(i32, i32)] = &[(112, 4), (113, 4), (114, 45), (115, 45)];
+
+/// Rewrites calls to the `bpf_probe_read_{kernel,user}[_str]` helpers, which
+/// were added in 5.5, into calls to `bpf_probe_read[_str]`.
+///
+/// Returns `None` if `instructions` don't call any of them.
+pub(crate) fn rewrite_probe_read(instructions: &[bpf_insn]) -> Option<Vec<bpf_insn>> {
+    let mut rewritten = instructions.to_vec();
+    let mut changed = false;
+    for ins in rewritten.iter_mut().filter(|ins| is_helper_call(ins)) {
+        if let Some((_, imm)) = PROBE_READ_REWRITES.iter().find(|(id, _)| *id == ins.imm) {
+            ins.imm = *imm;
+            changed = true;
+        }
+    }
+    changed.then_some(rewritten)
+}
+
+// Opcodes missing from the generated bindings, see include/uapi/linux/bpf.h
+const BPF_JMP32: u32 = 0x06;
+const BPF_IMM: u32 = 0x00;
+const BPF_MEM: u32 = 0x60;
+const BPF_MEMSX: u32 = 0x80;
+const BPF_LSH: u32 = 0x60;
+const BPF_ARSH: u32 = 0xc0;
+const BPF_JA: u32 = 0x00;
+const BPF_EXIT: u32 = 0x90;
+
+/// Rewrites the sign-extending loads of cpu v4 (`BPF_LDX | BPF_MEMSX`), which
+/// were added in 6.6 and run as `BPF_PROBE_MEMSX` when they read kernel
+/// memory, into `BPF_MEM` loads followed by a sign extension.
+///
+/// Two instructions are inserted after every rewritten load, so the offsets
+/// of jumps and calls are adjusted, and the new index of every instruction is
+/// returned with the rewritten program to adjust func and line info.
+///
+/// Returns `None` if `instructions` don't contain any sign-extending load, or
+/// if a jump can't reach its target anymore.
+pub(crate) fn rewrite_probe_mem(instructions: &[bpf_insn]) -> Option<(Vec<bpf_insn>, Vec<u32>)> {
+    let is_memsx = |ins: &bpf_insn| ins.code as u32 & 0xe7 == BPF_LDX | BPF_MEMSX;
+    if !instructions.iter().any(is_memsx) {
+        return None;
+    }
+
+    let mut offsets = Vec::with_capacity(instructions.len() + 1);
+    let mut len = 0;
+    for ins in instructions {
+        offsets.push(len);
+        len += if is_memsx(ins) { 3 } else { 1 };
+    }
+    offsets.push(len);
+    // the new offset of a jump by `delta` from the instruction at `index`
+    let relocate = |index: usize, delta: i32| -> Option<i32> {
+        let target = usize::try_from(index as i64 + 1 + delta as i64).ok()?;
+        Some(*offsets.get(target)? as i32 - offsets[index] as i32 - 1)
+    };
+
+    let mut rewritten = Vec::with_capacity(len as usize);
+    for (index, ins) in instructions.iter().enumerate() {
+        let mut ins = *ins;
+        let code = ins.code as u32;
+        let (class, op, size) = (code & 0x07, code & 0xf0, code & 0x18);
+        if is_memsx(&ins) {
+            let bits = match size {
+                BPF_B => 8,
+                BPF_H => 16,
+                _ => 32,
+            };
+            ins.code = (BPF_LDX | BPF_MEM | size) as u8;
+            rewritten.push(ins);
+            for op in [BPF_LSH, BPF_ARSH] {
+                rewritten.push(bpf_insn {
+                    code: (BPF_ALU64 | op | BPF_K) as u8,
+                    _bitfield_align_1: [],
+                    _bitfield_1: bpf_insn::new_bitfield_1(ins.dst_reg(), 0),
+                    off: 0,
+                    imm: 64 - bits,
+                });
+            }
+            continue;
+        }
+        if class == BPF_JMP || class == BPF_JMP32 {
+            match op {
+                BPF_CALL if class == BPF_JMP && ins.src_reg() as u32 == BPF_PSEUDO_CALL => {
+                    ins.imm = relocate(index, ins.imm)?;
+                }
+                BPF_CALL | BPF_EXIT => {}
+                // gotol, the long jump of cpu v4, uses the immediate
+                BPF_JA if class == BPF_JMP32 => ins.imm = relocate(index, ins.imm)?,
+                _ => ins.off = i16::try_from(relocate(index, ins.off as i32)?).ok()?,
+            }
+        } else if code == BPF_LD | BPF_IMM | BPF_DW && ins.src_reg() as u32 == BPF_PSEUDO_FUNC {
+            ins.imm = relocate(index, ins.imm)?;
+        }
+        rewritten.push(ins);
+    }
+    Some((rewritten, offsets))
+}
+
+// Matches `name` against a glob pattern, where `*` matches any sequence of
+// characters and `?` any single character.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    let (mut p, mut n) = (0, 0);
+    // the position after the last `*`, and the position in `name` it matched up to
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(c) if *c == b'?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bn)) => {
+                    p = bp;
+                    n = bn + 1;
+                    backtrack = Some((bp, bn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+fn is_helper_call(ins: &bpf_insn) -> bool {
+    ins.code as u32 == BPF_JMP | BPF_CALL | BPF_K && ins.src_reg() == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    fn call(imm: i32, src_reg: u8) -> bpf_insn {
+        bpf_insn {
+            code: (BPF_JMP | BPF_CALL) as u8,
+            _bitfield_align_1: [],
+            _bitfield_1: bpf_insn::new_bitfield_1(0, src_reg),
+            off: 0,
+            imm,
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("tcp_*", "tcp_sendmsg"));
+        assert!(glob_match("*_sendmsg", "tcp_sendmsg"));
+        assert!(glob_match("*send*", "udp_sendmsg"));
+        assert!(glob_match("vfs_rea?", "vfs_read"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("vfs_read", "vfs_read"));
+        assert!(!glob_match("vfs_read", "vfs_readv"));
+        assert!(!glob_match("tcp_*", "udp_sendmsg"));
+        assert!(!glob_match("vfs_rea?", "vfs_rea"));
+        assert!(!glob_match("*_recvmsg", "tcp_sendmsg"));
+    }
+
+    #[test]
+    fn test_is_gpl_compatible() {
+        for license in ["GPL", "Dual MIT/GPL", "GPL and additional rights"] {
+            assert!(is_gpl_compatible(&CString::new(license).unwrap()));
+        }
+        for license in ["MIT", "Proprietary", "gpl", ""] {
+            assert!(!is_gpl_compatible(&CString::new(license).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_find_gpl_only_helper() {
+        // bpf_map_lookup_elem
+        assert_eq!(find_gpl_only_helper(&[call(1, 0)]), None);
+        // a bpf-to-bpf call with the same immediate as bpf_trace_printk
+        assert_eq!(find_gpl_only_helper(&[call(6, 1)]), None);
+        assert_eq!(
+            find_gpl_only_helper(&[call(1, 0), call(6, 0)]),
+            Some("bpf_trace_printk")
+        );
+        assert_eq!(
+            find_gpl_only_helper(&[call(171, 0)]),
+            Some("bpf_timer_start")
+        );
+    }
+
+    fn insn(code: u32, dst_reg: u8, src_reg: u8, off: i16, imm: i32) -> bpf_insn {
+        bpf_insn {
+            code: code as u8,
+            _bitfield_align_1: [],
+            _bitfield_1: bpf_insn::new_bitfield_1(dst_reg, src_reg),
+            off,
+            imm,
+        }
+    }
+
+    #[test]
+    fn test_rewrite_probe_mem() {
+        let exit = insn(BPF_JMP | BPF_EXIT, 0, 0, 0, 0);
+        assert!(rewrite_probe_mem(&[exit]).is_none());
+
+        let insns = [
+            // if r1 == 0 goto exit
+            insn(BPF_JMP | 0x10 | BPF_K, 1, 0, 2, 0),
+            // r0 = *(s16 *)(r1 + 4)
+            insn(BPF_LDX | BPF_MEMSX | BPF_H, 0, 1, 4, 0),
+            // call the subprogram at exit
+            insn(BPF_JMP | BPF_CALL, 0, BPF_PSEUDO_CALL as u8, 0, 0),
+            exit,
+        ];
+        let (rewritten, offsets) = rewrite_probe_mem(&insns).unwrap();
+        assert_eq!(offsets, vec![0, 1, 4, 5, 6]);
+        assert_eq!(
+            rewritten
+                .iter()
+                .map(|ins| (ins.code as u32, ins.dst_reg(), ins.off, ins.imm))
+                .collect::<Vec<_>>(),
+            vec![
+                (BPF_JMP | 0x10 | BPF_K, 1, 4, 0),
+                (BPF_LDX | BPF_MEM | BPF_H, 0, 4, 0),
+                (BPF_ALU64 | BPF_LSH | BPF_K, 0, 0, 48),
+                (BPF_ALU64 | BPF_ARSH | BPF_K, 0, 0, 48),
+                (BPF_JMP | BPF_CALL, 0, 0, 0),
+                (BPF_JMP | BPF_EXIT, 0, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_probe_read() {
+        assert!(rewrite_probe_read(&[call(1, 0), call(113, 1)]).is_none());
+
+        let insns = rewrite_probe_read(&[call(113, 0), call(1, 0), call(115, 0)]).unwrap();
+        assert_eq!(
+            insns.iter().map(|ins| ins.imm).collect::<Vec<_>>(),
+            vec![4, 1, 45]
+        );
+    }
+}
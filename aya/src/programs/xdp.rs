@@ -1,7 +1,15 @@
 //! eXpress Data Path (XDP) programs.
 use bitflags;
-use libc::if_nametoindex;
-use std::{convert::TryFrom, ffi::CString, hash::Hash, io, mem, os::unix::io::RawFd};
+use libc::{if_nametoindex, sysconf, _SC_PAGESIZE, EOPNOTSUPP};
+use std::{
+    convert::TryFrom,
+    ffi::CString,
+    hash::Hash,
+    io, mem,
+    net::{IpAddr, SocketAddr},
+    os::unix::io::RawFd,
+    time::Duration,
+};
 use thiserror::Error;
 
 use crate::{
@@ -9,30 +17,81 @@ use crate::{
         bpf_attach_type::{self, BPF_XDP},
         bpf_link_type,
         bpf_prog_type::BPF_PROG_TYPE_XDP,
-        XDP_FLAGS_DRV_MODE, XDP_FLAGS_HW_MODE, XDP_FLAGS_REPLACE, XDP_FLAGS_SKB_MODE,
-        XDP_FLAGS_UPDATE_IF_NOEXIST,
+        BPF_F_TEST_XDP_LIVE_FRAMES, XDP_FLAGS_DRV_MODE, XDP_FLAGS_HW_MODE, XDP_FLAGS_REPLACE,
+        XDP_FLAGS_SKB_MODE, XDP_FLAGS_UPDATE_IF_NOEXIST,
     },
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, LinkError, ProgramData, ProgramError,
+        attach_error, define_link_wrapper,
+        guard::{self, Rollback},
+        load_program, FdLink, Link, LinkError, ProgramData, ProgramError,
     },
     sys::{
-        bpf_link_create, bpf_link_get_info_by_fd, bpf_link_update, kernel_version,
-        netlink_set_xdp_fd,
+        bpf_link_create, bpf_link_get_info_by_fd, bpf_link_update, bpf_prog_test_run,
+        ext_ack_suffix, kernel_version, netdev_channels, netdev_mtu, netlink_set_xdp_fd,
+        netlink_xdp_features, BpfProgTestRunAttrs, NetlinkError,
     },
+    util::ifindex_from_ifname,
 };
 
 /// The type returned when attaching an [`Xdp`] program fails on kernels `< 5.9`.
 #[derive(Debug, Error)]
 pub enum XdpError {
     /// netlink error while attaching XDP program
-    #[error("netlink error while attaching XDP program")]
+    #[error("netlink error while attaching XDP program{}", ext_ack_suffix(.message))]
     NetlinkError {
         /// the [`io::Error`] from the netlink call
         #[source]
         io_error: io::Error,
+        /// the extended ack message the kernel reported with the error, if any
+        message: Option<String>,
+    },
+}
+
+/// The error type returned when building test frames, see [`udp_frame`].
+#[derive(Debug, Error)]
+pub enum FrameError {
+    /// The source and destination addresses aren't of the same address family.
+    #[error("source and destination addresses must be of the same address family")]
+    AddressFamilyMismatch,
+    /// The payload doesn't fit in a single packet.
+    #[error("payload of {len} bytes doesn't fit in a packet")]
+    PayloadTooLarge {
+        /// the payload length
+        len: usize,
     },
 }
 
+/// A device condition found by [`Xdp::preflight`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XdpPreflightIssue {
+    /// The interface MTU is too large for an XDP program without multi-buffer
+    /// support. Attaching in driver or hardware mode is rejected by most
+    /// drivers.
+    MtuTooLarge {
+        /// the interface MTU
+        mtu: u32,
+        /// the largest MTU a single page packet buffer can hold
+        max_mtu: u32,
+    },
+    /// The interface has more RX queues than there are entries in the map the
+    /// program uses to redirect packets per queue or per CPU. Packets received
+    /// on the extra queues can't be redirected.
+    QueueCountExceedsMap {
+        /// the number of RX queues of the interface
+        queues: u32,
+        /// the max entries of the map
+        max_entries: u32,
+    },
+}
+
+impl XdpPreflightIssue {
+    /// Returns `true` if the issue is expected to make attaching fail, and
+    /// `false` if it's only a warning.
+    pub fn is_error(&self) -> bool {
+        matches!(self, XdpPreflightIssue::MtuTooLarge { .. })
+    }
+}
+
 bitflags! {
     /// Flags passed to [`Xdp::attach()`].
     #[derive(Default)]
@@ -50,6 +109,71 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// The XDP features supported by a network device, see [`xdp_features`].
+    #[derive(Default)]
+    pub struct XdpFeatures: u64 {
+        /// The driver supports running XDP programs in driver mode, with the
+        /// `XDP_ABORTED`, `XDP_DROP`, `XDP_PASS` and `XDP_TX` actions.
+        const BASIC = 1 << 0;
+        /// The driver supports `XDP_REDIRECT` in driver mode.
+        const REDIRECT = 1 << 1;
+        /// The device can be the target of an `XDP_REDIRECT`.
+        const NDO_XMIT = 1 << 2;
+        /// The driver supports zero-copy `AF_XDP` sockets.
+        const XSK_ZEROCOPY = 1 << 3;
+        /// The device supports offloading XDP programs to hardware.
+        const HW_OFFLOAD = 1 << 4;
+        /// The driver supports multi-buffer packets on receive.
+        const RX_SG = 1 << 5;
+        /// The device can be the target of an `XDP_REDIRECT` of multi-buffer
+        /// packets.
+        const NDO_XMIT_SG = 1 << 6;
+    }
+}
+
+impl XdpFeatures {
+    /// Returns the attach mode to use with a device supporting these
+    /// features: [`XdpFlags::DRV_MODE`] if the driver supports running XDP
+    /// programs natively, and [`XdpFlags::SKB_MODE`] otherwise.
+    pub fn preferred_mode(&self) -> XdpFlags {
+        if self.contains(XdpFeatures::BASIC) {
+            XdpFlags::DRV_MODE
+        } else {
+            XdpFlags::SKB_MODE
+        }
+    }
+}
+
+/// Returns the XDP features supported by the given interface.
+///
+/// This can be used to pick the attach mode, or to check whether zero-copy
+/// `AF_XDP` sockets can be used, before attaching.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.3. On older
+/// kernels an error of kind [`io::ErrorKind::NotFound`] is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{Bpf, programs::{xdp::xdp_features, Xdp, XdpFlags}};
+///
+/// let mode = xdp_features("eth0")
+///     .map(|features| features.preferred_mode())
+///     .unwrap_or(XdpFlags::SKB_MODE);
+/// let program: &mut Xdp = bpf.program_mut("intercept_packets").unwrap().try_into()?;
+/// program.attach("eth0", mode)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+pub fn xdp_features(interface: &str) -> Result<XdpFeatures, io::Error> {
+    let if_index = ifindex_from_ifname(interface)?;
+    let features = unsafe { netlink_xdp_features(if_index as i32) }?;
+    Ok(XdpFeatures::from_bits_truncate(features))
+}
+
 /// An XDP program.
 ///
 /// eXpress Data Path (XDP) programs can be attached to the very early stages of network
@@ -98,6 +222,7 @@ impl Xdp {
     /// [`XdpError::NetlinkError`] is returned for older
     /// kernels.
     pub fn attach(&mut self, interface: &str, flags: XdpFlags) -> Result<XdpLinkId, ProgramError> {
+        trace_span!("attach", name = ?self.data.name, interface, ?flags);
         let prog_fd = self.data.fd_or_err()?;
         let c_interface = CString::new(interface).unwrap();
         let if_index = unsafe { if_nametoindex(c_interface.as_ptr()) } as RawFd;
@@ -109,18 +234,16 @@ impl Xdp {
 
         let k_ver = kernel_version().unwrap();
         if k_ver >= (5, 9, 0) {
-            let link_fd = bpf_link_create(prog_fd, if_index, BPF_XDP, None, flags.bits).map_err(
-                |(_, io_error)| ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                },
-            )? as RawFd;
+            let link_fd = bpf_link_create(prog_fd, if_index, BPF_XDP, None, flags.bits)
+                .map_err(|(_, io_error)| attach_error("bpf_link_create", io_error))?
+                as RawFd;
             self.data
                 .links
                 .insert(XdpLink(XdpLinkInner::FdLink(FdLink::new(link_fd))))
         } else {
-            unsafe { netlink_set_xdp_fd(if_index, prog_fd, None, flags.bits) }
-                .map_err(|io_error| XdpError::NetlinkError { io_error })?;
+            unsafe { netlink_set_xdp_fd(if_index, prog_fd, None, flags.bits) }.map_err(
+                |NetlinkError { io_error, message }| XdpError::NetlinkError { io_error, message },
+            )?;
 
             self.data.links.insert(XdpLink(XdpLinkInner::NlLink(NlLink {
                 if_index,
@@ -130,6 +253,153 @@ impl Xdp {
         }
     }
 
+    /// Checks whether `interface` is set up in a way that's known to make
+    /// attaching with `flags` fail or misbehave, without attaching the program.
+    ///
+    /// The MTU is checked when attaching in driver or hardware mode, or when
+    /// no mode is given and the kernel may pick driver mode. If
+    /// `queue_map_entries` is set to the max entries of a map indexed by RX
+    /// queue or CPU, for example an `XSKMAP` or a `CPUMAP`, it's also checked
+    /// against the queue count of the interface. The queue count is skipped if
+    /// the driver doesn't report it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = Bpf::load_file("ebpf_programs.o")?;
+    /// use aya::{Bpf, programs::{Xdp, XdpFlags}};
+    ///
+    /// let program: &mut Xdp = bpf.program_mut("intercept_packets").unwrap().try_into()?;
+    /// let issues = program.preflight("eth0", XdpFlags::DRV_MODE, Some(64))?;
+    /// if issues.iter().any(|issue| issue.is_error()) {
+    ///     program.attach("eth0", XdpFlags::SKB_MODE)?;
+    /// } else {
+    ///     program.attach("eth0", XdpFlags::DRV_MODE)?;
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn preflight(
+        &self,
+        interface: &str,
+        flags: XdpFlags,
+        queue_map_entries: Option<u32>,
+    ) -> Result<Vec<XdpPreflightIssue>, ProgramError> {
+        let c_interface = CString::new(interface).unwrap();
+        if unsafe { if_nametoindex(c_interface.as_ptr()) } == 0 {
+            return Err(ProgramError::UnknownInterface {
+                name: interface.to_string(),
+            });
+        }
+
+        let mtu = netdev_mtu(interface).map_err(|io_error| ProgramError::SyscallError {
+            call: "ioctl(SIOCGIFMTU)".to_owned(),
+            io_error,
+        })?;
+        let queues = match queue_map_entries {
+            Some(_) => match netdev_channels(interface) {
+                Ok(channels) => Some(channels.rx_count + channels.combined_count),
+                Err(e) if e.raw_os_error() == Some(EOPNOTSUPP) => None,
+                Err(io_error) => {
+                    return Err(ProgramError::SyscallError {
+                        call: "ioctl(ETHTOOL_GCHANNELS)".to_owned(),
+                        io_error,
+                    })
+                }
+            },
+            None => None,
+        };
+        let page_size = unsafe { sysconf(_SC_PAGESIZE) } as u32;
+
+        Ok(preflight_issues(
+            flags,
+            mtu,
+            page_size,
+            queues,
+            queue_map_entries,
+        ))
+    }
+
+    /// Runs the program on `repeat` copies of `frame` in live frames mode.
+    ///
+    /// In live frames mode the frames are handled like received packets:
+    /// frames for which the program returns `XDP_TX` or `XDP_REDIRECT` are
+    /// transmitted, and frames for which it returns `XDP_PASS` go up the
+    /// network stack. This makes it possible to benchmark a program or to
+    /// generate traffic with millions of frames without a traffic generator.
+    /// Test frames can be crafted with [`udp_frame`], [`tcp_frame`] or
+    /// [`ip_frame`].
+    ///
+    /// The frames are received on the first RX queue of `interface`, which is
+    /// also the interface `XDP_TX` transmits them on. If `interface` is `None`
+    /// the frames are received on the loopback interface.
+    ///
+    /// `batch_size` is the number of frames allocated and processed at a time,
+    /// 64 if `None`. Returns the average time the program took to process
+    /// each frame.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.18.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::{xdp::udp_frame, Xdp};
+    ///
+    /// let program: &mut Xdp = bpf.program_mut("redirect").unwrap().try_into()?;
+    /// program.load()?;
+    /// let frame = udp_frame(
+    ///     [0x02, 0, 0, 0, 0, 1],
+    ///     [0x02, 0, 0, 0, 0, 2],
+    ///     "10.0.0.1:1234".parse().unwrap(),
+    ///     "10.0.0.2:5678".parse().unwrap(),
+    ///     &[0; 18],
+    /// )?;
+    /// let duration = program.run_live_frames(&frame, Some("eth0"), 10_000_000, None)?;
+    /// println!("{:?} per frame", duration);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn run_live_frames(
+        &self,
+        frame: &[u8],
+        interface: Option<&str>,
+        repeat: u32,
+        batch_size: Option<u32>,
+    ) -> Result<Duration, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let if_index = match interface {
+            Some(interface) => {
+                ifindex_from_ifname(interface).map_err(|_| ProgramError::UnknownInterface {
+                    name: interface.to_string(),
+                })?
+            }
+            None => 0,
+        };
+        // struct xdp_md, which isn't in the generated bindings: data, data_end,
+        // data_meta, ingress_ifindex, rx_queue_index and egress_ifindex
+        let xdp_md = [0, frame.len() as u32, 0, if_index, 0, 0];
+        let mut ctx_in = xdp_md
+            .iter()
+            .flat_map(|field| field.to_ne_bytes())
+            .collect::<Vec<_>>();
+        let attr = BpfProgTestRunAttrs {
+            data_in: Some(frame),
+            ctx_in: interface.map(|_| &mut ctx_in[..]),
+            repeat,
+            flags: BPF_F_TEST_XDP_LIVE_FRAMES,
+            batch_size: batch_size.unwrap_or(0),
+            ..Default::default()
+        };
+        let out = bpf_prog_test_run(prog_fd, attr).map_err(|(_, io_error)| {
+            ProgramError::SyscallError {
+                call: "bpf_prog_test_run".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(Duration::from_nanos(out.duration as u64))
+    }
+
     /// Detaches the program.
     ///
     /// See [Xdp::attach].
@@ -172,7 +442,12 @@ impl Xdp {
                 let replace_flags = flags | XdpFlags::REPLACE;
                 unsafe {
                     netlink_set_xdp_fd(if_index, prog_fd, Some(old_prog_fd), replace_flags.bits())
-                        .map_err(|io_error| XdpError::NetlinkError { io_error })?;
+                        .map_err(
+                            |NetlinkError { io_error, message }| XdpError::NetlinkError {
+                                io_error,
+                                message,
+                            },
+                        )?;
                 }
                 // dispose of link and avoid detach on drop
                 mem::forget(link);
@@ -186,6 +461,41 @@ impl Xdp {
     }
 }
 
+// headroom reserved in front of each packet, XDP_PACKET_HEADROOM
+const XDP_HEADROOM: u32 = 256;
+// SKB_DATA_ALIGN(sizeof(struct skb_shared_info)) on 64-bit
+const SKB_SHARED_INFO_SIZE: u32 = 320;
+// ethernet header plus two VLAN tags
+const ETH_OVERHEAD: u32 = 14 + 2 * 4;
+
+fn preflight_issues(
+    flags: XdpFlags,
+    mtu: u32,
+    page_size: u32,
+    queues: Option<u32>,
+    queue_map_entries: Option<u32>,
+) -> Vec<XdpPreflightIssue> {
+    let mut issues = Vec::new();
+
+    if !flags.contains(XdpFlags::SKB_MODE) {
+        let max_mtu = page_size.saturating_sub(XDP_HEADROOM + SKB_SHARED_INFO_SIZE + ETH_OVERHEAD);
+        if mtu > max_mtu {
+            issues.push(XdpPreflightIssue::MtuTooLarge { mtu, max_mtu });
+        }
+    }
+
+    if let (Some(queues), Some(max_entries)) = (queues, queue_map_entries) {
+        if queues > max_entries {
+            issues.push(XdpPreflightIssue::QueueCountExceedsMap {
+                queues,
+                max_entries,
+            });
+        }
+    }
+
+    issues
+}
+
 #[derive(Debug)]
 pub(crate) struct NlLink {
     if_index: i32,
@@ -242,6 +552,23 @@ impl Link for XdpLinkInner {
     }
 }
 
+impl guard::sealed::Sealed for XdpLink {
+    fn rollback(&self) -> Result<Rollback, ProgramError> {
+        match &self.0 {
+            XdpLinkInner::FdLink(fd_link) => fd_link.rollback(),
+            XdpLinkInner::NlLink(nl_link) => {
+                let k_ver = kernel_version().unwrap();
+                let flags = if k_ver >= (5, 7, 0) {
+                    nl_link.flags.bits | XDP_FLAGS_REPLACE
+                } else {
+                    nl_link.flags.bits
+                };
+                Rollback::xdp(nl_link.if_index, nl_link.prog_fd, flags)
+            }
+        }
+    }
+}
+
 impl TryFrom<XdpLink> for FdLink {
     type Error = LinkError;
 
@@ -281,3 +608,358 @@ define_link_wrapper!(
     XdpLinkInner,
     XdpLinkIdInner
 );
+
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+bitflags! {
+    /// The flags of the TCP segments built by [`tcp_frame`].
+    pub struct TcpFlags: u8 {
+        /// No more data from the sender.
+        const FIN = 0x01;
+        /// Synchronizes the sequence numbers.
+        const SYN = 0x02;
+        /// Resets the connection.
+        const RST = 0x04;
+        /// Pushes the data to the application.
+        const PSH = 0x08;
+        /// The acknowledgment number is valid.
+        const ACK = 0x10;
+        /// The urgent pointer is valid.
+        const URG = 0x20;
+    }
+}
+
+/// Builds an Ethernet frame containing a UDP datagram.
+///
+/// The frame is an IPv4 or IPv6 packet depending on the addresses, with
+/// valid IP and UDP checksums. It can be used to test XDP programs, for
+/// example with [`Xdp::run_live_frames`].
+///
+/// # Errors
+///
+/// Returns an error if `src` and `dst` aren't of the same address family, or
+/// if `payload` doesn't fit in a single datagram.
+pub fn udp_frame(
+    src_mac: [u8; 6],
+    dst_mac: [u8; 6],
+    src: SocketAddr,
+    dst: SocketAddr,
+    payload: &[u8],
+) -> Result<Vec<u8>, FrameError> {
+    let udp_len = u16::try_from(8 + payload.len())
+        .map_err(|_| FrameError::PayloadTooLarge { len: payload.len() })?;
+    let mut datagram = Vec::with_capacity(udp_len as usize);
+    datagram.extend_from_slice(&src.port().to_be_bytes());
+    datagram.extend_from_slice(&dst.port().to_be_bytes());
+    datagram.extend_from_slice(&udp_len.to_be_bytes());
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(payload);
+
+    let udp_checksum = match transport_checksum(src.ip(), dst.ip(), IPPROTO_UDP, &datagram)? {
+        // a zero checksum means no checksum
+        0 => 0xffff,
+        c => c,
+    };
+    datagram[6..8].copy_from_slice(&udp_checksum.to_be_bytes());
+
+    ip_frame(src_mac, dst_mac, src.ip(), dst.ip(), IPPROTO_UDP, &datagram)
+}
+
+/// Builds an Ethernet frame containing a TCP segment.
+///
+/// The segment has the given flags, sequence and acknowledgment numbers
+/// of 0, a window of 65535 and no options. Like [`udp_frame`], the frame is
+/// an IPv4 or IPv6 packet depending on the addresses, with valid IP and TCP
+/// checksums.
+///
+/// # Errors
+///
+/// Returns an error if `src` and `dst` aren't of the same address family, or
+/// if `payload` doesn't fit in a single packet.
+pub fn tcp_frame(
+    src_mac: [u8; 6],
+    dst_mac: [u8; 6],
+    src: SocketAddr,
+    dst: SocketAddr,
+    flags: TcpFlags,
+    payload: &[u8],
+) -> Result<Vec<u8>, FrameError> {
+    let mut segment = Vec::with_capacity(20 + payload.len());
+    segment.extend_from_slice(&src.port().to_be_bytes());
+    segment.extend_from_slice(&dst.port().to_be_bytes());
+    // sequence and acknowledgment numbers
+    segment.extend_from_slice(&[0; 8]);
+    // a 5 words header
+    segment.extend_from_slice(&[5 << 4, flags.bits()]);
+    segment.extend_from_slice(&u16::MAX.to_be_bytes());
+    // checksum and urgent pointer
+    segment.extend_from_slice(&[0; 4]);
+    segment.extend_from_slice(payload);
+
+    let tcp_checksum = transport_checksum(src.ip(), dst.ip(), IPPROTO_TCP, &segment)?;
+    segment[16..18].copy_from_slice(&tcp_checksum.to_be_bytes());
+
+    ip_frame(src_mac, dst_mac, src.ip(), dst.ip(), IPPROTO_TCP, &segment)
+}
+
+/// Builds an Ethernet frame containing an IP packet.
+///
+/// The packet is an IPv4 packet with a valid header checksum, or an IPv6
+/// packet, depending on the addresses. `payload` is the transport segment,
+/// whose checksum can be computed with [`transport_checksum`].
+///
+/// # Errors
+///
+/// Returns an error if `src` and `dst` aren't of the same address family, or
+/// if `payload` doesn't fit in a single packet.
+pub fn ip_frame(
+    src_mac: [u8; 6],
+    dst_mac: [u8; 6],
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    payload: &[u8],
+) -> Result<Vec<u8>, FrameError> {
+    let too_large = || FrameError::PayloadTooLarge { len: payload.len() };
+    let payload_len = u16::try_from(payload.len()).map_err(|_| too_large())?;
+    let mut frame = Vec::with_capacity(14 + 40 + payload.len());
+    frame.extend_from_slice(&dst_mac);
+    frame.extend_from_slice(&src_mac);
+
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            let total_len = payload_len.checked_add(20).ok_or_else(too_large)?;
+            frame.extend_from_slice(&ETH_P_IP.to_be_bytes());
+            let ip_start = frame.len();
+            frame.extend_from_slice(&[0x45, 0]);
+            frame.extend_from_slice(&total_len.to_be_bytes());
+            // id, don't fragment, ttl, protocol and checksum
+            frame.extend_from_slice(&[0, 0, 0x40, 0, 64, protocol, 0, 0]);
+            frame.extend_from_slice(&src.octets());
+            frame.extend_from_slice(&dst.octets());
+            let ip_checksum = checksum(&frame[ip_start..]);
+            frame[ip_start + 10..ip_start + 12].copy_from_slice(&ip_checksum.to_be_bytes());
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            frame.extend_from_slice(&ETH_P_IPV6.to_be_bytes());
+            frame.extend_from_slice(&[0x60, 0, 0, 0]);
+            frame.extend_from_slice(&payload_len.to_be_bytes());
+            // next header and hop limit
+            frame.extend_from_slice(&[protocol, 64]);
+            frame.extend_from_slice(&src.octets());
+            frame.extend_from_slice(&dst.octets());
+        }
+        _ => return Err(FrameError::AddressFamilyMismatch),
+    }
+    frame.extend_from_slice(payload);
+
+    Ok(frame)
+}
+
+/// Returns the checksum of a TCP or UDP segment, which covers the segment
+/// and a pseudo header made of the addresses, the protocol and the length
+/// of the segment.
+///
+/// The checksum field of `segment` must be zero. UDP replaces a zero
+/// checksum with `0xffff`, since a zero checksum means no checksum.
+///
+/// # Errors
+///
+/// Returns an error if `src` and `dst` aren't of the same address family, or
+/// if `segment` doesn't fit in a single packet.
+pub fn transport_checksum(
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    segment: &[u8],
+) -> Result<u16, FrameError> {
+    let too_large = || FrameError::PayloadTooLarge { len: segment.len() };
+    let mut pseudo_header = Vec::with_capacity(40 + segment.len());
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            pseudo_header.extend_from_slice(&src.octets());
+            pseudo_header.extend_from_slice(&dst.octets());
+            pseudo_header.extend_from_slice(&[0, protocol]);
+            let len = u16::try_from(segment.len()).map_err(|_| too_large())?;
+            pseudo_header.extend_from_slice(&len.to_be_bytes());
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            pseudo_header.extend_from_slice(&src.octets());
+            pseudo_header.extend_from_slice(&dst.octets());
+            let len = u32::try_from(segment.len()).map_err(|_| too_large())?;
+            pseudo_header.extend_from_slice(&len.to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, protocol]);
+        }
+        _ => return Err(FrameError::AddressFamilyMismatch),
+    }
+    pseudo_header.extend_from_slice(segment);
+    Ok(checksum(&pseudo_header))
+}
+
+/// Returns the internet checksum of `data`, from RFC 1071.
+///
+/// This is the checksum of IPv4 headers, and of ICMP messages. A header
+/// whose checksum field is valid checksums to zero.
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preflight_issues() {
+        assert!(preflight_issues(XdpFlags::DRV_MODE, 1500, 4096, Some(4), Some(4)).is_empty());
+
+        assert_eq!(
+            preflight_issues(XdpFlags::DRV_MODE, 9000, 4096, None, None),
+            vec![XdpPreflightIssue::MtuTooLarge {
+                mtu: 9000,
+                max_mtu: 3498
+            }]
+        );
+        // no MTU limit in generic mode
+        assert!(preflight_issues(XdpFlags::SKB_MODE, 9000, 4096, None, None).is_empty());
+
+        let issues = preflight_issues(XdpFlags::default(), 1500, 4096, Some(64), Some(16));
+        assert_eq!(
+            issues,
+            vec![XdpPreflightIssue::QueueCountExceedsMap {
+                queues: 64,
+                max_entries: 16
+            }]
+        );
+        assert!(!issues[0].is_error());
+    }
+
+    #[test]
+    fn test_preferred_mode() {
+        let features = XdpFeatures::BASIC | XdpFeatures::REDIRECT;
+        assert_eq!(features.preferred_mode(), XdpFlags::DRV_MODE);
+        assert_eq!(XdpFeatures::empty().preferred_mode(), XdpFlags::SKB_MODE);
+    }
+
+    #[test]
+    fn test_udp_frame() {
+        let src_mac = [2, 0, 0, 0, 0, 1];
+        let dst_mac = [2, 0, 0, 0, 0, 2];
+
+        let frame = udp_frame(
+            src_mac,
+            dst_mac,
+            "10.0.0.1:1234".parse().unwrap(),
+            "10.0.0.2:53".parse().unwrap(),
+            b"hello",
+        )
+        .unwrap();
+        assert_eq!(frame.len(), 14 + 20 + 8 + 5);
+        assert_eq!(frame[..6], dst_mac);
+        assert_eq!(frame[6..12], src_mac);
+        assert_eq!(frame[12..14], [0x08, 0x00]);
+        // a valid header checksums to zero
+        assert_eq!(checksum(&frame[14..34]), 0);
+        assert_eq!(frame[16..18], 33u16.to_be_bytes());
+        assert_eq!(frame[36..38], 53u16.to_be_bytes());
+        assert_eq!(&frame[42..], b"hello");
+        let mut pseudo_header = frame[26..34].to_vec();
+        pseudo_header.extend_from_slice(&[0, 17, 0, 13]);
+        pseudo_header.extend_from_slice(&frame[34..]);
+        assert_eq!(checksum(&pseudo_header), 0);
+
+        let frame = udp_frame(
+            src_mac,
+            dst_mac,
+            "[fd00::1]:1234".parse().unwrap(),
+            "[fd00::2]:53".parse().unwrap(),
+            b"hello",
+        )
+        .unwrap();
+        assert_eq!(frame.len(), 14 + 40 + 8 + 5);
+        assert_eq!(frame[12..14], [0x86, 0xdd]);
+        assert_eq!(frame[18..20], 13u16.to_be_bytes());
+        let mut pseudo_header = frame[22..54].to_vec();
+        pseudo_header.extend_from_slice(&[0, 0, 0, 13, 0, 0, 0, 17]);
+        pseudo_header.extend_from_slice(&frame[54..]);
+        assert_eq!(checksum(&pseudo_header), 0);
+
+        assert!(matches!(
+            udp_frame(
+                src_mac,
+                dst_mac,
+                "10.0.0.1:1234".parse().unwrap(),
+                "[fd00::2]:53".parse().unwrap(),
+                b"hello",
+            ),
+            Err(FrameError::AddressFamilyMismatch)
+        ));
+        assert!(matches!(
+            udp_frame(
+                src_mac,
+                dst_mac,
+                "10.0.0.1:1234".parse().unwrap(),
+                "10.0.0.2:53".parse().unwrap(),
+                &[0; 65530],
+            ),
+            Err(FrameError::PayloadTooLarge { len: 65530 })
+        ));
+    }
+
+    #[test]
+    fn test_tcp_frame() {
+        let src = "10.0.0.1:1234".parse::<SocketAddr>().unwrap();
+        let dst = "10.0.0.2:80".parse::<SocketAddr>().unwrap();
+        let frame = tcp_frame([0; 6], [0; 6], src, dst, TcpFlags::SYN, b"").unwrap();
+        assert_eq!(frame.len(), 14 + 20 + 20);
+        assert_eq!(frame[23], IPPROTO_TCP);
+        assert_eq!(checksum(&frame[14..34]), 0);
+        assert_eq!(frame[46..48], [0x50, 0x02]);
+        assert_eq!(
+            transport_checksum(src.ip(), dst.ip(), IPPROTO_TCP, &frame[34..]).unwrap(),
+            0
+        );
+
+        let src = "[fd00::1]:1234".parse::<SocketAddr>().unwrap();
+        let dst = "[fd00::2]:80".parse::<SocketAddr>().unwrap();
+        let frame = tcp_frame(
+            [0; 6],
+            [0; 6],
+            src,
+            dst,
+            TcpFlags::PSH | TcpFlags::ACK,
+            b"GET /",
+        )
+        .unwrap();
+        assert_eq!(frame.len(), 14 + 40 + 20 + 5);
+        assert_eq!(frame[20], IPPROTO_TCP);
+        assert_eq!(frame[18..20], 25u16.to_be_bytes());
+        assert_eq!(
+            transport_checksum(src.ip(), dst.ip(), IPPROTO_TCP, &frame[54..]).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        // the example of RFC 1071
+        assert_eq!(
+            checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]),
+            !0xddf2
+        );
+        // odd lengths are padded with a zero byte
+        assert_eq!(
+            checksum(&[0x12, 0x34, 0x56]),
+            checksum(&[0x12, 0x34, 0x56, 0])
+        );
+    }
+}
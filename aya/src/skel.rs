@@ -0,0 +1,293 @@
+//! Light skeleton support.
+//!
+//! A light skeleton, as generated by `bpftool gen skeleton -L`, doesn't
+//! contain an ELF object. Instead it embeds a loader program of type
+//! `BPF_PROG_TYPE_SYSCALL` together with a data blob. The loader program runs
+//! inside the kernel, creates the maps and loads the real programs described
+//! by the blob, and writes the resulting file descriptors back into a context
+//! structure shared with user space.
+//!
+//! Because the only program user space loads directly is the loader, light
+//! skeletons can be used on systems that restrict `BPF_PROG_LOAD` to signed
+//! loader programs.
+use std::{
+    ffi::CString,
+    io, mem,
+    os::unix::io::{AsRawFd, RawFd},
+};
+
+use thiserror::Error;
+
+use crate::{
+    bpf_map_def,
+    generated::{
+        bpf_map_type::BPF_MAP_TYPE_ARRAY, bpf_prog_type::BPF_PROG_TYPE_SYSCALL, BPF_F_SLEEPABLE,
+    },
+    maps::{MapData, MapError},
+    obj::{self, copy_instructions, LegacyMap, MapKind},
+    programs::ProgramError,
+    sys::{
+        bpf_load_program, bpf_map_update_elem_ptr, bpf_prog_test_run, retry_with_verifier_logs,
+        BpfLoadProgramAttrs, BpfProgTestRunAttrs,
+    },
+    util::VerifierLog,
+};
+
+const LOADER_MAP_NAME: &str = "__loader.map";
+const LOADER_PROG_NAME: &str = "__loader.prog";
+const LOADER_LICENSE: &str = "Dual BSD/GPL";
+
+/// The header every light skeleton context starts with.
+///
+/// This mirrors `struct bpf_loader_ctx` from libbpf. The generated skeleton
+/// context embeds this header as its first field, followed by the map and
+/// program descriptors the loader program fills in.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoaderCtx {
+    /// The size of the whole context, including this header.
+    pub sz: u32,
+    /// The verifier log level used when loading the skeleton programs.
+    pub log_level: u32,
+    /// The size of the buffer pointed to by `log_buf`.
+    pub log_size: u32,
+    /// A user space buffer receiving the verifier log of the skeleton programs.
+    pub log_buf: u64,
+}
+
+/// Errors returned when loading a [`LightSkeleton`].
+#[derive(Error, Debug)]
+pub enum SkeletonError {
+    /// The loader program instructions are not a whole number of instructions.
+    #[error("invalid loader program instructions")]
+    InvalidInstructions,
+
+    /// The context is too small to hold the loader header.
+    #[error("invalid loader context: {size} bytes, expected at least {expected}")]
+    InvalidContext {
+        /// the provided context size
+        size: usize,
+        /// the minimum context size
+        expected: usize,
+    },
+
+    /// The `sz` field of the context doesn't match its length.
+    #[error("invalid loader context: {size} bytes, but its `sz` field is {sz}")]
+    ContextSizeMismatch {
+        /// the provided context size
+        size: usize,
+        /// the value of the `sz` field
+        sz: usize,
+    },
+
+    /// Creating or populating the loader map failed.
+    #[error(transparent)]
+    MapError(#[from] MapError),
+
+    /// Loading or running the loader program failed.
+    #[error(transparent)]
+    ProgramError(#[from] ProgramError),
+
+    /// The loader program ran but failed to load the skeleton.
+    #[error("the loader program failed")]
+    LoaderFailed {
+        /// The error returned by the loader program.
+        #[source]
+        io_error: io::Error,
+    },
+}
+
+/// A light skeleton loader.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.14.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::skel::{LightSkeleton, LoaderCtx};
+///
+/// # let (insns, data): (&[u8], &[u8]) = (&[], &[]);
+/// // `insns` and `data` are the `opts_insn` and `opts_data` blobs of the
+/// // generated skeleton
+/// let skel = LightSkeleton::new(insns, data);
+///
+/// // the generated context struct, starting with a LoaderCtx header
+/// #[repr(C)]
+/// #[derive(Default)]
+/// struct Ctx {
+///     hdr: LoaderCtx,
+///     map_fd: i32,
+///     prog_fd: i32,
+/// }
+/// let mut ctx = Ctx::default();
+/// ctx.hdr.sz = std::mem::size_of::<Ctx>() as u32;
+/// let bytes = unsafe {
+///     std::slice::from_raw_parts_mut(&mut ctx as *mut Ctx as *mut u8, ctx.hdr.sz as usize)
+/// };
+/// skel.load(bytes)?;
+/// // ctx.map_fd and ctx.prog_fd are now owned by the caller
+/// # Ok::<(), aya::skel::SkeletonError>(())
+/// ```
+#[derive(Debug)]
+pub struct LightSkeleton<'a> {
+    insns: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> LightSkeleton<'a> {
+    /// Creates a new skeleton loader from the loader program instructions and
+    /// its data blob.
+    pub fn new(insns: &'a [u8], data: &'a [u8]) -> LightSkeleton<'a> {
+        LightSkeleton { insns, data }
+    }
+
+    /// Loads the skeleton.
+    ///
+    /// `ctx` is the skeleton context, starting with a [`LoaderCtx`] header
+    /// whose `sz` field is set to the size of the context. On success the
+    /// loader program has written the file descriptors of the skeleton maps
+    /// and programs into `ctx`, and the caller is responsible for closing
+    /// them.
+    pub fn load(&self, ctx: &mut [u8]) -> Result<(), SkeletonError> {
+        let header_size = mem::size_of::<LoaderCtx>();
+        if ctx.len() < header_size {
+            return Err(SkeletonError::InvalidContext {
+                size: ctx.len(),
+                expected: header_size,
+            });
+        }
+        let header = unsafe { (ctx.as_ptr() as *const LoaderCtx).read_unaligned() };
+        if header.sz as usize != ctx.len() {
+            return Err(SkeletonError::ContextSizeMismatch {
+                size: ctx.len(),
+                sz: header.sz as usize,
+            });
+        }
+
+        let insns =
+            copy_instructions(self.insns).map_err(|_| SkeletonError::InvalidInstructions)?;
+
+        let mut map = MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: mem::size_of::<u32>() as u32,
+                    value_size: self.data.len() as u32,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 0,
+                symbol_index: 0,
+                data: Vec::new(),
+                kind: MapKind::Other,
+                map_extra: 0,
+                numa_node: None,
+            }),
+            fd: None,
+            btf_fd: None,
+            pinned: false,
+        };
+        let map_fd = map.create(LOADER_MAP_NAME)?;
+        let mut data = self.data.to_vec();
+        bpf_map_update_elem_ptr(map_fd, &0u32 as *const _, data.as_mut_ptr(), 0).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            },
+        )?;
+
+        let license = CString::new(LOADER_LICENSE).unwrap();
+        let fd_array = [map_fd];
+        let attr = BpfLoadProgramAttrs {
+            name: Some(CString::new(LOADER_PROG_NAME).unwrap()),
+            ty: BPF_PROG_TYPE_SYSCALL,
+            insns: &insns,
+            license: &license,
+            kernel_version: 0,
+            expected_attach_type: None,
+            prog_btf_fd: None,
+            attach_btf_obj_fd: None,
+            attach_btf_id: None,
+            attach_prog_fd: None,
+            func_info_rec_size: 0,
+            func_info: Default::default(),
+            line_info_rec_size: 0,
+            line_info: Default::default(),
+            flags: BPF_F_SLEEPABLE,
+            fd_array: Some(&fd_array),
+        };
+        let mut logger = VerifierLog::new();
+        let prog_fd =
+            retry_with_verifier_logs(10, &mut logger, |logger| bpf_load_program(&attr, logger, 1))
+                .map_err(|(_, io_error)| {
+                logger.truncate();
+                ProgramError::LoadError {
+                    io_error,
+                    verifier_log: logger
+                        .as_c_str()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_else(|| "[none]".to_owned()),
+                }
+            })? as RawFd;
+        let prog = LoaderProgram(prog_fd);
+
+        let ret = bpf_prog_test_run(
+            prog.as_raw_fd(),
+            BpfProgTestRunAttrs {
+                ctx_in: Some(ctx),
+                ..Default::default()
+            },
+        )
+        .map_err(|(_, io_error)| ProgramError::SyscallError {
+            call: "bpf_prog_test_run".to_owned(),
+            io_error,
+        })?;
+
+        // the loader program returns a negative errno on failure
+        let retval = ret.retval as i32;
+        if retval < 0 {
+            return Err(SkeletonError::LoaderFailed {
+                io_error: io::Error::from_raw_os_error(-retval),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+struct LoaderProgram(RawFd);
+
+impl AsRawFd for LoaderProgram {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for LoaderProgram {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_context() {
+        let skel = LightSkeleton::new(&[], &[]);
+        assert!(matches!(
+            skel.load(&mut [0u8; 4]),
+            Err(SkeletonError::InvalidContext { size: 4, .. })
+        ));
+
+        let mut ctx = [0u8; mem::size_of::<LoaderCtx>() + 8];
+        ctx[..4].copy_from_slice(&4u32.to_ne_bytes());
+        assert!(matches!(
+            skel.load(&mut ctx),
+            Err(SkeletonError::ContextSizeMismatch { sz: 4, .. })
+        ));
+    }
+}
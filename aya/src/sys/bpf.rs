@@ -1,18 +1,18 @@
 use std::{
     cmp::{self, min},
     ffi::{CStr, CString},
-    io,
+    fs, io,
     mem::{self, MaybeUninit},
     os::unix::io::RawFd,
-    slice,
+    ptr, slice,
 };
 
-use libc::{c_char, c_long, close, ENOENT, ENOSPC};
+use libc::{c_char, c_long, close, pid_t, ENOENT, ENOSPC};
 
 use crate::{
     generated::{
-        bpf_attach_type, bpf_attr, bpf_btf_info, bpf_cmd, bpf_insn, bpf_link_info, bpf_map_info,
-        bpf_prog_info, bpf_prog_type, BPF_F_REPLACE,
+        bpf_attach_type, bpf_attr, bpf_btf_info, bpf_cmd, bpf_func_info, bpf_insn, bpf_link_info,
+        bpf_map_info, bpf_map_type, bpf_prog_info, bpf_prog_type, BPF_F_REPLACE,
     },
     maps::PerCpuValues,
     obj::{
@@ -37,6 +37,10 @@ pub(crate) fn bpf_create_map(name: &CStr, def: &obj::Map, btf_fd: Option<RawFd>)
     u.value_size = def.value_size();
     u.max_entries = def.max_entries();
     u.map_flags = def.map_flags();
+    u.map_extra = def.map_extra();
+    if let Some(numa_node) = def.numa_node() {
+        u.numa_node = numa_node;
+    }
 
     if let obj::Map::Btf(m) = def {
         u.btf_key_type_id = m.def.btf_key_type_id;
@@ -58,6 +62,34 @@ pub(crate) fn bpf_create_map(name: &CStr, def: &obj::Map, btf_fd: Option<RawFd>)
     sys_bpf(bpf_cmd::BPF_MAP_CREATE, &attr)
 }
 
+// since kernel 5.6
+pub(crate) fn bpf_create_struct_ops_map(
+    name: &CStr,
+    value_size: u32,
+    value_type_id: u32,
+    btf_fd: RawFd,
+    flags: u32,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.__bindgen_anon_1 };
+    u.map_type = bpf_map_type::BPF_MAP_TYPE_STRUCT_OPS as u32;
+    u.key_size = mem::size_of::<u32>() as u32;
+    u.value_size = value_size;
+    u.max_entries = 1;
+    u.map_flags = flags;
+    // the value is a kernel type, but the kernel still requires the BTF of
+    // the object
+    u.btf_fd = btf_fd as u32;
+    u.btf_vmlinux_value_type_id = value_type_id;
+
+    let name_len = cmp::min(name.to_bytes().len(), BPF_OBJ_NAME_LEN - 1);
+    u.map_name[..name_len]
+        .copy_from_slice(unsafe { slice::from_raw_parts(name.as_ptr(), name_len) });
+
+    sys_bpf(bpf_cmd::BPF_MAP_CREATE, &attr)
+}
+
 pub(crate) fn bpf_pin_object(fd: RawFd, path: &CStr) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     let u = unsafe { &mut attr.__bindgen_anon_4 };
@@ -79,7 +111,7 @@ pub(crate) struct BpfLoadProgramAttrs<'a> {
     pub(crate) insns: &'a [bpf_insn],
     pub(crate) license: &'a CStr,
     pub(crate) kernel_version: u32,
-    pub(crate) expected_attach_type: Option<bpf_attach_type>,
+    pub(crate) expected_attach_type: Option<u32>,
     pub(crate) prog_btf_fd: Option<RawFd>,
     pub(crate) attach_btf_obj_fd: Option<u32>,
     pub(crate) attach_btf_id: Option<u32>,
@@ -88,6 +120,8 @@ pub(crate) struct BpfLoadProgramAttrs<'a> {
     pub(crate) func_info: FuncSecInfo,
     pub(crate) line_info_rec_size: usize,
     pub(crate) line_info: LineSecInfo,
+    pub(crate) flags: u32,
+    pub(crate) fd_array: Option<&'a [RawFd]>,
 }
 
 pub(crate) fn bpf_load_program(
@@ -111,12 +145,16 @@ pub(crate) fn bpf_load_program(
 
     u.prog_type = aya_attr.ty as u32;
     if let Some(v) = aya_attr.expected_attach_type {
-        u.expected_attach_type = v as u32;
+        u.expected_attach_type = v;
     }
     u.insns = aya_attr.insns.as_ptr() as u64;
     u.insn_cnt = aya_attr.insns.len() as u32;
     u.license = aya_attr.license.as_ptr() as u64;
     u.kern_version = aya_attr.kernel_version;
+    u.prog_flags = aya_attr.flags;
+    if let Some(fd_array) = aya_attr.fd_array {
+        u.fd_array = fd_array.as_ptr() as u64;
+    }
 
     // these must be allocated here to ensure the slice outlives the pointer
     // so .as_ptr below won't point to garbage
@@ -203,8 +241,8 @@ pub(crate) fn bpf_map_lookup_elem_per_cpu<K: Pod, V: Pod>(
 ) -> Result<Option<PerCpuValues<V>>, (c_long, io::Error)> {
     let mut mem = PerCpuValues::<V>::alloc_kernel_mem().map_err(|io_error| (-1, io_error))?;
     match bpf_map_lookup_elem_ptr(fd, Some(key), mem.as_mut_ptr(), flags) {
-        Ok(_) => Ok(Some(unsafe { PerCpuValues::from_kernel_mem(mem) })),
-        Err((_, io_error)) if io_error.raw_os_error() == Some(ENOENT) => Ok(None),
+        Ok(Some(())) => Ok(Some(unsafe { PerCpuValues::from_kernel_mem(mem) })),
+        Ok(None) => Ok(None),
         Err(e) => Err(e),
     }
 }
@@ -320,6 +358,28 @@ pub(crate) fn bpf_map_get_next_key<K>(
     }
 }
 
+// Like bpf_map_get_next_key, for keys whose size is only known at runtime.
+pub(crate) fn bpf_map_get_next_key_bytes(
+    fd: RawFd,
+    key: Option<&[u8]>,
+    next_key: &mut [u8],
+) -> Result<bool, (c_long, io::Error)> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.__bindgen_anon_2 };
+    u.map_fd = fd as u32;
+    if let Some(key) = key {
+        u.key = key.as_ptr() as u64;
+    }
+    u.__bindgen_anon_1.next_key = next_key.as_mut_ptr() as u64;
+
+    match sys_bpf(bpf_cmd::BPF_MAP_GET_NEXT_KEY, &attr) {
+        Ok(_) => Ok(true),
+        Err((_, io_error)) if io_error.raw_os_error() == Some(ENOENT) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
 // since kernel 5.2
 pub(crate) fn bpf_map_freeze(fd: RawFd) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
@@ -328,6 +388,69 @@ pub(crate) fn bpf_map_freeze(fd: RawFd) -> SysResult {
     sys_bpf(bpf_cmd::BPF_MAP_FREEZE, &attr)
 }
 
+// Looks up, and deletes if `delete` is set, the entries following
+// `in_batch`, writing at most `keys.len()` keys and their values to `keys`
+// and `values`. Returns the number of entries read and whether there are
+// more entries. Since kernel 5.6.
+pub(crate) fn bpf_map_lookup_batch<K: Pod>(
+    fd: RawFd,
+    in_batch: Option<&K>,
+    out_batch: &mut K,
+    keys: &mut [K],
+    values: &mut [u8],
+    delete: bool,
+) -> Result<(u32, bool), (c_long, io::Error)> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.batch };
+    u.map_fd = fd as u32;
+    if let Some(in_batch) = in_batch {
+        u.in_batch = in_batch as *const _ as u64;
+    }
+    u.out_batch = out_batch as *mut _ as u64;
+    u.keys = keys.as_mut_ptr() as u64;
+    u.values = values.as_mut_ptr() as u64;
+    u.count = keys.len() as u32;
+
+    let cmd = if delete {
+        bpf_cmd::BPF_MAP_LOOKUP_AND_DELETE_BATCH
+    } else {
+        bpf_cmd::BPF_MAP_LOOKUP_BATCH
+    };
+    let res = sys_bpf(cmd, &attr);
+    // the kernel writes back the number of entries read, even when it fails
+    #[cfg(not(test))]
+    let count = unsafe { attr.batch.count };
+    #[cfg(test)]
+    let count = crate::sys::TEST_BATCH_COUNT
+        .with(|count| count.borrow_mut().take())
+        .unwrap_or(unsafe { attr.batch.count });
+    match res {
+        Ok(_) => Ok((count, true)),
+        Err((_, io_error)) if io_error.raw_os_error() == Some(ENOENT) => Ok((count, false)),
+        Err(e) => Err(e),
+    }
+}
+
+// Updates the entries of the given keys with `values`. Since kernel 5.6.
+pub(crate) fn bpf_map_update_batch<K: Pod>(
+    fd: RawFd,
+    keys: &[K],
+    values: &[u8],
+    flags: u64,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.batch };
+    u.map_fd = fd as u32;
+    u.keys = keys.as_ptr() as u64;
+    u.values = values.as_ptr() as u64;
+    u.count = keys.len() as u32;
+    u.elem_flags = flags;
+
+    sys_bpf(bpf_cmd::BPF_MAP_UPDATE_BATCH, &attr)
+}
+
 // since kernel 5.7
 pub(crate) fn bpf_link_create(
     prog_fd: RawFd,
@@ -349,6 +472,137 @@ pub(crate) fn bpf_link_create(
     sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
 }
 
+// since kernel 5.18
+pub(crate) fn bpf_link_create_kprobe_multi(
+    prog_fd: RawFd,
+    symbols: &[CString],
+    flags: u32,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let syms = symbols.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+    attr.link_create.prog_fd = prog_fd as u32;
+    attr.link_create.attach_type = bpf_attach_type::BPF_TRACE_KPROBE_MULTI as u32;
+    let kprobe_multi = unsafe { &mut attr.link_create.__bindgen_anon_2.kprobe_multi };
+    kprobe_multi.flags = flags;
+    kprobe_multi.cnt = syms.len() as u32;
+    kprobe_multi.syms = syms.as_ptr() as u64;
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 6.6
+pub(crate) fn bpf_link_create_uprobe_multi(
+    prog_fd: RawFd,
+    path: &CStr,
+    offsets: &[u64],
+    cookies: Option<&[u64]>,
+    pid: Option<pid_t>,
+    flags: u32,
+) -> SysResult {
+    // not in the generated bindings yet, the link_create attributes with
+    // the uprobe_multi member of the union
+    #[repr(C)]
+    struct LinkCreateUProbeMulti {
+        prog_fd: u32,
+        target_fd: u32,
+        attach_type: u32,
+        flags: u32,
+        path: u64,
+        offsets: u64,
+        ref_ctr_offsets: u64,
+        cookies: u64,
+        cnt: u32,
+        uprobe_flags: u32,
+        pid: u32,
+    }
+
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let link_create = LinkCreateUProbeMulti {
+        prog_fd: prog_fd as u32,
+        target_fd: 0,
+        attach_type: bpf_attach_type::BPF_TRACE_UPROBE_MULTI as u32,
+        flags: 0,
+        path: path.as_ptr() as u64,
+        offsets: offsets.as_ptr() as u64,
+        ref_ctr_offsets: 0,
+        cookies: cookies.map(|c| c.as_ptr() as u64).unwrap_or(0),
+        cnt: offsets.len() as u32,
+        uprobe_flags: flags,
+        pid: pid.unwrap_or(0) as u32,
+    };
+    // Safety: bpf_attr is larger than the link_create attributes
+    unsafe {
+        ptr::write(
+            &mut attr as *mut _ as *mut LinkCreateUProbeMulti,
+            link_create,
+        )
+    };
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 6.4
+pub(crate) fn bpf_link_create_netfilter(
+    prog_fd: RawFd,
+    pf: u32,
+    hooknum: u32,
+    priority: i32,
+    flags: u32,
+) -> SysResult {
+    // not in the generated bindings yet, the link_create attributes with
+    // the netfilter member of the union
+    #[repr(C)]
+    struct LinkCreateNetfilter {
+        prog_fd: u32,
+        target_fd: u32,
+        attach_type: u32,
+        flags: u32,
+        pf: u32,
+        hooknum: u32,
+        priority: i32,
+        netfilter_flags: u32,
+    }
+
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let link_create = LinkCreateNetfilter {
+        prog_fd: prog_fd as u32,
+        target_fd: 0,
+        attach_type: bpf_attach_type::BPF_NETFILTER as u32,
+        flags: 0,
+        pf,
+        hooknum,
+        priority,
+        netfilter_flags: flags,
+    };
+    // Safety: bpf_attr is larger than the link_create attributes
+    unsafe { ptr::write(&mut attr as *mut _ as *mut LinkCreateNetfilter, link_create) };
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 6.4
+pub(crate) fn bpf_link_create_struct_ops(map_fd: RawFd) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    // map_fd shares its place with prog_fd
+    attr.link_create.prog_fd = map_fd as u32;
+    attr.link_create.attach_type = bpf_attach_type::BPF_STRUCT_OPS as u32;
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 5.8
+pub(crate) fn bpf_create_iter(link_fd: RawFd) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.iter_create.link_fd = link_fd as u32;
+
+    sys_bpf(bpf_cmd::BPF_ITER_CREATE, &attr)
+}
+
 // since kernel 5.7
 pub(crate) fn bpf_link_update(
     link_fd: RawFd,
@@ -370,16 +624,27 @@ pub(crate) fn bpf_link_update(
     sys_bpf(bpf_cmd::BPF_LINK_UPDATE, &attr)
 }
 
+// since kernel 5.9
+pub(crate) fn bpf_link_detach(link_fd: RawFd) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.link_detach.link_fd = link_fd as u32;
+
+    sys_bpf(bpf_cmd::BPF_LINK_DETACH, &attr)
+}
+
 pub(crate) fn bpf_prog_attach(
     prog_fd: RawFd,
     target_fd: RawFd,
     attach_type: bpf_attach_type,
+    flags: u32,
 ) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
 
     attr.__bindgen_anon_5.attach_bpf_fd = prog_fd as u32;
     attr.__bindgen_anon_5.target_fd = target_fd as u32;
     attr.__bindgen_anon_5.attach_type = attach_type as u32;
+    attr.__bindgen_anon_5.attach_flags = flags;
 
     sys_bpf(bpf_cmd::BPF_PROG_ATTACH, &attr)
 }
@@ -436,6 +701,36 @@ pub(crate) fn bpf_prog_get_fd_by_id(prog_id: u32) -> Result<RawFd, io::Error> {
     }
 }
 
+pub(crate) fn bpf_prog_get_next_id(id: u32) -> Result<Option<u32>, io::Error> {
+    bpf_obj_get_next_id(bpf_cmd::BPF_PROG_GET_NEXT_ID, id)
+}
+
+pub(crate) fn bpf_map_get_next_id(id: u32) -> Result<Option<u32>, io::Error> {
+    bpf_obj_get_next_id(bpf_cmd::BPF_MAP_GET_NEXT_ID, id)
+}
+
+fn bpf_obj_get_next_id(cmd: bpf_cmd, id: u32) -> Result<Option<u32>, io::Error> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    attr.__bindgen_anon_6.__bindgen_anon_1.start_id = id;
+
+    match sys_bpf(cmd, &attr) {
+        Ok(_) => Ok(Some(unsafe { attr.__bindgen_anon_6.next_id })),
+        Err((_, io_error)) if io_error.raw_os_error() == Some(ENOENT) => Ok(None),
+        Err((_, io_error)) => Err(io_error),
+    }
+}
+
+pub(crate) fn bpf_map_get_fd_by_id(map_id: u32) -> Result<RawFd, io::Error> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.__bindgen_anon_6.__bindgen_anon_1.map_id = map_id;
+
+    match sys_bpf(bpf_cmd::BPF_MAP_GET_FD_BY_ID, &attr) {
+        Ok(v) => Ok(v as RawFd),
+        Err((_, err)) => Err(err),
+    }
+}
+
 pub(crate) fn bpf_prog_get_info_by_fd(prog_fd: RawFd) -> Result<bpf_prog_info, io::Error> {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     // info gets entirely populated by the kernel
@@ -451,6 +746,35 @@ pub(crate) fn bpf_prog_get_info_by_fd(prog_fd: RawFd) -> Result<bpf_prog_info, i
     }
 }
 
+// Returns the func info records of the program, the first of which is for
+// the main function.
+pub(crate) fn bpf_prog_get_func_info(prog_fd: RawFd) -> Result<Vec<bpf_func_info>, io::Error> {
+    let info = bpf_prog_get_info_by_fd(prog_fd)?;
+    let rec_size = info.func_info_rec_size as usize;
+    if info.nr_func_info == 0 || rec_size < mem::size_of::<bpf_func_info>() {
+        return Ok(Vec::new());
+    }
+
+    let mut buf = vec![0u8; info.nr_func_info as usize * rec_size];
+    let mut info = unsafe { mem::zeroed::<bpf_prog_info>() };
+    info.nr_func_info = (buf.len() / rec_size) as u32;
+    info.func_info_rec_size = rec_size as u32;
+    info.func_info = buf.as_mut_ptr() as u64;
+
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    attr.info.bpf_fd = prog_fd as u32;
+    attr.info.info = &mut info as *mut _ as u64;
+    attr.info.info_len = mem::size_of::<bpf_prog_info>() as u32;
+    sys_bpf(bpf_cmd::BPF_OBJ_GET_INFO_BY_FD, &attr).map_err(|(_, err)| err)?;
+
+    let nr_func_info = (info.nr_func_info as usize).min(buf.len() / rec_size);
+    Ok(buf
+        .chunks_exact(rec_size)
+        .take(nr_func_info)
+        .map(|rec| unsafe { (rec.as_ptr() as *const bpf_func_info).read_unaligned() })
+        .collect())
+}
+
 pub(crate) fn bpf_map_get_info_by_fd(prog_fd: RawFd) -> Result<bpf_map_info, io::Error> {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     // info gets entirely populated by the kernel
@@ -500,6 +824,20 @@ pub(crate) fn btf_obj_get_info_by_fd(
     }
 }
 
+// Returns the raw BTF data of the BTF object `btf_fd`.
+pub(crate) fn btf_obj_get_data(btf_fd: RawFd) -> Result<Vec<u8>, io::Error> {
+    // the size of the BTF data is only known after a first call, so start
+    // with a buffer that's large enough for small objects
+    let mut buf = vec![0u8; 4096];
+    let mut info = btf_obj_get_info_by_fd(btf_fd, &mut buf)?;
+    if info.btf_size as usize > buf.len() {
+        buf.resize(info.btf_size as usize, 0);
+        info = btf_obj_get_info_by_fd(btf_fd, &mut buf)?;
+    }
+    buf.truncate(info.btf_size as usize);
+    Ok(buf)
+}
+
 pub(crate) fn bpf_raw_tracepoint_open(name: Option<&CStr>, prog_fd: RawFd) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
 
@@ -512,6 +850,64 @@ pub(crate) fn bpf_raw_tracepoint_open(name: Option<&CStr>, prog_fd: RawFd) -> Sy
     sys_bpf(bpf_cmd::BPF_RAW_TRACEPOINT_OPEN, &attr)
 }
 
+#[derive(Default)]
+pub(crate) struct BpfProgTestRunAttrs<'a> {
+    pub(crate) data_in: Option<&'a [u8]>,
+    pub(crate) data_out: Option<&'a mut [u8]>,
+    // for BPF_PROG_TYPE_SYSCALL programs the kernel writes the context back to ctx_in
+    pub(crate) ctx_in: Option<&'a mut [u8]>,
+    pub(crate) ctx_out: Option<&'a mut [u8]>,
+    pub(crate) repeat: u32,
+    pub(crate) flags: u32,
+    pub(crate) cpu: u32,
+    pub(crate) batch_size: u32,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct BpfProgTestRunOutput {
+    pub(crate) retval: u32,
+    // average run time in nanoseconds
+    pub(crate) duration: u32,
+}
+
+pub(crate) fn bpf_prog_test_run(
+    prog_fd: RawFd,
+    aya_attr: BpfProgTestRunAttrs,
+) -> Result<BpfProgTestRunOutput, (c_long, io::Error)> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.test };
+    u.prog_fd = prog_fd as u32;
+    if let Some(data_in) = aya_attr.data_in {
+        u.data_in = data_in.as_ptr() as u64;
+        u.data_size_in = data_in.len() as u32;
+    }
+    if let Some(data_out) = aya_attr.data_out {
+        u.data_out = data_out.as_mut_ptr() as u64;
+        u.data_size_out = data_out.len() as u32;
+    }
+    if let Some(ctx_in) = aya_attr.ctx_in {
+        u.ctx_in = ctx_in.as_mut_ptr() as u64;
+        u.ctx_size_in = ctx_in.len() as u32;
+    }
+    if let Some(ctx_out) = aya_attr.ctx_out {
+        u.ctx_out = ctx_out.as_mut_ptr() as u64;
+        u.ctx_size_out = ctx_out.len() as u32;
+    }
+    u.repeat = aya_attr.repeat;
+    u.flags = aya_attr.flags;
+    u.cpu = aya_attr.cpu;
+    u.batch_size = aya_attr.batch_size;
+
+    sys_bpf(bpf_cmd::BPF_PROG_TEST_RUN, &attr)?;
+
+    let u = unsafe { &attr.test };
+    Ok(BpfProgTestRunOutput {
+        retval: u.retval,
+        duration: u.duration,
+    })
+}
+
 pub(crate) fn bpf_load_btf(raw_btf: &[u8], log: &mut VerifierLog) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     let u = unsafe { &mut attr.__bindgen_anon_7 };
@@ -800,6 +1196,66 @@ pub fn sys_bpf(cmd: bpf_cmd, attr: &bpf_attr) -> SysResult {
     syscall(Syscall::Bpf { cmd, attr })
 }
 
+/// The kind of BPF object a file descriptor refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum BpfFdKind {
+    Map,
+    Program,
+    Link,
+}
+
+/// Returns the kind of BPF object `fd` refers to, or `None` if it isn't a BPF
+/// map, program or link.
+pub(crate) fn bpf_fd_kind(fd: RawFd) -> Option<BpfFdKind> {
+    match bpf_obj_info_len(fd) {
+        Ok(info_len) => Some(bpf_fd_kind_from_info_len(info_len)),
+        Err(_) => bpf_fd_kind_from_proc(fd),
+    }
+}
+
+// Returns the size of the info struct the kernel has for the object `fd`
+// refers to, up to the size of `bpf_prog_info`.
+fn bpf_obj_info_len(fd: RawFd) -> Result<u32, io::Error> {
+    // the sizes below only tell the kinds apart since links exist
+    match kernel_version() {
+        Ok(version) if version >= (5, 7, 0) => {}
+        _ => return Err(io::Error::from_raw_os_error(libc::ENOTSUP)),
+    }
+
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    let info = MaybeUninit::<bpf_prog_info>::zeroed();
+
+    attr.info.bpf_fd = fd as u32;
+    attr.info.info = info.as_ptr() as u64;
+    attr.info.info_len = mem::size_of::<bpf_prog_info>() as u32;
+
+    sys_bpf(bpf_cmd::BPF_OBJ_GET_INFO_BY_FD, &attr).map_err(|(_, err)| err)?;
+    Ok(unsafe { attr.info.info_len })
+}
+
+// The kernel copies as much of its info struct as fits in the buffer and
+// reports the size it copied. Since links were added, that struct has been
+// larger than 128 bytes for programs, between 64 and 128 bytes for maps and
+// at most 64 bytes for links.
+fn bpf_fd_kind_from_info_len(info_len: u32) -> BpfFdKind {
+    match info_len {
+        0..=64 => BpfFdKind::Link,
+        65..=128 => BpfFdKind::Map,
+        _ => BpfFdKind::Program,
+    }
+}
+
+fn bpf_fd_kind_from_proc(fd: RawFd) -> Option<BpfFdKind> {
+    // BPF objects are anonymous inodes named after their kind
+    let target = fs::read_link(format!("/proc/self/fd/{}", fd)).ok()?;
+    match target.to_str()? {
+        "anon_inode:bpf-map" => Some(BpfFdKind::Map),
+        "anon_inode:bpf-prog" => Some(BpfFdKind::Program),
+        "anon_inode:bpf_link" => Some(BpfFdKind::Link),
+        _ => None,
+    }
+}
+
 pub(crate) fn retry_with_verifier_logs<F>(
     max_retries: usize,
     log: &mut VerifierLog,
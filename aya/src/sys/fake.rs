@@ -10,6 +10,8 @@ type SyscallFn = unsafe fn(Syscall) -> SysResult;
 thread_local! {
     pub(crate) static TEST_SYSCALL: RefCell<SyscallFn> = RefCell::new(test_syscall);
     pub(crate) static TEST_MMAP_RET: RefCell<*mut c_void> = RefCell::new(ptr::null_mut());
+    // the count the kernel writes back to the attr of the next batch lookup
+    pub(crate) static TEST_BATCH_COUNT: RefCell<Option<u32>> = const { RefCell::new(None) };
 }
 
 #[cfg(test)]
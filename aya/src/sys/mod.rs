@@ -1,29 +1,35 @@
 mod bpf;
+mod netdev;
 mod netlink;
 mod perf_event;
 
 #[cfg(test)]
 mod fake;
 
-use std::io;
 #[cfg(not(test))]
 use std::{ffi::CString, mem};
+use std::{io, thread};
 
 #[cfg(not(test))]
 use libc::utsname;
-use libc::{c_int, c_long, pid_t};
+use libc::{c_int, c_long, c_ulong, pid_t, EAGAIN, EINTR};
 
 pub(crate) use bpf::*;
 #[cfg(test)]
 pub(crate) use fake::*;
+pub(crate) use netdev::*;
 pub(crate) use netlink::*;
 pub(crate) use perf_event::*;
 
-use crate::generated::{bpf_attr, bpf_cmd, perf_event_attr};
+use crate::{
+    generated::{bpf_attr, bpf_cmd, perf_event_attr},
+    util::syscall_retry,
+};
 
 pub(crate) type SysResult = Result<c_long, (c_long, io::Error)>;
 
 #[cfg_attr(test, allow(dead_code))]
+#[derive(Copy, Clone)]
 pub(crate) enum Syscall<'a> {
     Bpf {
         cmd: bpf_cmd,
@@ -39,11 +45,57 @@ pub(crate) enum Syscall<'a> {
     PerfEventIoctl {
         fd: c_int,
         request: c_int,
-        arg: c_int,
+        arg: c_ulong,
     },
+    // netlink requests take several syscalls, this lets tests fake them as a
+    // whole
+    #[cfg(test)]
+    Netlink {
+        msg: &'a [u8],
+    },
+}
+
+impl Syscall<'_> {
+    // Whether the call is known to fail transiently with EAGAIN
+    fn retries_eagain(&self) -> bool {
+        use bpf_cmd::*;
+        matches!(
+            self,
+            Syscall::Bpf {
+                cmd: BPF_PROG_LOAD
+                    | BPF_MAP_LOOKUP_BATCH
+                    | BPF_MAP_LOOKUP_AND_DELETE_BATCH
+                    | BPF_MAP_UPDATE_BATCH
+                    | BPF_MAP_DELETE_BATCH,
+                ..
+            }
+        )
+    }
 }
 
+// Invokes the syscall, retrying transient failures according to
+// `util::syscall_retry()`.
 fn syscall(call: Syscall) -> SysResult {
+    let mut eagain_retries = 0;
+    loop {
+        let ret = syscall_once(call);
+        let errno = match &ret {
+            Ok(_) => return ret,
+            Err((_, io_error)) => io_error.raw_os_error(),
+        };
+        let retry = syscall_retry();
+        match errno {
+            Some(EINTR) if retry.retry_interrupted => {}
+            Some(EAGAIN) if call.retries_eagain() && eagain_retries < retry.max_retries => {
+                thread::sleep(retry.backoff(eagain_retries));
+                eagain_retries += 1;
+            }
+            _ => return ret,
+        }
+    }
+}
+
+fn syscall_once(call: Syscall) -> SysResult {
     #[cfg(not(test))]
     return unsafe { syscall_impl(call) };
 
@@ -56,25 +108,56 @@ unsafe fn syscall_impl(call: Syscall) -> SysResult {
     use libc::{SYS_bpf, SYS_perf_event_open};
 
     use Syscall::*;
-    let ret = match call {
-        Bpf { cmd, attr } => libc::syscall(SYS_bpf, cmd, attr, mem::size_of::<bpf_attr>()),
+    let ret = match &call {
+        Bpf { cmd, attr } => libc::syscall(SYS_bpf, *cmd, *attr, mem::size_of::<bpf_attr>()),
         PerfEventOpen {
             attr,
             pid,
             cpu,
             group,
             flags,
-        } => libc::syscall(SYS_perf_event_open, &attr, pid, cpu, group, flags),
+        } => libc::syscall(SYS_perf_event_open, attr, *pid, *cpu, *group, *flags),
         PerfEventIoctl { fd, request, arg } => {
-            libc::ioctl(fd, request.try_into().unwrap(), arg) as libc::c_long
+            libc::ioctl(*fd, (*request).try_into().unwrap(), *arg) as libc::c_long
         }
     };
 
-    if ret < 0 {
-        return Err((ret, io::Error::last_os_error()));
-    }
+    let ret = if ret < 0 {
+        Err((ret, io::Error::last_os_error()))
+    } else {
+        Ok(ret)
+    };
+
+    #[cfg(feature = "tracing")]
+    trace_syscall(&call, &ret);
 
-    Ok(ret)
+    ret
+}
+
+#[cfg(all(feature = "tracing", not(test)))]
+fn trace_syscall(call: &Syscall, ret: &SysResult) {
+    match call {
+        Syscall::Bpf { cmd, .. } => tracing::debug!(?cmd, ?ret, "bpf"),
+        Syscall::PerfEventOpen {
+            attr,
+            pid,
+            cpu,
+            group,
+            flags,
+        } => tracing::debug!(
+            r#type = attr.type_,
+            config = attr.config,
+            pid,
+            cpu,
+            group,
+            flags,
+            ?ret,
+            "perf_event_open"
+        ),
+        Syscall::PerfEventIoctl { fd, request, arg } => {
+            tracing::debug!(fd, request, arg, ?ret, "perf_event_ioctl")
+        }
+    }
 }
 
 #[cfg(test)]
@@ -108,3 +191,50 @@ pub(crate) fn kernel_version() -> Result<(u32, u32, u32), ()> {
         Ok((major, minor, patch))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, mem};
+
+    use super::*;
+
+    thread_local! {
+        static CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn fail(errno: c_int) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(errno)))
+    }
+
+    #[test]
+    fn test_syscall_retry() {
+        let attr = unsafe { mem::zeroed::<bpf_attr>() };
+        let call = |cmd| {
+            CALLS.with(|calls| calls.set(0));
+            let ret = syscall(Syscall::Bpf { cmd, attr: &attr });
+            (ret.ok(), CALLS.with(|calls| calls.get()))
+        };
+
+        // interrupted calls are restarted
+        override_syscall(|_| {
+            let n = CALLS.with(|calls| calls.replace(calls.get() + 1));
+            if n < 2 {
+                fail(EINTR)
+            } else {
+                Ok(42)
+            }
+        });
+        assert_eq!(call(bpf_cmd::BPF_MAP_LOOKUP_ELEM), (Some(42), 3));
+
+        // EAGAIN is only retried for the calls that fail transiently
+        override_syscall(|_| {
+            CALLS.with(|calls| calls.set(calls.get() + 1));
+            fail(EAGAIN)
+        });
+        assert_eq!(call(bpf_cmd::BPF_MAP_LOOKUP_ELEM), (None, 1));
+        assert_eq!(
+            call(bpf_cmd::BPF_PROG_LOAD),
+            (None, syscall_retry().max_retries as usize + 1)
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::{io, mem, os::unix::io::RawFd};
+
+use libc::{
+    c_char, c_int, c_ulong, c_void, close, ioctl, socket, AF_INET, SOCK_CLOEXEC, SOCK_DGRAM,
+};
+
+// from linux/sockios.h and linux/ethtool.h
+const SIOCGIFMTU: c_ulong = 0x8921;
+const SIOCETHTOOL: c_ulong = 0x8946;
+const ETHTOOL_GCHANNELS: u32 = 0x3c;
+const IFNAMSIZ: usize = 16;
+
+#[repr(C)]
+union IfReqData {
+    mtu: c_int,
+    data: *mut c_void,
+    _pad: [u8; 24],
+}
+
+#[repr(C)]
+struct IfReq {
+    name: [c_char; IFNAMSIZ],
+    data: IfReqData,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct EthtoolChannels {
+    cmd: u32,
+    pub(crate) max_rx: u32,
+    pub(crate) max_tx: u32,
+    pub(crate) max_other: u32,
+    pub(crate) max_combined: u32,
+    pub(crate) rx_count: u32,
+    pub(crate) tx_count: u32,
+    pub(crate) other_count: u32,
+    pub(crate) combined_count: u32,
+}
+
+struct Socket(RawFd);
+
+impl Socket {
+    fn open() -> Result<Socket, io::Error> {
+        // Safety: libc wrapper
+        let fd = unsafe { socket(AF_INET, SOCK_DGRAM | SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Socket(fd))
+    }
+
+    fn ioctl(&self, request: c_ulong, req: &mut IfReq) -> Result<(), io::Error> {
+        // Safety: libc wrapper, req is a valid ifreq
+        if unsafe { ioctl(self.0, request as _, req as *mut IfReq) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        // Safety: libc wrapper
+        unsafe { close(self.0) };
+    }
+}
+
+fn if_req(if_name: &str) -> Result<IfReq, io::Error> {
+    let bytes = if_name.as_bytes();
+    if bytes.len() >= IFNAMSIZ || bytes.contains(&0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid interface name",
+        ));
+    }
+    // Safety: IfReq is POD so this is safe
+    let mut req = unsafe { mem::zeroed::<IfReq>() };
+    for (dst, src) in req.name.iter_mut().zip(bytes) {
+        *dst = *src as c_char;
+    }
+    Ok(req)
+}
+
+/// Returns the MTU of the given interface.
+pub(crate) fn netdev_mtu(if_name: &str) -> Result<u32, io::Error> {
+    let sock = Socket::open()?;
+    let mut req = if_req(if_name)?;
+    sock.ioctl(SIOCGIFMTU, &mut req)?;
+    // Safety: SIOCGIFMTU fills in the mtu field
+    Ok(unsafe { req.data.mtu } as u32)
+}
+
+/// Returns the channel (queue) configuration of the given interface.
+pub(crate) fn netdev_channels(if_name: &str) -> Result<EthtoolChannels, io::Error> {
+    let sock = Socket::open()?;
+    let mut req = if_req(if_name)?;
+    let mut channels = EthtoolChannels {
+        cmd: ETHTOOL_GCHANNELS,
+        ..Default::default()
+    };
+    req.data.data = &mut channels as *mut _ as *mut c_void;
+    sock.ioctl(SIOCETHTOOL, &mut req)?;
+    Ok(channels)
+}
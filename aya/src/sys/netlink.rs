@@ -1,19 +1,28 @@
-use std::{collections::HashMap, ffi::CStr, io, mem, os::unix::io::RawFd, ptr, slice};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    io, mem,
+    os::unix::io::RawFd,
+    ptr, slice,
+};
 use thiserror::Error;
 
 use libc::{
-    close, getsockname, nlattr, nlmsgerr, nlmsghdr, recv, send, setsockopt, sockaddr_nl, socket,
-    AF_NETLINK, AF_UNSPEC, ETH_P_ALL, IFLA_XDP, NETLINK_EXT_ACK, NETLINK_ROUTE, NLA_ALIGNTO,
-    NLA_F_NESTED, NLA_TYPE_MASK, NLMSG_DONE, NLMSG_ERROR, NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP,
-    NLM_F_ECHO, NLM_F_EXCL, NLM_F_MULTI, NLM_F_REQUEST, RTM_DELTFILTER, RTM_GETTFILTER,
-    RTM_NEWQDISC, RTM_NEWTFILTER, RTM_SETLINK, SOCK_RAW, SOL_NETLINK,
+    c_int, close, fcntl, getsockname, nlattr, nlmsgerr, nlmsghdr, recv, send, setsockopt,
+    sockaddr_nl, socket, AF_NETLINK, AF_UNSPEC, ETH_P_ALL, F_GETFL, F_SETFL, IFLA_XDP,
+    NETLINK_EXT_ACK, NETLINK_GENERIC, NETLINK_ROUTE, NLA_ALIGNTO, NLA_F_NESTED, NLA_TYPE_MASK,
+    NLMSG_DONE, NLMSG_ERROR, NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP, NLM_F_ECHO, NLM_F_EXCL,
+    NLM_F_MULTI, NLM_F_REQUEST, O_NONBLOCK, RTM_DELLINK, RTM_DELQDISC, RTM_DELTFILTER, RTM_GETLINK,
+    RTM_GETTFILTER, RTM_NEWLINK, RTM_NEWQDISC, RTM_NEWTFILTER, RTM_SETLINK, SOCK_RAW, SOL_NETLINK,
 };
 
 use crate::{
     generated::{
-        ifinfomsg, tcmsg, IFLA_XDP_EXPECTED_FD, IFLA_XDP_FD, IFLA_XDP_FLAGS, NLMSG_ALIGNTO,
-        TCA_BPF_FD, TCA_BPF_FLAGS, TCA_BPF_FLAG_ACT_DIRECT, TCA_BPF_NAME, TCA_KIND, TCA_OPTIONS,
-        TC_H_CLSACT, TC_H_INGRESS, TC_H_MAJ_MASK, TC_H_UNSPEC, XDP_FLAGS_REPLACE,
+        ifinfomsg, tcmsg, IFLA_XDP_ATTACHED, IFLA_XDP_DRV_PROG_ID, IFLA_XDP_EXPECTED_FD,
+        IFLA_XDP_FD, IFLA_XDP_FLAGS, IFLA_XDP_HW_PROG_ID, IFLA_XDP_PROG_ID, IFLA_XDP_SKB_PROG_ID,
+        NLMSG_ALIGNTO, TCA_BPF_FD, TCA_BPF_FLAGS, TCA_BPF_FLAG_ACT_DIRECT, TCA_BPF_ID,
+        TCA_BPF_NAME, TCA_KIND, TCA_OPTIONS, TC_H_CLSACT, TC_H_INGRESS, TC_H_MAJ_MASK, TC_H_UNSPEC,
+        XDP_FLAGS_DRV_MODE, XDP_FLAGS_HW_MODE, XDP_FLAGS_REPLACE, XDP_FLAGS_SKB_MODE,
     },
     programs::TcAttachType,
     util::tc_handler_make,
@@ -21,6 +30,20 @@ use crate::{
 
 const NLA_HDR_LEN: usize = align_to(mem::size_of::<nlattr>(), NLA_ALIGNTO as usize);
 
+// from linux/netlink.h, not exported by libc
+const NLM_F_CAPPED: u16 = 0x100;
+const NLM_F_ACK_TLVS: u16 = 0x200;
+const NLMSGERR_ATTR_MSG: u16 = 1;
+
+// from linux/genetlink.h and linux/netdev.h
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+const NETDEV_CMD_DEV_GET: u8 = 1;
+const NETDEV_A_DEV_IFINDEX: u16 = 1;
+const NETDEV_A_DEV_XDP_FEATURES: u16 = 3;
+
 // Safety: marking this as unsafe overall because of all the pointer math required to comply with
 // netlink alignments
 pub(crate) unsafe fn netlink_set_xdp_fd(
@@ -28,7 +51,7 @@ pub(crate) unsafe fn netlink_set_xdp_fd(
     fd: RawFd,
     old_fd: Option<RawFd>,
     flags: u32,
-) -> Result<(), io::Error> {
+) -> Result<(), NetlinkError> {
     let sock = NetlinkSocket::open()?;
 
     // Safety: Request is POD so this is safe
@@ -68,16 +91,30 @@ pub(crate) unsafe fn netlink_set_xdp_fd(
     Ok(())
 }
 
-pub(crate) unsafe fn netlink_qdisc_add_clsact(if_index: i32) -> Result<(), io::Error> {
-    let sock = NetlinkSocket::open()?;
+pub(crate) unsafe fn netlink_qdisc_add_clsact(if_index: i32) -> Result<(), NetlinkError> {
+    netlink_qdisc_clsact(
+        if_index,
+        RTM_NEWQDISC,
+        (NLM_F_REQUEST | NLM_F_ACK | NLM_F_EXCL | NLM_F_CREATE) as u16,
+    )
+}
+
+pub(crate) unsafe fn netlink_qdisc_del_clsact(if_index: i32) -> Result<(), NetlinkError> {
+    netlink_qdisc_clsact(if_index, RTM_DELQDISC, (NLM_F_REQUEST | NLM_F_ACK) as u16)
+}
 
+unsafe fn netlink_qdisc_clsact(
+    if_index: i32,
+    nlmsg_type: u16,
+    nlmsg_flags: u16,
+) -> Result<(), NetlinkError> {
     let mut req = mem::zeroed::<TcRequest>();
 
     let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<tcmsg>();
     req.header = nlmsghdr {
         nlmsg_len: nlmsg_len as u32,
-        nlmsg_flags: (NLM_F_REQUEST | NLM_F_ACK | NLM_F_EXCL | NLM_F_CREATE) as u16,
-        nlmsg_type: RTM_NEWQDISC,
+        nlmsg_flags,
+        nlmsg_type,
         nlmsg_pid: 0,
         nlmsg_seq: 1,
     };
@@ -92,10 +129,24 @@ pub(crate) unsafe fn netlink_qdisc_add_clsact(if_index: i32) -> Result<(), io::E
     let attr_len = write_attr_bytes(attrs_buf, 0, TCA_KIND as u16, b"clsact\0")?;
     req.header.nlmsg_len += align_to(attr_len, NLA_ALIGNTO as usize) as u32;
 
-    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
-    sock.recv()?;
+    netlink_request(&bytes_of(&req)[..req.header.nlmsg_len as usize])
+}
 
-    Ok(())
+// Sends a request and waits for the kernel to acknowledge it.
+fn netlink_request(msg: &[u8]) -> Result<(), NetlinkError> {
+    #[cfg(not(test))]
+    {
+        let sock = NetlinkSocket::open()?;
+        sock.send(msg)?;
+        sock.recv()?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    return crate::sys::TEST_SYSCALL
+        .with(|test_impl| unsafe { test_impl.borrow()(crate::sys::Syscall::Netlink { msg }) })
+        .map(|_| ())
+        .map_err(|(_, io_error)| io_error.into());
 }
 
 pub(crate) unsafe fn netlink_qdisc_attach(
@@ -104,7 +155,7 @@ pub(crate) unsafe fn netlink_qdisc_attach(
     prog_fd: RawFd,
     prog_name: &CStr,
     priority: u16,
-) -> Result<u16, io::Error> {
+) -> Result<u16, NetlinkError> {
     let sock = NetlinkSocket::open()?;
     let mut req = mem::zeroed::<TcRequest>();
 
@@ -155,7 +206,8 @@ pub(crate) unsafe fn netlink_qdisc_attach(
             return Err(io::Error::new(
                 io::ErrorKind::Other,
                 "no RTM_NEWTFILTER reply received, this is a bug.",
-            ));
+            )
+            .into());
         }
     };
 
@@ -167,7 +219,7 @@ pub(crate) unsafe fn netlink_qdisc_detach(
     if_index: i32,
     attach_type: &TcAttachType,
     priority: u16,
-) -> Result<(), io::Error> {
+) -> Result<(), NetlinkError> {
     let sock = NetlinkSocket::open()?;
     let mut req = mem::zeroed::<TcRequest>();
 
@@ -197,6 +249,18 @@ pub(crate) unsafe fn netlink_find_filter_with_name(
     attach_type: TcAttachType,
     name: &CStr,
 ) -> Result<Vec<u16>, io::Error> {
+    Ok(netlink_filters(if_index, attach_type)?
+        .into_iter()
+        .filter(|filter| filter.name.as_deref() == Some(name))
+        .map(|filter| filter.priority)
+        .collect())
+}
+
+// Returns the filters of the given interface.
+pub(crate) unsafe fn netlink_filters(
+    if_index: i32,
+    attach_type: TcAttachType,
+) -> Result<Vec<NlTcFilter>, io::Error> {
     let mut req = mem::zeroed::<TcRequest>();
 
     let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<tcmsg>();
@@ -215,29 +279,362 @@ pub(crate) unsafe fn netlink_find_filter_with_name(
     let sock = NetlinkSocket::open()?;
     sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
 
-    let mut prios = Vec::new();
+    let mut filters = Vec::new();
     for msg in sock.recv()? {
-        if msg.header.nlmsg_type != RTM_NEWTFILTER {
-            continue;
+        if msg.header.nlmsg_type == RTM_NEWTFILTER {
+            filters.push(NlTcFilter::parse(&msg)?);
         }
+    }
 
-        let tc_msg = ptr::read_unaligned(msg.data.as_ptr() as *const tcmsg);
-        let priority = (tc_msg.tcm_info >> 16) as u16;
+    Ok(filters)
+}
+
+// A tc filter notification, or a filter of a RTM_GETTFILTER dump.
+pub(crate) struct NlTcFilter {
+    // a RTM_DELTFILTER notification
+    pub(crate) deleted: bool,
+    pub(crate) if_index: i32,
+    pub(crate) parent: u32,
+    pub(crate) priority: u16,
+    pub(crate) handle: u32,
+    // the classifier, eg `bpf` or `flower`
+    pub(crate) kind: Option<String>,
+    // the name, program id and flags of `bpf` filters
+    pub(crate) name: Option<CString>,
+    pub(crate) program_id: Option<u32>,
+    pub(crate) direct_action: bool,
+}
+
+impl NlTcFilter {
+    fn parse(msg: &NetlinkMessage) -> Result<NlTcFilter, io::Error> {
+        if msg.data.len() < mem::size_of::<tcmsg>() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "short tcmsg"));
+        }
+        // Safety: tcmsg is POD and the buffer is large enough
+        let tc_msg = unsafe { ptr::read_unaligned(msg.data.as_ptr() as *const tcmsg) };
         let attrs = parse_attrs(&msg.data[mem::size_of::<tcmsg>()..])?;
 
+        let mut filter = NlTcFilter {
+            deleted: msg.header.nlmsg_type == RTM_DELTFILTER,
+            if_index: tc_msg.tcm_ifindex,
+            parent: tc_msg.tcm_parent,
+            priority: (tc_msg.tcm_info >> 16) as u16,
+            handle: tc_msg.tcm_handle,
+            kind: attrs.get(&(TCA_KIND as u16)).map(|attr| {
+                let len = attr
+                    .data
+                    .iter()
+                    .position(|b| *b == 0)
+                    .unwrap_or(attr.data.len());
+                String::from_utf8_lossy(&attr.data[..len]).into_owned()
+            }),
+            name: None,
+            program_id: None,
+            direct_action: false,
+        };
         if let Some(opts) = attrs.get(&(TCA_OPTIONS as u16)) {
             let opts = parse_attrs(opts.data)?;
             if let Some(f_name) = opts.get(&(TCA_BPF_NAME as u16)) {
-                if let Ok(f_name) = CStr::from_bytes_with_nul(f_name.data) {
-                    if name == f_name {
-                        prios.push(priority);
-                    }
+                filter.name = CStr::from_bytes_with_nul(f_name.data)
+                    .ok()
+                    .map(CStr::to_owned);
+            }
+            if let Some(id) = opts.get(&(TCA_BPF_ID as u16)) {
+                filter.program_id = Some(read_attr::<u32>(id)?);
+            }
+            if let Some(flags) = opts.get(&(TCA_BPF_FLAGS as u16)) {
+                filter.direct_action = read_attr::<u32>(flags)? & TCA_BPF_FLAG_ACT_DIRECT != 0;
+            }
+        }
+        Ok(filter)
+    }
+}
+
+// from linux/if_link.h, not in the generated bindings
+const XDP_ATTACHED_DRV: u8 = 1;
+const XDP_ATTACHED_SKB: u8 = 2;
+const XDP_ATTACHED_HW: u8 = 3;
+const XDP_ATTACHED_MULTI: u8 = 4;
+
+// Returns the XDP programs attached with netlink as (if_index, prog_id,
+// XDP_FLAGS_*_MODE) tuples.
+pub(crate) unsafe fn netlink_xdp_programs() -> Result<Vec<(i32, u32, u32)>, io::Error> {
+    Ok(netlink_links()?
+        .into_iter()
+        .flat_map(|link| {
+            link.xdp_programs
+                .into_iter()
+                .map(move |(prog_id, mode)| (link.if_index, prog_id, mode))
+        })
+        .collect())
+}
+
+// Returns all the links, with a RTM_GETLINK dump.
+pub(crate) unsafe fn netlink_links() -> Result<Vec<NlLinkEvent>, io::Error> {
+    let mut req = mem::zeroed::<Request>();
+
+    let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<ifinfomsg>();
+    req.header = nlmsghdr {
+        nlmsg_len: nlmsg_len as u32,
+        nlmsg_type: RTM_GETLINK,
+        nlmsg_flags: (NLM_F_REQUEST | NLM_F_DUMP) as u16,
+        nlmsg_pid: 0,
+        nlmsg_seq: 1,
+    };
+    req.if_info.ifi_family = AF_UNSPEC as u8;
+
+    let sock = NetlinkSocket::open()?;
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+
+    let mut links = Vec::new();
+    for msg in sock.recv()? {
+        if msg.header.nlmsg_type != RTM_NEWLINK {
+            continue;
+        }
+        links.extend(NlLinkEvent::parse(&msg)?);
+    }
+
+    Ok(links)
+}
+
+// from linux/netlink.h and linux/if_link.h, not exported by libc
+const NETLINK_ADD_MEMBERSHIP: c_int = 1;
+const IFLA_IFNAME: u16 = 3;
+
+// A link notification, or a link of a RTM_GETLINK dump.
+pub(crate) struct NlLinkEvent {
+    // a RTM_DELLINK notification
+    pub(crate) deleted: bool,
+    pub(crate) if_index: i32,
+    pub(crate) name: Option<String>,
+    // (prog_id, XDP_FLAGS_*_MODE)
+    pub(crate) xdp_programs: Vec<(u32, u32)>,
+}
+
+impl NlLinkEvent {
+    fn parse(msg: &NetlinkMessage) -> Result<Option<NlLinkEvent>, io::Error> {
+        let offset = align_to(mem::size_of::<ifinfomsg>(), NLMSG_ALIGNTO as usize);
+        if msg.data.len() < offset {
+            return Ok(None);
+        }
+
+        // Safety: ifinfomsg is POD and the buffer is large enough
+        let if_info = unsafe { ptr::read_unaligned(msg.data.as_ptr() as *const ifinfomsg) };
+        let attrs = parse_attrs(&msg.data[offset..])?;
+        let name = attrs.get(&IFLA_IFNAME).map(|attr| {
+            // the name is nul terminated
+            let len = attr
+                .data
+                .iter()
+                .position(|b| *b == 0)
+                .unwrap_or(attr.data.len());
+            String::from_utf8_lossy(&attr.data[..len]).into_owned()
+        });
+
+        let mut xdp_programs = Vec::new();
+        if let Some(attr) = attrs.get(&IFLA_XDP) {
+            let xdp = parse_attrs(attr.data)?;
+            let attached = match xdp.get(&(IFLA_XDP_ATTACHED as u16)) {
+                Some(attr) => read_attr::<u8>(attr)?,
+                None => 0,
+            };
+            let modes: &[(u32, u32)] = match attached {
+                XDP_ATTACHED_DRV => &[(IFLA_XDP_PROG_ID, XDP_FLAGS_DRV_MODE)],
+                XDP_ATTACHED_SKB => &[(IFLA_XDP_PROG_ID, XDP_FLAGS_SKB_MODE)],
+                XDP_ATTACHED_HW => &[(IFLA_XDP_PROG_ID, XDP_FLAGS_HW_MODE)],
+                // a program in each mode, reported in their own attribute
+                XDP_ATTACHED_MULTI => &[
+                    (IFLA_XDP_DRV_PROG_ID, XDP_FLAGS_DRV_MODE),
+                    (IFLA_XDP_SKB_PROG_ID, XDP_FLAGS_SKB_MODE),
+                    (IFLA_XDP_HW_PROG_ID, XDP_FLAGS_HW_MODE),
+                ],
+                _ => &[],
+            };
+            for (attr, mode) in modes {
+                if let Some(attr) = xdp.get(&(*attr as u16)) {
+                    xdp_programs.push((read_attr::<u32>(attr)?, *mode));
                 }
             }
         }
+
+        Ok(Some(NlLinkEvent {
+            deleted: msg.header.nlmsg_type == RTM_DELLINK,
+            if_index: if_info.ifi_index,
+            name,
+            xdp_programs,
+        }))
+    }
+}
+
+// A netlink socket subscribed to a group of notifications, like
+// RTNLGRP_LINK for interfaces being added, removed, renamed or changing XDP
+// program, or RTNLGRP_TC for tc filters being added or removed.
+pub(crate) struct NetlinkMonitor {
+    sock: NetlinkSocket,
+}
+
+impl NetlinkMonitor {
+    pub(crate) fn new(group: u32) -> Result<NetlinkMonitor, io::Error> {
+        let sock = NetlinkSocket::open()?;
+        // Safety: libc wrappers
+        unsafe {
+            if setsockopt(
+                sock.sock,
+                SOL_NETLINK,
+                NETLINK_ADD_MEMBERSHIP,
+                &group as *const _ as *const _,
+                mem::size_of_val(&group) as u32,
+            ) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            let flags = fcntl(sock.sock, F_GETFL);
+            if flags < 0 || fcntl(sock.sock, F_SETFL, flags | O_NONBLOCK) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(NetlinkMonitor { sock })
+    }
+
+    pub(crate) fn fd(&self) -> RawFd {
+        self.sock.sock
+    }
+
+    // Returns the pending messages, or an empty list if there aren't any.
+    // Fails with ENOBUFS if notifications were dropped because the socket
+    // buffer was full.
+    fn recv(&self) -> Result<Vec<NetlinkMessage>, io::Error> {
+        match self.sock.recv() {
+            Err(e) if e.io_error.kind() == io::ErrorKind::WouldBlock => Ok(Vec::new()),
+            ret => Ok(ret?),
+        }
+    }
+
+    pub(crate) fn recv_links(&self) -> Result<Vec<NlLinkEvent>, io::Error> {
+        let mut events = Vec::new();
+        for msg in self.recv()? {
+            if matches!(msg.header.nlmsg_type, RTM_NEWLINK | RTM_DELLINK) {
+                events.extend(NlLinkEvent::parse(&msg)?);
+            }
+        }
+        Ok(events)
+    }
+
+    pub(crate) fn recv_filters(&self) -> Result<Vec<NlTcFilter>, io::Error> {
+        let mut events = Vec::new();
+        for msg in self.recv()? {
+            if matches!(msg.header.nlmsg_type, RTM_NEWTFILTER | RTM_DELTFILTER) {
+                events.push(NlTcFilter::parse(&msg)?);
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Returns the `NETDEV_XDP_ACT_*` features of the given interface.
+///
+/// This uses the `netdev` generic netlink family, available since 6.3. On
+/// older kernels the family doesn't exist and [`io::ErrorKind::NotFound`] is
+/// returned.
+pub(crate) unsafe fn netlink_xdp_features(if_index: i32) -> Result<u64, io::Error> {
+    let sock = NetlinkSocket::open_protocol(NETLINK_GENERIC)?;
+    let family_id = genl_family_id(&sock, b"netdev\0")?;
+
+    let mut req = mem::zeroed::<GenlRequest>();
+    let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<genlmsghdr>();
+    req.header = nlmsghdr {
+        nlmsg_len: nlmsg_len as u32,
+        nlmsg_flags: (NLM_F_REQUEST | NLM_F_ACK) as u16,
+        nlmsg_type: family_id,
+        nlmsg_pid: 0,
+        nlmsg_seq: 1,
+    };
+    req.genl.cmd = NETDEV_CMD_DEV_GET;
+    req.genl.version = 1;
+
+    let attrs_buf = request_attributes(&mut req, nlmsg_len);
+    let attr_len = write_attr(attrs_buf, 0, NETDEV_A_DEV_IFINDEX, if_index as u32)?;
+    req.header.nlmsg_len += align_to(attr_len, NLA_ALIGNTO as usize) as u32;
+
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+    for msg in sock.recv()? {
+        if let Some(attr) = genl_attrs(&msg.data)?.get(&NETDEV_A_DEV_XDP_FEATURES) {
+            return read_attr::<u64>(attr);
+        }
     }
 
-    Ok(prios)
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "missing NETDEV_A_DEV_XDP_FEATURES",
+    ))
+}
+
+unsafe fn genl_family_id(sock: &NetlinkSocket, name: &[u8]) -> Result<u16, io::Error> {
+    let mut req = mem::zeroed::<GenlRequest>();
+    let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<genlmsghdr>();
+    req.header = nlmsghdr {
+        nlmsg_len: nlmsg_len as u32,
+        nlmsg_flags: (NLM_F_REQUEST | NLM_F_ACK) as u16,
+        nlmsg_type: GENL_ID_CTRL,
+        nlmsg_pid: 0,
+        nlmsg_seq: 1,
+    };
+    req.genl.cmd = CTRL_CMD_GETFAMILY;
+    req.genl.version = 1;
+
+    let attrs_buf = request_attributes(&mut req, nlmsg_len);
+    let attr_len = write_attr_bytes(attrs_buf, 0, CTRL_ATTR_FAMILY_NAME, name)?;
+    req.header.nlmsg_len += align_to(attr_len, NLA_ALIGNTO as usize) as u32;
+
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+    for msg in sock.recv()? {
+        if let Some(attr) = genl_attrs(&msg.data)?.get(&CTRL_ATTR_FAMILY_ID) {
+            return read_attr::<u16>(attr);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "missing CTRL_ATTR_FAMILY_ID",
+    ))
+}
+
+fn genl_attrs(data: &[u8]) -> Result<HashMap<u16, NlAttr<'_>>, io::Error> {
+    let offset = align_to(mem::size_of::<genlmsghdr>(), NLMSG_ALIGNTO as usize);
+    if data.len() < offset {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "buffer smaller than genlmsghdr",
+        ));
+    }
+    Ok(parse_attrs(&data[offset..])?)
+}
+
+fn read_attr<T: Copy>(attr: &NlAttr) -> Result<T, io::Error> {
+    if attr.data.len() < mem::size_of::<T>() {
+        return Err(NlAttrError::InvalidBufferLength {
+            size: attr.data.len(),
+            expected: mem::size_of::<T>(),
+        }
+        .into());
+    }
+    // Safety: T is POD and the buffer is large enough
+    Ok(unsafe { ptr::read_unaligned(attr.data.as_ptr() as *const T) })
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct genlmsghdr {
+    cmd: u8,
+    version: u8,
+    reserved: u16,
+}
+
+#[repr(C)]
+struct GenlRequest {
+    header: nlmsghdr,
+    genl: genlmsghdr,
+    attrs: [u8; 64],
 }
 
 #[repr(C)]
@@ -261,8 +658,12 @@ struct NetlinkSocket {
 
 impl NetlinkSocket {
     fn open() -> Result<NetlinkSocket, io::Error> {
+        NetlinkSocket::open_protocol(NETLINK_ROUTE)
+    }
+
+    fn open_protocol(protocol: c_int) -> Result<NetlinkSocket, io::Error> {
         // Safety: libc wrapper
-        let sock = unsafe { socket(AF_NETLINK, SOCK_RAW, NETLINK_ROUTE) };
+        let sock = unsafe { socket(AF_NETLINK, SOCK_RAW, protocol) };
         if sock < 0 {
             return Err(io::Error::last_os_error());
         }
@@ -302,8 +703,11 @@ impl NetlinkSocket {
         Ok(())
     }
 
-    fn recv(&self) -> Result<Vec<NetlinkMessage>, io::Error> {
-        let mut buf = [0u8; 4096];
+    fn recv(&self) -> Result<Vec<NetlinkMessage>, NetlinkError> {
+        // dump datagrams can be up to 8KiB with large pages, and grow up to
+        // 32KiB after a larger buffer was used on the socket, which would
+        // truncate them with a smaller buffer
+        let mut buf = vec![0u8; 32 * 1024];
         let mut messages = Vec::new();
         let mut multipart = true;
         'out: while multipart {
@@ -311,7 +715,7 @@ impl NetlinkSocket {
             // Safety: libc wrapper
             let len = unsafe { recv(self.sock, buf.as_mut_ptr() as *mut _, buf.len(), 0) };
             if len < 0 {
-                return Err(io::Error::last_os_error());
+                return Err(io::Error::last_os_error().into());
             }
             if len == 0 {
                 break;
@@ -330,7 +734,10 @@ impl NetlinkSocket {
                             // this is an ACK
                             continue;
                         }
-                        return Err(io::Error::from_raw_os_error(-err.error));
+                        return Err(NetlinkError {
+                            io_error: io::Error::from_raw_os_error(-err.error),
+                            message: message.error_msg,
+                        });
                     }
                     NLMSG_DONE => break 'out,
                     _ => messages.push(message),
@@ -346,6 +753,45 @@ struct NetlinkMessage {
     header: nlmsghdr,
     data: Vec<u8>,
     error: Option<nlmsgerr>,
+    error_msg: Option<String>,
+}
+
+/// A netlink error, together with the extended ack message the kernel
+/// reported with it, if any.
+#[derive(Debug)]
+pub(crate) struct NetlinkError {
+    pub(crate) io_error: io::Error,
+    pub(crate) message: Option<String>,
+}
+
+impl NetlinkError {
+    pub(crate) fn raw_os_error(&self) -> Option<i32> {
+        self.io_error.raw_os_error()
+    }
+}
+
+impl From<io::Error> for NetlinkError {
+    fn from(io_error: io::Error) -> NetlinkError {
+        NetlinkError {
+            io_error,
+            message: None,
+        }
+    }
+}
+
+impl From<NetlinkError> for io::Error {
+    fn from(e: NetlinkError) -> io::Error {
+        e.io_error
+    }
+}
+
+// Formats the extended ack message of a netlink error, to be appended to the
+// message of the error wrapping it.
+pub(crate) fn ext_ack_suffix(message: &Option<String>) -> String {
+    match message {
+        Some(message) => format!(": {message}"),
+        None => String::new(),
+    }
 }
 
 impl NetlinkMessage {
@@ -369,28 +815,39 @@ impl NetlinkMessage {
             return Err(io::Error::new(io::ErrorKind::Other, "need more data"));
         }
 
-        let (data, error) = if header.nlmsg_type == NLMSG_ERROR as u16 {
+        let (data, error, error_msg) = if header.nlmsg_type == NLMSG_ERROR as u16 {
             if data_offset + mem::size_of::<nlmsgerr>() > buf.len() {
                 return Err(io::Error::new(
                     io::ErrorKind::Other,
                     "NLMSG_ERROR but not enough space for nlmsgerr",
                 ));
             }
-            (
-                Vec::new(),
-                // Safety: nlmsgerr is POD so read is safe
-                Some(unsafe {
-                    ptr::read_unaligned(buf[data_offset..].as_ptr() as *const nlmsgerr)
-                }),
-            )
+            // Safety: nlmsgerr is POD so read is safe
+            let err =
+                unsafe { ptr::read_unaligned(buf[data_offset..].as_ptr() as *const nlmsgerr) };
+            let error_msg = if header.nlmsg_flags & NLM_F_ACK_TLVS != 0 {
+                // the TLVs follow the original request, which is only echoed
+                // back in full if the ack isn't capped
+                let mut tlv_offset = data_offset + mem::size_of::<nlmsgerr>();
+                if header.nlmsg_flags & NLM_F_CAPPED == 0 {
+                    tlv_offset +=
+                        (err.msg.nlmsg_len as usize).saturating_sub(mem::size_of::<nlmsghdr>());
+                }
+                buf.get(align_to(tlv_offset, NLMSG_ALIGNTO as usize)..msg_len)
+                    .and_then(ext_ack_message)
+            } else {
+                None
+            };
+            (Vec::new(), Some(err), error_msg)
         } else {
-            (buf[data_offset..msg_len].to_vec(), None)
+            (buf[data_offset..msg_len].to_vec(), None, None)
         };
 
         Ok(NetlinkMessage {
             header,
             data,
             error,
+            error_msg,
         })
     }
 }
@@ -553,6 +1010,17 @@ fn parse_attrs(buf: &[u8]) -> Result<HashMap<u16, NlAttr>, NlAttrError> {
     Ok(attrs)
 }
 
+fn ext_ack_message(tlvs: &[u8]) -> Option<String> {
+    let attrs = parse_attrs(tlvs).ok()?;
+    let msg = attrs.get(&NLMSGERR_ATTR_MSG)?;
+    let len = msg
+        .data
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(msg.data.len());
+    Some(String::from_utf8_lossy(&msg.data[..len]).into_owned())
+}
+
 #[derive(Clone)]
 struct NlAttr<'a> {
     header: nlattr,
@@ -718,4 +1186,97 @@ mod tests {
         let name = CStr::from_bytes_with_nul(inner.data).unwrap();
         assert_eq!(name.to_string_lossy(), "foo");
     }
+
+    #[test]
+    fn test_ext_ack_message() {
+        let msg = b"XDP program too large\0";
+        let mut buf = [0u8; 64];
+        // an unrelated attr followed by NLMSGERR_ATTR_MSG
+        let mut offset = write_attr(&mut buf, 0, 4, 42u32).unwrap();
+        offset += write_attr_bytes(&mut buf, offset, NLMSGERR_ATTR_MSG, msg).unwrap();
+
+        assert_eq!(
+            ext_ack_message(&buf[..offset]).as_deref(),
+            Some("XDP program too large")
+        );
+        assert_eq!(ext_ack_message(&buf[..8]), None);
+    }
+
+    // Writes an NLMSG_ERROR message for `errno` with an extended ack message,
+    // returns its length.
+    fn write_ext_ack_error(buf: &mut [u8], errno: i32, msg: &[u8]) -> usize {
+        let hdr_len = mem::size_of::<nlmsghdr>();
+        let err_len = mem::size_of::<nlmsgerr>();
+        let attr_len = write_attr_bytes(buf, hdr_len + err_len, NLMSGERR_ATTR_MSG, msg).unwrap();
+        let len = hdr_len + err_len + attr_len;
+
+        let header = nlmsghdr {
+            nlmsg_len: len as u32,
+            nlmsg_type: NLMSG_ERROR as u16,
+            nlmsg_flags: NLM_F_CAPPED | NLM_F_ACK_TLVS,
+            nlmsg_seq: 1,
+            nlmsg_pid: 0,
+        };
+        buf[..hdr_len].copy_from_slice(bytes_of(&header));
+        buf[hdr_len..hdr_len + 4].copy_from_slice(&(-errno).to_ne_bytes());
+        len
+    }
+
+    #[test]
+    fn test_read_error_with_ext_ack() {
+        let mut buf = [0u8; 128];
+        let len = write_ext_ack_error(&mut buf, libc::EEXIST, b"Exclusivity flag on\0");
+
+        let message = NetlinkMessage::read(&buf[..len]).unwrap();
+        assert_eq!(message.error.unwrap().error, -libc::EEXIST);
+        assert_eq!(message.error_msg.as_deref(), Some("Exclusivity flag on"));
+    }
+
+    #[test]
+    fn test_recv_error_with_ext_ack() {
+        let mut buf = [0u8; 128];
+        let len = write_ext_ack_error(&mut buf, libc::EEXIST, b"Exclusivity flag on\0");
+
+        let mut fds = [0; 2];
+        assert_eq!(
+            unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_DGRAM, 0, fds.as_mut_ptr()) },
+            0
+        );
+        let sock = NetlinkSocket {
+            sock: fds[0],
+            _nl_pid: 0,
+        };
+        assert_eq!(
+            unsafe { send(fds[1], buf.as_ptr() as *const _, len, 0) },
+            len as isize
+        );
+        unsafe { close(fds[1]) };
+
+        let err = match sock.recv() {
+            Err(err) => err,
+            Ok(_) => panic!("expected an error"),
+        };
+        // the errno is kept so callers can still match on it
+        assert_eq!(err.io_error.raw_os_error(), Some(libc::EEXIST));
+        assert_eq!(err.message.as_deref(), Some("Exclusivity flag on"));
+        assert_eq!(io::Error::from(err).raw_os_error(), Some(libc::EEXIST));
+    }
+
+    #[test]
+    fn test_genl_attrs() {
+        let mut buf = [0u8; 64];
+        let hdr_len = mem::size_of::<genlmsghdr>();
+        let len = write_attr(&mut buf, hdr_len, NETDEV_A_DEV_IFINDEX, 2u32).unwrap();
+        let len =
+            len + write_attr(&mut buf, hdr_len + len, NETDEV_A_DEV_XDP_FEATURES, 0x49u64).unwrap();
+
+        let attrs = genl_attrs(&buf[..hdr_len + len]).unwrap();
+        assert_eq!(read_attr::<u32>(&attrs[&NETDEV_A_DEV_IFINDEX]).unwrap(), 2);
+        assert_eq!(
+            read_attr::<u64>(&attrs[&NETDEV_A_DEV_XDP_FEATURES]).unwrap(),
+            0x49
+        );
+        assert!(read_attr::<u64>(&attrs[&NETDEV_A_DEV_IFINDEX]).is_err());
+        assert!(genl_attrs(&buf[..2]).is_err());
+    }
 }
@@ -1,6 +1,9 @@
-use std::{ffi::CString, mem};
+use std::{
+    ffi::{CStr, CString},
+    mem,
+};
 
-use libc::{c_int, pid_t};
+use libc::{c_int, c_ulong, pid_t};
 
 use crate::generated::{
     perf_event_attr,
@@ -11,6 +14,7 @@ use crate::generated::{
 };
 
 use super::{syscall, SysResult, Syscall};
+use crate::{maps::perf::Wakeup, PERF_EVENT_IOC_SET_FILTER};
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn perf_event_open(
@@ -20,7 +24,7 @@ pub(crate) fn perf_event_open(
     cpu: c_int,
     sample_period: u64,
     sample_frequency: Option<u64>,
-    wakeup: bool,
+    wakeup: Wakeup,
     flags: u32,
 ) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<perf_event_attr>() };
@@ -30,7 +34,13 @@ pub(crate) fn perf_event_open(
     attr.type_ = perf_type;
     attr.sample_type = PERF_SAMPLE_RAW as u64;
     // attr.inherits = if pid > 0 { 1 } else { 0 };
-    attr.__bindgen_anon_2.wakeup_events = u32::from(wakeup);
+    match wakeup {
+        Wakeup::Events(events) => attr.__bindgen_anon_2.wakeup_events = events,
+        Wakeup::Watermark(bytes) => {
+            attr.set_watermark(1);
+            attr.__bindgen_anon_2.wakeup_watermark = bytes;
+        }
+    }
 
     if let Some(frequency) = sample_frequency {
         attr.set_freq(1);
@@ -48,7 +58,22 @@ pub(crate) fn perf_event_open(
     })
 }
 
-pub(crate) fn perf_event_open_bpf(cpu: c_int) -> SysResult {
+pub(crate) fn perf_event_open_attr(
+    attr: perf_event_attr,
+    pid: pid_t,
+    cpu: c_int,
+    flags: u32,
+) -> SysResult {
+    syscall(Syscall::PerfEventOpen {
+        attr,
+        pid,
+        cpu,
+        group: -1,
+        flags,
+    })
+}
+
+pub(crate) fn perf_event_open_bpf(cpu: c_int, wakeup: Wakeup) -> SysResult {
     perf_event_open(
         PERF_TYPE_SOFTWARE as u32,
         PERF_COUNT_SW_BPF_OUTPUT as u64,
@@ -56,7 +81,7 @@ pub(crate) fn perf_event_open_bpf(cpu: c_int) -> SysResult {
         cpu,
         1,
         None,
-        true,
+        wakeup,
         PERF_FLAG_FD_CLOEXEC,
     )
 }
@@ -113,6 +138,14 @@ pub(crate) fn perf_event_open_trace_point(id: u32, pid: Option<pid_t>) -> SysRes
 }
 
 pub(crate) fn perf_event_ioctl(fd: c_int, request: c_int, arg: c_int) -> SysResult {
+    perf_event_ioctl_impl(fd, request, arg as c_ulong)
+}
+
+pub(crate) fn perf_event_ioctl_set_filter(fd: c_int, filter: &CStr) -> SysResult {
+    perf_event_ioctl_impl(fd, PERF_EVENT_IOC_SET_FILTER, filter.as_ptr() as c_ulong)
+}
+
+fn perf_event_ioctl_impl(fd: c_int, request: c_int, arg: c_ulong) -> SysResult {
     let call = Syscall::PerfEventIoctl { fd, request, arg };
     #[cfg(not(test))]
     return syscall(call);
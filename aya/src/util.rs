@@ -6,9 +6,14 @@ use std::{
     io::{self, BufReader},
     mem, slice,
     str::FromStr,
+    sync::RwLock,
+    time::Duration,
 };
 
-use crate::generated::{TC_H_MAJ_MASK, TC_H_MIN_MASK};
+use crate::{
+    generated::{TC_H_MAJ_MASK, TC_H_MIN_MASK},
+    BPF_OBJ_NAME_LEN,
+};
 
 use libc::{if_nametoindex, sysconf, _SC_PAGESIZE};
 
@@ -16,16 +21,13 @@ use io::BufRead;
 
 const ONLINE_CPUS: &str = "/sys/devices/system/cpu/online";
 pub(crate) const POSSIBLE_CPUS: &str = "/sys/devices/system/cpu/possible";
+const PRESENT_CPUS: &str = "/sys/devices/system/cpu/present";
 
 /// Returns the numeric IDs of the CPUs currently online.
+///
+/// See `/sys/devices/system/cpu/online`.
 pub fn online_cpus() -> Result<Vec<u32>, io::Error> {
-    let data = fs::read_to_string(ONLINE_CPUS)?;
-    parse_cpu_ranges(data.trim()).map_err(|_| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("unexpected {} format", ONLINE_CPUS),
-        )
-    })
+    read_cpu_ranges(ONLINE_CPUS)
 }
 
 /// Get the number of possible cpus.
@@ -37,15 +39,80 @@ pub fn nr_cpus() -> Result<usize, io::Error> {
 
 /// Get the list of possible cpus.
 ///
+/// Possible CPUs are the CPUs that can be brought online at any point during
+/// the lifetime of the system, including CPUs that can be hotplugged. Per-CPU
+/// maps are sized according to this list.
+///
 /// See `/sys/devices/system/cpu/possible`.
-pub(crate) fn possible_cpus() -> Result<Vec<u32>, io::Error> {
-    let data = fs::read_to_string(POSSIBLE_CPUS)?;
-    parse_cpu_ranges(data.trim()).map_err(|_| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("unexpected {} format", POSSIBLE_CPUS),
-        )
-    })
+pub fn possible_cpus() -> Result<Vec<u32>, io::Error> {
+    read_cpu_ranges(POSSIBLE_CPUS)
+}
+
+/// Get the list of present cpus.
+///
+/// Present CPUs are the CPUs currently installed in the system, whether they
+/// are online or not.
+///
+/// See `/sys/devices/system/cpu/present`.
+pub fn present_cpus() -> Result<Vec<u32>, io::Error> {
+    read_cpu_ranges(PRESENT_CPUS)
+}
+
+/// Returns the NUMA node of `cpu`, or `None` if the system doesn't expose
+/// NUMA information.
+///
+/// The kernel allocates the memory of a perf buffer opened with
+/// [`PerfEventArray::open`](crate::maps::PerfEventArray::open) on the node of
+/// the buffer's CPU, so reading the buffer from a thread running on that node,
+/// see [`numa_node_cpus`] and [`set_cpu_affinity`], avoids cross-node memory
+/// accesses.
+///
+/// See `/sys/devices/system/cpu/cpu<cpu>/node<node>`.
+pub fn cpu_numa_node(cpu: u32) -> Result<Option<u32>, io::Error> {
+    let path = format!("/sys/devices/system/cpu/cpu{}", cpu);
+    for entry in fs::read_dir(path)? {
+        let name = entry?.file_name();
+        if let Some(node) = name.to_str().and_then(parse_node_entry) {
+            return Ok(Some(node));
+        }
+    }
+    Ok(None)
+}
+
+fn parse_node_entry(name: &str) -> Option<u32> {
+    name.strip_prefix("node")?.parse().ok()
+}
+
+/// Returns the CPUs of the given NUMA node.
+///
+/// See `/sys/devices/system/node/node<node>/cpulist`.
+pub fn numa_node_cpus(node: u32) -> Result<Vec<u32>, io::Error> {
+    read_cpu_ranges(&format!("/sys/devices/system/node/node{}/cpulist", node))
+}
+
+/// Restricts the calling thread to run on the given CPUs.
+///
+/// This can be used to pin the threads reading per-CPU buffers to the NUMA
+/// node of the buffers they read, see [`cpu_numa_node`].
+pub fn set_cpu_affinity(cpus: &[u32]) -> Result<(), io::Error> {
+    let mut set = unsafe { mem::zeroed::<libc::cpu_set_t>() };
+    for cpu in cpus {
+        if *cpu as usize >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        unsafe { libc::CPU_SET(*cpu as usize, &mut set) };
+    }
+    let ret = unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn read_cpu_ranges(path: &str) -> Result<Vec<u32>, io::Error> {
+    let data = fs::read_to_string(path)?;
+    parse_cpu_ranges(data.trim())
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, format!("unexpected {} format", path)))
 }
 
 fn parse_cpu_ranges(data: &str) -> Result<Vec<u32>, ()> {
@@ -53,6 +120,7 @@ fn parse_cpu_ranges(data: &str) -> Result<Vec<u32>, ()> {
     for range in data.split(',') {
         cpus.extend({
             match range
+                .trim()
                 .splitn(2, '-')
                 .map(u32::from_str)
                 .collect::<Result<Vec<_>, _>>()
@@ -61,7 +129,8 @@ fn parse_cpu_ranges(data: &str) -> Result<Vec<u32>, ()> {
             {
                 &[] | &[_, _, _, ..] => return Err(()),
                 &[start] => start..=start,
-                &[start, end] => start..=end,
+                &[start, end] if start <= end => start..=end,
+                &[_, _] => return Err(()),
             }
         })
     }
@@ -92,6 +161,47 @@ fn parse_kernel_symbols(reader: impl BufRead) -> Result<BTreeMap<u64, String>, i
     Ok(syms)
 }
 
+/// Loads the symbols of the code generated by a JIT compiler in the process
+/// `pid`, from the perf map file `/tmp/perf-<pid>.map`.
+///
+/// Runtimes like the JVM (with a perf map agent) or Node.js (with
+/// `--perf-basic-prof`) write the address, size and name of the functions
+/// they compile to this file. The symbols are returned as a map of start
+/// address to `(size, name)`, which can be passed to
+/// [`StackTrace::resolve_sized`](crate::maps::stack_trace::StackTrace::resolve_sized)
+/// to resolve the JITed frames of user space stack traces. When an address was
+/// reused for another function, the latest entry of the file wins. Malformed
+/// lines are skipped.
+pub fn perf_map_symbols(pid: u32) -> Result<BTreeMap<u64, (u64, String)>, io::Error> {
+    let mut reader = BufReader::new(File::open(format!("/tmp/perf-{}.map", pid))?);
+    parse_perf_map(&mut reader)
+}
+
+fn parse_perf_map(reader: impl BufRead) -> Result<BTreeMap<u64, (u64, String)>, io::Error> {
+    let mut syms = BTreeMap::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        // the file is written while the runtime runs, so a line can be
+        // partially written or garbled, skip it instead of losing all the
+        // other symbols
+        if let Some((addr, size, name)) = parse_perf_map_line(&line) {
+            syms.insert(addr, (size, name.to_owned()));
+        }
+    }
+
+    Ok(syms)
+}
+
+fn parse_perf_map_line(line: &str) -> Option<(u64, u64, &str)> {
+    let parse_hex = |s: &str| u64::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16).ok();
+    let mut parts = line.splitn(3, ' ');
+    let addr = parse_hex(parts.next()?)?;
+    let size = parse_hex(parts.next()?)?;
+    let name = parts.next()?;
+    Some((addr, size, name))
+}
+
 pub(crate) fn ifindex_from_ifname(if_name: &str) -> Result<u32, io::Error> {
     let c_str_if_name = CString::new(if_name)?;
     let c_if_name = c_str_if_name.as_ptr();
@@ -144,11 +254,97 @@ macro_rules! include_bytes_aligned {
     }};
 }
 
+/// How syscalls that fail transiently are retried.
+///
+/// Signal-heavy applications can see syscalls fail with `EINTR`, or with
+/// `EAGAIN` when the verifier gives up because a signal is pending. By
+/// default both are retried, so the failures aren't surfaced to callers.
+///
+/// `EAGAIN` is only retried for the operations known to fail transiently:
+/// loading programs and batch map operations.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use aya::util::{set_syscall_retry, syscall_retry, SyscallRetry};
+///
+/// set_syscall_retry(SyscallRetry {
+///     max_retries: 10,
+///     max_backoff: Duration::from_secs(1),
+///     ..Default::default()
+/// });
+/// assert_eq!(syscall_retry().max_retries, 10);
+/// # set_syscall_retry(SyscallRetry::default());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyscallRetry {
+    /// Whether syscalls interrupted by a signal are restarted.
+    pub retry_interrupted: bool,
+    /// The maximum number of times an operation failing with `EAGAIN` is
+    /// retried.
+    pub max_retries: u32,
+    /// The delay before the first `EAGAIN` retry. The delay doubles on every
+    /// retry.
+    pub initial_backoff: Duration,
+    /// The maximum delay between `EAGAIN` retries.
+    pub max_backoff: Duration,
+}
+
+impl SyscallRetry {
+    const DEFAULT: SyscallRetry = SyscallRetry {
+        retry_interrupted: true,
+        max_retries: 5,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(100),
+    };
+
+    // Returns the delay before the given retry, starting from 0.
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for SyscallRetry {
+    fn default() -> Self {
+        SyscallRetry::DEFAULT
+    }
+}
+
+static SYSCALL_RETRY: RwLock<SyscallRetry> = RwLock::new(SyscallRetry::DEFAULT);
+
+/// Sets how syscalls that fail transiently are retried, for the whole
+/// process.
+pub fn set_syscall_retry(retry: SyscallRetry) {
+    *SYSCALL_RETRY.write().unwrap_or_else(|e| e.into_inner()) = retry;
+}
+
+/// Returns how syscalls that fail transiently are retried.
+///
+/// See [`set_syscall_retry`].
+pub fn syscall_retry() -> SyscallRetry {
+    *SYSCALL_RETRY.read().unwrap_or_else(|e| e.into_inner())
+}
+
 pub(crate) fn page_size() -> usize {
     // Safety: libc
     (unsafe { sysconf(_SC_PAGESIZE) }) as usize
 }
 
+// Returns the name the kernel gives to a map or program called `name`. Object
+// names are at most BPF_OBJ_NAME_LEN - 1 bytes, the longer ones are truncated
+// on a char boundary.
+pub(crate) fn kernel_object_name(name: &str) -> &str {
+    let mut len = name.len().min(BPF_OBJ_NAME_LEN - 1);
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    &name[..len]
+}
+
 // bytes_of converts a <T> to a byte slice
 pub(crate) unsafe fn bytes_of<T>(val: &T) -> &[u8] {
     let size = mem::size_of::<T>();
@@ -210,6 +406,22 @@ impl VerifierLog {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_syscall_retry_backoff() {
+        let retry = SyscallRetry::default();
+        assert_eq!(retry.backoff(0), Duration::from_millis(1));
+        assert_eq!(retry.backoff(3), Duration::from_millis(8));
+        assert_eq!(retry.backoff(7), Duration::from_millis(100));
+        assert_eq!(retry.backoff(40), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_kernel_object_name() {
+        assert_eq!(kernel_object_name("short"), "short");
+        assert_eq!(kernel_object_name("sixteen_chars_ab"), "sixteen_chars_a");
+        assert_eq!(kernel_object_name("fifteen_chars_é"), "fifteen_chars_");
+    }
+
     #[test]
     fn test_parse_online_cpus() {
         assert_eq!(parse_cpu_ranges("0").unwrap(), vec![0]);
@@ -227,11 +439,28 @@ mod tests {
             parse_cpu_ranges("0-5,6,7").unwrap(),
             (0..=7).collect::<Vec<_>>()
         );
+        assert_eq!(
+            parse_cpu_ranges("0-3,5,7-8").unwrap(),
+            vec![0, 1, 2, 3, 5, 7, 8]
+        );
+        assert_eq!(parse_cpu_ranges("0, 2-3").unwrap(), vec![0, 2, 3]);
         assert!(parse_cpu_ranges("").is_err());
+        assert!(parse_cpu_ranges("3-1").is_err());
+        assert!(parse_cpu_ranges("0,,1").is_err());
+        assert!(parse_cpu_ranges("0-1-2").is_err());
         assert!(parse_cpu_ranges("0-1,2-").is_err());
         assert!(parse_cpu_ranges("foo").is_err());
     }
 
+    #[test]
+    fn test_parse_node_entry() {
+        assert_eq!(parse_node_entry("node0"), Some(0));
+        assert_eq!(parse_node_entry("node12"), Some(12));
+        assert_eq!(parse_node_entry("node"), None);
+        assert_eq!(parse_node_entry("topology"), None);
+        assert_eq!(parse_node_entry("nodefoo"), None);
+    }
+
     #[test]
     fn test_parse_kernel_symbols() {
         let data = "0000000000002000 A irq_stack_backing_store\n\
@@ -245,4 +474,28 @@ mod tests {
         );
         assert_eq!(syms.get(&0x6000u64).unwrap().as_str(), "cpu_tss_rw");
     }
+
+    #[test]
+    fn test_parse_perf_map() {
+        let data = "7f3a4c001000 40 LazyCompile:~main /app/index.js:1\n\
+                    0x7f3a4c002000 1a0 Ljava/lang/String;::hashCode\n\
+                    \n\
+                    7f3a4c003000 40\n\
+                    7f3a4c004000 zz foo\n\
+                    7f3a4c001000 80 LazyCompile:*main /app/index.js:1\n"
+            .as_bytes();
+        let syms = parse_perf_map(&mut BufReader::new(data)).unwrap();
+        assert_eq!(
+            syms.keys().collect::<Vec<_>>(),
+            vec![&0x7f3a4c001000, &0x7f3a4c002000]
+        );
+        assert_eq!(
+            syms[&0x7f3a4c001000],
+            (0x80, "LazyCompile:*main /app/index.js:1".to_owned())
+        );
+        assert_eq!(
+            syms[&0x7f3a4c002000],
+            (0x1a0, "Ljava/lang/String;::hashCode".to_owned())
+        );
+    }
 }
@@ -13,6 +13,7 @@ pub mod per_cpu_array;
 pub mod perf;
 pub mod program_array;
 pub mod queue;
+pub mod ring_buf;
 pub mod sock_hash;
 pub mod sock_map;
 pub mod stack;
@@ -26,6 +27,7 @@ pub use per_cpu_array::PerCpuArray;
 pub use perf::{PerfEventArray, PerfEventByteArray};
 pub use program_array::ProgramArray;
 pub use queue::Queue;
+pub use ring_buf::RingBuf;
 pub use sock_hash::SockHash;
 pub use sock_map::SockMap;
 pub use stack::Stack;
@@ -2,4 +2,4 @@ mod perf_event_array;
 mod perf_event_byte_array;
 
 pub use perf_event_array::PerfEventArray;
-pub use perf_event_byte_array::PerfEventByteArray;
+pub use perf_event_byte_array::{MapUpdate, PerfEventByteArray};
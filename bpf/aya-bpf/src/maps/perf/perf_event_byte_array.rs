@@ -1,4 +1,4 @@
-use core::{cell::UnsafeCell, mem};
+use core::{cell::UnsafeCell, mem, slice};
 
 use crate::{
     bindings::{bpf_map_def, bpf_map_type::BPF_MAP_TYPE_PERF_EVENT_ARRAY, BPF_F_CURRENT_CPU},
@@ -14,6 +14,21 @@ pub struct PerfEventByteArray {
 
 unsafe impl Sync for PerfEventByteArray {}
 
+/// A notification that an entry of a map changed.
+///
+/// This is what the `notify_update` and `notify_changed` methods of
+/// [`PerfEventByteArray`] and [`RingBuf`](crate::maps::RingBuf) write. User
+/// space reads them with `aya::maps::notify::MapUpdates`. Keys with an
+/// alignment greater than 8 aren't supported.
+#[repr(C)]
+pub struct MapUpdate<K> {
+    /// Identifies the map, chosen by the program.
+    pub tag: u32,
+    /// The size of `key`, or 0 if the changed keys aren't known.
+    pub key_size: u32,
+    pub key: K,
+}
+
 impl PerfEventByteArray {
     pub const fn new(flags: u32) -> PerfEventByteArray {
         PerfEventByteArray::with_max_entries(0, flags)
@@ -63,4 +78,43 @@ impl PerfEventByteArray {
             );
         }
     }
+
+    /// Notifies user space that the entry at `key` of the map identified by
+    /// `tag` changed.
+    ///
+    /// This is meant to be called after updating the map, so user space can
+    /// read the new value instead of polling the whole map.
+    pub fn notify_update<C: BpfContext, K: Copy>(&self, ctx: &C, tag: u32, key: &K) {
+        self.output_update(
+            ctx,
+            &MapUpdate {
+                tag,
+                key_size: mem::size_of::<K>() as u32,
+                key: *key,
+            },
+        )
+    }
+
+    /// Notifies user space that any entry of the map identified by `tag` may
+    /// have changed.
+    pub fn notify_changed<C: BpfContext>(&self, ctx: &C, tag: u32) {
+        self.output_update(
+            ctx,
+            &MapUpdate {
+                tag,
+                key_size: 0,
+                key: (),
+            },
+        )
+    }
+
+    fn output_update<C: BpfContext, K>(&self, ctx: &C, update: &MapUpdate<K>) {
+        let data = unsafe {
+            slice::from_raw_parts(
+                update as *const _ as *const u8,
+                mem::size_of::<MapUpdate<K>>(),
+            )
+        };
+        self.output(ctx, data, 0)
+    }
 }
@@ -0,0 +1,117 @@
+use core::{cell::UnsafeCell, mem};
+
+use crate::{
+    bindings::{
+        bpf_map_def, bpf_map_type::BPF_MAP_TYPE_RINGBUF, BPF_RB_AVAIL_DATA, BPF_RB_FORCE_WAKEUP,
+        BPF_RB_NO_WAKEUP,
+    },
+    helpers::{bpf_ringbuf_output, bpf_ringbuf_query},
+    maps::{perf::MapUpdate, PinningType},
+};
+
+// the size of the header the kernel adds to every record
+const RECORD_HEADER_SIZE: u64 = 8;
+
+#[repr(transparent)]
+pub struct RingBuf {
+    def: UnsafeCell<bpf_map_def>,
+}
+
+unsafe impl Sync for RingBuf {}
+
+impl RingBuf {
+    /// Creates a ring buffer of `byte_size` bytes, which must be a power of 2
+    /// multiple of the page size.
+    pub const fn with_byte_size(byte_size: u32, flags: u32) -> RingBuf {
+        RingBuf::new(byte_size, flags, PinningType::None)
+    }
+
+    pub const fn pinned(byte_size: u32, flags: u32) -> RingBuf {
+        RingBuf::new(byte_size, flags, PinningType::ByName)
+    }
+
+    const fn new(byte_size: u32, flags: u32, pinning: PinningType) -> RingBuf {
+        RingBuf {
+            def: UnsafeCell::new(bpf_map_def {
+                type_: BPF_MAP_TYPE_RINGBUF,
+                key_size: 0,
+                value_size: 0,
+                max_entries: byte_size,
+                map_flags: flags,
+                id: 0,
+                pinning: pinning as u32,
+            }),
+        }
+    }
+
+    /// Copies `data` to the ring buffer.
+    ///
+    /// `flags` can be `BPF_RB_NO_WAKEUP` or `BPF_RB_FORCE_WAKEUP` to control
+    /// whether user space is notified.
+    pub fn output<T>(&self, data: &T, flags: u64) -> Result<(), i64> {
+        let ret = unsafe {
+            bpf_ringbuf_output(
+                self.def.get() as *mut _,
+                data as *const _ as *mut _,
+                mem::size_of::<T>() as u64,
+                flags,
+            )
+        };
+        (ret == 0).then_some(()).ok_or(ret)
+    }
+
+    /// Copies `data` to the ring buffer, and only notifies user space once at
+    /// least `watermark` bytes are waiting to be read.
+    ///
+    /// Notifying user space for every event is expensive at high event
+    /// rates. With a watermark, a consumer sleeping until it's notified
+    /// wakes up once per batch of events instead.
+    pub fn output_with_watermark<T>(&self, data: &T, watermark: u64) -> Result<(), i64> {
+        let pending =
+            self.query(BPF_RB_AVAIL_DATA as u64) + RECORD_HEADER_SIZE + mem::size_of::<T>() as u64;
+        let flags = if pending >= watermark {
+            BPF_RB_FORCE_WAKEUP
+        } else {
+            BPF_RB_NO_WAKEUP
+        };
+        self.output(data, flags as u64)
+    }
+
+    /// Queries the state of the ring buffer.
+    ///
+    /// `flags` is one of `BPF_RB_AVAIL_DATA`, `BPF_RB_RING_SIZE`,
+    /// `BPF_RB_CONS_POS` or `BPF_RB_PROD_POS`. The returned value is only a
+    /// snapshot, and can be out of date by the time it's used.
+    pub fn query(&self, flags: u64) -> u64 {
+        unsafe { bpf_ringbuf_query(self.def.get() as *mut _, flags) }
+    }
+
+    /// Notifies user space that the entry at `key` of the map identified by
+    /// `tag` changed.
+    ///
+    /// This is meant to be called after updating the map, so user space can
+    /// read the new value instead of polling the whole map.
+    pub fn notify_update<K: Copy>(&self, tag: u32, key: &K, flags: u64) -> Result<(), i64> {
+        self.output(
+            &MapUpdate {
+                tag,
+                key_size: mem::size_of::<K>() as u32,
+                key: *key,
+            },
+            flags,
+        )
+    }
+
+    /// Notifies user space that any entry of the map identified by `tag` may
+    /// have changed.
+    pub fn notify_changed(&self, tag: u32, flags: u64) -> Result<(), i64> {
+        self.output(
+            &MapUpdate {
+                tag,
+                key_size: 0,
+                key: (),
+            },
+            flags,
+        )
+    }
+}
@@ -0,0 +1,58 @@
+use aya_bpf_cty::{c_long, c_void};
+
+use crate::{
+    bindings::{__sk_buff, bpf_flow_keys},
+    programs::sk_buff::SkBuff,
+    BpfContext,
+};
+
+pub struct FlowDissectorContext {
+    pub skb: SkBuff,
+}
+
+impl FlowDissectorContext {
+    pub fn new(skb: *mut __sk_buff) -> FlowDissectorContext {
+        let skb = SkBuff { skb };
+        FlowDissectorContext { skb }
+    }
+
+    #[inline]
+    pub fn data(&self) -> usize {
+        self.skb.data()
+    }
+
+    #[inline]
+    pub fn data_end(&self) -> usize {
+        self.skb.data_end()
+    }
+
+    /// Returns the flow keys the program fills in.
+    ///
+    /// `nhoff` and `thoff` are initialized by the kernel to the offset of the
+    /// network header, and must be updated as the headers are parsed.
+    #[inline]
+    pub fn flow_keys(&mut self) -> &mut bpf_flow_keys {
+        unsafe { &mut *(*self.skb.skb).__bindgen_anon_1.flow_keys }
+    }
+
+    #[inline]
+    pub fn load<T>(&self, offset: usize) -> Result<T, c_long> {
+        self.skb.load(offset)
+    }
+
+    /// Reads some bytes from the packet into the specified buffer, returning
+    /// how many bytes were read.
+    ///
+    /// Starts reading at `offset` and reads at most `dst.len()` or
+    /// `self.len() - offset` bytes, depending on which one is smaller.
+    #[inline(always)]
+    pub fn load_bytes(&self, offset: usize, dst: &mut [u8]) -> Result<usize, c_long> {
+        self.skb.load_bytes(offset, dst)
+    }
+}
+
+impl BpfContext for FlowDissectorContext {
+    fn as_ptr(&self) -> *mut c_void {
+        self.skb.as_ptr()
+    }
+}
@@ -0,0 +1,66 @@
+use core::ffi::c_void;
+
+use crate::{
+    helpers::{bpf_rc_keydown, bpf_rc_pointer_rel, bpf_rc_repeat},
+    BpfContext,
+};
+
+// from linux/lirc.h
+pub const LIRC_MODE2_SPACE: u32 = 0x00000000;
+pub const LIRC_MODE2_PULSE: u32 = 0x01000000;
+pub const LIRC_MODE2_FREQUENCY: u32 = 0x02000000;
+pub const LIRC_MODE2_TIMEOUT: u32 = 0x03000000;
+pub const LIRC_MODE2_OVERFLOW: u32 = 0x04000000;
+
+const LIRC_VALUE_MASK: u32 = 0x00FFFFFF;
+const LIRC_MODE2_MASK: u32 = 0xFF000000;
+
+pub struct LircMode2Context {
+    sample: *mut u32,
+}
+
+impl LircMode2Context {
+    pub fn new(sample: *mut u32) -> LircMode2Context {
+        LircMode2Context { sample }
+    }
+
+    /// Returns the raw IR sample, made of a mode and a value.
+    pub fn sample(&self) -> u32 {
+        unsafe { *self.sample }
+    }
+
+    /// Returns the mode of the sample, one of the `LIRC_MODE2_*` constants.
+    pub fn mode(&self) -> u32 {
+        self.sample() & LIRC_MODE2_MASK
+    }
+
+    /// Returns the value of the sample, the duration of a pulse or space in
+    /// microseconds.
+    pub fn value(&self) -> u32 {
+        self.sample() & LIRC_VALUE_MASK
+    }
+
+    /// Reports a decoded key press to the rc subsystem.
+    pub fn keydown(&self, protocol: u32, scancode: u64, toggle: u32) -> Result<(), i64> {
+        let ret = unsafe { bpf_rc_keydown(self.as_ptr(), protocol, scancode, toggle) };
+        (ret == 0).then_some(()).ok_or(ret)
+    }
+
+    /// Reports that the last key is still being pressed.
+    pub fn repeat(&self) -> Result<(), i64> {
+        let ret = unsafe { bpf_rc_repeat(self.as_ptr()) };
+        (ret == 0).then_some(()).ok_or(ret)
+    }
+
+    /// Reports a relative movement of the pointer.
+    pub fn pointer_rel(&self, rel_x: i32, rel_y: i32) -> Result<(), i64> {
+        let ret = unsafe { bpf_rc_pointer_rel(self.as_ptr(), rel_x, rel_y) };
+        (ret == 0).then_some(()).ok_or(ret)
+    }
+}
+
+impl BpfContext for LircMode2Context {
+    fn as_ptr(&self) -> *mut c_void {
+        self.sample as *mut _
+    }
+}
@@ -1,5 +1,9 @@
+pub mod device;
 pub mod fentry;
 pub mod fexit;
+pub mod flow_dissector;
+pub mod fmod_ret;
+pub mod lirc_mode2;
 pub mod lsm;
 pub mod perf_event;
 pub mod probe;
@@ -11,14 +15,19 @@ pub mod sock;
 pub mod sock_addr;
 pub mod sock_ops;
 pub mod sockopt;
+pub mod syscall;
 pub mod sysctl;
 pub mod tc;
 pub mod tp_btf;
 pub mod tracepoint;
 pub mod xdp;
 
+pub use device::DeviceContext;
 pub use fentry::FEntryContext;
 pub use fexit::FExitContext;
+pub use flow_dissector::FlowDissectorContext;
+pub use fmod_ret::FModRetContext;
+pub use lirc_mode2::LircMode2Context;
 pub use lsm::LsmContext;
 pub use perf_event::PerfEventContext;
 pub use probe::ProbeContext;
@@ -30,6 +39,7 @@ pub use sock::SockContext;
 pub use sock_addr::SockAddrContext;
 pub use sock_ops::SockOpsContext;
 pub use sockopt::SockoptContext;
+pub use syscall::SyscallContext;
 pub use sysctl::SysctlContext;
 pub use tc::TcContext;
 pub use tp_btf::BtfTracePointContext;
@@ -1,5 +1,9 @@
-use crate::BpfContext;
-use core::ffi::c_void;
+use crate::{
+    bindings::{bpf_perf_event_data, bpf_perf_event_value},
+    helpers::bpf_perf_prog_read_value,
+    BpfContext,
+};
+use core::{ffi::c_void, mem};
 
 pub struct PerfEventContext {
     ctx: *mut c_void,
@@ -9,6 +13,45 @@ impl PerfEventContext {
     pub fn new(ctx: *mut c_void) -> PerfEventContext {
         PerfEventContext { ctx }
     }
+
+    /// Reads the value of the perf event the program is attached to.
+    ///
+    /// Besides the counter, the returned value contains the time the event
+    /// was enabled and running. When more events are opened than the PMU has
+    /// counters, the kernel multiplexes them, and the counter can be scaled
+    /// by `enabled / running` to estimate its real value.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #![allow(dead_code)]
+    /// # use aya_bpf::programs::PerfEventContext;
+    /// fn try_on_sample(ctx: PerfEventContext) -> Result<u32, i64> {
+    ///     let value = ctx.read_value()?;
+    ///     let _scaled = if value.running > 0 {
+    ///         value.counter * value.enabled / value.running
+    ///     } else {
+    ///         value.counter
+    ///     };
+    ///
+    ///     Ok(0)
+    /// }
+    /// ```
+    pub fn read_value(&self) -> Result<bpf_perf_event_value, i64> {
+        let mut value = bpf_perf_event_value {
+            counter: 0,
+            enabled: 0,
+            running: 0,
+        };
+        let ret = unsafe {
+            bpf_perf_prog_read_value(
+                self.ctx as *mut bpf_perf_event_data,
+                &mut value,
+                mem::size_of::<bpf_perf_event_value>() as u32,
+            )
+        };
+        (ret == 0).then_some(value).ok_or(ret)
+    }
 }
 
 impl BpfContext for PerfEventContext {
@@ -1,6 +1,6 @@
 use core::ffi::c_void;
 
-use crate::BpfContext;
+use crate::{args::FromBtfArgument, BpfContext};
 
 pub struct RawTracePointContext {
     ctx: *mut c_void,
@@ -10,6 +10,32 @@ impl RawTracePointContext {
     pub fn new(ctx: *mut c_void) -> RawTracePointContext {
         RawTracePointContext { ctx }
     }
+
+    /// Returns the `n`th argument of the raw tracepoint, starting from 0.
+    ///
+    /// The context of a raw tracepoint is a `struct bpf_raw_tracepoint_args`,
+    /// which holds the arguments of the tracepoint as an array of `u64`. The
+    /// arguments are the ones declared by the `TP_PROTO` of the tracepoint,
+    /// not the fields of its format in `/sys/kernel/debug/tracing/events`.
+    ///
+    /// SAFETY: This function is deeply unsafe, as we are reading raw pointers into kernel memory.
+    /// In particular, the value of `n` must not exceed the number of tracepoint arguments.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #![allow(dead_code)]
+    /// # use aya_bpf::{programs::RawTracePointContext, cty::c_long};
+    /// unsafe fn try_sys_enter(ctx: RawTracePointContext) -> Result<i32, i32> {
+    ///     // sys_enter is declared as TP_PROTO(struct pt_regs *regs, long id)
+    ///     let id: c_long = ctx.arg(1);
+    ///
+    ///     Ok(0)
+    /// }
+    /// ```
+    pub unsafe fn arg<T: FromBtfArgument>(&self, n: usize) -> T {
+        T::from_argument(self.ctx as *const _, n)
+    }
 }
 
 impl BpfContext for RawTracePointContext {
@@ -10,6 +10,63 @@ impl SkLookupContext {
     pub fn new(lookup: *mut bpf_sk_lookup) -> SkLookupContext {
         SkLookupContext { lookup }
     }
+
+    /// Returns the address family of the packet, `AF_INET` or `AF_INET6`.
+    #[inline]
+    pub fn family(&self) -> u32 {
+        unsafe { (*self.lookup).family }
+    }
+
+    /// Returns the IP protocol of the packet, `IPPROTO_TCP` or `IPPROTO_UDP`.
+    #[inline]
+    pub fn protocol(&self) -> u32 {
+        unsafe { (*self.lookup).protocol }
+    }
+
+    /// Returns the destination IPv4 address, in network byte order.
+    #[inline]
+    pub fn local_ip4(&self) -> u32 {
+        unsafe { (*self.lookup).local_ip4 }
+    }
+
+    /// Returns the destination IPv6 address, in network byte order.
+    #[inline]
+    pub fn local_ip6(&self) -> [u32; 4] {
+        unsafe { (*self.lookup).local_ip6 }
+    }
+
+    /// Returns the destination port, in host byte order.
+    ///
+    /// This is the port the packet is sent to, which with socket steering
+    /// doesn't have to be the port the selected socket is bound to.
+    #[inline]
+    pub fn local_port(&self) -> u32 {
+        unsafe { (*self.lookup).local_port }
+    }
+
+    /// Returns the source IPv4 address, in network byte order.
+    #[inline]
+    pub fn remote_ip4(&self) -> u32 {
+        unsafe { (*self.lookup).remote_ip4 }
+    }
+
+    /// Returns the source IPv6 address, in network byte order.
+    #[inline]
+    pub fn remote_ip6(&self) -> [u32; 4] {
+        unsafe { (*self.lookup).remote_ip6 }
+    }
+
+    /// Returns the source port, in network byte order.
+    #[inline]
+    pub fn remote_port(&self) -> u16 {
+        unsafe { (*self.lookup).remote_port }
+    }
+
+    /// Returns the index of the interface the packet was received on.
+    #[inline]
+    pub fn ingress_ifindex(&self) -> u32 {
+        unsafe { (*self.lookup).ingress_ifindex }
+    }
 }
 
 impl BpfContext for SkLookupContext {
@@ -0,0 +1,64 @@
+use core::{ffi::c_void, mem};
+
+use crate::{
+    cty::c_long,
+    helpers::{bpf_sys_bpf, bpf_sys_close},
+    BpfContext,
+};
+
+pub struct SyscallContext {
+    ctx: *mut c_void,
+}
+
+impl SyscallContext {
+    pub fn new(ctx: *mut c_void) -> SyscallContext {
+        SyscallContext { ctx }
+    }
+
+    /// Returns the context passed by user space to `BPF_PROG_RUN`, as a `T`.
+    ///
+    /// # Safety
+    ///
+    /// The context passed by user space must be at least as large as `T`,
+    /// which the verifier checks when the program is loaded with a context
+    /// size hint.
+    pub unsafe fn read<T>(&self) -> T {
+        (self.ctx as *const T).read_unaligned()
+    }
+
+    /// Writes the context returned to user space by `BPF_PROG_RUN`.
+    ///
+    /// # Safety
+    ///
+    /// The context passed by user space must be at least as large as `T`.
+    pub unsafe fn write<T>(&self, value: T) {
+        (self.ctx as *mut T).write_unaligned(value)
+    }
+
+    /// Issues the `bpf()` syscall with the given command and attributes.
+    ///
+    /// Only the commands needed to load programs and maps, like
+    /// `BPF_PROG_LOAD`, `BPF_MAP_CREATE` and `BPF_MAP_UPDATE_ELEM`, are
+    /// allowed. Returns the result of the command, for example the fd of the
+    /// created map.
+    pub fn sys_bpf<T>(&self, cmd: u32, attr: &mut T) -> Result<c_long, c_long> {
+        let ret = unsafe { bpf_sys_bpf(cmd, attr as *mut _ as *mut _, mem::size_of::<T>() as u32) };
+        if ret < 0 {
+            Err(ret)
+        } else {
+            Ok(ret)
+        }
+    }
+
+    /// Closes an fd returned by [`SyscallContext::sys_bpf`].
+    pub fn sys_close(&self, fd: u32) -> Result<(), c_long> {
+        let ret = unsafe { bpf_sys_close(fd) };
+        (ret == 0).then_some(()).ok_or(ret)
+    }
+}
+
+impl BpfContext for SyscallContext {
+    fn as_ptr(&self) -> *mut c_void {
+        self.ctx
+    }
+}
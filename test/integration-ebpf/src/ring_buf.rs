@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+use aya_bpf::{
+    macros::{map, uprobe},
+    maps::RingBuf,
+    programs::ProbeContext,
+};
+
+#[map]
+static RING_BUF: RingBuf = RingBuf::with_byte_size(4096, 0);
+
+#[uprobe]
+pub fn ring_buf_test(ctx: ProbeContext) {
+    if let Some(arg) = ctx.arg::<u64>(0) {
+        let _ = RING_BUF.output(&arg, 0);
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    unsafe { core::hint::unreachable_unchecked() }
+}
@@ -0,0 +1,48 @@
+use aya::{include_bytes_aligned, maps::RingBuf, programs::UProbe, Bpf};
+
+use log::info;
+
+use super::{integration_test, kernel_version, IntegrationTest};
+
+#[integration_test]
+fn ring_buf() {
+    let (major, minor, _) = kernel_version().unwrap();
+    if (major, minor) < (5, 8) {
+        info!(
+            "skipping as {}.{} does not meet version requirement of 5.8",
+            major, minor
+        );
+        return;
+    }
+
+    let bytes = include_bytes_aligned!("../../../../target/bpfel-unknown-none/debug/ring_buf");
+    let mut bpf = Bpf::load(bytes).unwrap();
+    let prog: &mut UProbe = bpf
+        .program_mut("ring_buf_test")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    prog.load().unwrap();
+    prog.attach(Some("trigger_ring_buf"), 0, "/proc/self/exe", None)
+        .unwrap();
+
+    let mut ring_buf = RingBuf::try_from(bpf.map_mut("RING_BUF").unwrap()).unwrap();
+    assert!(ring_buf.next().is_none());
+
+    let values = [1u64, 2, 3];
+    for value in values {
+        trigger_ring_buf(value);
+    }
+
+    let mut seen = Vec::new();
+    while let Some(item) = ring_buf.next() {
+        seen.push(u64::from_ne_bytes(item[..].try_into().unwrap()));
+    }
+    assert_eq!(seen, values);
+}
+
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn trigger_ring_buf(arg: u64) {
+    std::hint::black_box(arg);
+}